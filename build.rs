@@ -1,4 +1,29 @@
 fn main() {
+    // The commit of the bindings, only set when building from a git checkout
+    // of the repository rather than from a published crate.
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("Unable to get MANIFEST_DIR");
+    let git_dir = std::path::Path::new(&manifest_dir).join(".git");
+    println!("cargo:rerun-if-changed=build.rs");
+    if git_dir.exists() {
+        // Rebuilds when the checked out commit changes, either by switching
+        // branches or by committing on the current one.
+        let head = git_dir.join("HEAD");
+        println!("cargo:rerun-if-changed={}", head.display());
+        if let Ok(head) = std::fs::read_to_string(&head) {
+            if let Some(reference) = head.trim().strip_prefix("ref: ") {
+                println!("cargo:rerun-if-changed={}", git_dir.join(reference).display());
+                println!("cargo:rerun-if-changed={}", git_dir.join("packed-refs").display());
+            }
+        }
+        let output = std::process::Command::new("git").args(["rev-parse", "HEAD"]).output();
+        match output {
+            Ok(output) if output.status.success() => {
+                let commit = String::from_utf8_lossy(&output.stdout);
+                println!("cargo:rustc-env=TCH_GIT_COMMIT={}", commit.trim());
+            }
+            _ => {}
+        }
+    }
     let os = std::env::var("CARGO_CFG_TARGET_OS").expect("Unable to get TARGET_OS");
     match os.as_str() {
         "linux" | "windows" => {
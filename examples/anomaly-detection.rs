@@ -0,0 +1,30 @@
+// Measures the overhead of the autograd anomaly detection mode on the forward
+// and backward passes of a small network.
+//
+// Run with: cargo run --release --example anomaly-detection
+use anyhow::Result;
+use tch::autograd;
+use tch::bench::Bench;
+use tch::{Device, Kind, Tensor};
+
+const DEPTH: usize = 20;
+const DIM: i64 = 64;
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let device = Device::Cpu;
+    let bench = Bench::new(5, 50);
+    let x = Tensor::randn([DIM, DIM], (Kind::Float, device)).set_requires_grad(true);
+    let step = || {
+        let mut ys = x.shallow_clone();
+        for _ in 0..DEPTH {
+            ys = ys.matmul(&x).tanh();
+        }
+        ys.sum(Kind::Float).backward();
+    };
+    let default = bench.f_run("default", device, step)?;
+    println!("{default}");
+    let anomaly = autograd::detect_anomaly(|| bench.f_run("anomaly detection", device, step))?;
+    println!("{anomaly}");
+    Ok(())
+}
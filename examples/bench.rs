@@ -0,0 +1,50 @@
+// Benchmarks matrix multiplications of various sizes and the inference of a
+// ResNet-18 on the CPU and, when available, on CUDA. The results are printed
+// as tables and written to bench.json. The weights are randomly initialized
+// as only the timings matter here.
+//
+// Run with: cargo run --release --example bench
+use anyhow::Result;
+use tch::bench::{Bench, Comparison};
+use tch::nn::{self, ModuleT};
+use tch::{Cuda, Device, Kind, Tensor};
+
+const WARMUP_ITERS: usize = 3;
+const MEASURE_ITERS: usize = 20;
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let mut targets = vec![(Device::Cpu, Kind::Float)];
+    if Cuda::is_available() {
+        targets.push((Device::Cuda(0), Kind::Float));
+        targets.push((Device::Cuda(0), Kind::Half));
+    }
+    let bench = Bench::new(WARMUP_ITERS, MEASURE_ITERS);
+    let mut all = Comparison::default();
+
+    for size in [128, 512, 1024] {
+        let comparison = bench.f_compare(&format!("matmul {size}"), &targets, |device, kind| {
+            let xs = Tensor::randn([size, size], (kind, device));
+            move || {
+                let _ = xs.matmul(&xs);
+            }
+        })?;
+        println!("{comparison}\n");
+        all.results.extend(comparison.results)
+    }
+
+    let comparison = bench.f_compare("resnet18", &targets, |device, kind| {
+        let mut vs = nn::VarStore::new(device);
+        let model = tch::vision::resnet::resnet18(&vs.root(), 1000);
+        vs.set_kind(kind);
+        let xs = Tensor::randn([8, 3, 224, 224], (kind, device));
+        move || {
+            let _ = tch::no_grad(|| model.forward_t(&xs, false));
+        }
+    })?;
+    println!("{comparison}");
+    all.results.extend(comparison.results);
+
+    std::fs::write("bench.json", all.to_json())?;
+    Ok(())
+}
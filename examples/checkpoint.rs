@@ -0,0 +1,62 @@
+// Compares the peak GPU memory used by a training step of a deep MLP with and
+// without activation checkpointing.
+//
+// Run with: cargo run --release --example checkpoint
+use anyhow::{bail, Result};
+use std::sync::{Arc, Mutex};
+use tch::nn::{self, Module};
+use tch::{cuda, utils, Cuda, Device, Kind, Tensor};
+
+const BLOCKS: i64 = 32;
+const HIDDEN: i64 = 1024;
+const BATCH_SIZE: i64 = 1024;
+const SEGMENTS: usize = 4;
+
+#[derive(Debug)]
+struct Block {
+    fc1: nn::Linear,
+    fc2: nn::Linear,
+}
+
+impl Module for Block {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        xs + xs.apply(&self.fc1).gelu("none").dropout(0.1, true).apply(&self.fc2)
+    }
+}
+
+// Returns the peak memory used by the training step in bytes.
+fn peak_memory<F: Fn(&Tensor) -> Tensor>(device: Device, xs: &Tensor, f: F) -> Result<i64> {
+    Cuda::synchronize(0);
+    cuda::reset_peak_memory_stats(device)?;
+    let before = cuda::memory_allocated(device)?;
+    f(xs).square().mean(Kind::Float).backward();
+    Cuda::synchronize(0);
+    Ok(cuda::max_memory_allocated(device)? - before)
+}
+
+fn main() -> Result<()> {
+    if !Cuda::is_available() {
+        bail!("this example requires a CUDA device")
+    }
+    let device = Device::Cuda(0);
+    let vs = nn::VarStore::new(device);
+    let blocks: Vec<Arc<Mutex<Block>>> = (0..BLOCKS)
+        .map(|i| {
+            let p = vs.root() / i;
+            let fc1 = nn::linear(&p / "fc1", HIDDEN, 4 * HIDDEN, Default::default());
+            let fc2 = nn::linear(&p / "fc2", 4 * HIDDEN, HIDDEN, Default::default());
+            Arc::new(Mutex::new(Block { fc1, fc2 }))
+        })
+        .collect();
+    let xs = Tensor::randn([BATCH_SIZE, HIDDEN], (Kind::Float, device));
+
+    let plain = peak_memory(device, &xs, |xs| {
+        blocks.iter().fold(xs.shallow_clone(), |xs, b| b.lock().unwrap().forward(&xs))
+    })?;
+    let checkpointed =
+        peak_memory(device, &xs, |xs| utils::checkpoint_sequential(&blocks, SEGMENTS, xs))?;
+    let mb = |bytes: i64| bytes as f64 / (1 << 20) as f64;
+    println!("peak memory without checkpointing: {:.1}MB", mb(plain));
+    println!("peak memory with {SEGMENTS} segments:      {:.1}MB", mb(checkpointed));
+    Ok(())
+}
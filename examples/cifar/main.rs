@@ -11,7 +11,8 @@ use tch::nn::{FuncT, ModuleT, OptimizerConfig, SequentialT};
 use tch::{nn, Device};
 
 fn conv_bn(vs: &nn::Path, c_in: i64, c_out: i64) -> SequentialT {
-    let conv2d_cfg = nn::ConvConfig { padding: 1, bias: false, ..Default::default() };
+    let conv2d_cfg =
+        nn::ConvConfig { padding: nn::Padding::Explicit(1), bias: false, ..Default::default() };
     nn::seq_t()
         .add(nn::conv2d(vs, c_in, c_out, 3, conv2d_cfg))
         .add(nn::batch_norm2d(vs, c_out, Default::default()))
@@ -0,0 +1,80 @@
+// Measures the host-side latency of a loop that allocates and frees large
+// activations while a background stream is busy, with the tensors either
+// dropped immediately or deferred with `Tensor::defer_drop` and freed after
+// the loop.
+//
+// Frees are only expensive when they end up calling cudaFree, which waits for
+// the device to be idle. Run with the caching allocator disabled to see the
+// spikes this causes:
+// PYTORCH_NO_CUDA_MEMORY_CACHING=1 cargo run --release --example cuda-deferred-free
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+use tch::cuda::{CudaStream, DeferredFree};
+use tch::{Cuda, Device, Kind, Tensor};
+
+const STEPS: usize = 200;
+
+// Queues enough work on a side stream to keep the device busy for a while.
+fn busy_background_stream(device: Device) -> Result<CudaStream> {
+    let stream = CudaStream::new(device)?;
+    let _guard = stream.set_current()?;
+    let xs = Tensor::randn([4096, 4096], (Kind::Float, device));
+    let mut ys = xs.shallow_clone();
+    for _ in 0..200 {
+        ys = ys.matmul(&xs).tanh();
+    }
+    // The output is used on the background stream only, dropping it here
+    // is safe as the allocator associated it with that stream.
+    Ok(stream)
+}
+
+// Returns the per-step latencies, sorted.
+fn run(device: Device, defer: bool) -> Result<Vec<Duration>> {
+    let xs = Tensor::randn([64, 1024], (Kind::Float, device));
+    let ws = Tensor::randn([1024, 1024], (Kind::Float, device));
+    let stream = busy_background_stream(device)?;
+    let mut latencies = Vec::with_capacity(STEPS);
+    for _ in 0..STEPS {
+        let start = Instant::now();
+        // A large activation that is only needed for the duration of the step.
+        let activations = Tensor::empty([64, 1024, 256], (Kind::Float, device));
+        let ys = xs.matmul(&ws).relu();
+        if defer {
+            activations.defer_drop()
+        } else {
+            drop(activations)
+        }
+        drop(ys);
+        latencies.push(start.elapsed());
+    }
+    let flush_start = Instant::now();
+    let freed = DeferredFree::flush();
+    if defer {
+        println!("flushed {freed} tensors in {:.2}ms", flush_start.elapsed().as_secs_f64() * 1e3);
+    }
+    stream.synchronize()?;
+    latencies.sort();
+    Ok(latencies)
+}
+
+fn report(name: &str, latencies: &[Duration]) {
+    let ms = |d: Duration| d.as_secs_f64() * 1e3;
+    let p50 = latencies[latencies.len() / 2];
+    let p99 = latencies[latencies.len() * 99 / 100];
+    let max = latencies[latencies.len() - 1];
+    println!("{name:>10}: p50 {:.3}ms, p99 {:.3}ms, max {:.3}ms", ms(p50), ms(p99), ms(max));
+}
+
+fn main() -> Result<()> {
+    if !Cuda::is_available() {
+        bail!("this example requires a CUDA device")
+    }
+    let device = Device::Cuda(0);
+    // Warm up the kernels and the allocator.
+    run(device, false)?;
+    let immediate = run(device, false)?;
+    let deferred = run(device, true)?;
+    report("immediate", &immediate);
+    report("deferred", &deferred);
+    Ok(())
+}
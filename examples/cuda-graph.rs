@@ -0,0 +1,64 @@
+// Compares the latency of a small MLP when run eagerly and when replayed from
+// a CUDA graph. For such small models, the time is dominated by the kernel
+// launch overhead which CUDA graphs mostly remove.
+//
+// Run with: cargo run --release --example cuda-graph
+use anyhow::{bail, Result};
+use std::time::Instant;
+use tch::cuda::CudaGraph;
+use tch::nn::{self, Module};
+use tch::{Cuda, Device, Kind, Tensor};
+
+const LAYERS: i64 = 8;
+const HIDDEN: i64 = 256;
+const BATCH_SIZE: i64 = 16;
+const STEPS: usize = 1000;
+
+fn main() -> Result<()> {
+    if !Cuda::is_available() {
+        bail!("this example requires a CUDA device")
+    }
+    tch::manual_seed(42);
+    let device = Device::Cuda(0);
+    let vs = nn::VarStore::new(device);
+    let mut mlp = nn::seq();
+    for i in 0..LAYERS {
+        mlp = mlp.add(nn::linear(vs.root() / i, HIDDEN, HIDDEN, Default::default()));
+        mlp = mlp.add_fn(|xs| xs.relu());
+    }
+    let _guard = tch::no_grad_guard();
+    let inputs: Vec<Tensor> =
+        (0..STEPS).map(|_| Tensor::randn([BATCH_SIZE, HIDDEN], (Kind::Float, device))).collect();
+
+    let mut graph = CudaGraph::new(device)?;
+    let _ = graph.capture(&[&inputs[0]], |xs| mlp.forward(&xs[0]))?;
+
+    let start = Instant::now();
+    for xs in inputs.iter() {
+        let _ = mlp.forward(xs);
+    }
+    Cuda::synchronize(0);
+    let eager = start.elapsed().as_secs_f64() / STEPS as f64;
+
+    let start = Instant::now();
+    for xs in inputs.iter() {
+        let _ = graph.replay_with(&[xs])?;
+    }
+    Cuda::synchronize(0);
+    let replay = start.elapsed().as_secs_f64() / STEPS as f64;
+
+    let max_diff = inputs
+        .iter()
+        .take(10)
+        .map(|xs| {
+            let ys = graph.replay_with(&[xs])?;
+            Ok(f64::try_from((ys - mlp.forward(xs)).abs().max())?)
+        })
+        .collect::<Result<Vec<f64>>>()?
+        .into_iter()
+        .fold(0f64, f64::max);
+    println!("eager:  {:.1}us/step", eager * 1e6);
+    println!("replay: {:.1}us/step", replay * 1e6);
+    println!("speedup: {:.2}x, max diff {max_diff:e}", eager / replay);
+    Ok(())
+}
@@ -0,0 +1,55 @@
+// Trains a ResNet-18 model on random CIFAR-10 sized images, first on a
+// single GPU and then on two GPUs using DataParallel, and reports the
+// time per training step for both configurations.
+use anyhow::{bail, Result};
+use tch::nn::{self, ModuleT, OptimizerConfig};
+use tch::{Cuda, Device, Kind, Tensor};
+
+const BATCH_SIZE: i64 = 256;
+const STEPS: usize = 50;
+const WARMUP_STEPS: usize = 5;
+
+fn synchronize(devices: &[Device]) {
+    for device in devices.iter() {
+        if let Device::Cuda(index) = device {
+            Cuda::synchronize(*index as i64)
+        }
+    }
+}
+
+// Returns the average time per step in seconds.
+fn train(devices: &[Device]) -> Result<f64> {
+    let mut model = nn::DataParallel::new(|p| tch::vision::resnet::resnet18(p, 10), devices)?;
+    let mut opt = nn::Sgd { momentum: 0.9, ..Default::default() }.build(model.var_store(), 0.1)?;
+    let primary = model.primary_device();
+    let xs = Tensor::randn([BATCH_SIZE, 3, 32, 32], (Kind::Float, primary));
+    let ys = Tensor::randint(10, [BATCH_SIZE], (Kind::Int64, primary));
+    let mut start = std::time::Instant::now();
+    for step in 0..WARMUP_STEPS + STEPS {
+        if step == WARMUP_STEPS {
+            synchronize(devices);
+            start = std::time::Instant::now();
+        }
+        let loss = model.forward_t(&xs, true).cross_entropy_for_logits(&ys);
+        opt.zero_grad();
+        loss.backward();
+        model.reduce_gradients()?;
+        opt.step();
+        model.sync_replicas()?;
+    }
+    synchronize(devices);
+    Ok(start.elapsed().as_secs_f64() / STEPS as f64)
+}
+
+fn main() -> Result<()> {
+    if Cuda::device_count() < 2 {
+        bail!("this example requires at least two GPUs")
+    }
+    tch::manual_seed(42);
+    let dt1 = train(&[Device::Cuda(0)])?;
+    println!("1 GPU:  {:.1}ms/step", dt1 * 1000.);
+    let dt2 = train(&[Device::Cuda(0), Device::Cuda(1)])?;
+    println!("2 GPUs: {:.1}ms/step", dt2 * 1000.);
+    println!("speedup: {:.2}x", dt1 / dt2);
+    Ok(())
+}
@@ -0,0 +1,16 @@
+// Prints the versions of the libraries in use and the build configuration,
+// to be included when filing bug reports.
+//
+// Run with: cargo run --example env_report
+// With the serde and serde_json features enabled, the report is also printed
+// as JSON.
+fn main() {
+    let info = tch::build_info();
+    println!("{info}");
+    match tch::version::libtorch_config() {
+        Ok(config) => println!("\nlibtorch config:\n{config}"),
+        Err(err) => println!("\nlibtorch config: {err}"),
+    }
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    println!("\n{}", serde_json::to_string_pretty(&info).unwrap());
+}
@@ -17,7 +17,12 @@ fn tr2d(p: nn::Path, c_in: i64, c_out: i64, padding: i64, stride: i64) -> nn::Co
 }
 
 fn conv2d(p: nn::Path, c_in: i64, c_out: i64, padding: i64, stride: i64) -> nn::Conv2D {
-    let cfg = nn::ConvConfig { stride, padding, bias: false, ..Default::default() };
+    let cfg = nn::ConvConfig {
+        stride,
+        padding: nn::Padding::Explicit(padding),
+        bias: false,
+        ..Default::default()
+    };
     nn::conv2d(p, c_in, c_out, 4, cfg)
 }
 
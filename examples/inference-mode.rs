@@ -0,0 +1,49 @@
+// Compares the latency of a ResNet-18 forward pass when run with no_grad and
+// when run in inference mode. The weights are randomly initialized as only the
+// timings matter here.
+//
+// Run with: cargo run --release --example inference-mode
+use anyhow::Result;
+use std::time::Instant;
+use tch::nn::{self, ModuleT};
+use tch::{Cuda, Device, Kind, Tensor};
+
+const BATCH_SIZE: i64 = 8;
+const STEPS: usize = 50;
+const WARMUP_STEPS: usize = 5;
+
+fn synchronize(device: Device) {
+    if let Device::Cuda(index) = device {
+        Cuda::synchronize(index as i64)
+    }
+}
+
+// Returns the average time per forward pass in seconds.
+fn bench<F: Fn() -> Tensor>(device: Device, f: F) -> f64 {
+    for _ in 0..WARMUP_STEPS {
+        let _ = f();
+    }
+    synchronize(device);
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        let _ = f();
+    }
+    synchronize(device);
+    start.elapsed().as_secs_f64() / STEPS as f64
+}
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let device = Device::cuda_if_available();
+    let vs = nn::VarStore::new(device);
+    let model = tch::vision::resnet::resnet18(&vs.root(), 1000);
+    let xs = Tensor::randn([BATCH_SIZE, 3, 224, 224], (Kind::Float, device));
+
+    let dt_no_grad = bench(device, || tch::no_grad(|| model.forward_t(&xs, false)));
+    let dt_inference = bench(device, || tch::inference_mode(|| model.forward_t(&xs, false)));
+    println!("device:         {device:?}");
+    println!("no_grad:        {:.2}ms/batch", dt_no_grad * 1000.);
+    println!("inference_mode: {:.2}ms/batch", dt_inference * 1000.);
+    println!("speedup:        {:.2}x", dt_no_grad / dt_inference);
+    Ok(())
+}
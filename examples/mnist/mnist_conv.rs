@@ -1,7 +1,7 @@
 // CNN model. This should rearch 99.1% accuracy.
 
 use anyhow::Result;
-use tch::{nn, nn::ModuleT, nn::OptimizerConfig, Device, Tensor};
+use tch::{nn, nn::ModuleT, nn::OptimizerConfig, train, Device, Tensor};
 
 #[derive(Debug)]
 struct Net {
@@ -38,17 +38,35 @@ impl nn::ModuleT for Net {
 
 pub fn run() -> Result<()> {
     let m = tch::vision::mnist::load_dir("data")?;
-    let vs = nn::VarStore::new(Device::cuda_if_available());
+    let mut vs = nn::VarStore::new(Device::accelerator_if_available());
+    let device = vs.device();
     let net = Net::new(&vs.root());
-    let mut opt = nn::Adam::default().build(&vs, 1e-4)?;
-    for epoch in 1..100 {
-        for (bimages, blabels) in m.train_iter(256).shuffle().to_device(vs.device()) {
-            let loss = net.forward_t(&bimages, true).cross_entropy_for_logits(&blabels);
-            opt.backward_step(&loss);
-        }
-        let test_accuracy =
-            net.batch_accuracy_for_logits(&m.test_images, &m.test_labels, vs.device(), 1024);
-        println!("epoch: {:4} test acc: {:5.2}%", epoch, 100. * test_accuracy,);
+    let opt = nn::Adam::default().build(&vs, 1e-4)?;
+    let callbacks: Vec<Box<dyn train::Callback>> = vec![
+        Box::new(train::MetricLogger::new(train::PrintSink)),
+        Box::new(train::EarlyStopping::new("test_acc", train::Mode::Max, 5)),
+        Box::new(
+            train::Checkpoint::new("mnist-checkpoint").track_best("test_acc", train::Mode::Max),
+        ),
+    ];
+    let mut trainer =
+        train::Trainer::new(&mut vs, opt, callbacks).max_epochs(100).validation(|| {
+            let test_acc =
+                net.batch_accuracy_for_logits(&m.test_images, &m.test_labels, device, 1024);
+            Ok(vec![("test_acc".to_string(), test_acc)])
+        });
+    // Continue from the last checkpoint if there is one, e.g. after Ctrl-C.
+    if std::path::Path::new("mnist-checkpoint/state.txt").exists() {
+        trainer.resume("mnist-checkpoint")?;
     }
+    trainer.fit_module(
+        &net,
+        || {
+            let mut iter = m.train_iter(256);
+            iter.shuffle().to_device(device);
+            iter
+        },
+        |logits, labels| logits.cross_entropy_for_logits(labels),
+    )?;
     Ok(())
 }
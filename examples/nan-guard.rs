@@ -0,0 +1,51 @@
+// Measures the overhead of checking the outputs of each layer of a MLP for
+// non-finite values, with the guard disabled and enabled.
+//
+// Run with: cargo run --release --example nan-guard
+use anyhow::Result;
+use tch::bench::Bench;
+use tch::debug::NanGuard;
+use tch::nn::{self, ModuleT};
+use tch::{Device, Kind, Tensor};
+
+const LAYERS: usize = 8;
+const HIDDEN_DIM: i64 = 1024;
+const BATCH_SIZE: i64 = 256;
+
+fn mlp(vs: &nn::VarStore, guard: Option<&NanGuard>) -> nn::SequentialT {
+    let mut net = nn::seq_t();
+    for i in 0..LAYERS {
+        let path = vs.root() / format!("fc{i}");
+        let linear = nn::linear(&path, HIDDEN_DIM, HIDDEN_DIM, Default::default());
+        net = match guard {
+            None => net.add(linear),
+            Some(guard) => net.add(guard.wrap(&path, linear)),
+        };
+        net = net.add_fn(|xs| xs.relu());
+    }
+    net
+}
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let device = Device::cuda_if_available();
+    let bench = Bench::new(5, 50);
+    let xs = Tensor::randn([BATCH_SIZE, HIDDEN_DIM], (Kind::Float, device));
+    let guard = NanGuard::new();
+    for (name, guard, enabled) in [
+        ("unguarded", None, false),
+        ("guard disabled", Some(&guard), false),
+        ("guard enabled", Some(&guard), true),
+    ] {
+        let vs = nn::VarStore::new(device);
+        let net = mlp(&vs, guard);
+        if let Some(guard) = guard {
+            guard.set_enabled(enabled)
+        }
+        let result = bench.f_run(name, device, || {
+            let _ = tch::no_grad(|| net.forward_t(&xs, false));
+        })?;
+        println!("{result}");
+    }
+    Ok(())
+}
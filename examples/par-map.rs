@@ -0,0 +1,60 @@
+// Compares sequential and parallel evaluation of an embedding-similarity
+// workload on the CPU. For each query, the cosine similarity with a set of
+// reference embeddings is computed and the best matching index is returned.
+//
+// Run with: cargo run --release --example par-map --features rayon
+use anyhow::Result;
+use std::time::Instant;
+use tch::{Kind, Tensor};
+
+const NUM_QUERIES: i64 = 8192;
+const NUM_REFERENCES: i64 = 4096;
+const EMBEDDING_DIM: i64 = 256;
+const CHUNK_SIZE: usize = 256;
+
+fn best_match(queries: &Tensor, references: &Tensor) -> Tensor {
+    let queries = queries / queries.norm_scalaropt_dim(2, [-1], true);
+    queries.matmul(&references.tr()).argmax(-1, false)
+}
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let queries = Tensor::randn([NUM_QUERIES, EMBEDDING_DIM], (Kind::Float, tch::Device::Cpu));
+    let references =
+        Tensor::randn([NUM_REFERENCES, EMBEDDING_DIM], (Kind::Float, tch::Device::Cpu));
+    let references = &references / references.norm_scalaropt_dim(2, [-1], true);
+    let _guard = tch::no_grad_guard();
+
+    // Sequential baseline, libtorch only uses a single thread here so that the
+    // comparison measures the rayon scaling.
+    let num_threads = tch::get_num_threads();
+    tch::set_num_threads(1);
+    let start = Instant::now();
+    let sequential = Tensor::cat(
+        &queries
+            .split(CHUNK_SIZE as i64, 0)
+            .iter()
+            .map(|q| best_match(q, &references))
+            .collect::<Vec<_>>(),
+        0,
+    );
+    let sequential_time = start.elapsed();
+    tch::set_num_threads(num_threads);
+
+    // The references tensor is shared across the rayon workers via a shallow
+    // clone per chunk.
+    let references = std::sync::Mutex::new(references);
+    let start = Instant::now();
+    let parallel = queries.par_map_dim0(CHUNK_SIZE, |q| {
+        let references = references.lock().unwrap().shallow_clone();
+        best_match(&q, &references)
+    })?;
+    let parallel_time = start.elapsed();
+
+    assert!(sequential.equal(&parallel));
+    let workers = rayon::current_num_threads();
+    println!("sequential: {sequential_time:?}");
+    println!("parallel:   {parallel_time:?} using {workers} workers");
+    println!("speedup:    {:.2}x", sequential_time.as_secs_f64() / parallel_time.as_secs_f64());
+    Ok(())
+}
@@ -39,7 +39,8 @@ pub fn main() -> Result<()> {
     let image = imagenet::load_image_and_resize224(image)?;
 
     // Create the model and load the weights from the file.
-    let mut vs = tch::nn::VarStore::new(tch::Device::Cpu);
+    let device = tch::Device::accelerator_if_available();
+    let mut vs = tch::nn::VarStore::new(device);
     let net: Box<dyn ModuleT> =
         match weights.file_stem().context("no stem")?.to_str().context("invalid stem")? {
             "resnet18" => Box::new(resnet::resnet18(&vs.root(), imagenet::CLASS_COUNT)),
@@ -70,8 +71,9 @@ pub fn main() -> Result<()> {
     vs.load(weights)?;
 
     // Apply the forward pass of the model to get the logits.
-    let output =
-        net.forward_t(&image.unsqueeze(0), /* train= */ false).softmax(-1, tch::Kind::Float); // Convert to probability.
+    let output = net
+        .forward_t(&image.unsqueeze(0).to_device(device), /* train= */ false)
+        .softmax(-1, tch::Kind::Float); // Convert to probability.
 
     // Print the top 5 categories for this image.
     for (probability, class) in imagenet::top(&output, 5).iter() {
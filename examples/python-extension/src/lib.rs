@@ -1,5 +1,6 @@
 use pyo3::prelude::*;
 use pyo3_tch::{wrap_tch_err, PyTensor};
+use tch::Tensor;
 
 #[pyfunction]
 fn add_one(tensor: PyTensor) -> PyResult<PyTensor> {
@@ -13,5 +14,12 @@ fn add_one(tensor: PyTensor) -> PyResult<PyTensor> {
 fn tch_ext(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     py.import("torch")?;
     m.add_function(wrap_pyfunction!(add_one, m)?)?;
+    // Available from Python as torch.ops.tch_ext.add_mul, including in
+    // TorchScript functions.
+    tch::register_op!(
+        "tch_ext::add_mul(Tensor a, Tensor b, float s) -> Tensor",
+        |a: Tensor, b: Tensor, s: f64| { (a + b).f_mul_scalar(s) }
+    )
+    .map_err(wrap_tch_err)?;
     Ok(())
 }
@@ -0,0 +1,29 @@
+import pytest
+import torch
+
+import tch_ext
+
+
+def test_add_one():
+    t = torch.tensor([[1.0, -1.0], [1.0, -1.0]])
+    assert torch.equal(tch_ext.add_one(t), t + 1)
+
+
+def test_custom_op():
+    a = torch.tensor([1.0, 2.0, 3.0])
+    b = torch.tensor([3.0, 2.0, 1.0])
+    assert torch.equal(torch.ops.tch_ext.add_mul(a, b, 0.5), torch.full((3,), 2.0))
+
+
+def test_custom_op_scripted():
+    @torch.jit.script
+    def f(a: torch.Tensor, b: torch.Tensor) -> torch.Tensor:
+        return torch.ops.tch_ext.add_mul(a, b, 2.0) + 1
+
+    a = torch.ones(2)
+    assert torch.equal(f(a, a), torch.full((2,), 5.0))
+
+
+def test_custom_op_error():
+    with pytest.raises(RuntimeError):
+        torch.ops.tch_ext.add_mul(torch.ones(2), torch.ones(3), 1.0)
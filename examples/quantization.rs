@@ -0,0 +1,36 @@
+// Quantizes the weights of a large linear layer to 8 and 4 bits and compares
+// the memory used by the weights and the error on the layer outputs. The
+// weights are randomly initialized, in practice they would be loaded from a
+// trained model.
+//
+// Run with: cargo run --release --example quantization
+use anyhow::Result;
+use tch::nn::{self, Module};
+use tch::quant::{QLinear, QuantConfig};
+use tch::{Device, Kind, Tensor};
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let device = Device::cuda_if_available();
+    let vs = nn::VarStore::new(device);
+    let linear = nn::linear(vs.root() / "fc", 4096, 4096, Default::default());
+    let float_bytes = linear.ws.numel() * linear.ws.kind().elt_size_in_bytes();
+    let xs = Tensor::randn([8, 4096], (Kind::Float, device));
+    let ys = tch::no_grad(|| linear.forward(&xs));
+    println!("float32 weight: {:8.2}MB", float_bytes as f64 / 1e6);
+
+    for bits in [8, 4] {
+        let qvs = nn::VarStore::new(device);
+        let config = QuantConfig { bits, group_size: 128, symmetric: true };
+        let qlinear = QLinear::f_from_linear(qvs.root() / "fc", &linear, config)?;
+        let bytes = qlinear.weight.size_in_bytes();
+        let err =
+            (qlinear.forward(&xs) - &ys).norm().double_value(&[]) / ys.norm().double_value(&[]);
+        println!(
+            "int{bits} weight:    {:8.2}MB ({:.2}x smaller), relative output error {err:.5}",
+            bytes as f64 / 1e6,
+            float_bytes as f64 / bytes as f64
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Applies a small convolutional model to a long 1-D signal in overlapping
+// chunks and stitches the outputs back together, then compares the result
+// with the one-shot forward pass. The zero padding makes the outputs differ
+// near the chunk boundaries, the cross-fade gives these positions a small
+// weight.
+//
+// Run with: cargo run --example sliding_windows
+use anyhow::Result;
+use tch::nn::{self, Module};
+use tch::utils::{self, Overlap};
+use tch::{Device, Kind, Tensor};
+
+const SIGNAL_LEN: i64 = 100_000;
+const WINDOW: i64 = 4096;
+const HOP: i64 = 3072;
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::ConvConfig { padding: nn::Padding::Explicit(2), ..Default::default() };
+    let model = nn::seq()
+        .add(nn::conv1d(vs.root() / "c1", 1, 8, 5, config))
+        .add_fn(|xs| xs.tanh())
+        .add(nn::conv1d(vs.root() / "c2", 8, 1, 5, config));
+    let signal = Tensor::randn([1, 1, SIGNAL_LEN], (Kind::Float, Device::Cpu));
+    let _no_grad = tch::no_grad_guard();
+    let expected = model.forward(&signal);
+
+    let outputs: Vec<_> = utils::sliding_windows(&signal, 2, WINDOW, HOP)
+        .map(|(chunk, range)| (model.forward(&chunk), range))
+        .collect();
+    println!("{} chunks of {WINDOW} samples", outputs.len());
+    for overlap in [Overlap::Mean, Overlap::Hann, Overlap::Last] {
+        let stitched = utils::f_stitch(&outputs, 2, SIGNAL_LEN, overlap)?;
+        let max_diff = (&stitched - &expected).abs().max().double_value(&[]);
+        println!("{overlap:?}: max difference {max_diff:.2e}");
+        if overlap == Overlap::Hann {
+            assert!(max_diff < 1e-2)
+        }
+    }
+    Ok(())
+}
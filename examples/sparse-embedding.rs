@@ -0,0 +1,34 @@
+// Compares the optimizer steps of a large embedding table trained with dense
+// and with sparse gradients. Each step only looks up a thousand rows, so the
+// sparse gradient avoids both materializing and applying a dense gradient of
+// the size of the whole table.
+//
+// Run with: cargo run --release --example sparse-embedding
+use anyhow::Result;
+use tch::bench::Bench;
+use tch::nn::{self, Module, OptimizerConfig};
+use tch::{Device, Kind, Tensor};
+
+const VOCAB_SIZE: i64 = 1_000_000;
+const EMBEDDING_DIM: i64 = 64;
+const ROWS_PER_STEP: i64 = 1_000;
+
+fn main() -> Result<()> {
+    tch::manual_seed(42);
+    let device = Device::cuda_if_available();
+    let bench = Bench::new(3, 20);
+    for sparse in [false, true] {
+        let vs = nn::VarStore::new(device);
+        let config = nn::EmbeddingConfig { sparse, ..Default::default() };
+        let embeddings = nn::embedding(vs.root(), VOCAB_SIZE, EMBEDDING_DIM, config);
+        let mut opt = nn::Sgd::default().build(&vs, 1e-2)?;
+        let name = if sparse { "sparse sgd step" } else { "dense sgd step" };
+        let result = bench.f_run(name, device, || {
+            let xs = Tensor::randint(VOCAB_SIZE, [ROWS_PER_STEP], (Kind::Int64, device));
+            let loss = embeddings.forward(&xs).square().mean(Kind::Float);
+            opt.backward_step(&loss);
+        })?;
+        println!("{result}");
+    }
+    Ok(())
+}
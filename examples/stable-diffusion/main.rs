@@ -820,7 +820,8 @@ impl SpatialTransformer {
         let inner_dim = n_heads * d_head;
         let group_cfg = nn::GroupNormConfig { eps: 1e-6, affine: true, ..Default::default() };
         let norm = nn::group_norm(&vs / "norm", config.num_groups, in_channels, group_cfg);
-        let conv_cfg = nn::ConvConfig { stride: 1, padding: 0, ..Default::default() };
+        let conv_cfg =
+            nn::ConvConfig { stride: 1, padding: nn::Padding::Explicit(0), ..Default::default() };
         let proj_in = nn::conv2d(&vs / "proj_in", in_channels, inner_dim, 1, conv_cfg);
         let mut transformer_blocks = vec![];
         let vs_tb = &vs / "transformer_blocks";
@@ -948,7 +949,11 @@ impl Downsample2D {
         padding: i64,
     ) -> Self {
         let conv = if use_conv {
-            let config = nn::ConvConfig { stride: 2, padding, ..Default::default() };
+            let config = nn::ConvConfig {
+                stride: 2,
+                padding: nn::Padding::Explicit(padding),
+                ..Default::default()
+            };
             let conv = nn::conv2d(&vs / "conv", in_channels, out_channels, 3, config);
             Some(conv)
         } else {
@@ -981,7 +986,7 @@ struct Upsample2D {
 
 impl Upsample2D {
     fn new(vs: nn::Path, in_channels: i64, out_channels: i64) -> Self {
-        let config = nn::ConvConfig { padding: 1, ..Default::default() };
+        let config = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv = nn::conv2d(&vs / "conv", in_channels, out_channels, 3, config);
         Self { conv }
     }
@@ -1043,7 +1048,8 @@ struct ResnetBlock2D {
 impl ResnetBlock2D {
     fn new(vs: nn::Path, in_channels: i64, config: ResnetBlock2DConfig) -> Self {
         let out_channels = config.out_channels.unwrap_or(in_channels);
-        let conv_cfg = nn::ConvConfig { stride: 1, padding: 1, ..Default::default() };
+        let conv_cfg =
+            nn::ConvConfig { stride: 1, padding: nn::Padding::Explicit(1), ..Default::default() };
         let group_cfg = nn::GroupNormConfig { eps: config.eps, affine: true, ..Default::default() };
         let norm1 = nn::group_norm(&vs / "norm1", config.groups, in_channels, group_cfg);
         let conv1 = nn::conv2d(&vs / "conv1", in_channels, out_channels, 3, conv_cfg);
@@ -1052,7 +1058,11 @@ impl ResnetBlock2D {
         let conv2 = nn::conv2d(&vs / "conv2", out_channels, out_channels, 3, conv_cfg);
         let use_in_shortcut = config.use_in_shortcut.unwrap_or(in_channels != out_channels);
         let conv_shortcut = if use_in_shortcut {
-            let conv_cfg = nn::ConvConfig { stride: 1, padding: 0, ..Default::default() };
+            let conv_cfg = nn::ConvConfig {
+                stride: 1,
+                padding: nn::Padding::Explicit(0),
+                ..Default::default()
+            };
             Some(nn::conv2d(&vs / "conv_shortcut", in_channels, out_channels, 1, conv_cfg))
         } else {
             None
@@ -1430,7 +1440,8 @@ struct Encoder {
 
 impl Encoder {
     fn new(vs: nn::Path, in_channels: i64, out_channels: i64, config: EncoderConfig) -> Self {
-        let conv_cfg = nn::ConvConfig { stride: 1, padding: 1, ..Default::default() };
+        let conv_cfg =
+            nn::ConvConfig { stride: 1, padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv_in =
             nn::conv2d(&vs / "conv_in", in_channels, config.block_out_channels[0], 3, conv_cfg);
         let mut down_blocks = vec![];
@@ -1473,7 +1484,7 @@ impl Encoder {
             group_cfg,
         );
         let conv_out_channels = if config.double_z { 2 * out_channels } else { out_channels };
-        let conv_cfg = nn::ConvConfig { padding: 1, ..Default::default() };
+        let conv_cfg = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv_out =
             nn::conv2d(&vs / "conv_out", last_block_out_channels, conv_out_channels, 3, conv_cfg);
         Self { conv_in, down_blocks, mid_block, conv_norm_out, conv_out, config }
@@ -1519,7 +1530,8 @@ impl Decoder {
     fn new(vs: nn::Path, in_channels: i64, out_channels: i64, config: DecoderConfig) -> Self {
         let n_block_out_channels = config.block_out_channels.len();
         let last_block_out_channels = *config.block_out_channels.last().unwrap();
-        let conv_cfg = nn::ConvConfig { stride: 1, padding: 1, ..Default::default() };
+        let conv_cfg =
+            nn::ConvConfig { stride: 1, padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv_in =
             nn::conv2d(&vs / "conv_in", in_channels, last_block_out_channels, 3, conv_cfg);
         let mid_cfg = UNetMidBlock2DConfig {
@@ -1561,7 +1573,7 @@ impl Decoder {
             config.block_out_channels[0],
             group_cfg,
         );
-        let conv_cfg = nn::ConvConfig { padding: 1, ..Default::default() };
+        let conv_cfg = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv_out =
             nn::conv2d(&vs / "conv_out", config.block_out_channels[0], out_channels, 3, conv_cfg);
         Self { conv_in, up_blocks, mid_block, conv_norm_out, conv_out, config }
@@ -2126,7 +2138,8 @@ impl UNet2DConditionModel {
         let b_channels = config.blocks[0].out_channels;
         let bl_channels = config.blocks.last().unwrap().out_channels;
         let time_embed_dim = b_channels * 4;
-        let conv_cfg = nn::ConvConfig { stride: 1, padding: 1, ..Default::default() };
+        let conv_cfg =
+            nn::ConvConfig { stride: 1, padding: nn::Padding::Explicit(1), ..Default::default() };
         let conv_in = nn::conv2d(&vs / "conv_in", in_channels, b_channels, 3, conv_cfg);
 
         let time_proj =
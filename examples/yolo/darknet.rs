@@ -123,7 +123,8 @@ fn conv(vs: nn::Path, index: usize, p: i64, b: &Block) -> Result<(i64, Bl)> {
         }
         Some(_) | None => (None, true),
     };
-    let conv_cfg = nn::ConvConfig { stride, padding: pad, bias, ..Default::default() };
+    let conv_cfg =
+        nn::ConvConfig { stride, padding: nn::Padding::Explicit(pad), bias, ..Default::default() };
     let vs = &vs / format!("conv_{index}");
     let conv = nn::conv2d(vs, p, filters, size, conv_cfg);
     let leaky = match activation {
@@ -61,6 +61,10 @@ let no_tensor_options =
     ; "randn_like"
     ]
 
+(* The C bindings are generated for these functions but the Rust wrappers are
+   hand-written, e.g. to validate the arguments before calling libtorch. *)
+let hand_written_wrappers = Set.of_list (module String) [ "narrow"; "select" ]
+
 let prefixed_functions =
   Set.of_list
     (module String)
@@ -469,6 +473,21 @@ module Func = struct
       | LayoutOption -> Printf.sprintf "%s.map_or(-1, |s| s.to_i8())" name
       | _ -> name)
     |> String.concat ~sep:",\n                "
+
+  (* The tensor arguments, their metadata is attached to the error returned
+     when the call fails. *)
+  let rust_tensor_args t ~self =
+    List.filter_map t.args ~f:(fun arg ->
+      let name =
+        if Option.value_map self ~default:false ~f:(String.( = ) arg.arg_name)
+        then "self"
+        else rust_name arg.arg_name
+      in
+      match arg.arg_type with
+      | Tensor | TensorOption | TensorList | TensorOptList ->
+        Some (Printf.sprintf "(\"%s\", %s)" name name)
+      | _ -> None)
+    |> String.concat ~sep:", "
 end
 
 exception Not_a_simple_arg
@@ -676,6 +695,7 @@ let write_fallible_wrapper funcs filename =
     pm "}";
     pm "";
     pm "impl Tensor {";
+    let funcs = Map.filter_keys funcs ~f:(fun name -> not (Set.mem hand_written_wrappers name)) in
     Map.iteri funcs ~f:(fun ~key:exported_name ~data:(func : Func.t) ->
       let rust_name = Func.rust_name exported_name in
       let self, rust_args_list = Func.rust_typed_args_list func in
@@ -683,6 +703,9 @@ let write_fallible_wrapper funcs filename =
       pm "    pub fn f_%s%s(" rust_name (Func.type_parameters func);
       pm "        %s" rust_args_list;
       pm "    )%s {" (Func.rust_return_type func ~fallible:true);
+      let op_args =
+        Printf.sprintf "\"%s\", [%s]," rust_name (Func.rust_tensor_args func ~self)
+      in
       List.iter func.args ~f:(fun arg ->
         match arg.arg_type with
         | DoubleOption | Int64Option ->
@@ -691,6 +714,7 @@ let write_fallible_wrapper funcs filename =
       match func.returns with
       | `dynamic ->
         pm "        let c_tensors = unsafe_torch_err!(";
+        pm "            %s" op_args;
         pm "            atg_%s(" exported_name;
         pm "                %s));" (Func.rust_binding_args func ~self);
         pm "        let mut r__ = vec![];";
@@ -706,6 +730,7 @@ let write_fallible_wrapper funcs filename =
         pm "    }"
       | `nothing ->
         pm "        unsafe_torch_err!(";
+        pm "            %s" op_args;
         pm "            atg_%s(" exported_name;
         pm "                %s" (Func.rust_binding_args func ~self);
         pm "            ));";
@@ -714,6 +739,7 @@ let write_fallible_wrapper funcs filename =
       | `fixed ntensors ->
         pm "        let mut c_tensors = [std::ptr::null_mut(); %d];" ntensors;
         pm "        unsafe_torch_err!(";
+        pm "            %s" op_args;
         pm "            atg_%s(c_tensors.as_mut_ptr()," exported_name;
         pm "                %s" (Func.rust_binding_args func ~self);
         pm "            ));";
@@ -735,6 +761,7 @@ let write_fallible_wrapper funcs filename =
         in
         pm "        let return_;";
         pm "        unsafe_torch_err!(";
+        pm "            %s" op_args;
         pm "            return_ = atg_%s(" exported_name;
         pm "                %s" (Func.rust_binding_args func ~self);
         pm "            ));";
@@ -754,6 +781,7 @@ let write_wrapper funcs filename =
     pm "use torch_sys::*;";
     pm "";
     pm "impl Tensor {";
+    let funcs = Map.filter_keys funcs ~f:(fun name -> not (Set.mem hand_written_wrappers name)) in
     Map.iteri funcs ~f:(fun ~key:exported_name ~data:(func : Func.t) ->
       let rust_name = Func.rust_name exported_name in
       let rust_name, fallible_rust_name =
@@ -824,7 +852,6 @@ let methods =
   in
   let ca arg_name arg_type = { Func.arg_name; arg_type; default_value = None } in
   [ c "grad" [ ca "self" Tensor ]
-  ; c "set_requires_grad" [ ca "self" Tensor; ca "r" Bool ]
   ; c "toType" [ ca "self" Tensor; ca "scalar_type" ScalarType ]
   ; c "to" [ ca "self" Tensor; ca "device" Device ]
   ]
@@ -0,0 +1,246 @@
+//! Gradient scaling for mixed precision training.
+//!
+//! When running the forward pass in half precision with `autocast`, small
+//! gradients can underflow to zero. A `GradScaler` multiplies the loss by a
+//! scale factor before the backward pass and divides the gradients by the same
+//! factor before the optimizer step. The scale factor is dynamically adjusted:
+//! it is reduced when some gradients are infinite or NaN, in which case the
+//! optimizer step is skipped, and it is increased after a given number of
+//! steps without any overflow.
+//!
+//! ```no_run
+//! # use tch::{amp::GradScaler, nn, nn::ModuleT, nn::OptimizerConfig, Device, Kind, Tensor};
+//! # fn main() -> Result<(), tch::TchError> {
+//! # let device = Device::Cuda(0);
+//! # let vs = nn::VarStore::new(device);
+//! # let model = nn::linear(vs.root(), 4, 1, Default::default());
+//! # let mut opt = nn::Sgd::default().build(&vs, 1e-3)?;
+//! # let (xs, ys) = (Tensor::randn([8, 4], (Kind::Float, device)), Tensor::zeros([8, 1], (Kind::Float, device)));
+//! let mut scaler = GradScaler::default();
+//! for _step in 0..100 {
+//!     let loss = tch::autocast(device, Kind::Half, true, || {
+//!         model.forward_t(&xs, true).mse_loss(&ys, tch::Reduction::Mean)
+//!     });
+//!     opt.zero_grad();
+//!     scaler.scale(&loss)?.backward();
+//!     // Unscaling is only required when the gradients have to be inspected or
+//!     // modified before the step, e.g. for clipping.
+//!     scaler.unscale(&mut opt)?;
+//!     opt.clip_grad_norm(1.0);
+//!     scaler.step(&mut opt)?;
+//!     scaler.update()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::nn::Optimizer;
+use crate::{Device, Kind, TchError, Tensor};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Unscaled,
+    Stepped,
+}
+
+#[derive(Debug)]
+struct OptimizerState {
+    stage: Stage,
+    // Singleton tensors, one per gradient device, that are non-zero when an
+    // infinite or NaN gradient has been found.
+    found_inf_per_device: HashMap<Device, Tensor>,
+}
+
+/// Scales the loss and unscales the gradients to avoid underflows in
+/// mixed precision training, see the module documentation.
+#[derive(Debug)]
+pub struct GradScaler {
+    init_scale: f64,
+    growth_factor: f64,
+    backoff_factor: f64,
+    growth_interval: i64,
+    // These are lazily created on the device of the first scaled loss.
+    scale: Option<Tensor>,
+    growth_tracker: Option<Tensor>,
+    // The optimizers are identified by their address, this state is reset by `update`.
+    per_optimizer_states: HashMap<usize, OptimizerState>,
+}
+
+impl Default for GradScaler {
+    fn default() -> Self {
+        GradScaler::new(65536.0, 2.0, 0.5, 2000)
+    }
+}
+
+fn optimizer_id(opt: &Optimizer) -> usize {
+    opt as *const Optimizer as usize
+}
+
+impl GradScaler {
+    /// Creates a new gradient scaler.
+    ///
+    /// The scale starts at `init_scale`, it is multiplied by `backoff_factor` on
+    /// steps where some gradients are not finite and by `growth_factor` after
+    /// `growth_interval` consecutive steps with finite gradients.
+    pub fn new(
+        init_scale: f64,
+        growth_factor: f64,
+        backoff_factor: f64,
+        growth_interval: i64,
+    ) -> GradScaler {
+        GradScaler {
+            init_scale,
+            growth_factor,
+            backoff_factor,
+            growth_interval,
+            scale: None,
+            growth_tracker: None,
+            per_optimizer_states: HashMap::new(),
+        }
+    }
+
+    /// Returns the current scale factor.
+    ///
+    /// This synchronizes with the device holding the scale.
+    pub fn get_scale(&self) -> f64 {
+        match &self.scale {
+            None => self.init_scale,
+            Some(scale) => f64::try_from(scale).unwrap(),
+        }
+    }
+
+    fn scale_tensor(&self) -> Result<&Tensor, TchError> {
+        self.scale.as_ref().ok_or_else(|| {
+            TchError::Torch("GradScaler::scale has not been called on any loss".to_string())
+        })
+    }
+
+    /// Multiplies the loss by the scale factor, the backward pass should then be
+    /// run on the returned tensor.
+    pub fn scale(&mut self, loss: &Tensor) -> Result<Tensor, TchError> {
+        let device = loss.device();
+        if self.scale.is_none() {
+            self.scale = Some(Tensor::f_full([1], self.init_scale, (Kind::Float, device))?);
+            self.growth_tracker = Some(Tensor::f_zeros([1], (Kind::Int, device))?);
+        }
+        let scale = self.scale_tensor()?.f_to_device(device)?;
+        loss.f_mul(&scale)
+    }
+
+    /// Divides the gradients of the optimizer variables by the scale factor.
+    ///
+    /// This is done automatically by `step` but can be called explicitly before it
+    /// to inspect or modify the gradients, e.g. to clip them. This can only be
+    /// called once per optimizer between two calls to `update`.
+    pub fn unscale(&mut self, opt: &mut Optimizer) -> Result<(), TchError> {
+        let id = optimizer_id(opt);
+        if let Some(state) = self.per_optimizer_states.get(&id) {
+            let msg = match state.stage {
+                Stage::Unscaled => "unscale has already been called on this optimizer",
+                Stage::Stepped => "unscale is being called after step",
+            };
+            return Err(TchError::Torch(format!("{msg} since the last update")));
+        }
+        // The inverse is computed in double precision to limit rounding errors.
+        let inv_scale =
+            self.scale_tensor()?.f_to_kind(Kind::Double)?.f_reciprocal()?.f_to_kind(Kind::Float)?;
+        let mut found_inf_per_device: HashMap<Device, Tensor> = HashMap::new();
+        crate::no_grad(|| {
+            for var in opt.trainable_variables() {
+                let mut grad = var.grad();
+                if !grad.defined() {
+                    continue;
+                }
+                let device = grad.device();
+                let found_inf = match found_inf_per_device.get_mut(&device) {
+                    Some(found_inf) => found_inf,
+                    None => {
+                        let found_inf = Tensor::f_zeros([1], (Kind::Float, device))?;
+                        found_inf_per_device.entry(device).or_insert(found_inf)
+                    }
+                };
+                let inv_scale = inv_scale.f_to_device(device)?;
+                if device.is_cuda() {
+                    grad.f_internal_amp_non_finite_check_and_unscale(found_inf, &inv_scale)?
+                } else {
+                    let non_finite = grad.f_isfinite()?.f_logical_not()?.f_any()?;
+                    let _ = found_inf.f_add_(&non_finite)?;
+                    let _ = grad.f_mul_(&inv_scale)?;
+                }
+            }
+            Ok::<_, TchError>(())
+        })?;
+        let state = OptimizerState { stage: Stage::Unscaled, found_inf_per_device };
+        self.per_optimizer_states.insert(id, state);
+        Ok(())
+    }
+
+    /// Unscales the gradients if needed and runs the optimizer step, unless some
+    /// of the gradients are infinite or NaN.
+    ///
+    /// Returns true if the optimizer step has been run. This synchronizes with the
+    /// gradient devices to check for non-finite values.
+    pub fn step(&mut self, opt: &mut Optimizer) -> Result<bool, TchError> {
+        let id = optimizer_id(opt);
+        match self.per_optimizer_states.get(&id).map(|s| s.stage) {
+            Some(Stage::Stepped) => {
+                return Err(TchError::Torch(
+                    "step has already been called on this optimizer since the last update".into(),
+                ))
+            }
+            Some(Stage::Unscaled) => {}
+            None => self.unscale(opt)?,
+        }
+        let state = self.per_optimizer_states.get_mut(&id).unwrap();
+        let mut found_inf = 0f64;
+        for t in state.found_inf_per_device.values() {
+            found_inf += f64::try_from(t)?
+        }
+        state.stage = Stage::Stepped;
+        if found_inf == 0. {
+            opt.step();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Updates the scale factor, this should be called once per iteration after
+    /// the optimizer steps.
+    ///
+    /// The update is performed using tensor operations so that it does not have
+    /// to synchronize with the device holding the scale.
+    pub fn update(&mut self) -> Result<(), TchError> {
+        let states = std::mem::take(&mut self.per_optimizer_states);
+        let (scale, growth_tracker) = match (&mut self.scale, &mut self.growth_tracker) {
+            (Some(scale), Some(growth_tracker)) => (scale, growth_tracker),
+            _ => return Ok(()),
+        };
+        let device = scale.device();
+        crate::no_grad(|| {
+            let mut found_inf = Tensor::f_zeros([1], (Kind::Float, device))?;
+            for state in states.values() {
+                for t in state.found_inf_per_device.values() {
+                    let _ = found_inf.f_add_(&t.f_to_device(device)?)?;
+                }
+            }
+            let found_inf = found_inf.f_gt(0.)?;
+            // The same logic as the _amp_update_scale_ kernel: on overflow, back off
+            // and reset the tracker, otherwise grow the scale every growth_interval
+            // steps if the grown scale is finite.
+            let tracker = growth_tracker
+                .f_add_scalar(1)?
+                .f_where_scalarother(&found_inf.f_logical_not()?, 0)?;
+            let grow = tracker.f_ge(self.growth_interval)?;
+            let grown_scale = scale.f_mul_scalar(self.growth_factor)?;
+            let grown_scale = grown_scale.f_where_self(&grown_scale.f_isfinite()?, scale)?;
+            let new_scale = scale
+                .f_mul_scalar(self.backoff_factor)?
+                .f_where_self(&found_inf, &grown_scale.f_where_self(&grow, scale)?)?;
+            let tracker = tracker.f_where_scalarother(&grow.f_logical_not()?, 0)?;
+            scale.f_copy_(&new_scale)?;
+            growth_tracker.f_copy_(&tracker)?;
+            Ok(())
+        })
+    }
+}
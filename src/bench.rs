@@ -0,0 +1,370 @@
+//! A benchmark harness to time operations and models across devices.
+//!
+//! On CUDA devices each iteration is timed with CUDA events recorded on the
+//! current stream of the device, on other devices the wall-clock time is
+//! measured after waiting for the device to complete its work.
+//!
+//! ```no_run
+//! # use tch::{bench, Device, Kind, Tensor};
+//! let comparison = bench::Bench::new(5, 50).compare(
+//!     "matmul",
+//!     &[(Device::Cpu, Kind::Float), (Device::Cuda(0), Kind::Half)],
+//!     |device, kind| {
+//!         let xs = Tensor::randn([1024, 1024], (kind, device));
+//!         move || {
+//!             let _ = xs.matmul(&xs);
+//!         }
+//!     },
+//! );
+//! println!("{comparison}");
+//! std::fs::write("bench.json", comparison.to_json()).unwrap();
+//! ```
+use crate::cuda::{CudaEvent, CudaStream};
+use crate::profiler::{self, Profile, ProfilerConfig};
+use crate::{Device, Kind, Mps, TchError};
+use std::time::Instant;
+
+/// The timings of a benchmark, all durations are in milliseconds.
+#[derive(Debug)]
+pub struct BenchResult {
+    pub name: String,
+    pub device: Device,
+    /// The kind the benchmark was run with, only set by comparisons.
+    pub kind: Option<Kind>,
+    /// The number of measured iterations.
+    pub iters: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub std_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    /// The profile of the slowest profiled iteration, only set when the
+    /// benchmark is run with `Bench::profile_slowest`.
+    pub trace: Option<Profile>,
+}
+
+fn device_name(device: Device) -> String {
+    match device {
+        Device::Cpu => "cpu".to_string(),
+        Device::Cuda(index) => format!("cuda:{index}"),
+        Device::Mps => "mps".to_string(),
+        Device::Vulkan => "vulkan".to_string(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// Non-finite values are not valid json numbers.
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        format!("{v}")
+    } else {
+        "null".to_string()
+    }
+}
+
+impl BenchResult {
+    /// Returns the result as a json object, the trace is not included.
+    pub fn to_json(&self) -> String {
+        let kind = self.kind.map_or("null".to_string(), |k| json_string(&format!("{k:?}")));
+        format!(
+            "{{\"name\":{},\"device\":{},\"kind\":{kind},\"iters\":{},\"mean_ms\":{},\"p50_ms\":{},\"p95_ms\":{},\"std_ms\":{},\"min_ms\":{},\"max_ms\":{}}}",
+            json_string(&self.name),
+            json_string(&device_name(self.device)),
+            self.iters,
+            json_number(self.mean_ms),
+            json_number(self.p50_ms),
+            json_number(self.p95_ms),
+            json_number(self.std_ms),
+            json_number(self.min_ms),
+            json_number(self.max_ms),
+        )
+    }
+}
+
+impl std::fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}): mean {:.4}ms p50 {:.4}ms p95 {:.4}ms std {:.4}ms over {} iterations",
+            self.name,
+            device_name(self.device),
+            self.mean_ms,
+            self.p50_ms,
+            self.p95_ms,
+            self.std_ms,
+            self.iters
+        )
+    }
+}
+
+/// The results of running the same benchmark on multiple devices and kinds.
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub results: Vec<BenchResult>,
+}
+
+impl Comparison {
+    /// Returns the results as a json array.
+    pub fn to_json(&self) -> String {
+        let results: Vec<String> = self.results.iter().map(|r| r.to_json()).collect();
+        format!("[{}]", results.join(","))
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    /// Prints a table with one row per result, the last column is the mean
+    /// time relative to the first result.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name_width = self.results.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+        write!(
+            f,
+            "{:name_width$} {:>8} {:>8} {:>12} {:>12} {:>12} {:>12} {:>8}",
+            "name", "device", "kind", "mean (ms)", "p50 (ms)", "p95 (ms)", "std (ms)", "relative"
+        )?;
+        let baseline = self.results.first().map(|r| r.mean_ms);
+        for r in self.results.iter() {
+            let kind = r.kind.map_or(String::new(), |k| format!("{k:?}"));
+            let relative = baseline.map_or(1., |b| r.mean_ms / b);
+            write!(
+                f,
+                "\n{:name_width$} {:>8} {:>8} {:>12.4} {:>12.4} {:>12.4} {:>12.4} {:>7.2}x",
+                r.name,
+                device_name(r.device),
+                kind,
+                r.mean_ms,
+                r.p50_ms,
+                r.p95_ms,
+                r.std_ms,
+                relative
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The timer for a single iteration on a given device.
+enum Timer {
+    Cuda(CudaStream),
+    WallClock(Device),
+}
+
+impl Timer {
+    fn new(device: Device) -> Result<Timer, TchError> {
+        match device {
+            Device::Cuda(_) => Ok(Timer::Cuda(CudaStream::current(device)?)),
+            _ => Ok(Timer::WallClock(device)),
+        }
+    }
+
+    fn synchronize(&self) -> Result<(), TchError> {
+        match self {
+            Timer::Cuda(stream) => stream.synchronize(),
+            Timer::WallClock(Device::Mps) => {
+                Mps::synchronize();
+                Ok(())
+            }
+            Timer::WallClock(_) => Ok(()),
+        }
+    }
+
+    // Returns the time taken by `f` in milliseconds.
+    fn time<F: FnMut()>(&self, f: &mut F) -> Result<f64, TchError> {
+        match self {
+            Timer::Cuda(stream) => {
+                let mut start = CudaEvent::new(true)?;
+                let mut end = CudaEvent::new(true)?;
+                start.record(stream)?;
+                f();
+                end.record(stream)?;
+                end.synchronize()?;
+                Ok(start.elapsed_time(&end)? as f64)
+            }
+            Timer::WallClock(_) => {
+                let start = Instant::now();
+                f();
+                self.synchronize()?;
+                Ok(start.elapsed().as_secs_f64() * 1000.)
+            }
+        }
+    }
+}
+
+// The nearest-rank percentile of some sorted samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarize(name: &str, device: Device, mut samples: Vec<f64>) -> BenchResult {
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let var = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / n;
+    BenchResult {
+        name: name.to_string(),
+        device,
+        kind: None,
+        iters: samples.len(),
+        mean_ms: mean,
+        p50_ms: percentile(&samples, 50.),
+        p95_ms: percentile(&samples, 95.),
+        std_ms: var.sqrt(),
+        min_ms: samples.first().copied().unwrap_or(f64::NAN),
+        max_ms: samples.last().copied().unwrap_or(f64::NAN),
+        trace: None,
+    }
+}
+
+/// The benchmark settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bench {
+    warmup_iters: usize,
+    measure_iters: usize,
+    profile: Option<ProfilerConfig>,
+}
+
+impl Bench {
+    /// Runs `warmup_iters` untimed iterations before timing `measure_iters`
+    /// iterations.
+    pub fn new(warmup_iters: usize, measure_iters: usize) -> Bench {
+        Bench { warmup_iters, measure_iters, profile: None }
+    }
+
+    /// Attaches a profiler trace of the slowest iteration to the results.
+    ///
+    /// The timed iterations are run without the profiler. They are followed
+    /// by the same number of iterations each run with the profiler, and the
+    /// profile of the slowest of these is kept.
+    pub fn profile_slowest(mut self, config: ProfilerConfig) -> Self {
+        self.profile = Some(config);
+        self
+    }
+
+    pub fn f_run<F: FnMut()>(
+        &self,
+        name: &str,
+        device: Device,
+        mut f: F,
+    ) -> Result<BenchResult, TchError> {
+        let timer = Timer::new(device)?;
+        for _ in 0..self.warmup_iters {
+            f()
+        }
+        timer.synchronize()?;
+        let samples =
+            (0..self.measure_iters).map(|_| timer.time(&mut f)).collect::<Result<Vec<_>, _>>()?;
+        let mut result = summarize(name, device, samples);
+        if let Some(config) = self.profile.as_ref() {
+            let mut slowest: Option<(f64, Profile)> = None;
+            for _ in 0..self.measure_iters {
+                let mut elapsed = Ok(0.);
+                let profile = profiler::profile(config.clone(), || elapsed = timer.time(&mut f))?;
+                let elapsed = elapsed?;
+                let is_slowest = match slowest.as_ref() {
+                    None => true,
+                    Some((max, _)) => elapsed > *max,
+                };
+                if is_slowest {
+                    slowest = Some((elapsed, profile))
+                }
+            }
+            result.trace = slowest.map(|(_, profile)| profile)
+        }
+        Ok(result)
+    }
+
+    pub fn run<F: FnMut()>(&self, name: &str, device: Device, f: F) -> BenchResult {
+        self.f_run(name, device, f).unwrap()
+    }
+
+    /// Runs the same benchmark for each device and kind. The `setup` closure
+    /// is called once per target, outside of the timed region, and returns
+    /// the closure to benchmark.
+    pub fn f_compare<S, F>(
+        &self,
+        name: &str,
+        targets: &[(Device, Kind)],
+        mut setup: S,
+    ) -> Result<Comparison, TchError>
+    where
+        S: FnMut(Device, Kind) -> F,
+        F: FnMut(),
+    {
+        let mut results = Vec::with_capacity(targets.len());
+        for &(device, kind) in targets {
+            let mut result = self.f_run(name, device, setup(device, kind))?;
+            result.kind = Some(kind);
+            results.push(result)
+        }
+        Ok(Comparison { results })
+    }
+
+    pub fn compare<S, F>(&self, name: &str, targets: &[(Device, Kind)], setup: S) -> Comparison
+    where
+        S: FnMut(Device, Kind) -> F,
+        F: FnMut(),
+    {
+        self.f_compare(name, targets, setup).unwrap()
+    }
+}
+
+/// Times `measure_iters` iterations of `f` on `device` after running
+/// `warmup_iters` untimed iterations.
+pub fn f_bench<F: FnMut()>(
+    name: &str,
+    device: Device,
+    warmup_iters: usize,
+    measure_iters: usize,
+    f: F,
+) -> Result<BenchResult, TchError> {
+    Bench::new(warmup_iters, measure_iters).f_run(name, device, f)
+}
+
+/// Times `measure_iters` iterations of `f` on `device` after running
+/// `warmup_iters` untimed iterations.
+pub fn bench<F: FnMut()>(
+    name: &str,
+    device: Device,
+    warmup_iters: usize,
+    measure_iters: usize,
+    f: F,
+) -> BenchResult {
+    f_bench(name, device, warmup_iters, measure_iters, f).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let r = summarize("test", Device::Cpu, vec![4., 1., 3., 2., 5.]);
+        assert_eq!(r.iters, 5);
+        assert_eq!(r.mean_ms, 3.);
+        assert_eq!(r.p50_ms, 3.);
+        assert_eq!(r.p95_ms, 5.);
+        assert_eq!(r.std_ms, 2f64.sqrt());
+        assert_eq!((r.min_ms, r.max_ms), (1., 5.));
+        assert!(r.to_json().starts_with("{\"name\":\"test\",\"device\":\"cpu\",\"kind\":null,"));
+        assert_eq!(json_string("a\"b\\c\n\t"), "\"a\\\"b\\\\c\\n\\u0009\"");
+    }
+}
@@ -0,0 +1,229 @@
+//! Calibration of classifier probabilities.
+//!
+//! Temperature scaling divides the logits by a single temperature fitted on
+//! held-out data, see "On Calibration of Modern Neural Networks", Guo et al.
+//! 2017. This changes the confidences but not the predicted classes.
+use crate::train::TensorboardWriter;
+use crate::{Kind, TchError, Tensor};
+
+// The inverse temperature is searched in this range.
+const MIN_INV_TEMPERATURE: f64 = 1e-2;
+const MAX_INV_TEMPERATURE: f64 = 1e2;
+
+/// Scales logits by a fitted temperature.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureScaler {
+    temperature: f64,
+}
+
+// The negative log-likelihood of the labels for the logits scaled by the
+// inverse temperature `s`, along with its first and second derivatives in `s`.
+// The log-likelihood is concave in `s` so Newton steps are well behaved.
+struct Nll<'a> {
+    logits: &'a Tensor,
+    label_logits: &'a Tensor,
+}
+
+impl Nll<'_> {
+    fn value(&self, s: f64) -> Result<f64, TchError> {
+        let lse = self.logits.f_mul_scalar(s)?.f_logsumexp(1, false)?;
+        let nll = lse.f_sub(&self.label_logits.f_mul_scalar(s)?)?.f_mean(Kind::Double)?;
+        f64::try_from(nll)
+    }
+
+    fn derivatives(&self, s: f64) -> Result<(f64, f64), TchError> {
+        let p = self.logits.f_mul_scalar(s)?.f_softmax(1, Kind::Double)?;
+        let mean = p.f_mul(self.logits)?.f_sum_dim_intlist(1, false, Kind::Double)?;
+        let sq = p.f_mul(&self.logits.f_square()?)?.f_sum_dim_intlist(1, false, Kind::Double)?;
+        let grad = mean.f_sub(self.label_logits)?.f_mean(Kind::Double)?;
+        let hess = sq.f_sub(&mean.f_square()?)?.f_mean(Kind::Double)?;
+        Ok((f64::try_from(grad)?, f64::try_from(hess)?))
+    }
+}
+
+fn check_inputs(xs: &Tensor, labels: &Tensor, what: &str) -> Result<(), TchError> {
+    match (xs.size().as_slice(), labels.size().as_slice()) {
+        ([n, _], [m]) if n == m => Ok(()),
+        (xs_size, labels_size) => Err(TchError::Shape(format!(
+            "expected {what} of shape [batch, classes] and labels of shape [batch], got {xs_size:?} and {labels_size:?}"
+        ))),
+    }
+}
+
+impl TemperatureScaler {
+    pub fn new(temperature: f64) -> TemperatureScaler {
+        TemperatureScaler { temperature }
+    }
+
+    pub fn temperature(&self) -> f64 {
+        self.temperature
+    }
+
+    /// Fits the temperature minimizing the negative log-likelihood of
+    /// `labels`, with `logits` of shape `[batch, classes]` and `labels` of
+    /// shape `[batch]`. The labels must contain at least two classes.
+    ///
+    /// The inverse temperature is initialized with a grid search and refined
+    /// with Newton steps, it is clamped between 1e-2 and 1e2.
+    pub fn fit(logits: &Tensor, labels: &Tensor) -> Result<TemperatureScaler, TchError> {
+        check_inputs(logits, labels, "logits")?;
+        let _no_grad = crate::no_grad_guard();
+        let labels = labels.f_to_device(logits.device())?.f_to_kind(Kind::Int64)?;
+        let min_label = i64::try_from(labels.f_min()?)?;
+        let max_label = i64::try_from(labels.f_max()?)?;
+        if min_label == max_label {
+            return Err(TchError::Shape(
+                "temperature scaling requires labels with at least two classes".to_string(),
+            ));
+        }
+        let logits = logits.f_to_kind(Kind::Double)?;
+        let label_logits = logits.f_gather(1, &labels.f_unsqueeze(1)?, false)?.f_squeeze_dim(1)?;
+        let nll = Nll { logits: &logits, label_logits: &label_logits };
+
+        let grid = (0..=40).map(|i| 10f64.powf(-2. + i as f64 / 10.));
+        let mut best = (1., nll.value(1.)?);
+        for s in grid {
+            let value = nll.value(s)?;
+            if value < best.1 {
+                best = (s, value)
+            }
+        }
+        let (mut s, mut value) = best;
+        for _ in 0..50 {
+            let (grad, hess) = nll.derivatives(s)?;
+            if grad.abs() < 1e-10 || hess <= 0. {
+                break;
+            }
+            // Damped Newton step, halved until the objective decreases.
+            let mut step = grad / hess;
+            let mut improved = false;
+            for _ in 0..30 {
+                let next = (s - step).clamp(MIN_INV_TEMPERATURE, MAX_INV_TEMPERATURE);
+                let next_value = nll.value(next)?;
+                if next_value <= value {
+                    improved = next != s;
+                    (s, value) = (next, next_value);
+                    break;
+                }
+                step /= 2.
+            }
+            if !improved || step.abs() < 1e-12 * s {
+                break;
+            }
+        }
+        Ok(TemperatureScaler { temperature: 1. / s })
+    }
+
+    /// Divides the logits by the temperature.
+    pub fn f_apply(&self, logits: &Tensor) -> Result<Tensor, TchError> {
+        logits.f_div_scalar(self.temperature)
+    }
+
+    /// Divides the logits by the temperature.
+    pub fn apply(&self, logits: &Tensor) -> Tensor {
+        self.f_apply(logits).unwrap()
+    }
+}
+
+/// The data of a reliability diagram: the predictions are grouped in bins of
+/// equal width by confidence, i.e. by the probability of the predicted class.
+///
+/// The tensors have one double element per bin, the confidences and
+/// accuracies of empty bins are 0.
+#[derive(Debug)]
+pub struct ReliabilityDiagram {
+    /// The mean confidence of the predictions in each bin.
+    pub confidences: Tensor,
+    /// The fraction of correct predictions in each bin.
+    pub accuracies: Tensor,
+    /// The number of predictions in each bin.
+    pub counts: Tensor,
+}
+
+impl ReliabilityDiagram {
+    /// The expected calibration error, i.e. the average over the bins of the
+    /// gap between accuracy and confidence weighted by the bin counts.
+    pub fn f_expected_calibration_error(&self) -> Result<f64, TchError> {
+        let gaps = self.accuracies.f_sub(&self.confidences)?.f_abs()?;
+        let total = f64::try_from(self.counts.f_sum(Kind::Double)?)?;
+        let weighted = f64::try_from(gaps.f_mul(&self.counts)?.f_sum(Kind::Double)?)?;
+        Ok(if total > 0. { weighted / total } else { 0. })
+    }
+
+    /// The expected calibration error, see `f_expected_calibration_error`.
+    pub fn expected_calibration_error(&self) -> f64 {
+        self.f_expected_calibration_error().unwrap()
+    }
+
+    /// Writes the accuracy and confidence of each bin as scalars using the
+    /// bin index as step, under `{tag}/accuracy` and `{tag}/confidence`.
+    pub fn write_tensorboard(
+        &self,
+        writer: &mut TensorboardWriter,
+        tag: &str,
+    ) -> Result<(), TchError> {
+        let accuracies = Vec::<f64>::try_from(&self.accuracies)?;
+        let confidences = Vec::<f64>::try_from(&self.confidences)?;
+        for (bin, (accuracy, confidence)) in accuracies.iter().zip(confidences.iter()).enumerate() {
+            writer.add_scalar(&format!("{tag}/accuracy"), *accuracy, bin as i64)?;
+            writer.add_scalar(&format!("{tag}/confidence"), *confidence, bin as i64)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes a reliability diagram with `n_bins` bins from probabilities of
+/// shape `[batch, classes]` and labels of shape `[batch]`. The bin `i` holds
+/// the confidences in `(i / n_bins, (i + 1) / n_bins]`.
+pub fn f_reliability_diagram(
+    probs: &Tensor,
+    labels: &Tensor,
+    n_bins: i64,
+) -> Result<ReliabilityDiagram, TchError> {
+    check_inputs(probs, labels, "probabilities")?;
+    if n_bins <= 0 {
+        return Err(TchError::Shape(format!("the number of bins must be positive, got {n_bins}")));
+    }
+    let _no_grad = crate::no_grad_guard();
+    let device = probs.device();
+    let (confidences, preds) = probs.f_to_kind(Kind::Double)?.f_max_dim(1, false)?;
+    let correct = preds.f_eq_tensor(&labels.f_to_device(device)?)?.f_to_kind(Kind::Double)?;
+    let bins = confidences
+        .f_mul_scalar(n_bins as f64)?
+        .f_ceil()?
+        .f_sub_scalar(1.)?
+        .f_clamp(0., (n_bins - 1) as f64)?
+        .f_to_kind(Kind::Int64)?;
+    let zeros = Tensor::f_zeros([n_bins], (Kind::Double, device))?;
+    let counts = zeros.f_index_add(0, &bins, &confidences.f_ones_like()?)?;
+    let sum_confidences = zeros.f_index_add(0, &bins, &confidences)?;
+    let sum_correct = zeros.f_index_add(0, &bins, &correct)?;
+    let denominator = counts.f_clamp_min(1.)?;
+    Ok(ReliabilityDiagram {
+        confidences: sum_confidences.f_div(&denominator)?,
+        accuracies: sum_correct.f_div(&denominator)?,
+        counts,
+    })
+}
+
+/// Computes a reliability diagram, see `f_reliability_diagram`.
+pub fn reliability_diagram(probs: &Tensor, labels: &Tensor, n_bins: i64) -> ReliabilityDiagram {
+    f_reliability_diagram(probs, labels, n_bins).unwrap()
+}
+
+/// The expected calibration error of probabilities of shape
+/// `[batch, classes]` for labels of shape `[batch]`, using `n_bins` bins of
+/// equal width.
+pub fn f_expected_calibration_error(
+    probs: &Tensor,
+    labels: &Tensor,
+    n_bins: i64,
+) -> Result<f64, TchError> {
+    f_reliability_diagram(probs, labels, n_bins)?.f_expected_calibration_error()
+}
+
+/// The expected calibration error, see `f_expected_calibration_error`.
+pub fn expected_calibration_error(probs: &Tensor, labels: &Tensor, n_bins: i64) -> f64 {
+    f_expected_calibration_error(probs, labels, n_bins).unwrap()
+}
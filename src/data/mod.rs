@@ -0,0 +1,913 @@
+//! Dataset iterators.
+pub mod sampler;
+
+use crate::{kind, kind::Kind, Device, IndexOp, SharedTensor, TchError, Tensor};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+
+pub use sampler::Sampler;
+
+/// A function applied to each mini-batch of an `Iter2`.
+type BatchFn = Box<dyn FnMut((Tensor, Tensor)) -> (Tensor, Tensor) + Send>;
+
+/// An iterator over a pair of tensors which have the same first dimension
+/// size.
+/// The typical use case is to iterate over batches. Each batch is a pair
+/// containing a (potentially random) slice of each of the two input
+/// tensors.
+pub struct Iter2 {
+    xs: Tensor,
+    ys: Tensor,
+    batch_index: i64,
+    batch_size: i64,
+    total_size: i64,
+    device: Device,
+    return_smaller_last_batch: bool,
+    rng: Option<StdRng>,
+    batch_fn: Option<BatchFn>,
+    prefetch: bool,
+    prefetch_thread: Option<PrefetchThread>,
+}
+
+// The thread producing the mini-batches when prefetching.
+struct PrefetchThread {
+    receiver: Option<mpsc::Receiver<Result<(Tensor, Tensor), TchError>>>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for PrefetchThread {
+    fn drop(&mut self) {
+        // Dropping the receiver makes the pending send fail so that the thread
+        // exits, it is then joined rather than left running in the background.
+        drop(self.receiver.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for Iter2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Iter2")
+            .field("xs", &self.xs)
+            .field("ys", &self.ys)
+            .field("batch_index", &self.batch_index)
+            .field("batch_size", &self.batch_size)
+            .field("total_size", &self.total_size)
+            .field("device", &self.device)
+            .field("return_smaller_last_batch", &self.return_smaller_last_batch)
+            .field("prefetch", &self.prefetch)
+            .finish_non_exhaustive()
+    }
+}
+
+// The start and the size of a mini-batch, or None once the iteration is over.
+fn batch_range(
+    batch_index: i64,
+    batch_size: i64,
+    total_size: i64,
+    return_smaller_last_batch: bool,
+) -> Option<(i64, i64)> {
+    let start = batch_index * batch_size;
+    let size = std::cmp::min(batch_size, total_size - start);
+    if size <= 0 || (!return_smaller_last_batch && size < batch_size) {
+        None
+    } else {
+        Some((start, size))
+    }
+}
+
+// Extracts a mini-batch, applies the batch function if any, and moves the
+// result to the device.
+fn make_batch(
+    xs: &Tensor,
+    ys: &Tensor,
+    (start, size): (i64, i64),
+    batch_fn: Option<&mut BatchFn>,
+    device: Device,
+) -> Result<(Tensor, Tensor), TchError> {
+    let batch = (xs.f_i(start..start + size)?, ys.f_i(start..start + size)?);
+    let (xs, ys) = match batch_fn {
+        Some(batch_fn) => batch_fn(batch),
+        None => batch,
+    };
+    Ok((xs.f_to_device(device)?, ys.f_to_device(device)?))
+}
+
+impl Iter2 {
+    /// Returns a new iterator.
+    ///
+    /// This takes as input two tensors which first dimension must match. The
+    /// returned iterator can be used to range over mini-batches of data of
+    /// specified size.
+    /// An error is returned if `xs` and `ys` have different first dimension
+    /// sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `xs` - the features to be used by the model.
+    /// * `ys` - the targets that the model attempts to predict.
+    /// * `batch_size` - the size of batches to be returned.
+    pub fn f_new(xs: &Tensor, ys: &Tensor, batch_size: i64) -> Result<Iter2, TchError> {
+        let total_size = xs.size()[0];
+        if ys.size()[0] != total_size {
+            return Err(TchError::Shape(format!(
+                "different dimension for the two inputs {xs:?} {ys:?}"
+            )));
+        }
+        Ok(Iter2 {
+            xs: xs.shallow_clone(),
+            ys: ys.shallow_clone(),
+            batch_index: 0,
+            batch_size,
+            total_size,
+            device: Device::Cpu,
+            return_smaller_last_batch: false,
+            rng: None,
+            batch_fn: None,
+            prefetch: false,
+            prefetch_thread: None,
+        })
+    }
+
+    /// Returns a new iterator.
+    ///
+    /// This takes as input two tensors which first dimension must match. The
+    /// returned iterator can be used to range over mini-batches of data of
+    /// specified size.
+    /// Panics if `xs` and `ys` have different first dimension sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `xs` - the features to be used by the model.
+    /// * `ys` - the targets that the model attempts to predict.
+    /// * `batch_size` - the size of batches to be returned.
+    pub fn new(xs: &Tensor, ys: &Tensor, batch_size: i64) -> Iter2 {
+        Iter2::f_new(xs, ys, batch_size).unwrap()
+    }
+
+    /// Seeds the random number generator used by `shuffle`, so that the order
+    /// of the mini-batches is reproducible. This has to be called before
+    /// `shuffle`.
+    pub fn seed(&mut self, seed: u64) -> &mut Iter2 {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Shuffles the dataset.
+    ///
+    /// The iterator would still run over the whole dataset but the order in
+    /// which elements are grouped in mini-batches is randomized.
+    pub fn shuffle(&mut self) -> &mut Iter2 {
+        let index = match self.rng.as_mut() {
+            Some(rng) => {
+                let mut index: Vec<i64> = (0..self.total_size).collect();
+                index.shuffle(rng);
+                Tensor::from_slice(&index).to_device(self.xs.device())
+            }
+            None => Tensor::randperm(self.total_size, (Kind::Int64, self.device)),
+        };
+        self.xs = self.xs.index_select(0, &index);
+        self.ys = self.ys.index_select(0, &index);
+        self
+    }
+
+    /// Transfers the mini-batches to a specified device.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_device(&mut self, device: Device) -> &mut Iter2 {
+        self.device = device;
+        self
+    }
+
+    /// Transfers the mini-batches to a specified device, preparing the next
+    /// mini-batch in a background thread while the current one is processed.
+    pub fn prefetch_to(&mut self, device: Device) -> &mut Iter2 {
+        self.device = device;
+        self.prefetch = true;
+        self
+    }
+
+    /// When set, returns the last batch even if smaller than the batch size.
+    pub fn return_smaller_last_batch(&mut self) -> &mut Iter2 {
+        self.return_smaller_last_batch = true;
+        self
+    }
+
+    /// Sets whether the last batch is skipped when smaller than the batch
+    /// size, this is the default.
+    pub fn drop_last(&mut self, drop_last: bool) -> &mut Iter2 {
+        self.return_smaller_last_batch = !drop_last;
+        self
+    }
+
+    /// Applies a function to each mini-batch, e.g. some data augmentation. The
+    /// function runs before the mini-batch is transferred to the device, in
+    /// the background thread when prefetching. Unlike `Iterator::map`, this
+    /// returns an `Iter2` so that it can be combined with `prefetch_to`.
+    pub fn map_batches<F>(&mut self, f: F) -> &mut Iter2
+    where
+        F: FnMut((Tensor, Tensor)) -> (Tensor, Tensor) + Send + 'static,
+    {
+        self.batch_fn = Some(Box::new(f));
+        self
+    }
+
+    fn spawn_prefetch_thread(&mut self) -> PrefetchThread {
+        // A single mini-batch is prepared in advance.
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let (xs, ys) = (self.xs.shallow_clone(), self.ys.shallow_clone());
+        let mut batch_fn = self.batch_fn.take();
+        let (batch_size, total_size) = (self.batch_size, self.total_size);
+        let (return_smaller_last_batch, device) = (self.return_smaller_last_batch, self.device);
+        let mut batch_index = self.batch_index;
+        let handle = std::thread::spawn(move || {
+            while let Some(range) =
+                batch_range(batch_index, batch_size, total_size, return_smaller_last_batch)
+            {
+                batch_index += 1;
+                let batch = make_batch(&xs, &ys, range, batch_fn.as_mut(), device);
+                // The receiver has been dropped when sending fails.
+                if sender.send(batch).is_err() {
+                    break;
+                }
+            }
+        });
+        PrefetchThread { receiver: Some(receiver), handle: Some(handle) }
+    }
+}
+
+impl Iterator for Iter2 {
+    type Item = (Tensor, Tensor);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.prefetch {
+            if self.prefetch_thread.is_none() {
+                self.prefetch_thread = Some(self.spawn_prefetch_thread())
+            }
+            let thread = self.prefetch_thread.as_mut()?;
+            return match thread.receiver.as_ref()?.recv() {
+                Ok(batch) => {
+                    self.batch_index += 1;
+                    Some(batch.unwrap())
+                }
+                Err(mpsc::RecvError) => {
+                    // Forwards the panics of the batch function.
+                    if let Some(Err(err)) = thread.handle.take().map(|h| h.join()) {
+                        std::panic::resume_unwind(err)
+                    }
+                    None
+                }
+            };
+        }
+        let range = batch_range(
+            self.batch_index,
+            self.batch_size,
+            self.total_size,
+            self.return_smaller_last_batch,
+        )?;
+        self.batch_index += 1;
+        Some(make_batch(&self.xs, &self.ys, range, self.batch_fn.as_mut(), self.device).unwrap())
+    }
+}
+
+/// A dataset of samples accessed by index, as used by `DataLoader`.
+///
+/// The samples are loaded from multiple threads so the dataset has to be
+/// `Sync`, the tensors stored in a dataset can be wrapped in `SharedTensor` as
+/// done by `TensorDataset`.
+pub trait Dataset: Send + Sync + 'static {
+    type Sample: Send + 'static;
+
+    /// The number of samples.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Loads the sample at `index`, `index` being smaller than `len()`.
+    fn get(&self, index: usize) -> Result<Self::Sample, TchError>;
+}
+
+/// A dataset of inputs and targets held in memory, the sample at index `i`
+/// being the entries at index `i` along the first dimension of both tensors.
+///
+/// The tensors are stored as `SharedTensor` so that the workers of a
+/// `DataLoader` can read them concurrently, each sample is a copy of the
+/// corresponding entries.
+#[derive(Debug, Clone)]
+pub struct TensorDataset {
+    xs: SharedTensor,
+    ys: SharedTensor,
+    len: usize,
+}
+
+impl TensorDataset {
+    /// Creates a dataset from inputs and targets with the same first dimension size.
+    pub fn new(xs: Tensor, ys: Tensor) -> Result<TensorDataset, TchError> {
+        let (xs_size, ys_size) = (xs.f_size()?, ys.f_size()?);
+        let len = match (xs_size.first(), ys_size.first()) {
+            (Some(&xs_len), Some(&ys_len)) if xs_len == ys_len => xs_len as usize,
+            _ => {
+                return Err(TchError::Shape(format!(
+                    "inputs and targets have incompatible shapes {xs_size:?} {ys_size:?}"
+                )))
+            }
+        };
+        Ok(TensorDataset { xs: SharedTensor::new(xs), ys: SharedTensor::new(ys), len })
+    }
+
+    pub fn xs(&self) -> &SharedTensor {
+        &self.xs
+    }
+
+    pub fn ys(&self) -> &SharedTensor {
+        &self.ys
+    }
+}
+
+impl Dataset for TensorDataset {
+    type Sample = (Tensor, Tensor);
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> Result<(Tensor, Tensor), TchError> {
+        let index = Tensor::f_from_slice(&[index as i64])?;
+        let select = |ts: &SharedTensor| {
+            let index = index.f_to_device(ts.device())?;
+            ts.f_index_select(0, &index)?.f_squeeze_dim(0)
+        };
+        Ok((select(&self.xs)?, select(&self.ys)?))
+    }
+}
+
+/// Combines the samples of a mini-batch into a batch.
+pub trait Collate<S>: Send + Sync + 'static {
+    type Batch: Send + 'static;
+
+    fn collate(&self, samples: Vec<S>) -> Result<Self::Batch, TchError>;
+}
+
+/// The default collate function, stacking the samples along a new first
+/// dimension. The samples can be tensors, pairs of tensors, or pairs of a
+/// tensor and an `i64` label, all the tensors at a given position must have
+/// the same shape.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stack;
+
+fn stack_samples(samples: &[Tensor]) -> Result<Tensor, TchError> {
+    if let Some(first) = samples.first() {
+        let size = first.size();
+        if let Some(other) = samples.iter().find(|s| s.size() != size) {
+            return Err(TchError::Shape(format!(
+                "cannot collate samples with shapes {size:?} and {:?}",
+                other.size()
+            )));
+        }
+    }
+    Tensor::f_stack(samples, 0)
+}
+
+impl Collate<Tensor> for Stack {
+    type Batch = Tensor;
+
+    fn collate(&self, samples: Vec<Tensor>) -> Result<Tensor, TchError> {
+        stack_samples(&samples)
+    }
+}
+
+impl Collate<(Tensor, Tensor)> for Stack {
+    type Batch = (Tensor, Tensor);
+
+    fn collate(&self, samples: Vec<(Tensor, Tensor)>) -> Result<(Tensor, Tensor), TchError> {
+        let (xs, ys): (Vec<_>, Vec<_>) = samples.into_iter().unzip();
+        Ok((stack_samples(&xs)?, stack_samples(&ys)?))
+    }
+}
+
+impl Collate<(Tensor, i64)> for Stack {
+    type Batch = (Tensor, Tensor);
+
+    fn collate(&self, samples: Vec<(Tensor, i64)>) -> Result<(Tensor, Tensor), TchError> {
+        let (xs, ys): (Vec<_>, Vec<_>) = samples.into_iter().unzip();
+        Ok((stack_samples(&xs)?, Tensor::f_from_slice(&ys)?))
+    }
+}
+
+type LoadFn<B> = dyn Fn(&[i64]) -> Result<B, TchError> + Send + Sync;
+
+/// Loads the mini-batches of a dataset, possibly using multiple worker
+/// threads.
+///
+/// Each call to `iter` runs over the dataset once, reshuffling the samples
+/// when `shuffle` is set or using the indexes of the sampler if any. The workers load and collate whole mini-batches,
+/// at most two mini-batches per worker are prepared in advance.
+#[derive(Debug)]
+pub struct DataLoader<D, C = Stack> {
+    dataset: Arc<D>,
+    collate: Arc<C>,
+    batch_size: usize,
+    shuffle: bool,
+    drop_last: bool,
+    num_workers: usize,
+    in_order: bool,
+    rng: StdRng,
+    sampler: Option<Box<dyn Sampler>>,
+}
+
+impl<D: Dataset> DataLoader<D, Stack> {
+    /// Returns a data loader using the `Stack` collate function. By default
+    /// the samples are not shuffled, the last mini-batch is returned even if
+    /// smaller, and the mini-batches are loaded on the current thread.
+    pub fn new(dataset: D, batch_size: usize) -> DataLoader<D, Stack> {
+        DataLoader {
+            dataset: Arc::new(dataset),
+            collate: Arc::new(Stack),
+            batch_size: batch_size.max(1),
+            shuffle: false,
+            drop_last: false,
+            num_workers: 0,
+            in_order: true,
+            rng: StdRng::from_entropy(),
+            sampler: None,
+        }
+    }
+}
+
+impl<D: Dataset, C: Collate<D::Sample>> DataLoader<D, C> {
+    /// Replaces the collate function.
+    pub fn with_collate<C2: Collate<D::Sample>>(self, collate: C2) -> DataLoader<D, C2> {
+        DataLoader {
+            dataset: self.dataset,
+            collate: Arc::new(collate),
+            batch_size: self.batch_size,
+            shuffle: self.shuffle,
+            drop_last: self.drop_last,
+            num_workers: self.num_workers,
+            in_order: self.in_order,
+            rng: self.rng,
+            sampler: self.sampler,
+        }
+    }
+
+    /// Sets whether the samples are shuffled at each iteration.
+    pub fn shuffle(&mut self, shuffle: bool) -> &mut Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Seeds the random number generator used for shuffling.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Sets whether the last mini-batch is skipped when smaller than the batch
+    /// size.
+    pub fn drop_last(&mut self, drop_last: bool) -> &mut Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// Sets the number of worker threads, with 0 the mini-batches are loaded
+    /// on the current thread.
+    pub fn num_workers(&mut self, num_workers: usize) -> &mut Self {
+        self.num_workers = num_workers;
+        self
+    }
+
+    /// Sets whether the mini-batches are returned in order, this is the
+    /// default. Otherwise they are returned as soon as they are loaded.
+    pub fn in_order(&mut self, in_order: bool) -> &mut Self {
+        self.in_order = in_order;
+        self
+    }
+
+    /// Uses a sampler to generate the indexes of the samples of each
+    /// iteration, the `shuffle` setting is then ignored.
+    pub fn sampler<S: Sampler>(&mut self, sampler: S) -> &mut Self {
+        self.sampler = Some(Box::new(sampler));
+        self
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    /// The number of mini-batches of an iteration.
+    pub fn len(&self) -> usize {
+        let samples = match &self.sampler {
+            Some(sampler) => sampler.len(),
+            None => self.dataset.len(),
+        };
+        if self.drop_last {
+            samples / self.batch_size
+        } else {
+            samples.div_ceil(self.batch_size)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the mini-batches of the dataset.
+    pub fn iter(&mut self) -> DataLoaderIter<C::Batch> {
+        let indexes = match self.sampler.as_mut() {
+            Some(sampler) => sampler.indexes(),
+            None => {
+                let mut indexes: Vec<i64> = (0..self.dataset.len() as i64).collect();
+                if self.shuffle {
+                    indexes.shuffle(&mut self.rng)
+                }
+                indexes
+            }
+        };
+        let batches: Vec<Vec<i64>> =
+            indexes.chunks(self.batch_size).take(self.len()).map(|c| c.to_vec()).collect();
+        let (dataset, collate) = (self.dataset.clone(), self.collate.clone());
+        let load: Arc<LoadFn<C::Batch>> = Arc::new(move |indexes: &[i64]| {
+            let get = |index: i64| match usize::try_from(index) {
+                Ok(index) if index < dataset.len() => dataset.get(index),
+                _ => Err(TchError::Shape(format!(
+                    "sample index {index} out of range for {} samples",
+                    dataset.len()
+                ))),
+            };
+            let samples = indexes.iter().map(|&i| get(i)).collect::<Result<Vec<_>, _>>()?;
+            collate.collate(samples)
+        });
+        let batches = Arc::new(batches);
+        let workers = if self.num_workers == 0 {
+            None
+        } else {
+            Some(Workers::spawn(self.num_workers, self.in_order, &batches, &load))
+        };
+        DataLoaderIter { batches, load, index: 0, workers }
+    }
+}
+
+impl<D: Dataset, C: Collate<D::Sample>> IntoIterator for &mut DataLoader<D, C> {
+    type Item = Result<C::Batch, TchError>;
+    type IntoIter = DataLoaderIter<C::Batch>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// The position of the workers in the list of mini-batches.
+struct WorkQueue {
+    next: usize,
+    delivered: usize,
+    stopped: bool,
+}
+
+enum Message<B> {
+    Batch(usize, Result<B, TchError>),
+    Panic(Box<dyn std::any::Any + Send>),
+}
+
+struct Workers<B> {
+    queue: Arc<(Mutex<WorkQueue>, Condvar)>,
+    receiver: mpsc::Receiver<Message<B>>,
+    // The mini-batches received ahead of their turn.
+    pending: HashMap<usize, Result<B, TchError>>,
+    in_order: bool,
+    handles: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl<B: Send + 'static> Workers<B> {
+    fn spawn(
+        num_workers: usize,
+        in_order: bool,
+        batches: &Arc<Vec<Vec<i64>>>,
+        load: &Arc<LoadFn<B>>,
+    ) -> Workers<B> {
+        let max_in_flight = 2 * num_workers;
+        let queue = WorkQueue { next: 0, delivered: 0, stopped: false };
+        let queue = Arc::new((Mutex::new(queue), Condvar::new()));
+        let (sender, receiver) = mpsc::channel();
+        let handles = (0..num_workers)
+            .map(|_| {
+                let (queue, sender) = (queue.clone(), sender.clone());
+                let (batches, load) = (batches.clone(), load.clone());
+                std::thread::spawn(move || loop {
+                    let index = {
+                        let (queue, condvar) = &*queue;
+                        let mut queue = queue.lock().unwrap();
+                        loop {
+                            if queue.stopped || queue.next >= batches.len() {
+                                return;
+                            }
+                            if queue.next < queue.delivered + max_in_flight {
+                                break;
+                            }
+                            queue = condvar.wait(queue).unwrap();
+                        }
+                        queue.next += 1;
+                        queue.next - 1
+                    };
+                    let load = || load(&batches[index]);
+                    let message = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(load))
+                    {
+                        Ok(batch) => Message::Batch(index, batch),
+                        Err(panic) => Message::Panic(panic),
+                    };
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Workers { queue, receiver, pending: HashMap::new(), in_order, handles }
+    }
+
+    fn next(&mut self, index: usize) -> Result<B, TchError> {
+        let batch = loop {
+            if let Some(batch) = self.pending.remove(&index) {
+                break batch;
+            }
+            match self.receiver.recv() {
+                Ok(Message::Batch(i, batch)) if !self.in_order || i == index => break batch,
+                Ok(Message::Batch(i, batch)) => {
+                    self.pending.insert(i, batch);
+                }
+                // Forwards the panics of the workers to the caller.
+                Ok(Message::Panic(panic)) => std::panic::resume_unwind(panic),
+                Err(mpsc::RecvError) => {
+                    return Err(TchError::Torch("the data loader workers have stopped".to_string()))
+                }
+            }
+        };
+        let (queue, condvar) = &*self.queue;
+        queue.lock().unwrap().delivered += 1;
+        condvar.notify_all();
+        batch
+    }
+}
+
+impl<B> Drop for Workers<B> {
+    fn drop(&mut self) {
+        let (queue, condvar) = &*self.queue;
+        if let Ok(mut queue) = queue.lock() {
+            queue.stopped = true;
+        }
+        condvar.notify_all();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// An iterator over the mini-batches of a `DataLoader`.
+pub struct DataLoaderIter<B> {
+    batches: Arc<Vec<Vec<i64>>>,
+    load: Arc<LoadFn<B>>,
+    index: usize,
+    workers: Option<Workers<B>>,
+}
+
+impl<B> std::fmt::Debug for DataLoaderIter<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataLoaderIter")
+            .field("batches", &self.batches.len())
+            .field("index", &self.index)
+            .field("workers", &self.workers.as_ref().map_or(0, |w| w.handles.len()))
+            .finish()
+    }
+}
+
+impl<B: Send + 'static> Iterator for DataLoaderIter<B> {
+    type Item = Result<B, TchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.batches.len() {
+            return None;
+        }
+        let batch = match self.workers.as_mut() {
+            None => (self.load)(&self.batches[self.index]),
+            Some(workers) => workers.next(self.index),
+        };
+        self.index += 1;
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.batches.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+/// The mapping between the bytes of a text and their labels, the labels
+/// being assigned in the order in which the bytes first appear.
+#[derive(Debug, Clone, Default)]
+pub struct CharMap {
+    char_for_label: Vec<char>,
+    label_for_char: HashMap<u8, u8>,
+}
+
+impl CharMap {
+    // Replaces the bytes with their labels, adding the new bytes to the map.
+    fn encode_and_update(&mut self, buffer: &mut [u8]) {
+        for c in buffer.iter_mut() {
+            *c = *self.label_for_char.entry(*c).or_insert_with(|| {
+                let label = self.char_for_label.len() as u8;
+                self.char_for_label.push(*c as char);
+                label
+            })
+        }
+    }
+
+    /// The number of labels.
+    pub fn len(&self) -> usize {
+        self.char_for_label.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.char_for_label.is_empty()
+    }
+
+    /// The characters indexed by their labels.
+    pub fn chars(&self) -> &[char] {
+        &self.char_for_label
+    }
+
+    pub fn label_to_char(&self, label: i64) -> char {
+        self.char_for_label[label as usize]
+    }
+
+    pub fn char_to_label(&self, c: char) -> Result<u8, TchError> {
+        match self.label_for_char.get(&(c as u8)) {
+            None => Err(TchError::Convert(format!("cannot find char {c}"))),
+            Some(v) => Ok(*v),
+        }
+    }
+
+    /// Converts a string to a tensor of labels.
+    pub fn encode(&self, s: &str) -> Result<Tensor, TchError> {
+        let labels = s.chars().map(|c| self.char_to_label(c)).collect::<Result<Vec<_>, _>>()?;
+        Tensor::f_from_slice(&labels)
+    }
+
+    /// Converts a tensor of labels, e.g. a generated sample, to a string.
+    pub fn decode(&self, labels: &Tensor) -> Result<String, TchError> {
+        let labels = Vec::<i64>::try_from(&labels.f_flatten(0, -1)?)?;
+        labels
+            .into_iter()
+            .map(|label| {
+                match usize::try_from(label).ok().and_then(|l| self.char_for_label.get(l)) {
+                    Some(c) => Ok(*c),
+                    None => Err(TchError::Convert(format!("cannot find label {label}"))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Text data holder.
+#[derive(Debug)]
+pub struct TextData {
+    data: Tensor,
+    char_map: CharMap,
+}
+
+/// Options for iterating over the windows of a `TextData`.
+#[derive(Debug, Clone, Copy)]
+pub struct TextIterConfig {
+    /// The distance between the starts of two consecutive windows, windows
+    /// overlap when this is smaller than the sequence length.
+    pub stride: i64,
+    pub shuffle: bool,
+    /// The seed used for shuffling, when not set the libtorch random number
+    /// generator is used.
+    pub seed: Option<u64>,
+}
+
+impl Default for TextIterConfig {
+    fn default() -> Self {
+        TextIterConfig { stride: 1, shuffle: true, seed: None }
+    }
+}
+
+/// Text data iterator.
+#[derive(Debug)]
+pub struct TextDataIter {
+    data: Tensor,
+    seq_len: i64,
+    batch_index: usize,
+    batch_size: usize,
+    indexes: Vec<i64>,
+}
+
+impl TextData {
+    fn read(filename: &std::path::Path) -> Result<Vec<u8>, TchError> {
+        let buffer = std::fs::read(filename)
+            .map_err(|err| std::io::Error::new(err.kind(), format!("{filename:?} {err}")))?;
+        Ok(buffer)
+    }
+
+    fn from_buffer(mut buffer: Vec<u8>) -> TextData {
+        let mut char_map = CharMap::default();
+        char_map.encode_and_update(&mut buffer);
+        TextData { data: Tensor::from_slice(&buffer), char_map }
+    }
+
+    /// Creates a text dataset from a file.
+    pub fn new<P: AsRef<std::path::Path>>(filename: P) -> Result<TextData, TchError> {
+        Ok(TextData::from_buffer(TextData::read(filename.as_ref())?))
+    }
+
+    /// Creates a text dataset from the concatenation of multiple files.
+    pub fn from_files<P: AsRef<std::path::Path>>(filenames: &[P]) -> Result<TextData, TchError> {
+        let mut buffer = vec![];
+        for filename in filenames.iter() {
+            buffer.extend(TextData::read(filename.as_ref())?)
+        }
+        Ok(TextData::from_buffer(buffer))
+    }
+
+    /// Creates a text dataset from a string.
+    pub fn from_string(s: &str) -> TextData {
+        TextData::from_buffer(s.as_bytes().to_vec())
+    }
+
+    /// Returns the number of different characters/labels used by the dataset.
+    pub fn labels(&self) -> i64 {
+        self.char_map.len() as i64
+    }
+
+    /// Returns a shallow copy of the data.
+    pub fn data(&self) -> Tensor {
+        self.data.shallow_clone()
+    }
+
+    /// The mapping between the characters and the labels.
+    pub fn char_map(&self) -> &CharMap {
+        &self.char_map
+    }
+
+    pub fn label_to_char(&self, label: i64) -> char {
+        self.char_map.label_to_char(label)
+    }
+
+    pub fn char_to_label(&self, c: char) -> Result<u8, TchError> {
+        self.char_map.char_to_label(c)
+    }
+
+    /// Returns a batch iterator over the dataset.
+    /// Each sample is made of seq_len characters.
+    pub fn iter_shuffle(&self, seq_len: i64, batch_size: i64) -> TextDataIter {
+        self.iter_with_config(seq_len, batch_size, Default::default())
+    }
+
+    /// Returns a batch iterator over the windows of `seq_len` characters of
+    /// the dataset. The windows start every `config.stride` characters and
+    /// the last window ends at the end of the data at most, the last batch is
+    /// dropped when smaller than `batch_size`.
+    pub fn iter_with_config(
+        &self,
+        seq_len: i64,
+        batch_size: i64,
+        config: TextIterConfig,
+    ) -> TextDataIter {
+        let stride = config.stride.max(1);
+        let len = self.data.size()[0];
+        let windows = if len < seq_len { 0 } else { (len - seq_len) / stride + 1 };
+        let mut indexes: Vec<i64> = (0..windows).map(|i| i * stride).collect();
+        if config.shuffle {
+            match config.seed {
+                Some(seed) => indexes.shuffle(&mut StdRng::seed_from_u64(seed)),
+                None => {
+                    let permutation = Tensor::randperm(windows, kind::INT64_CPU);
+                    let permutation = Vec::<i64>::try_from(&permutation).unwrap();
+                    indexes = permutation.into_iter().map(|i| i * stride).collect()
+                }
+            }
+        }
+        TextDataIter {
+            data: self.data.shallow_clone(),
+            seq_len,
+            batch_index: 0,
+            batch_size: batch_size.max(1) as usize,
+            indexes,
+        }
+    }
+}
+
+impl Iterator for TextDataIter {
+    type Item = Tensor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.batch_index * self.batch_size;
+        let indexes = self.indexes.get(start..start + self.batch_size)?;
+        self.batch_index += 1;
+        let batch: Vec<_> = indexes.iter().map(|&i| self.data.i(i..i + self.seq_len)).collect();
+        Some(Tensor::stack(&batch, 0))
+    }
+}
@@ -0,0 +1,222 @@
+//! Samplers generating the indexes of the samples used for each epoch.
+//!
+//! The samplers can be plugged into a `DataLoader` with
+//! `DataLoader::sampler`, or used on their own, e.g. to select the samples of
+//! a tensor with `index_select`.
+use crate::{TchError, Tensor};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Generates the indexes of the samples of an epoch.
+pub trait Sampler: std::fmt::Debug + Send + 'static {
+    /// The number of indexes generated for each epoch.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the indexes to use for the next epoch.
+    fn indexes(&mut self) -> Vec<i64>;
+
+    /// Returns the indexes to use for the next epoch as an `Int64` tensor.
+    fn index_tensor(&mut self) -> Tensor {
+        Tensor::from_slice(&self.indexes())
+    }
+}
+
+/// Samples all the indexes in a random order, the order changes at each
+/// epoch.
+#[derive(Debug)]
+pub struct RandomSampler {
+    len: usize,
+    rng: StdRng,
+}
+
+impl RandomSampler {
+    pub fn new(len: usize) -> RandomSampler {
+        RandomSampler { len, rng: StdRng::from_entropy() }
+    }
+
+    /// Seeds the random number generator, for reproducible orders.
+    pub fn seed(&mut self, seed: u64) -> &mut RandomSampler {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn indexes(&mut self) -> Vec<i64> {
+        let mut indexes: Vec<i64> = (0..self.len as i64).collect();
+        indexes.shuffle(&mut self.rng);
+        indexes
+    }
+}
+
+/// Samples the indexes with probabilities proportional to some weights, e.g.
+/// to balance the classes of a dataset.
+#[derive(Debug)]
+pub struct WeightedRandomSampler {
+    weights: Vec<f64>,
+    num_samples: usize,
+    replacement: bool,
+    rng: StdRng,
+}
+
+impl WeightedRandomSampler {
+    /// Returns a sampler generating `num_samples` indexes per epoch, the index
+    /// `i` being sampled with a probability proportional to `weights[i]`.
+    ///
+    /// The weights must be non-negative and not all zero. Without
+    /// replacement, each index is sampled at most once and so `num_samples`
+    /// cannot be larger than the number of positive weights.
+    pub fn new(
+        weights: &[f64],
+        num_samples: usize,
+        replacement: bool,
+    ) -> Result<WeightedRandomSampler, TchError> {
+        if let Some(w) = weights.iter().find(|w| !w.is_finite() || **w < 0.) {
+            return Err(TchError::Shape(format!(
+                "sampling weights have to be non-negative, got {w}"
+            )));
+        }
+        let positive = weights.iter().filter(|w| **w > 0.).count();
+        if positive == 0 {
+            return Err(TchError::Shape("sampling weights sum to zero".to_string()));
+        }
+        if !replacement && num_samples > positive {
+            return Err(TchError::Shape(format!(
+                "cannot sample {num_samples} indexes without replacement from {positive} positive weights"
+            )));
+        }
+        Ok(WeightedRandomSampler {
+            weights: weights.to_vec(),
+            num_samples,
+            replacement,
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    /// Seeds the random number generator, for reproducible samples.
+    pub fn seed(&mut self, seed: u64) -> &mut WeightedRandomSampler {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Sampler for WeightedRandomSampler {
+    fn len(&self) -> usize {
+        self.num_samples
+    }
+
+    fn indexes(&mut self) -> Vec<i64> {
+        if self.replacement {
+            // The weights have been checked when creating the sampler.
+            let distribution = WeightedIndex::new(&self.weights).unwrap();
+            return (0..self.num_samples)
+                .map(|_| distribution.sample(&mut self.rng) as i64)
+                .collect();
+        }
+        // Weighted sampling without replacement, the indexes with the largest
+        // keys u^(1/w) are selected.
+        // Efraimidis, Spirakis, Weighted random sampling with a reservoir, 2006.
+        let mut keys: Vec<(f64, i64)> = self
+            .weights
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| **w > 0.)
+            .map(|(i, w)| (self.rng.gen::<f64>().ln() / w, i as i64))
+            .collect();
+        keys.sort_by(|a, b| b.0.total_cmp(&a.0));
+        keys.into_iter().take(self.num_samples).map(|(_, i)| i).collect()
+    }
+}
+
+/// Partitions the indexes between the replicas of a distributed training,
+/// each replica using the indexes of its rank.
+///
+/// All the replicas get the same number of indexes: by default some indexes
+/// are repeated so that the dataset length becomes a multiple of the number
+/// of replicas, with `drop_last` the last indexes are dropped instead. When
+/// shuffling, all the replicas must use the same seed and call `set_epoch`
+/// at the beginning of each epoch so that they use the same permutation.
+#[derive(Debug)]
+pub struct DistributedSampler {
+    len: usize,
+    num_replicas: usize,
+    rank: usize,
+    shuffle: bool,
+    seed: u64,
+    epoch: u64,
+    drop_last: bool,
+}
+
+impl DistributedSampler {
+    pub fn new(
+        len: usize,
+        num_replicas: usize,
+        rank: usize,
+        shuffle: bool,
+        seed: u64,
+    ) -> Result<DistributedSampler, TchError> {
+        if rank >= num_replicas {
+            return Err(TchError::Shape(format!(
+                "invalid rank {rank} for {num_replicas} replicas"
+            )));
+        }
+        Ok(DistributedSampler {
+            len,
+            num_replicas,
+            rank,
+            shuffle,
+            seed,
+            epoch: 0,
+            drop_last: false,
+        })
+    }
+
+    /// Sets the epoch used to seed the shuffling.
+    pub fn set_epoch(&mut self, epoch: u64) -> &mut DistributedSampler {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Drops the last indexes rather than repeating some indexes so that all
+    /// the replicas get the same number of indexes.
+    pub fn drop_last(&mut self, drop_last: bool) -> &mut DistributedSampler {
+        self.drop_last = drop_last;
+        self
+    }
+}
+
+impl Sampler for DistributedSampler {
+    fn len(&self) -> usize {
+        if self.drop_last {
+            self.len / self.num_replicas
+        } else {
+            self.len.div_ceil(self.num_replicas)
+        }
+    }
+
+    fn indexes(&mut self) -> Vec<i64> {
+        let mut indexes: Vec<i64> = (0..self.len as i64).collect();
+        if self.shuffle {
+            let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.epoch));
+            indexes.shuffle(&mut rng);
+        }
+        let total_size = self.len() * self.num_replicas;
+        if total_size > indexes.len() && !indexes.is_empty() {
+            let padding: Vec<i64> =
+                indexes.iter().cycle().take(total_size - indexes.len()).copied().collect();
+            indexes.extend(padding);
+        }
+        indexes.truncate(total_size);
+        indexes.into_iter().skip(self.rank).step_by(self.num_replicas).collect()
+    }
+}
@@ -0,0 +1,235 @@
+//! Detection of the first layer producing non-finite values.
+//!
+//! The sub-modules wrapped with `NanGuard::wrap` check their outputs for NaN
+//! and infinite values with a single reduction, and the first offending
+//! module is reported along with the step number and statistics of its input.
+//! By default the forward pass of this module fails with a
+//! `TchError::NonFinite` error, a callback can be used instead to log the
+//! report and continue. A disabled guard only costs an atomic load per
+//! wrapped module.
+//!
+//! ```no_run
+//! # use tch::{debug::NanGuard, nn, nn::ModuleT, Device, Tensor};
+//! let vs = nn::VarStore::new(Device::Cpu);
+//! let guard = NanGuard::new();
+//! let root = vs.root();
+//! let net = nn::seq_t()
+//!     .add(guard.wrap(&root / "fc1", nn::linear(&root / "fc1", 784, 128, Default::default())))
+//!     .add_fn(|xs| xs.relu())
+//!     .add(guard.wrap(&root / "fc2", nn::linear(&root / "fc2", 128, 10, Default::default())));
+//! let xs = Tensor::zeros([64, 784], tch::kind::FLOAT_CPU);
+//! match net.f_forward_t(&xs, true) {
+//!     Err(tch::TchError::NonFinite(report)) => println!("{report}"),
+//!     _ => guard.step(),
+//! }
+//! ```
+use crate::nn::{ModuleT, Path};
+use crate::{Kind, TchError, Tensor};
+use std::borrow::Borrow;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Summary statistics of a tensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorStats {
+    pub size: Vec<i64>,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl TensorStats {
+    /// Computes the statistics of a tensor, the minimum, maximum and mean of
+    /// an empty tensor are NaN.
+    pub fn f_new(xs: &Tensor) -> Result<TensorStats, TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let size = xs.size();
+        if xs.numel() == 0 {
+            return Ok(TensorStats { size, min: f64::NAN, max: f64::NAN, mean: f64::NAN });
+        }
+        let xs = xs.f_detach()?.f_to_kind(Kind::Double)?;
+        let min = f64::try_from(xs.f_min()?)?;
+        let max = f64::try_from(xs.f_max()?)?;
+        let mean = f64::try_from(xs.f_mean(Kind::Double)?)?;
+        Ok(TensorStats { size, min, max, mean })
+    }
+}
+
+impl std::fmt::Display for TensorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?} min {} max {} mean {}", self.size, self.min, self.max, self.mean)
+    }
+}
+
+/// The first module whose output contained non-finite values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NonFiniteReport {
+    /// The var-store path of the module, e.g. `encoder.layer3.fc`.
+    pub module: String,
+    /// The step counter of the guard when the values were detected.
+    pub step: u64,
+    /// The statistics of the module input.
+    pub input: TensorStats,
+    pub nan_count: i64,
+    pub inf_count: i64,
+}
+
+impl std::fmt::Display for NonFiniteReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} output {} NaN and {} infinite values at step {}, input {}",
+            self.module, self.nan_count, self.inf_count, self.step, self.input
+        )
+    }
+}
+
+type Callback = Box<dyn Fn(&NonFiniteReport) + Send>;
+
+struct State {
+    enabled: AtomicBool,
+    step: AtomicU64,
+    first: Mutex<Option<NonFiniteReport>>,
+    callback: Mutex<Option<Callback>>,
+    // The last step for which the callback was called.
+    reported_step: Mutex<Option<u64>>,
+}
+
+/// Checks the outputs of the wrapped modules for non-finite values.
+///
+/// The guard can be cloned, the clones share the same state.
+#[derive(Clone)]
+pub struct NanGuard {
+    state: Arc<State>,
+}
+
+impl std::fmt::Debug for NanGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("NanGuard")
+            .field("enabled", &self.is_enabled())
+            .field("step", &self.current_step())
+            .finish()
+    }
+}
+
+impl Default for NanGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NanGuard {
+    /// Creates an enabled guard raising errors on non-finite values.
+    pub fn new() -> NanGuard {
+        let state = State {
+            enabled: AtomicBool::new(true),
+            step: AtomicU64::new(0),
+            first: Mutex::new(None),
+            callback: Mutex::new(None),
+            reported_step: Mutex::new(None),
+        };
+        NanGuard { state: Arc::new(state) }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Calls `f` with the report of the first module producing non-finite
+    /// values in each step rather than returning an error, the forward pass
+    /// then continues with the non-finite values.
+    pub fn set_callback<F: Fn(&NonFiniteReport) + Send + 'static>(&self, f: F) {
+        *self.state.callback.lock().unwrap() = Some(Box::new(f))
+    }
+
+    /// Increments the step counter, to be called after each training step.
+    pub fn step(&self) {
+        self.state.step.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn current_step(&self) -> u64 {
+        self.state.step.load(Ordering::Relaxed)
+    }
+
+    /// The report of the first non-finite values detected since the creation
+    /// of the guard or the last call to `reset`.
+    pub fn first_report(&self) -> Option<NonFiniteReport> {
+        self.state.first.lock().unwrap().clone()
+    }
+
+    /// Forgets the reports and resets the step counter.
+    pub fn reset(&self) {
+        *self.state.first.lock().unwrap() = None;
+        *self.state.reported_step.lock().unwrap() = None;
+        self.state.step.store(0, Ordering::Relaxed)
+    }
+
+    /// Wraps `module` so that its outputs are checked, `path` is the
+    /// var-store path of the module used in the reports.
+    pub fn wrap<'a, M: ModuleT, T: Borrow<Path<'a>>>(&self, path: T, module: M) -> Guarded<M> {
+        let name = path.borrow().components().collect::<Vec<_>>().join(".");
+        Guarded { module, name, guard: self.clone() }
+    }
+
+    fn check(&self, name: &str, xs: &Tensor, ys: &Tensor) -> Result<(), TchError> {
+        if !ys.f_is_floating_point()? {
+            return Ok(());
+        }
+        let _no_grad = crate::no_grad_guard();
+        if ys.f_isfinite()?.f_all()?.f_int64_value(&[])? != 0 {
+            return Ok(());
+        }
+        let nan_count = ys.f_isnan()?.f_sum(Kind::Int64)?.f_int64_value(&[])?;
+        let inf_count = ys.f_isinf()?.f_sum(Kind::Int64)?.f_int64_value(&[])?;
+        let step = self.current_step();
+        let report = NonFiniteReport {
+            module: name.to_string(),
+            step,
+            input: TensorStats::f_new(xs)?,
+            nan_count,
+            inf_count,
+        };
+        let mut first = self.state.first.lock().unwrap();
+        if first.is_none() {
+            *first = Some(report.clone())
+        }
+        drop(first);
+        match self.state.callback.lock().unwrap().as_ref() {
+            None => Err(TchError::NonFinite(Box::new(report))),
+            Some(callback) => {
+                let mut reported_step = self.state.reported_step.lock().unwrap();
+                if *reported_step != Some(step) {
+                    *reported_step = Some(step);
+                    callback(&report)
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A module whose outputs are checked by a `NanGuard`.
+#[derive(Debug)]
+pub struct Guarded<M> {
+    pub module: M,
+    name: String,
+    guard: NanGuard,
+}
+
+impl<M: ModuleT> ModuleT for Guarded<M> {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let ys = self.module.f_forward_t(xs, train)?;
+        if self.guard.is_enabled() {
+            self.guard.check(&self.name, xs, &ys)?
+        }
+        Ok(ys)
+    }
+}
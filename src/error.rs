@@ -1,3 +1,4 @@
+use crate::{Device, Kind, Tensor};
 use std::ffi::NulError;
 use std::io;
 use std::num::ParseIntError;
@@ -5,6 +6,73 @@ use std::num::ParseIntError;
 use thiserror::Error;
 use zip::result::ZipError;
 
+/// The metadata of a tensor argument of a failed operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TensorMeta {
+    /// The argument name.
+    pub name: &'static str,
+    pub size: Vec<i64>,
+    pub kind: Kind,
+    pub device: Device,
+    pub requires_grad: bool,
+}
+
+impl TensorMeta {
+    /// Returns None for undefined tensors.
+    pub(crate) fn from_tensor(name: &'static str, tensor: &Tensor) -> Option<TensorMeta> {
+        if !tensor.defined() {
+            return None;
+        }
+        let size = tensor.f_size().ok()?;
+        let kind = tensor.f_kind().ok()?;
+        let device = tensor.device();
+        let requires_grad = tensor.requires_grad();
+        Some(TensorMeta { name, size, kind, device, requires_grad })
+    }
+}
+
+impl std::fmt::Display for TensorMeta {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = match self.kind {
+            Kind::Uint8 => "u8",
+            Kind::Int8 => "i8",
+            Kind::Int16 => "i16",
+            Kind::Int => "i32",
+            Kind::Int64 => "i64",
+            Kind::Half => "f16",
+            Kind::Float => "f32",
+            Kind::Double => "f64",
+            Kind::ComplexHalf => "c32",
+            Kind::ComplexFloat => "c64",
+            Kind::ComplexDouble => "c128",
+            Kind::Bool => "bool",
+            Kind::QInt8 => "qi8",
+            Kind::QUInt8 => "qu8",
+            Kind::QInt32 => "qi32",
+            Kind::BFloat16 => "bf16",
+        };
+        write!(f, "{}={:?} {kind} ", self.name, self.size)?;
+        match self.device {
+            Device::Cpu => write!(f, "cpu")?,
+            Device::Cuda(index) => write!(f, "cuda:{index}")?,
+            Device::Mps => write!(f, "mps")?,
+            Device::Vulkan => write!(f, "vulkan")?,
+        }
+        if self.requires_grad {
+            write!(f, " requires_grad")?
+        }
+        Ok(())
+    }
+}
+
+fn fmt_inputs(inputs: &[TensorMeta]) -> String {
+    inputs.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+fn fmt_mismatches(mismatches: &[crate::autograd::GradcheckMismatch]) -> String {
+    mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")
+}
+
 /// Main library error type.
 #[derive(Error, Debug)]
 pub enum TchError {
@@ -44,6 +112,13 @@ pub enum TchError {
     #[error("invalid shape: {0}")]
     Shape(String),
 
+    /// An index out of range for a tensor dimension, `dim` is the non-negative
+    /// dimension index and `shape` the shape of the indexed tensor.
+    #[error(
+        "index {index} out of range for dimension {dim} of size {size} (tensor shape {shape:?})"
+    )]
+    IndexOutOfRange { dim: i64, index: i64, size: i64, shape: Vec<i64> },
+
     /// Unknown kind
     #[error("unknown kind: {0}")]
     UnknownKind(libc::c_int),
@@ -52,6 +127,40 @@ pub enum TchError {
     #[error("Internal torch error: {0}")]
     Torch(String),
 
+    /// Errors returned by the Torch C++ API when running an operation, together
+    /// with the metadata of its tensor arguments.
+    #[error("{op} failed: {message} {}", fmt_inputs(.inputs))]
+    OpFailed { op: &'static str, message: String, inputs: Vec<TensorMeta> },
+
+    /// The CUDA caching allocator ran out of memory. The sizes are in bytes and
+    /// are parsed from the allocator message so they are rounded, e.g. to a
+    /// hundredth of GiB.
+    #[error("{message}")]
+    CudaOutOfMemory { requested: u64, free: u64, total: u64, message: String },
+
+    /// Tensors created in inference mode being used in autograd, or being
+    /// modified in place outside of inference mode.
+    #[error("inference tensor error, tensors created in inference mode cannot be used in autograd, use Tensor::copy outside of inference mode to get a normal tensor: {0}")]
+    InferenceTensor(String),
+
+    /// A metric used by a training callback is not available.
+    #[error("missing metric {0}: {1}")]
+    MissingMetric(String, String),
+
+    /// The gradients computed by the backward pass differ from their
+    /// numerical estimates, with the worst mismatch for each input.
+    #[error("gradcheck failed: {}", fmt_mismatches(.0))]
+    Gradcheck(Vec<crate::autograd::GradcheckMismatch>),
+
+    /// A module wrapped by a `NanGuard` produced NaN or infinite values.
+    #[error("non-finite values: {0}")]
+    NonFinite(Box<crate::debug::NonFiniteReport>),
+
+    /// The computation was stopped through a `CancellationToken`, either
+    /// explicitly or because its deadline passed.
+    #[error("computation cancelled")]
+    Cancelled,
+
     /// Zip file format error.
     #[error(transparent)]
     Zip(#[from] ZipError),
@@ -65,10 +174,47 @@ pub enum TchError {
 }
 
 impl TchError {
-    pub fn path_context(&self, path_name: &str) -> Self {
+    /// Prefixes the message of errors returned by the Torch C++ API with
+    /// `path_name`, other errors are returned unchanged.
+    pub fn path_context(self, path_name: &str) -> Self {
         match self {
             TchError::Torch(error) => TchError::Torch(format!("{path_name}: {error}")),
-            _ => unimplemented!(),
+            TchError::InferenceTensor(error) => {
+                TchError::InferenceTensor(format!("{path_name}: {error}"))
+            }
+            TchError::OpFailed { op, message, inputs } => {
+                TchError::OpFailed { op, message: format!("{path_name}: {message}"), inputs }
+            }
+            err @ (TchError::Convert(_)
+            | TchError::FileFormat(_)
+            | TchError::TensorNameNotFound(_, _)
+            | TchError::Io(_)
+            | TchError::Kind(_)
+            | TchError::MissingImage(_)
+            | TchError::Nul(_)
+            | TchError::ParseInt(_)
+            | TchError::Shape(_)
+            | TchError::IndexOutOfRange { .. }
+            | TchError::UnknownKind(_)
+            | TchError::CudaOutOfMemory { .. }
+            | TchError::MissingMetric(_, _)
+            | TchError::Gradcheck(_)
+            | TchError::NonFinite(_)
+            | TchError::Cancelled
+            | TchError::Zip(_)
+            | TchError::NdArray(_)
+            | TchError::SafeTensorError { .. }) => err,
+        }
+    }
+
+    // Attaches the operation name and the tensor arguments to the errors
+    // returned by the Torch C++ API.
+    pub(crate) fn op_context(self, op: &'static str, inputs: Vec<TensorMeta>) -> Self {
+        match self {
+            TchError::Torch(message) => {
+                TchError::OpFailed { op, message: message.trim_end().to_string(), inputs }
+            }
+            err => err,
         }
     }
 }
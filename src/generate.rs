@@ -0,0 +1,403 @@
+//! Token generation from a function returning the next-token logits.
+//!
+//! The step function receives the token ids generated so far, including the
+//! prompt, with shape `[batch, seq_len]` and returns the logits for the next
+//! token with shape `[batch, vocab_size]`. A step function using a key/value
+//! cache only has to run the tokens that are not in the cache yet.
+//!
+//! ```no_run
+//! # use tch::{generate, Device, Kind, Tensor};
+//! # let model = |ids: &Tensor| Tensor::zeros([ids.size()[0], 32], (Kind::Float, Device::Cpu));
+//! let config = generate::GenerateConfig {
+//!     max_new_tokens: 20,
+//!     do_sample: true,
+//!     temperature: 0.8,
+//!     top_p: Some(0.9),
+//!     seed: Some(42),
+//!     ..Default::default()
+//! };
+//! let prompt = Tensor::from_slice(&[1i64, 2, 3]).view([1, 3]);
+//! let ids = generate::generate(&prompt, &mut (), |ids, _| model(ids), &config);
+//! ```
+use crate::nn::KvCache;
+use crate::{Kind, TchError, Tensor};
+
+/// The state carried across the calls to the step function, e.g. a key/value
+/// cache.
+pub trait State {
+    /// Reorders the state along the batch dimension, this is called by beam
+    /// search after selecting the new beams.
+    fn reorder(&mut self, indices: &Tensor) -> Result<(), TchError>;
+}
+
+impl State for () {
+    fn reorder(&mut self, _indices: &Tensor) -> Result<(), TchError> {
+        Ok(())
+    }
+}
+
+impl State for KvCache {
+    fn reorder(&mut self, indices: &Tensor) -> Result<(), TchError> {
+        self.f_reorder(indices)
+    }
+}
+
+/// The generation settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateConfig {
+    pub max_new_tokens: i64,
+    /// Sequences are finished once this token has been generated, finished
+    /// sequences are padded with this token.
+    pub eos_token: Option<i64>,
+    /// Samples the next token from the filtered distribution, otherwise the
+    /// most likely token is selected.
+    pub do_sample: bool,
+    /// The logits are divided by this positive temperature before sampling.
+    pub temperature: f64,
+    /// Only sample among the `top_k` most likely tokens.
+    pub top_k: Option<i64>,
+    /// Only sample among the smallest set of most likely tokens whose
+    /// probabilities add up to at least `top_p`.
+    pub top_p: Option<f64>,
+    /// Penalizes the tokens that already appear in the sequence, 1.0 means no
+    /// penalty.
+    pub repetition_penalty: f64,
+    /// Beam search is used when this is greater than 1, the step function
+    /// and the state then operate on `batch * num_beams` sequences.
+    pub num_beams: usize,
+    /// Beam scores are divided by the number of generated tokens to this
+    /// power.
+    pub length_penalty: f64,
+    /// Stops the beam search for a batch element as soon as `num_beams`
+    /// finished sequences are available.
+    pub early_stopping: bool,
+    /// Seeds the random number generators used for sampling. The prior states
+    /// of the generators are restored once the generation completes, so that
+    /// the other random operations of the process are not affected.
+    pub seed: Option<i64>,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        GenerateConfig {
+            max_new_tokens: 20,
+            eos_token: None,
+            do_sample: false,
+            temperature: 1.0,
+            top_k: None,
+            top_p: None,
+            repetition_penalty: 1.0,
+            num_beams: 1,
+            length_penalty: 1.0,
+            early_stopping: true,
+            seed: None,
+        }
+    }
+}
+
+/// Divides the positive logits of the tokens that appear in `ids` by
+/// `penalty` and multiplies the negative ones by `penalty`.
+pub fn f_apply_repetition_penalty(
+    logits: &Tensor,
+    ids: &Tensor,
+    penalty: f64,
+) -> Result<Tensor, TchError> {
+    let scores = logits.f_gather(-1, ids, false)?;
+    let penalized = scores
+        .f_mul_scalar(penalty)?
+        .f_where_self(&scores.f_lt(0.)?, &scores.f_div_scalar(penalty)?)?;
+    logits.f_scatter(-1, ids, &penalized)
+}
+
+pub fn apply_repetition_penalty(logits: &Tensor, ids: &Tensor, penalty: f64) -> Tensor {
+    f_apply_repetition_penalty(logits, ids, penalty).unwrap()
+}
+
+// Sets the logits to -inf where `remove` is set for the tokens sorted by
+// decreasing logits.
+fn remove_sorted(logits: &Tensor, indices: &Tensor, remove: &Tensor) -> Result<Tensor, TchError> {
+    let remove = remove.f_scatter(-1, indices, remove)?;
+    logits.f_masked_fill(&remove, f64::NEG_INFINITY)
+}
+
+/// Keeps the `k` largest logits on the last dimension and sets the others to
+/// -inf. Ties are broken in favor of the lowest token ids.
+pub fn f_top_k_filter(logits: &Tensor, k: i64) -> Result<Tensor, TchError> {
+    let (_, indices) = logits.f_sort_stable(true, -1, true)?;
+    let vocab_size = *logits.size().last().unwrap_or(&0);
+    let rank = Tensor::f_arange(vocab_size, (Kind::Int64, logits.device()))?;
+    let remove = rank.f_ge(k.max(1))?.f_expand_as(&indices)?.f_contiguous()?;
+    remove_sorted(logits, &indices, &remove)
+}
+
+pub fn top_k_filter(logits: &Tensor, k: i64) -> Tensor {
+    f_top_k_filter(logits, k).unwrap()
+}
+
+/// Keeps the smallest set of largest logits on the last dimension whose
+/// probabilities add up to at least `p` and sets the others to -inf. The most
+/// likely token is always kept, ties are broken in favor of the lowest token
+/// ids.
+pub fn f_top_p_filter(logits: &Tensor, p: f64) -> Result<Tensor, TchError> {
+    let (sorted, indices) = logits.f_sort_stable(true, -1, true)?;
+    let probs = sorted.f_softmax(-1, Kind::Float)?;
+    // The probability mass of the more likely tokens.
+    let mass_before = probs.f_cumsum(-1, Kind::Float)?.f_sub(&probs)?;
+    let remove = mass_before.f_ge(p)?;
+    let _ = remove.f_narrow(-1, 0, 1)?.f_fill_(false)?;
+    remove_sorted(logits, &indices, &remove)
+}
+
+pub fn top_p_filter(logits: &Tensor, p: f64) -> Tensor {
+    f_top_p_filter(logits, p).unwrap()
+}
+
+fn step<S, F>(
+    ids: &Tensor,
+    state: &mut S,
+    step_fn: &mut F,
+    config: &GenerateConfig,
+) -> Result<Tensor, TchError>
+where
+    F: FnMut(&Tensor, &mut S) -> Tensor,
+{
+    let logits = step_fn(ids, state).f_to_kind(Kind::Float)?;
+    if config.repetition_penalty != 1.0 {
+        f_apply_repetition_penalty(&logits, ids, config.repetition_penalty)
+    } else {
+        Ok(logits)
+    }
+}
+
+fn greedy_or_sample<S, F>(
+    input_ids: &Tensor,
+    state: &mut S,
+    mut step_fn: F,
+    config: &GenerateConfig,
+) -> Result<Tensor, TchError>
+where
+    F: FnMut(&Tensor, &mut S) -> Tensor,
+{
+    if config.do_sample && (config.temperature <= 0. || config.temperature.is_nan()) {
+        return Err(TchError::Torch(format!(
+            "the sampling temperature has to be positive, got {}",
+            config.temperature
+        )));
+    }
+    let (batch, _) = input_ids.size2()?;
+    let mut ids = input_ids.shallow_clone();
+    let mut finished = Tensor::f_zeros([batch], (Kind::Bool, input_ids.device()))?;
+    for _ in 0..config.max_new_tokens {
+        let logits = step(&ids, state, &mut step_fn, config)?;
+        let next = if config.do_sample {
+            let mut logits = logits.f_div_scalar(config.temperature)?;
+            if let Some(k) = config.top_k {
+                logits = f_top_k_filter(&logits, k)?
+            }
+            if let Some(p) = config.top_p {
+                logits = f_top_p_filter(&logits, p)?
+            }
+            logits.f_softmax(-1, Kind::Float)?.f_multinomial(1, false)?.f_squeeze_dim(-1)?
+        } else {
+            logits.f_argmax(-1, false)?
+        };
+        let next = match config.eos_token {
+            Some(eos) => {
+                let next = next.f_masked_fill(&finished, eos)?;
+                finished = finished.f_logical_or(&next.f_eq(eos)?)?;
+                next
+            }
+            None => next,
+        };
+        ids = Tensor::f_cat(&[&ids, &next.f_unsqueeze(-1)?], 1)?;
+        if config.eos_token.is_some() && bool::try_from(finished.f_all()?)? {
+            break;
+        }
+    }
+    Ok(ids)
+}
+
+// The finished sequences of a batch element, at most `num_beams` of them are
+// kept, the best ones first.
+struct Hypotheses {
+    num_beams: usize,
+    hyps: Vec<(f64, Vec<i64>)>,
+}
+
+impl Hypotheses {
+    fn add(&mut self, score: f64, tokens: Vec<i64>) {
+        let index = self.hyps.partition_point(|(s, _)| *s >= score);
+        self.hyps.insert(index, (score, tokens));
+        self.hyps.truncate(self.num_beams)
+    }
+
+    fn worst_score(&self) -> Option<f64> {
+        if self.hyps.len() < self.num_beams {
+            None
+        } else {
+            self.hyps.last().map(|(s, _)| *s)
+        }
+    }
+}
+
+fn beam_search<S, F>(
+    input_ids: &Tensor,
+    state: &mut S,
+    mut step_fn: F,
+    config: &GenerateConfig,
+) -> Result<Tensor, TchError>
+where
+    S: State,
+    F: FnMut(&Tensor, &mut S) -> Tensor,
+{
+    let device = input_ids.device();
+    let n = config.num_beams;
+    let (batch, prompt_len) = input_ids.size2()?;
+    let batch = batch as usize;
+    let pad = config.eos_token.unwrap_or(0);
+    let normalize = |score: f64, len: i64| score / (len.max(1) as f64).powf(config.length_penalty);
+    let mut ids = input_ids.f_repeat_interleave_self_int(n as i64, 0, None)?;
+    // Only the first beam is used initially so that the beams differ.
+    let mut beam_scores: Vec<f64> =
+        (0..batch * n).map(|i| if i % n == 0 { 0. } else { -1e9 }).collect();
+    let mut hyps: Vec<Hypotheses> =
+        (0..batch).map(|_| Hypotheses { num_beams: n, hyps: vec![] }).collect();
+    let mut done = vec![false; batch];
+    for gen_len in 1..=config.max_new_tokens {
+        let logits = step(&ids, state, &mut step_fn, config)?;
+        let vocab_size = logits.size2()?.1;
+        let scores =
+            Tensor::from_slice(&beam_scores).f_to_device(device)?.f_to_kind(Kind::Float)?;
+        let scores = logits.f_log_softmax(-1, Kind::Float)?.f_add(&scores.f_unsqueeze(-1)?)?;
+        let k = (2 * n as i64).min(n as i64 * vocab_size);
+        let (top_scores, top_indices) =
+            scores.f_view([batch as i64, -1])?.f_topk(k, -1, true, true)?;
+        let top_scores = Vec::<Vec<f64>>::try_from(&top_scores)?;
+        let top_indices = Vec::<Vec<i64>>::try_from(&top_indices)?;
+        let generated = Vec::<Vec<i64>>::try_from(&ids.f_narrow(1, prompt_len, gen_len - 1)?)?;
+        let mut next_tokens = Vec::with_capacity(batch * n);
+        let mut next_beams = Vec::with_capacity(batch * n);
+        let mut next_scores = Vec::with_capacity(batch * n);
+        for b in 0..batch {
+            let first = next_tokens.len();
+            if !done[b] {
+                for (rank, (&score, &index)) in
+                    top_scores[b].iter().zip(top_indices[b].iter()).enumerate()
+                {
+                    let beam = b * n + (index / vocab_size) as usize;
+                    let token = index % vocab_size;
+                    if Some(token) == config.eos_token {
+                        // Finished sequences outside of the top beams are ignored.
+                        if rank < n {
+                            let mut tokens = generated[beam].clone();
+                            tokens.push(token);
+                            hyps[b].add(normalize(score, gen_len), tokens)
+                        }
+                    } else {
+                        next_tokens.push(token);
+                        next_beams.push(beam as i64);
+                        next_scores.push(score);
+                    }
+                    if next_tokens.len() - first == n {
+                        break;
+                    }
+                }
+                done[b] = match hyps[b].worst_score() {
+                    None => false,
+                    Some(_) if config.early_stopping => true,
+                    Some(worst) => match next_scores.get(first) {
+                        None => true,
+                        Some(&best) => worst >= normalize(best, gen_len),
+                    },
+                };
+            }
+            // Finished batch elements keep padding their first beam.
+            while next_tokens.len() - first < n {
+                next_tokens.push(pad);
+                next_beams.push((b * n) as i64);
+                next_scores.push(-1e9);
+            }
+        }
+        if done.iter().all(|&d| d) {
+            break;
+        }
+        beam_scores = next_scores;
+        let beam_indices = Tensor::from_slice(&next_beams).f_to_device(device)?;
+        let next_tokens = Tensor::from_slice(&next_tokens).f_to_device(device)?;
+        ids = Tensor::f_cat(
+            &[ids.f_index_select(0, &beam_indices)?, next_tokens.f_unsqueeze(-1)?],
+            1,
+        )?;
+        state.reorder(&beam_indices)?;
+    }
+    // The running beams of unfinished batch elements are candidates too.
+    let (_, seq_len) = ids.size2()?;
+    let generated =
+        Vec::<Vec<i64>>::try_from(&ids.f_narrow(1, prompt_len, seq_len - prompt_len)?)?;
+    for b in 0..batch {
+        if !done[b] {
+            for beam in b * n..(b + 1) * n {
+                hyps[b].add(
+                    normalize(beam_scores[beam], seq_len - prompt_len),
+                    generated[beam].clone(),
+                )
+            }
+        }
+    }
+    let prompts = Vec::<Vec<i64>>::try_from(input_ids)?;
+    let max_len =
+        hyps.iter().map(|h| h.hyps.first().map_or(0, |(_, t)| t.len())).max().unwrap_or(0);
+    let mut out = Vec::with_capacity(batch * (prompt_len as usize + max_len));
+    for (prompt, hyps) in prompts.into_iter().zip(hyps.iter()) {
+        let tokens = hyps.hyps.first().map_or(&[][..], |(_, t)| t.as_slice());
+        out.extend(prompt);
+        out.extend_from_slice(tokens);
+        out.resize(out.len() + max_len - tokens.len(), pad);
+    }
+    Tensor::from_slice(&out).f_view([batch as i64, -1])?.f_to_device(device)
+}
+
+/// Generates up to `max_new_tokens` tokens after the prompts `input_ids` of
+/// shape `[batch, prompt_len]`, and returns the prompts followed by the
+/// generated tokens. Sequences finished before the others are padded with
+/// the end of sequence token.
+pub fn f_generate<S, F>(
+    input_ids: &Tensor,
+    state: &mut S,
+    step_fn: F,
+    config: &GenerateConfig,
+) -> Result<Tensor, TchError>
+where
+    S: State,
+    F: FnMut(&Tensor, &mut S) -> Tensor,
+{
+    let _prior_rng_state = match config.seed {
+        Some(seed) => {
+            let prior = crate::rng::RestoreOnDrop(crate::rng::f_fork_state()?);
+            crate::manual_seed(seed);
+            Some(prior)
+        }
+        None => None,
+    };
+    let _no_grad = crate::no_grad_guard();
+    let input_ids = input_ids.f_to_kind(Kind::Int64)?;
+    if config.num_beams > 1 {
+        beam_search(&input_ids, state, step_fn, config)
+    } else {
+        greedy_or_sample(&input_ids, state, step_fn, config)
+    }
+}
+
+/// Generates tokens after some prompts, see `f_generate`.
+pub fn generate<S, F>(
+    input_ids: &Tensor,
+    state: &mut S,
+    step_fn: F,
+    config: &GenerateConfig,
+) -> Tensor
+where
+    S: State,
+    F: FnMut(&Tensor, &mut S) -> Tensor,
+{
+    f_generate(input_ids, state, step_fn, config).unwrap()
+}
@@ -0,0 +1,134 @@
+//! Cooperative cancellation of long-running computations.
+//!
+//! A `CancellationToken` is checked between operations, either explicitly with
+//! `check` or automatically before each forward pass of the modules wrapped
+//! with `wrap`. Once the token has been cancelled or its deadline has passed,
+//! the next check returns a `TchError::Cancelled` error that is propagated
+//! with `?`, the tensors owned by the computation are then freed as usual.
+//!
+//! The checks happen between operations only: a libtorch operation that has
+//! started, e.g. a single large matmul or convolution, always runs to
+//! completion. The same applies to the forward pass of a `CModule` which runs
+//! as a single operation from the Rust side, the TorchScript graph executor
+//! does not provide interruption points. The latency of the cancellation is
+//! thus the duration of the longest operation, or of the longest module in
+//! the case of wrapped modules, between two checks.
+//!
+//! ```no_run
+//! # use tch::interrupt::{self, CancellationToken};
+//! # use tch::{Kind, Tensor};
+//! let token = CancellationToken::new();
+//! let res = interrupt::run_with_timeout(std::time::Duration::from_millis(100), &token, |t| {
+//!     let mut xs = Tensor::randn([256, 256], (Kind::Float, tch::Device::Cpu));
+//!     loop {
+//!         t.check()?;
+//!         xs = xs.f_matmul(&xs)?.f_tanh()?;
+//!     }
+//! });
+//! assert!(matches!(res, Err::<(), _>(tch::TchError::Cancelled)));
+//! ```
+use crate::nn::ModuleT;
+use crate::{TchError, Tensor};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct State {
+    cancelled: AtomicBool,
+    deadline: Option<Instant>,
+    parent: Option<CancellationToken>,
+}
+
+/// A token used to cancel computations, the clones of a token share its
+/// state so that it can be cancelled from another thread.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    state: Arc<State>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    /// Creates a token that is only cancelled by calling `cancel`.
+    pub fn new() -> CancellationToken {
+        let state = State { cancelled: AtomicBool::new(false), deadline: None, parent: None };
+        CancellationToken { state: Arc::new(state) }
+    }
+
+    /// Creates a token that is cancelled after `timeout`, or when this token
+    /// is cancelled.
+    pub fn child_with_timeout(&self, timeout: Duration) -> CancellationToken {
+        let state = State {
+            cancelled: AtomicBool::new(false),
+            deadline: Instant::now().checked_add(timeout),
+            parent: Some(self.clone()),
+        };
+        CancellationToken { state: Arc::new(state) }
+    }
+
+    /// Cancels the computations checking this token or its children.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed)
+    }
+
+    /// Returns true if the token has been cancelled or its deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+            || self.state.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            || self.state.parent.as_ref().is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Returns a `TchError::Cancelled` error if the token has been cancelled.
+    pub fn check(&self) -> Result<(), TchError> {
+        if self.is_cancelled() {
+            Err(TchError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wraps `module` so that the token is checked before each of its forward
+    /// passes.
+    pub fn wrap<M: ModuleT>(&self, module: M) -> Cancellable<M> {
+        Cancellable { module, token: self.clone() }
+    }
+}
+
+/// Runs `f` with a token that is cancelled after `timeout` or when `token` is
+/// cancelled, `f` has to check the token regularly and return early with the
+/// `TchError::Cancelled` error of the check.
+pub fn run_with_timeout<T, F>(
+    timeout: Duration,
+    token: &CancellationToken,
+    f: F,
+) -> Result<T, TchError>
+where
+    F: FnOnce(&CancellationToken) -> Result<T, TchError>,
+{
+    let token = token.child_with_timeout(timeout);
+    token.check()?;
+    f(&token)
+}
+
+/// A module whose forward passes check a `CancellationToken` first.
+#[derive(Debug)]
+pub struct Cancellable<M> {
+    pub module: M,
+    token: CancellationToken,
+}
+
+impl<M: ModuleT> ModuleT for Cancellable<M> {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        self.token.check()?;
+        self.module.f_forward_t(xs, train)
+    }
+}
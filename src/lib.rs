@@ -1,34 +1,60 @@
+// The panic-free feature deprecates the panicking functions for the users of
+// the crate, they are still used internally.
+#![cfg_attr(feature = "panic-free", allow(deprecated))]
 #[macro_use]
 extern crate lazy_static;
 
+pub mod amp;
+pub mod bench;
+pub mod calibrate;
 pub mod data;
+pub mod debug;
+pub mod generate;
+pub mod interrupt;
+pub mod metrics;
 
 mod error;
-pub use error::TchError;
+pub use error::{TchError, TensorMeta};
 pub type Result<T> = std::result::Result<T, error::TchError>;
 
 pub(crate) mod wrappers;
-pub use wrappers::device::{Cuda, Device};
+pub use wrappers::autograd;
+pub use wrappers::backends;
+pub use wrappers::cuda;
+pub use wrappers::device::{Cuda, Device, Mps};
+#[cfg(feature = "distributed")]
+pub use wrappers::distributed;
 pub use wrappers::jit::{self, CModule, IValue, TrainableCModule};
 pub use wrappers::kind::{self, Kind};
 pub use wrappers::layout::Layout;
+pub use wrappers::ops;
 pub use wrappers::optimizer::COptimizer;
+pub use wrappers::profiler;
 #[cfg(feature = "python-extension")]
 pub use wrappers::python;
+pub use wrappers::rng;
 pub use wrappers::scalar::Scalar;
 pub use wrappers::utils;
+pub use wrappers::version::{self, build_info, BuildInfo};
+pub use wrappers::warnings;
 pub use wrappers::{
-    get_num_interop_threads, get_num_threads, manual_seed, set_num_interop_threads,
-    set_num_threads, QEngine,
+    deterministic_report, f_set_num_interop_threads, f_set_num_threads, get_num_interop_threads,
+    get_num_threads, initial_seed, manual_seed, manual_seed_all, set_deterministic,
+    set_num_interop_threads, set_num_threads, DeterminismReport, QEngine,
 };
 
 mod tensor;
 pub use tensor::{
-    autocast, display, index, no_grad, no_grad_guard, with_grad, IndexOp, NewAxis, NoGradGuard,
-    Reduction, Shape, Tensor, TensorIndexer,
+    autocast, autocast_guard, display, f_autocast_guard, index, inference_mode, is_grad_enabled,
+    no_grad, no_grad_guard, with_grad, AutocastGuard, GradGuard, IndexOp, InferenceModeGuard,
+    NewAxis, NoGradGuard, OutputArena, Reduction, Shape, SharedTensor, Tensor, TensorIndexer,
 };
 
 pub mod nn;
+pub mod prune;
+pub mod quant;
+pub mod train;
+pub mod typed;
 pub mod vision;
 
 pub fn maybe_init_cuda() {
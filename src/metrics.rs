@@ -0,0 +1,406 @@
+//! Evaluation metrics accumulated over batches.
+//!
+//! The accumulators keep their state in tensors on the device of the inputs
+//! so that updating them does not require copying data back to the host,
+//! the data is only copied in `compute`. Accumulators can be merged, e.g. to
+//! combine the results computed on multiple devices.
+//!
+//! ```no_run
+//! # use tch::{metrics::{Accuracy, ConfusionMatrix, Metric}, Device, Kind, Tensor};
+//! # let batches: Vec<(Tensor, Tensor)> = vec![];
+//! let mut accuracy = Accuracy::topk(5);
+//! let mut confusion = ConfusionMatrix::new(10);
+//! for (logits, targets) in batches.iter() {
+//!     accuracy.update(logits, targets)?;
+//!     confusion.update(logits, targets)?;
+//! }
+//! println!("top-5 accuracy: {:.3}", accuracy.compute()?);
+//! println!("macro f1: {:.3}", confusion.compute()?.macro_f1);
+//! # Ok::<(), tch::TchError>(())
+//! ```
+use crate::{Kind, TchError, Tensor};
+
+/// An accumulator for a metric.
+pub trait Metric {
+    type Output;
+
+    /// Computes the metric for all the values accumulated since the last
+    /// reset.
+    fn compute(&self) -> Result<Self::Output, TchError>;
+
+    fn reset(&mut self);
+
+    /// Adds the values accumulated by another accumulator of the same metric.
+    fn merge(&mut self, other: &Self) -> Result<(), TchError>;
+}
+
+// Adds `other` to an optional accumulator tensor, moving it to the device of
+// the accumulator.
+fn accumulate(acc: &mut Option<Tensor>, other: &Tensor) -> Result<(), TchError> {
+    *acc = Some(match acc.as_ref() {
+        Some(acc) => acc.f_add(&other.f_to_device(acc.device())?)?,
+        None => other.shallow_clone(),
+    });
+    Ok(())
+}
+
+fn scalar(t: &Option<Tensor>) -> Result<f64, TchError> {
+    match t.as_ref() {
+        Some(t) => f64::try_from(t),
+        None => Ok(0.),
+    }
+}
+
+// Returns class indices of shape `[n]` from either class indices or scores of
+// shape `[n, num_classes]`.
+fn class_indices(preds: &Tensor) -> Result<Tensor, TchError> {
+    if preds.f_dim()? == 2 {
+        preds.f_argmax(-1, false)
+    } else {
+        preds.f_to_kind(Kind::Int64)
+    }
+}
+
+// Returns an error if some class indices are not in `[0, num_classes)`.
+fn check_class_range(name: &str, indices: &Tensor, num_classes: i64) -> Result<(), TchError> {
+    if indices.numel() == 0 {
+        return Ok(());
+    }
+    let min = i64::try_from(&indices.f_min()?)?;
+    let max = i64::try_from(&indices.f_max()?)?;
+    if min < 0 || max >= num_classes {
+        return Err(TchError::Shape(format!(
+            "{name} should be class indices in [0, {num_classes}), got values between {min} and {max}"
+        )));
+    }
+    Ok(())
+}
+
+/// The fraction of samples for which the target is among the `k` highest
+/// scores.
+#[derive(Debug)]
+pub struct Accuracy {
+    k: i64,
+    correct: Option<Tensor>,
+    total: i64,
+}
+
+impl Accuracy {
+    pub fn new() -> Accuracy {
+        Accuracy::topk(1)
+    }
+
+    pub fn topk(k: i64) -> Accuracy {
+        Accuracy { k, correct: None, total: 0 }
+    }
+
+    /// Updates the accuracy with scores of shape `[n, num_classes]` and class
+    /// indices of shape `[n]`.
+    pub fn update(&mut self, scores: &Tensor, targets: &Tensor) -> Result<(), TchError> {
+        let (n, num_classes) = scores.size2()?;
+        let (_, topk) = scores.f_topk(self.k.min(num_classes), -1, true, false)?;
+        let targets = targets.f_to_kind(Kind::Int64)?.f_to_device(scores.device())?;
+        let correct = topk.f_eq_tensor(&targets.f_view([n, 1])?)?.f_any_dim(-1, false)?;
+        accumulate(&mut self.correct, &correct.f_sum(Kind::Int64)?)?;
+        self.total += n;
+        Ok(())
+    }
+}
+
+impl Default for Accuracy {
+    fn default() -> Self {
+        Accuracy::new()
+    }
+}
+
+impl Metric for Accuracy {
+    type Output = f64;
+
+    fn compute(&self) -> Result<f64, TchError> {
+        if self.total == 0 {
+            return Ok(0.);
+        }
+        Ok(scalar(&self.correct)? / self.total as f64)
+    }
+
+    fn reset(&mut self) {
+        self.correct = None;
+        self.total = 0
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), TchError> {
+        if let Some(correct) = other.correct.as_ref() {
+            accumulate(&mut self.correct, correct)?
+        }
+        self.total += other.total;
+        Ok(())
+    }
+}
+
+/// The weighted mean of some values, e.g. of the loss over an epoch.
+#[derive(Debug, Default)]
+pub struct MeanMetric {
+    sum: Option<Tensor>,
+    count: f64,
+}
+
+impl MeanMetric {
+    pub fn new() -> MeanMetric {
+        MeanMetric::default()
+    }
+
+    /// Adds all the elements of `values`.
+    pub fn update(&mut self, values: &Tensor) -> Result<(), TchError> {
+        let sum = values.f_detach()?.f_sum(Kind::Double)?;
+        accumulate(&mut self.sum, &sum)?;
+        self.count += values.numel() as f64;
+        Ok(())
+    }
+
+    /// Adds a value with the given weight, e.g. the mean loss of a batch
+    /// weighted by the batch size.
+    pub fn update_weighted(&mut self, value: &Tensor, weight: f64) -> Result<(), TchError> {
+        let sum = value.f_detach()?.f_sum(Kind::Double)?.f_mul_scalar(weight)?;
+        accumulate(&mut self.sum, &sum)?;
+        self.count += weight;
+        Ok(())
+    }
+}
+
+impl Metric for MeanMetric {
+    type Output = f64;
+
+    fn compute(&self) -> Result<f64, TchError> {
+        if self.count == 0. {
+            return Ok(0.);
+        }
+        Ok(scalar(&self.sum)? / self.count)
+    }
+
+    fn reset(&mut self) {
+        self.sum = None;
+        self.count = 0.
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), TchError> {
+        if let Some(sum) = other.sum.as_ref() {
+            accumulate(&mut self.sum, sum)?
+        }
+        self.count += other.count;
+        Ok(())
+    }
+}
+
+/// Per-class and averaged classification metrics derived from a confusion
+/// matrix. Ratios with a zero denominator are set to 0.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClassificationReport {
+    /// The number of samples of class `i` predicted as class `j` is
+    /// `matrix[i][j]`.
+    pub matrix: Vec<Vec<i64>>,
+    pub precision: Vec<f64>,
+    pub recall: Vec<f64>,
+    pub f1: Vec<f64>,
+    /// The number of samples of each class.
+    pub support: Vec<i64>,
+    pub accuracy: f64,
+    /// The unweighted means of the per-class metrics.
+    pub macro_precision: f64,
+    pub macro_recall: f64,
+    pub macro_f1: f64,
+    /// The metrics computed from the total counts over all classes, for
+    /// single-label classification these are all equal to the accuracy.
+    pub micro_precision: f64,
+    pub micro_recall: f64,
+    pub micro_f1: f64,
+}
+
+fn ratio(num: i64, den: i64) -> f64 {
+    if den == 0 {
+        0.
+    } else {
+        num as f64 / den as f64
+    }
+}
+
+fn f1(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0. {
+        0.
+    } else {
+        2. * precision * recall / (precision + recall)
+    }
+}
+
+fn mean(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        0.
+    } else {
+        xs.iter().sum::<f64>() / xs.len() as f64
+    }
+}
+
+impl ClassificationReport {
+    fn from_matrix(matrix: Vec<Vec<i64>>) -> ClassificationReport {
+        let num_classes = matrix.len();
+        let tp: Vec<i64> = (0..num_classes).map(|c| matrix[c][c]).collect();
+        let support: Vec<i64> = matrix.iter().map(|row| row.iter().sum()).collect();
+        let predicted: Vec<i64> =
+            (0..num_classes).map(|c| matrix.iter().map(|row| row[c]).sum()).collect();
+        let precision: Vec<f64> = (0..num_classes).map(|c| ratio(tp[c], predicted[c])).collect();
+        let recall: Vec<f64> = (0..num_classes).map(|c| ratio(tp[c], support[c])).collect();
+        let f1s: Vec<f64> = precision.iter().zip(recall.iter()).map(|(&p, &r)| f1(p, r)).collect();
+        let total_tp = tp.iter().sum();
+        let micro_precision = ratio(total_tp, predicted.iter().sum());
+        let micro_recall = ratio(total_tp, support.iter().sum());
+        ClassificationReport {
+            accuracy: ratio(total_tp, support.iter().sum()),
+            macro_precision: mean(&precision),
+            macro_recall: mean(&recall),
+            macro_f1: mean(&f1s),
+            micro_precision,
+            micro_recall,
+            micro_f1: f1(micro_precision, micro_recall),
+            matrix,
+            precision,
+            recall,
+            f1: f1s,
+            support,
+        }
+    }
+}
+
+/// Counts the predictions for each pair of target and predicted classes.
+#[derive(Debug)]
+pub struct ConfusionMatrix {
+    num_classes: i64,
+    counts: Option<Tensor>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(num_classes: i64) -> ConfusionMatrix {
+        ConfusionMatrix { num_classes, counts: None }
+    }
+
+    /// Updates the counts with predictions, either class indices of shape
+    /// `[n]` or scores of shape `[n, num_classes]`, and class indices of
+    /// shape `[n]`. An error is returned for class indices outside of
+    /// `[0, num_classes)`.
+    pub fn update(&mut self, preds: &Tensor, targets: &Tensor) -> Result<(), TchError> {
+        let c = self.num_classes;
+        let preds = class_indices(preds)?;
+        let targets = targets.f_to_kind(Kind::Int64)?.f_to_device(preds.device())?;
+        check_class_range("predictions", &preds, c)?;
+        check_class_range("targets", &targets, c)?;
+        let index = targets.f_mul_scalar(c)?.f_add(&preds)?.f_view([-1])?;
+        let counts = index.f_bincount::<Tensor>(None, c * c)?.f_view([c, c])?;
+        accumulate(&mut self.counts, &counts)
+    }
+
+    /// The counts with shape `[num_classes, num_classes]`, rows are indexed by
+    /// the targets and columns by the predictions.
+    pub fn matrix(&self) -> Result<Tensor, TchError> {
+        match self.counts.as_ref() {
+            Some(counts) => Ok(counts.shallow_clone()),
+            None => Tensor::f_zeros([self.num_classes, self.num_classes], crate::kind::INT64_CPU),
+        }
+    }
+}
+
+impl Metric for ConfusionMatrix {
+    type Output = ClassificationReport;
+
+    fn compute(&self) -> Result<ClassificationReport, TchError> {
+        let matrix = Vec::<Vec<i64>>::try_from(&self.matrix()?)?;
+        Ok(ClassificationReport::from_matrix(matrix))
+    }
+
+    fn reset(&mut self) {
+        self.counts = None
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), TchError> {
+        if other.num_classes != self.num_classes {
+            return Err(TchError::Shape(format!(
+                "cannot merge confusion matrices with {} and {} classes",
+                self.num_classes, other.num_classes
+            )));
+        }
+        if let Some(counts) = other.counts.as_ref() {
+            accumulate(&mut self.counts, counts)?
+        }
+        Ok(())
+    }
+}
+
+/// The area under the ROC curve for binary classification.
+///
+/// The scores and targets are accumulated and the area is computed with the
+/// rank-based formulation of the Mann-Whitney U statistic, tied scores get
+/// their average rank.
+#[derive(Debug, Default)]
+pub struct AuRoc {
+    scores: Vec<Tensor>,
+    targets: Vec<Tensor>,
+}
+
+impl AuRoc {
+    pub fn new() -> AuRoc {
+        AuRoc::default()
+    }
+
+    /// Adds scores for the positive class and binary targets, both of shape
+    /// `[n]`.
+    pub fn update(&mut self, scores: &Tensor, targets: &Tensor) -> Result<(), TchError> {
+        self.scores.push(scores.f_detach()?.f_flatten(0, -1)?.f_to_kind(Kind::Double)?);
+        let targets = targets.f_flatten(0, -1)?.f_to_device(scores.device())?;
+        self.targets.push(targets.f_gt(0.5)?);
+        Ok(())
+    }
+}
+
+impl Metric for AuRoc {
+    type Output = f64;
+
+    fn compute(&self) -> Result<f64, TchError> {
+        if self.scores.is_empty() {
+            return Ok(0.);
+        }
+        let device = self.scores[0].device();
+        let scores: Vec<Tensor> =
+            self.scores.iter().map(|s| s.f_to_device(device)).collect::<Result<_, _>>()?;
+        let targets: Vec<Tensor> =
+            self.targets.iter().map(|t| t.f_to_device(device)).collect::<Result<_, _>>()?;
+        let scores = Tensor::f_cat(&scores, 0)?;
+        let targets = Tensor::f_cat(&targets, 0)?;
+        let (sorted, order) = scores.f_sort_stable(true, 0, false)?;
+        let (_, inverse, counts) = sorted.f_unique_consecutive(true, true, None::<i64>)?;
+        // The 1-based average rank of each group of tied scores.
+        let counts = counts.f_to_kind(Kind::Double)?;
+        let avg_ranks =
+            counts.f_cumsum(0, Kind::Double)?.f_sub(&counts.f_sub_scalar(1.)?.f_div_scalar(2.)?)?;
+        let ranks = avg_ranks.f_index_select(0, &inverse)?;
+        let positive = targets.f_index_select(0, &order)?;
+        let num_pos = i64::try_from(positive.f_sum(Kind::Int64)?)?;
+        let num_neg = positive.numel() as i64 - num_pos;
+        if num_pos == 0 || num_neg == 0 {
+            return Err(TchError::Shape(
+                "auroc is not defined when only one class is present".to_string(),
+            ));
+        }
+        let pos_ranks = f64::try_from(ranks.f_masked_select(&positive)?.f_sum(Kind::Double)?)?;
+        let (p, n) = (num_pos as f64, num_neg as f64);
+        Ok((pos_ranks - p * (p + 1.) / 2.) / (p * n))
+    }
+
+    fn reset(&mut self) {
+        self.scores.clear();
+        self.targets.clear()
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), TchError> {
+        self.scores.extend(other.scores.iter().map(|s| s.shallow_clone()));
+        self.targets.extend(other.targets.iter().map(|t| t.shallow_clone()));
+        Ok(())
+    }
+}
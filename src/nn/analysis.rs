@@ -0,0 +1,222 @@
+//! Estimation of the compute and memory costs of a module.
+//!
+//! The estimation runs a forward pass in which the layers of this crate report
+//! their costs: linear, convolution, transposed convolution, normalization and
+//! embedding layers. FLOPs are counted as multiply-accumulates, the convention
+//! used by the usual published figures and by fvcore. Operations applied
+//! directly on tensors, e.g. pooling or activations in a closure, are not
+//! counted. The modules that do not report any cost, e.g. custom modules or
+//! closures in a sequential layer, are listed as unmeasured.
+//!
+//! ```no_run
+//! # use tch::{nn, Device};
+//! let vs = nn::VarStore::new(Device::Cpu);
+//! let net = tch::vision::resnet::resnet18(&vs.root(), 1000);
+//! let report = nn::analysis::estimate(&net, &[1, 3, 224, 224]);
+//! println!("{report}");
+//! ```
+use super::ModuleT;
+use crate::{Device, Kind, TchError, Tensor};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// The costs of a single layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerReport {
+    /// A name made of the layer kind and its index among the layers of the
+    /// same kind, e.g. `conv2d.3`.
+    pub name: String,
+    pub kind: String,
+    /// The number of multiply-accumulates, `None` for unmeasured layers.
+    pub flops: Option<u64>,
+    pub params: u64,
+    pub output_shape: Vec<i64>,
+    /// The size of the layer output in bytes.
+    pub activation_bytes: u64,
+}
+
+/// The costs of all the layers run in a forward pass, in execution order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisReport {
+    pub layers: Vec<LayerReport>,
+}
+
+impl AnalysisReport {
+    /// The total number of multiply-accumulates of the measured layers.
+    pub fn total_flops(&self) -> u64 {
+        self.layers.iter().filter_map(|l| l.flops).sum()
+    }
+
+    /// The total number of parameters of the measured layers.
+    pub fn total_params(&self) -> u64 {
+        self.layers.iter().map(|l| l.params).sum()
+    }
+
+    /// The total size of the layer outputs in bytes.
+    pub fn total_activation_bytes(&self) -> u64 {
+        self.layers.iter().map(|l| l.activation_bytes).sum()
+    }
+
+    /// The layers for which the FLOPs could not be measured.
+    pub fn unmeasured(&self) -> impl Iterator<Item = &LayerReport> {
+        self.layers.iter().filter(|l| l.flops.is_none())
+    }
+}
+
+impl std::fmt::Display for AnalysisReport {
+    /// Prints a table with one row per layer, most expensive layers first.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut layers: Vec<&LayerReport> = self.layers.iter().collect();
+        layers.sort_by_key(|l| std::cmp::Reverse(l.flops));
+        let name_width = layers.iter().map(|l| l.name.len()).max().unwrap_or(0).max(5);
+        writeln!(
+            f,
+            "{:name_width$} {:>20} {:>12} {:>16} {:>14}",
+            "layer", "output shape", "params", "flops", "activations"
+        )?;
+        for l in layers {
+            let flops = l.flops.map_or("unmeasured".to_string(), |flops| flops.to_string());
+            let shape = format!("{:?}", l.output_shape);
+            writeln!(
+                f,
+                "{:name_width$} {:>20} {:>12} {:>16} {:>14}",
+                l.name, shape, l.params, flops, l.activation_bytes
+            )?;
+        }
+        write!(
+            f,
+            "{:name_width$} {:>20} {:>12} {:>16} {:>14}",
+            "total",
+            "",
+            self.total_params(),
+            self.total_flops(),
+            self.total_activation_bytes()
+        )
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    layers: Vec<LayerReport>,
+    counts: HashMap<String, usize>,
+}
+
+impl Recorder {
+    fn push(&mut self, kind: String, output: &Tensor, params: u64, flops: Option<u64>) {
+        let count = self.counts.entry(kind.clone()).or_insert(0);
+        let name = format!("{kind}.{count}");
+        *count += 1;
+        let activation_bytes = (output.numel() * output.kind().elt_size_in_bytes()) as u64;
+        self.layers.push(LayerReport {
+            name,
+            kind,
+            flops,
+            params,
+            output_shape: output.size(),
+            activation_bytes,
+        })
+    }
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+fn numel(t: Option<&Tensor>) -> u64 {
+    t.map_or(0, |t| t.numel() as u64)
+}
+
+/// The multiply-accumulates of a convolution, each output element uses the
+/// weights for one output channel.
+pub(crate) fn conv_flops(output_numel: usize, ws: &Tensor) -> u64 {
+    output_numel as u64 * ws.size()[1..].iter().product::<i64>() as u64
+}
+
+/// Reports the costs of a layer when an estimation is running on the current
+/// thread, `flops` is only called in this case.
+pub(crate) fn record_layer<F>(kind: &str, output: &Tensor, params: &[Option<&Tensor>], flops: F)
+where
+    F: FnOnce() -> u64,
+{
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            let params = params.iter().map(|p| numel(*p)).sum();
+            recorder.push(kind.to_string(), output, params, Some(flops()))
+        }
+    })
+}
+
+fn layer_count() -> Option<usize> {
+    RECORDER.with(|recorder| recorder.borrow().as_ref().map(|r| r.layers.len()))
+}
+
+fn push_unmeasured<M: std::fmt::Debug + ?Sized>(module: &M, output: &Tensor) {
+    // Use the type name from the Debug representation, e.g. `MyModule { .. }`.
+    let name = format!("{module:?}");
+    let end = name.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(name.len());
+    let kind = if end == 0 { "module" } else { &name[..end] };
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.push(kind.to_string(), output, 0, None)
+        }
+    })
+}
+
+/// Runs the forward pass of a sub-module, the sub-module is reported as
+/// unmeasured if no layer reported its costs while it ran.
+pub(crate) fn record_module<M, F>(module: &M, f: F) -> Tensor
+where
+    M: std::fmt::Debug + ?Sized,
+    F: FnOnce() -> Tensor,
+{
+    f_record_module(module, || Ok(f())).unwrap()
+}
+
+/// Runs the fallible forward pass of a sub-module, see `record_module`.
+pub(crate) fn f_record_module<M, F>(module: &M, f: F) -> Result<Tensor, TchError>
+where
+    M: std::fmt::Debug + ?Sized,
+    F: FnOnce() -> Result<Tensor, TchError>,
+{
+    let before = layer_count();
+    let output = f()?;
+    if before.is_some() && before == layer_count() {
+        push_unmeasured(module, &output)
+    }
+    Ok(output)
+}
+
+// Restores the previous recorder, including when the forward pass panics.
+struct RecorderGuard(Option<Recorder>);
+
+impl Drop for RecorderGuard {
+    fn drop(&mut self) {
+        RECORDER.with(|recorder| recorder.replace(self.0.take()));
+    }
+}
+
+/// Estimates the costs of a module by running a forward pass in evaluation
+/// mode on the given input, without tracking gradients.
+pub fn f_estimate_input(module: &dyn ModuleT, xs: &Tensor) -> Result<AnalysisReport, TchError> {
+    let _no_grad = crate::no_grad_guard();
+    let _guard = RecorderGuard(RECORDER.with(|r| r.replace(Some(Recorder::default()))));
+    let output = module.f_forward_t(xs, false)?;
+    if layer_count() == Some(0) {
+        push_unmeasured(module, &output)
+    }
+    let recorder = RECORDER.with(|r| r.borrow_mut().take()).unwrap_or_default();
+    Ok(AnalysisReport { layers: recorder.layers })
+}
+
+/// Estimates the costs of a module for a float input of the given shape on
+/// the CPU, see `f_estimate_input`.
+pub fn f_estimate(module: &dyn ModuleT, input_shape: &[i64]) -> Result<AnalysisReport, TchError> {
+    let xs = Tensor::f_zeros(input_shape, (Kind::Float, Device::Cpu))?;
+    f_estimate_input(module, &xs)
+}
+
+/// Estimates the costs of a module for a float input of the given shape on
+/// the CPU, see `f_estimate_input`.
+pub fn estimate(module: &dyn ModuleT, input_shape: &[i64]) -> AnalysisReport {
+    f_estimate(module, input_shape).unwrap()
+}
@@ -0,0 +1,328 @@
+//! A multi-head attention layer.
+use crate::{Kind, TchError, Tensor};
+use std::borrow::Borrow;
+
+/// Configuration for a multi-head attention layer.
+#[derive(Debug, Clone, Copy)]
+pub struct MultiHeadAttentionConfig {
+    /// The dropout probability on the attention weights, only applied in
+    /// training mode.
+    pub dropout: f64,
+    pub bias: bool,
+    /// The number of features of the keys, defaults to `embed_dim`.
+    pub kdim: Option<i64>,
+    /// The number of features of the values, defaults to `embed_dim`.
+    pub vdim: Option<i64>,
+    /// When set the inputs and outputs use the `[batch, seq, feature]` layout
+    /// rather than `[seq, batch, feature]`.
+    pub batch_first: bool,
+}
+
+impl Default for MultiHeadAttentionConfig {
+    fn default() -> Self {
+        MultiHeadAttentionConfig {
+            dropout: 0.,
+            bias: true,
+            kdim: None,
+            vdim: None,
+            batch_first: false,
+        }
+    }
+}
+
+/// A multi-head attention layer, equivalent to `torch.nn.MultiheadAttention`.
+///
+/// The variables use the PyTorch names so that the checkpoints of PyTorch
+/// models can be loaded: `in_proj_weight` when the keys and values have
+/// `embed_dim` features and `q_proj_weight`, `k_proj_weight`, `v_proj_weight`
+/// otherwise, then `in_proj_bias`, `out_proj.weight` and `out_proj.bias`.
+#[derive(Debug)]
+pub struct MultiHeadAttention {
+    pub in_proj_weight: Option<Tensor>,
+    pub q_proj_weight: Option<Tensor>,
+    pub k_proj_weight: Option<Tensor>,
+    pub v_proj_weight: Option<Tensor>,
+    pub in_proj_bias: Option<Tensor>,
+    pub out_proj: super::Linear,
+    embed_dim: i64,
+    num_heads: i64,
+    kdim: i64,
+    vdim: i64,
+    config: MultiHeadAttentionConfig,
+}
+
+/// Creates a new multi-head attention layer, `embed_dim` must be divisible by
+/// `num_heads`.
+pub fn multi_head_attention<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    embed_dim: i64,
+    num_heads: i64,
+    config: MultiHeadAttentionConfig,
+) -> MultiHeadAttention {
+    let vs = vs.borrow();
+    assert!(
+        num_heads > 0 && embed_dim % num_heads == 0,
+        "embed_dim {embed_dim} is not divisible by num_heads {num_heads}"
+    );
+    let kdim = config.kdim.unwrap_or(embed_dim);
+    let vdim = config.vdim.unwrap_or(embed_dim);
+    // Xavier uniform initialization of the projections, as done by PyTorch.
+    let xavier = |fan_in: i64, fan_out: i64| {
+        let bound = (6. / (fan_in + fan_out) as f64).sqrt();
+        super::Init::Uniform { lo: -bound, up: bound }
+    };
+    let same_dims = kdim == embed_dim && vdim == embed_dim;
+    let (in_proj_weight, q_proj_weight, k_proj_weight, v_proj_weight) = if same_dims {
+        let init = xavier(embed_dim, 3 * embed_dim);
+        let ws = vs.var("in_proj_weight", &[3 * embed_dim, embed_dim], init);
+        (Some(ws), None, None, None)
+    } else {
+        let q = vs.var("q_proj_weight", &[embed_dim, embed_dim], xavier(embed_dim, embed_dim));
+        let k = vs.var("k_proj_weight", &[embed_dim, kdim], xavier(kdim, embed_dim));
+        let v = vs.var("v_proj_weight", &[embed_dim, vdim], xavier(vdim, embed_dim));
+        (None, Some(q), Some(k), Some(v))
+    };
+    let in_proj_bias = if config.bias {
+        Some(vs.var("in_proj_bias", &[3 * embed_dim], super::Init::Const(0.)))
+    } else {
+        None
+    };
+    let out_config = super::LinearConfig {
+        bias: config.bias,
+        bs_init: Some(super::Init::Const(0.)),
+        ..Default::default()
+    };
+    let out_proj = super::linear(vs / "out_proj", embed_dim, embed_dim, out_config);
+    MultiHeadAttention {
+        in_proj_weight,
+        q_proj_weight,
+        k_proj_weight,
+        v_proj_weight,
+        in_proj_bias,
+        out_proj,
+        embed_dim,
+        num_heads,
+        kdim,
+        vdim,
+        config,
+    }
+}
+
+// Converts a boolean mask where true marks the positions that cannot be
+// attended to into an additive mask.
+fn additive_mask(mask: &Tensor, kind: Kind) -> Result<Tensor, TchError> {
+    if mask.kind() == Kind::Bool {
+        mask.f_zeros_like()?.f_to_kind(kind)?.f_masked_fill(mask, f64::NEG_INFINITY)
+    } else {
+        mask.f_to_kind(kind)
+    }
+}
+
+impl MultiHeadAttention {
+    pub fn embed_dim(&self) -> i64 {
+        self.embed_dim
+    }
+
+    pub fn num_heads(&self) -> i64 {
+        self.num_heads
+    }
+
+    // The query, key and value projection weights and biases.
+    fn projections(&self) -> Result<Vec<(Tensor, Option<Tensor>)>, TchError> {
+        let ws = match &self.in_proj_weight {
+            Some(ws) => ws.f_chunk(3, 0)?,
+            None => [&self.q_proj_weight, &self.k_proj_weight, &self.v_proj_weight]
+                .iter()
+                .map(|ws| ws.as_ref().unwrap().shallow_clone())
+                .collect(),
+        };
+        let bs = match &self.in_proj_bias {
+            Some(bs) => bs.f_chunk(3, 0)?.into_iter().map(Some).collect(),
+            None => vec![None, None, None],
+        };
+        Ok(ws.into_iter().zip(bs).collect())
+    }
+
+    // Moves the inputs to the [batch, seq, feature] layout.
+    fn to_batch_first(&self, xs: &Tensor, batched: bool) -> Result<Tensor, TchError> {
+        if !batched {
+            xs.f_unsqueeze(0)
+        } else if self.config.batch_first {
+            Ok(xs.shallow_clone())
+        } else {
+            xs.f_transpose(0, 1)
+        }
+    }
+
+    /// Computes the attention of `query` over `key` and `value`.
+    ///
+    /// The inputs have shape `[seq, batch, feature]`, `[batch, seq, feature]`
+    /// with `batch_first`, or `[seq, feature]` for unbatched inputs.
+    /// `key_padding_mask` has shape `[batch, src_seq]` and `attn_mask` has
+    /// shape `[tgt_seq, src_seq]` or `[batch * num_heads, tgt_seq, src_seq]`.
+    /// Boolean masks are true for the positions that cannot be attended to,
+    /// floating point masks are added to the attention scores.
+    ///
+    /// When `need_weights` is set, the attention weights averaged over the
+    /// heads are returned along with the output, with shape
+    /// `[batch, tgt_seq, src_seq]`. Otherwise the attention is computed with
+    /// the fused `scaled_dot_product_attention` kernel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn f_forward_qkv_t(
+        &self,
+        query: &Tensor,
+        key: &Tensor,
+        value: &Tensor,
+        key_padding_mask: Option<&Tensor>,
+        attn_mask: Option<&Tensor>,
+        need_weights: bool,
+        train: bool,
+    ) -> Result<(Tensor, Option<Tensor>), TchError> {
+        let batched = query.dim() == 3;
+        let expected_dim = if batched { 3 } else { 2 };
+        for (name, xs, dim) in
+            [("query", query, self.embed_dim), ("key", key, self.kdim), ("value", value, self.vdim)]
+        {
+            let size = xs.size();
+            if size.len() != expected_dim || size.last() != Some(&dim) {
+                return Err(TchError::Shape(format!(
+                    "multi_head_attention {name} of shape {size:?}, expected {expected_dim} dims with {dim} features"
+                )));
+            }
+        }
+        let query = self.to_batch_first(query, batched)?;
+        let key = self.to_batch_first(key, batched)?;
+        let value = self.to_batch_first(value, batched)?;
+        let (bsz, tgt_len, _) = query.size3()?;
+        let src_len = key.size()[1];
+        let head_dim = self.embed_dim / self.num_heads;
+        // Projects the inputs and splits the heads, [batch, heads, seq, head_dim].
+        let proj = self.projections()?;
+        let heads = |xs: &Tensor, (ws, bs): &(Tensor, Option<Tensor>)| {
+            let len = xs.size()[1];
+            xs.f_linear(ws, bs.as_ref())?
+                .f_view([bsz, len, self.num_heads, head_dim])?
+                .f_transpose(1, 2)
+        };
+        let q = heads(&query, &proj[0])?;
+        let k = heads(&key, &proj[1])?;
+        let v = heads(&value, &proj[2])?;
+        let mut mask: Option<Tensor> = None;
+        if let Some(attn_mask) = attn_mask {
+            let attn_mask = match attn_mask.dim() {
+                2 => attn_mask.f_reshape([1, 1, tgt_len, src_len])?,
+                _ => attn_mask.f_reshape([bsz, self.num_heads, tgt_len, src_len])?,
+            };
+            mask = Some(additive_mask(&attn_mask, q.kind())?)
+        }
+        if let Some(key_padding_mask) = key_padding_mask {
+            let padding =
+                additive_mask(&key_padding_mask.f_reshape([bsz, 1, 1, src_len])?, q.kind())?;
+            mask = Some(match mask {
+                Some(mask) => mask.f_add(&padding)?,
+                None => padding,
+            })
+        }
+        let dropout = if train { self.config.dropout } else { 0. };
+        let (ys, weights) = if need_weights {
+            let mut scores =
+                q.f_matmul(&k.f_transpose(-2, -1)?)?.f_div_scalar((head_dim as f64).sqrt())?;
+            if let Some(mask) = &mask {
+                scores = scores.f_add(mask)?
+            }
+            let weights = scores.f_softmax(-1, None)?.f_dropout(dropout, train)?;
+            let ys = weights.f_matmul(&v)?;
+            (ys, Some(weights.f_mean_dim(1, false, None)?))
+        } else {
+            let ys = Tensor::f_scaled_dot_product_attention(&q, &k, &v, mask, dropout, false)?;
+            (ys, None)
+        };
+        let ys = ys.f_transpose(1, 2)?.f_reshape([bsz, tgt_len, self.embed_dim])?;
+        let ys = ys.f_linear(&self.out_proj.ws, self.out_proj.bs.as_ref())?;
+        let (ys, weights) = if !batched {
+            (ys.f_squeeze_dim(0)?, weights.map(|w| w.f_squeeze_dim(0)).transpose()?)
+        } else if self.config.batch_first {
+            (ys, weights)
+        } else {
+            (ys.f_transpose(0, 1)?, weights)
+        };
+        super::analysis::record_layer(
+            "multi_head_attention",
+            &ys,
+            &[
+                self.in_proj_weight.as_ref(),
+                self.q_proj_weight.as_ref(),
+                self.k_proj_weight.as_ref(),
+                self.v_proj_weight.as_ref(),
+                self.in_proj_bias.as_ref(),
+                Some(&self.out_proj.ws),
+                self.out_proj.bs.as_ref(),
+            ],
+            || {
+                let (bsz, tgt_len, src_len) = (bsz as u64, tgt_len as u64, src_len as u64);
+                let (embed_dim, kdim, vdim) =
+                    (self.embed_dim as u64, self.kdim as u64, self.vdim as u64);
+                let proj =
+                    2 * tgt_len * embed_dim * embed_dim + src_len * embed_dim * (kdim + vdim);
+                bsz * (proj + 2 * tgt_len * src_len * embed_dim)
+            },
+        );
+        Ok((ys, weights))
+    }
+
+    /// Computes the attention of `query` over `key` and `value`, see
+    /// `f_forward_qkv_t`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn forward_qkv_t(
+        &self,
+        query: &Tensor,
+        key: &Tensor,
+        value: &Tensor,
+        key_padding_mask: Option<&Tensor>,
+        attn_mask: Option<&Tensor>,
+        need_weights: bool,
+        train: bool,
+    ) -> (Tensor, Option<Tensor>) {
+        self.f_forward_qkv_t(query, key, value, key_padding_mask, attn_mask, need_weights, train)
+            .unwrap()
+    }
+
+    /// Computes the attention in evaluation mode, i.e. without dropout, see
+    /// `f_forward_qkv_t`.
+    pub fn f_forward_qkv(
+        &self,
+        query: &Tensor,
+        key: &Tensor,
+        value: &Tensor,
+        key_padding_mask: Option<&Tensor>,
+        attn_mask: Option<&Tensor>,
+        need_weights: bool,
+    ) -> Result<(Tensor, Option<Tensor>), TchError> {
+        self.f_forward_qkv_t(query, key, value, key_padding_mask, attn_mask, need_weights, false)
+    }
+
+    /// Computes the attention in evaluation mode, i.e. without dropout, see
+    /// `f_forward_qkv_t`.
+    pub fn forward_qkv(
+        &self,
+        query: &Tensor,
+        key: &Tensor,
+        value: &Tensor,
+        key_padding_mask: Option<&Tensor>,
+        attn_mask: Option<&Tensor>,
+        need_weights: bool,
+    ) -> (Tensor, Option<Tensor>) {
+        self.f_forward_qkv(query, key, value, key_padding_mask, attn_mask, need_weights).unwrap()
+    }
+}
+
+/// Self-attention without masks, the input is used as query, key and value.
+impl super::module::ModuleT for MultiHeadAttention {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        Ok(self.f_forward_qkv_t(xs, xs, xs, None, None, false, train)?.0)
+    }
+}
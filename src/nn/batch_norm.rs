@@ -1,6 +1,7 @@
 //! A batch-normalization layer.
 use crate::Tensor;
 use std::borrow::Borrow;
+use std::cell::Cell;
 
 /// Batch-normalization config.
 #[derive(Debug, Clone, Copy)]
@@ -37,6 +38,26 @@ pub struct BatchNorm {
     pub nd: usize,
 }
 
+thread_local! {
+    static MOMENTUM: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+// Restores the previous momentum override when dropped.
+struct MomentumGuard(Option<f64>);
+
+impl Drop for MomentumGuard {
+    fn drop(&mut self) {
+        MOMENTUM.with(|m| m.set(self.0))
+    }
+}
+
+/// Runs `f` with the momentum of all the batch-normalization layers replaced
+/// by `momentum` on the current thread.
+pub(crate) fn with_momentum<T, F: FnOnce() -> T>(momentum: f64, f: F) -> T {
+    let _guard = MomentumGuard(MOMENTUM.with(|m| m.replace(Some(momentum))));
+    f()
+}
+
 fn batch_norm<'a, T: Borrow<super::Path<'a>>>(
     vs: T,
     nd: usize,
@@ -117,16 +138,23 @@ impl super::module::ModuleT for BatchNorm {
                 xs.size()
             )
         };
-        Tensor::batch_norm(
+        let ys = Tensor::batch_norm(
             xs,
             self.ws.as_ref(),
             self.bs.as_ref(),
             Some(&self.running_mean),
             Some(&self.running_var),
             train,
-            self.config.momentum,
+            MOMENTUM.with(|m| m.get()).unwrap_or(self.config.momentum),
             self.config.eps,
             self.config.cudnn_enabled,
-        )
+        );
+        super::analysis::record_layer(
+            "batch_norm",
+            &ys,
+            &[self.ws.as_ref(), self.bs.as_ref()],
+            || ys.numel() as u64,
+        );
+        ys
     }
 }
@@ -0,0 +1,71 @@
+//! Gradient clipping for arbitrary sets of tensors.
+use crate::{TchError, Tensor};
+
+// The p-norm of all the elements of a gradient, p can be infinite.
+fn grad_norm(grad: &Tensor, norm_type: f64) -> Result<Tensor, TchError> {
+    let grad = grad.f_reshape([-1])?;
+    if norm_type == f64::INFINITY {
+        grad.f_abs()?.f_max()
+    } else {
+        grad.f_norm_scalaropt_dim(norm_type, [0], false)
+    }
+}
+
+/// Scales the gradients of `vars` so that their total `norm_type`-norm is at
+/// most `max_norm`, and returns the total norm before clipping.
+///
+/// The norm is computed over all gradients together, as if they were
+/// concatenated into a single vector, `norm_type` can be `f64::INFINITY` for
+/// the max norm. The tensors with an undefined gradient are skipped, the
+/// returned norm is zero when there are no gradients.
+pub fn f_clip_grad_norm(
+    vars: &[Tensor],
+    max_norm: f64,
+    norm_type: f64,
+) -> Result<Tensor, TchError> {
+    let _no_grad = crate::no_grad_guard();
+    let grads = vars.iter().map(|v| v.f_grad()).collect::<Result<Vec<_>, TchError>>()?;
+    let grads: Vec<Tensor> = grads.into_iter().filter(|g| g.defined()).collect();
+    let first = match grads.first() {
+        None => return Ok(Tensor::from(0f32)),
+        Some(first) => first,
+    };
+    let device = first.device();
+    let norms = grads
+        .iter()
+        .map(|g| grad_norm(g, norm_type)?.f_to_device(device))
+        .collect::<Result<Vec<_>, TchError>>()?;
+    let total_norm = grad_norm(&Tensor::f_stack(&norms, 0)?, norm_type)?;
+    // The coefficient is clamped rather than compared on the host so that
+    // clipping does not require a device synchronization.
+    let clip_coef = total_norm.f_add_scalar(1e-6)?.f_reciprocal()?.f_mul_scalar(max_norm)?;
+    let clip_coef = clip_coef.f_clamp_max(1.)?;
+    for grad in grads.iter() {
+        let _ = grad.shallow_clone().f_mul_(&clip_coef.f_to_device(grad.device())?)?;
+    }
+    Ok(total_norm)
+}
+
+/// Scales the gradients of `vars` so that their total norm is at most
+/// `max_norm`, see `f_clip_grad_norm`.
+pub fn clip_grad_norm(vars: &[Tensor], max_norm: f64, norm_type: f64) -> Tensor {
+    f_clip_grad_norm(vars, max_norm, norm_type).unwrap()
+}
+
+/// Clamps the gradients of `vars` in place between `-clip_value` and
+/// `clip_value`, the tensors with an undefined gradient are skipped.
+pub fn f_clip_grad_value(vars: &[Tensor], clip_value: f64) -> Result<(), TchError> {
+    let _no_grad = crate::no_grad_guard();
+    for var in vars.iter() {
+        let mut grad = var.f_grad()?;
+        if grad.defined() {
+            let _ = grad.f_clamp_(-clip_value, clip_value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Clamps the gradients of `vars` in place, see `f_clip_grad_value`.
+pub fn clip_grad_value(vars: &[Tensor], clip_value: f64) {
+    f_clip_grad_value(vars, clip_value).unwrap()
+}
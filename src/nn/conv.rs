@@ -5,7 +5,7 @@ use std::borrow::Borrow;
 
 /// How padding is performed by convolution operations
 /// on the edge of the input tensor.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PaddingMode {
     Zeros,
     Reflect,
@@ -39,12 +39,25 @@ impl PaddingMode {
     }
 }
 
+/// The padding added on both sides of the input of a convolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding<ND> {
+    /// Pads each spatial dimension with the given size.
+    Explicit(ND),
+    /// Pads so that the output has the same spatial size as the input, this
+    /// requires a stride of 1. When the total padding of a dimension is odd,
+    /// the extra padding is added on the right as in PyTorch.
+    Same,
+    /// No padding.
+    Valid,
+}
+
 /// Generic convolution config.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub struct ConvConfigND<ND> {
     pub stride: ND,
-    pub padding: ND,
+    pub padding: Padding<ND>,
     pub dilation: ND,
     pub groups: i64,
     pub bias: bool,
@@ -60,7 +73,7 @@ impl Default for ConvConfig {
     fn default() -> Self {
         ConvConfig {
             stride: 1,
-            padding: 0,
+            padding: Padding::Explicit(0),
             dilation: 1,
             groups: 1,
             bias: true,
@@ -75,7 +88,7 @@ impl Default for ConvConfigND<[i64; 2]> {
     fn default() -> Self {
         ConvConfigND::<[i64; 2]> {
             stride: [1, 1],
-            padding: [0, 0],
+            padding: Padding::Explicit([0, 0]),
             dilation: [1, 1],
             groups: 1,
             bias: true,
@@ -97,10 +110,31 @@ pub fn no_bias() -> ConvConfig {
 pub struct Conv<ND> {
     pub ws: Tensor,
     pub bs: Option<Tensor>,
+    // The padding of the convolution operation, zero when the input is padded
+    // beforehand with reversed_padding_repeated_twice.
+    padding: Vec<i64>,
+    pad_input: bool,
     reversed_padding_repeated_twice: Vec<i64>,
     config: ConvConfigND<ND>,
 }
 
+impl<ND> Conv<ND> {
+    pub(crate) fn config(&self) -> &ConvConfigND<ND> {
+        &self.config
+    }
+
+    // Pads the input when this is not done by the convolution operation,
+    // returning the padding to use in the convolution operation.
+    pub(crate) fn f_pad_input(&self, xs: &Tensor) -> Result<(Tensor, &[i64]), TchError> {
+        if self.pad_input {
+            let xs = self.config.padding_mode.f_pad(xs, &self.reversed_padding_repeated_twice)?;
+            Ok((xs, &self.padding))
+        } else {
+            Ok((xs.shallow_clone(), &self.padding))
+        }
+    }
+}
+
 /// One dimension convolution layer.
 pub type Conv1D = Conv<[i64; 1]>;
 
@@ -111,41 +145,82 @@ pub type Conv2D = Conv<[i64; 2]>;
 pub type Conv3D = Conv<[i64; 3]>;
 
 /// Creates a new convolution layer for any number of dimensions.
-pub fn conv<'a, ND: std::convert::AsRef<[i64]>, T: Borrow<super::Path<'a>>>(
+///
+/// This returns an error when using `Padding::Same` with a stride other than 1.
+pub fn f_conv<'a, ND: std::convert::AsRef<[i64]>, T: Borrow<super::Path<'a>>>(
     vs: T,
     in_dim: i64,
     out_dim: i64,
     ksizes: ND,
     config: ConvConfigND<ND>,
-) -> Conv<ND> {
+) -> Result<Conv<ND>, TchError> {
     let vs = vs.borrow();
+    let ksizes = ksizes.as_ref();
+    // The padding on the left and on the right of each spatial dimension.
+    let padding: Vec<(i64, i64)> = match &config.padding {
+        Padding::Explicit(padding) => padding.as_ref().iter().map(|&p| (p, p)).collect(),
+        Padding::Valid => ksizes.iter().map(|_| (0, 0)).collect(),
+        Padding::Same => {
+            let stride = config.stride.as_ref();
+            if stride.iter().any(|&s| s != 1) {
+                return Err(TchError::Shape(format!(
+                    "conv: same padding is not supported for strided convolutions, got stride {stride:?}"
+                )));
+            }
+            let dilation = config.dilation.as_ref();
+            ksizes
+                .iter()
+                .zip(dilation.iter().cycle())
+                .map(|(&k, &d)| {
+                    let total = d * (k - 1);
+                    (total / 2, total - total / 2)
+                })
+                .collect()
+        }
+    };
     let bs = if config.bias { Some(vs.var("bias", &[out_dim], config.bs_init)) } else { None };
     let mut weight_size = vec![out_dim, in_dim / config.groups];
-    weight_size.extend(ksizes.as_ref().iter());
+    weight_size.extend(ksizes.iter());
     let ws = vs.var("weight", weight_size.as_slice(), config.ws_init);
-    let mut reversed_padding_repeated_twice = vec![];
-    for &v in config.padding.as_ref().iter().rev() {
-        reversed_padding_repeated_twice.push(v)
-    }
-    for &v in config.padding.as_ref().iter().rev() {
-        reversed_padding_repeated_twice.push(v)
-    }
-    Conv { ws, bs, config, reversed_padding_repeated_twice }
+    let reversed_padding_repeated_twice =
+        padding.iter().rev().flat_map(|&(left, right)| [left, right]).collect();
+    // The convolution operation only supports symmetric zero padding.
+    let pad_input = config.padding_mode != PaddingMode::Zeros
+        || padding.iter().any(|(left, right)| left != right);
+    let padding =
+        if pad_input { vec![0; padding.len()] } else { padding.iter().map(|p| p.0).collect() };
+    Ok(Conv { ws, bs, config, padding, pad_input, reversed_padding_repeated_twice })
+}
+
+/// Creates a new convolution layer for any number of dimensions, see `f_conv`.
+pub fn conv<'a, ND: std::convert::AsRef<[i64]>, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    in_dim: i64,
+    out_dim: i64,
+    ksizes: ND,
+    config: ConvConfigND<ND>,
+) -> Conv<ND> {
+    f_conv(vs, in_dim, out_dim, ksizes, config).unwrap()
 }
 
 trait Create: std::convert::AsRef<[i64]> + std::marker::Sized {
     fn make_array(i: i64) -> Self;
 
-    fn conv<'a, T: Borrow<super::Path<'a>>>(
+    fn f_conv<'a, T: Borrow<super::Path<'a>>>(
         vs: T,
         in_dim: i64,
         out_dim: i64,
         ksize: i64,
         config: ConvConfig,
-    ) -> Conv<Self> {
+    ) -> Result<Conv<Self>, TchError> {
+        let padding = match config.padding {
+            Padding::Explicit(padding) => Padding::Explicit(Self::make_array(padding)),
+            Padding::Same => Padding::Same,
+            Padding::Valid => Padding::Valid,
+        };
         let config = ConvConfigND::<Self> {
             stride: Self::make_array(config.stride),
-            padding: Self::make_array(config.padding),
+            padding,
             dilation: Self::make_array(config.dilation),
             groups: config.groups,
             bias: config.bias,
@@ -153,7 +228,7 @@ trait Create: std::convert::AsRef<[i64]> + std::marker::Sized {
             bs_init: config.bs_init,
             padding_mode: config.padding_mode,
         };
-        conv(vs, in_dim, out_dim, Self::make_array(ksize), config)
+        f_conv(vs, in_dim, out_dim, Self::make_array(ksize), config)
     }
 }
 
@@ -175,68 +250,118 @@ impl Create for [i64; 3] {
     }
 }
 
+/// Creates a new one dimension convolution layer.
+pub fn f_conv1d<'a, T: Borrow<Path<'a>>>(
+    vs: T,
+    i: i64,
+    o: i64,
+    k: i64,
+    c: ConvConfig,
+) -> Result<Conv1D, TchError> {
+    <[i64; 1]>::f_conv(vs, i, o, k, c)
+}
+
 /// Creates a new one dimension convolution layer.
 pub fn conv1d<'a, T: Borrow<Path<'a>>>(vs: T, i: i64, o: i64, k: i64, c: ConvConfig) -> Conv1D {
-    <[i64; 1]>::conv(vs, i, o, k, c)
+    f_conv1d(vs, i, o, k, c).unwrap()
+}
+
+/// Creates a new two dimension convolution layer.
+pub fn f_conv2d<'a, T: Borrow<Path<'a>>>(
+    vs: T,
+    i: i64,
+    o: i64,
+    k: i64,
+    c: ConvConfig,
+) -> Result<Conv2D, TchError> {
+    <[i64; 2]>::f_conv(vs, i, o, k, c)
 }
 
 /// Creates a new two dimension convolution layer.
 pub fn conv2d<'a, T: Borrow<Path<'a>>>(vs: T, i: i64, o: i64, k: i64, c: ConvConfig) -> Conv2D {
-    <[i64; 2]>::conv(vs, i, o, k, c)
+    f_conv2d(vs, i, o, k, c).unwrap()
+}
+
+/// Creates a new three dimension convolution layer.
+pub fn f_conv3d<'a, T: Borrow<Path<'a>>>(
+    vs: T,
+    i: i64,
+    o: i64,
+    k: i64,
+    c: ConvConfig,
+) -> Result<Conv3D, TchError> {
+    <[i64; 3]>::f_conv(vs, i, o, k, c)
 }
 
 /// Creates a new three dimension convolution layer.
 pub fn conv3d<'a, T: Borrow<Path<'a>>>(vs: T, i: i64, o: i64, k: i64, c: ConvConfig) -> Conv3D {
-    <[i64; 3]>::conv(vs, i, o, k, c)
+    f_conv3d(vs, i, o, k, c).unwrap()
 }
 
 impl super::module::Module for Conv1D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        let (xs, padding) = match self.config.padding_mode {
-            PaddingMode::Zeros => (xs.shallow_clone(), self.config.padding),
-            p => (p.pad(xs, &self.reversed_padding_repeated_twice), [0]),
-        };
-        xs.conv1d(
+        let (xs, padding) = self.f_pad_input(xs).unwrap();
+        let ys = xs.conv1d(
             &self.ws,
             self.bs.as_ref(),
             self.config.stride,
             padding,
             self.config.dilation,
             self.config.groups,
-        )
+        );
+        super::analysis::record_layer("conv1d", &ys, &[Some(&self.ws), self.bs.as_ref()], || {
+            super::analysis::conv_flops(ys.numel(), &self.ws)
+        });
+        ys
     }
 }
 
 impl super::module::Module for Conv2D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        let (xs, padding) = match self.config.padding_mode {
-            PaddingMode::Zeros => (xs.shallow_clone(), self.config.padding),
-            p => (p.pad(xs, &self.reversed_padding_repeated_twice), [0, 0]),
-        };
-        xs.conv2d(
+        let (xs, padding) = self.f_pad_input(xs).unwrap();
+        let ys = xs.conv2d(
             &self.ws,
             self.bs.as_ref(),
             self.config.stride,
             padding,
             self.config.dilation,
             self.config.groups,
-        )
+        );
+        super::analysis::record_layer("conv2d", &ys, &[Some(&self.ws), self.bs.as_ref()], || {
+            super::analysis::conv_flops(ys.numel(), &self.ws)
+        });
+        ys
+    }
+}
+
+impl Conv2D {
+    // Applies the convolution with the layer config but the given weights,
+    // used by the layers masking or adapting the weights.
+    pub(crate) fn f_forward_with(
+        &self,
+        xs: &Tensor,
+        ws: &Tensor,
+        bs: Option<&Tensor>,
+    ) -> Result<Tensor, TchError> {
+        let (xs, padding) = self.f_pad_input(xs)?;
+        xs.f_conv2d(ws, bs, self.config.stride, padding, self.config.dilation, self.config.groups)
     }
 }
 
 impl super::module::Module for Conv3D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        let (xs, padding) = match self.config.padding_mode {
-            PaddingMode::Zeros => (xs.shallow_clone(), self.config.padding),
-            p => (p.pad(xs, &self.reversed_padding_repeated_twice), [0, 0, 0]),
-        };
-        xs.conv3d(
+        let (xs, padding) = self.f_pad_input(xs).unwrap();
+        let ys = xs.conv3d(
             &self.ws,
             self.bs.as_ref(),
             self.config.stride,
             padding,
             self.config.dilation,
             self.config.groups,
-        )
+        );
+        super::analysis::record_layer("conv3d", &ys, &[Some(&self.ws), self.bs.as_ref()], || {
+            super::analysis::conv_flops(ys.numel(), &self.ws)
+        });
+        ys
     }
 }
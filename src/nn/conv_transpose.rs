@@ -145,7 +145,7 @@ pub fn conv_transpose3d<'a, T: Borrow<Path<'a>>>(
 
 impl super::module::Module for ConvTranspose1D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::conv_transpose1d(
+        let ys = Tensor::conv_transpose1d(
             xs,
             &self.ws,
             self.bs.as_ref(),
@@ -154,13 +154,21 @@ impl super::module::Module for ConvTranspose1D {
             self.config.output_padding,
             self.config.groups,
             self.config.dilation,
-        )
+        );
+        // Each input element is multiplied by the weights for one input channel.
+        super::analysis::record_layer(
+            "conv_transpose1d",
+            &ys,
+            &[Some(&self.ws), self.bs.as_ref()],
+            || super::analysis::conv_flops(xs.numel(), &self.ws),
+        );
+        ys
     }
 }
 
 impl super::module::Module for ConvTranspose2D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::conv_transpose2d(
+        let ys = Tensor::conv_transpose2d(
             xs,
             &self.ws,
             self.bs.as_ref(),
@@ -169,13 +177,21 @@ impl super::module::Module for ConvTranspose2D {
             self.config.output_padding,
             self.config.groups,
             self.config.dilation,
-        )
+        );
+        // Each input element is multiplied by the weights for one input channel.
+        super::analysis::record_layer(
+            "conv_transpose2d",
+            &ys,
+            &[Some(&self.ws), self.bs.as_ref()],
+            || super::analysis::conv_flops(xs.numel(), &self.ws),
+        );
+        ys
     }
 }
 
 impl super::module::Module for ConvTranspose3D {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::conv_transpose3d(
+        let ys = Tensor::conv_transpose3d(
             xs,
             &self.ws,
             self.bs.as_ref(),
@@ -184,6 +200,14 @@ impl super::module::Module for ConvTranspose3D {
             self.config.output_padding,
             self.config.groups,
             self.config.dilation,
-        )
+        );
+        // Each input element is multiplied by the weights for one input channel.
+        super::analysis::record_layer(
+            "conv_transpose3d",
+            &ys,
+            &[Some(&self.ws), self.bs.as_ref()],
+            || super::analysis::conv_flops(xs.numel(), &self.ws),
+        );
+        ys
     }
 }
@@ -0,0 +1,204 @@
+//! Single-process data parallelism over multiple devices.
+use super::{ModuleT, Path, VarStore};
+use crate::cuda::{CudaEvent, CudaStream};
+use crate::{Device, SharedTensor, TchError, Tensor};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct Replica<M> {
+    vs: VarStore,
+    module: M,
+    // Side stream used to run the forward pass, only set for CUDA devices.
+    stream: Option<CudaStream>,
+}
+
+impl<M: ModuleT> Replica<M> {
+    fn forward_t(&self, xs: &Tensor, train: bool, primary: Device) -> Result<Tensor, TchError> {
+        let device = self.vs.device();
+        let stream = match &self.stream {
+            None => {
+                return self.module.forward_t(&xs.f_to_device(device)?, train).f_to_device(primary)
+            }
+            Some(stream) => stream,
+        };
+        // The side stream waits for the work queued on the current stream, and
+        // the current stream then waits for the forward pass to complete.
+        let current = CudaStream::current(device)?;
+        let mut ready = CudaEvent::new(false)?;
+        ready.record(&current)?;
+        stream.wait_event(&ready)?;
+        let ys = {
+            let _guard = stream.set_current()?;
+            self.module.forward_t(&xs.f_to_device(device)?, train).f_to_device(primary)?
+        };
+        let mut done = CudaEvent::new(false)?;
+        done.record(stream)?;
+        current.wait_event(&done)?;
+        Ok(ys)
+    }
+}
+
+/// Replicates a module over multiple devices.
+///
+/// The input batch is split along its first dimension, each chunk is processed
+/// by the replica on the corresponding device and the outputs are gathered on
+/// the primary device, i.e. the first device. Optimizers should be built on
+/// the primary var-store, see [`DataParallel::var_store`].
+///
+/// A training step looks as follows:
+/// - run `forward_t` and compute the loss,
+/// - call `backward` on the loss and then `reduce_gradients` to sum the
+///   gradients of all the replicas into the primary replica,
+/// - run the optimizer step,
+/// - call `sync_replicas` to copy the updated weights to the other replicas.
+#[derive(Debug)]
+pub struct DataParallel<M> {
+    replicas: Vec<Replica<M>>,
+}
+
+impl<M: ModuleT> DataParallel<M> {
+    /// Creates a new data-parallel module.
+    ///
+    /// The module is built once per device using `module_builder`, the weights
+    /// of all the replicas are then set to the weights of the primary replica.
+    pub fn new<F>(module_builder: F, devices: &[Device]) -> Result<Self, TchError>
+    where
+        F: Fn(&Path) -> M,
+    {
+        if devices.is_empty() {
+            return Err(TchError::Torch("DataParallel requires at least one device".into()));
+        }
+        let mut replicas: Vec<Replica<M>> = Vec::with_capacity(devices.len());
+        for &device in devices.iter() {
+            let vs = VarStore::new(device);
+            let module = module_builder(&vs.root());
+            // There is no point in using side streams with a single replica.
+            let stream = if device.is_cuda() && devices.len() > 1 {
+                Some(CudaStream::new(device)?)
+            } else {
+                None
+            };
+            replicas.push(Replica { vs, module, stream })
+        }
+        let mut data_parallel = DataParallel { replicas };
+        data_parallel.sync_replicas()?;
+        Ok(data_parallel)
+    }
+
+    /// The var-store of the primary replica.
+    pub fn var_store(&self) -> &VarStore {
+        &self.replicas[0].vs
+    }
+
+    /// The module of the primary replica.
+    pub fn module(&self) -> &M {
+        &self.replicas[0].module
+    }
+
+    /// The device holding the primary replica, outputs are gathered on this device.
+    pub fn primary_device(&self) -> Device {
+        self.replicas[0].vs.device()
+    }
+
+    /// The devices used by the replicas, starting with the primary device.
+    pub fn devices(&self) -> Vec<Device> {
+        self.replicas.iter().map(|r| r.vs.device()).collect()
+    }
+
+    /// Runs the forward pass, returning the outputs on the primary device.
+    ///
+    /// When the batch size is smaller than the number of devices, only the
+    /// first replicas are used.
+    pub fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let primary = self.primary_device();
+        if self.replicas.len() == 1 {
+            return self.replicas[0].forward_t(xs, train, primary);
+        }
+        let chunks = xs.f_chunk(self.replicas.len() as i64, 0)?;
+        let ys = chunks
+            .iter()
+            .zip(self.replicas.iter())
+            .map(|(xs, replica)| replica.forward_t(xs, train, primary))
+            .collect::<Result<Vec<_>, _>>()?;
+        Tensor::f_cat(&ys, 0)
+    }
+
+    /// Sums the gradients of the replicas into the gradients of the primary replica.
+    ///
+    /// This should be called after the backward pass and before the optimizer step.
+    /// The gradients of the non-primary replicas are reset to zero.
+    pub fn reduce_gradients(&self) -> Result<(), TchError> {
+        let (primary, replicas) = self.replicas.split_first().unwrap();
+        let primary_device = primary.vs.device();
+        let primary_variables = primary.vs.variables();
+        for replica in replicas.iter() {
+            for (name, mut var) in replica.vs.variables() {
+                let grad = var.grad();
+                if !grad.defined() {
+                    continue;
+                }
+                let mut primary_var = match primary_variables.get(&name) {
+                    Some(primary_var) => primary_var.shallow_clone(),
+                    None => {
+                        return Err(TchError::TensorNameNotFound(name, "primary var-store".into()))
+                    }
+                };
+                let mut primary_grad = primary_var.grad();
+                crate::no_grad(|| {
+                    let grad = grad.f_to_device(primary_device)?;
+                    if primary_grad.defined() {
+                        let _ = primary_grad.f_add_(&grad)?;
+                    } else {
+                        // Parameters that are not used by the primary replica get their
+                        // gradient from the first replica that uses them, the gradient
+                        // is copied as the replica gradient gets reset below.
+                        primary_var.f_set_grad(&grad.f_copy()?)?;
+                    }
+                    Ok::<_, TchError>(())
+                })?;
+                var.zero_grad()
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the weights of the primary replica to the other replicas.
+    ///
+    /// This should be called after each optimizer step.
+    pub fn sync_replicas(&mut self) -> Result<(), TchError> {
+        let (primary, replicas) = self.replicas.split_first().unwrap();
+        // The primary weights are shared read-only with one thread per replica,
+        // the copies to the different devices then run concurrently.
+        let weights = primary.vs.shared_variables();
+        let weights = &weights;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = replicas
+                .iter()
+                .map(|replica| {
+                    let vs = &replica.vs;
+                    scope.spawn(move || copy_weights(vs, weights))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|err| std::panic::resume_unwind(err)))
+                .collect()
+        })
+    }
+}
+
+fn copy_weights(vs: &VarStore, weights: &HashMap<String, SharedTensor>) -> Result<(), TchError> {
+    for (name, mut var) in vs.variables() {
+        match weights.get(&name) {
+            Some(weight) => weight.f_copy_to(&mut var)?,
+            None => return Err(TchError::TensorNameNotFound(name, "primary var-store".into())),
+        }
+    }
+    Ok(())
+}
+
+impl<M: ModuleT> ModuleT for DataParallel<M> {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+}
@@ -0,0 +1,66 @@
+//! Dropout layers.
+use crate::{TchError, Tensor};
+
+/// The elements that are zeroed by a dropout layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropoutMode {
+    /// Each element is zeroed independently.
+    Element,
+    /// Whole channels of `[N, C, H, W]` inputs are zeroed, see
+    /// `Tensor::dropout2d`.
+    Channel2d,
+    /// Whole channels of `[N, C, D, H, W]` inputs are zeroed, see
+    /// `Tensor::dropout3d`.
+    Channel3d,
+    /// Alpha dropout which keeps the mean and variance of the inputs, this is
+    /// meant to be used with the SELU activation.
+    Alpha,
+}
+
+/// A dropout layer, this is only active in train mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Dropout {
+    pub p: f64,
+    pub mode: DropoutMode,
+}
+
+/// Creates a dropout layer zeroing each element with probability `p`.
+pub fn dropout(p: f64) -> Dropout {
+    Dropout { p, mode: DropoutMode::Element }
+}
+
+/// Creates a dropout layer zeroing whole channels of 2D inputs.
+pub fn dropout2d(p: f64) -> Dropout {
+    Dropout { p, mode: DropoutMode::Channel2d }
+}
+
+/// Creates a dropout layer zeroing whole channels of 3D inputs.
+pub fn dropout3d(p: f64) -> Dropout {
+    Dropout { p, mode: DropoutMode::Channel3d }
+}
+
+/// Creates an alpha dropout layer.
+pub fn alpha_dropout(p: f64) -> Dropout {
+    Dropout { p, mode: DropoutMode::Alpha }
+}
+
+impl super::module::ModuleT for Dropout {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        match self.mode {
+            DropoutMode::Element => {
+                crate::tensor::check_dropout_p("dropout", self.p)?;
+                xs.f_dropout(self.p, train)
+            }
+            DropoutMode::Channel2d => xs.f_dropout2d(self.p, train),
+            DropoutMode::Channel3d => xs.f_dropout3d(self.p, train),
+            DropoutMode::Alpha => {
+                crate::tensor::check_dropout_p("alpha_dropout", self.p)?;
+                xs.f_alpha_dropout(self.p, train)
+            }
+        }
+    }
+}
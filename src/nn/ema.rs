@@ -0,0 +1,114 @@
+//! Exponential moving average of the weights of a var-store.
+use super::VarStore;
+use crate::{TchError, Tensor};
+use std::collections::{HashMap, HashSet};
+
+/// An exponential moving average of the trainable variables of a var-store,
+/// `ema = decay * ema + (1 - decay) * var` after each update.
+///
+/// The trainable variables added to the var-store after the creation of the
+/// average are added lazily: their average starts from their value at the
+/// first `update` following their creation.
+#[derive(Debug)]
+pub struct VarStoreEma {
+    averages: HashMap<String, Tensor>,
+    decay: f64,
+}
+
+impl VarStoreEma {
+    /// Creates an average starting from the current trainable variables of
+    /// `vs`, `decay` is usually close to 1, e.g. 0.999.
+    pub fn new(vs: &VarStore, decay: f64) -> VarStoreEma {
+        let _no_grad = crate::no_grad_guard();
+        let averages = vs
+            .trainable_variables_with_names()
+            .into_iter()
+            .map(|(name, t)| (name, t.detach().copy()))
+            .collect();
+        VarStoreEma { averages, decay }
+    }
+
+    pub fn decay(&self) -> f64 {
+        self.decay
+    }
+
+    /// The averaged variables along with their names.
+    pub fn averages(&self) -> &HashMap<String, Tensor> {
+        &self.averages
+    }
+
+    // An averaged variable that is not a trainable variable of the var-store.
+    fn missing_variable(&self, variables: &[(String, Tensor)]) -> Option<&str> {
+        let names: HashSet<_> = variables.iter().map(|(name, _)| name.as_str()).collect();
+        self.averages.keys().map(|name| name.as_str()).find(|name| !names.contains(name))
+    }
+
+    /// Moves the averages towards the current trainable variables of `vs`.
+    pub fn f_update(&mut self, vs: &VarStore) -> Result<(), TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let variables = vs.trainable_variables_with_names();
+        if let Some(name) = self.missing_variable(&variables) {
+            return Err(TchError::TensorNameNotFound(
+                name.to_string(),
+                "the var-store".to_string(),
+            ));
+        }
+        for (name, var) in variables {
+            match self.averages.get_mut(&name) {
+                Some(avg) => {
+                    let _ = avg.f_lerp_(&var, 1. - self.decay)?;
+                }
+                None => {
+                    let _ = self.averages.insert(name, var.f_detach()?.f_copy()?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the averages towards the current trainable variables of `vs`.
+    pub fn update(&mut self, vs: &VarStore) {
+        self.f_update(vs).unwrap()
+    }
+
+    /// Copies the averaged weights into `vs`, e.g. before saving it.
+    pub fn f_copy_into(&self, vs: &mut VarStore) -> Result<(), TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let variables = vs.variables_.lock().unwrap();
+        for (name, avg) in self.averages.iter() {
+            let var = variables.named_variables.get(name).ok_or_else(|| {
+                TchError::TensorNameNotFound(name.to_string(), "the var-store".to_string())
+            })?;
+            var.shallow_clone().f_copy_(avg)?
+        }
+        Ok(())
+    }
+
+    /// Copies the averaged weights into `vs`, see `f_copy_into`.
+    pub fn copy_into(&self, vs: &mut VarStore) {
+        self.f_copy_into(vs).unwrap()
+    }
+
+    /// Exchanges the averaged weights with the weights of `vs`.
+    ///
+    /// This is used to evaluate the model with the averaged weights, a second
+    /// call restores the training weights and the averages.
+    pub fn f_swap(&mut self, vs: &mut VarStore) -> Result<(), TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let variables = vs.variables_.lock().unwrap();
+        for (name, avg) in self.averages.iter_mut() {
+            let var = variables.named_variables.get(name).ok_or_else(|| {
+                TchError::TensorNameNotFound(name.to_string(), "the var-store".to_string())
+            })?;
+            let current = var.f_detach()?.f_copy()?;
+            var.shallow_clone().f_copy_(avg)?;
+            avg.f_copy_(&current)?
+        }
+        Ok(())
+    }
+
+    /// Exchanges the averaged weights with the weights of `vs`, see `f_swap`.
+    pub fn swap(&mut self, vs: &mut VarStore) {
+        self.f_swap(vs).unwrap()
+    }
+}
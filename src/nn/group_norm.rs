@@ -54,13 +54,24 @@ pub fn group_norm<'a, T: Borrow<super::Path<'a>>>(
 
 impl super::module::Module for GroupNorm {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::group_norm(
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, crate::TchError> {
+        let ys = Tensor::f_group_norm(
             xs,
             self.num_groups,
             self.ws.as_ref(),
             self.bs.as_ref(),
             self.config.eps,
             self.config.cudnn_enabled,
-        )
+        )?;
+        super::analysis::record_layer(
+            "group_norm",
+            &ys,
+            &[self.ws.as_ref(), self.bs.as_ref()],
+            || ys.numel() as u64,
+        );
+        Ok(ys)
     }
 }
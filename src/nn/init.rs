@@ -45,9 +45,15 @@ pub enum NormalOrUniform {
 
 /// The non-linear function that follows this layer. ReLU is the
 /// recommended value.
+///
+/// `LeakyReLU(a)` corresponds to the `a` argument of the PyTorch Kaiming
+/// initializations, e.g. `LeakyReLU(5f64.sqrt())` gives the default
+/// initialization of the PyTorch linear layers.
 #[derive(Debug, Copy, Clone)]
 pub enum NonLinearity {
     ReLU,
+    /// A leaky ReLU with the given negative slope.
+    LeakyReLU(f64),
     Linear,
     Sigmoid,
     Tanh,
@@ -59,6 +65,7 @@ impl NonLinearity {
     pub fn gain(&self) -> f64 {
         match *self {
             NonLinearity::ReLU => 2f64.sqrt(),
+            NonLinearity::LeakyReLU(slope) => (2. / (1. + slope * slope)).sqrt(),
             NonLinearity::Tanh => 5. / 3.,
             NonLinearity::Linear | NonLinearity::Sigmoid => 1.,
             NonLinearity::SELU => 0.75,
@@ -86,6 +93,16 @@ pub enum Init {
 
     /// Orthogonal initialization
     Orthogonal { gain: f64 },
+
+    /// Xavier initialization, also known as Glorot initialization.
+    /// See "Understanding the difficulty of training deep feedforward neural
+    /// networks" Glorot, X. & Bengio, Y. (2010). The standard deviation is
+    /// `gain * sqrt(2 / (fan_in + fan_out))`.
+    Xavier { dist: NormalOrUniform, gain: f64 },
+
+    /// Random normal with some mean and standard deviation, truncated to the
+    /// `[lo, up]` interval.
+    TruncatedNormal { mean: f64, stdev: f64, lo: f64, up: f64 },
 }
 
 pub const DEFAULT_KAIMING_UNIFORM: Init = Init::Kaiming {
@@ -100,6 +117,56 @@ pub const DEFAULT_KAIMING_NORMAL: Init = Init::Kaiming {
     non_linearity: NonLinearity::ReLU,
 };
 
+// The standard deviation of the Kaiming and Xavier initializations.
+fn kaiming_std(dims: &[i64], fan: FanInOut, non_linearity: NonLinearity) -> f64 {
+    non_linearity.gain() / (fan.for_weight_dims(dims) as f64).sqrt()
+}
+
+fn xavier_std(dims: &[i64], gain: f64) -> f64 {
+    let fan_in = FanInOut::FanIn.for_weight_dims(dims);
+    let fan_out = FanInOut::FanOut.for_weight_dims(dims);
+    gain * (2. / (fan_in + fan_out) as f64).sqrt()
+}
+
+// Fills a tensor with a zero mean normal or uniform distribution.
+fn f_fill_dist_(tensor: &mut Tensor, dist: NormalOrUniform, std: f64) -> Result<(), TchError> {
+    match dist {
+        NormalOrUniform::Uniform => {
+            let bound = 3f64.sqrt() * std;
+            let _ = tensor.f_uniform_(-bound, bound)?;
+        }
+        NormalOrUniform::Normal => {
+            let _ = tensor.f_normal_(0., std)?;
+        }
+    }
+    Ok(())
+}
+
+// Fills a tensor with a truncated normal distribution by inverting the
+// cumulative distribution function of uniform samples.
+fn f_trunc_normal_(
+    tensor: &mut Tensor,
+    mean: f64,
+    stdev: f64,
+    lo: f64,
+    up: f64,
+) -> Result<(), TchError> {
+    if lo >= up || stdev <= 0. {
+        return Err(TchError::Torch(format!(
+            "invalid truncated normal, mean {mean} stdev {stdev} on [{lo}, {up}]"
+        )));
+    }
+    let sqrt2 = std::f64::consts::SQRT_2;
+    let bounds = Tensor::f_from_slice(&[(lo - mean) / stdev, (up - mean) / stdev])?;
+    let bounds = Vec::<f64>::try_from(bounds.f_div_scalar(sqrt2)?.f_erf()?)?;
+    let _ = tensor.f_uniform_(bounds[0], bounds[1])?;
+    let _ = tensor.f_erfinv_()?;
+    let _ = tensor.f_mul_scalar_(stdev * sqrt2)?;
+    let _ = tensor.f_add_scalar_(mean)?;
+    let _ = tensor.f_clamp_(lo, up)?;
+    Ok(())
+}
+
 /// Creates a new float tensor with the specified shape, device, and initialization.
 pub fn f_init(i: Init, dims: &[i64], device: Device) -> Result<Tensor, TchError> {
     match i {
@@ -124,9 +191,7 @@ pub fn f_init(i: Init, dims: &[i64], device: Device) -> Result<Tensor, TchError>
             }
         }
         Init::Kaiming { dist, fan, non_linearity } => {
-            let fan = fan.for_weight_dims(dims);
-            let gain = non_linearity.gain();
-            let std = gain / (fan as f64).sqrt();
+            let std = kaiming_std(dims, fan, non_linearity);
             match dist {
                 NormalOrUniform::Uniform => {
                     let bound = 3f64.sqrt() * std;
@@ -138,6 +203,16 @@ pub fn f_init(i: Init, dims: &[i64], device: Device) -> Result<Tensor, TchError>
                 }
             }
         }
+        Init::Xavier { dist, gain } => {
+            let mut tensor = Tensor::f_empty(dims, (Kind::Float, device))?;
+            f_fill_dist_(&mut tensor, dist, xavier_std(dims, gain))?;
+            Ok(tensor)
+        }
+        Init::TruncatedNormal { mean, stdev, lo, up } => {
+            let mut tensor = Tensor::f_empty(dims, (Kind::Float, device))?;
+            f_trunc_normal_(&mut tensor, mean, stdev, lo, up)?;
+            Ok(tensor)
+        }
         Init::Orthogonal { gain } => {
             if dims.len() < 2 {
                 return Err(TchError::Shape(
@@ -180,9 +255,7 @@ impl Init {
                 let _ = tensor.uniform_(lo, up);
             }
             Init::Kaiming { dist, fan, non_linearity } => {
-                let fan = fan.for_weight_dims(&tensor.size());
-                let gain = non_linearity.gain();
-                let std = gain / (fan as f64).sqrt();
+                let std = kaiming_std(&tensor.size(), fan, non_linearity);
                 match dist {
                     NormalOrUniform::Uniform => {
                         let bound = 3f64.sqrt() * std;
@@ -200,10 +273,52 @@ impl Init {
                 let q = f_init(Init::Orthogonal { gain }, &tensor.size(), tensor.device()).unwrap();
                 crate::no_grad(|| tensor.view_as(&q).copy_(&q));
             }
+            Init::Xavier { dist, gain } => {
+                let std = xavier_std(&tensor.size(), gain);
+                f_fill_dist_(tensor, dist, std).unwrap()
+            }
+            Init::TruncatedNormal { mean, stdev, lo, up } => {
+                f_trunc_normal_(tensor, mean, stdev, lo, up).unwrap()
+            }
         }
     }
 }
 
+/// Fills `tensor` in place with the Kaiming uniform initialization, the
+/// standard deviation is `gain / sqrt(fan)`.
+pub fn kaiming_uniform_(tensor: &mut Tensor, fan: FanInOut, non_linearity: NonLinearity) {
+    let init = Init::Kaiming { dist: NormalOrUniform::Uniform, fan, non_linearity };
+    crate::no_grad(|| init.set(tensor))
+}
+
+/// Fills `tensor` in place with the Kaiming normal initialization, the
+/// standard deviation is `gain / sqrt(fan)`.
+pub fn kaiming_normal_(tensor: &mut Tensor, fan: FanInOut, non_linearity: NonLinearity) {
+    let init = Init::Kaiming { dist: NormalOrUniform::Normal, fan, non_linearity };
+    crate::no_grad(|| init.set(tensor))
+}
+
+/// Fills `tensor` in place with the Xavier uniform initialization.
+pub fn xavier_uniform_(tensor: &mut Tensor, gain: f64) {
+    crate::no_grad(|| Init::Xavier { dist: NormalOrUniform::Uniform, gain }.set(tensor))
+}
+
+/// Fills `tensor` in place with the Xavier normal initialization.
+pub fn xavier_normal_(tensor: &mut Tensor, gain: f64) {
+    crate::no_grad(|| Init::Xavier { dist: NormalOrUniform::Normal, gain }.set(tensor))
+}
+
+/// Fills `tensor` in place with a scaled orthogonal matrix, the trailing
+/// dimensions are flattened.
+pub fn orthogonal_(tensor: &mut Tensor, gain: f64) {
+    crate::no_grad(|| Init::Orthogonal { gain }.set(tensor))
+}
+
+/// Fills `tensor` in place with a normal distribution truncated to `[a, b]`.
+pub fn trunc_normal_(tensor: &mut Tensor, mean: f64, std: f64, a: f64, b: f64) {
+    crate::no_grad(|| Init::TruncatedNormal { mean, stdev: std, lo: a, up: b }.set(tensor))
+}
+
 impl Tensor {
     /// Re-initializes the tensor using the specified initialization.
     pub fn init(&mut self, i: Init) {
@@ -0,0 +1,131 @@
+//! An instance-normalization layer.
+//! Instance Normalization <https://arxiv.org/abs/1607.08022>
+use crate::{TchError, Tensor};
+use std::borrow::Borrow;
+
+/// Instance-normalization config.
+///
+/// As with PyTorch the layer is not affine by default. Running statistics are
+/// not tracked: the statistics of each input instance are used both in
+/// training and evaluation.
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceNormConfig {
+    pub cudnn_enabled: bool,
+    pub eps: f64,
+    pub affine: bool,
+    pub ws_init: super::Init,
+    pub bs_init: super::Init,
+}
+
+impl Default for InstanceNormConfig {
+    fn default() -> Self {
+        InstanceNormConfig {
+            cudnn_enabled: true,
+            eps: 1e-5,
+            affine: false,
+            ws_init: super::Init::Const(1.),
+            bs_init: super::Init::Const(0.),
+        }
+    }
+}
+
+/// An instance-normalization layer, each channel of each sample is normalized
+/// over its spatial dimensions.
+#[derive(Debug)]
+pub struct InstanceNorm {
+    config: InstanceNormConfig,
+    pub ws: Option<Tensor>,
+    pub bs: Option<Tensor>,
+    pub nd: usize,
+}
+
+fn instance_norm<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    nd: usize,
+    num_features: i64,
+    config: InstanceNormConfig,
+) -> InstanceNorm {
+    let vs = vs.borrow();
+    let (ws, bs) = if config.affine {
+        let ws = vs.var("weight", &[num_features], config.ws_init);
+        let bs = vs.var("bias", &[num_features], config.bs_init);
+        (Some(ws), Some(bs))
+    } else {
+        (None, None)
+    };
+    InstanceNorm { config, ws, bs, nd }
+}
+
+/// Applies Instance Normalization over a three dimension input.
+///
+/// The input shape is assumed to be (N, C, L), or (C, L) for unbatched inputs.
+pub fn instance_norm1d<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    num_features: i64,
+    config: InstanceNormConfig,
+) -> InstanceNorm {
+    instance_norm(vs, 1, num_features, config)
+}
+
+/// Applies Instance Normalization over a four dimension input.
+///
+/// The input shape is assumed to be (N, C, H, W), or (C, H, W) for unbatched
+/// inputs.
+pub fn instance_norm2d<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    num_features: i64,
+    config: InstanceNormConfig,
+) -> InstanceNorm {
+    instance_norm(vs, 2, num_features, config)
+}
+
+/// Applies Instance Normalization over a five dimension input.
+///
+/// The input shape is assumed to be (N, C, D, H, W), or (C, D, H, W) for
+/// unbatched inputs.
+pub fn instance_norm3d<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    num_features: i64,
+    config: InstanceNormConfig,
+) -> InstanceNorm {
+    instance_norm(vs, 3, num_features, config)
+}
+
+impl super::module::Module for InstanceNorm {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let dim = xs.dim();
+        let unbatched = dim == self.nd + 1;
+        if !unbatched && dim != self.nd + 2 {
+            return Err(TchError::Shape(format!(
+                "as nd={}, expected an input tensor with {} or {} dims, got {dim} ({:?})",
+                self.nd,
+                self.nd + 1,
+                self.nd + 2,
+                xs.size()
+            )));
+        }
+        let input = if unbatched { xs.f_unsqueeze(0)? } else { xs.shallow_clone() };
+        let ys = input.f_instance_norm(
+            self.ws.as_ref(),
+            self.bs.as_ref(),
+            None,
+            None,
+            true,
+            0.,
+            self.config.eps,
+            self.config.cudnn_enabled,
+        )?;
+        let ys = if unbatched { ys.f_squeeze_dim(0)? } else { ys };
+        super::analysis::record_layer(
+            "instance_norm",
+            &ys,
+            &[self.ws.as_ref(), self.bs.as_ref()],
+            || ys.numel() as u64,
+        );
+        Ok(ys)
+    }
+}
@@ -0,0 +1,211 @@
+//! A key/value cache for autoregressive transformer inference.
+use crate::{Device, Kind, TchError, Tensor};
+
+/// Configuration for a key/value cache.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheConfig {
+    /// The number of positions that can be stored per layer.
+    pub max_seq_len: i64,
+    pub num_heads: i64,
+    pub head_dim: i64,
+    pub batch: i64,
+    pub dtype: Kind,
+    pub device: Device,
+    /// When set, appending past `max_seq_len` positions overwrites the oldest
+    /// positions so that only the last `max_seq_len` positions are kept.
+    /// Otherwise appending past `max_seq_len` positions returns an error.
+    pub sliding_window: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_seq_len: 2048,
+            num_heads: 1,
+            head_dim: 64,
+            batch: 1,
+            dtype: Kind::Float,
+            device: Device::Cpu,
+            sliding_window: false,
+        }
+    }
+}
+
+// The cache of a single layer, the valid positions are `start..end` and
+// position `p` is stored at index `p % max_seq_len`.
+#[derive(Debug)]
+struct LayerCache {
+    k: Tensor,
+    v: Tensor,
+    start: i64,
+    end: i64,
+}
+
+/// Preallocated key and value tensors for each layer of a transformer.
+///
+/// The keys and values use the `[batch, num_heads, seq_len, head_dim]`
+/// layout, i.e. the layout expected by `Tensor::scaled_dot_product_attention`.
+#[derive(Debug)]
+pub struct KvCache {
+    layers: Vec<LayerCache>,
+    config: CacheConfig,
+}
+
+impl KvCache {
+    pub fn f_new(num_layers: usize, config: CacheConfig) -> Result<KvCache, TchError> {
+        let shape = [config.batch, config.num_heads, config.max_seq_len, config.head_dim];
+        let options = (config.dtype, config.device);
+        let layers = (0..num_layers)
+            .map(|_| {
+                let k = Tensor::f_zeros(shape, options)?;
+                let v = Tensor::f_zeros(shape, options)?;
+                Ok(LayerCache { k, v, start: 0, end: 0 })
+            })
+            .collect::<Result<Vec<_>, TchError>>()?;
+        Ok(KvCache { layers, config })
+    }
+
+    pub fn new(num_layers: usize, config: CacheConfig) -> KvCache {
+        Self::f_new(num_layers, config).unwrap()
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// The number of positions appended to the first layer, this is the
+    /// position of the next token. In sliding window mode this can exceed the
+    /// number of cached positions.
+    pub fn len(&self) -> i64 {
+        self.layers.first().map_or(0, |l| l.end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn layer(&self, layer: usize) -> Result<&LayerCache, TchError> {
+        let num_layers = self.layers.len();
+        self.layers.get(layer).ok_or_else(|| TchError::IndexOutOfRange {
+            dim: 0,
+            index: layer as i64,
+            size: num_layers as i64,
+            shape: vec![num_layers as i64],
+        })
+    }
+
+    // Returns the keys and values of the valid positions in order. This is
+    // a view except when a sliding window has wrapped around.
+    fn views(&self, layer: &LayerCache) -> Result<(Tensor, Tensor), TchError> {
+        let max_seq_len = self.config.max_seq_len;
+        let len = layer.end - layer.start;
+        let first = layer.start % max_seq_len;
+        let view = |t: &Tensor| {
+            if first + len <= max_seq_len {
+                t.f_narrow(2, first, len)
+            } else {
+                let head = t.f_narrow(2, first, max_seq_len - first)?;
+                let tail = t.f_narrow(2, 0, first + len - max_seq_len)?;
+                Tensor::f_cat(&[head, tail], 2)
+            }
+        };
+        Ok((view(&layer.k)?, view(&layer.v)?))
+    }
+
+    /// Returns the cached keys and values of a layer.
+    pub fn f_get(&self, layer: usize) -> Result<(Tensor, Tensor), TchError> {
+        self.views(self.layer(layer)?)
+    }
+
+    /// Appends keys and values of shape `[batch, num_heads, seq_len, head_dim]`
+    /// to the cache of a layer, and returns the keys and values for all the
+    /// cached positions of this layer, including the appended ones.
+    ///
+    /// The returned tensors are views on the cache so they are only valid
+    /// until the next modification of the cache. When a sliding window has
+    /// wrapped around, the cached positions are copied so that they are
+    /// returned in order.
+    pub fn f_append(
+        &mut self,
+        layer: usize,
+        k: &Tensor,
+        v: &Tensor,
+    ) -> Result<(Tensor, Tensor), TchError> {
+        let config = self.config;
+        let (batch, num_heads, seq_len, head_dim) = k.size4()?;
+        if k.size() != v.size()
+            || (batch, num_heads, head_dim) != (config.batch, config.num_heads, config.head_dim)
+        {
+            return Err(TchError::Shape(format!(
+                "kv-cache append: unexpected shapes {:?} {:?} for batch {} heads {} head_dim {}",
+                k.size(),
+                v.size(),
+                config.batch,
+                config.num_heads,
+                config.head_dim
+            )));
+        }
+        let max_seq_len = config.max_seq_len;
+        let cache = self.layer(layer)?;
+        let end = cache.end + seq_len;
+        if !config.sliding_window && end > max_seq_len {
+            return Err(TchError::Shape(format!(
+                "kv-cache append: {end} positions exceed the maximum sequence length {max_seq_len}"
+            )));
+        }
+        let _no_grad = crate::no_grad_guard();
+        let cache = &mut self.layers[layer];
+        // Only the last `max_seq_len` positions are written.
+        let mut pos = cache.end.max(end - max_seq_len);
+        while pos < end {
+            let index = pos % max_seq_len;
+            let len = (max_seq_len - index).min(end - pos);
+            let offset = pos - cache.end;
+            cache.k.f_narrow(2, index, len)?.f_copy_(&k.f_narrow(2, offset, len)?)?;
+            cache.v.f_narrow(2, index, len)?.f_copy_(&v.f_narrow(2, offset, len)?)?;
+            pos += len;
+        }
+        cache.end = end;
+        cache.start = cache.start.max(end - max_seq_len);
+        self.views(&self.layers[layer])
+    }
+
+    /// Appends keys and values to the cache of a layer, see `f_append`.
+    pub fn append(&mut self, layer: usize, k: &Tensor, v: &Tensor) -> (Tensor, Tensor) {
+        self.f_append(layer, k, v).unwrap()
+    }
+
+    /// Reorders the cache along the batch dimension, the new batch element
+    /// `i` is the previous batch element `beam_indices[i]`. This is used in
+    /// beam search to follow the selected beams.
+    pub fn f_reorder(&self, beam_indices: &Tensor) -> Result<(), TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let beam_indices = beam_indices.f_to_device(self.config.device)?;
+        for layer in self.layers.iter() {
+            for t in [&layer.k, &layer.v] {
+                let reordered = t.f_index_select(0, &beam_indices)?;
+                t.shallow_clone().f_copy_(&reordered)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Reorders the cache along the batch dimension, see `f_reorder`.
+    pub fn reorder(&self, beam_indices: &Tensor) {
+        self.f_reorder(beam_indices).unwrap()
+    }
+
+    /// Discards the positions starting from `len` in all the layers, e.g. to
+    /// roll back rejected tokens.
+    pub fn crop(&mut self, len: i64) {
+        let len = len.max(0);
+        for layer in self.layers.iter_mut() {
+            layer.end = layer.end.min(len);
+            layer.start = layer.start.min(layer.end);
+        }
+    }
+}
@@ -53,13 +53,24 @@ pub fn layer_norm<'a, T: Borrow<super::Path<'a>>>(
 
 impl super::module::Module for LayerNorm {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::layer_norm(
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, crate::TchError> {
+        let ys = Tensor::f_layer_norm(
             xs,
             self.normalized_shape.as_slice(),
             self.ws.as_ref(),
             self.bs.as_ref(),
             self.config.eps,
             self.config.cudnn_enabled,
-        )
+        )?;
+        super::analysis::record_layer(
+            "layer_norm",
+            &ys,
+            &[self.ws.as_ref(), self.bs.as_ref()],
+            || ys.numel() as u64,
+        );
+        Ok(ys)
     }
 }
@@ -46,7 +46,15 @@ pub fn linear<'a, T: Borrow<super::Path<'a>>>(
 
 impl super::module::Module for Linear {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        xs.linear(&self.ws, self.bs.as_ref())
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, crate::TchError> {
+        let ys = xs.f_linear(&self.ws, self.bs.as_ref())?;
+        super::analysis::record_layer("linear", &ys, &[Some(&self.ws), self.bs.as_ref()], || {
+            super::analysis::conv_flops(ys.numel(), &self.ws)
+        });
+        Ok(ys)
     }
 }
 
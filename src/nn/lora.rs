@@ -0,0 +1,399 @@
+//! Low-rank adapters for parameter-efficient fine-tuning.
+//!
+//! LoRA freezes the pretrained weight `W` of a layer and learns a low-rank
+//! update `(alpha / r) * B A` with `A` of rank `r`, see "LoRA: Low-Rank
+//! Adaptation of Large Language Models", Hu et al. 2021. `B` is initialized
+//! with zeros so that the adapted layer starts from the pretrained one.
+//!
+//! The adapter weights are stored in the var-store under the `lora_a` and
+//! `lora_b` sub-paths of the layer, e.g. `encoder.q.lora_a.weight`, so they
+//! can be saved and loaded on their own with `save_adapters` and
+//! `load_adapters`.
+use super::{Conv2D, Init, Linear, LinearConfig, ModuleT, PaddingMode, Path, VarStore};
+use crate::{TchError, Tensor};
+use std::borrow::Borrow;
+
+/// Configuration for the low-rank adapters.
+#[derive(Debug, Clone, Copy)]
+pub struct LoraConfig {
+    /// The rank of the update.
+    pub r: i64,
+    /// The update is scaled by `alpha / r`.
+    pub alpha: f64,
+    /// The dropout probability applied to the input of the adapter in train
+    /// mode.
+    pub dropout: f64,
+}
+
+impl Default for LoraConfig {
+    fn default() -> Self {
+        LoraConfig { r: 8, alpha: 16., dropout: 0. }
+    }
+}
+
+#[derive(Debug)]
+struct Adapter {
+    a: Tensor,
+    b: Tensor,
+    scale: f64,
+    dropout: f64,
+    merged: bool,
+}
+
+impl Adapter {
+    // Freezes the base weights and creates the adapter weights under `path`,
+    // the path of the adapted layer.
+    fn new(
+        path: &Path,
+        ws: &Tensor,
+        bs: Option<&Tensor>,
+        a_size: &[i64],
+        b_size: &[i64],
+        config: LoraConfig,
+    ) -> Result<Adapter, TchError> {
+        if config.r <= 0 {
+            return Err(TchError::Shape(format!(
+                "lora: the rank must be positive, got {}",
+                config.r
+            )));
+        }
+        let vs = path.var_store();
+        vs.freeze_variable(ws);
+        if let Some(bs) = bs {
+            vs.freeze_variable(bs)
+        }
+        let a = path.sub("lora_a").var("weight", a_size, super::init::DEFAULT_KAIMING_UNIFORM);
+        let b = path.sub("lora_b").var("weight", b_size, Init::Const(0.));
+        let scale = config.alpha / config.r as f64;
+        Ok(Adapter { a, b, scale, dropout: config.dropout, merged: false })
+    }
+
+    // The update `scale * B A` reshaped as the base weight.
+    fn delta(&self, ws: &Tensor) -> Result<Tensor, TchError> {
+        let r = self.a.size()[0];
+        let b = self.b.f_view([-1, r])?;
+        let a = self.a.f_view([r, -1])?;
+        b.f_mm(&a)?.f_mul_scalar(self.scale)?.f_view(ws.size().as_slice())?.f_to_kind(ws.kind())
+    }
+
+    fn merge(&mut self, ws: &Tensor) {
+        if !self.merged {
+            let _no_grad = crate::no_grad_guard();
+            let _ = ws.shallow_clone().f_add_(&self.delta(ws).unwrap()).unwrap();
+            self.merged = true
+        }
+    }
+
+    fn unmerge(&mut self, ws: &Tensor) {
+        if self.merged {
+            let _no_grad = crate::no_grad_guard();
+            let _ = ws.shallow_clone().f_sub_(&self.delta(ws).unwrap()).unwrap();
+            self.merged = false
+        }
+    }
+}
+
+// Finds the path of the layer owning the weight `ws` in `vs`.
+fn layer_path<'a>(vs: &'a VarStore, ws: &Tensor) -> Result<Path<'a>, TchError> {
+    let name = {
+        let variables = vs.variables_.lock().unwrap();
+        let ptr = ws.data_ptr();
+        variables.named_variables.iter().find(|(_, t)| t.data_ptr() == ptr).map(|(n, _)| n.clone())
+    };
+    let name = name.ok_or_else(|| {
+        TchError::TensorNameNotFound(
+            "weight of the wrapped layer".to_string(),
+            "the var-store".to_string(),
+        )
+    })?;
+    let mut components: Vec<&str> = name.split('.').collect();
+    components.pop();
+    Ok(components.iter().fold(vs.root(), |path, c| path.sub(c)))
+}
+
+/// A linear layer with a frozen base weight and an optional low-rank
+/// adapter.
+#[derive(Debug)]
+pub struct LoraLinear {
+    pub base: Linear,
+    adapter: Option<Adapter>,
+}
+
+impl LoraLinear {
+    /// Adds an adapter to `linear`, a layer whose weights are stored in `vs`.
+    /// The weight and bias of `linear` are frozen and removed from the
+    /// trainable variables of `vs`.
+    pub fn wrap(vs: &VarStore, linear: Linear, config: LoraConfig) -> Result<LoraLinear, TchError> {
+        let path = layer_path(vs, &linear.ws)?;
+        LoraLinear::wrap_with_path(&path, linear, config)
+    }
+
+    fn wrap_with_path(
+        path: &Path,
+        linear: Linear,
+        config: LoraConfig,
+    ) -> Result<LoraLinear, TchError> {
+        let (out_dim, in_dim) = match linear.ws.size().as_slice() {
+            &[out_dim, in_dim] => (out_dim, in_dim),
+            size => {
+                return Err(TchError::Shape(format!(
+                    "lora: unexpected linear weight shape {size:?}"
+                )))
+            }
+        };
+        let adapter = Adapter::new(
+            path,
+            &linear.ws,
+            linear.bs.as_ref(),
+            &[config.r, in_dim],
+            &[out_dim, config.r],
+            config,
+        )?;
+        Ok(LoraLinear { base: linear, adapter: Some(adapter) })
+    }
+
+    /// Returns true if this layer has an adapter.
+    pub fn has_adapter(&self) -> bool {
+        self.adapter.is_some()
+    }
+
+    /// The adapter weights `A` of shape `[r, in_dim]` and `B` of shape
+    /// `[out_dim, r]`.
+    pub fn adapter_weights(&self) -> Option<(&Tensor, &Tensor)> {
+        self.adapter.as_ref().map(|a| (&a.a, &a.b))
+    }
+
+    /// Returns true if the adapter has been merged in the base weight.
+    pub fn is_merged(&self) -> bool {
+        matches!(&self.adapter, Some(adapter) if adapter.merged)
+    }
+
+    /// Adds the adapter update to the base weight, the forward pass then only
+    /// uses the base layer. The adapter should not be trained while merged.
+    pub fn merge(&mut self) {
+        if let Some(adapter) = self.adapter.as_mut() {
+            adapter.merge(&self.base.ws)
+        }
+    }
+
+    /// Removes the adapter update from the base weight, undoing `merge`.
+    pub fn unmerge(&mut self) {
+        if let Some(adapter) = self.adapter.as_mut() {
+            adapter.unmerge(&self.base.ws)
+        }
+    }
+}
+
+impl ModuleT for LoraLinear {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let ys = super::Module::f_forward(&self.base, xs)?;
+        match &self.adapter {
+            Some(adapter) if !adapter.merged => {
+                let delta = xs
+                    .f_dropout(adapter.dropout, train)?
+                    .f_linear::<&Tensor>(&adapter.a, None)?
+                    .f_linear::<&Tensor>(&adapter.b, None)?;
+                ys.f_add(&delta.f_mul_scalar(adapter.scale)?)
+            }
+            _ => Ok(ys),
+        }
+    }
+}
+
+/// A two dimensional convolution layer with a frozen base weight and a
+/// low-rank adapter.
+///
+/// `A` is a convolution with the kernel, stride, padding and dilation of the
+/// base layer and `r` output channels, `B` is a 1x1 convolution.
+#[derive(Debug)]
+pub struct LoraConv2D {
+    pub base: Conv2D,
+    adapter: Adapter,
+}
+
+impl LoraConv2D {
+    /// Adds an adapter to `conv`, a layer whose weights are stored in `vs`.
+    /// Only convolutions with a single group and zero padding are supported.
+    pub fn wrap(vs: &VarStore, conv: Conv2D, config: LoraConfig) -> Result<LoraConv2D, TchError> {
+        let c = conv.config();
+        if c.groups != 1 || c.padding_mode != PaddingMode::Zeros {
+            return Err(TchError::Shape(format!(
+                "lora: unsupported convolution with {} groups and padding mode {:?}",
+                c.groups, c.padding_mode
+            )));
+        }
+        let (out_dim, in_dim, kh, kw) = match conv.ws.size().as_slice() {
+            &[out_dim, in_dim, kh, kw] => (out_dim, in_dim, kh, kw),
+            size => {
+                return Err(TchError::Shape(format!("lora: unexpected conv weight shape {size:?}")))
+            }
+        };
+        let path = layer_path(vs, &conv.ws)?;
+        let adapter = Adapter::new(
+            &path,
+            &conv.ws,
+            conv.bs.as_ref(),
+            &[config.r, in_dim, kh, kw],
+            &[out_dim, config.r, 1, 1],
+            config,
+        )?;
+        Ok(LoraConv2D { base: conv, adapter })
+    }
+
+    /// The adapter weights `A` of shape `[r, in_dim, kh, kw]` and `B` of
+    /// shape `[out_dim, r, 1, 1]`.
+    pub fn adapter_weights(&self) -> (&Tensor, &Tensor) {
+        (&self.adapter.a, &self.adapter.b)
+    }
+
+    /// Returns true if the adapter has been merged in the base weight.
+    pub fn is_merged(&self) -> bool {
+        self.adapter.merged
+    }
+
+    /// Adds the adapter update to the base weight, see `LoraLinear::merge`.
+    pub fn merge(&mut self) {
+        self.adapter.merge(&self.base.ws)
+    }
+
+    /// Removes the adapter update from the base weight, undoing `merge`.
+    pub fn unmerge(&mut self) {
+        self.adapter.unmerge(&self.base.ws)
+    }
+}
+
+impl ModuleT for LoraConv2D {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let ys = super::Module::f_forward(&self.base, xs)?;
+        let adapter = &self.adapter;
+        if adapter.merged {
+            return Ok(ys);
+        }
+        let c = self.base.config();
+        let (xs, padding) = self.base.f_pad_input(&xs.f_dropout(adapter.dropout, train)?)?;
+        let delta = xs
+            .f_conv2d::<&Tensor>(&adapter.a, None, c.stride, padding, c.dilation, 1)?
+            .f_conv2d::<&Tensor>(&adapter.b, None, [1, 1], [0, 0], [1, 1], 1)?;
+        ys.f_add(&delta.f_mul_scalar(adapter.scale)?)
+    }
+}
+
+/// Selects the linear layers that get an adapter when created with `linear`,
+/// by matching their path against patterns.
+///
+/// The patterns are matched against the full path of the layer, e.g.
+/// `encoder.layer0.attn.q`, a `*` matches any sequence of characters
+/// including the separators.
+#[derive(Debug, Clone)]
+pub struct LoraTargets {
+    patterns: Vec<String>,
+    config: LoraConfig,
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
+
+impl LoraTargets {
+    /// Targets without any pattern, so that no adapter is created.
+    pub fn new(config: LoraConfig) -> LoraTargets {
+        LoraTargets { patterns: vec![], config }
+    }
+
+    /// Adds a pattern for the layers to adapt.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn config(&self) -> LoraConfig {
+        self.config
+    }
+
+    /// Returns true if the layer at path `name` matches one of the patterns.
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| glob_match(p.as_bytes(), name.as_bytes()))
+    }
+}
+
+/// Creates a linear layer, see `nn::linear`, with an adapter if its path
+/// matches `targets`.
+///
+/// Building a model with this function in place of `nn::linear` adapts all
+/// the matching layers, the pretrained weights can then be loaded with
+/// `VarStore::load_partial` as the adapter weights are not part of them.
+pub fn linear<'a, T: Borrow<Path<'a>>>(
+    vs: T,
+    in_dim: i64,
+    out_dim: i64,
+    c: LinearConfig,
+    targets: &LoraTargets,
+) -> LoraLinear {
+    let vs = vs.borrow();
+    let base = super::linear(vs, in_dim, out_dim, c);
+    let name = vs.components().collect::<Vec<_>>().join(".");
+    if targets.matches(&name) {
+        LoraLinear::wrap_with_path(vs, base, targets.config).unwrap()
+    } else {
+        LoraLinear { base, adapter: None }
+    }
+}
+
+fn is_adapter_variable(name: &str) -> bool {
+    name.split('.').any(|c| c == "lora_a" || c == "lora_b")
+}
+
+/// Saves the adapter weights of `vs` to a file, using the safetensors format
+/// when the file has a `.safetensors` extension.
+pub fn save_adapters<T: AsRef<std::path::Path>>(vs: &VarStore, path: T) -> Result<(), TchError> {
+    let variables = vs.variables_.lock().unwrap();
+    let named_tensors = variables
+        .named_variables
+        .iter()
+        .filter(|(name, _)| is_adapter_variable(name))
+        .collect::<Vec<_>>();
+    match path.as_ref().extension().and_then(|x| x.to_str()) {
+        Some("safetensors") => Tensor::write_safetensors(named_tensors.as_slice(), path),
+        Some(_) | None => Tensor::save_multi(named_tensors.as_slice(), path),
+    }
+}
+
+/// Loads the adapter weights of `vs` from a file written by `save_adapters`.
+///
+/// Every adapter variable of `vs` must be present in the file, the other
+/// variables are left untouched.
+pub fn load_adapters<T: AsRef<std::path::Path>>(
+    vs: &mut VarStore,
+    path: T,
+) -> Result<(), TchError> {
+    let named_tensors = vs.named_tensors(&path)?;
+    let variables = vs.variables_.lock().unwrap();
+    let _no_grad = crate::no_grad_guard();
+    for (name, var) in variables.named_variables.iter() {
+        if !is_adapter_variable(name) {
+            continue;
+        }
+        match named_tensors.get(name) {
+            Some(src) => var.shallow_clone().f_copy_(src).map_err(|e| e.path_context(name))?,
+            None => {
+                return Err(TchError::TensorNameNotFound(
+                    name.to_string(),
+                    path.as_ref().to_string_lossy().into_owned(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Loss functions.
+use crate::{Kind, Reduction, TchError, Tensor};
+
+// The log-sum-exp of the logits of a vocabulary chunk and the logits of the
+// targets that fall in this chunk, stacked in a [2, n] tensor.
+fn chunk_stats(hidden: &Tensor, weight: &Tensor, targets: &Tensor, start: i64) -> Tensor {
+    let len = weight.size()[0];
+    let mut logits = hidden.matmul(&weight.tr());
+    if matches!(logits.kind(), Kind::Half | Kind::BFloat16) {
+        logits = logits.to_kind(Kind::Float)
+    }
+    let logsumexp = logits.logsumexp([1], false);
+    let in_chunk = targets.ge(start).logical_and(&targets.lt(start + len));
+    let local = (targets - start).clamp(0, len - 1).unsqueeze(1);
+    let target_logits = logits.gather(1, &local, false).squeeze_dim(1);
+    let target_logits = &target_logits * in_chunk.to_kind(target_logits.kind());
+    Tensor::stack(&[logsumexp, target_logits], 0)
+}
+
+/// The cross-entropy loss of a linear classifier, computed without
+/// materializing the logits for the whole vocabulary.
+///
+/// `hidden` has shape `[.., dim]`, `classifier_weight` has shape
+/// `[vocab, dim]` and `targets` contains the class indexes with the leading
+/// dimensions of `hidden`. This gives the same result as
+/// `hidden.matmul(&classifier_weight.tr()).cross_entropy_loss(..)` but the
+/// logits are computed by chunks of `chunk_size` classes, so the memory used
+/// for them is bounded by `n * chunk_size` elements rather than `n * vocab`.
+/// Each chunk is checkpointed: its logits are freed once the log-sum-exp and
+/// the target logits have been accumulated, and recomputed in the backward
+/// pass to get the gradients of `hidden` and `classifier_weight`.
+///
+/// The targets equal to `ignore_index` do not contribute to the loss, they
+/// get a zero loss with `Reduction::None`. The logits of half-precision
+/// inputs are accumulated in single precision.
+pub fn f_chunked_cross_entropy(
+    hidden: &Tensor,
+    classifier_weight: &Tensor,
+    targets: &Tensor,
+    chunk_size: i64,
+    ignore_index: i64,
+    reduction: Reduction,
+) -> Result<Tensor, TchError> {
+    let hidden_size = hidden.size();
+    let (dim, leading) = match hidden_size.split_last() {
+        Some((&dim, leading)) => (dim, leading),
+        None => return Err(TchError::Shape("chunked_cross_entropy on a scalar".to_string())),
+    };
+    let weight_size = classifier_weight.size();
+    if weight_size.len() != 2 || weight_size[1] != dim {
+        return Err(TchError::Shape(format!(
+            "chunked_cross_entropy weight {weight_size:?} incompatible with hidden {hidden_size:?}"
+        )));
+    }
+    if targets.size() != leading {
+        return Err(TchError::Shape(format!(
+            "chunked_cross_entropy targets {:?} incompatible with hidden {hidden_size:?}",
+            targets.size()
+        )));
+    }
+    if chunk_size <= 0 {
+        return Err(TchError::Shape(format!(
+            "chunked_cross_entropy chunk size must be positive, got {chunk_size}"
+        )));
+    }
+    let hidden = hidden.f_reshape([-1, dim])?;
+    let targets = targets.f_reshape([-1])?;
+    let vocab = weight_size[0];
+    let mut stats = vec![];
+    for start in (0..vocab).step_by(chunk_size as usize) {
+        let weight = classifier_weight.f_narrow(0, start, chunk_size.min(vocab - start))?;
+        let chunk_targets = targets.shallow_clone();
+        let f = move |xs: &[Tensor]| chunk_stats(&xs[0], &xs[1], &chunk_targets, start);
+        stats.push(crate::utils::f_checkpoint_multi(f, &[&hidden, &weight])?);
+    }
+    let stats = Tensor::f_stack(&stats, 0)?;
+    let logsumexp = stats.f_select(1, 0)?.f_logsumexp([0], false)?;
+    let target_logits = stats.f_select(1, 1)?.f_sum_dim_intlist(0, false, stats.kind())?;
+    let kept = targets.f_ne(ignore_index)?;
+    let loss = logsumexp.f_sub(&target_logits)?.f_mul(&kept.f_to_kind(stats.kind())?)?;
+    match reduction {
+        Reduction::None => loss.f_reshape(leading),
+        Reduction::Sum => loss.f_sum(loss.kind()),
+        Reduction::Mean => loss.f_sum(loss.kind())?.f_div(&kept.f_sum(loss.kind())?),
+        Reduction::Other(r) => {
+            Err(TchError::Torch(format!("chunked_cross_entropy unsupported reduction {r}")))
+        }
+    }
+}
+
+/// The cross-entropy loss of a linear classifier computed by chunks of
+/// classes, see `f_chunked_cross_entropy`.
+pub fn chunked_cross_entropy(
+    hidden: &Tensor,
+    classifier_weight: &Tensor,
+    targets: &Tensor,
+    chunk_size: i64,
+    ignore_index: i64,
+    reduction: Reduction,
+) -> Tensor {
+    f_chunked_cross_entropy(hidden, classifier_weight, targets, chunk_size, ignore_index, reduction)
+        .unwrap()
+}
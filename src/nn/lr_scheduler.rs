@@ -0,0 +1,229 @@
+//! Learning rate schedules for `Optimizer`.
+//!
+//! A scheduler sets the learning rate of each parameter group to a function of
+//! the base learning rate of the group and of the current epoch or step. The
+//! base learning rates are the learning rates of the groups when the
+//! scheduler is stepped for the first time, so groups using different
+//! learning rates keep their ratios. The schedules follow the ones of
+//! `torch.optim.lr_scheduler`.
+use super::Optimizer;
+use std::f64::consts::PI;
+
+/// A learning rate schedule.
+pub trait Scheduler {
+    /// The learning rate at `epoch_or_step` for a group with a base learning
+    /// rate of `base_lr`.
+    fn lr(&self, base_lr: f64, epoch_or_step: i64) -> f64;
+
+    /// Sets the learning rate of each parameter group of `opt` for the given
+    /// epoch or step, counted from 0.
+    fn step(&mut self, opt: &mut Optimizer, epoch_or_step: i64);
+}
+
+// The base learning rates of the parameter groups, recorded the first time
+// that a group is seen.
+#[derive(Debug, Clone, Default)]
+struct BaseLrs(Vec<f64>);
+
+impl BaseLrs {
+    fn update(&mut self, opt: &Optimizer) -> Vec<f64> {
+        for group in self.0.len()..opt.num_groups() {
+            self.0.push(opt.lr_group(group))
+        }
+        self.0.clone()
+    }
+}
+
+fn set_lrs<S: Scheduler>(scheduler: &S, base_lrs: &[f64], opt: &mut Optimizer, step: i64) {
+    for (group, &base_lr) in base_lrs.iter().enumerate() {
+        opt.set_lr_group(group, scheduler.lr(base_lr, step))
+    }
+}
+
+// Implements `Scheduler::step` using the `base_lrs` field.
+macro_rules! scheduler_step {
+    () => {
+        fn step(&mut self, opt: &mut Optimizer, epoch_or_step: i64) {
+            let base_lrs = self.base_lrs.update(opt);
+            set_lrs(self, &base_lrs, opt, epoch_or_step)
+        }
+    };
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` epochs.
+#[derive(Debug, Clone)]
+pub struct StepLR {
+    step_size: i64,
+    gamma: f64,
+    base_lrs: BaseLrs,
+}
+
+impl StepLR {
+    pub fn new(step_size: i64, gamma: f64) -> StepLR {
+        StepLR { step_size: step_size.max(1), gamma, base_lrs: BaseLrs::default() }
+    }
+}
+
+impl Scheduler for StepLR {
+    fn lr(&self, base_lr: f64, epoch: i64) -> f64 {
+        base_lr * self.gamma.powi((epoch.max(0) / self.step_size) as i32)
+    }
+
+    scheduler_step!();
+}
+
+/// Multiplies the learning rate by `gamma` at each of the `milestones`
+/// epochs.
+#[derive(Debug, Clone)]
+pub struct MultiStepLR {
+    milestones: Vec<i64>,
+    gamma: f64,
+    base_lrs: BaseLrs,
+}
+
+impl MultiStepLR {
+    pub fn new(milestones: &[i64], gamma: f64) -> MultiStepLR {
+        MultiStepLR { milestones: milestones.to_vec(), gamma, base_lrs: BaseLrs::default() }
+    }
+}
+
+impl Scheduler for MultiStepLR {
+    fn lr(&self, base_lr: f64, epoch: i64) -> f64 {
+        let passed = self.milestones.iter().filter(|&&m| m <= epoch).count();
+        base_lr * self.gamma.powi(passed as i32)
+    }
+
+    scheduler_step!();
+}
+
+/// Anneals the learning rate from the base learning rate to `eta_min` with
+/// a cosine over `t_max` epochs. As with PyTorch, the learning rate rises
+/// again after `t_max` epochs following the same cosine.
+#[derive(Debug, Clone)]
+pub struct CosineAnnealingLR {
+    t_max: i64,
+    eta_min: f64,
+    base_lrs: BaseLrs,
+}
+
+impl CosineAnnealingLR {
+    pub fn new(t_max: i64, eta_min: f64) -> CosineAnnealingLR {
+        CosineAnnealingLR { t_max: t_max.max(1), eta_min, base_lrs: BaseLrs::default() }
+    }
+}
+
+impl Scheduler for CosineAnnealingLR {
+    fn lr(&self, base_lr: f64, epoch: i64) -> f64 {
+        let t = epoch.max(0) as f64 / self.t_max as f64;
+        self.eta_min + (base_lr - self.eta_min) * (1. + (PI * t).cos()) / 2.
+    }
+
+    scheduler_step!();
+}
+
+/// The 1cycle policy, see "Super-Convergence: Very Fast Training of Neural
+/// Networks Using Large Learning Rates", Smith et al. 2017.
+///
+/// The learning rate of each group rises from `base_lr / div_factor` to the
+/// base learning rate during the first `pct_start` fraction of the
+/// `total_steps` steps, then anneals down to
+/// `base_lr / (div_factor * final_div_factor)`, both phases using a cosine.
+/// This matches `torch.optim.lr_scheduler.OneCycleLR` with `max_lr` set to the
+/// base learning rates, without momentum cycling.
+#[derive(Debug, Clone)]
+pub struct OneCycleLR {
+    total_steps: i64,
+    pct_start: f64,
+    div_factor: f64,
+    final_div_factor: f64,
+    base_lrs: BaseLrs,
+}
+
+impl OneCycleLR {
+    pub fn new(total_steps: i64) -> OneCycleLR {
+        OneCycleLR {
+            total_steps: total_steps.max(2),
+            pct_start: 0.3,
+            div_factor: 25.,
+            final_div_factor: 1e4,
+            base_lrs: BaseLrs::default(),
+        }
+    }
+
+    /// The fraction of the steps spent increasing the learning rate, 0.3 by
+    /// default.
+    pub fn pct_start(mut self, pct_start: f64) -> Self {
+        self.pct_start = pct_start;
+        self
+    }
+
+    /// The initial learning rate is the base learning rate divided by this,
+    /// 25 by default.
+    pub fn div_factor(mut self, div_factor: f64) -> Self {
+        self.div_factor = div_factor;
+        self
+    }
+
+    /// The final learning rate is the initial learning rate divided by this,
+    /// 1e4 by default.
+    pub fn final_div_factor(mut self, final_div_factor: f64) -> Self {
+        self.final_div_factor = final_div_factor;
+        self
+    }
+}
+
+// Cosine interpolation from `start` to `end` for `pct` between 0 and 1.
+fn cosine_anneal(start: f64, end: f64, pct: f64) -> f64 {
+    end + (start - end) / 2. * ((PI * pct).cos() + 1.)
+}
+
+impl Scheduler for OneCycleLR {
+    fn lr(&self, base_lr: f64, step: i64) -> f64 {
+        let initial_lr = base_lr / self.div_factor;
+        let min_lr = initial_lr / self.final_div_factor;
+        let warmup_end = self.pct_start * self.total_steps as f64 - 1.;
+        let end = (self.total_steps - 1) as f64;
+        let step = step.clamp(0, self.total_steps - 1) as f64;
+        if warmup_end > 0. && step <= warmup_end {
+            cosine_anneal(initial_lr, base_lr, step / warmup_end)
+        } else {
+            cosine_anneal(base_lr, min_lr, (step - warmup_end) / (end - warmup_end))
+        }
+    }
+
+    scheduler_step!();
+}
+
+/// Increases the learning rate linearly from `start_factor` times the base
+/// learning rate to the base learning rate over `warmup_steps` steps, then
+/// follows the wrapped scheduler with the steps counted from the end of the
+/// warmup.
+///
+/// This matches chaining `LinearLR` and the wrapped scheduler with
+/// `torch.optim.lr_scheduler.SequentialLR`.
+#[derive(Debug, Clone)]
+pub struct LinearWarmup<S> {
+    inner: S,
+    warmup_steps: i64,
+    start_factor: f64,
+    base_lrs: BaseLrs,
+}
+
+impl<S: Scheduler> LinearWarmup<S> {
+    pub fn new(inner: S, warmup_steps: i64, start_factor: f64) -> LinearWarmup<S> {
+        LinearWarmup { inner, warmup_steps, start_factor, base_lrs: BaseLrs::default() }
+    }
+}
+
+impl<S: Scheduler> Scheduler for LinearWarmup<S> {
+    fn lr(&self, base_lr: f64, step: i64) -> f64 {
+        if step < self.warmup_steps {
+            let t = step.max(0) as f64 / self.warmup_steps as f64;
+            base_lr * (self.start_factor + (1. - self.start_factor) * t)
+        } else {
+            self.inner.lr(base_lr, step - self.warmup_steps)
+        }
+    }
+
+    scheduler_step!();
+}
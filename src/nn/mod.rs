@@ -2,14 +2,17 @@
 //!
 //! This library tries to stay as close as possible to the original
 //! Python and C++ implementations.
+pub mod analysis;
+
 pub mod init;
 pub use init::{f_init, init, Init};
 
 mod var_store;
-pub use var_store::{Path, VarStore, Variables};
+pub(crate) use var_store::write_atomic;
+pub use var_store::{LoadOptions, LoadPartialReport, Path, VarStore, Variables};
 
 mod module;
-pub use module::{Module, ModuleT};
+pub use module::{Module, ModuleAny, ModuleIO, ModuleT};
 
 mod linear;
 pub use linear::*;
@@ -29,23 +32,68 @@ pub use group_norm::*;
 mod layer_norm;
 pub use layer_norm::*;
 
+mod instance_norm;
+pub use instance_norm::*;
+
+mod rms_norm;
+pub use rms_norm::*;
+
 mod sparse;
 pub use sparse::*;
 
 mod rnn;
 pub use rnn::*;
 
+mod packed_sequence;
+pub use packed_sequence::*;
+
+mod attention;
+pub use attention::*;
+
+mod transformer;
+pub use transformer::*;
+
+mod dropout;
+pub use dropout::*;
+
+mod kv_cache;
+pub use kv_cache::{CacheConfig, KvCache};
+
+mod swa;
+pub use swa::{Swa, SwaLr};
+
+mod ema;
+pub use ema::VarStoreEma;
+
+pub mod lora;
+
+pub mod loss;
+
+#[cfg(feature = "distributed")]
+pub mod parallel;
+
 mod func;
 pub use func::*;
 
 mod sequential;
 pub use sequential::*;
 
+mod data_parallel;
+pub use data_parallel::DataParallel;
+
+mod clip_grad;
+pub use clip_grad::{clip_grad_norm, clip_grad_value, f_clip_grad_norm, f_clip_grad_value};
+
 mod optimizer;
 pub use optimizer::{
-    adam, adamw, rms_prop, sgd, Adam, AdamW, Optimizer, OptimizerConfig, RmsProp, Sgd,
+    adagrad, adam, adamw, rms_prop, sgd, Adagrad, Adam, AdamW, Optimizer, OptimizerConfig,
+    ParamGroupConfig, RmsProp, Sgd,
 };
 
+pub mod lr_scheduler;
+
+pub use crate::amp::GradScaler;
+
 /// An identity layer. This just propagates its tensor input as output.
 #[derive(Debug)]
 pub struct Id();
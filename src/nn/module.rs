@@ -1,9 +1,34 @@
 //! Basic module traits defining the forward pass.
-use crate::{data::Iter2, Device, Tensor};
+use crate::{data::Iter2, Device, TchError, Tensor};
+
+// Runs f, converting the panics that it triggers to errors. This is used by the
+// modules that do not provide a fallible implementation of their forward pass.
+fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, TchError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|err| {
+        let msg = match err.downcast_ref::<&str>() {
+            Some(msg) => msg.to_string(),
+            None => match err.downcast_ref::<String>() {
+                Some(msg) => msg.clone(),
+                None => "unknown panic".to_string(),
+            },
+        };
+        TchError::Torch(format!("forward pass panicked: {msg}"))
+    })
+}
 
 /// The simplest module trait, defining a forward function.
 pub trait Module: std::fmt::Debug + Send {
     fn forward(&self, xs: &Tensor) -> Tensor;
+
+    /// Runs the forward pass, returning an error rather than panicking, e.g. on
+    /// invalid input shapes.
+    ///
+    /// The default implementation converts the panics of `forward` to errors,
+    /// this requires the panic strategy to be `unwind`. The panic message is
+    /// still reported by the panic hook.
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        catch_panic(|| self.forward(xs))
+    }
 }
 
 /// Module trait with an additional train parameter.
@@ -13,6 +38,12 @@ pub trait Module: std::fmt::Debug + Send {
 pub trait ModuleT: std::fmt::Debug + Send {
     fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor;
 
+    /// Runs the forward pass, returning an error rather than panicking, see
+    /// `Module::f_forward`.
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        catch_panic(|| self.forward_t(xs, train))
+    }
+
     fn batch_accuracy_for_logits(
         &self,
         xs: &Tensor,
@@ -33,6 +64,81 @@ pub trait ModuleT: std::fmt::Debug + Send {
     }
 }
 
+/// The inputs and outputs of the layers of a `SequentialAny`.
+#[derive(Debug)]
+pub enum ModuleIO {
+    Single(Tensor),
+    Pair(Tensor, Tensor),
+    Many(Vec<Tensor>),
+}
+
+impl ModuleIO {
+    pub fn shallow_clone(&self) -> ModuleIO {
+        match self {
+            ModuleIO::Single(xs) => ModuleIO::Single(xs.shallow_clone()),
+            ModuleIO::Pair(xs, ys) => ModuleIO::Pair(xs.shallow_clone(), ys.shallow_clone()),
+            ModuleIO::Many(xs) => ModuleIO::Many(xs.iter().map(|x| x.shallow_clone()).collect()),
+        }
+    }
+
+    /// The tensor of a `Single` value, other values result in an error.
+    pub fn f_single(&self) -> Result<&Tensor, TchError> {
+        match self {
+            ModuleIO::Single(xs) => Ok(xs),
+            ModuleIO::Pair(_, _) => {
+                Err(TchError::Torch("expected a single tensor, got a pair".to_string()))
+            }
+            ModuleIO::Many(xs) => {
+                Err(TchError::Torch(format!("expected a single tensor, got {} tensors", xs.len())))
+            }
+        }
+    }
+
+    /// The tensor of a `Single` value, panics for other values.
+    pub fn single(&self) -> &Tensor {
+        self.f_single().unwrap()
+    }
+
+    /// The tensors in order, whatever the variant.
+    pub fn into_tensors(self) -> Vec<Tensor> {
+        match self {
+            ModuleIO::Single(xs) => vec![xs],
+            ModuleIO::Pair(xs, ys) => vec![xs, ys],
+            ModuleIO::Many(xs) => xs,
+        }
+    }
+}
+
+impl From<Tensor> for ModuleIO {
+    fn from(xs: Tensor) -> Self {
+        ModuleIO::Single(xs)
+    }
+}
+
+impl From<(Tensor, Tensor)> for ModuleIO {
+    fn from((xs, ys): (Tensor, Tensor)) -> Self {
+        ModuleIO::Pair(xs, ys)
+    }
+}
+
+impl From<Vec<Tensor>> for ModuleIO {
+    fn from(xs: Vec<Tensor>) -> Self {
+        ModuleIO::Many(xs)
+    }
+}
+
+/// Module trait for layers that take or return multiple tensors, e.g. the
+/// recurrent layers which return their final state along with their outputs.
+pub trait ModuleAny: std::fmt::Debug + Send {
+    fn forward_any(&self, xs: &ModuleIO, train: bool) -> ModuleIO;
+
+    /// Runs the forward pass, returning an error rather than panicking, see
+    /// `Module::f_forward`.
+    fn f_forward_any(&self, xs: &ModuleIO, train: bool) -> Result<ModuleIO, TchError> {
+        catch_panic(|| self.forward_any(xs, train))
+    }
+}
+
 impl<T> ModuleT for T
 where
     T: Module,
@@ -40,6 +146,10 @@ where
     fn forward_t(&self, xs: &Tensor, _train: bool) -> Tensor {
         self.forward(xs)
     }
+
+    fn f_forward_t(&self, xs: &Tensor, _train: bool) -> Result<Tensor, TchError> {
+        self.f_forward(xs)
+    }
 }
 
 impl Tensor {
@@ -47,10 +157,18 @@ impl Tensor {
         m.forward(self)
     }
 
+    pub fn f_apply<M: Module>(&self, m: &M) -> Result<Tensor, TchError> {
+        m.f_forward(self)
+    }
+
     pub fn apply_t<M: ModuleT>(&self, m: &M, train: bool) -> Tensor {
         m.forward_t(self, train)
     }
 
+    pub fn f_apply_t<M: ModuleT>(&self, m: &M, train: bool) -> Result<Tensor, TchError> {
+        m.f_forward_t(self, train)
+    }
+
     pub fn apply_opt<M: Module>(&self, m: &Option<M>) -> Tensor {
         match m {
             Some(m) => m.forward(self),
@@ -2,6 +2,7 @@
 use super::var_store::{VarStore, Variables};
 use crate::wrappers::optimizer::COptimizer;
 use crate::{TchError, Tensor};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 /// An optimizer to run gradient descent.
@@ -10,6 +11,20 @@ pub struct Optimizer {
     opt: COptimizer,
     variables: Arc<Mutex<Variables>>,
     variables_in_optimizer: usize,
+    supports_sparse: bool,
+    lr: f64,
+    group_lrs: HashMap<usize, f64>,
+    extra_parameters: Vec<(Tensor, usize)>,
+}
+
+/// Options of a parameter group overriding the ones of the optimizer, the
+/// options left to `None` are unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParamGroupConfig {
+    pub lr: Option<f64>,
+    pub weight_decay: Option<f64>,
+    /// The momentum for SGD and RMSProp, or the first beta for Adam and AdamW.
+    pub momentum: Option<f64>,
 }
 
 /// Optimizer configurations. These configs can be used to build optimizer.
@@ -19,9 +34,17 @@ where
 {
     fn build_copt(&self, lr: f64) -> Result<COptimizer, TchError>;
 
+    /// Returns true if the optimizer can update variables with sparse
+    /// gradients, e.g. the weights of an embedding layer created with
+    /// `sparse: true`, without converting the gradients to dense tensors.
+    fn supports_sparse(&self) -> bool {
+        false
+    }
+
     /// Builds an optimizer with the specified learning rate handling variables stored in `vs`.
     fn build(self, vs: &VarStore, lr: f64) -> Result<Optimizer, TchError> {
         let mut opt = self.build_copt(lr)?;
+        let supports_sparse = self.supports_sparse();
         let v = vs.variables_.lock().unwrap();
         for var in &v.trainable_variables {
             opt.add_parameters(&var.tensor, var.group)?;
@@ -30,6 +53,10 @@ where
             opt,
             variables: vs.variables_.clone(),
             variables_in_optimizer: v.trainable_variables.len(),
+            supports_sparse,
+            lr,
+            group_lrs: HashMap::new(),
+            extra_parameters: vec![],
         })
     }
 }
@@ -58,6 +85,12 @@ impl OptimizerConfig for Sgd {
     fn build_copt(&self, lr: f64) -> Result<COptimizer, TchError> {
         COptimizer::sgd(lr, self.momentum, self.dampening, self.wd, self.nesterov)
     }
+
+    /// Sparse gradients are supported without weight decay, the decay would
+    /// make the updates dense.
+    fn supports_sparse(&self) -> bool {
+        self.wd == 0.
+    }
 }
 
 /// Parameters for the Adam optimizer.
@@ -195,6 +228,38 @@ impl OptimizerConfig for RmsProp {
     }
 }
 
+/// Parameters for the Adagrad optimizer.
+#[derive(Debug, Copy, Clone)]
+pub struct Adagrad {
+    pub lr_decay: f64,
+    pub wd: f64,
+    pub initial_accumulator_value: f64,
+    pub eps: f64,
+}
+
+impl Default for Adagrad {
+    fn default() -> Self {
+        Adagrad { lr_decay: 0., wd: 0., initial_accumulator_value: 0., eps: 1e-10 }
+    }
+}
+
+/// Creates the configuration for the Adagrad optimizer.
+pub fn adagrad(lr_decay: f64, wd: f64) -> Adagrad {
+    Adagrad { lr_decay, wd, ..Default::default() }
+}
+
+impl OptimizerConfig for Adagrad {
+    fn build_copt(&self, lr: f64) -> Result<COptimizer, TchError> {
+        COptimizer::adagrad(lr, self.lr_decay, self.wd, self.initial_accumulator_value, self.eps)
+    }
+
+    /// Sparse gradients are supported without weight decay, only the rows of
+    /// the accumulated squared gradients that have a gradient are updated.
+    fn supports_sparse(&self) -> bool {
+        self.wd == 0.
+    }
+}
+
 impl Optimizer {
     fn add_missing_variables(&mut self) {
         let v = self.variables.lock().unwrap();
@@ -207,20 +272,57 @@ impl Optimizer {
     }
 
     /// Zeroes the gradient for the tensors tracked by this optimizer.
+    ///
+    /// Sparse gradients stay sparse, they are emptied rather than filled with
+    /// dense zeros.
     pub fn zero_grad(&mut self) {
         self.add_missing_variables();
         self.opt.zero_grad().unwrap()
     }
 
-    /// Clips gradient value at some specified maximum value.
-    pub fn clip_grad_value(&self, max: f64) {
-        let v = self.variables.lock().unwrap();
-        for var in v.trainable_variables.iter() {
-            let mut grad = var.tensor.grad();
-            if grad.defined() {
-                let _t = grad.clamp_(-max, max);
+    /// Resets the gradient for the tensors tracked by this optimizer to
+    /// undefined tensors rather than filling them with zeros, this saves the
+    /// memory of the gradients until the next backward pass.
+    pub fn zero_grad_set_to_none(&mut self) {
+        self.add_missing_variables();
+        self.opt.zero_grad_set_to_none().unwrap()
+    }
+
+    // The trainable variables of the variable store along with the parameters
+    // added with `add_parameters_with_config`.
+    fn parameters(&self) -> Vec<Tensor> {
+        let mut parameters = self.trainable_variables();
+        parameters.extend(self.extra_parameters.iter().map(|(t, _)| t.shallow_clone()));
+        parameters
+    }
+
+    /// Returns true if this optimizer can update variables with sparse
+    /// gradients, see `OptimizerConfig::supports_sparse`.
+    pub fn supports_sparse(&self) -> bool {
+        self.supports_sparse
+    }
+
+    // Returns an error if some gradients are sparse and the optimizer does not
+    // support them, rather than silently converting them to dense tensors.
+    fn check_sparse_grads(&self) -> Result<(), TchError> {
+        if self.supports_sparse {
+            return Ok(());
+        }
+        for tensor in self.parameters().iter() {
+            let grad = tensor.f_grad()?;
+            if grad.defined() && grad.is_sparse() {
+                return Err(TchError::Torch(format!(
+                    "a variable of shape {:?} has a sparse gradient which this optimizer does not support, use Sgd or Adagrad without weight decay",
+                    tensor.size()
+                )));
             }
         }
+        Ok(())
+    }
+
+    /// Clips gradient value at some specified maximum value.
+    pub fn clip_grad_value(&self, max: f64) {
+        super::clip_grad_value(&self.parameters(), max)
     }
 
     /// Clips gradient L2 norm over all trainable parameters.
@@ -228,32 +330,22 @@ impl Optimizer {
     /// The norm is computed over all gradients together, as if they were
     /// concatenated into a single vector.
     pub fn clip_grad_norm(&self, max: f64) {
-        crate::no_grad(|| {
-            let v = self.variables.lock().unwrap();
-            let mut norms = vec![];
-            for var in v.trainable_variables.iter() {
-                let grad = var.tensor.grad();
-                if grad.defined() {
-                    norms.push(grad.norm());
-                }
-            }
-            let total_norm = f64::try_from(Tensor::stack(&norms, 0).norm()).unwrap();
-            let clip_coef = max / (total_norm + 1e-6);
-            if clip_coef < 1.0 {
-                for var in v.trainable_variables.iter() {
-                    let mut grad = var.tensor.grad();
-                    if grad.defined() {
-                        let _t = grad.g_mul_scalar_(clip_coef);
-                    }
-                }
-            }
-        })
+        let _ = super::clip_grad_norm(&self.parameters(), max, 2.);
     }
 
     /// Performs an optimization step, updating the tracked tensors based on their gradients.
-    pub fn step(&mut self) {
+    ///
+    /// Returns an error if some gradients are sparse and the optimizer does
+    /// not support sparse gradients.
+    pub fn f_step(&mut self) -> Result<(), TchError> {
         self.add_missing_variables();
-        self.opt.step().unwrap()
+        self.check_sparse_grads()?;
+        self.opt.step()
+    }
+
+    /// Performs an optimization step, updating the tracked tensors based on their gradients.
+    pub fn step(&mut self) {
+        self.f_step().unwrap()
     }
 
     /// Applies a backward step pass, update the gradients, and performs an optimization step.
@@ -261,6 +353,7 @@ impl Optimizer {
         self.add_missing_variables();
         self.opt.zero_grad().unwrap();
         loss.backward();
+        self.check_sparse_grads().unwrap();
         self.opt.step().unwrap()
     }
 
@@ -272,6 +365,7 @@ impl Optimizer {
         self.opt.zero_grad().unwrap();
         loss.backward();
         self.clip_grad_value(max);
+        self.check_sparse_grads().unwrap();
         self.opt.step().unwrap()
     }
 
@@ -283,12 +377,47 @@ impl Optimizer {
         self.opt.zero_grad().unwrap();
         loss.backward();
         self.clip_grad_norm(max);
+        self.check_sparse_grads().unwrap();
         self.opt.step().unwrap()
     }
 
-    /// Sets the optimizer learning rate.
+    /// Saves the optimizer state, e.g. the moment estimates of Adam, and the
+    /// options of the parameter groups such as the learning rate.
+    pub fn save<T: AsRef<std::path::Path>>(&mut self, path: T) -> Result<(), TchError> {
+        self.add_missing_variables();
+        self.opt.save(path)
+    }
+
+    /// Loads an optimizer state saved with `save`, the optimizer should track
+    /// the same variables as the saved one.
+    pub fn load<T: AsRef<std::path::Path>>(&mut self, path: T) -> Result<(), TchError> {
+        self.add_missing_variables();
+        self.opt.load(path)
+    }
+
+    /// Sets the optimizer learning rate, for all the parameter groups.
     pub fn set_lr(&mut self, lr: f64) {
-        self.opt.set_learning_rate(lr).unwrap()
+        self.opt.set_learning_rate(lr).unwrap();
+        self.lr = lr;
+        self.group_lrs.clear()
+    }
+
+    /// The learning rate set when building the optimizer or with `set_lr`.
+    pub fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    /// The learning rate of a parameter group.
+    pub fn lr_group(&self, group: usize) -> f64 {
+        self.group_lrs.get(&group).copied().unwrap_or(self.lr)
+    }
+
+    /// The number of parameter groups, groups are numbered from 0 so this is
+    /// one more than the largest group of the trainable variables.
+    pub fn num_groups(&self) -> usize {
+        let v = self.variables.lock().unwrap();
+        let groups = v.trainable_variables.iter().map(|var| var.group + 1);
+        groups.chain(self.extra_parameters.iter().map(|(_, group)| group + 1)).max().unwrap_or(0)
     }
 
     /// Sets the optimizer momentum.
@@ -298,11 +427,14 @@ impl Optimizer {
 
     /// Sets the optimizer learning rate for a parameter group.
     pub fn set_lr_group(&mut self, group: usize, lr: f64) {
-        self.opt.set_learning_rate_group(group, lr).unwrap()
+        self.add_missing_variables();
+        self.opt.set_learning_rate_group(group, lr).unwrap();
+        let _ = self.group_lrs.insert(group, lr);
     }
 
-    /// Sets the optimizer momentum.
+    /// Sets the optimizer momentum for a parameter group.
     pub fn set_momentum_group(&mut self, group: usize, m: f64) {
+        self.add_missing_variables();
         self.opt.set_momentum_group(group, m).unwrap()
     }
 
@@ -317,8 +449,55 @@ impl Optimizer {
         self.opt.set_weight_decay(weight_decay).unwrap()
     }
 
-    /// Sets the optimizer weight decay.
+    /// Sets the optimizer weight decay for a parameter group.
     pub fn set_weight_decay_group(&mut self, group: usize, weight_decay: f64) {
+        self.add_missing_variables();
         self.opt.set_weight_decay_group(group, weight_decay).unwrap()
     }
+
+    /// Sets the options of a parameter group, e.g. to disable weight decay
+    /// for the biases and normalization weights put in a separate group with
+    /// `Path::set_group`.
+    pub fn set_group_config(&mut self, group: usize, config: &ParamGroupConfig) {
+        if let Some(lr) = config.lr {
+            self.set_lr_group(group, lr)
+        }
+        if let Some(weight_decay) = config.weight_decay {
+            self.set_weight_decay_group(group, weight_decay)
+        }
+        if let Some(momentum) = config.momentum {
+            self.set_momentum_group(group, momentum)
+        }
+    }
+
+    /// Adds tensors that are not variables of the variable store of this
+    /// optimizer to a new parameter group using the given options, and
+    /// returns the index of this group.
+    ///
+    /// The group index is one more than the largest existing group, variables
+    /// later added to the variable store with this group index join it.
+    pub fn add_parameters_with_config(
+        &mut self,
+        params: &[Tensor],
+        config: &ParamGroupConfig,
+    ) -> Result<usize, TchError> {
+        self.add_missing_variables();
+        let mut known =
+            self.parameters().iter().map(|t| t.f_impl_id()).collect::<Result<HashSet<_>, _>>()?;
+        for t in params.iter() {
+            if !known.insert(t.f_impl_id()?) {
+                return Err(TchError::Torch(format!(
+                    "a tensor of shape {:?} is already tracked by the optimizer",
+                    t.f_size()?
+                )));
+            }
+        }
+        let group = self.num_groups();
+        for t in params.iter() {
+            self.opt.add_parameters(t, group)?;
+            self.extra_parameters.push((t.shallow_clone(), group))
+        }
+        self.set_group_config(group, config);
+        Ok(group)
+    }
 }
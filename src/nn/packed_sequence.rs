@@ -0,0 +1,178 @@
+//! Packed sequences, batches of variable length sequences for the recurrent
+//! layers.
+use crate::{Device, Kind, TchError, Tensor};
+use std::borrow::Borrow;
+
+/// A batch of variable length sequences, packed so that the recurrent layers
+/// do not process the padding.
+///
+/// `data` contains the elements of the time step 0 of all the sequences,
+/// followed by the ones of time step 1 and so on, `batch_sizes[t]` being the
+/// number of sequences with more than `t` elements. The sequences are sorted
+/// by decreasing length, when the batch was not sorted originally
+/// `sorted_indices` maps the sorted sequences to the original batch and
+/// `unsorted_indices` is its inverse.
+#[derive(Debug)]
+pub struct PackedSequence {
+    pub data: Tensor,
+    pub batch_sizes: Tensor,
+    pub sorted_indices: Option<Tensor>,
+    pub unsorted_indices: Option<Tensor>,
+}
+
+impl PackedSequence {
+    pub fn shallow_clone(&self) -> PackedSequence {
+        PackedSequence {
+            data: self.data.shallow_clone(),
+            batch_sizes: self.batch_sizes.shallow_clone(),
+            sorted_indices: self.sorted_indices.as_ref().map(Tensor::shallow_clone),
+            unsorted_indices: self.unsorted_indices.as_ref().map(Tensor::shallow_clone),
+        }
+    }
+
+    /// The number of sequences in the batch.
+    pub fn f_batch_dim(&self) -> Result<i64, TchError> {
+        self.batch_sizes.f_int64_value(&[0])
+    }
+
+    /// Returns a packed sequence with the same layout and other data, e.g. the
+    /// outputs of a recurrent layer.
+    pub fn with_data(&self, data: Tensor) -> PackedSequence {
+        PackedSequence { data, ..self.shallow_clone() }
+    }
+
+    /// Reorders the batch dimension of a recurrent state from the original
+    /// batch order to the packed one.
+    pub(crate) fn f_sort_state(&self, state: &Tensor) -> Result<Tensor, TchError> {
+        match &self.sorted_indices {
+            Some(indices) => state.f_index_select(1, indices),
+            None => Ok(state.shallow_clone()),
+        }
+    }
+
+    /// Reorders the batch dimension of a recurrent state from the packed
+    /// order to the original batch one.
+    pub(crate) fn f_unsort_state(&self, state: &Tensor) -> Result<Tensor, TchError> {
+        match &self.unsorted_indices {
+            Some(indices) => state.f_index_select(1, indices),
+            None => Ok(state.shallow_clone()),
+        }
+    }
+}
+
+/// Packs a batch of padded sequences.
+///
+/// `input` has shape `[batch_size, seq_len, *]` when `batch_first` is set and
+/// `[seq_len, batch_size, *]` otherwise, `lengths` contains the length of each
+/// sequence. When `enforce_sorted` is set, the sequences must be sorted by
+/// decreasing length, otherwise they are sorted and the original order is
+/// restored by `pad_packed_sequence`.
+pub fn f_pack_padded_sequence(
+    input: &Tensor,
+    lengths: &Tensor,
+    batch_first: bool,
+    enforce_sorted: bool,
+) -> Result<PackedSequence, TchError> {
+    let lengths = lengths.f_to_device(Device::Cpu)?.f_to_kind(Kind::Int64)?;
+    let (input, lengths, sorted_indices) = if enforce_sorted {
+        (input.shallow_clone(), lengths, None)
+    } else {
+        let (lengths, sorted_indices) = lengths.f_sort(0, true)?;
+        let sorted_indices = sorted_indices.f_to_device(input.device())?;
+        let batch_dim = if batch_first { 0 } else { 1 };
+        (input.f_index_select(batch_dim, &sorted_indices)?, lengths, Some(sorted_indices))
+    };
+    let (data, batch_sizes) = input.f_internal_pack_padded_sequence(&lengths, batch_first)?;
+    let unsorted_indices = sorted_indices.as_ref().map(invert_permutation).transpose()?;
+    Ok(PackedSequence { data, batch_sizes, sorted_indices, unsorted_indices })
+}
+
+/// Packs a batch of padded sequences, see `f_pack_padded_sequence`.
+pub fn pack_padded_sequence(
+    input: &Tensor,
+    lengths: &Tensor,
+    batch_first: bool,
+    enforce_sorted: bool,
+) -> PackedSequence {
+    f_pack_padded_sequence(input, lengths, batch_first, enforce_sorted).unwrap()
+}
+
+fn invert_permutation(permutation: &Tensor) -> Result<Tensor, TchError> {
+    let size = permutation.size1()?;
+    let range = Tensor::f_arange(size, (Kind::Int64, permutation.device()))?;
+    permutation.f_empty_like()?.f_scatter(0, permutation, &range)
+}
+
+/// Pads a packed batch of sequences, this is the inverse of
+/// `pack_padded_sequence`.
+///
+/// Returns the padded sequences in the original batch order along with their
+/// lengths. The sequences are padded with `padding_value` up to
+/// `total_length`, or up to the length of the longest sequence.
+pub fn f_pad_packed_sequence(
+    sequence: &PackedSequence,
+    batch_first: bool,
+    padding_value: f64,
+    total_length: Option<i64>,
+) -> Result<(Tensor, Tensor), TchError> {
+    let max_seq_length = sequence.batch_sizes.size1()?;
+    let total_length = match total_length {
+        Some(total_length) if total_length < max_seq_length => {
+            return Err(TchError::Shape(format!(
+                "pad_packed_sequence: total_length {total_length} is less than the length of the longest sequence {max_seq_length}"
+            )))
+        }
+        Some(total_length) => total_length,
+        None => max_seq_length,
+    };
+    let (padded, lengths) = Tensor::f_internal_pad_packed_sequence(
+        &sequence.data,
+        &sequence.batch_sizes,
+        batch_first,
+        padding_value,
+        total_length,
+    )?;
+    match &sequence.unsorted_indices {
+        Some(indices) => {
+            let batch_dim = if batch_first { 0 } else { 1 };
+            let padded = padded.f_index_select(batch_dim, indices)?;
+            let lengths = lengths.f_index_select(0, &indices.f_to_device(Device::Cpu)?)?;
+            Ok((padded, lengths))
+        }
+        None => Ok((padded, lengths)),
+    }
+}
+
+/// Pads a packed batch of sequences, see `f_pad_packed_sequence`.
+pub fn pad_packed_sequence(
+    sequence: &PackedSequence,
+    batch_first: bool,
+    padding_value: f64,
+    total_length: Option<i64>,
+) -> (Tensor, Tensor) {
+    f_pad_packed_sequence(sequence, batch_first, padding_value, total_length).unwrap()
+}
+
+/// Packs a list of variable length sequences of shape `[len, *]`.
+pub fn f_pack_sequence<T: Borrow<Tensor>>(
+    sequences: &[T],
+    enforce_sorted: bool,
+) -> Result<PackedSequence, TchError> {
+    if sequences.is_empty() {
+        return Err(TchError::Shape("pack_sequence: no sequences".to_string()));
+    }
+    let lengths = sequences
+        .iter()
+        .map(|s| match s.borrow().f_size()?.first() {
+            Some(&len) => Ok(len),
+            None => Err(TchError::Shape("pack_sequence: scalar sequence".to_string())),
+        })
+        .collect::<Result<Vec<_>, TchError>>()?;
+    let padded = Tensor::f_pad_sequence(sequences, false, 0.)?;
+    f_pack_padded_sequence(&padded, &Tensor::from_slice(&lengths), false, enforce_sorted)
+}
+
+/// Packs a list of variable length sequences, see `f_pack_sequence`.
+pub fn pack_sequence<T: Borrow<Tensor>>(sequences: &[T], enforce_sorted: bool) -> PackedSequence {
+    f_pack_sequence(sequences, enforce_sorted).unwrap()
+}
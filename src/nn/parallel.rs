@@ -0,0 +1,285 @@
+//! Tensor parallel layers, splitting the weights of a layer across the
+//! processes of a process group, see "Megatron-LM: Training Multi-Billion
+//! Parameter Language Models Using Model Parallelism", Shoeybi et al. 2019.
+//!
+//! Each process only stores its shard of the weights, so the variable store
+//! of a process only contains local shards. Checkpoints are saved per rank
+//! with `save_shard` and loaded with `load_shard`, which checks that the
+//! checkpoint was written by a process with the same rank and world size.
+use super::init::NormalOrUniform;
+use super::{Init, Path, VarStore};
+use crate::distributed::ProcessGroup;
+use crate::{TchError, Tensor};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const RANK_KEY: &str = "tensor_parallel.rank";
+const WORLD_SIZE_KEY: &str = "tensor_parallel.world_size";
+
+/// Configuration for the tensor parallel linear layers.
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelLinearConfig {
+    /// The initialization of the weights, Kaiming initializations use the
+    /// fan of the full weight matrix rather than the one of the shard.
+    pub ws_init: Init,
+    pub bs_init: Option<Init>,
+    pub bias: bool,
+    /// Whether the inputs are already split across the processes. For
+    /// `RowParallelLinear` this means that each process gets the chunk of the
+    /// last input dimension matching its shard. For `ColumnParallelLinear`
+    /// this means that the input gradients are already summed across the
+    /// processes, e.g. because the input does not require gradients.
+    pub input_is_parallel: bool,
+    /// Whether `ColumnParallelLinear` gathers the outputs of all processes.
+    /// When not set, each process returns its chunk of the last output
+    /// dimension which can be used as the input of a `RowParallelLinear`.
+    pub gather_output: bool,
+}
+
+impl Default for ParallelLinearConfig {
+    fn default() -> Self {
+        ParallelLinearConfig {
+            ws_init: super::init::DEFAULT_KAIMING_UNIFORM,
+            bs_init: None,
+            bias: true,
+            input_is_parallel: false,
+            gather_output: true,
+        }
+    }
+}
+
+// The initialization of a shard of a weight with dimensions `full_dims`.
+fn shard_init(init: Init, full_dims: &[i64]) -> Init {
+    match init {
+        Init::Kaiming { dist, fan, non_linearity } => {
+            let fan = fan.for_weight_dims(full_dims);
+            let std = non_linearity.gain() / (fan as f64).sqrt();
+            match dist {
+                NormalOrUniform::Uniform => {
+                    let bound = 3f64.sqrt() * std;
+                    Init::Uniform { lo: -bound, up: bound }
+                }
+                NormalOrUniform::Normal => Init::Randn { mean: 0., stdev: std },
+            }
+        }
+        init => init,
+    }
+}
+
+fn bias_init(c: &ParallelLinearConfig, in_dim: i64) -> Init {
+    c.bs_init.unwrap_or_else(|| {
+        let bound = 1.0 / (in_dim as f64).sqrt();
+        Init::Uniform { lo: -bound, up: bound }
+    })
+}
+
+fn shard_size(dim: i64, pg: &ProcessGroup, what: &str) -> Result<i64, TchError> {
+    let world_size = pg.world_size() as i64;
+    if dim % world_size != 0 {
+        return Err(TchError::Shape(format!(
+            "{what} dimension {dim} is not divisible by the world size {world_size}"
+        )));
+    }
+    Ok(dim / world_size)
+}
+
+/// A linear layer which weight is split along the output dimension, each
+/// process computing a chunk of the outputs.
+#[derive(Debug)]
+pub struct ColumnParallelLinear {
+    /// The local shard of the weight, of shape `[out_dim / world_size, in_dim]`.
+    pub ws: Tensor,
+    /// The local shard of the bias, of shape `[out_dim / world_size]`.
+    pub bs: Option<Tensor>,
+    pg: Arc<ProcessGroup>,
+    input_is_parallel: bool,
+    gather_output: bool,
+}
+
+impl ColumnParallelLinear {
+    /// Creates the local shard of a linear layer from `in_dim` to `out_dim`,
+    /// `out_dim` has to be divisible by the world size of `pg`.
+    pub fn new<'a, T: std::borrow::Borrow<Path<'a>>>(
+        vs: T,
+        pg: Arc<ProcessGroup>,
+        in_dim: i64,
+        out_dim: i64,
+        c: ParallelLinearConfig,
+    ) -> Result<ColumnParallelLinear, TchError> {
+        let vs = vs.borrow();
+        let local_out = shard_size(out_dim, &pg, "output")?;
+        let ws_init = shard_init(c.ws_init, &[out_dim, in_dim]);
+        let ws = vs.f_var("weight", &[local_out, in_dim], ws_init)?;
+        let bs = if c.bias {
+            Some(vs.f_var("bias", &[local_out], bias_init(&c, in_dim))?)
+        } else {
+            None
+        };
+        Ok(ColumnParallelLinear {
+            ws,
+            bs,
+            pg,
+            input_is_parallel: c.input_is_parallel,
+            gather_output: c.gather_output,
+        })
+    }
+
+    /// Reconstructs the full weight matrix of shape `[out_dim, in_dim]` by
+    /// gathering the shards of all the processes, for debugging purposes.
+    pub fn gather_full_weight(&self) -> Result<Tensor, TchError> {
+        let _guard = crate::no_grad_guard();
+        Tensor::f_cat(&self.pg.all_gather(&self.ws)?, 0)
+    }
+}
+
+impl super::Module for ColumnParallelLinear {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let xs = if self.input_is_parallel {
+            xs.shallow_clone()
+        } else {
+            self.pg.copy_to_parallel(xs)?
+        };
+        let ys = xs.f_linear(&self.ws, self.bs.as_ref())?;
+        if self.gather_output {
+            self.pg.gather_from_parallel(&ys, -1)
+        } else {
+            Ok(ys)
+        }
+    }
+}
+
+/// A linear layer which weight is split along the input dimension, each
+/// process computing partial outputs from a chunk of the inputs that are then
+/// summed across processes.
+#[derive(Debug)]
+pub struct RowParallelLinear {
+    /// The local shard of the weight, of shape `[out_dim, in_dim / world_size]`.
+    pub ws: Tensor,
+    /// The bias, of shape `[out_dim]`, which is the same on all the processes.
+    pub bs: Option<Tensor>,
+    pg: Arc<ProcessGroup>,
+    input_is_parallel: bool,
+}
+
+impl RowParallelLinear {
+    /// Creates the local shard of a linear layer from `in_dim` to `out_dim`,
+    /// `in_dim` has to be divisible by the world size of `pg`.
+    ///
+    /// The bias is broadcast from the process with rank 0, so all the
+    /// processes of the group have to create the layer at the same time.
+    pub fn new<'a, T: std::borrow::Borrow<Path<'a>>>(
+        vs: T,
+        pg: Arc<ProcessGroup>,
+        in_dim: i64,
+        out_dim: i64,
+        c: ParallelLinearConfig,
+    ) -> Result<RowParallelLinear, TchError> {
+        let vs = vs.borrow();
+        let local_in = shard_size(in_dim, &pg, "input")?;
+        let ws_init = shard_init(c.ws_init, &[out_dim, in_dim]);
+        let ws = vs.f_var("weight", &[out_dim, local_in], ws_init)?;
+        let bs = if c.bias {
+            let bs = vs.f_var("bias", &[out_dim], bias_init(&c, in_dim))?;
+            let _guard = crate::no_grad_guard();
+            pg.broadcast(&mut bs.shallow_clone(), 0)?;
+            Some(bs)
+        } else {
+            None
+        };
+        Ok(RowParallelLinear { ws, bs, pg, input_is_parallel: c.input_is_parallel })
+    }
+
+    /// Reconstructs the full weight matrix of shape `[out_dim, in_dim]` by
+    /// gathering the shards of all the processes, for debugging purposes.
+    pub fn gather_full_weight(&self) -> Result<Tensor, TchError> {
+        let _guard = crate::no_grad_guard();
+        Tensor::f_cat(&self.pg.all_gather(&self.ws)?, 1)
+    }
+}
+
+impl super::Module for RowParallelLinear {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let xs = if self.input_is_parallel {
+            xs.shallow_clone()
+        } else {
+            self.pg.scatter_to_parallel(xs, -1)?
+        };
+        let ys = self.pg.reduce_from_parallel(&xs.f_linear::<&Tensor>(&self.ws, None)?)?;
+        match &self.bs {
+            Some(bs) => ys.f_add(bs),
+            None => Ok(ys),
+        }
+    }
+}
+
+/// The path of the checkpoint of a rank, obtained by adding
+/// `-rank{rank}-of-{world_size}` to the file stem of `path`, e.g.
+/// `model-rank1-of-4.safetensors` for `model.safetensors`.
+pub fn shard_path<T: AsRef<std::path::Path>>(
+    path: T,
+    rank: usize,
+    world_size: usize,
+) -> std::path::PathBuf {
+    let path = path.as_ref();
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut name = format!("{stem}-rank{rank}-of-{world_size}");
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy())
+    }
+    path.with_file_name(name)
+}
+
+/// Saves the local shards of `vs` to the checkpoint of the current rank, see
+/// `shard_path`. The rank and world size are stored as metadata, so `path`
+/// has to use the safetensors format.
+pub fn save_shard<T: AsRef<std::path::Path>>(
+    vs: &VarStore,
+    path: T,
+    pg: &ProcessGroup,
+) -> Result<(), TchError> {
+    let (rank, world_size) = (pg.rank(), pg.world_size());
+    let metadata = HashMap::from([
+        (RANK_KEY.to_string(), rank.to_string()),
+        (WORLD_SIZE_KEY.to_string(), world_size.to_string()),
+    ]);
+    vs.save_with_metadata(shard_path(path, rank, world_size), &metadata)
+}
+
+/// Loads the local shards of `vs` from the checkpoint of the current rank
+/// written by `save_shard`. An error is returned if the checkpoint was
+/// written for a different rank or world size.
+pub fn load_shard<T: AsRef<std::path::Path>>(
+    vs: &mut VarStore,
+    path: T,
+    pg: &ProcessGroup,
+) -> Result<(), TchError> {
+    let (rank, world_size) = (pg.rank(), pg.world_size());
+    let path = shard_path(path, rank, world_size);
+    check_shard_metadata(&path, rank, world_size)?;
+    vs.load(path)
+}
+
+fn check_shard_metadata(
+    path: &std::path::Path,
+    rank: usize,
+    world_size: usize,
+) -> Result<(), TchError> {
+    let metadata = VarStore::read_metadata(path)?;
+    let get = |key: &str| metadata.get(key).and_then(|v| v.parse::<usize>().ok());
+    match (get(RANK_KEY), get(WORLD_SIZE_KEY)) {
+        (Some(r), Some(w)) if r == rank && w == world_size => Ok(()),
+        (r, w) => Err(TchError::FileFormat(format!(
+            "{}: tensor parallel shard for rank {r:?} of {w:?}, expected rank {rank} of {world_size}",
+            path.display()
+        ))),
+    }
+}
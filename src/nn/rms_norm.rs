@@ -0,0 +1,75 @@
+//! A root-mean-square normalization layer.
+//! Root Mean Square Layer Normalization <https://arxiv.org/abs/1910.07467>
+use crate::{Kind, TchError, Tensor};
+use std::borrow::Borrow;
+
+/// RMS-normalization config.
+#[derive(Debug, Clone, Copy)]
+pub struct RmsNormConfig {
+    pub eps: f64,
+    pub elementwise_affine: bool,
+    pub ws_init: super::Init,
+}
+
+impl Default for RmsNormConfig {
+    fn default() -> Self {
+        RmsNormConfig { eps: 1e-6, elementwise_affine: true, ws_init: super::Init::Const(1.) }
+    }
+}
+
+/// A root-mean-square normalization layer, as used in LLaMA-style models.
+///
+/// The inputs are divided by the root mean square over the last dimensions
+/// given by `normalized_shape`, without centering, and then scaled by the
+/// weight. This layer has no bias. Half-precision inputs are normalized in
+/// single precision and converted back.
+#[derive(Debug)]
+pub struct RmsNorm {
+    config: RmsNormConfig,
+    pub ws: Option<Tensor>,
+    pub normalized_shape: Vec<i64>,
+}
+
+pub fn rms_norm<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    normalized_shape: Vec<i64>,
+    config: RmsNormConfig,
+) -> RmsNorm {
+    let vs = vs.borrow();
+    let ws = if config.elementwise_affine {
+        Some(vs.var("weight", normalized_shape.as_slice(), config.ws_init))
+    } else {
+        None
+    };
+    RmsNorm { config, ws, normalized_shape }
+}
+
+impl super::module::Module for RmsNorm {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let size = xs.size();
+        let n = self.normalized_shape.len();
+        if size.len() < n || size[size.len() - n..] != self.normalized_shape[..] {
+            return Err(TchError::Shape(format!(
+                "rms_norm with normalized shape {:?} on an input of shape {size:?}",
+                self.normalized_shape
+            )));
+        }
+        let kind = xs.kind();
+        let upcast = matches!(kind, Kind::Half | Kind::BFloat16);
+        let input = if upcast { xs.f_to_kind(Kind::Float)? } else { xs.shallow_clone() };
+        let dims: Vec<i64> = (size.len() - n..size.len()).map(|d| d as i64).collect();
+        let mean_square = input.f_square()?.f_mean_dim(dims.as_slice(), true, None)?;
+        let ys = input.f_mul(&mean_square.f_add_scalar(self.config.eps)?.f_rsqrt()?)?;
+        let ys = if upcast { ys.f_to_kind(kind)? } else { ys };
+        let ys = match &self.ws {
+            Some(ws) => ys.f_mul(ws)?,
+            None => ys,
+        };
+        super::analysis::record_layer("rms_norm", &ys, &[self.ws.as_ref()], || ys.numel() as u64);
+        Ok(ys)
+    }
+}
@@ -1,5 +1,6 @@
 //! Recurrent Neural Networks
-use crate::{Device, Tensor};
+use super::PackedSequence;
+use crate::{Device, Kind, TchError, Tensor};
 use std::borrow::Borrow;
 
 /// Trait for Recurrent Neural Networks.
@@ -29,6 +30,63 @@ pub trait RNN {
     ///
     /// The input should have dimensions [batch_size, seq_len, features].
     fn seq_init(&self, input: &Tensor, state: &Self::State) -> (Tensor, Self::State);
+
+    /// Applies the recurrent network to a packed batch of variable length
+    /// sequences, the padding is not processed.
+    ///
+    /// The initial state is the result of applying zero_state.
+    fn f_seq_packed(
+        &self,
+        input: &PackedSequence,
+    ) -> Result<(PackedSequence, Self::State), TchError> {
+        let state = self.zero_state(input.f_batch_dim()?);
+        self.f_seq_packed_init(input, &state)
+    }
+
+    /// Applies the recurrent network to a packed batch of variable length
+    /// sequences, see `f_seq_packed`.
+    fn seq_packed(&self, input: &PackedSequence) -> (PackedSequence, Self::State) {
+        self.f_seq_packed(input).unwrap()
+    }
+
+    /// Applies the recurrent network to a packed batch of variable length
+    /// sequences.
+    ///
+    /// The states use the original batch order, the final state of each
+    /// sequence is the one after its last element.
+    ///
+    /// The default implementation pads the batch and runs `seq_init` on it, so
+    /// the padding is processed and the final state of the sequences shorter
+    /// than the longest one includes the padding steps. The LSTM and GRU layers
+    /// process the packed data directly.
+    fn f_seq_packed_init(
+        &self,
+        input: &PackedSequence,
+        state: &Self::State,
+    ) -> Result<(PackedSequence, Self::State), TchError> {
+        let (padded, lengths) = super::f_pad_packed_sequence(input, true, 0., None)?;
+        let (output, state) = self.seq_init(&padded, state);
+        // The outputs are packed back using the layout of the input.
+        let (output, lengths) = match &input.sorted_indices {
+            Some(indices) => (
+                output.f_index_select(0, indices)?,
+                lengths.f_index_select(0, &indices.f_to_device(Device::Cpu)?)?,
+            ),
+            None => (output, lengths),
+        };
+        let (data, _batch_sizes) = output.f_internal_pack_padded_sequence(&lengths, true)?;
+        Ok((input.with_data(data), state))
+    }
+
+    /// Applies the recurrent network to a packed batch of variable length
+    /// sequences, see `f_seq_packed_init`.
+    fn seq_packed_init(
+        &self,
+        input: &PackedSequence,
+        state: &Self::State,
+    ) -> (PackedSequence, Self::State) {
+        self.f_seq_packed_init(input, state).unwrap()
+    }
 }
 
 /// The state for a LSTM network, this contains two tensors.
@@ -46,6 +104,79 @@ impl LSTMState {
     pub fn c(&self) -> Tensor {
         (self.0).1.shallow_clone()
     }
+
+    /// The hidden state vectors of the last layer, with dimensions
+    /// [num_directions, batch_size, hidden_size]. With `bidirectional`, the
+    /// forward direction comes first.
+    pub fn last_layer_h(&self, bidirectional: bool) -> Tensor {
+        last_layer(&(self.0).0, bidirectional)
+    }
+
+    /// The cell state vectors of the last layer, see `last_layer_h`.
+    pub fn last_layer_c(&self, bidirectional: bool) -> Tensor {
+        last_layer(&(self.0).1, bidirectional)
+    }
+}
+
+// The states have dimensions [num_layers * num_directions, batch_size, hidden_size].
+fn last_layer(state: &Tensor, bidirectional: bool) -> Tensor {
+    let num_directions = if bidirectional { 2 } else { 1 };
+    let layer_dim = state.size()[0];
+    state.narrow(0, layer_dim - num_directions, num_directions)
+}
+
+/// Returns the output of the last valid time step of each sequence, with
+/// dimensions [batch_size, num_directions * hidden_size].
+///
+/// `output` is the padded output of a recurrent layer, e.g. as returned by
+/// `pad_packed_sequence`, and `lengths` the length of each sequence. For
+/// bidirectional layers, the backward direction ends on the first time step so
+/// its output is taken there. As the backward direction processes the padding
+/// when applied to a padded batch, this is only exact for packed batches.
+pub fn f_last_outputs(
+    output: &Tensor,
+    lengths: &Tensor,
+    batch_first: bool,
+    bidirectional: bool,
+) -> Result<Tensor, TchError> {
+    let output = if batch_first { output.shallow_clone() } else { output.f_transpose(0, 1)? };
+    let (batch_dim, seq_len, dim) = output.size3()?;
+    let lengths = lengths.f_to_device(output.device())?.f_to_kind(Kind::Int64)?;
+    if lengths.size() != [batch_dim] {
+        return Err(TchError::Shape(format!(
+            "last_outputs: expected {batch_dim} lengths, got {:?}",
+            lengths.size()
+        )));
+    }
+    if batch_dim > 0 {
+        let (min, max) =
+            (lengths.f_min()?.f_int64_value(&[])?, lengths.f_max()?.f_int64_value(&[])?);
+        if min < 1 || max > seq_len {
+            return Err(TchError::Shape(format!(
+                "last_outputs: lengths have to be between 1 and {seq_len}, got {min} to {max}"
+            )));
+        }
+    }
+    let index =
+        lengths.f_sub_scalar(1)?.f_view([batch_dim, 1, 1])?.f_expand([batch_dim, 1, dim], false)?;
+    let last = output.f_gather(1, &index, false)?.f_squeeze_dim(1)?;
+    if !bidirectional {
+        return Ok(last);
+    }
+    let hidden_dim = dim / 2;
+    let backward = output.f_select(1, 0)?.f_narrow(1, hidden_dim, hidden_dim)?;
+    Tensor::f_cat(&[&last.f_narrow(1, 0, hidden_dim)?, &backward], 1)
+}
+
+/// Returns the output of the last valid time step of each sequence, see
+/// `f_last_outputs`.
+pub fn last_outputs(
+    output: &Tensor,
+    lengths: &Tensor,
+    batch_first: bool,
+    bidirectional: bool,
+) -> Tensor {
+    f_last_outputs(output, lengths, batch_first, bidirectional).unwrap()
 }
 
 // The GRU and LSTM layers share the same config.
@@ -59,6 +190,9 @@ pub struct RNNConfig {
     pub train: bool,
     pub bidirectional: bool,
     pub batch_first: bool,
+    /// When positive, the hidden states of the LSTM layers are projected to
+    /// this size, this is not supported by the GRU layers.
+    pub proj_size: i64,
     pub w_ih_init: super::Init,
     pub w_hh_init: super::Init,
     pub b_ih_init: Option<super::Init>,
@@ -74,6 +208,7 @@ impl Default for RNNConfig {
             train: true,
             bidirectional: false,
             batch_first: true,
+            proj_size: 0,
             w_ih_init: super::init::DEFAULT_KAIMING_UNIFORM,
             w_hh_init: super::init::DEFAULT_KAIMING_UNIFORM,
             b_ih_init: Some(super::Init::Const(0.)),
@@ -91,10 +226,12 @@ fn rnn_weights<'a, T: Borrow<super::Path<'a>>>(
     c: RNNConfig,
 ) -> Vec<Tensor> {
     let vs = vs.borrow();
+    // With projections, the outputs and hidden states use the projection size.
+    let out_dim = if c.proj_size > 0 { c.proj_size } else { hidden_dim };
     let mut flat_weights = vec![];
     for layer_idx in 0..c.num_layers {
         for direction_idx in 0..num_directions {
-            let in_dim = if layer_idx == 0 { in_dim } else { hidden_dim * num_directions };
+            let in_dim = if layer_idx == 0 { in_dim } else { out_dim * num_directions };
             let suffix = if direction_idx == 1 { "_reverse" } else { "" };
             let w_ih = vs.var(
                 &format!("weight_ih_l{layer_idx}{suffix}"),
@@ -103,7 +240,7 @@ fn rnn_weights<'a, T: Borrow<super::Path<'a>>>(
             );
             let w_hh = vs.var(
                 &format!("weight_hh_l{layer_idx}{suffix}"),
-                &[gate_dim, hidden_dim],
+                &[gate_dim, out_dim],
                 c.w_hh_init,
             );
             flat_weights.push(w_ih);
@@ -122,6 +259,14 @@ fn rnn_weights<'a, T: Borrow<super::Path<'a>>>(
                 flat_weights.push(b_ih);
                 flat_weights.push(b_hh);
             }
+            if c.proj_size > 0 {
+                let w_hr = vs.var(
+                    &format!("weight_hr_l{layer_idx}{suffix}"),
+                    &[c.proj_size, hidden_dim],
+                    c.w_hh_init,
+                );
+                flat_weights.push(w_hr);
+            }
         }
     }
     flat_weights
@@ -154,11 +299,11 @@ pub fn lstm<'a, T: Borrow<super::Path<'a>>>(
     if vs.device().is_cuda() && crate::Cuda::cudnn_is_available() {
         let _ = Tensor::internal_cudnn_rnn_flatten_weight(
             &flat_weights,
-            4,
+            flat_weights.len() as i64 / (c.num_layers * num_directions),
             in_dim,
             2, /* 2 for LSTM see rnn.cpp in pytorch */
             hidden_dim,
-            0, /* disables projections */
+            c.proj_size,
             c.num_layers,
             c.batch_first,
             c.bidirectional,
@@ -173,9 +318,15 @@ impl RNN for LSTM {
     fn zero_state(&self, batch_dim: i64) -> LSTMState {
         let num_directions = if self.config.bidirectional { 2 } else { 1 };
         let layer_dim = self.config.num_layers * num_directions;
-        let shape = [layer_dim, batch_dim, self.hidden_dim];
-        let zeros = Tensor::zeros(shape, (self.flat_weights[0].kind(), self.device));
-        LSTMState((zeros.shallow_clone(), zeros.shallow_clone()))
+        let options = (self.flat_weights[0].kind(), self.device);
+        let c = Tensor::zeros([layer_dim, batch_dim, self.hidden_dim], options);
+        // With projections, the hidden state uses the projection size.
+        let h = if self.config.proj_size > 0 {
+            Tensor::zeros([layer_dim, batch_dim, self.config.proj_size], options)
+        } else {
+            c.shallow_clone()
+        };
+        LSTMState((h, c))
     }
 
     fn step(&self, input: &Tensor, in_state: &LSTMState) -> LSTMState {
@@ -199,6 +350,39 @@ impl RNN for LSTM {
         );
         (output, LSTMState((h, c)))
     }
+
+    fn f_seq_packed_init(
+        &self,
+        input: &PackedSequence,
+        in_state: &LSTMState,
+    ) -> Result<(PackedSequence, LSTMState), TchError> {
+        let LSTMState((h, c)) = in_state;
+        let (h, c) = (input.f_sort_state(h)?, input.f_sort_state(c)?);
+        let flat_weights = self.flat_weights.iter().collect::<Vec<_>>();
+        let (output, h, c) = Tensor::f_lstm_data(
+            &input.data,
+            &input.batch_sizes,
+            &[&h, &c],
+            &flat_weights,
+            self.config.has_biases,
+            self.config.num_layers,
+            self.config.dropout,
+            self.config.train,
+            self.config.bidirectional,
+        )?;
+        let state = LSTMState((input.f_unsort_state(&h)?, input.f_unsort_state(&c)?));
+        Ok((input.with_data(output), state))
+    }
+}
+
+/// Applies the LSTM to a single input tensor, see `RNN::seq`. The output
+/// contains the outputs for each step followed by the final hidden and cell
+/// states.
+impl super::ModuleAny for LSTM {
+    fn forward_any(&self, xs: &super::ModuleIO, _train: bool) -> super::ModuleIO {
+        let (ys, LSTMState((h, c))) = self.seq(xs.single());
+        super::ModuleIO::Many(vec![ys, h, c])
+    }
 }
 
 /// A GRU state, this contains a single tensor.
@@ -210,6 +394,12 @@ impl GRUState {
     pub fn value(&self) -> Tensor {
         self.0.shallow_clone()
     }
+
+    /// The hidden state vectors of the last layer, see
+    /// `LSTMState::last_layer_h`.
+    pub fn last_layer(&self, bidirectional: bool) -> Tensor {
+        last_layer(&self.0, bidirectional)
+    }
 }
 
 /// A Gated Recurrent Unit (GRU) layer.
@@ -232,6 +422,7 @@ pub fn gru<'a, T: Borrow<super::Path<'a>>>(
     c: RNNConfig,
 ) -> GRU {
     let vs = vs.borrow();
+    assert!(c.proj_size == 0, "proj_size is only supported by the LSTM layers");
     let num_directions = if c.bidirectional { 2 } else { 1 };
     let gate_dim = 3 * hidden_dim;
     let flat_weights = rnn_weights(vs, in_dim, hidden_dim, gate_dim, num_directions, c);
@@ -239,7 +430,7 @@ pub fn gru<'a, T: Borrow<super::Path<'a>>>(
     if vs.device().is_cuda() && crate::Cuda::cudnn_is_available() {
         let _ = Tensor::internal_cudnn_rnn_flatten_weight(
             &flat_weights,
-            4,
+            flat_weights.len() as i64 / (c.num_layers * num_directions),
             in_dim,
             3, /* 3 for GRU see rnn.cpp in pytorch */
             hidden_dim,
@@ -282,4 +473,33 @@ impl RNN for GRU {
         );
         (output, GRUState(h))
     }
+
+    fn f_seq_packed_init(
+        &self,
+        input: &PackedSequence,
+        in_state: &GRUState,
+    ) -> Result<(PackedSequence, GRUState), TchError> {
+        let GRUState(h) = in_state;
+        let (output, h) = Tensor::f_gru_data(
+            &input.data,
+            &input.batch_sizes,
+            &input.f_sort_state(h)?,
+            &self.flat_weights,
+            self.config.has_biases,
+            self.config.num_layers,
+            self.config.dropout,
+            self.config.train,
+            self.config.bidirectional,
+        )?;
+        Ok((input.with_data(output), GRUState(input.f_unsort_state(&h)?)))
+    }
+}
+
+/// Applies the GRU to a single input tensor, see `RNN::seq`. The output
+/// contains the outputs for each step and the final hidden state.
+impl super::ModuleAny for GRU {
+    fn forward_any(&self, xs: &super::ModuleIO, _train: bool) -> super::ModuleIO {
+        let (ys, GRUState(h)) = self.seq(xs.single());
+        super::ModuleIO::Pair(ys, h)
+    }
 }
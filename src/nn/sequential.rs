@@ -1,6 +1,6 @@
 //! A sequential layer used to chain multiple layers and closures.
-use super::{Module, ModuleT};
-use crate::Tensor;
+use super::{Module, ModuleAny, ModuleIO, ModuleT};
+use crate::{TchError, Tensor};
 
 /// A sequential layer combining multiple other layers.
 #[derive(Debug)]
@@ -30,10 +30,21 @@ impl Module for Sequential {
         if self.layers.is_empty() {
             xs.shallow_clone()
         } else {
-            let xs = self.layers[0].forward(xs);
-            self.layers.iter().skip(1).fold(xs, |xs, layer| layer.forward(&xs))
+            let forward = |xs: &Tensor, layer: &dyn Module| {
+                super::analysis::record_module(layer, || layer.forward(xs))
+            };
+            let xs = forward(xs, self.layers[0].as_ref());
+            self.layers.iter().skip(1).fold(xs, |xs, layer| forward(&xs, layer.as_ref()))
         }
     }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let mut xs = xs.shallow_clone();
+        for layer in self.layers.iter() {
+            xs = super::analysis::f_record_module(layer.as_ref(), || layer.f_forward(&xs))?;
+        }
+        Ok(xs)
+    }
 }
 
 impl Sequential {
@@ -99,10 +110,22 @@ impl ModuleT for SequentialT {
         if self.layers.is_empty() {
             xs.shallow_clone()
         } else {
-            let xs = self.layers[0].forward_t(xs, train);
-            self.layers.iter().skip(1).fold(xs, |xs, layer| layer.forward_t(&xs, train))
+            let forward = |xs: &Tensor, layer: &dyn ModuleT| {
+                super::analysis::record_module(layer, || layer.forward_t(xs, train))
+            };
+            let xs = forward(xs, self.layers[0].as_ref());
+            self.layers.iter().skip(1).fold(xs, |xs, layer| forward(&xs, layer.as_ref()))
         }
     }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let mut xs = xs.shallow_clone();
+        for layer in self.layers.iter() {
+            xs =
+                super::analysis::f_record_module(layer.as_ref(), || layer.f_forward_t(&xs, train))?;
+        }
+        Ok(xs)
+    }
 }
 
 impl SequentialT {
@@ -147,3 +170,120 @@ impl SequentialT {
         }
     }
 }
+
+/// A sequential layer whose layers can take and return multiple tensors.
+///
+/// The layers implementing `ModuleT` are added with `add`, they expect a
+/// single tensor as input and return a single tensor. Other layers, e.g. the
+/// recurrent layers, are added with `add_any`.
+#[derive(Debug)]
+pub struct SequentialAny {
+    layers: Vec<Box<dyn ModuleAny>>,
+}
+
+/// Creates a new empty sequential layer with layers taking and returning
+/// multiple tensors.
+pub fn seq_any() -> SequentialAny {
+    SequentialAny { layers: vec![] }
+}
+
+// A layer of a `SequentialAny` with a single input and output tensor.
+#[derive(Debug)]
+struct SingleLayer<M>(M);
+
+impl<M: ModuleT> ModuleAny for SingleLayer<M> {
+    fn forward_any(&self, xs: &ModuleIO, train: bool) -> ModuleIO {
+        self.f_forward_any(xs, train).unwrap()
+    }
+
+    fn f_forward_any(&self, xs: &ModuleIO, train: bool) -> Result<ModuleIO, TchError> {
+        let xs = xs.f_single()?;
+        let ys = super::analysis::f_record_module(&self.0, || self.0.f_forward_t(xs, train))?;
+        Ok(ModuleIO::Single(ys))
+    }
+}
+
+// A layer of a `SequentialAny` defined by a closure.
+#[allow(clippy::type_complexity)]
+struct FuncAny {
+    f: Box<dyn Fn(&ModuleIO, bool) -> ModuleIO + Send>,
+}
+
+impl std::fmt::Debug for FuncAny {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "funcAny")
+    }
+}
+
+impl ModuleAny for FuncAny {
+    fn forward_any(&self, xs: &ModuleIO, train: bool) -> ModuleIO {
+        (*self.f)(xs, train)
+    }
+}
+
+impl SequentialAny {
+    /// The number of sub-layers embedded in this layer.
+    pub fn len(&self) -> i64 {
+        self.layers.len() as i64
+    }
+
+    /// Returns true if this layer does not have any sub-layer.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Appends a layer taking and returning a single tensor.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<M: ModuleT + 'static>(self, layer: M) -> Self {
+        self.add_any(SingleLayer(layer))
+    }
+
+    /// Appends a layer taking and returning any number of tensors.
+    pub fn add_any<M: ModuleAny + 'static>(mut self, layer: M) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Appends a closure after all the current layers.
+    pub fn add_fn<F>(self, f: F) -> Self
+    where
+        F: 'static + Fn(&ModuleIO) -> ModuleIO + Send,
+    {
+        self.add_any(FuncAny { f: Box::new(move |xs, _train| f(xs)) })
+    }
+
+    /// Appends a closure after all the current layers.
+    pub fn add_fn_t<F>(self, f: F) -> Self
+    where
+        F: 'static + Fn(&ModuleIO, bool) -> ModuleIO + Send,
+    {
+        self.add_any(FuncAny { f: Box::new(f) })
+    }
+}
+
+impl ModuleAny for SequentialAny {
+    fn forward_any(&self, xs: &ModuleIO, train: bool) -> ModuleIO {
+        self.f_forward_any(xs, train).unwrap()
+    }
+
+    fn f_forward_any(&self, xs: &ModuleIO, train: bool) -> Result<ModuleIO, TchError> {
+        let mut xs = xs.shallow_clone();
+        for layer in self.layers.iter() {
+            xs = layer.f_forward_any(&xs, train)?;
+        }
+        Ok(xs)
+    }
+}
+
+/// The layers are applied to a single tensor and the last layer must return a
+/// single tensor.
+impl ModuleT for SequentialAny {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let ys = self.f_forward_any(&ModuleIO::Single(xs.shallow_clone()), train)?;
+        Ok(ys.f_single()?.shallow_clone())
+    }
+}
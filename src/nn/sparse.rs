@@ -1,5 +1,5 @@
 //! Sparse Layers
-use crate::Tensor;
+use crate::{TchError, Tensor};
 use std::borrow::Borrow;
 
 /// Configuration option for an embedding layer.
@@ -8,7 +8,15 @@ pub struct EmbeddingConfig {
     pub sparse: bool,
     pub scale_grad_by_freq: bool,
     pub ws_init: super::Init,
+    /// The index of the padding embedding, this embedding is initialized to
+    /// zero and does not receive any gradient so it stays zero during
+    /// training. A negative value disables padding.
     pub padding_idx: i64,
+    /// When set, the embeddings that are looked up and have a norm larger
+    /// than `max_norm` are renormalized in place to have this norm.
+    pub max_norm: Option<f64>,
+    /// The p of the p-norm used with `max_norm`.
+    pub norm_type: f64,
 }
 
 impl Default for EmbeddingConfig {
@@ -18,6 +26,8 @@ impl Default for EmbeddingConfig {
             scale_grad_by_freq: false,
             ws_init: super::Init::Randn { mean: 0., stdev: 1. },
             padding_idx: -1,
+            max_norm: None,
+            norm_type: 2.,
         }
     }
 }
@@ -32,6 +42,36 @@ pub struct Embedding {
     config: EmbeddingConfig,
 }
 
+// Creates the embedding weights, the padding embedding is set to zero.
+fn embedding_weights(
+    vs: &super::Path,
+    num_embeddings: i64,
+    embedding_dim: i64,
+    ws_init: super::Init,
+    padding_idx: i64,
+) -> Tensor {
+    assert!(
+        padding_idx < num_embeddings,
+        "padding_idx {padding_idx} must be less than num_embeddings {num_embeddings}"
+    );
+    let ws = vs.var("weight", &[num_embeddings, embedding_dim], ws_init);
+    if padding_idx >= 0 {
+        crate::no_grad(|| {
+            let _ = ws.get(padding_idx).fill_(0.);
+        })
+    }
+    ws
+}
+
+// Renormalizes the embeddings looked up by `xs` when `max_norm` is set.
+fn renorm(ws: &Tensor, xs: &Tensor, max_norm: Option<f64>, norm_type: f64) -> Result<(), TchError> {
+    if let Some(max_norm) = max_norm {
+        let _no_grad = crate::no_grad_guard();
+        let _ = ws.shallow_clone().f_embedding_renorm_(xs, max_norm, norm_type)?;
+    }
+    Ok(())
+}
+
 pub fn embedding<'a, T: Borrow<super::Path<'a>>>(
     vs: T,
     num_embeddings: i64,
@@ -39,17 +79,177 @@ pub fn embedding<'a, T: Borrow<super::Path<'a>>>(
     config: EmbeddingConfig,
 ) -> Embedding {
     let vs = vs.borrow();
-    Embedding { ws: vs.var("weight", &[num_embeddings, embedding_dim], config.ws_init), config }
+    let ws =
+        embedding_weights(vs, num_embeddings, embedding_dim, config.ws_init, config.padding_idx);
+    Embedding { ws, config }
 }
 
 impl super::module::Module for Embedding {
     fn forward(&self, xs: &Tensor) -> Tensor {
-        Tensor::embedding(
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        renorm(&self.ws, xs, self.config.max_norm, self.config.norm_type)?;
+        let ys = Tensor::f_embedding(
             &self.ws,
             xs,
             self.config.padding_idx,
             self.config.scale_grad_by_freq,
             self.config.sparse,
-        )
+        )?;
+        // Embeddings are lookups and do not perform any multiply-accumulate.
+        super::analysis::record_layer("embedding", &ys, &[Some(&self.ws)], || 0);
+        Ok(ys)
+    }
+}
+
+/// The reduction applied to the embeddings of each bag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingBagMode {
+    Sum,
+    Mean,
+    Max,
+}
+
+impl EmbeddingBagMode {
+    fn to_int(self) -> i64 {
+        match self {
+            EmbeddingBagMode::Sum => 0,
+            EmbeddingBagMode::Mean => 1,
+            EmbeddingBagMode::Max => 2,
+        }
+    }
+}
+
+/// Configuration option for an embedding bag layer.
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddingBagConfig {
+    pub mode: EmbeddingBagMode,
+    pub sparse: bool,
+    pub scale_grad_by_freq: bool,
+    pub ws_init: super::Init,
+    /// The index of the padding embedding, it is initialized to zero and is
+    /// excluded from the reductions. A negative value disables padding.
+    pub padding_idx: i64,
+    /// See `EmbeddingConfig::max_norm`.
+    pub max_norm: Option<f64>,
+    pub norm_type: f64,
+    /// When set, `offsets` has one more element than the number of bags and
+    /// its last element is the number of indexes.
+    pub include_last_offset: bool,
+}
+
+impl Default for EmbeddingBagConfig {
+    fn default() -> Self {
+        EmbeddingBagConfig {
+            mode: EmbeddingBagMode::Mean,
+            sparse: false,
+            scale_grad_by_freq: false,
+            ws_init: super::Init::Randn { mean: 0., stdev: 1. },
+            padding_idx: -1,
+            max_norm: None,
+            norm_type: 2.,
+            include_last_offset: false,
+        }
+    }
+}
+
+/// An embedding bag layer, this reduces the embeddings of bags of indexes
+/// without materializing the embeddings of each index.
+#[derive(Debug)]
+pub struct EmbeddingBag {
+    pub ws: Tensor,
+    config: EmbeddingBagConfig,
+}
+
+pub fn embedding_bag<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    num_embeddings: i64,
+    embedding_dim: i64,
+    config: EmbeddingBagConfig,
+) -> EmbeddingBag {
+    let vs = vs.borrow();
+    let ws =
+        embedding_weights(vs, num_embeddings, embedding_dim, config.ws_init, config.padding_idx);
+    EmbeddingBag { ws, config }
+}
+
+impl EmbeddingBag {
+    /// Reduces the embeddings of each bag, returning a tensor of shape
+    /// `[num_bags, embedding_dim]`.
+    ///
+    /// `xs` either has shape `[num_bags, bag_size]` for bags of the same size,
+    /// or is a 1D tensor of indexes where the bag `i` starts at `offsets[i]`.
+    /// With `EmbeddingBagMode::Sum`, the embeddings can be weighted by
+    /// `per_sample_weights` which has the same shape as `xs`.
+    pub fn f_forward_bags(
+        &self,
+        xs: &Tensor,
+        offsets: Option<&Tensor>,
+        per_sample_weights: Option<&Tensor>,
+    ) -> Result<Tensor, TchError> {
+        let per_sample_weights = per_sample_weights.map(|w| w.shallow_clone());
+        let (xs, offsets, per_sample_weights) = match (xs.dim(), offsets) {
+            (1, Some(offsets)) => (xs.shallow_clone(), offsets.shallow_clone(), per_sample_weights),
+            (2, None) => {
+                let (num_bags, bag_size) = xs.size2()?;
+                let options = (xs.kind(), xs.device());
+                let offsets =
+                    Tensor::f_arange_start_step(0, num_bags * bag_size, bag_size, options)?;
+                let per_sample_weights =
+                    per_sample_weights.map(|w| w.f_reshape([-1])).transpose()?;
+                (xs.f_reshape([-1])?, offsets, per_sample_weights)
+            }
+            (dim, offsets) => {
+                let with = if offsets.is_some() { "with" } else { "without" };
+                return Err(TchError::Shape(format!(
+                    "embedding_bag expects 2D indexes or 1D indexes with offsets, got {dim}D indexes {with} offsets"
+                )));
+            }
+        };
+        if per_sample_weights.is_some() && self.config.mode != EmbeddingBagMode::Sum {
+            return Err(TchError::Torch(format!(
+                "embedding_bag per_sample_weights are only supported with the sum mode, got {:?}",
+                self.config.mode
+            )));
+        }
+        renorm(&self.ws, &xs, self.config.max_norm, self.config.norm_type)?;
+        let padding_idx =
+            if self.config.padding_idx >= 0 { Some(self.config.padding_idx) } else { None };
+        let (ys, _, _, _) = Tensor::f_embedding_bag_padding_idx(
+            &self.ws,
+            &xs,
+            &offsets,
+            self.config.scale_grad_by_freq,
+            self.config.mode.to_int(),
+            self.config.sparse,
+            per_sample_weights.as_ref(),
+            self.config.include_last_offset,
+            padding_idx,
+        )?;
+        super::analysis::record_layer("embedding_bag", &ys, &[Some(&self.ws)], || 0);
+        Ok(ys)
+    }
+
+    /// Reduces the embeddings of each bag, see `f_forward_bags`.
+    pub fn forward_bags(
+        &self,
+        xs: &Tensor,
+        offsets: Option<&Tensor>,
+        per_sample_weights: Option<&Tensor>,
+    ) -> Tensor {
+        self.f_forward_bags(xs, offsets, per_sample_weights).unwrap()
+    }
+}
+
+/// Reduces bags of the same size, `xs` has shape `[num_bags, bag_size]`.
+impl super::module::Module for EmbeddingBag {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        self.f_forward_bags(xs, None, None)
     }
 }
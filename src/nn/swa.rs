@@ -0,0 +1,185 @@
+//! Stochastic Weight Averaging.
+//!
+//! SWA averages the weights visited by the optimizer during the last epochs
+//! of training, usually with a constant or cyclic learning rate, see
+//! "Averaging Weights Leads to Wider Optima and Better Generalization",
+//! Izmailov et al. 2018.
+use super::VarStore;
+use crate::train::{Callback, Context};
+use crate::{TchError, Tensor};
+use std::collections::{HashMap, HashSet};
+
+/// A running average of the trainable variables of a var-store.
+#[derive(Debug)]
+pub struct Swa {
+    averages: HashMap<String, Tensor>,
+    n_averaged: i64,
+}
+
+impl Swa {
+    /// Creates an empty average for the trainable variables of `vs`, the
+    /// first call to `update` copies the current weights.
+    pub fn new(vs: &VarStore) -> Swa {
+        let _no_grad = crate::no_grad_guard();
+        let variables = vs.variables_.lock().unwrap();
+        let trainable: HashSet<_> =
+            variables.trainable_variables.iter().map(|v| v.tensor.data_ptr()).collect();
+        let averages = variables
+            .named_variables
+            .iter()
+            .filter(|(_, t)| trainable.contains(&t.data_ptr()))
+            .map(|(name, t)| (name.clone(), t.detach().copy()))
+            .collect();
+        Swa { averages, n_averaged: 0 }
+    }
+
+    /// The number of snapshots included in the average.
+    pub fn n_averaged(&self) -> i64 {
+        self.n_averaged
+    }
+
+    /// The averaged variables along with their names.
+    pub fn averages(&self) -> &HashMap<String, Tensor> {
+        &self.averages
+    }
+
+    /// Adds the current weights of `vs` to the average.
+    pub fn f_update(&mut self, vs: &VarStore) -> Result<(), TchError> {
+        let _no_grad = crate::no_grad_guard();
+        let variables = vs.variables_.lock().unwrap();
+        for (name, avg) in self.averages.iter_mut() {
+            let var = variables.named_variables.get(name).ok_or_else(|| {
+                TchError::TensorNameNotFound(name.to_string(), "the var-store".to_string())
+            })?;
+            if self.n_averaged == 0 {
+                avg.f_copy_(var)?
+            } else {
+                let delta = (var - &*avg) / (self.n_averaged + 1) as f64;
+                let _ = avg.f_add_(&delta)?;
+            }
+        }
+        self.n_averaged += 1;
+        Ok(())
+    }
+
+    /// Adds the current weights of `vs` to the average.
+    pub fn update(&mut self, vs: &VarStore) {
+        self.f_update(vs).unwrap()
+    }
+
+    /// Copies the averaged weights into `vs` and re-estimates the running
+    /// statistics of the batch-normalization layers.
+    ///
+    /// The statistics of the averaged weights are unknown, so they are reset
+    /// and recomputed as the average over all the batches of `data`.
+    /// `forward` should run the model in train mode, e.g. with
+    /// `model.forward_t(&xs, true)`, it is called without tracking gradients.
+    /// The data is not used when the model has no batch-normalization layer.
+    pub fn f_finalize<I, F>(
+        &self,
+        vs: &mut VarStore,
+        data: I,
+        mut forward: F,
+    ) -> Result<(), TchError>
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> Tensor,
+    {
+        if self.n_averaged == 0 {
+            return Err(TchError::Torch("swa: no weights have been averaged".to_string()));
+        }
+        let _no_grad = crate::no_grad_guard();
+        let mut has_batch_norm = false;
+        {
+            let variables = vs.variables_.lock().unwrap();
+            for (name, avg) in self.averages.iter() {
+                let var = variables.named_variables.get(name).ok_or_else(|| {
+                    TchError::TensorNameNotFound(name.to_string(), "the var-store".to_string())
+                })?;
+                var.shallow_clone().f_copy_(avg)?
+            }
+            for (name, var) in variables.named_variables.iter() {
+                if name.ends_with("running_mean") {
+                    let _ = var.shallow_clone().f_zero_()?;
+                    has_batch_norm = true
+                } else if name.ends_with("running_var") {
+                    let _ = var.shallow_clone().f_fill_(1.)?;
+                }
+            }
+        }
+        if has_batch_norm {
+            // With a momentum of 1/n for the n-th batch, the running statistics
+            // are the cumulative average of the batch statistics.
+            for (index, item) in data.into_iter().enumerate() {
+                let momentum = 1. / (index + 1) as f64;
+                let _ = super::batch_norm::with_momentum(momentum, || forward(item));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the averaged weights into `vs` and re-estimates the running
+    /// statistics of the batch-normalization layers, see `f_finalize`.
+    pub fn finalize<I, F>(&self, vs: &mut VarStore, data: I, forward: F)
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> Tensor,
+    {
+        self.f_finalize(vs, data, forward).unwrap()
+    }
+}
+
+/// The learning rate schedule for the SWA phase of training, starting at
+/// epoch `swa_start`. The learning rate is left untouched before that so
+/// that this can be combined with another scheduler.
+///
+/// When used as a training callback, the learning rate is updated after each
+/// batch. The position in the cycle is not restored when resuming.
+#[derive(Debug, Clone)]
+pub struct SwaLr {
+    swa_start: usize,
+    max_lr: f64,
+    min_lr: f64,
+    cycle_len: usize,
+    epoch: usize,
+    step: usize,
+}
+
+impl SwaLr {
+    /// A constant learning rate `lr` from epoch `swa_start`.
+    pub fn constant(swa_start: usize, lr: f64) -> SwaLr {
+        SwaLr::cyclic(swa_start, lr, lr, 1)
+    }
+
+    /// A learning rate decreasing linearly from `max_lr` to `min_lr` over
+    /// `cycle_len` steps and then jumping back to `max_lr`. The weights are
+    /// usually averaged at the end of each cycle.
+    pub fn cyclic(swa_start: usize, max_lr: f64, min_lr: f64, cycle_len: usize) -> SwaLr {
+        SwaLr { swa_start, max_lr, min_lr, cycle_len: cycle_len.max(1), epoch: 0, step: 0 }
+    }
+
+    /// The learning rate for the given step, counted from the start of the
+    /// SWA phase.
+    pub fn lr(&self, step: usize) -> f64 {
+        let t = (step % self.cycle_len + 1) as f64 / self.cycle_len as f64;
+        (1. - t) * self.max_lr + t * self.min_lr
+    }
+}
+
+impl Callback for SwaLr {
+    fn on_epoch_begin(&mut self, ctx: &mut Context, epoch: usize) -> Result<(), TchError> {
+        self.epoch = epoch;
+        if epoch >= self.swa_start {
+            ctx.opt.set_lr(self.lr(self.step))
+        }
+        Ok(())
+    }
+
+    fn on_batch_end(&mut self, ctx: &mut Context, _: usize, _: f64) -> Result<(), TchError> {
+        if self.epoch >= self.swa_start {
+            self.step += 1;
+            ctx.opt.set_lr(self.lr(self.step))
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+//! Transformer encoder and decoder layers.
+use super::{LayerNorm, Linear, Module, ModuleT, MultiHeadAttention};
+use crate::{TchError, Tensor};
+use std::borrow::Borrow;
+
+/// The activation of the feed-forward blocks of transformer layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformerActivation {
+    Relu,
+    Gelu,
+}
+
+/// Configuration for transformer encoder and decoder layers, the defaults are
+/// the ones of `torch.nn.TransformerEncoderLayer`.
+#[derive(Debug, Clone, Copy)]
+pub struct TransformerLayerConfig {
+    pub d_model: i64,
+    pub nhead: i64,
+    pub dim_feedforward: i64,
+    /// The dropout probability of the attention weights and of the residual
+    /// branches, only applied in training mode.
+    pub dropout: f64,
+    pub activation: TransformerActivation,
+    pub layer_norm_eps: f64,
+    /// When set the inputs and outputs use the `[batch, seq, feature]` layout
+    /// rather than `[seq, batch, feature]`.
+    pub batch_first: bool,
+    /// When set the layer normalizations are applied at the start of the
+    /// attention and feed-forward blocks (pre-LN) rather than after the
+    /// residual additions (post-LN).
+    pub norm_first: bool,
+    /// Whether the attention and linear layers have biases.
+    pub bias: bool,
+}
+
+impl Default for TransformerLayerConfig {
+    fn default() -> Self {
+        TransformerLayerConfig {
+            d_model: 512,
+            nhead: 8,
+            dim_feedforward: 2048,
+            dropout: 0.1,
+            activation: TransformerActivation::Relu,
+            layer_norm_eps: 1e-5,
+            batch_first: false,
+            norm_first: false,
+            bias: true,
+        }
+    }
+}
+
+// The sub-layers of the encoder and decoder layers. Note that `bias` only
+// applies to the attention and linear layers, the layer normalizations always
+// have a bias.
+fn attention(vs: &super::Path, c: &TransformerLayerConfig) -> MultiHeadAttention {
+    let config = super::MultiHeadAttentionConfig {
+        dropout: c.dropout,
+        bias: c.bias,
+        batch_first: c.batch_first,
+        ..Default::default()
+    };
+    super::multi_head_attention(vs, c.d_model, c.nhead, config)
+}
+
+fn linear(vs: &super::Path, in_dim: i64, out_dim: i64, c: &TransformerLayerConfig) -> Linear {
+    super::linear(vs, in_dim, out_dim, super::LinearConfig { bias: c.bias, ..Default::default() })
+}
+
+fn layer_norm(vs: &super::Path, c: &TransformerLayerConfig) -> LayerNorm {
+    let config = super::LayerNormConfig { eps: c.layer_norm_eps, ..Default::default() };
+    super::layer_norm(vs, vec![c.d_model], config)
+}
+
+/// The feed-forward block of a transformer layer.
+#[derive(Debug)]
+struct FeedForward {
+    linear1: Linear,
+    linear2: Linear,
+    activation: TransformerActivation,
+    dropout: f64,
+}
+
+impl FeedForward {
+    fn new(vs: &super::Path, c: &TransformerLayerConfig) -> FeedForward {
+        FeedForward {
+            linear1: linear(&(vs / "linear1"), c.d_model, c.dim_feedforward, c),
+            linear2: linear(&(vs / "linear2"), c.dim_feedforward, c.d_model, c),
+            activation: c.activation,
+            dropout: c.dropout,
+        }
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        let xs = self.linear1.f_forward(xs)?;
+        let xs = match self.activation {
+            TransformerActivation::Relu => xs.f_relu()?,
+            TransformerActivation::Gelu => xs.f_gelu("none")?,
+        };
+        let xs = xs.f_dropout(self.dropout, train)?;
+        self.linear2.f_forward(&xs)?.f_dropout(self.dropout, train)
+    }
+}
+
+// Adds the output of a block to its input, the block is applied to the
+// normalized input with pre-LN and the sum is normalized with post-LN.
+fn residual<F>(xs: &Tensor, norm: &LayerNorm, norm_first: bool, f: F) -> Result<Tensor, TchError>
+where
+    F: FnOnce(&Tensor) -> Result<Tensor, TchError>,
+{
+    if norm_first {
+        xs.f_add(&f(&norm.f_forward(xs)?)?)
+    } else {
+        norm.f_forward(&xs.f_add(&f(xs)?)?)
+    }
+}
+
+/// A transformer encoder layer, equivalent to `torch.nn.TransformerEncoderLayer`.
+///
+/// The variables use the PyTorch names: `self_attn`, `linear1`, `linear2`,
+/// `norm1` and `norm2`.
+#[derive(Debug)]
+pub struct TransformerEncoderLayer {
+    pub self_attn: MultiHeadAttention,
+    feed_forward: FeedForward,
+    pub norm1: LayerNorm,
+    pub norm2: LayerNorm,
+    config: TransformerLayerConfig,
+}
+
+/// Creates a new transformer encoder layer.
+pub fn transformer_encoder_layer<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    config: TransformerLayerConfig,
+) -> TransformerEncoderLayer {
+    let vs = vs.borrow();
+    TransformerEncoderLayer {
+        self_attn: attention(&(vs / "self_attn"), &config),
+        feed_forward: FeedForward::new(vs, &config),
+        norm1: layer_norm(&(vs / "norm1"), &config),
+        norm2: layer_norm(&(vs / "norm2"), &config),
+        config,
+    }
+}
+
+impl TransformerEncoderLayer {
+    pub fn config(&self) -> &TransformerLayerConfig {
+        &self.config
+    }
+
+    /// Applies the layer to `src`, the masks are the ones of
+    /// `MultiHeadAttention::f_forward_qkv_t`.
+    pub fn f_forward_masked_t(
+        &self,
+        src: &Tensor,
+        src_mask: Option<&Tensor>,
+        src_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Result<Tensor, TchError> {
+        let dropout = self.config.dropout;
+        let xs = residual(src, &self.norm1, self.config.norm_first, |xs| {
+            let (ys, _) = self.self_attn.f_forward_qkv_t(
+                xs,
+                xs,
+                xs,
+                src_key_padding_mask,
+                src_mask,
+                false,
+                train,
+            )?;
+            ys.f_dropout(dropout, train)
+        })?;
+        residual(&xs, &self.norm2, self.config.norm_first, |xs| {
+            self.feed_forward.f_forward_t(xs, train)
+        })
+    }
+
+    /// Applies the layer to `src`, see `f_forward_masked_t`.
+    pub fn forward_masked_t(
+        &self,
+        src: &Tensor,
+        src_mask: Option<&Tensor>,
+        src_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Tensor {
+        self.f_forward_masked_t(src, src_mask, src_key_padding_mask, train).unwrap()
+    }
+}
+
+impl ModuleT for TransformerEncoderLayer {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        self.f_forward_masked_t(xs, None, None, train)
+    }
+}
+
+/// A transformer decoder layer, equivalent to `torch.nn.TransformerDecoderLayer`.
+///
+/// The variables use the PyTorch names: `self_attn`, `multihead_attn`,
+/// `linear1`, `linear2`, `norm1`, `norm2` and `norm3`.
+#[derive(Debug)]
+pub struct TransformerDecoderLayer {
+    pub self_attn: MultiHeadAttention,
+    pub multihead_attn: MultiHeadAttention,
+    feed_forward: FeedForward,
+    pub norm1: LayerNorm,
+    pub norm2: LayerNorm,
+    pub norm3: LayerNorm,
+    config: TransformerLayerConfig,
+}
+
+/// Creates a new transformer decoder layer.
+pub fn transformer_decoder_layer<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    config: TransformerLayerConfig,
+) -> TransformerDecoderLayer {
+    let vs = vs.borrow();
+    TransformerDecoderLayer {
+        self_attn: attention(&(vs / "self_attn"), &config),
+        multihead_attn: attention(&(vs / "multihead_attn"), &config),
+        feed_forward: FeedForward::new(vs, &config),
+        norm1: layer_norm(&(vs / "norm1"), &config),
+        norm2: layer_norm(&(vs / "norm2"), &config),
+        norm3: layer_norm(&(vs / "norm3"), &config),
+        config,
+    }
+}
+
+impl TransformerDecoderLayer {
+    pub fn config(&self) -> &TransformerLayerConfig {
+        &self.config
+    }
+
+    /// Applies the layer to `tgt` attending to the encoder output `memory`,
+    /// the masks are the ones of `MultiHeadAttention::f_forward_qkv_t`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn f_forward_masked_t(
+        &self,
+        tgt: &Tensor,
+        memory: &Tensor,
+        tgt_mask: Option<&Tensor>,
+        memory_mask: Option<&Tensor>,
+        tgt_key_padding_mask: Option<&Tensor>,
+        memory_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Result<Tensor, TchError> {
+        let (dropout, norm_first) = (self.config.dropout, self.config.norm_first);
+        let xs = residual(tgt, &self.norm1, norm_first, |xs| {
+            let (ys, _) = self.self_attn.f_forward_qkv_t(
+                xs,
+                xs,
+                xs,
+                tgt_key_padding_mask,
+                tgt_mask,
+                false,
+                train,
+            )?;
+            ys.f_dropout(dropout, train)
+        })?;
+        let xs = residual(&xs, &self.norm2, norm_first, |xs| {
+            let (ys, _) = self.multihead_attn.f_forward_qkv_t(
+                xs,
+                memory,
+                memory,
+                memory_key_padding_mask,
+                memory_mask,
+                false,
+                train,
+            )?;
+            ys.f_dropout(dropout, train)
+        })?;
+        residual(&xs, &self.norm3, norm_first, |xs| self.feed_forward.f_forward_t(xs, train))
+    }
+
+    /// Applies the layer to `tgt` attending to `memory`, see
+    /// `f_forward_masked_t`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn forward_masked_t(
+        &self,
+        tgt: &Tensor,
+        memory: &Tensor,
+        tgt_mask: Option<&Tensor>,
+        memory_mask: Option<&Tensor>,
+        tgt_key_padding_mask: Option<&Tensor>,
+        memory_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Tensor {
+        self.f_forward_masked_t(
+            tgt,
+            memory,
+            tgt_mask,
+            memory_mask,
+            tgt_key_padding_mask,
+            memory_key_padding_mask,
+            train,
+        )
+        .unwrap()
+    }
+}
+
+/// A stack of transformer encoder layers, equivalent to
+/// `torch.nn.TransformerEncoder`.
+///
+/// The layers are stored under `layers.0`, `layers.1`, etc. The final layer
+/// normalization is not created by `transformer_encoder`, it can be set on
+/// `norm`, e.g. for pre-LN stacks, and should then use the `norm` path.
+#[derive(Debug)]
+pub struct TransformerEncoder {
+    pub layers: Vec<TransformerEncoderLayer>,
+    pub norm: Option<LayerNorm>,
+}
+
+/// Creates a stack of `num_layers` transformer encoder layers.
+pub fn transformer_encoder<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    config: TransformerLayerConfig,
+    num_layers: usize,
+) -> TransformerEncoder {
+    let vs = vs.borrow() / "layers";
+    let layers = (0..num_layers).map(|i| transformer_encoder_layer(&vs / i, config)).collect();
+    TransformerEncoder { layers, norm: None }
+}
+
+impl TransformerEncoder {
+    /// Applies the layers to `src`, the masks are passed to each layer.
+    pub fn f_forward_masked_t(
+        &self,
+        src: &Tensor,
+        mask: Option<&Tensor>,
+        src_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Result<Tensor, TchError> {
+        let mut xs = src.shallow_clone();
+        for layer in self.layers.iter() {
+            xs = layer.f_forward_masked_t(&xs, mask, src_key_padding_mask, train)?
+        }
+        match &self.norm {
+            Some(norm) => norm.f_forward(&xs),
+            None => Ok(xs),
+        }
+    }
+
+    /// Applies the layers to `src`, see `f_forward_masked_t`.
+    pub fn forward_masked_t(
+        &self,
+        src: &Tensor,
+        mask: Option<&Tensor>,
+        src_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Tensor {
+        self.f_forward_masked_t(src, mask, src_key_padding_mask, train).unwrap()
+    }
+}
+
+impl ModuleT for TransformerEncoder {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.f_forward_t(xs, train).unwrap()
+    }
+
+    fn f_forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor, TchError> {
+        self.f_forward_masked_t(xs, None, None, train)
+    }
+}
+
+/// A stack of transformer decoder layers, equivalent to
+/// `torch.nn.TransformerDecoder`.
+///
+/// The layers are stored under `layers.0`, `layers.1`, etc. and the optional
+/// final layer normalization under `norm`, see `TransformerEncoder`.
+#[derive(Debug)]
+pub struct TransformerDecoder {
+    pub layers: Vec<TransformerDecoderLayer>,
+    pub norm: Option<LayerNorm>,
+}
+
+/// Creates a stack of `num_layers` transformer decoder layers.
+pub fn transformer_decoder<'a, T: Borrow<super::Path<'a>>>(
+    vs: T,
+    config: TransformerLayerConfig,
+    num_layers: usize,
+) -> TransformerDecoder {
+    let vs = vs.borrow() / "layers";
+    let layers = (0..num_layers).map(|i| transformer_decoder_layer(&vs / i, config)).collect();
+    TransformerDecoder { layers, norm: None }
+}
+
+impl TransformerDecoder {
+    /// Applies the layers to `tgt` attending to `memory`, the masks are passed
+    /// to each layer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn f_forward_masked_t(
+        &self,
+        tgt: &Tensor,
+        memory: &Tensor,
+        tgt_mask: Option<&Tensor>,
+        memory_mask: Option<&Tensor>,
+        tgt_key_padding_mask: Option<&Tensor>,
+        memory_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Result<Tensor, TchError> {
+        let mut xs = tgt.shallow_clone();
+        for layer in self.layers.iter() {
+            xs = layer.f_forward_masked_t(
+                &xs,
+                memory,
+                tgt_mask,
+                memory_mask,
+                tgt_key_padding_mask,
+                memory_key_padding_mask,
+                train,
+            )?
+        }
+        match &self.norm {
+            Some(norm) => norm.f_forward(&xs),
+            None => Ok(xs),
+        }
+    }
+
+    /// Applies the layers to `tgt` attending to `memory`, see
+    /// `f_forward_masked_t`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn forward_masked_t(
+        &self,
+        tgt: &Tensor,
+        memory: &Tensor,
+        tgt_mask: Option<&Tensor>,
+        memory_mask: Option<&Tensor>,
+        tgt_key_padding_mask: Option<&Tensor>,
+        memory_key_padding_mask: Option<&Tensor>,
+        train: bool,
+    ) -> Tensor {
+        self.f_forward_masked_t(
+            tgt,
+            memory,
+            tgt_mask,
+            memory_mask,
+            tgt_key_padding_mask,
+            memory_key_padding_mask,
+            train,
+        )
+        .unwrap()
+    }
+}
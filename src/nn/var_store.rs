@@ -1,6 +1,6 @@
 //! Variable stores.
 use super::Init;
-use crate::tensor::Tensor;
+use crate::tensor::{SharedTensor, Tensor};
 use crate::wrappers::stream::ReadSeekAdapter;
 use crate::{Device, Kind, TchError};
 use std::collections::hash_map::Entry::{Occupied, Vacant};
@@ -12,8 +12,74 @@ use std::sync::{Arc, Mutex, MutexGuard};
 /// The separator is used to separate path elements in the tensor names.
 const SEP: char = '.';
 
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const CHECKPOINT_SUFFIX: &str = ".safetensors";
+const LATEST_CHECKPOINT: &str = "latest";
+
+fn is_safetensors(path: &std::path::Path) -> bool {
+    path.extension().and_then(|x| x.to_str()) == Some("safetensors")
+}
+
+// Writes a file by calling `write` on a temporary file in the same directory,
+// the temporary file is then synced and renamed to `path` so that `path`
+// always holds a complete file. The temporary file name includes the process
+// id and a counter so that concurrent saves to the same path do not collide.
+pub(crate) fn write_atomic<F>(path: &std::path::Path, write: F) -> Result<(), TchError>
+where
+    F: FnOnce(&std::path::Path) -> Result<(), TchError>,
+{
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| TchError::FileFormat(format!("invalid file name {path:?}")))?;
+    static TMP_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let counter = TMP_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.{counter}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    if let Err(err) = write(&tmp_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    // The file is opened for writing as flushing a read-only handle fails on
+    // Windows.
+    let synced = std::fs::OpenOptions::new().write(true).open(&tmp_path)?.sync_all();
+    if let Err(err) = synced.and_then(|()| std::fs::rename(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+    // Syncs the directory too so that the rename is durable.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// The numbered checkpoints of `dir` sorted by number.
+fn checkpoints(dir: &std::path::Path) -> Result<Vec<(u64, std::path::PathBuf)>, TchError> {
+    let mut checkpoints = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let index = entry.file_name().to_str().and_then(|name| {
+            name.strip_prefix(CHECKPOINT_PREFIX)?.strip_suffix(CHECKPOINT_SUFFIX)?.parse().ok()
+        });
+        if let Some(index) = index {
+            checkpoints.push((index, entry.path()))
+        }
+    }
+    checkpoints.sort();
+    Ok(checkpoints)
+}
+
 #[derive(Debug)]
 pub struct Var {
+    /// The name of the variable in `Variables::named_variables`.
+    pub name: String,
     pub tensor: Tensor,
     pub group: usize,
 }
@@ -52,6 +118,43 @@ pub struct Entry<'a> {
     path: &'a Path<'a>,
 }
 
+/// The options of `VarStore::load_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Fails when a variable is missing from the file, and copies the tensors
+    /// regardless of their shapes, as done by `VarStore::load`. Otherwise the
+    /// variables missing from the file or with a different shape there are
+    /// skipped, as done by `VarStore::load_partial`.
+    pub strict: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions { strict: true }
+    }
+}
+
+/// The variable names that did not match when loading a var-store, sorted in
+/// alphabetical order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadPartialReport {
+    /// The variables of the var-store missing from the file.
+    pub missing: Vec<String>,
+    /// The tensors of the file that are not variables of the var-store.
+    pub unexpected: Vec<String>,
+    /// The variables whose shape differs from the one in the file, these are
+    /// not loaded.
+    pub shape_mismatch: Vec<String>,
+}
+
+impl LoadPartialReport {
+    /// Returns true if all the variables were loaded and all the tensors of the
+    /// file were used.
+    pub fn is_exact(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.shape_mismatch.is_empty()
+    }
+}
+
 impl VarStore {
     /// Creates a new var-store located on the specified device.
     pub fn new(device: Device) -> VarStore {
@@ -92,9 +195,10 @@ impl VarStore {
                         }
                     }
                 }
-                for trainable_var in
+                for mut trainable_var in
                     var_store.variables_.lock().unwrap().trainable_variables.drain(..)
                 {
+                    trainable_var.name = format!("{}{}", prefix.unwrap_or(""), trainable_var.name);
                     new_variables.trainable_variables.push(trainable_var);
                 }
             }
@@ -128,6 +232,20 @@ impl VarStore {
         variables.trainable_variables.iter().map(|v| v.tensor.shallow_clone()).collect()
     }
 
+    /// Returns the trainable variables along with their names, sorted by
+    /// name. Frozen variables are included, their tensors do not require
+    /// gradients.
+    pub fn trainable_variables_with_names(&self) -> Vec<(String, Tensor)> {
+        let variables = self.variables_.lock().unwrap();
+        let mut named: Vec<_> = variables
+            .trainable_variables
+            .iter()
+            .map(|v| (v.name.clone(), v.tensor.shallow_clone()))
+            .collect();
+        named.sort_by(|(n1, _), (n2, _)| n1.cmp(n2));
+        named
+    }
+
     /// Returns all variables along with their names.
     pub fn variables(&self) -> HashMap<String, Tensor> {
         let variables = self.variables_.lock().unwrap();
@@ -138,6 +256,20 @@ impl VarStore {
             .collect()
     }
 
+    /// Returns all variables along with their names as tensors that can be
+    /// shared between threads, e.g. to run concurrent forward passes.
+    ///
+    /// The var-store still holds the variables so they should not be updated,
+    /// e.g. by an optimizer step, while they are shared.
+    pub fn shared_variables(&self) -> HashMap<String, SharedTensor> {
+        let variables = self.variables_.lock().unwrap();
+        variables
+            .named_variables
+            .iter()
+            .map(|(name, v)| (name.clone(), SharedTensor::new(v.shallow_clone())))
+            .collect()
+    }
+
     /// Gets the root path for this variable store.
     ///
     /// Variables are named and organized using paths. This function returns
@@ -150,14 +282,128 @@ impl VarStore {
     /// Saves the var-store variable values to a file.
     ///
     /// Weight values for all the tensors currently stored in the
-    /// var-store are saved in the given file.
+    /// var-store are saved in the given file. The values are first written
+    /// to a temporary file in the same directory which then replaces the
+    /// given file, so that an interrupted save does not corrupt an existing
+    /// checkpoint. Files with a `.safetensors` extension use the safetensors
+    /// format, the variables keep their kinds in both formats.
     pub fn save<T: AsRef<std::path::Path>>(&self, path: T) -> Result<(), TchError> {
         let variables = self.variables_.lock().unwrap();
         let named_tensors = variables.named_variables.iter().collect::<Vec<_>>();
-        match path.as_ref().extension().and_then(|x| x.to_str()) {
-            Some("safetensors") => Tensor::write_safetensors(named_tensors.as_slice(), path),
-            Some(_) | None => Tensor::save_multi(named_tensors.as_slice(), path),
+        let safetensors = is_safetensors(path.as_ref());
+        write_atomic(path.as_ref(), |tmp_path| {
+            if safetensors {
+                Tensor::write_safetensors(named_tensors.as_slice(), tmp_path)
+            } else {
+                Tensor::save_multi(named_tensors.as_slice(), tmp_path)
+            }
+        })
+    }
+
+    /// Saves the var-store variable values to a safetensors file along with
+    /// a metadata map, e.g. holding the epoch or a git hash, see `save`.
+    ///
+    /// The versions of tch and libtorch are added to the metadata under the
+    /// `tch_version` and `torch_version` keys. The metadata can be retrieved
+    /// with `read_metadata`.
+    pub fn save_with_metadata<T: AsRef<std::path::Path>>(
+        &self,
+        path: T,
+        metadata: &HashMap<String, String>,
+    ) -> Result<(), TchError> {
+        let path = path.as_ref();
+        if !is_safetensors(path) {
+            return Err(TchError::FileFormat(format!(
+                "metadata can only be saved in the safetensors format, got {path:?}"
+            )));
         }
+        let mut metadata = metadata.clone();
+        metadata.insert("tch_version".to_string(), env!("CARGO_PKG_VERSION").to_string());
+        metadata.insert("torch_version".to_string(), crate::utils::version_torch()?);
+        let variables = self.variables_.lock().unwrap();
+        let named_tensors = variables.named_variables.iter().collect::<Vec<_>>();
+        write_atomic(path, |tmp_path| {
+            Tensor::write_safetensors_with_metadata(named_tensors.as_slice(), &metadata, tmp_path)
+        })
+    }
+
+    /// Reads the metadata saved by `save_with_metadata` without loading the
+    /// tensors. The map is empty for files saved without metadata, including
+    /// all the files that are not in the safetensors format.
+    pub fn read_metadata<T: AsRef<std::path::Path>>(
+        path: T,
+    ) -> Result<HashMap<String, String>, TchError> {
+        if is_safetensors(path.as_ref()) {
+            Tensor::read_safetensors_metadata(path)
+        } else {
+            Ok(HashMap::new())
+        }
+    }
+
+    /// Saves the var-store in a new numbered checkpoint in `dir`, see
+    /// `save_versioned_with_metadata`.
+    pub fn save_versioned<T: AsRef<std::path::Path>>(
+        &self,
+        dir: T,
+        keep_last_n: usize,
+    ) -> Result<std::path::PathBuf, TchError> {
+        self.save_versioned_with_metadata(dir, keep_last_n, &HashMap::new())
+    }
+
+    /// Saves the var-store in a new numbered checkpoint in `dir`, e.g.
+    /// `checkpoint-000012.safetensors`, and returns its path.
+    ///
+    /// Only the last `keep_last_n` checkpoints are kept, at least one. The
+    /// name of the new checkpoint is written to the `latest` file of `dir`
+    /// once the checkpoint is complete, see `latest_checkpoint`. The
+    /// checkpoint number is added to the metadata under the `checkpoint` key.
+    pub fn save_versioned_with_metadata<T: AsRef<std::path::Path>>(
+        &self,
+        dir: T,
+        keep_last_n: usize,
+        metadata: &HashMap<String, String>,
+    ) -> Result<std::path::PathBuf, TchError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let existing = checkpoints(dir)?;
+        let index = existing.last().map_or(1, |(index, _)| index + 1);
+        let file_name = format!("{CHECKPOINT_PREFIX}{index:06}{CHECKPOINT_SUFFIX}");
+        let path = dir.join(&file_name);
+        let mut metadata = metadata.clone();
+        metadata.insert("checkpoint".to_string(), index.to_string());
+        self.save_with_metadata(&path, &metadata)?;
+        write_atomic(&dir.join(LATEST_CHECKPOINT), |tmp_path| {
+            Ok(std::fs::write(tmp_path, &file_name)?)
+        })?;
+        let n_removed = (existing.len() + 1).saturating_sub(keep_last_n.max(1));
+        for (_, old_path) in existing.iter().take(n_removed) {
+            std::fs::remove_file(old_path)?
+        }
+        Ok(path)
+    }
+
+    /// The path of the last checkpoint saved in `dir` by `save_versioned`,
+    /// or `None` if there is no checkpoint.
+    pub fn latest_checkpoint<T: AsRef<std::path::Path>>(
+        dir: T,
+    ) -> Result<Option<std::path::PathBuf>, TchError> {
+        let dir = dir.as_ref();
+        match std::fs::read_to_string(dir.join(LATEST_CHECKPOINT)) {
+            Ok(file_name) => {
+                let path = dir.join(file_name.trim());
+                if path.is_file() {
+                    return Ok(Some(path));
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+        // Falls back on the checkpoint with the largest number, e.g. when the
+        // `latest` file has been removed.
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+        Ok(checkpoints(dir)?.pop().map(|(_, path)| path))
     }
 
     /// Saves the var-store variable values to a stream.
@@ -170,7 +416,7 @@ impl VarStore {
         Tensor::save_multi_to_stream(named_tensors.as_slice(), stream)
     }
 
-    fn named_tensors<T: AsRef<std::path::Path>>(
+    pub(crate) fn named_tensors<T: AsRef<std::path::Path>>(
         &self,
         path: T,
     ) -> Result<HashMap<String, Tensor>, TchError> {
@@ -190,24 +436,64 @@ impl VarStore {
         dst.f_copy_(src)
     }
 
-    fn load_internal<T: AsRef<std::path::Path>>(&mut self, path: T) -> Result<(), TchError> {
-        let named_tensors = self.named_tensors(&path)?;
+    fn load_internal<T: AsRef<std::path::Path>>(
+        &mut self,
+        path: T,
+        rename: &dyn Fn(&str) -> Option<String>,
+        options: LoadOptions,
+    ) -> Result<LoadPartialReport, TchError> {
+        let path = path.as_ref();
+        let mut report = LoadPartialReport::default();
         let mut variables = self.variables_.lock().unwrap();
-        for (name, var) in variables.named_variables.iter_mut() {
-            match named_tensors.get(name) {
-                Some(src) => crate::no_grad(|| {
-                    Self::copy_data_with_precision_update(src, var)
-                        .map_err(|e| e.path_context(name))
+        // The original key of the file tensors indexed by their renamed key.
+        let mut keys: HashMap<String, String> = HashMap::new();
+        let mut load = |key: String, src: Tensor| {
+            let name = match rename(&key) {
+                None => return Ok(()),
+                Some(name) => name,
+            };
+            if let Some(other) = keys.get(&name) {
+                return Err(TchError::FileFormat(format!(
+                    "{other} and {key} are both renamed to {name} in {}",
+                    path.to_string_lossy()
+                )));
+            }
+            match variables.named_variables.get_mut(&name) {
+                Some(var) if options.strict || src.size() == var.size() => crate::no_grad(|| {
+                    Self::copy_data_with_precision_update(&src, var)
+                        .map_err(|e| e.path_context(&name))
                 })?,
-                None => {
-                    return Err(TchError::TensorNameNotFound(
-                        name.to_string(),
-                        path.as_ref().to_string_lossy().into_owned(),
-                    ));
-                }
+                Some(_) => report.shape_mismatch.push(name.clone()),
+                None => report.unexpected.push(key.clone()),
+            }
+            keys.insert(name, key);
+            Ok(())
+        };
+        if is_safetensors(path) {
+            // The tensors are loaded one at a time rather than reading the
+            // whole file in memory.
+            crate::tensor::safetensors::f_visit_safetensors(path, load)?
+        } else {
+            for (key, src) in self.named_tensors(path)? {
+                load(key, src)?
             }
         }
-        Ok(())
+        for name in variables.named_variables.keys() {
+            if keys.contains_key(name) {
+                continue;
+            }
+            if options.strict {
+                return Err(TchError::TensorNameNotFound(
+                    name.to_string(),
+                    path.to_string_lossy().into_owned(),
+                ));
+            }
+            report.missing.push(name.to_owned())
+        }
+        report.missing.sort();
+        report.unexpected.sort();
+        report.shape_mismatch.sort();
+        Ok(report)
     }
 
     /// Loads the var-store variable values from a file.
@@ -215,16 +501,76 @@ impl VarStore {
     /// Weight values for all the tensors currently stored in the
     /// var-store are loaded from the given file. Note that the set of
     /// variables stored in the var-store is not changed, only the values
-    /// for these tensors are modified.
+    /// for these tensors are modified, the variables take the kinds of the
+    /// file tensors. Files with a `.safetensors` extension are read one tensor
+    /// at a time.
     pub fn load<T: AsRef<std::path::Path>>(&mut self, path: T) -> Result<(), TchError> {
+        self.load_with_options(path, LoadOptions { strict: true })?;
+        Ok(())
+    }
+
+    /// Loads the var-store variable values from a file, see `LoadOptions`
+    /// for the handling of the variables missing from the file.
+    ///
+    /// The returned report lists the tensors of the file that are not
+    /// variables of the var-store, along with the missing variables and the
+    /// variables with a different shape in non-strict mode.
+    pub fn load_with_options<T: AsRef<std::path::Path>>(
+        &mut self,
+        path: T,
+        options: LoadOptions,
+    ) -> Result<LoadPartialReport, TchError> {
+        self.load_with_rename_and_options(path, |key| Some(key.to_string()), options)
+    }
+
+    /// Loads the var-store variable values from a file whose tensor names
+    /// differ from the variable names, e.g. because of a different prefix.
+    ///
+    /// `rename` maps the names of the file tensors to variable names, the
+    /// tensors for which it returns `None` are ignored. This fails if a
+    /// variable is missing as with `load`, the unexpected tensors of the
+    /// returned report use their names in the file.
+    ///
+    /// ```no_run
+    /// # let mut vs = tch::nn::VarStore::new(tch::Device::Cpu);
+    /// vs.load_with_rename("model.safetensors", |key| {
+    ///     key.strip_prefix("model.").map(|key| key.to_string())
+    /// })?;
+    /// # Ok::<(), tch::TchError>(())
+    /// ```
+    pub fn load_with_rename<T, F>(
+        &mut self,
+        path: T,
+        rename: F,
+    ) -> Result<LoadPartialReport, TchError>
+    where
+        T: AsRef<std::path::Path>,
+        F: Fn(&str) -> Option<String>,
+    {
+        self.load_with_rename_and_options(path, rename, LoadOptions { strict: true })
+    }
+
+    /// Loads the var-store variable values from a file, renaming the file
+    /// tensors as in `load_with_rename` and handling the missing variables
+    /// according to `options`.
+    pub fn load_with_rename_and_options<T, F>(
+        &mut self,
+        path: T,
+        rename: F,
+        options: LoadOptions,
+    ) -> Result<LoadPartialReport, TchError>
+    where
+        T: AsRef<std::path::Path>,
+        F: Fn(&str) -> Option<String>,
+    {
         if self.device != Device::Mps {
-            self.load_internal(path)
+            self.load_internal(path, &rename, options)
         } else {
             // Current workaround to allow loading in MPS device.
             // On new libtorch releases check if direct loading becomes possible and revert
             // See (https://github.com/LaurentMazare/tch-rs/issues/609#issuecomment-1427071598).
             self.set_device(Device::Cpu);
-            let or_error = self.load_internal(path);
+            let or_error = self.load_internal(path, &rename, options);
             // Be cautious not to early exit so as to ensure that the device is set back to Mps
             // even on errors.
             self.set_device(Device::Mps);
@@ -264,31 +610,18 @@ impl VarStore {
     ///
     /// Weight values for the tensors currently stored in the var-store and the given file get
     /// loaded from the given file. If a variable in the var store is not present in the given file,
-    /// it is skipped and its values are not updated. This method should be used if pre-trained
-    /// weight for only parts of the model are available.
+    /// or has a different shape there, it is skipped and its values are not updated. This method
+    /// should be used if pre-trained weight for only parts of the model are available.
     /// Note that the set of variables stored in the var-store is not changed, only the values
     /// for these tensors are modified.
     ///
-    /// Returns a String Vector containing the names of missing variables.
+    /// Returns a report listing the missing variables, the variables with a different shape and
+    /// the unexpected tensors of the file.
     pub fn load_partial<T: AsRef<std::path::Path>>(
         &mut self,
         path: T,
-    ) -> Result<Vec<String>, TchError> {
-        let named_tensors = self.named_tensors(&path)?;
-        let mut variables = self.variables_.lock().unwrap();
-        let mut missing_variables = Vec::new();
-        for (name, var) in variables.named_variables.iter_mut() {
-            match named_tensors.get(name) {
-                Some(src) => crate::no_grad(|| {
-                    Self::copy_data_with_precision_update(src, var)
-                        .map_err(|e| e.path_context(name))
-                })?,
-                None => {
-                    missing_variables.push(name.to_owned());
-                }
-            }
-        }
-        Ok(missing_variables)
+    ) -> Result<LoadPartialReport, TchError> {
+        self.load_with_options(path, LoadOptions { strict: false })
     }
 
     /// Freezes a var store.
@@ -302,6 +635,47 @@ impl VarStore {
         }
     }
 
+    // Sets the gradient tracking of the trainable variables under `prefix` and
+    // returns the number of these variables.
+    fn set_requires_grad_prefix(&self, prefix: &str, requires_grad: bool) -> usize {
+        let mut count = 0;
+        for (name, tensor) in self.trainable_variables_with_names() {
+            let under_prefix = prefix.is_empty()
+                || name.strip_prefix(prefix).is_some_and(|r| r.is_empty() || r.starts_with(SEP));
+            if under_prefix {
+                let _v = tensor.set_requires_grad(requires_grad);
+                count += 1
+            }
+        }
+        count
+    }
+
+    /// Freezes the trainable variables under a path prefix, e.g. `encoder` or
+    /// `encoder.layer1`, and returns the number of frozen variables.
+    ///
+    /// The prefix matches whole path components: `encoder` matches
+    /// `encoder.fc.weight` but not `encoder2.fc.weight`. An empty prefix
+    /// matches all the variables.
+    pub fn freeze_prefix(&mut self, prefix: &str) -> usize {
+        self.set_requires_grad_prefix(prefix, false)
+    }
+
+    /// Unfreezes the trainable variables under a path prefix and returns the
+    /// number of these variables, see `freeze_prefix`.
+    pub fn unfreeze_prefix(&mut self, prefix: &str) -> usize {
+        self.set_requires_grad_prefix(prefix, true)
+    }
+
+    /// Stops tracking the gradients of a single variable and removes it from
+    /// the trainable variables, so that it is not affected by `unfreeze` nor
+    /// by the optimizers created afterwards.
+    pub(crate) fn freeze_variable(&self, tensor: &Tensor) {
+        let mut variables = self.variables_.lock().unwrap();
+        let ptr = tensor.data_ptr();
+        variables.trainable_variables.retain(|v| v.tensor.data_ptr() != ptr);
+        let _v = tensor.set_requires_grad(false);
+    }
+
     /// Unfreezes a var store.
     ///
     /// Gradients for the variables in this store are tracked again.
@@ -400,6 +774,10 @@ impl<'a> Path<'a> {
         self.var_store.device
     }
 
+    pub(crate) fn var_store(&self) -> &'a VarStore {
+        self.var_store
+    }
+
     pub fn path(&self, name: &str) -> String {
         if name.chars().any(|x| x == SEP) {
             panic!("variable name cannot contain {SEP} {name}");
@@ -483,7 +861,7 @@ impl<'a> Path<'a> {
         };
         let tensor = if trainable { tensor.set_requires_grad(true) } else { tensor };
         if trainable {
-            let var = Var { tensor: tensor.shallow_clone(), group: self.group };
+            let var = Var { name: path.clone(), tensor: tensor.shallow_clone(), group: self.group };
             variables.trainable_variables.push(var);
         };
         variables.named_variables.insert(path, tensor.shallow_clone());
@@ -504,7 +882,7 @@ impl<'a> Path<'a> {
 
         let tensor = if trainable { tensor.set_requires_grad(true) } else { tensor };
         if trainable {
-            let var = Var { tensor: tensor.shallow_clone(), group: self.group };
+            let var = Var { name: path.clone(), tensor: tensor.shallow_clone(), group: self.group };
             variables.trainable_variables.push(var);
         }
         variables.named_variables.insert(path, tensor.shallow_clone());
@@ -0,0 +1,316 @@
+//! Magnitude pruning of layer weights.
+//!
+//! The masks returned by `magnitude_mask` are boolean tensors with the shape of
+//! the weight, `true` marking the weights that are kept. They can either be
+//! applied once with `apply_masks`, or used to train with the pruned weights
+//! held at zero by wrapping the layers in `PrunedLinear` or `PrunedConv2D`.
+//! Pruned output channels of a convolution can then be removed with
+//! `compact_conv2d` to get a smaller layer.
+//!
+//! ```no_run
+//! # use tch::{nn, prune, Device};
+//! let vs = nn::VarStore::new(Device::Cpu);
+//! let conv = nn::conv2d(vs.root() / "conv", 3, 16, 3, Default::default());
+//! let mask = prune::magnitude_mask(&conv.ws, 0.5, prune::Granularity::Channel);
+//! let conv = prune::PrunedConv2D::new(vs.root() / "conv", conv, &mask);
+//! ```
+use crate::nn::{self, Conv2D, Linear, Module, Path, VarStore};
+use crate::{Kind, TchError, Tensor};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+/// The weights pruned together by `magnitude_mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    /// Single weights, ranked by absolute value.
+    Elementwise,
+    /// Rows along the last dimension of the weight, ranked by L2 norm. For a
+    /// linear layer these are the weights of an output feature.
+    Row,
+    /// Slices along the first dimension of the weight, ranked by L2 norm. For
+    /// a convolution these are the weights of an output channel.
+    Channel,
+}
+
+/// Returns the mask pruning the fraction `sparsity` of the weights, or of the
+/// rows or channels, with the smallest magnitude. The mask is a boolean tensor
+/// with the shape of `weight` which is `false` for the pruned weights.
+///
+/// The number of pruned groups is `sparsity` times the number of groups
+/// rounded to the nearest integer, ties are broken by position.
+pub fn f_magnitude_mask(
+    weight: &Tensor,
+    sparsity: f64,
+    granularity: Granularity,
+) -> Result<Tensor, TchError> {
+    if !(0. ..=1.).contains(&sparsity) {
+        return Err(TchError::Shape(format!(
+            "magnitude mask: sparsity {sparsity} is not between 0 and 1"
+        )));
+    }
+    let size = weight.size();
+    if size.is_empty() && granularity != Granularity::Elementwise {
+        return Err(TchError::Shape(format!(
+            "magnitude mask: {granularity:?} pruning of a scalar weight"
+        )));
+    }
+    let _no_grad = crate::no_grad_guard();
+    let weight = weight.f_detach()?.f_to_kind(Kind::Double)?;
+    let scores = match granularity {
+        Granularity::Elementwise => weight.f_abs()?.f_reshape([-1])?,
+        Granularity::Row => {
+            let rows = weight.f_reshape([-1, size[size.len() - 1]])?;
+            rows.f_square()?.f_sum_dim_intlist(1, false, Kind::Double)?
+        }
+        Granularity::Channel => {
+            let channels = weight.f_reshape([size[0], -1])?;
+            channels.f_square()?.f_sum_dim_intlist(1, false, Kind::Double)?
+        }
+    };
+    let n = scores.numel() as i64;
+    let pruned = (sparsity * n as f64).round() as i64;
+    let order = scores.f_argsort_stable(true, 0, false)?;
+    let mut keep = Tensor::f_ones([n], (Kind::Bool, weight.device()))?;
+    let _ = keep.f_index_fill_(0, &order.f_narrow(0, 0, pruned)?, 0)?;
+    // Each kept group is expanded to the weights that it covers.
+    let group_numel = if n > 0 { weight.numel() as i64 / n } else { 0 };
+    keep.f_reshape([n, 1])?.f_expand([n, group_numel], false)?.f_reshape(size.as_slice())
+}
+
+/// Returns the mask pruning the weights with the smallest magnitude, see
+/// `f_magnitude_mask`.
+pub fn magnitude_mask(weight: &Tensor, sparsity: f64, granularity: Granularity) -> Tensor {
+    f_magnitude_mask(weight, sparsity, granularity).unwrap()
+}
+
+fn check_mask(weight: &Tensor, mask: &Tensor) -> Result<(), TchError> {
+    if weight.size() != mask.size() {
+        return Err(TchError::Shape(format!(
+            "prune: mask of shape {:?} for a weight of shape {:?}",
+            mask.size(),
+            weight.size()
+        )));
+    }
+    Ok(())
+}
+
+// Zeros the pruned weights in place.
+fn mask_weight(weight: &Tensor, mask: &Tensor) -> Result<(), TchError> {
+    check_mask(weight, mask)?;
+    let _no_grad = crate::no_grad_guard();
+    let mask = mask.f_to_device(weight.device())?;
+    let _ = weight.shallow_clone().f_mul_(&mask)?;
+    Ok(())
+}
+
+/// Zeros in place the variables of `vs` pruned by the masks, the masks being
+/// indexed by the variable names, e.g. `conv1.weight`.
+pub fn apply_masks(vs: &mut VarStore, masks: &HashMap<String, Tensor>) -> Result<(), TchError> {
+    let variables = vs.variables();
+    for (name, mask) in masks.iter() {
+        let weight = variables.get(name).ok_or_else(|| {
+            TchError::TensorNameNotFound(name.to_string(), "the var-store".to_string())
+        })?;
+        mask_weight(weight, mask)?
+    }
+    Ok(())
+}
+
+// Stores the mask of a layer weight in the var-store under `weight_mask`,
+// zeroing the pruned weights.
+fn add_mask(vs: &Path, weight: &Tensor, mask: &Tensor) -> Result<Tensor, TchError> {
+    check_mask(weight, mask)?;
+    let mask = mask.f_to_kind(Kind::Bool)?.f_to_device(weight.device())?.f_detach_copy()?;
+    mask_weight(weight, &mask)?;
+    Ok(vs.add("weight_mask", mask, false))
+}
+
+// The bias is masked for the outputs whose weights are all pruned, so that
+// pruned outputs are zero.
+fn masked_bias(bs: Option<&Tensor>, mask: &Tensor) -> Result<Option<Tensor>, TchError> {
+    match bs {
+        None => Ok(None),
+        Some(bs) => {
+            let kept = mask.f_reshape([mask.size()[0], -1])?.f_any_dim(1, false)?;
+            Ok(Some(bs.f_mul(&kept)?))
+        }
+    }
+}
+
+/// A linear layer with pruned weights. The pruned weights are zero in the
+/// forward pass and so get zero gradients, the bias of an output feature with
+/// all its weights pruned is zero too.
+#[derive(Debug)]
+pub struct PrunedLinear {
+    pub base: Linear,
+    pub mask: Tensor,
+}
+
+impl PrunedLinear {
+    /// Wraps `linear`, a layer created under the path `vs`. The mask is
+    /// stored as a non-trainable variable under `weight_mask` and the pruned
+    /// weights are set to zero.
+    pub fn f_new<'a, T: Borrow<Path<'a>>>(
+        vs: T,
+        linear: Linear,
+        mask: &Tensor,
+    ) -> Result<PrunedLinear, TchError> {
+        let mask = add_mask(vs.borrow(), &linear.ws, mask)?;
+        Ok(PrunedLinear { base: linear, mask })
+    }
+
+    /// Wraps `linear`, see `f_new`.
+    pub fn new<'a, T: Borrow<Path<'a>>>(vs: T, linear: Linear, mask: &Tensor) -> PrunedLinear {
+        PrunedLinear::f_new(vs, linear, mask).unwrap()
+    }
+
+    /// The fraction of pruned weights.
+    pub fn sparsity(&self) -> f64 {
+        1. - f64::try_from(self.mask.mean(Kind::Double)).unwrap()
+    }
+}
+
+impl Module for PrunedLinear {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let ws = self.base.ws.f_mul(&self.mask)?;
+        let bs = masked_bias(self.base.bs.as_ref(), &self.mask)?;
+        xs.f_linear(&ws, bs.as_ref())
+    }
+}
+
+/// A two dimensional convolution layer with pruned weights, see
+/// `PrunedLinear`.
+#[derive(Debug)]
+pub struct PrunedConv2D {
+    pub base: Conv2D,
+    pub mask: Tensor,
+}
+
+impl PrunedConv2D {
+    /// Wraps `conv`, a layer created under the path `vs`. The mask is stored
+    /// as a non-trainable variable under `weight_mask` and the pruned weights
+    /// are set to zero.
+    pub fn f_new<'a, T: Borrow<Path<'a>>>(
+        vs: T,
+        conv: Conv2D,
+        mask: &Tensor,
+    ) -> Result<PrunedConv2D, TchError> {
+        let mask = add_mask(vs.borrow(), &conv.ws, mask)?;
+        Ok(PrunedConv2D { base: conv, mask })
+    }
+
+    /// Wraps `conv`, see `f_new`.
+    pub fn new<'a, T: Borrow<Path<'a>>>(vs: T, conv: Conv2D, mask: &Tensor) -> PrunedConv2D {
+        PrunedConv2D::f_new(vs, conv, mask).unwrap()
+    }
+
+    /// The fraction of pruned weights.
+    pub fn sparsity(&self) -> f64 {
+        1. - f64::try_from(self.mask.mean(Kind::Double)).unwrap()
+    }
+
+    /// The output channels with at least one weight kept, to be passed to
+    /// `compact_conv2d`.
+    pub fn kept_channels(&self) -> Result<Vec<i64>, TchError> {
+        kept_channels(&self.mask)
+    }
+}
+
+impl Module for PrunedConv2D {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let ws = self.base.ws.f_mul(&self.mask)?;
+        let bs = masked_bias(self.base.bs.as_ref(), &self.mask)?;
+        self.base.f_forward_with(xs, &ws, bs.as_ref())
+    }
+}
+
+/// The indexes of the slices along the first dimension of `mask` with at
+/// least one weight kept, i.e. the output channels of a convolution that are
+/// not entirely pruned.
+pub fn kept_channels(mask: &Tensor) -> Result<Vec<i64>, TchError> {
+    if mask.dim() == 0 {
+        return Err(TchError::Shape("prune: no channels in a scalar mask".to_string()));
+    }
+    let kept = mask.f_reshape([mask.size()[0], -1])?.f_any_dim(1, false)?;
+    Vec::<i64>::try_from(kept.f_nonzero()?.f_reshape([-1])?)
+}
+
+fn check_conv(conv: &Conv2D) -> Result<(), TchError> {
+    if conv.config().groups != 1 {
+        return Err(TchError::Shape(format!(
+            "prune: cannot compact a convolution with {} groups",
+            conv.config().groups
+        )));
+    }
+    Ok(())
+}
+
+// Creates a convolution under `vs` with the config of `conv` and the given
+// weights.
+fn conv_with_weights(
+    vs: &Path,
+    conv: &Conv2D,
+    ws: &Tensor,
+    bs: Option<&Tensor>,
+) -> Result<Conv2D, TchError> {
+    let size = ws.size();
+    let config = nn::ConvConfigND { bias: bs.is_some(), ..*conv.config() };
+    let compact = nn::conv(vs, size[1], size[0], [size[2], size[3]], config);
+    let _no_grad = crate::no_grad_guard();
+    compact.ws.shallow_clone().f_copy_(ws)?;
+    if let (Some(dst), Some(src)) = (compact.bs.as_ref(), bs) {
+        dst.shallow_clone().f_copy_(src)?
+    }
+    Ok(compact)
+}
+
+/// Creates under `vs` a convolution with only the `kept_channels` output
+/// channels of `conv`, and returns it along with the index map of these
+/// channels, an int64 tensor to be passed to `compact_conv2d_inputs` for the
+/// next layer.
+pub fn compact_conv2d(
+    vs: &Path,
+    conv: &Conv2D,
+    kept_channels: &[i64],
+) -> Result<(Conv2D, Tensor), TchError> {
+    check_conv(conv)?;
+    let out_channels = conv.ws.size()[0];
+    let mut sorted = kept_channels.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if kept_channels.is_empty()
+        || sorted.len() != kept_channels.len()
+        || kept_channels.iter().any(|&c| c < 0 || c >= out_channels)
+    {
+        return Err(TchError::Shape(format!(
+            "prune: invalid channels {kept_channels:?} for a convolution with {out_channels} output channels"
+        )));
+    }
+    let index_map = Tensor::f_from_slice(kept_channels)?.f_to_device(conv.ws.device())?;
+    let ws = conv.ws.f_index_select(0, &index_map)?;
+    let bs = conv.bs.as_ref().map(|bs| bs.f_index_select(0, &index_map)).transpose()?;
+    let compact = conv_with_weights(vs, conv, &ws, bs.as_ref())?;
+    Ok((compact, index_map))
+}
+
+/// Creates under `vs` a convolution with the input channels of `conv` in
+/// `index_map`, the index map returned by `compact_conv2d` for the previous
+/// layer.
+pub fn compact_conv2d_inputs(
+    vs: &Path,
+    conv: &Conv2D,
+    index_map: &Tensor,
+) -> Result<Conv2D, TchError> {
+    check_conv(conv)?;
+    let index_map = index_map.f_to_device(conv.ws.device())?;
+    let ws = conv.ws.f_index_select(1, &index_map)?;
+    conv_with_weights(vs, conv, &ws, conv.bs.as_ref())
+}
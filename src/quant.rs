@@ -0,0 +1,263 @@
+//! Group-wise weight quantization for inference.
+//!
+//! The weights of a linear layer are split in groups of `group_size`
+//! consecutive input features, each group is quantized to 8 or 4 bits with its
+//! own scale and, for asymmetric quantization, its own zero point. 4-bit
+//! values are packed two per byte, the low nibble holding the even feature.
+//!
+//! ```no_run
+//! # use tch::{nn, quant, Device, Tensor, Kind};
+//! let vs = nn::VarStore::new(Device::Cpu);
+//! let linear = nn::linear(vs.root() / "fc", 4096, 4096, Default::default());
+//! let qvs = nn::VarStore::new(Device::Cpu);
+//! let config = quant::QuantConfig { bits: 4, ..Default::default() };
+//! let qlinear = quant::QLinear::from_linear(qvs.root() / "fc", &linear, config);
+//! let ys = nn::Module::forward(&qlinear, &Tensor::randn([1, 4096], (Kind::Float, Device::Cpu)));
+//! ```
+use crate::nn::{self, Module, Path};
+use crate::{Kind, TchError, Tensor};
+use std::borrow::Borrow;
+
+/// The quantization settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantConfig {
+    /// The number of bits per weight, either 8 or 4.
+    pub bits: u8,
+    /// The number of consecutive input features sharing a scale, this has to
+    /// divide the number of input features.
+    pub group_size: i64,
+    /// Symmetric quantization maps zero to zero and does not use zero points.
+    pub symmetric: bool,
+}
+
+impl Default for QuantConfig {
+    fn default() -> Self {
+        QuantConfig { bits: 8, group_size: 128, symmetric: true }
+    }
+}
+
+impl QuantConfig {
+    // Returns the number of output and input features of a weight.
+    fn check(&self, shape: &[i64]) -> Result<(i64, i64), TchError> {
+        if self.bits != 8 && self.bits != 4 {
+            return Err(TchError::Kind(format!("unsupported quantization bits {}", self.bits)));
+        }
+        let (out_dim, in_dim) = match shape {
+            [out_dim, in_dim] => (*out_dim, *in_dim),
+            _ => return Err(TchError::Shape(format!("expected a 2d weight, got {shape:?}"))),
+        };
+        let group_size = self.group_size;
+        if group_size <= 0 || in_dim % group_size != 0 || (self.bits == 4 && group_size % 2 != 0) {
+            return Err(TchError::Shape(format!(
+                "invalid group size {group_size} for {in_dim} input features and {} bits",
+                self.bits
+            )));
+        }
+        Ok((out_dim, in_dim))
+    }
+
+    fn levels(&self) -> i64 {
+        1 << self.bits
+    }
+}
+
+/// A quantized weight of shape `[out_dim, in_dim]`.
+#[derive(Debug)]
+pub struct QuantizedWeight {
+    /// The quantized values as a `Uint8` tensor of shape `[out_dim, in_dim]`,
+    /// or `[out_dim, in_dim / 2]` for 4 bits.
+    pub packed: Tensor,
+    /// The `Float` scales, one per group, of shape `[out_dim, in_dim / group_size]`.
+    pub scales: Tensor,
+    /// The `Uint8` zero points for asymmetric quantization, with the same
+    /// shape as the scales.
+    pub zero_points: Option<Tensor>,
+    pub config: QuantConfig,
+}
+
+// Packs pairs of 4-bit values along the last dimension in single bytes.
+fn pack4(q: &Tensor) -> Result<Tensor, TchError> {
+    let (out_dim, in_dim) = q.size2()?;
+    let q = q.f_to_kind(Kind::Int64)?.f_reshape([out_dim, in_dim / 2, 2])?;
+    let low = q.f_select(-1, 0)?;
+    let high = q.f_select(-1, 1)?;
+    low.f_add(&high.f_mul_scalar(16)?)?.f_to_kind(Kind::Uint8)
+}
+
+fn unpack4(packed: &Tensor) -> Result<Tensor, TchError> {
+    let (out_dim, in_dim) = packed.size2()?;
+    let packed = packed.f_to_kind(Kind::Int64)?;
+    let low = packed.f_bitwise_and(15)?;
+    let high = packed.f_bitwise_right_shift_tensor_scalar(4)?;
+    Tensor::f_stack(&[low, high], -1)?.f_reshape([out_dim, in_dim * 2])
+}
+
+/// Quantizes a weight of shape `[out_dim, in_dim]` group-wise.
+pub fn f_quantize_weights(
+    weight: &Tensor,
+    config: QuantConfig,
+) -> Result<QuantizedWeight, TchError> {
+    let (out_dim, in_dim) = config.check(&weight.size())?;
+    let groups = in_dim / config.group_size;
+    let _no_grad = crate::no_grad_guard();
+    let ws = weight.f_to_kind(Kind::Float)?.f_reshape([out_dim, groups, config.group_size])?;
+    let qmax = config.levels() - 1;
+    let (q, scales, zero_points) = if config.symmetric {
+        // The values are stored with an offset of half the levels, the range
+        // is kept symmetric so that zero is exactly representable.
+        let half = config.levels() / 2;
+        let scales = ws.f_abs()?.f_amax(-1, true)?.f_div_scalar(half - 1)?.f_clamp_min(1e-12)?;
+        let q = ws.f_div(&scales)?.f_round()?.f_clamp(1 - half, half - 1)?.f_add_scalar(half)?;
+        (q, scales, None)
+    } else {
+        // The range is extended to include zero so that it is exactly
+        // representable.
+        let min = ws.f_amin(-1, true)?.f_clamp_max(0.)?;
+        let max = ws.f_amax(-1, true)?.f_clamp_min(0.)?;
+        let scales = max.f_sub(&min)?.f_div_scalar(qmax)?.f_clamp_min(1e-12)?;
+        let zero_points = min.f_neg()?.f_div(&scales)?.f_round()?.f_clamp(0, qmax)?;
+        let q = ws.f_div(&scales)?.f_round()?.f_add(&zero_points)?.f_clamp(0, qmax)?;
+        (q, scales, Some(zero_points))
+    };
+    let q = q.f_reshape([out_dim, in_dim])?.f_to_kind(Kind::Uint8)?;
+    let packed = if config.bits == 4 { pack4(&q)? } else { q };
+    let zero_points = match zero_points {
+        Some(zp) => Some(zp.f_squeeze_dim(-1)?.f_to_kind(Kind::Uint8)?),
+        None => None,
+    };
+    Ok(QuantizedWeight { packed, scales: scales.f_squeeze_dim(-1)?, zero_points, config })
+}
+
+/// Quantizes a weight of shape `[out_dim, in_dim]` group-wise.
+pub fn quantize_weights(weight: &Tensor, config: QuantConfig) -> QuantizedWeight {
+    f_quantize_weights(weight, config).unwrap()
+}
+
+impl QuantizedWeight {
+    /// The shape of the original weight, `[out_dim, in_dim]`.
+    pub fn shape(&self) -> Result<(i64, i64), TchError> {
+        let (out_dim, packed_dim) = self.packed.size2()?;
+        let in_dim = if self.config.bits == 4 { packed_dim * 2 } else { packed_dim };
+        Ok((out_dim, in_dim))
+    }
+
+    /// Returns the `Float` weight approximated by the quantized values.
+    pub fn f_dequantize(&self) -> Result<Tensor, TchError> {
+        let (out_dim, in_dim) = self.shape()?;
+        let q = if self.config.bits == 4 {
+            unpack4(&self.packed)?
+        } else {
+            self.packed.shallow_clone()
+        };
+        let groups = in_dim / self.config.group_size;
+        let q = q.f_to_kind(Kind::Float)?.f_reshape([out_dim, groups, self.config.group_size])?;
+        let q = match self.zero_points.as_ref() {
+            Some(zp) => q.f_sub(&zp.f_to_kind(Kind::Float)?.f_unsqueeze(-1)?)?,
+            None => q.f_sub_scalar(self.config.levels() / 2)?,
+        };
+        q.f_mul(&self.scales.f_unsqueeze(-1)?)?.f_reshape([out_dim, in_dim])
+    }
+
+    /// Returns the `Float` weight approximated by the quantized values.
+    pub fn dequantize(&self) -> Tensor {
+        self.f_dequantize().unwrap()
+    }
+
+    /// The memory used by the packed values, the scales, and the zero points.
+    pub fn size_in_bytes(&self) -> usize {
+        let size = |t: &Tensor| t.numel() * t.kind().elt_size_in_bytes();
+        size(&self.packed) + size(&self.scales) + self.zero_points.as_ref().map_or(0, size)
+    }
+}
+
+/// A linear layer with a quantized weight, the weight is dequantized on the
+/// fly in the forward pass.
+///
+/// The packed values, scales, zero points, and bias are stored in the var
+/// store as non-trainable variables so they can be saved and loaded.
+#[derive(Debug)]
+pub struct QLinear {
+    pub weight: QuantizedWeight,
+    pub bs: Option<Tensor>,
+}
+
+/// Creates a quantized linear layer with zero weights, the actual weights are
+/// expected to be loaded from a var store file.
+pub fn qlinear<'a, T: Borrow<Path<'a>>>(
+    vs: T,
+    in_dim: i64,
+    out_dim: i64,
+    config: QuantConfig,
+    bias: bool,
+) -> QLinear {
+    let vs = vs.borrow();
+    config.check(&[out_dim, in_dim]).unwrap();
+    let device = vs.device();
+    let packed_dim = if config.bits == 4 { in_dim / 2 } else { in_dim };
+    let groups = in_dim / config.group_size;
+    let packed =
+        vs.add("packed", Tensor::zeros([out_dim, packed_dim], (Kind::Uint8, device)), false);
+    let scales = vs.zeros_no_train("scales", &[out_dim, groups]);
+    let zero_points = if config.symmetric {
+        None
+    } else {
+        Some(vs.add("zero_points", Tensor::zeros([out_dim, groups], (Kind::Uint8, device)), false))
+    };
+    let bs = if bias { Some(vs.zeros_no_train("bias", &[out_dim])) } else { None };
+    QLinear { weight: QuantizedWeight { packed, scales, zero_points, config }, bs }
+}
+
+impl QLinear {
+    /// Quantizes the weight of a linear layer, the quantized weight and the
+    /// bias are added to the var store.
+    pub fn f_from_linear<'a, T: Borrow<Path<'a>>>(
+        vs: T,
+        linear: &nn::Linear,
+        config: QuantConfig,
+    ) -> Result<QLinear, TchError> {
+        let vs = vs.borrow();
+        let device = vs.device();
+        let weight = f_quantize_weights(&linear.ws, config)?;
+        let packed = vs.add("packed", weight.packed.f_to_device(device)?, false);
+        let scales = vs.add("scales", weight.scales.f_to_device(device)?, false);
+        let zero_points = match weight.zero_points {
+            Some(zp) => Some(vs.add("zero_points", zp.f_to_device(device)?, false)),
+            None => None,
+        };
+        let bs = match linear.bs.as_ref() {
+            Some(bs) => Some(vs.add("bias", bs.f_detach_copy()?.f_to_device(device)?, false)),
+            None => None,
+        };
+        Ok(QLinear { weight: QuantizedWeight { packed, scales, zero_points, config }, bs })
+    }
+
+    /// Quantizes the weight of a linear layer, the quantized weight and the
+    /// bias are added to the var store.
+    pub fn from_linear<'a, T: Borrow<Path<'a>>>(
+        vs: T,
+        linear: &nn::Linear,
+        config: QuantConfig,
+    ) -> QLinear {
+        Self::f_from_linear(vs, linear, config).unwrap()
+    }
+}
+
+impl Module for QLinear {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.f_forward(xs).unwrap()
+    }
+
+    fn f_forward(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        let ws = self.weight.f_dequantize()?.f_to_kind(xs.kind())?;
+        let bs = match self.bs.as_ref() {
+            Some(bs) => Some(bs.f_to_kind(xs.kind())?),
+            None => None,
+        };
+        let ys = xs.f_linear(&ws, bs.as_ref())?;
+        let params = [Some(&self.weight.packed), Some(&self.weight.scales), self.bs.as_ref()];
+        nn::analysis::record_layer("qlinear", &ys, &params, || {
+            nn::analysis::conv_flops(ys.numel(), &ws)
+        });
+        Ok(ys)
+    }
+}
@@ -0,0 +1,289 @@
+//! Raw bytes serialization for tensors.
+//!
+//! This implements a minimal framed binary format, convenient to send tensors
+//! over the network. A payload starts with a header:
+//!
+//! - the magic string `TCHB`,
+//! - the format version as a single byte,
+//! - the element kind as a single byte,
+//! - the number of dimensions as a little-endian u32,
+//! - each dimension as a little-endian u64,
+//!
+//! followed by the tensor elements in contiguous, row-major, little-endian order.
+use crate::{Device, Kind, TchError, Tensor};
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"TCHB";
+const VERSION: u8 = 1;
+// Limit the number of dimensions so that a malformed header cannot trigger a
+// large allocation.
+const MAX_DIMS: usize = 64;
+
+// These codes are part of the format so they should never be changed. They are
+// intentionally decoupled from the c10 scalar type values.
+fn kind_to_code(kind: Kind) -> Result<u8, TchError> {
+    let code = match kind {
+        Kind::Uint8 => 0,
+        Kind::Int8 => 1,
+        Kind::Int16 => 2,
+        Kind::Int => 3,
+        Kind::Int64 => 4,
+        Kind::Half => 5,
+        Kind::Float => 6,
+        Kind::Double => 7,
+        Kind::ComplexHalf => 8,
+        Kind::ComplexFloat => 9,
+        Kind::ComplexDouble => 10,
+        Kind::Bool => 11,
+        Kind::BFloat16 => 12,
+        Kind::QInt8 | Kind::QUInt8 | Kind::QInt32 => {
+            return Err(TchError::Kind(format!("unsupported kind for serialization {kind:?}")))
+        }
+    };
+    Ok(code)
+}
+
+fn kind_of_code(code: u8) -> Result<Kind, TchError> {
+    let kind = match code {
+        0 => Kind::Uint8,
+        1 => Kind::Int8,
+        2 => Kind::Int16,
+        3 => Kind::Int,
+        4 => Kind::Int64,
+        5 => Kind::Half,
+        6 => Kind::Float,
+        7 => Kind::Double,
+        8 => Kind::ComplexHalf,
+        9 => Kind::ComplexFloat,
+        10 => Kind::ComplexDouble,
+        11 => Kind::Bool,
+        12 => Kind::BFloat16,
+        _ => return Err(TchError::FileFormat(format!("unknown kind code {code}"))),
+    };
+    Ok(kind)
+}
+
+#[derive(Debug, PartialEq)]
+struct Header {
+    kind: Kind,
+    shape: Vec<i64>,
+}
+
+impl Header {
+    fn write<W: Write>(&self, w: &mut W) -> Result<(), TchError> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION, kind_to_code(self.kind)?])?;
+        w.write_all(&(self.shape.len() as u32).to_le_bytes())?;
+        for &d in self.shape.iter() {
+            w.write_all(&(d as u64).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(r: &mut R) -> Result<Header, TchError> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(TchError::FileFormat("magic string mismatch".to_string()));
+        }
+        let mut version_and_kind = [0u8; 2];
+        r.read_exact(&mut version_and_kind)?;
+        if version_and_kind[0] != VERSION {
+            return Err(TchError::FileFormat(format!(
+                "unsupported version {}",
+                version_and_kind[0]
+            )));
+        }
+        let kind = kind_of_code(version_and_kind[1])?;
+        let mut ndims = [0u8; 4];
+        r.read_exact(&mut ndims)?;
+        let ndims = u32::from_le_bytes(ndims) as usize;
+        if ndims > MAX_DIMS {
+            return Err(TchError::FileFormat(format!("too many dimensions {ndims}")));
+        }
+        let mut shape = Vec::with_capacity(ndims);
+        for _ in 0..ndims {
+            let mut dim = [0u8; 8];
+            r.read_exact(&mut dim)?;
+            let dim = u64::from_le_bytes(dim);
+            let dim = i64::try_from(dim)
+                .map_err(|_| TchError::FileFormat(format!("invalid dimension {dim}")))?;
+            shape.push(dim)
+        }
+        Ok(Header { kind, shape })
+    }
+
+    // Returns the number of data bytes that follow the header, checking for overflows.
+    fn data_len(&self) -> Result<usize, TchError> {
+        let overflow = || TchError::FileFormat(format!("shape overflow {:?}", self.shape));
+        let numel = self.shape.iter().try_fold(1usize, |acc, &d| {
+            usize::try_from(d).ok().and_then(|d| acc.checked_mul(d)).ok_or_else(overflow)
+        })?;
+        numel.checked_mul(self.kind.elt_size_in_bytes()).ok_or_else(overflow)
+    }
+}
+
+// Size in bytes of the scalar components that have to be byte swapped when
+// converting from/to little-endian, complex values are handled component-wise.
+#[cfg(target_endian = "big")]
+fn swap_size(kind: Kind) -> usize {
+    match kind {
+        Kind::ComplexHalf | Kind::ComplexFloat | Kind::ComplexDouble => {
+            kind.elt_size_in_bytes() / 2
+        }
+        _ => kind.elt_size_in_bytes(),
+    }
+}
+
+#[cfg(target_endian = "big")]
+fn swap_bytes(data: &mut [u8], kind: Kind) {
+    let swap_size = swap_size(kind);
+    if swap_size > 1 {
+        data.chunks_exact_mut(swap_size).for_each(|c| c.reverse())
+    }
+}
+
+impl Tensor {
+    /// Writes the tensor to a stream using the raw bytes format.
+    ///
+    /// Contiguous CPU tensors are written directly from their storage, other
+    /// tensors are first copied to a contiguous CPU buffer.
+    pub fn write_le_bytes<W: Write>(&self, w: &mut W) -> Result<(), TchError> {
+        let kind = self.f_kind()?;
+        let header = Header { kind, shape: self.size() };
+        let data_len = header.data_len()?;
+        header.write(w)?;
+        if data_len == 0 {
+            return Ok(());
+        }
+        if cfg!(target_endian = "little") && self.device() == Device::Cpu && self.is_contiguous() {
+            // Safety: the tensor is contiguous and on the cpu so its storage holds
+            // data_len bytes starting at data_ptr, this storage is kept alive by
+            // the borrow on self.
+            let data =
+                unsafe { std::slice::from_raw_parts(self.data_ptr() as *const u8, data_len) };
+            w.write_all(data)?;
+        } else {
+            #[allow(unused_mut)]
+            let mut data = vec![0u8; data_len];
+            self.f_copy_data_u8(&mut data, self.numel())?;
+            #[cfg(target_endian = "big")]
+            swap_bytes(&mut data, kind);
+            w.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the tensor using the raw bytes format.
+    pub fn f_to_le_bytes(&self) -> Result<Vec<u8>, TchError> {
+        let mut buffer = vec![];
+        self.write_le_bytes(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Serializes the tensor using the raw bytes format. Panics on quantized
+    /// tensors.
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        self.f_to_le_bytes().unwrap()
+    }
+
+    /// Reads a tensor using the raw bytes format from a stream.
+    ///
+    /// The stream is only consumed up to the end of the tensor data so that
+    /// multiple tensors can be read from the same stream.
+    pub fn read_le_bytes<R: Read>(r: &mut R) -> Result<Tensor, TchError> {
+        let header = Header::read(r)?;
+        let data_len = header.data_len()?;
+        // Avoid pre-allocating a buffer based on a potentially malformed header.
+        let mut data = vec![];
+        r.take(data_len as u64).read_to_end(&mut data)?;
+        if data.len() != data_len {
+            return Err(TchError::FileFormat(format!(
+                "unexpected end of data, expected {data_len} bytes, got {}",
+                data.len()
+            )));
+        }
+        #[cfg(target_endian = "big")]
+        swap_bytes(&mut data, header.kind);
+        Tensor::f_from_data_size(&data, &header.shape, header.kind)
+    }
+
+    /// Deserializes a tensor using the raw bytes format.
+    ///
+    /// An error is returned if the length of `data` does not exactly match the
+    /// header.
+    pub fn from_le_bytes(data: &[u8]) -> Result<Tensor, TchError> {
+        let mut reader = data;
+        let header = Header::read(&mut reader)?;
+        let data_len = header.data_len()?;
+        if reader.len() != data_len {
+            return Err(TchError::FileFormat(format!(
+                "data length mismatch, expected {data_len} bytes, got {}",
+                reader.len()
+            )));
+        }
+        #[cfg(target_endian = "big")]
+        let swapped = {
+            let mut swapped = reader.to_vec();
+            swap_bytes(&mut swapped, header.kind);
+            swapped
+        };
+        #[cfg(target_endian = "big")]
+        let reader = swapped.as_slice();
+        Tensor::f_from_data_size(reader, &header.shape, header.kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Header, MAGIC, VERSION};
+    use crate::Kind;
+
+    fn header_bytes(version: u8, kind: u8, shape: &[u64]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version);
+        bytes.push(kind);
+        bytes.extend_from_slice(&(shape.len() as u32).to_le_bytes());
+        for d in shape.iter() {
+            bytes.extend_from_slice(&d.to_le_bytes())
+        }
+        bytes
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let h = Header { kind: Kind::Float, shape: vec![2, 0, 3] };
+        let mut bytes = vec![];
+        h.write(&mut bytes).unwrap();
+        assert_eq!(bytes, header_bytes(VERSION, 6, &[2, 0, 3]));
+        let h2 = Header::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(h, h2);
+        assert_eq!(h2.data_len().unwrap(), 0);
+        let h = Header { kind: Kind::QInt8, shape: vec![] };
+        assert!(h.write(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut bytes = header_bytes(VERSION, 6, &[2]);
+        bytes[0] = b'X';
+        assert!(Header::read(&mut bytes.as_slice()).is_err());
+        let bytes = header_bytes(VERSION + 1, 6, &[2]);
+        assert!(Header::read(&mut bytes.as_slice()).is_err());
+        let bytes = header_bytes(VERSION, 42, &[2]);
+        assert!(Header::read(&mut bytes.as_slice()).is_err());
+        let bytes = header_bytes(VERSION, 6, &[u64::MAX]);
+        assert!(Header::read(&mut bytes.as_slice()).is_err());
+        let bytes = header_bytes(VERSION, 6, &[1 << 40, 1 << 40]);
+        let h = Header::read(&mut bytes.as_slice()).unwrap();
+        assert!(h.data_len().is_err());
+        let mut bytes = header_bytes(VERSION, 6, &[]);
+        bytes[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Header::read(&mut bytes.as_slice()).is_err());
+        // Truncated headers.
+        let bytes = header_bytes(VERSION, 6, &[3, 4]);
+        for len in 0..bytes.len() {
+            assert!(Header::read(&mut &bytes[..len]).is_err());
+        }
+    }
+}
@@ -1,8 +1,9 @@
 //! Implement conversion traits for tensors
 use super::Tensor;
-use crate::{kind::Element, TchError};
+use crate::{kind::Element, Device, TchError};
 use half::{bf16, f16};
-use std::convert::{TryFrom, TryInto};
+use ndarray::ShapeBuilder;
+use std::convert::TryFrom;
 
 impl<T: Element + Copy> TryFrom<&Tensor> for Vec<T> {
     type Error = TchError;
@@ -122,18 +123,100 @@ from_tensor!(u8);
 from_tensor!(bool);
 from_tensor!(bf16);
 
-impl<T: Element + Copy> TryInto<ndarray::ArrayD<T>> for &Tensor {
+impl<T: Element> TryFrom<&Tensor> for ndarray::ArrayD<T> {
     type Error = TchError;
 
-    fn try_into(self) -> Result<ndarray::ArrayD<T>, Self::Error> {
-        let num_elem = self.numel();
-        let mut vec = vec![T::ZERO; num_elem];
-        self.f_to_kind(T::KIND)?.f_copy_data(&mut vec, num_elem)?;
-        let shape: Vec<usize> = self.size().iter().map(|s| *s as usize).collect();
-        Ok(ndarray::ArrayD::from_shape_vec(ndarray::IxDyn(&shape), vec)?)
+    /// Copies the data of a CPU tensor to an array, the kind of the tensor has
+    /// to match the element type.
+    fn try_from(tensor: &Tensor) -> Result<Self, Self::Error> {
+        let device = tensor.device();
+        if device != Device::Cpu {
+            return Err(TchError::Convert(format!(
+                "cannot convert a tensor on {device:?} to an array, the tensor should be on the cpu"
+            )));
+        }
+        let kind = tensor.f_kind()?;
+        if kind != T::KIND {
+            return Err(TchError::Kind(format!(
+                "cannot convert a tensor of kind {kind:?} to an array of {:?}",
+                T::KIND
+            )));
+        }
+        let size: Vec<usize> = tensor.size().iter().map(|&s| s as usize).collect();
+        if tensor.numel() == 0 {
+            return Ok(ndarray::ArrayD::from_shape_vec(size, vec![])?);
+        }
+        let strides: Vec<usize> = tensor.stride().iter().map(|&s| s as usize).collect();
+        let shape = ndarray::IxDyn(&size).strides(ndarray::IxDyn(&strides));
+        // The view is only used to copy the data, following the strides of the
+        // tensor, while the tensor is borrowed.
+        let view =
+            unsafe { ndarray::ArrayViewD::from_shape_ptr(shape, tensor.data_ptr() as *const T) };
+        Ok(view.to_owned())
+    }
+}
+
+impl<T: Element> TryFrom<Tensor> for ndarray::ArrayD<T> {
+    type Error = TchError;
+
+    fn try_from(tensor: Tensor) -> Result<Self, Self::Error> {
+        ndarray::ArrayD::<T>::try_from(&tensor)
+    }
+}
+
+extern "C" fn drop_boxed<A>(ptr: *mut libc::c_void) {
+    drop(unsafe { Box::from_raw(ptr as *mut A) })
+}
+
+impl Tensor {
+    /// Converts an array to a CPU tensor without copying the data, the tensor
+    /// takes ownership of the array which is dropped with the tensor storage.
+    /// Arrays with negative strides are copied to the standard layout first.
+    pub fn f_from_ndarray<T, D>(array: ndarray::Array<T, D>) -> Result<Tensor, TchError>
+    where
+        T: Element + Send,
+        D: ndarray::Dimension,
+    {
+        let size: Vec<i64> = array.shape().iter().map(|&s| s as i64).collect();
+        if array.is_empty() {
+            return Tensor::f_empty(size, (T::KIND, Device::Cpu));
+        }
+        let array = if array.strides().iter().any(|&s| s < 0) {
+            array.as_standard_layout().into_owned()
+        } else {
+            array
+        };
+        let strides: Vec<i64> = array.strides().iter().map(|&s| s as i64).collect();
+        let data = array.as_ptr() as *const u8;
+        let array = Box::into_raw(Box::new(array));
+        // On errors the array is leaked rather than risking a double free, as
+        // libtorch may already own it.
+        unsafe {
+            Tensor::f_from_blob_with_deleter(
+                data,
+                &size,
+                &strides,
+                T::KIND,
+                drop_boxed::<ndarray::Array<T, D>>,
+                array as *mut libc::c_void,
+            )
+        }
+    }
+
+    /// Converts an array to a CPU tensor without copying the data, see
+    /// `f_from_ndarray`.
+    pub fn from_ndarray<T, D>(array: ndarray::Array<T, D>) -> Tensor
+    where
+        T: Element + Send,
+        D: ndarray::Dimension,
+    {
+        Tensor::f_from_ndarray(array).unwrap()
     }
 }
 
+/// Copies the data of an array to a tensor, arrays that are not in the
+/// standard layout are supported. Use `Tensor::from_ndarray` to avoid the
+/// copy when the array can be moved.
 impl<T, D> TryFrom<&ndarray::ArrayBase<T, D>> for Tensor
 where
     T: ndarray::Data,
@@ -143,6 +226,7 @@ where
     type Error = TchError;
 
     fn try_from(value: &ndarray::ArrayBase<T, D>) -> Result<Self, Self::Error> {
+        let value = value.as_standard_layout();
         let slice = value
             .as_slice()
             .ok_or_else(|| TchError::Convert("cannot convert to slice".to_string()))?;
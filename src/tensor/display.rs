@@ -60,15 +60,25 @@ impl std::fmt::Debug for Tensor {
                         | Kind::ComplexFloat
                         | Kind::ComplexDouble => (false, false),
                     };
+                    // The values are only displayed if they can be extracted, e.g. this is
+                    // not the case for quantized tensors.
                     match (self.size().as_slice(), is_int, is_float) {
-                        ([], true, false) => write!(f, "[{}]", i64::try_from(self).unwrap()),
-                        ([s], true, false) if *s < 10 => {
-                            write!(f, "{:?}", Vec::<i64>::try_from(self).unwrap())
-                        }
-                        ([], false, true) => write!(f, "[{}]", f64::try_from(self).unwrap()),
-                        ([s], false, true) if *s < 10 => {
-                            write!(f, "{:?}", Vec::<f64>::try_from(self).unwrap())
-                        }
+                        ([], true, false) => match i64::try_from(self) {
+                            Ok(v) => write!(f, "[{v}]"),
+                            Err(_) => write!(f, "Tensor[{:?}, {:?}]", self.size(), kind),
+                        },
+                        ([s], true, false) if *s < 10 => match Vec::<i64>::try_from(self) {
+                            Ok(v) => write!(f, "{v:?}"),
+                            Err(_) => write!(f, "Tensor[{:?}, {:?}]", self.size(), kind),
+                        },
+                        ([], false, true) => match f64::try_from(self) {
+                            Ok(v) => write!(f, "[{v}]"),
+                            Err(_) => write!(f, "Tensor[{:?}, {:?}]", self.size(), kind),
+                        },
+                        ([s], false, true) if *s < 10 => match Vec::<f64>::try_from(self) {
+                            Ok(v) => write!(f, "{v:?}"),
+                            Err(_) => write!(f, "Tensor[{:?}, {:?}]", self.size(), kind),
+                        },
                         _ => write!(f, "Tensor[{:?}, {:?}]", self.size(), kind),
                     }
                 }
@@ -387,29 +397,43 @@ impl std::fmt::Display for Tensor {
         if self.defined() {
             let po = PRINT_OPTS.lock().unwrap();
             let summarize = self.numel() > po.threshold;
-            let basic_kind = BasicKind::for_tensor(self);
+            // Quantized, sparse, and mkldnn tensors are converted to dense tensors
+            // first, the values are not displayed if this fails.
+            let values = match self.f_kind() {
+                Ok(Kind::QInt8 | Kind::QUInt8 | Kind::QInt32) => self.f_dequantize(),
+                Ok(_) if self.is_sparse() || self.is_mkldnn() => self.f_to_dense(None),
+                Ok(_) => Ok(self.shallow_clone()),
+                Err(err) => Err(err),
+            };
+            let (values, basic_kind) = match values {
+                Ok(values) => {
+                    let basic_kind = BasicKind::for_tensor(&values);
+                    (values, basic_kind)
+                }
+                Err(_) => (self.shallow_clone(), BasicKind::Complex),
+            };
             let to_display = if summarize {
-                get_summarized_data(self, po.edge_items as i64)
+                get_summarized_data(&values, po.edge_items as i64)
             } else {
-                self.shallow_clone()
+                values.shallow_clone()
             };
             match basic_kind {
                 BasicKind::Int => {
                     let tf = IntFormatter;
                     let max_w = tf.max_width(&to_display);
-                    tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                    tf.fmt_tensor(&values, 1, max_w, summarize, &po, f)?;
                     writeln!(f)?;
                 }
                 BasicKind::Float => {
                     let tf = FloatFormatter::new(&to_display, &po);
                     let max_w = tf.max_width(&to_display);
-                    tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                    tf.fmt_tensor(&values, 1, max_w, summarize, &po, f)?;
                     writeln!(f)?;
                 }
                 BasicKind::Bool => {
                     let tf = BoolFormatter;
                     let max_w = tf.max_width(&to_display);
-                    tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                    tf.fmt_tensor(&values, 1, max_w, summarize, &po, f)?;
                     writeln!(f)?;
                 }
                 BasicKind::Complex => {}
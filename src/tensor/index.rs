@@ -132,15 +132,22 @@ impl_from_range!(RangeTo<i64>);
 impl_from_range!(RangeToInclusive<i64>);
 
 pub trait IndexOp<T> {
-    fn i(&self, index: T) -> Tensor;
+    /// Indexes the tensor, returning an error on invalid indexes.
+    fn f_i(&self, index: T) -> Result<Tensor, TchError>;
+
+    /// Indexes the tensor, panics on invalid indexes.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_i"))]
+    fn i(&self, index: T) -> Tensor {
+        self.f_i(index).unwrap()
+    }
 }
 
 impl<A> IndexOp<A> for Tensor
 where
     A: Into<TensorIndexer>,
 {
-    fn i(&self, index: A) -> Tensor {
-        self.indexer(&[index.into()])
+    fn f_i(&self, index: A) -> Result<Tensor, TchError> {
+        self.f_indexer(&[index.into()])
     }
 }
 
@@ -148,9 +155,9 @@ impl<A> IndexOp<(A,)> for Tensor
 where
     A: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A,)) -> Tensor {
+    fn f_i(&self, index: (A,)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
-        self.indexer(&[idx_a])
+        self.f_indexer(&[idx_a])
     }
 }
 
@@ -159,10 +166,10 @@ where
     A: Into<TensorIndexer>,
     B: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B)) -> Tensor {
+    fn f_i(&self, index: (A, B)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
-        self.indexer(&[idx_a, idx_b])
+        self.f_indexer(&[idx_a, idx_b])
     }
 }
 
@@ -172,11 +179,11 @@ where
     B: Into<TensorIndexer>,
     C: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B, C)) -> Tensor {
+    fn f_i(&self, index: (A, B, C)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
         let idx_c = index.2.into();
-        self.indexer(&[idx_a, idx_b, idx_c])
+        self.f_indexer(&[idx_a, idx_b, idx_c])
     }
 }
 
@@ -187,12 +194,12 @@ where
     C: Into<TensorIndexer>,
     D: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B, C, D)) -> Tensor {
+    fn f_i(&self, index: (A, B, C, D)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
         let idx_c = index.2.into();
         let idx_d = index.3.into();
-        self.indexer(&[idx_a, idx_b, idx_c, idx_d])
+        self.f_indexer(&[idx_a, idx_b, idx_c, idx_d])
     }
 }
 
@@ -204,13 +211,13 @@ where
     D: Into<TensorIndexer>,
     E: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B, C, D, E)) -> Tensor {
+    fn f_i(&self, index: (A, B, C, D, E)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
         let idx_c = index.2.into();
         let idx_d = index.3.into();
         let idx_e = index.4.into();
-        self.indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e])
+        self.f_indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e])
     }
 }
 
@@ -223,14 +230,14 @@ where
     E: Into<TensorIndexer>,
     F: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B, C, D, E, F)) -> Tensor {
+    fn f_i(&self, index: (A, B, C, D, E, F)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
         let idx_c = index.2.into();
         let idx_d = index.3.into();
         let idx_e = index.4.into();
         let idx_f = index.5.into();
-        self.indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e, idx_f])
+        self.f_indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e, idx_f])
     }
 }
 
@@ -244,7 +251,7 @@ where
     F: Into<TensorIndexer>,
     G: Into<TensorIndexer>,
 {
-    fn i(&self, index: (A, B, C, D, E, F, G)) -> Tensor {
+    fn f_i(&self, index: (A, B, C, D, E, F, G)) -> Result<Tensor, TchError> {
         let idx_a = index.0.into();
         let idx_b = index.1.into();
         let idx_c = index.2.into();
@@ -252,7 +259,7 @@ where
         let idx_e = index.4.into();
         let idx_f = index.5.into();
         let idx_g = index.6.into();
-        self.indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e, idx_f, idx_g])
+        self.f_indexer(&[idx_a, idx_b, idx_c, idx_d, idx_e, idx_f, idx_g])
     }
 }
 
@@ -295,14 +302,29 @@ impl Tensor {
         // Apply indexing from left to right
         let mut curr_tensor = self.shallow_clone();
         let mut curr_idx: i64 = 0;
+        // The dimension of `self` indexed by the current spec.
+        let mut orig_idx: i64 = 0;
 
         for spec in index_spec.iter() {
             let (next_tensor, next_idx) = match spec {
-                InsertNewAxis => (curr_tensor.unsqueeze(curr_idx), curr_idx + 1),
-                Select(index) => (
-                    curr_tensor.select(curr_idx, *index),
-                    curr_idx, // not advanced because select() squeezes dimension
-                ),
+                InsertNewAxis => (curr_tensor.f_unsqueeze(curr_idx)?, curr_idx + 1),
+                Select(index) => {
+                    // Errors refer to the dimension and shape of `self` rather than
+                    // to the ones of the partially indexed tensor.
+                    let selected =
+                        curr_tensor.f_select(curr_idx, *index).map_err(|err| match err {
+                            TchError::IndexOutOfRange { index, size, .. } => {
+                                TchError::IndexOutOfRange {
+                                    dim: orig_idx,
+                                    index,
+                                    size,
+                                    shape: self.size(),
+                                }
+                            }
+                            err => err,
+                        })?;
+                    (selected, curr_idx) // not advanced because select() squeezes dimension
+                }
                 Narrow(start, end) => {
                     if let Some((start, length)) = match (start, end) {
                         (Unbounded, Unbounded) => None,
@@ -321,25 +343,24 @@ impl Tensor {
                         (Excluded(start), Included(end)) => Some((*start + 1, *end - *start)),
                         (Excluded(start), Excluded(end)) => Some((*start + 1, *end - *start - 1)),
                     } {
-                        (curr_tensor.narrow(curr_idx, start, length.max(0)), curr_idx + 1)
+                        (curr_tensor.f_narrow(curr_idx, start, length.max(0))?, curr_idx + 1)
                     } else {
                         (curr_tensor, curr_idx + 1)
                     }
                 }
                 IndexSelect(index_tensor) => {
-                    let index_tensor = index_tensor.to_device(curr_tensor.device());
-                    (curr_tensor.index_select(curr_idx, &index_tensor), curr_idx + 1)
+                    let index_tensor = index_tensor.f_to_device(curr_tensor.device())?;
+                    (curr_tensor.f_index_select(curr_idx, &index_tensor)?, curr_idx + 1)
                 }
             };
 
+            if spec != &InsertNewAxis {
+                orig_idx += 1
+            }
             curr_tensor = next_tensor;
             curr_idx = next_idx;
         }
 
         Ok(curr_tensor)
     }
-
-    fn indexer(&self, index_spec: &[TensorIndexer]) -> Tensor {
-        self.f_indexer(index_spec).unwrap()
-    }
 }
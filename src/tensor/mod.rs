@@ -2,18 +2,27 @@
 use crate::{Device, Kind, TchError};
 use torch_sys::*;
 
+mod bytes;
 mod convert;
 pub mod display;
 pub mod index;
 mod iter;
 mod npy;
 mod ops;
-mod safetensors;
+mod out;
+#[cfg(feature = "rayon")]
+mod par;
+pub(crate) mod safetensors;
+mod shared;
 
 pub use super::wrappers::tensor::{
-    autocast, no_grad, no_grad_guard, with_grad, NoGradGuard, Reduction, Tensor,
+    autocast, autocast_guard, f_autocast_guard, inference_mode, is_grad_enabled, no_grad,
+    no_grad_guard, with_grad, AutocastGuard, GradGuard, InferenceModeGuard, NoGradGuard, Reduction,
+    Tensor,
 };
 pub use index::{IndexOp, NewAxis, TensorIndexer};
+pub use out::OutputArena;
+pub use shared::SharedTensor;
 
 pub trait Shape {
     fn to_shape(&self) -> Box<[i64]>;
@@ -96,20 +105,20 @@ impl Tensor {
         self.f_view_(&s.to_shape())
     }
 
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_view"))]
     pub fn view<T: Shape>(&self, s: T) -> Tensor {
         self.view_(&s.to_shape())
     }
 
     pub fn f_zero_pad1d(&self, left: i64, right: i64) -> Result<Tensor, TchError> {
-        if self.dim() != 3 {
-            return Err(TchError::Shape(format!(
-                "expected a 3 dimension tensor, got {:?}",
-                self.size()
-            )));
+        let size = self.f_size()?;
+        if size.len() != 3 {
+            return Err(TchError::Shape(format!("expected a 3 dimension tensor, got {size:?}")));
         }
         self.f_constant_pad_nd([left, right])
     }
 
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_zero_pad1d"))]
     pub fn zero_pad1d(&self, left: i64, right: i64) -> Tensor {
         self.f_zero_pad1d(left, right).unwrap()
     }
@@ -121,15 +130,14 @@ impl Tensor {
         top: i64,
         bottom: i64,
     ) -> Result<Tensor, TchError> {
-        if self.dim() != 4 {
-            return Err(TchError::Shape(format!(
-                "expected a 4 dimension tensor, got {:?}",
-                self.size()
-            )));
+        let size = self.f_size()?;
+        if size.len() != 4 {
+            return Err(TchError::Shape(format!("expected a 4 dimension tensor, got {size:?}")));
         }
         self.f_constant_pad_nd([left, right, top, bottom])
     }
 
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_zero_pad2d"))]
     pub fn zero_pad2d(&self, left: i64, right: i64, top: i64, bottom: i64) -> Tensor {
         self.f_zero_pad2d(left, right, top, bottom).unwrap()
     }
@@ -146,13 +154,34 @@ impl<T: crate::kind::Element> From<T> for Tensor {
         Tensor::from_slice(&[v]).view(())
     }
 }
+// The MPS backend does not support double precision values, libtorch reports
+// this in different ways depending on the operation so this is checked upfront.
+fn check_mps_kind(kind: Kind, device: Device) -> Result<(), TchError> {
+    if device == Device::Mps && kind == Kind::Double {
+        return Err(TchError::Kind("the MPS backend does not support the Double kind".to_string()));
+    }
+    Ok(())
+}
+
+// libtorch reports an invalid probability without mentioning the operation.
+pub(crate) fn check_dropout_p(name: &str, p: f64) -> Result<(), TchError> {
+    if !(0.0..=1.0).contains(&p) {
+        return Err(TchError::Torch(format!(
+            "{name} probability has to be between 0 and 1, got {p}"
+        )));
+    }
+    Ok(())
+}
+
 impl Tensor {
     /// Casts a tensor to a specified kind.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_to_kind"))]
     pub fn to_kind(&self, kind: Kind) -> Tensor {
-        self.totype(kind)
+        self.f_to_kind(kind).unwrap()
     }
 
     pub fn f_to_kind(&self, kind: Kind) -> Result<Tensor, TchError> {
+        check_mps_kind(kind, self.device())?;
         self.f_totype(kind)
     }
 
@@ -163,62 +192,181 @@ impl Tensor {
 
 impl Tensor {
     /// Computes the cross-entropy loss based on some logits and targets.
+    pub fn f_cross_entropy_for_logits(&self, targets: &Tensor) -> Result<Tensor, TchError> {
+        self.f_log_softmax(-1, Kind::Float)?.f_nll_loss(
+            targets,
+            None::<Tensor>,
+            Reduction::Mean,
+            -100,
+        )
+    }
+
+    /// Computes the cross-entropy loss based on some logits and targets.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_cross_entropy_for_logits"))]
     pub fn cross_entropy_for_logits(&self, targets: &Tensor) -> Tensor {
-        self.log_softmax(-1, Kind::Float).nll_loss(targets)
+        self.f_cross_entropy_for_logits(targets).unwrap()
     }
 
     /// Returns the average accuracy for some given logits assuming that
     /// targets represent ground-truth.
+    pub fn f_accuracy_for_logits(&self, targets: &Tensor) -> Result<Tensor, TchError> {
+        self.f_argmax(-1, false)?.f_eq_tensor(targets)?.f_to_kind(Kind::Float)?.f_mean(Kind::Float)
+    }
+
+    /// Returns the average accuracy for some given logits assuming that
+    /// targets represent ground-truth.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_accuracy_for_logits"))]
     pub fn accuracy_for_logits(&self, targets: &Tensor) -> Tensor {
-        self.argmax(-1, false).eq_tensor(targets).to_kind(Kind::Float).mean(Kind::Float)
+        self.f_accuracy_for_logits(targets).unwrap()
     }
 
-    pub fn random_batch(&self, batch_size: i64) -> Tensor {
-        let len: i64 = self.size()[0];
-        let index = Tensor::randint(len, [batch_size], (Kind::Int64, self.device()));
-        self.index_select(0, &index)
+    pub fn f_random_batch(&self, batch_size: i64) -> Result<Tensor, TchError> {
+        let len = match self.f_size()?.first() {
+            Some(&len) => len,
+            None => return Err(TchError::Shape("random_batch: scalar tensor".to_string())),
+        };
+        let index = Tensor::f_randint(len, [batch_size], (Kind::Int64, self.device()))?;
+        self.f_index_select(0, &index)
     }
 
-    pub fn random_batch2(t1: &Tensor, t2: &Tensor, batch_size: i64) -> (Tensor, Tensor) {
-        let len1: i64 = t1.size()[0];
-        let len2: i64 = t2.size()[0];
-        if len1 != len2 {
-            panic!("random_batch2: shape mismatch {:?} {:?}", t1.size(), t2.size())
-        }
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_random_batch"))]
+    pub fn random_batch(&self, batch_size: i64) -> Tensor {
+        self.f_random_batch(batch_size).unwrap()
+    }
+
+    pub fn f_random_batch2(
+        t1: &Tensor,
+        t2: &Tensor,
+        batch_size: i64,
+    ) -> Result<(Tensor, Tensor), TchError> {
+        let (size1, size2) = (t1.f_size()?, t2.f_size()?);
+        let len1 = match (size1.first(), size2.first()) {
+            (Some(&len1), Some(&len2)) if len1 == len2 => len1,
+            _ => {
+                return Err(TchError::Shape(format!(
+                    "random_batch2: shape mismatch {size1:?} {size2:?}"
+                )))
+            }
+        };
         let device1 = t1.device();
         let device2 = t2.device();
         if device1 != device2 {
-            panic!("random_batch2: device mismatch {device1:?} {device2:?}")
+            return Err(TchError::Torch(format!(
+                "random_batch2: device mismatch {device1:?} {device2:?}"
+            )));
         }
-        let index = Tensor::randint(len1, [batch_size], (Kind::Int64, device1));
-        let batch1 = t1.index_select(0, &index);
-        let batch2 = t2.index_select(0, &index);
-        (batch1, batch2)
+        let index = Tensor::f_randint(len1, [batch_size], (Kind::Int64, device1))?;
+        let batch1 = t1.f_index_select(0, &index)?;
+        let batch2 = t2.f_index_select(0, &index)?;
+        Ok((batch1, batch2))
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_random_batch2"))]
+    pub fn random_batch2(t1: &Tensor, t2: &Tensor, batch_size: i64) -> (Tensor, Tensor) {
+        Tensor::f_random_batch2(t1, t2, batch_size).unwrap()
     }
 
     /// Moves a tensor to a specified device.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_to_device"))]
     pub fn to_device(&self, device: Device) -> Tensor {
-        self.to(device)
+        self.f_to_device(device).unwrap()
     }
 
     pub fn f_to_device(&self, device: Device) -> Result<Tensor, TchError> {
+        if device == Device::Vulkan && !crate::utils::has_vulkan() {
+            return Err(TchError::Torch(
+                "the Vulkan backend is not available in this libtorch build".to_string(),
+            ));
+        }
+        check_mps_kind(self.f_kind()?, device)?;
         self.f_to(device)
     }
 
+    pub fn f_avg_pool2d_default(&self, ksize: i64) -> Result<Tensor, TchError> {
+        self.f_avg_pool2d([ksize, ksize], [ksize, ksize], [0, 0], false, true, 1)
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_avg_pool2d_default"))]
     pub fn avg_pool2d_default(&self, ksize: i64) -> Tensor {
-        self.avg_pool2d([ksize, ksize], [ksize, ksize], [0, 0], false, true, 1)
+        self.f_avg_pool2d_default(ksize).unwrap()
+    }
+
+    pub fn f_max_pool2d_default(&self, ksize: i64) -> Result<Tensor, TchError> {
+        self.f_max_pool2d([ksize, ksize], [ksize, ksize], [0, 0], [1, 1], false)
     }
 
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_max_pool2d_default"))]
     pub fn max_pool2d_default(&self, ksize: i64) -> Tensor {
-        self.max_pool2d([ksize, ksize], [ksize, ksize], [0, 0], [1, 1], false)
+        self.f_max_pool2d_default(ksize).unwrap()
+    }
+
+    /// Randomly zeroes whole channels with probability `p` in train mode, the
+    /// remaining channels are scaled by `1 / (1 - p)`.
+    ///
+    /// The input has shape `[N, C, H, W]` or `[C, H, W]`. A probability of 0
+    /// returns the input and a probability of 1 returns zeros.
+    pub fn f_dropout2d(&self, p: f64, train: bool) -> Result<Tensor, TchError> {
+        self.f_channel_dropout("dropout2d", 4, p, train)
+    }
+
+    /// Randomly zeroes whole channels, see `f_dropout2d`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_dropout2d"))]
+    pub fn dropout2d(&self, p: f64, train: bool) -> Tensor {
+        self.f_dropout2d(p, train).unwrap()
+    }
+
+    /// Randomly zeroes whole channels with probability `p` in train mode, see
+    /// `f_dropout2d`.
+    ///
+    /// The input has shape `[N, C, D, H, W]` or `[C, D, H, W]`.
+    pub fn f_dropout3d(&self, p: f64, train: bool) -> Result<Tensor, TchError> {
+        self.f_channel_dropout("dropout3d", 5, p, train)
+    }
+
+    /// Randomly zeroes whole channels, see `f_dropout3d`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_dropout3d"))]
+    pub fn dropout3d(&self, p: f64, train: bool) -> Tensor {
+        self.f_dropout3d(p, train).unwrap()
+    }
+
+    // Channel-wise dropout over batched inputs with `dim` dimensions, or
+    // unbatched inputs with `dim - 1` dimensions.
+    fn f_channel_dropout(
+        &self,
+        name: &str,
+        dim: usize,
+        p: f64,
+        train: bool,
+    ) -> Result<Tensor, TchError> {
+        check_dropout_p(name, p)?;
+        let size = self.f_size()?;
+        if size.len() == dim - 1 {
+            return self.f_unsqueeze(0)?.f_feature_dropout(p, train)?.f_squeeze_dim(0);
+        }
+        if size.len() != dim {
+            return Err(TchError::Shape(format!(
+                "{name} expects a {dim} or {} dimension tensor, got {size:?}",
+                dim - 1
+            )));
+        }
+        self.f_feature_dropout(p, train)
     }
 
     /// Flattens a tensor.
     ///
     /// This returns a flattened version of the given tensor. The first dimension
     /// is preserved as it is assumed to be the mini-batch dimension.
+    pub fn f_flat_view(&self) -> Result<Tensor, TchError> {
+        match self.f_size()?.first() {
+            Some(&batch_size) => self.f_view((batch_size, -1)),
+            None => Err(TchError::Shape("flat_view: scalar tensor".to_string())),
+        }
+    }
+
+    /// Flattens a tensor, see `f_flat_view`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_flat_view"))]
     pub fn flat_view(&self) -> Tensor {
-        self.view((self.size()[0], -1))
+        self.f_flat_view().unwrap()
     }
 
     /// Converts a tensor to a one-hot encoded version.
@@ -226,30 +374,66 @@ impl Tensor {
     /// If the input has a size [N1, N2, ..., Nk], the returned tensor has a size
     /// [N1, ..., Nk, labels]. The returned tensor uses float values.
     /// Elements of the input vector are expected to be between 0 and labels-1.
+    pub fn f_onehot(&self, labels: i64) -> Result<Tensor, TchError> {
+        Tensor::f_zeros([self.f_size()?, vec![labels]].concat(), (Kind::Float, self.device()))?
+            .f_scatter_value_(-1, &self.f_unsqueeze(-1)?.f_to_kind(Kind::Int64)?, 1.0)
+    }
+
+    /// Converts a tensor to a one-hot encoded version, see `f_onehot`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_onehot"))]
     pub fn onehot(&self, labels: i64) -> Tensor {
-        Tensor::zeros([self.size(), vec![labels]].concat(), (Kind::Float, self.device()))
-            .scatter_value_(-1, &self.unsqueeze(-1).to_kind(Kind::Int64), 1.0)
+        self.f_onehot(labels).unwrap()
     }
 
     /// Copies a tensor to a newly allocated tensor using the same shape and device.
+    pub fn f_copy(&self) -> Result<Tensor, TchError> {
+        let mut result = self.f_zeros_like()?;
+        result.f_copy_(self)?;
+        Ok(result)
+    }
+
+    /// Copies a tensor to a newly allocated tensor using the same shape and device.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_copy"))]
     pub fn copy(&self) -> Tensor {
-        let mut result = self.zeros_like();
-        result.copy_(self);
-        result
+        self.f_copy().unwrap()
     }
 
     /// Copies the data from a two dimensional slice in a tensor object.
+    ///
+    /// All the rows must have the same length, a `TchError::Shape` error is
+    /// returned otherwise.
+    pub fn f_from_slice2<T, U>(v: &[U]) -> Result<Tensor, TchError>
+    where
+        T: crate::kind::Element,
+        U: AsRef<[T]>,
+    {
+        let ncols = v.first().map_or(0, |row| row.as_ref().len());
+        if let Some((index, row)) = v.iter().enumerate().find(|(_, r)| r.as_ref().len() != ncols) {
+            return Err(TchError::Shape(format!(
+                "from_slice2: row {index} has {} elements, expected {ncols}",
+                row.as_ref().len()
+            )));
+        }
+        let data: Vec<T> = v.iter().flat_map(|row| row.as_ref().iter().cloned()).collect();
+        Tensor::f_from_slice(&data)?.f_view([v.len() as i64, ncols as i64])
+    }
+
+    /// Copies the data from a two dimensional slice in a tensor object.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_from_slice2"))]
     pub fn from_slice2<T, U>(v: &[U]) -> Tensor
     where
         T: crate::kind::Element,
         U: AsRef<[T]>,
     {
-        let inner: Vec<Tensor> = v.iter().map(|v| Tensor::from_slice(v.as_ref())).collect();
-        Tensor::stack(&inner, 0)
+        Tensor::f_from_slice2(v).unwrap()
+    }
+
+    pub fn f_to_mkldnn_default(&self) -> Result<Tensor, TchError> {
+        self.f_to_mkldnn(self.f_kind()?)
     }
 
     pub fn to_mkldnn(&self) -> Tensor {
-        self.g_to_mkldnn(self.kind())
+        self.f_to_mkldnn_default().unwrap()
     }
 }
 
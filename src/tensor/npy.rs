@@ -3,6 +3,7 @@
 //! This module implements the support for reading and writing `.npy` and `.npz` files. The file
 //! format spec can be found at:
 //! <https://docs.scipy.org/doc/numpy-1.14.2/neps/npy-format.html>.
+use crate::wrappers::utils::path_io_error;
 use crate::{Kind, TchError, Tensor};
 use std::collections::HashMap;
 use std::fs::File;
@@ -44,22 +45,24 @@ impl Header {
     fn to_string(&self) -> Result<String, TchError> {
         let fortran_order = if self.fortran_order { "True" } else { "False" };
         let mut shape = self.shape.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+        // Single byte types have no byte order, numpy uses `|` for these.
         let descr = match self.descr {
-            Kind::Half => "f2",
-            Kind::Float => "f4",
-            Kind::Double => "f8",
-            Kind::Int => "i4",
-            Kind::Int64 => "i8",
-            Kind::Int16 => "i2",
-            Kind::Int8 => "i1",
-            Kind::Uint8 => "u1",
+            Kind::Half => "<f2",
+            Kind::Float => "<f4",
+            Kind::Double => "<f8",
+            Kind::Int => "<i4",
+            Kind::Int64 => "<i8",
+            Kind::Int16 => "<i2",
+            Kind::Int8 => "|i1",
+            Kind::Uint8 => "|u1",
+            Kind::Bool => "|b1",
             descr => return Err(TchError::FileFormat(format!("unsupported kind {descr:?}"))),
         };
         if !shape.is_empty() {
             shape.push(',')
         }
         Ok(format!(
-            "{{'descr': '<{descr}', 'fortran_order': {fortran_order}, 'shape': ({shape}), }}"
+            "{{'descr': '{descr}', 'fortran_order': {fortran_order}, 'shape': ({shape}), }}"
         ))
     }
 
@@ -123,7 +126,7 @@ impl Header {
                     return Err(TchError::FileFormat("empty descr".to_string()));
                 }
                 if descr.starts_with('>') {
-                    return Err(TchError::FileFormat(format!("little-endian descr {descr}")));
+                    return Err(TchError::FileFormat(format!("big-endian descr {descr}")));
                 }
                 // the only supported types in tensor are:
                 //     float64, float32, float16,
@@ -166,10 +169,18 @@ impl Header {
     }
 }
 
+fn open(path: &Path) -> Result<File, TchError> {
+    File::open(path).map_err(|err| path_io_error(path, err))
+}
+
+fn create(path: &Path) -> Result<File, TchError> {
+    File::create(path).map_err(|err| path_io_error(path, err))
+}
+
 impl crate::Tensor {
     /// Reads a npy file and return the stored tensor.
     pub fn read_npy<T: AsRef<Path>>(path: T) -> Result<Tensor, TchError> {
-        let mut reader = File::open(path.as_ref())?;
+        let mut reader = open(path.as_ref())?;
         let header = read_header(&mut reader)?;
         let header = Header::parse(&header)?;
         if header.fortran_order {
@@ -182,7 +193,7 @@ impl crate::Tensor {
 
     /// Reads a npz file and returns some named tensors.
     pub fn read_npz<T: AsRef<Path>>(path: T) -> Result<Vec<(String, Tensor)>, TchError> {
-        let zip_reader = BufReader::new(File::open(path.as_ref())?);
+        let zip_reader = BufReader::new(open(path.as_ref())?);
         let mut zip = zip::ZipArchive::new(zip_reader)?;
         let mut result = vec![];
         for i in 0..zip.len() {
@@ -225,16 +236,21 @@ impl crate::Tensor {
     }
 
     /// Writes a tensor in the npy format so that it can be read using python.
+    ///
+    /// Tensors that are not on the CPU are copied to the CPU and non-contiguous
+    /// tensors are written in row-major order.
     pub fn write_npy<T: AsRef<Path>>(&self, path: T) -> Result<(), TchError> {
-        let mut f = File::create(path.as_ref())?;
+        let mut f = create(path.as_ref())?;
         self.write(&mut f)
     }
 
+    /// Writes some named tensors in the npz format, each tensor is stored
+    /// uncompressed as `{name}.npy` in the archive, see `write_npy`.
     pub fn write_npz<S: AsRef<str>, T: AsRef<Tensor>, P: AsRef<Path>>(
         ts: &[(S, T)],
         path: P,
     ) -> Result<(), TchError> {
-        let mut zip = zip::ZipWriter::new(File::create(path.as_ref())?);
+        let mut zip = zip::ZipWriter::new(create(path.as_ref())?);
         let options =
             zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
@@ -273,5 +289,12 @@ mod tests {
             h.to_string().unwrap(),
             "{'descr': '<i8', 'fortran_order': False, 'shape': (), }"
         );
+
+        let h = Header { descr: crate::Kind::Bool, fortran_order: false, shape: vec![2] };
+        assert_eq!(
+            h.to_string().unwrap(),
+            "{'descr': '|b1', 'fortran_order': False, 'shape': (2,), }"
+        );
+        assert_eq!(Header::parse(&h.to_string().unwrap()).unwrap(), h);
     }
 }
@@ -0,0 +1,297 @@
+//! Operations writing to preallocated output tensors.
+//!
+//! The `_into` variants validate the output tensor before running the `_out`
+//! variant of the operation so that the output is never silently resized, a
+//! resize would allocate new memory and defeat the purpose of preallocating.
+use crate::{Device, Kind, TchError, Tensor};
+use std::collections::HashMap;
+
+fn broadcast_size(a: &[i64], b: &[i64]) -> Option<Vec<i64>> {
+    let len = a.len().max(b.len());
+    let dim = |s: &[i64], i: usize| if i < len - s.len() { 1 } else { s[i + s.len() - len] };
+    (0..len)
+        .map(|i| match (dim(a, i), dim(b, i)) {
+            (x, y) if x == y || y == 1 => Some(x),
+            (1, y) => Some(y),
+            _ => None,
+        })
+        .collect()
+}
+
+fn matmul_size(a: &[i64], b: &[i64]) -> Option<Vec<i64>> {
+    match (a, b) {
+        ([], _) | (_, []) => None,
+        ([k1], [k2]) => (k1 == k2).then(Vec::new),
+        (a, b) => {
+            // One dimensional arguments are promoted to matrices, the added
+            // dimension is then removed from the result.
+            let (a_batch, n, k1) = match a {
+                [k] => (&a[..0], None, *k),
+                _ => (&a[..a.len() - 2], Some(a[a.len() - 2]), a[a.len() - 1]),
+            };
+            let (b_batch, k2, m) = match b {
+                [k] => (&b[..0], *k, None),
+                _ => (&b[..b.len() - 2], b[b.len() - 2], Some(b[b.len() - 1])),
+            };
+            if k1 != k2 {
+                return None;
+            }
+            let mut size = broadcast_size(a_batch, b_batch)?;
+            size.extend(n);
+            size.extend(m);
+            Some(size)
+        }
+    }
+}
+
+fn conv2d_size(
+    input: &[i64],
+    weight: &[i64],
+    stride: [i64; 2],
+    padding: [i64; 2],
+    dilation: [i64; 2],
+    groups: i64,
+) -> Option<Vec<i64>> {
+    match (input, weight) {
+        ([n, c, h, w], [o, c_g, kh, kw]) if *c == c_g * groups && groups > 0 => {
+            let out = |x: i64, k: i64, i: usize| {
+                (x + 2 * padding[i] - dilation[i] * (k - 1) - 1) / stride[i].max(1) + 1
+            };
+            Some(vec![*n, *o, out(*h, *kh, 0), out(*w, *kw, 1)])
+        }
+        _ => None,
+    }
+}
+
+impl Tensor {
+    // Checks that `out` can hold the result of an operation on `self`
+    // returning a tensor of the given size.
+    fn check_out(&self, op: &str, out: &Tensor, size: Option<Vec<i64>>) -> Result<(), TchError> {
+        let size = size.ok_or_else(|| {
+            TchError::Shape(format!("{op}: incompatible input shape {:?}", self.size()))
+        })?;
+        if out.size() != size {
+            return Err(TchError::Shape(format!(
+                "{op}: the output has shape {:?} rather than {size:?}",
+                out.size()
+            )));
+        }
+        if out.kind() != self.kind() {
+            return Err(TchError::Kind(format!(
+                "{op}: the output has kind {:?} rather than {:?}",
+                out.kind(),
+                self.kind()
+            )));
+        }
+        if out.device() != self.device() {
+            return Err(TchError::Shape(format!(
+                "{op}: the output is on {:?} rather than {:?}",
+                out.device(),
+                self.device()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Matrix product of `self` and `other` written to `out`, which must have
+    /// the shape, kind and device of the result.
+    pub fn f_matmul_into(&self, other: &Tensor, out: &mut Tensor) -> Result<(), TchError> {
+        self.check_out("matmul_into", out, matmul_size(&self.size(), &other.size()))?;
+        let _ = self.f_matmul_out(out, other)?;
+        Ok(())
+    }
+
+    pub fn matmul_into(&self, other: &Tensor, out: &mut Tensor) {
+        self.f_matmul_into(other, out).unwrap()
+    }
+
+    /// Linear layer `self * weight^T + bias` written to `out`, which must
+    /// have the shape, kind and device of the result.
+    pub fn f_linear_into(
+        &self,
+        weight: &Tensor,
+        bias: Option<&Tensor>,
+        out: &mut Tensor,
+    ) -> Result<(), TchError> {
+        let size = match (self.size().as_slice(), weight.size().as_slice()) {
+            ([batch @ .., i], [o, w_i]) if i == w_i => {
+                Some(batch.iter().copied().chain(std::iter::once(*o)).collect())
+            }
+            _ => None,
+        };
+        self.check_out("linear_into", out, size)?;
+        let _ = self.f_linear_out(out, weight, bias)?;
+        Ok(())
+    }
+
+    pub fn linear_into(&self, weight: &Tensor, bias: Option<&Tensor>, out: &mut Tensor) {
+        self.f_linear_into(weight, bias, out).unwrap()
+    }
+
+    /// Two dimensional convolution of an input of shape
+    /// `[batch, channels, height, width]` written to `out`, which must have
+    /// the shape, kind and device of the result.
+    #[allow(clippy::too_many_arguments)]
+    pub fn f_conv2d_into(
+        &self,
+        weight: &Tensor,
+        bias: Option<&Tensor>,
+        stride: [i64; 2],
+        padding: [i64; 2],
+        dilation: [i64; 2],
+        groups: i64,
+        out: &mut Tensor,
+    ) -> Result<(), TchError> {
+        let size = conv2d_size(&self.size(), &weight.size(), stride, padding, dilation, groups);
+        self.check_out("conv2d_into", out, size)?;
+        let _ = self.f_convolution_out(
+            out,
+            weight,
+            bias,
+            stride,
+            padding,
+            dilation,
+            false,
+            [0, 0],
+            groups,
+        )?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn conv2d_into(
+        &self,
+        weight: &Tensor,
+        bias: Option<&Tensor>,
+        stride: [i64; 2],
+        padding: [i64; 2],
+        dilation: [i64; 2],
+        groups: i64,
+        out: &mut Tensor,
+    ) {
+        self.f_conv2d_into(weight, bias, stride, padding, dilation, groups, out).unwrap()
+    }
+
+    /// Softmax along `dim` written to `out`, which must have the shape, kind
+    /// and device of `self`.
+    pub fn f_softmax_into(&self, dim: i64, out: &mut Tensor) -> Result<(), TchError> {
+        self.check_out("softmax_into", out, Some(self.size()))?;
+        let _ = self.f_softmax_int_out(out, dim, None)?;
+        Ok(())
+    }
+
+    pub fn softmax_into(&self, dim: i64, out: &mut Tensor) {
+        self.f_softmax_into(dim, out).unwrap()
+    }
+
+    /// Rectified linear unit written to `out`, which must have the shape,
+    /// kind and device of `self`.
+    pub fn f_relu_into(&self, out: &mut Tensor) -> Result<(), TchError> {
+        self.check_out("relu_into", out, Some(self.size()))?;
+        let _ = self.f_relu_out(out)?;
+        Ok(())
+    }
+
+    pub fn relu_into(&self, out: &mut Tensor) {
+        self.f_relu_into(out).unwrap()
+    }
+
+    /// Sum of `self` and `other` with broadcasting written to `out`, which
+    /// must have the shape, kind and device of the result.
+    pub fn f_add_into(&self, other: &Tensor, out: &mut Tensor) -> Result<(), TchError> {
+        self.check_out("add_into", out, broadcast_size(&self.size(), &other.size()))?;
+        let _ = self.f_add_out(out, other)?;
+        Ok(())
+    }
+
+    pub fn add_into(&self, other: &Tensor, out: &mut Tensor) {
+        self.f_add_into(other, out).unwrap()
+    }
+}
+
+/// A set of preallocated output tensors identified by name, to be used with
+/// the `_into` operations so that repeated forward passes do not allocate.
+///
+/// The tensors are allocated on the first request for a name. A later request
+/// with a different shape or kind returns an error unless resizing has been
+/// allowed, in which case the tensor is resized and only reallocated if its
+/// storage is too small or its kind changes.
+#[derive(Debug)]
+pub struct OutputArena {
+    tensors: HashMap<String, Tensor>,
+    device: Device,
+    allow_resize: bool,
+}
+
+impl OutputArena {
+    pub fn new(device: Device) -> OutputArena {
+        OutputArena { tensors: HashMap::new(), device, allow_resize: false }
+    }
+
+    /// Allows the tensors to be resized when requested with a different shape.
+    pub fn allow_resize(mut self, allow_resize: bool) -> Self {
+        self.allow_resize = allow_resize;
+        self
+    }
+
+    pub fn device(&self) -> Device {
+        self.device
+    }
+
+    /// Returns the output tensor for `name` with the given shape and kind.
+    ///
+    /// The returned tensor shares its storage with the arena, so it is
+    /// overwritten by the next forward pass using the same name.
+    pub fn f_get(&mut self, name: &str, size: &[i64], kind: Kind) -> Result<Tensor, TchError> {
+        let device = self.device;
+        match self.tensors.get_mut(name) {
+            None => {
+                let tensor = Tensor::f_empty(size, (kind, device))?;
+                self.tensors.insert(name.to_string(), tensor.shallow_clone());
+                Ok(tensor)
+            }
+            Some(tensor) if tensor.size() == size && tensor.kind() == kind => {
+                Ok(tensor.shallow_clone())
+            }
+            Some(tensor) => {
+                if !self.allow_resize {
+                    return Err(TchError::Shape(format!(
+                        "output arena: {name} has shape {:?} and kind {:?}, requested {size:?} and {kind:?}",
+                        tensor.size(),
+                        tensor.kind(),
+                    )));
+                }
+                if tensor.kind() == kind {
+                    let _ = tensor.f_resize_(size)?;
+                } else {
+                    *tensor = Tensor::f_empty(size, (kind, device))?;
+                }
+                Ok(tensor.shallow_clone())
+            }
+        }
+    }
+
+    /// Returns the output tensor for `name`, see `f_get`.
+    pub fn get(&mut self, name: &str, size: &[i64], kind: Kind) -> Tensor {
+        self.f_get(name, size, kind).unwrap()
+    }
+
+    /// The number of preallocated tensors.
+    pub fn len(&self) -> usize {
+        self.tensors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tensors.is_empty()
+    }
+
+    /// The memory used by the preallocated tensors in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.tensors.values().map(|t| t.numel() * t.kind().elt_size_in_bytes()).sum()
+    }
+
+    /// Releases all the preallocated tensors.
+    pub fn clear(&mut self) {
+        self.tensors.clear()
+    }
+}
@@ -0,0 +1,75 @@
+//! Parallel helpers for tensors, this requires the `rayon` feature.
+use crate::{TchError, Tensor};
+use rayon::prelude::*;
+
+// Restores the number of intra-op threads on drop so that this also happens
+// when the mapped closure panics.
+struct NumThreadsGuard(i32);
+
+impl Drop for NumThreadsGuard {
+    fn drop(&mut self) {
+        crate::set_num_threads(self.0)
+    }
+}
+
+impl Tensor {
+    /// Applies `f` to chunks of `chunk` elements along the first dimension in
+    /// parallel and concatenates the results.
+    ///
+    /// The chunks are processed on the rayon global thread pool. As libtorch
+    /// operations can themselves use an intra-op thread pool, running both at
+    /// the same time would oversubscribe the cores. To avoid this the number
+    /// of libtorch intra-op threads is set to 1 while the map runs and restored
+    /// afterwards. Note that this setting is process-wide so this impacts
+    /// tensor operations that run concurrently on other threads.
+    ///
+    /// All the outputs of `f` must have the same number of dimensions and the
+    /// same size on all but the first dimension.
+    pub fn par_map_dim0<F>(&self, chunk: usize, f: F) -> Result<Tensor, TchError>
+    where
+        F: Fn(Tensor) -> Tensor + Sync,
+    {
+        if chunk == 0 {
+            return Err(TchError::Shape("par_map_dim0: chunk size must be positive".to_string()));
+        }
+        let size0 = match self.size().first() {
+            None => {
+                return Err(TchError::Shape("par_map_dim0: expected a non-scalar tensor".into()))
+            }
+            Some(&size0) => size0,
+        };
+        if size0 == 0 {
+            return Ok(f(self.shallow_clone()));
+        }
+        let chunk = chunk as i64;
+        let chunks = (0..size0)
+            .step_by(chunk as usize)
+            .map(|start| self.f_narrow(0, start, i64::min(chunk, size0 - start)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs: Vec<Tensor> = {
+            let _guard = NumThreadsGuard(crate::get_num_threads());
+            crate::set_num_threads(1);
+            chunks.into_par_iter().map(&f).collect()
+        };
+        let trailing_size = |t: &Tensor| {
+            let size = t.size();
+            if size.is_empty() {
+                None
+            } else {
+                Some(size[1..].to_vec())
+            }
+        };
+        let expected = trailing_size(&outputs[0]);
+        for (index, output) in outputs.iter().enumerate() {
+            let trailing = trailing_size(output);
+            if expected.is_none() || trailing != expected {
+                return Err(TchError::Shape(format!(
+                    "par_map_dim0: incompatible output shape for chunk {index}, {:?} vs {:?}",
+                    output.size(),
+                    outputs[0].size(),
+                )));
+            }
+        }
+        Tensor::f_cat(&outputs, 0)
+    }
+}
@@ -5,10 +5,12 @@
 use crate::nn::VarStore;
 use crate::{Kind, TchError, Tensor};
 
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
-use safetensors::tensor::{Dtype, SafeTensors, TensorView, View};
+use safetensors::tensor::{Dtype, SafeTensorError, SafeTensors, TensorView, View};
 
 impl TryFrom<Kind> for Dtype {
     type Error = TchError;
@@ -104,7 +106,7 @@ impl<'a> View for SafeView<'a> {
 }
 
 fn wrap_err<P: AsRef<Path>>(path: P, err: safetensors::SafeTensorError) -> TchError {
-    TchError::SafeTensorError { path: path.as_ref().to_string_lossy().to_string(), err }
+    TchError::SafeTensorError { path: crate::wrappers::utils::path_to_string(path.as_ref()), err }
 }
 
 impl crate::Tensor {
@@ -120,15 +122,329 @@ impl crate::Tensor {
         tensors: &[(S, T)],
         path: P,
     ) -> Result<(), TchError> {
-        let views = tensors
-            .iter()
-            .map(|(name, tensor)| {
-                Ok::<(&str, SafeView), TchError>((name.as_ref(), tensor.as_ref().try_into()?))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
-        safetensors::tensor::serialize_to_file(views, &None, path.as_ref())
-            .map_err(|e| wrap_err(path, e))?;
-        Ok(())
+        write_safetensors(tensors, None, path)
+    }
+
+    /// Writes some tensors in the safetensors format along with a metadata
+    /// map stored in the header of the file.
+    pub fn write_safetensors_with_metadata<S: AsRef<str>, T: AsRef<Tensor>, P: AsRef<Path>>(
+        tensors: &[(S, T)],
+        metadata: &HashMap<String, String>,
+        path: P,
+    ) -> Result<(), TchError> {
+        write_safetensors(tensors, Some(metadata.clone()), path)
+    }
+
+    /// Reads the metadata map of a safetensors file, only the header of the
+    /// file is read. The map is empty for files written without metadata.
+    pub fn read_safetensors_metadata<T: AsRef<Path>>(
+        path: T,
+    ) -> Result<HashMap<String, String>, TchError> {
+        let mut file = std::fs::File::open(&path).map_err(|e| wrap_err(&path, e.into()))?;
+        let header = read_header(&mut file, path.as_ref())?;
+        HeaderParser { bytes: &header, pos: 0 }
+            .metadata()
+            .ok_or_else(|| wrap_err(&path, SafeTensorError::InvalidHeaderDeserialization))
+    }
+}
+
+// Reads the JSON header of a safetensors file, the file is left positioned at
+// the start of the tensor data.
+fn read_header(file: &mut std::fs::File, path: &Path) -> Result<Vec<u8>, TchError> {
+    let mut len = [0u8; 8];
+    file.read_exact(&mut len).map_err(|_| wrap_err(path, SafeTensorError::HeaderTooSmall))?;
+    let len = u64::from_le_bytes(len);
+    if len > MAX_HEADER_SIZE {
+        return Err(wrap_err(path, SafeTensorError::HeaderTooLarge));
+    }
+    let mut header = vec![0u8; len as usize];
+    file.read_exact(&mut header)
+        .map_err(|_| wrap_err(path, SafeTensorError::InvalidHeaderLength))?;
+    Ok(header)
+}
+
+fn kind_of_dtype(dtype: &str) -> Option<Kind> {
+    let kind = match dtype {
+        "BOOL" => Kind::Bool,
+        "U8" => Kind::Uint8,
+        "I8" => Kind::Int8,
+        "I16" => Kind::Int16,
+        "I32" => Kind::Int,
+        "I64" => Kind::Int64,
+        "BF16" => Kind::BFloat16,
+        "F16" => Kind::Half,
+        "F32" => Kind::Float,
+        "F64" => Kind::Double,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Calls `f` on each tensor of a safetensors file, only the header and a single
+/// tensor are held in memory at a time. The tensors are created on the CPU
+/// with the kind of the file and are visited in the order of their data.
+pub(crate) fn f_visit_safetensors<P, F>(path: P, mut f: F) -> Result<(), TchError>
+where
+    P: AsRef<Path>,
+    F: FnMut(String, Tensor) -> Result<(), TchError>,
+{
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path).map_err(|e| wrap_err(path, e.into()))?;
+    let header = read_header(&mut file, path)?;
+    let mut entries = HeaderParser { bytes: &header, pos: 0 }
+        .tensor_entries()
+        .ok_or_else(|| wrap_err(path, SafeTensorError::InvalidHeaderDeserialization))?;
+    entries.sort_by_key(|entry| entry.offsets.0);
+    let data_start = 8 + header.len() as u64;
+    let mut data = vec![];
+    for entry in entries {
+        let kind = kind_of_dtype(&entry.dtype)
+            .ok_or_else(|| TchError::Convert(format!("unsupported dtype {}", entry.dtype)))?;
+        let (start, end) = entry.offsets;
+        let numel = entry.shape.iter().try_fold(1u64, |n, &d| n.checked_mul(d as u64));
+        let nbytes = numel.and_then(|n| n.checked_mul(kind.elt_size_in_bytes() as u64));
+        if nbytes != Some(end - start) {
+            return Err(wrap_err(path, SafeTensorError::InvalidOffset(entry.name)));
+        }
+        data.resize((end - start) as usize, 0);
+        file.seek(SeekFrom::Start(data_start + start)).map_err(|e| wrap_err(path, e.into()))?;
+        file.read_exact(&mut data).map_err(|e| wrap_err(path, e.into()))?;
+        f(entry.name, Tensor::f_from_data_size(&data, &entry.shape, kind)?)?
+    }
+    Ok(())
+}
+
+fn write_safetensors<S: AsRef<str>, T: AsRef<Tensor>, P: AsRef<Path>>(
+    tensors: &[(S, T)],
+    metadata: Option<HashMap<String, String>>,
+    path: P,
+) -> Result<(), TchError> {
+    let views = tensors
+        .iter()
+        .map(|(name, tensor)| {
+            Ok::<(&str, SafeView), TchError>((name.as_ref(), tensor.as_ref().try_into()?))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    safetensors::tensor::serialize_to_file(views, &metadata, path.as_ref())
+        .map_err(|e| wrap_err(path, e))?;
+    Ok(())
+}
+
+// The limit used by the safetensors crate.
+const MAX_HEADER_SIZE: u64 = 100_000_000;
+
+// The entry of a tensor in the header of a safetensors file, the offsets are
+// relative to the start of the tensor data.
+struct TensorEntry {
+    name: String,
+    dtype: String,
+    shape: Vec<i64>,
+    offsets: (u64, u64),
+}
+
+// A minimal JSON parser extracting the `__metadata__` map or the tensor
+// entries from the header of a safetensors file.
+struct HeaderParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl HeaderParser<'_> {
+    fn skip_whitespaces(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1
+        }
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<()> {
+        self.skip_whitespaces();
+        (self.next()? == expected).then_some(())
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespaces();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            match self.next()? {
+                b'"' => return String::from_utf8(bytes).ok(),
+                b'\\' => {
+                    let c = match self.next()? {
+                        c @ (b'"' | b'\\' | b'/') => c as char,
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.hex4()?;
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                (self.next()? == b'\\' && self.next()? == b'u').then_some(())?;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return None;
+                                }
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                high
+                            };
+                            char::from_u32(code)?
+                        }
+                        _ => return None,
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                c => bytes.push(c),
+            }
+        }
+    }
+
+    // Parses an object calling `f` on each key, `f` has to consume the value.
+    fn object<F: FnMut(&mut Self, String) -> Option<()>>(&mut self, mut f: F) -> Option<()> {
+        self.expect(b'{')?;
+        if self.peek()? == b'}' {
+            self.pos += 1;
+            return Some(());
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            f(self, key)?;
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b'}' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'"' => self.string().map(|_| ()),
+            b'{' => self.object(|p, _| p.skip_value()),
+            b'[' => {
+                self.pos += 1;
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Some(());
+                }
+                loop {
+                    self.skip_value()?;
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b']' => {
+                            self.pos += 1;
+                            return Some(());
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                // Numbers and literals.
+                let start = self.pos;
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || b"+-.".contains(c))
+                {
+                    self.pos += 1
+                }
+                (self.pos > start).then_some(())
+            }
+        }
+    }
+
+    fn uint(&mut self) -> Option<u64> {
+        self.skip_whitespaces();
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos]).ok()?.parse().ok()
+    }
+
+    fn uints(&mut self) -> Option<Vec<u64>> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        if self.peek()? == b']' {
+            self.pos += 1;
+            return Some(values);
+        }
+        loop {
+            values.push(self.uint()?);
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b']' => {
+                    self.pos += 1;
+                    return Some(values);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn tensor_entries(&mut self) -> Option<Vec<TensorEntry>> {
+        let mut entries = vec![];
+        self.object(|p, name| {
+            if name == "__metadata__" {
+                return p.skip_value();
+            }
+            let (mut dtype, mut shape, mut offsets) = (None, None, None);
+            p.object(|p, key| {
+                match key.as_str() {
+                    "dtype" => dtype = Some(p.string()?),
+                    "shape" => shape = Some(p.uints()?),
+                    "data_offsets" => offsets = Some(p.uints()?),
+                    _ => p.skip_value()?,
+                }
+                Some(())
+            })?;
+            let offsets = match offsets?[..] {
+                [start, end] if start <= end => (start, end),
+                _ => return None,
+            };
+            let shape = shape?.into_iter().map(|d| i64::try_from(d).ok()).collect::<Option<_>>()?;
+            entries.push(TensorEntry { name, dtype: dtype?, shape, offsets });
+            Some(())
+        })?;
+        self.skip_whitespaces();
+        (self.pos == self.bytes.len()).then_some(entries)
+    }
+
+    fn metadata(&mut self) -> Option<HashMap<String, String>> {
+        let mut metadata = HashMap::new();
+        self.object(|p, key| {
+            if key == "__metadata__" {
+                p.object(|p, key| {
+                    metadata.insert(key, p.string()?);
+                    Some(())
+                })
+            } else {
+                p.skip_value()
+            }
+        })?;
+        self.skip_whitespaces();
+        (self.pos == self.bytes.len()).then_some(metadata)
     }
 }
 
@@ -180,4 +496,41 @@ mod tests {
         assert_eq!(TryInto::<Kind>::try_into(Dtype::I8).unwrap(), Kind::Int8);
         assert_eq!(TryInto::<Kind>::try_into(Dtype::U8).unwrap(), Kind::Uint8);
     }
+
+    #[test]
+    fn header_metadata() {
+        let parse =
+            |header: &str| super::HeaderParser { bytes: header.as_bytes(), pos: 0 }.metadata();
+        let header = r#"{"a":{"dtype":"F32","shape":[2, 3],"data_offsets":[0,24]},
+            "__metadata__":{"epoch":"12","note":"caf\u00e9 \"q\" \ud83d\ude00"}}"#;
+        let metadata = parse(header).unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["epoch"], "12");
+        assert_eq!(metadata["note"], "café \"q\" \u{1f600}");
+        assert_eq!(
+            parse(r#"{"a":{"dtype":"F32","shape":[],"data_offsets":[0,4]}}"#).unwrap().len(),
+            0
+        );
+        assert!(parse(r#"{"__metadata__":{"epoch":12}}"#).is_none());
+        assert!(parse(r#"{"a":[1,2}"#).is_none());
+    }
+
+    #[test]
+    fn header_tensor_entries() {
+        let parse = |header: &str| {
+            super::HeaderParser { bytes: header.as_bytes(), pos: 0 }.tensor_entries()
+        };
+        let header = r#"{"__metadata__":{"epoch":"12"},
+            "a":{"dtype":"F32","shape":[2, 3],"data_offsets":[0,24]},
+            "b":{"shape":[],"dtype":"BF16","data_offsets":[24, 26]}}"#;
+        let entries = parse(header).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].name.as_str(), entries[0].dtype.as_str()), ("a", "F32"));
+        assert_eq!((&entries[0].shape, entries[0].offsets), (&vec![2, 3], (0, 24)));
+        assert_eq!((entries[1].name.as_str(), entries[1].dtype.as_str()), ("b", "BF16"));
+        assert_eq!((&entries[1].shape, entries[1].offsets), (&vec![], (24, 26)));
+        assert!(parse(r#"{"a":{"dtype":"F32","shape":[2],"data_offsets":[8,0]}}"#).is_none());
+        assert!(parse(r#"{"a":{"dtype":"F32","shape":[-2],"data_offsets":[0,8]}}"#).is_none());
+        assert!(parse(r#"{"a":{"shape":[2],"data_offsets":[0,8]}}"#).is_none());
+    }
 }
@@ -0,0 +1,165 @@
+//! Tensors shared between threads.
+use crate::{Device, Kind, TchError, Tensor};
+use std::sync::Arc;
+
+// Reading a tensor, i.e. running operations that take it as an input and
+// return new tensors, is thread-safe in libtorch: the reference count of the
+// underlying tensor implementation is atomic and autograd supports concurrent
+// forward passes over shared parameters.
+struct Inner(Tensor);
+
+unsafe impl Sync for Inner {}
+
+/// A reference counted tensor that can be shared between threads.
+///
+/// `Tensor` is `Send` but not `Sync`: a tensor can be moved to another thread
+/// but not accessed from multiple threads at once. A `SharedTensor` is both
+/// `Send` and `Sync`, cloning it is cheap and returns a handle to the same
+/// tensor.
+///
+/// The tensor cannot be modified while it is shared: rather than giving access
+/// to the underlying `Tensor`, which would allow in-place writes through
+/// `shallow_clone`, views or the `_out` operations, a shared tensor only
+/// exposes a few operations that return newly allocated tensors. Use
+/// `SharedTensor::try_unwrap` to get exclusive access back once all the other
+/// handles have been dropped.
+#[derive(Clone)]
+pub struct SharedTensor {
+    inner: Arc<Inner>,
+}
+
+impl SharedTensor {
+    pub fn new(tensor: Tensor) -> SharedTensor {
+        SharedTensor { inner: Arc::new(Inner(tensor)) }
+    }
+
+    /// Returns the tensor if this is the only handle to it, otherwise the
+    /// shared tensor is returned back as the error.
+    pub fn try_unwrap(this: SharedTensor) -> Result<Tensor, SharedTensor> {
+        match Arc::try_unwrap(this.inner) {
+            Ok(Inner(tensor)) => Ok(tensor),
+            Err(inner) => Err(SharedTensor { inner }),
+        }
+    }
+
+    /// The number of handles to this tensor.
+    pub fn handle_count(this: &SharedTensor) -> usize {
+        Arc::strong_count(&this.inner)
+    }
+
+    fn tensor(&self) -> &Tensor {
+        &self.inner.0
+    }
+
+    pub fn f_size(&self) -> Result<Vec<i64>, TchError> {
+        self.tensor().f_size()
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_size"))]
+    pub fn size(&self) -> Vec<i64> {
+        self.f_size().unwrap()
+    }
+
+    pub fn f_dim(&self) -> Result<usize, TchError> {
+        self.tensor().f_dim()
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_dim"))]
+    pub fn dim(&self) -> usize {
+        self.f_dim().unwrap()
+    }
+
+    pub fn numel(&self) -> usize {
+        self.tensor().numel()
+    }
+
+    pub fn f_kind(&self) -> Result<Kind, TchError> {
+        self.tensor().f_kind()
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_kind"))]
+    pub fn kind(&self) -> Kind {
+        self.f_kind().unwrap()
+    }
+
+    pub fn device(&self) -> Device {
+        self.tensor().device()
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.tensor().requires_grad()
+    }
+
+    /// Copies the tensor to a newly allocated tensor on the same device.
+    pub fn f_copy(&self) -> Result<Tensor, TchError> {
+        self.tensor().f_copy()
+    }
+
+    /// Copies the tensor to a newly allocated tensor on the same device.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_copy"))]
+    pub fn copy(&self) -> Tensor {
+        self.f_copy().unwrap()
+    }
+
+    /// Copies the tensor to a newly allocated tensor on the given device, a copy
+    /// is made even if the tensor is already on this device.
+    pub fn f_to_device(&self, device: Device) -> Result<Tensor, TchError> {
+        self.tensor().f_to_device_(device, self.f_kind()?, false, true)
+    }
+
+    /// Copies the tensor to a newly allocated tensor on the given device.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_to_device"))]
+    pub fn to_device(&self, device: Device) -> Tensor {
+        self.f_to_device(device).unwrap()
+    }
+
+    /// Copies the values of the tensor into `dst`, converting them to the kind
+    /// and device of `dst`. The copy is not recorded by autograd.
+    pub fn f_copy_to(&self, dst: &mut Tensor) -> Result<(), TchError> {
+        crate::no_grad(|| dst.f_copy_(self.tensor()))
+    }
+
+    /// Returns a newly allocated tensor with the entries at `index` along
+    /// dimension `dim`.
+    pub fn f_index_select(&self, dim: i64, index: &Tensor) -> Result<Tensor, TchError> {
+        self.tensor().f_index_select(dim, index)
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_index_select"))]
+    pub fn index_select(&self, dim: i64, index: &Tensor) -> Tensor {
+        self.f_index_select(dim, index).unwrap()
+    }
+
+    /// Applies a linear transformation to `xs` using this tensor as the weight,
+    /// i.e. computes `xs * self^T + bias`.
+    pub fn f_linear(&self, xs: &Tensor, bias: Option<&SharedTensor>) -> Result<Tensor, TchError> {
+        xs.f_linear(self.tensor(), bias.map(SharedTensor::tensor))
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_linear"))]
+    pub fn linear(&self, xs: &Tensor, bias: Option<&SharedTensor>) -> Tensor {
+        self.f_linear(xs, bias).unwrap()
+    }
+
+    /// Returns the matrix product `xs * self`.
+    pub fn f_matmul(&self, xs: &Tensor) -> Result<Tensor, TchError> {
+        xs.f_matmul(self.tensor())
+    }
+
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_matmul"))]
+    pub fn matmul(&self, xs: &Tensor) -> Tensor {
+        self.f_matmul(xs).unwrap()
+    }
+}
+
+impl From<Tensor> for SharedTensor {
+    fn from(tensor: Tensor) -> SharedTensor {
+        SharedTensor::new(tensor)
+    }
+}
+
+impl std::fmt::Debug for SharedTensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.tensor().fmt(f)
+    }
+}
@@ -0,0 +1,302 @@
+//! The built-in training callbacks.
+use super::{save_checkpoint, Callback, Context, TensorboardWriter, TrainState};
+use crate::TchError;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// A destination for the metrics computed at the end of each epoch.
+pub trait MetricSink {
+    fn write(&mut self, epoch: usize, metrics: &BTreeMap<String, f64>) -> Result<(), TchError>;
+}
+
+/// Prints the metrics on stdout, one line per epoch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrintSink;
+
+impl MetricSink for PrintSink {
+    fn write(&mut self, epoch: usize, metrics: &BTreeMap<String, f64>) -> Result<(), TchError> {
+        let metrics: Vec<String> = metrics.iter().map(|(k, v)| format!("{k}: {v:.5}")).collect();
+        println!("epoch: {epoch:4} {}", metrics.join(" "));
+        Ok(())
+    }
+}
+
+impl<F> MetricSink for F
+where
+    F: FnMut(usize, &BTreeMap<String, f64>),
+{
+    fn write(&mut self, epoch: usize, metrics: &BTreeMap<String, f64>) -> Result<(), TchError> {
+        self(epoch, metrics);
+        Ok(())
+    }
+}
+
+impl MetricSink for TensorboardWriter {
+    fn write(&mut self, epoch: usize, metrics: &BTreeMap<String, f64>) -> Result<(), TchError> {
+        for (name, value) in metrics.iter() {
+            self.add_scalar(name, *value, epoch as i64)?
+        }
+        self.flush()
+    }
+}
+
+/// Writes the epoch metrics to some sinks and optionally prints the running
+/// training loss every few batches.
+pub struct MetricLogger {
+    sinks: Vec<Box<dyn MetricSink>>,
+    log_every: Option<usize>,
+    sum_loss: f64,
+    batches: usize,
+}
+
+impl std::fmt::Debug for MetricLogger {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MetricLogger")
+            .field("sinks", &self.sinks.len())
+            .field("log_every", &self.log_every)
+            .finish()
+    }
+}
+
+impl MetricLogger {
+    pub fn new<S: MetricSink + 'static>(sink: S) -> MetricLogger {
+        MetricLogger { sinks: vec![Box::new(sink)], log_every: None, sum_loss: 0., batches: 0 }
+    }
+
+    /// Adds another sink for the epoch metrics.
+    pub fn add_sink<S: MetricSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Prints the average training loss of the current epoch every `n` batches.
+    pub fn log_every(mut self, n: usize) -> Self {
+        self.log_every = Some(n);
+        self
+    }
+}
+
+impl Callback for MetricLogger {
+    fn on_epoch_begin(&mut self, _ctx: &mut Context, _epoch: usize) -> Result<(), TchError> {
+        self.sum_loss = 0.;
+        self.batches = 0;
+        Ok(())
+    }
+
+    fn on_batch_end(&mut self, ctx: &mut Context, batch: usize, loss: f64) -> Result<(), TchError> {
+        self.sum_loss += loss;
+        self.batches += 1;
+        if let Some(n) = self.log_every {
+            if (batch + 1).is_multiple_of(n) {
+                let avg = self.sum_loss / self.batches as f64;
+                println!("epoch: {:4} batch: {:6} train_loss: {avg:.5}", ctx.state.epoch, batch + 1)
+            }
+        }
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, ctx: &mut Context, epoch: usize) -> Result<(), TchError> {
+        if let Some(metrics) = ctx.state.last_metrics() {
+            for sink in self.sinks.iter_mut() {
+                sink.write(epoch, metrics)?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether lower or higher values of a monitored metric are better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Min,
+    Max,
+}
+
+// Tracks the best value of a metric.
+#[derive(Debug, Clone)]
+struct Best {
+    monitor: String,
+    mode: Mode,
+    min_delta: f64,
+    value: Option<f64>,
+}
+
+impl Best {
+    fn new(monitor: &str, mode: Mode) -> Best {
+        Best { monitor: monitor.to_string(), mode, min_delta: 0., value: None }
+    }
+
+    // Returns true if the value is an improvement over the best one so far.
+    fn update(&mut self, value: f64) -> bool {
+        let improved = match (self.value, self.mode) {
+            (None, _) => true,
+            (Some(best), Mode::Min) => value < best - self.min_delta,
+            (Some(best), Mode::Max) => value > best + self.min_delta,
+        };
+        if improved {
+            self.value = Some(value)
+        }
+        improved
+    }
+
+    // Replays the history of a training state, returning for each epoch
+    // whether the metric improved.
+    fn replay(&mut self, state: &TrainState) -> Result<Vec<bool>, TchError> {
+        self.value = None;
+        let mut improved = vec![];
+        for m in state.history.iter() {
+            let value = m.metrics.get(&self.monitor).copied().ok_or_else(|| {
+                TchError::MissingMetric(self.monitor.clone(), format!("epoch {}", m.epoch))
+            })?;
+            improved.push(self.update(value))
+        }
+        Ok(improved)
+    }
+}
+
+/// Stops the training when a monitored metric has not improved for
+/// `patience` epochs.
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    best: Best,
+    patience: usize,
+    wait: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: &str, mode: Mode, patience: usize) -> EarlyStopping {
+        EarlyStopping { best: Best::new(monitor, mode), patience, wait: 0 }
+    }
+
+    /// The minimal change of the metric to be counted as an improvement.
+    pub fn min_delta(mut self, min_delta: f64) -> Self {
+        self.best.min_delta = min_delta;
+        self
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_epoch_end(&mut self, ctx: &mut Context, _epoch: usize) -> Result<(), TchError> {
+        let value = ctx.state.metric(&self.best.monitor)?;
+        if self.best.update(value) {
+            self.wait = 0
+        } else {
+            self.wait += 1;
+            if self.wait >= self.patience {
+                ctx.request_stop()
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, state: &TrainState) -> Result<(), TchError> {
+        let improved = self.best.replay(state)?;
+        self.wait = improved.iter().rev().take_while(|improved| !**improved).count();
+        Ok(())
+    }
+}
+
+/// Saves checkpoints that can be used to resume the training with
+/// `Trainer::resume`, and optionally the variables of the best model.
+///
+/// A checkpoint is saved every `every` epochs, at the end of the training, and
+/// when the training is interrupted. The best model variables are saved in
+/// `best_model.ot`.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    every: usize,
+    best: Option<Best>,
+}
+
+impl Checkpoint {
+    pub fn new<T: Into<PathBuf>>(dir: T) -> Checkpoint {
+        Checkpoint { dir: dir.into(), every: 1, best: None }
+    }
+
+    /// Saves a checkpoint every `every` epochs, the default is 1.
+    pub fn every(mut self, every: usize) -> Self {
+        self.every = every;
+        self
+    }
+
+    /// Saves the variables in `best_model.ot` when a monitored metric improves.
+    pub fn track_best(mut self, monitor: &str, mode: Mode) -> Self {
+        self.best = Some(Best::new(monitor, mode));
+        self
+    }
+
+    fn save(&self, ctx: &mut Context) -> Result<(), TchError> {
+        save_checkpoint(&self.dir, ctx.vs, ctx.opt, ctx.state)
+    }
+}
+
+impl Callback for Checkpoint {
+    fn on_epoch_end(&mut self, ctx: &mut Context, epoch: usize) -> Result<(), TchError> {
+        if let Some(best) = self.best.as_mut() {
+            if best.update(ctx.state.metric(&best.monitor)?) {
+                std::fs::create_dir_all(&self.dir)?;
+                ctx.vs.save(self.dir.join("best_model.ot"))?
+            }
+        }
+        if (epoch + 1).is_multiple_of(self.every) {
+            self.save(ctx)?
+        }
+        Ok(())
+    }
+
+    fn on_train_end(&mut self, ctx: &mut Context) -> Result<(), TchError> {
+        self.save(ctx)
+    }
+
+    fn on_interrupt(&mut self, ctx: &mut Context) -> Result<(), TchError> {
+        self.save(ctx)
+    }
+
+    fn restore(&mut self, state: &TrainState) -> Result<(), TchError> {
+        if let Some(best) = self.best.as_mut() {
+            best.replay(state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets the learning rate at the beginning of each epoch to a function of the
+/// epoch number. The schedule only depends on the epoch so it is restored
+/// when resuming.
+pub struct LrScheduler {
+    f: Box<dyn Fn(usize) -> f64>,
+}
+
+impl std::fmt::Debug for LrScheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LrScheduler")
+    }
+}
+
+impl LrScheduler {
+    pub fn new<F: Fn(usize) -> f64 + 'static>(f: F) -> LrScheduler {
+        LrScheduler { f: Box::new(f) }
+    }
+
+    /// Multiplies the learning rate by `gamma` every `step_size` epochs.
+    pub fn step(lr: f64, step_size: usize, gamma: f64) -> LrScheduler {
+        LrScheduler::new(move |epoch| lr * gamma.powi((epoch / step_size.max(1)) as i32))
+    }
+
+    /// Anneals the learning rate from `lr` to `min_lr` with a cosine over
+    /// `epochs` epochs.
+    pub fn cosine(lr: f64, min_lr: f64, epochs: usize) -> LrScheduler {
+        LrScheduler::new(move |epoch| {
+            let t = epoch.min(epochs) as f64 / epochs.max(1) as f64;
+            min_lr + (lr - min_lr) * (1. + (std::f64::consts::PI * t).cos()) / 2.
+        })
+    }
+}
+
+impl Callback for LrScheduler {
+    fn on_epoch_begin(&mut self, ctx: &mut Context, epoch: usize) -> Result<(), TchError> {
+        ctx.opt.set_lr((self.f)(epoch));
+        Ok(())
+    }
+}
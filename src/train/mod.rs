@@ -0,0 +1,405 @@
+//! A training loop with callbacks.
+//!
+//! The `Trainer` runs the epochs, optionally evaluates the model after each
+//! epoch, and calls the callbacks that handle logging, early stopping,
+//! checkpointing, and learning rate schedules.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), tch::TchError> {
+//! use tch::nn::{self, ModuleT, OptimizerConfig};
+//! use tch::train::{Checkpoint, EarlyStopping, MetricLogger, Mode, PrintSink, Trainer};
+//! let m = tch::vision::mnist::load_dir("data")?;
+//! let mut vs = nn::VarStore::new(tch::Device::Cpu);
+//! let net = nn::seq().add(nn::linear(vs.root(), 784, 10, Default::default()));
+//! let opt = nn::Adam::default().build(&vs, 1e-3)?;
+//! let callbacks: Vec<Box<dyn tch::train::Callback>> = vec![
+//!     Box::new(MetricLogger::new(PrintSink)),
+//!     Box::new(EarlyStopping::new("test_acc", Mode::Max, 3)),
+//!     Box::new(Checkpoint::new("checkpoints").track_best("test_acc", Mode::Max)),
+//! ];
+//! let mut trainer = Trainer::new(&mut vs, opt, callbacks).max_epochs(10).validation(|| {
+//!     let acc = net.batch_accuracy_for_logits(&m.test_images, &m.test_labels, tch::Device::Cpu, 1024);
+//!     Ok(vec![("test_acc".to_string(), acc)])
+//! });
+//! trainer.fit_module(&net, || m.train_iter(256), |logits, ys| logits.cross_entropy_for_logits(ys))?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::nn::{write_atomic, ModuleT, Optimizer, VarStore};
+use crate::{TchError, Tensor};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod callbacks;
+pub use callbacks::{
+    Checkpoint, EarlyStopping, LrScheduler, MetricLogger, MetricSink, Mode, PrintSink,
+};
+
+mod tensorboard;
+pub use tensorboard::TensorboardWriter;
+
+/// The metrics of a completed epoch, the average training loss is stored as
+/// `train_loss`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochMetrics {
+    pub epoch: usize,
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// The training progress, saved in the checkpoints so that training can be
+/// resumed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrainState {
+    /// The number of completed epochs.
+    pub epoch: usize,
+    pub history: Vec<EpochMetrics>,
+    /// True if the last call to `fit` was interrupted with Ctrl-C.
+    pub interrupted: bool,
+}
+
+impl TrainState {
+    /// The metrics of the last completed epoch.
+    pub fn last_metrics(&self) -> Option<&BTreeMap<String, f64>> {
+        self.history.last().map(|m| &m.metrics)
+    }
+
+    /// Returns a metric of the last completed epoch.
+    pub fn metric(&self, name: &str) -> Result<f64, TchError> {
+        let metrics = self.last_metrics().ok_or_else(|| {
+            TchError::MissingMetric(name.to_string(), "no completed epoch".to_string())
+        })?;
+        metrics.get(name).copied().ok_or_else(|| {
+            let available = metrics.keys().cloned().collect::<Vec<_>>().join(", ");
+            TchError::MissingMetric(name.to_string(), format!("available metrics: {available}"))
+        })
+    }
+
+    // The state is saved as a text file with one line per metric, the metric
+    // name comes last as it can contain spaces. The f64 values round-trip
+    // through their Display representation.
+    fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), TchError> {
+        let mut lines = vec![format!("epoch {}", self.epoch)];
+        for m in self.history.iter() {
+            for (name, value) in m.metrics.iter() {
+                lines.push(format!("metric {} {value} {name}", m.epoch))
+            }
+        }
+        lines.push(String::new());
+        write_atomic(path.as_ref(), |tmp_path| Ok(std::fs::write(tmp_path, lines.join("\n"))?))
+    }
+
+    fn load<T: AsRef<Path>>(path: T) -> Result<TrainState, TchError> {
+        let path = path.as_ref();
+        let err = |line: &str| TchError::FileFormat(format!("{path:?}: unexpected line {line}"));
+        let mut state = TrainState::default();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.splitn(4, ' ').collect();
+            match fields.as_slice() {
+                ["epoch", epoch] => state.epoch = epoch.parse()?,
+                ["metric", epoch, value, name] => {
+                    let epoch: usize = epoch.parse()?;
+                    let value: f64 = value.parse().map_err(|_| err(line))?;
+                    if state.history.last().map(|m| m.epoch) != Some(epoch) {
+                        state.history.push(EpochMetrics { epoch, metrics: BTreeMap::new() })
+                    }
+                    state.history.last_mut().unwrap().metrics.insert(name.to_string(), value);
+                }
+                [""] => {}
+                _ => return Err(err(line)),
+            }
+        }
+        Ok(state)
+    }
+}
+
+// The layout of a checkpoint directory.
+const MODEL_FILE: &str = "model.ot";
+const OPTIMIZER_FILE: &str = "optimizer.pt";
+const STATE_FILE: &str = "state.txt";
+
+/// Saves the variables, the optimizer state, and the training state in a
+/// checkpoint directory.
+///
+/// Each file is written to a temporary file which is then renamed, so a crash
+/// while saving does not leave truncated files behind.
+pub fn save_checkpoint<T: AsRef<Path>>(
+    dir: T,
+    vs: &VarStore,
+    opt: &mut Optimizer,
+    state: &TrainState,
+) -> Result<(), TchError> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    vs.save(dir.join(MODEL_FILE))?;
+    write_atomic(&dir.join(OPTIMIZER_FILE), |tmp_path| opt.save(tmp_path))?;
+    // The state file is written last so that a partially written checkpoint is
+    // not picked up when resuming.
+    state.save(dir.join(STATE_FILE))
+}
+
+/// Loads a checkpoint saved with `save_checkpoint`, restoring the variables
+/// and the optimizer state, and returns the training state.
+pub fn load_checkpoint<T: AsRef<Path>>(
+    dir: T,
+    vs: &mut VarStore,
+    opt: &mut Optimizer,
+) -> Result<TrainState, TchError> {
+    let dir = dir.as_ref();
+    let state = TrainState::load(dir.join(STATE_FILE))?;
+    vs.load(dir.join(MODEL_FILE))?;
+    opt.load(dir.join(OPTIMIZER_FILE))?;
+    Ok(state)
+}
+
+/// What the callbacks can access while training.
+pub struct Context<'a> {
+    pub vs: &'a VarStore,
+    pub opt: &'a mut Optimizer,
+    /// The training state, the metrics of an epoch are added to the history
+    /// before `on_epoch_end` is called.
+    pub state: &'a TrainState,
+    stop: &'a mut bool,
+}
+
+impl<'a> Context<'a> {
+    /// Stops the training at the end of the current epoch.
+    pub fn request_stop(&mut self) {
+        *self.stop = true
+    }
+}
+
+/// Callbacks run at the different stages of the training loop, epochs are
+/// numbered from 0.
+pub trait Callback {
+    fn on_train_begin(&mut self, _ctx: &mut Context) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    fn on_epoch_begin(&mut self, _ctx: &mut Context, _epoch: usize) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    fn on_batch_end(
+        &mut self,
+        _ctx: &mut Context,
+        _batch: usize,
+        _loss: f64,
+    ) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, _ctx: &mut Context, _epoch: usize) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    fn on_train_end(&mut self, _ctx: &mut Context) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    /// Called when the training is interrupted with Ctrl-C, before `fit`
+    /// returns. The interrupted epoch is not part of the training state.
+    fn on_interrupt(&mut self, _ctx: &mut Context) -> Result<(), TchError> {
+        Ok(())
+    }
+
+    /// Restores the callback state when resuming from a checkpoint.
+    fn restore(&mut self, _state: &TrainState) -> Result<(), TchError> {
+        Ok(())
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst)
+}
+
+// Installs the Ctrl-C handler and restores the previous one when dropped.
+struct SigintGuard(libc::sighandler_t);
+
+impl SigintGuard {
+    fn new() -> SigintGuard {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        SigintGuard(unsafe { libc::signal(libc::SIGINT, handler) })
+    }
+}
+
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        unsafe { libc::signal(libc::SIGINT, self.0) };
+    }
+}
+
+type Validation<'a> = Box<dyn FnMut() -> Result<Vec<(String, f64)>, TchError> + 'a>;
+
+/// A training loop over epochs running callbacks.
+pub struct Trainer<'a> {
+    vs: &'a mut VarStore,
+    opt: Optimizer,
+    callbacks: Vec<Box<dyn Callback + 'a>>,
+    validation: Option<Validation<'a>>,
+    max_epochs: usize,
+    seed: Option<i64>,
+    handle_ctrl_c: bool,
+    state: TrainState,
+}
+
+impl<'a> std::fmt::Debug for Trainer<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Trainer")
+            .field("max_epochs", &self.max_epochs)
+            .field("seed", &self.seed)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<'a> Trainer<'a> {
+    /// Creates a trainer for the variables of `vs` optimized by `opt`.
+    pub fn new(
+        vs: &'a mut VarStore,
+        opt: Optimizer,
+        callbacks: Vec<Box<dyn Callback + 'a>>,
+    ) -> Trainer<'a> {
+        Trainer {
+            vs,
+            opt,
+            callbacks,
+            validation: None,
+            max_epochs: 1,
+            seed: None,
+            handle_ctrl_c: true,
+            state: TrainState::default(),
+        }
+    }
+
+    /// The number of epochs after which `fit` stops, including the epochs
+    /// completed before resuming.
+    pub fn max_epochs(mut self, max_epochs: usize) -> Self {
+        self.max_epochs = max_epochs;
+        self
+    }
+
+    /// Seeds the random number generator at the beginning of each epoch with
+    /// `seed + epoch`, so that a resumed training continues identically.
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Stops the training cleanly on Ctrl-C, calling `on_interrupt` on the
+    /// callbacks, e.g. to save a final checkpoint. This is enabled by default,
+    /// the handler is only installed while `fit` runs.
+    pub fn handle_ctrl_c(mut self, handle_ctrl_c: bool) -> Self {
+        self.handle_ctrl_c = handle_ctrl_c;
+        self
+    }
+
+    /// Sets the validation pass run after each training epoch, the returned
+    /// metrics are added to the epoch metrics. Gradients are not tracked while
+    /// it runs.
+    pub fn validation<F>(mut self, f: F) -> Self
+    where
+        F: FnMut() -> Result<Vec<(String, f64)>, TchError> + 'a,
+    {
+        self.validation = Some(Box::new(f));
+        self
+    }
+
+    pub fn state(&self) -> &TrainState {
+        &self.state
+    }
+
+    pub fn optimizer(&mut self) -> &mut Optimizer {
+        &mut self.opt
+    }
+
+    /// Restores the variables, the optimizer, the training state, and the
+    /// callback states from a checkpoint directory, the next call to `fit`
+    /// continues from the saved epoch.
+    pub fn resume<T: AsRef<Path>>(&mut self, dir: T) -> Result<(), TchError> {
+        self.state = load_checkpoint(dir, self.vs, &mut self.opt)?;
+        for callback in self.callbacks.iter_mut() {
+            callback.restore(&self.state)?
+        }
+        Ok(())
+    }
+
+    fn run_callbacks<F>(&mut self, stop: &mut bool, mut f: F) -> Result<(), TchError>
+    where
+        F: FnMut(&mut dyn Callback, &mut Context) -> Result<(), TchError>,
+    {
+        for callback in self.callbacks.iter_mut() {
+            let mut ctx = Context { vs: &*self.vs, opt: &mut self.opt, state: &self.state, stop };
+            f(callback.as_mut(), &mut ctx)?
+        }
+        Ok(())
+    }
+
+    /// Trains until `max_epochs` epochs have been completed, a callback
+    /// requests a stop, or the training is interrupted.
+    ///
+    /// `data` returns the batch iterator for an epoch, `loss_fn` computes the
+    /// loss for a batch of inputs and targets in training mode.
+    pub fn fit<D, I, F>(&mut self, mut data: D, mut loss_fn: F) -> Result<&TrainState, TchError>
+    where
+        D: FnMut() -> I,
+        I: IntoIterator<Item = (Tensor, Tensor)>,
+        F: FnMut(&Tensor, &Tensor) -> Tensor,
+    {
+        let _sigint_guard = if self.handle_ctrl_c { Some(SigintGuard::new()) } else { None };
+        let mut stop = false;
+        self.state.interrupted = false;
+        self.run_callbacks(&mut stop, |c, ctx| c.on_train_begin(ctx))?;
+        while self.state.epoch < self.max_epochs && !stop {
+            let epoch = self.state.epoch;
+            if let Some(seed) = self.seed {
+                crate::manual_seed(seed + epoch as i64)
+            }
+            self.run_callbacks(&mut stop, |c, ctx| c.on_epoch_begin(ctx, epoch))?;
+            let (mut sum_loss, mut batches) = (0f64, 0usize);
+            for (xs, ys) in data() {
+                let loss = loss_fn(&xs, &ys);
+                self.opt.backward_step(&loss);
+                let loss = f64::try_from(&loss)?;
+                sum_loss += loss;
+                self.run_callbacks(&mut stop, |c, ctx| c.on_batch_end(ctx, batches, loss))?;
+                batches += 1;
+                if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                    self.state.interrupted = true;
+                    self.run_callbacks(&mut stop, |c, ctx| c.on_interrupt(ctx))?;
+                    return Ok(&self.state);
+                }
+            }
+            let mut metrics = BTreeMap::new();
+            metrics.insert("train_loss".to_string(), sum_loss / batches.max(1) as f64);
+            if let Some(validation) = self.validation.as_mut() {
+                let _no_grad = crate::no_grad_guard();
+                metrics.extend(validation()?);
+            }
+            self.state.history.push(EpochMetrics { epoch, metrics });
+            self.state.epoch += 1;
+            self.run_callbacks(&mut stop, |c, ctx| c.on_epoch_end(ctx, epoch))?;
+        }
+        self.run_callbacks(&mut stop, |c, ctx| c.on_train_end(ctx))?;
+        Ok(&self.state)
+    }
+
+    /// Trains a module, `loss_fn` computes the loss from the module outputs
+    /// in training mode and the targets, see `fit`.
+    pub fn fit_module<D, I, F>(
+        &mut self,
+        module: &dyn ModuleT,
+        data: D,
+        loss_fn: F,
+    ) -> Result<&TrainState, TchError>
+    where
+        D: FnMut() -> I,
+        I: IntoIterator<Item = (Tensor, Tensor)>,
+        F: Fn(&Tensor, &Tensor) -> Tensor,
+    {
+        self.fit(data, |xs, ys| loss_fn(&module.forward_t(xs, true), ys))
+    }
+}
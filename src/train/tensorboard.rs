@@ -0,0 +1,124 @@
+//! A minimal writer for the TensorBoard event files, only scalars are
+//! supported.
+use crate::TchError;
+use std::io::Write;
+use std::path::Path;
+
+// CRC-32C (Castagnoli) as used by the TFRecord format.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0x82f6_3b78 & mask);
+        }
+    }
+    !crc
+}
+
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+// Protobuf encoding helpers.
+fn write_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8)
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, field << 3 | 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes)
+}
+
+// Encodes an Event message, wall_time is field 1, step field 2, file_version
+// field 3, and summary field 5.
+fn event(step: i64, file_version: Option<&str>, summary: Option<&[u8]>) -> Vec<u8> {
+    let wall_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0., |d| d.as_secs_f64());
+    let mut buf = vec![];
+    write_varint(&mut buf, 1 << 3 | 1);
+    buf.extend_from_slice(&wall_time.to_le_bytes());
+    write_varint(&mut buf, 2 << 3);
+    write_varint(&mut buf, step as u64);
+    if let Some(file_version) = file_version {
+        write_bytes_field(&mut buf, 3, file_version.as_bytes())
+    }
+    if let Some(summary) = summary {
+        write_bytes_field(&mut buf, 5, summary)
+    }
+    buf
+}
+
+// Encodes a Summary with a single Value, tag is field 1 and simple_value
+// field 2 of the value.
+fn scalar_summary(tag: &str, value: f32) -> Vec<u8> {
+    let mut value_buf = vec![];
+    write_bytes_field(&mut value_buf, 1, tag.as_bytes());
+    write_varint(&mut value_buf, 2 << 3 | 5);
+    value_buf.extend_from_slice(&value.to_le_bytes());
+    let mut buf = vec![];
+    write_bytes_field(&mut buf, 1, &value_buf);
+    buf
+}
+
+/// Writes scalars to an event file that can be displayed with TensorBoard.
+#[derive(Debug)]
+pub struct TensorboardWriter {
+    file: std::io::BufWriter<std::fs::File>,
+}
+
+impl TensorboardWriter {
+    /// Creates a new event file in `logdir`, the directory is created if it
+    /// does not exist.
+    pub fn new<T: AsRef<Path>>(logdir: T) -> Result<TensorboardWriter, TchError> {
+        let logdir = logdir.as_ref();
+        std::fs::create_dir_all(logdir)?;
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let filename = format!("events.out.tfevents.{secs}.tch.{}", std::process::id());
+        let file = std::fs::File::create(logdir.join(filename))?;
+        let mut writer = TensorboardWriter { file: std::io::BufWriter::new(file) };
+        writer.write_record(&event(0, Some("brain.Event:2"), None))?;
+        Ok(writer)
+    }
+
+    // Writes a record in the TFRecord format: the length, its checksum, the
+    // data, and the data checksum.
+    fn write_record(&mut self, data: &[u8]) -> Result<(), TchError> {
+        let len = (data.len() as u64).to_le_bytes();
+        self.file.write_all(&len)?;
+        self.file.write_all(&masked_crc32c(&len).to_le_bytes())?;
+        self.file.write_all(data)?;
+        self.file.write_all(&masked_crc32c(data).to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Adds a scalar value for the given tag and step.
+    pub fn add_scalar(&mut self, tag: &str, value: f64, step: i64) -> Result<(), TchError> {
+        let summary = scalar_summary(tag, value as f32);
+        self.write_record(&event(step, None, Some(&summary)))
+    }
+
+    pub fn flush(&mut self) -> Result<(), TchError> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn crc32c() {
+        assert_eq!(super::crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(super::crc32c(b""), 0);
+    }
+}
@@ -0,0 +1,349 @@
+//! Tensors with their rank and kind checked at compile time.
+//!
+//! A `TypedTensor<K, D>` wraps a `Tensor` of kind `K` with `D` dimensions. The
+//! operations that change the rank, e.g. matrix multiplications, reshaping,
+//! squeezing, or indexing, have typed signatures so that rank mismatches are
+//! reported by the compiler rather than deep inside a model at runtime. All the
+//! other operations remain available through `Deref<Target = Tensor>` and
+//! return untyped tensors, which can be checked again with `try_from`. This is
+//! also the case for the typed operations that are not valid for the rank at
+//! hand, e.g. `get` on a tensor with no dimensions resolves to `Tensor::get`,
+//! so the mistake is caught when the result is used as a typed tensor.
+//!
+//! ```no_run
+//! # use tch::typed::{TypedTensor, F32};
+//! # use tch::Device;
+//! # fn main() -> Result<(), tch::TchError> {
+//! let xs = TypedTensor::<F32, 2>::randn([32, 512], Device::Cpu);
+//! let ws = TypedTensor::<F32, 2>::randn([512, 10], Device::Cpu);
+//! let ys: TypedTensor<F32, 2> = xs.matmul(&ws);
+//! let ys: TypedTensor<F32, 3> = ys.unsqueeze::<0>();
+//! let zs = TypedTensor::<F32, 3>::try_from(ys.relu())?;
+//! let v: f32 = zs.value([0, 1, 2]);
+//! # Ok(())
+//! # }
+//! ```
+use crate::kind::Element;
+use crate::{Device, Kind, TchError, Tensor};
+use std::marker::PhantomData;
+
+/// A marker type for the kind of the tensor elements.
+pub trait KindMarker: std::fmt::Debug + Send + 'static {
+    const KIND: Kind;
+    type Elem: Element + Copy;
+}
+
+macro_rules! kind_marker {
+    ($name:ident, $elem:ty, $kind:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {}
+
+        impl KindMarker for $name {
+            const KIND: Kind = Kind::$kind;
+            type Elem = $elem;
+        }
+    };
+}
+
+kind_marker!(U8, u8, Uint8);
+kind_marker!(I8, i8, Int8);
+kind_marker!(I16, i16, Int16);
+kind_marker!(I32, i32, Int);
+kind_marker!(I64, i64, Int64);
+kind_marker!(F16, half::f16, Half);
+kind_marker!(F32, f32, Float);
+kind_marker!(F64, f64, Double);
+kind_marker!(Bool, bool, Bool);
+
+/// A tensor of kind `K` with `D` dimensions, see the module documentation.
+#[derive(Debug)]
+pub struct TypedTensor<K: KindMarker, const D: usize> {
+    tensor: Tensor,
+    kind: PhantomData<K>,
+}
+
+/// Builds the result of a typed operation, the rank and kind have already been
+/// checked statically.
+#[doc(hidden)]
+pub trait FromTensorUnchecked {
+    fn from_tensor_unchecked(tensor: Tensor) -> Self;
+}
+
+impl<K: KindMarker, const D: usize> FromTensorUnchecked for TypedTensor<K, D> {
+    fn from_tensor_unchecked(tensor: Tensor) -> Self {
+        TypedTensor { tensor, kind: PhantomData }
+    }
+}
+
+/// Inserting a dimension at position `AXIS`.
+pub trait Unsqueeze<const AXIS: usize> {
+    type Output: FromTensorUnchecked;
+}
+
+/// Removing the dimension at position `AXIS`.
+pub trait Squeeze<const AXIS: usize> {
+    type Output: FromTensorUnchecked;
+}
+
+/// Selecting an element along the first dimension.
+pub trait Select {
+    type Output: FromTensorUnchecked;
+}
+
+/// Matrix products with the `Rhs` tensor, following the `Tensor::matmul`
+/// broadcasting rules.
+pub trait MatMul<Rhs> {
+    type Output: FromTensorUnchecked;
+}
+
+macro_rules! rank_up {
+    ($d:literal => $e:literal; $($axis:literal),*) => {
+        impl<K: KindMarker> Select for TypedTensor<K, $e> {
+            type Output = TypedTensor<K, $d>;
+        }
+        $(
+            impl<K: KindMarker> Unsqueeze<$axis> for TypedTensor<K, $d> {
+                type Output = TypedTensor<K, $e>;
+            }
+            impl<K: KindMarker> Squeeze<$axis> for TypedTensor<K, $e> {
+                type Output = TypedTensor<K, $d>;
+            }
+        )*
+    };
+}
+
+rank_up!(0 => 1; 0);
+rank_up!(1 => 2; 0, 1);
+rank_up!(2 => 3; 0, 1, 2);
+rank_up!(3 => 4; 0, 1, 2, 3);
+rank_up!(4 => 5; 0, 1, 2, 3, 4);
+rank_up!(5 => 6; 0, 1, 2, 3, 4, 5);
+
+macro_rules! matmul {
+    ($($d:literal, $e:literal => $out:literal;)*) => {
+        $(
+            impl<K: KindMarker> MatMul<TypedTensor<K, $e>> for TypedTensor<K, $d> {
+                type Output = TypedTensor<K, $out>;
+            }
+        )*
+    };
+}
+
+matmul! {
+    1, 1 => 0;
+    1, 2 => 1;
+    2, 1 => 1;
+    2, 2 => 2;
+    1, 3 => 2;
+    3, 1 => 2;
+    2, 3 => 3;
+    3, 2 => 3;
+    3, 3 => 3;
+    2, 4 => 4;
+    4, 2 => 4;
+    3, 4 => 4;
+    4, 3 => 4;
+    4, 4 => 4;
+}
+
+impl<K: KindMarker, const D: usize> TypedTensor<K, D> {
+    fn check(tensor: &Tensor) -> Result<(), TchError> {
+        let size = tensor.f_size()?;
+        if size.len() != D {
+            return Err(TchError::Shape(format!("expected {D} dimensions, got {size:?}")));
+        }
+        let kind = tensor.f_kind()?;
+        if kind != K::KIND {
+            return Err(TchError::Kind(format!("expected kind {:?}, got {kind:?}", K::KIND)));
+        }
+        Ok(())
+    }
+
+    pub fn f_zeros(size: [i64; D], device: Device) -> Result<Self, TchError> {
+        let tensor = Tensor::f_zeros(size, (K::KIND, device))?;
+        Ok(Self::from_tensor_unchecked(tensor))
+    }
+
+    pub fn zeros(size: [i64; D], device: Device) -> Self {
+        Self::f_zeros(size, device).unwrap()
+    }
+
+    /// Returns an error for kinds that do not support random normal values, e.g.
+    /// integer kinds.
+    pub fn f_randn(size: [i64; D], device: Device) -> Result<Self, TchError> {
+        let tensor = Tensor::f_randn(size, (K::KIND, device))?;
+        Ok(Self::from_tensor_unchecked(tensor))
+    }
+
+    pub fn randn(size: [i64; D], device: Device) -> Self {
+        Self::f_randn(size, device).unwrap()
+    }
+
+    /// The untyped tensor.
+    pub fn as_tensor(&self) -> &Tensor {
+        &self.tensor
+    }
+
+    /// Converts to an untyped tensor.
+    pub fn into_dyn(self) -> Tensor {
+        self.tensor
+    }
+
+    pub fn shallow_clone(&self) -> Self {
+        Self::from_tensor_unchecked(self.tensor.shallow_clone())
+    }
+
+    /// The tensor dimensions.
+    pub fn size(&self) -> [i64; D] {
+        let mut size = [0; D];
+        size.copy_from_slice(&self.tensor.size());
+        size
+    }
+
+    pub fn f_to_kind<K2: KindMarker>(&self) -> Result<TypedTensor<K2, D>, TchError> {
+        let tensor = self.tensor.f_to_kind(K2::KIND)?;
+        Ok(TypedTensor::from_tensor_unchecked(tensor))
+    }
+
+    pub fn to_kind<K2: KindMarker>(&self) -> TypedTensor<K2, D> {
+        self.f_to_kind().unwrap()
+    }
+
+    /// Reshapes the tensor, the rank of the result is the length of `shape`. An
+    /// error is returned if the number of elements does not match.
+    pub fn f_reshape<const N: usize>(
+        &self,
+        shape: [i64; N],
+    ) -> Result<TypedTensor<K, N>, TchError> {
+        let tensor = self.tensor.f_reshape(shape)?;
+        Ok(TypedTensor::from_tensor_unchecked(tensor))
+    }
+
+    pub fn reshape<const N: usize>(&self, shape: [i64; N]) -> TypedTensor<K, N> {
+        self.f_reshape(shape).unwrap()
+    }
+
+    /// Inserts a dimension of size one at position `AXIS`.
+    pub fn unsqueeze<const AXIS: usize>(&self) -> <Self as Unsqueeze<AXIS>>::Output
+    where
+        Self: Unsqueeze<AXIS>,
+    {
+        let tensor = self.tensor.unsqueeze(AXIS as i64);
+        FromTensorUnchecked::from_tensor_unchecked(tensor)
+    }
+
+    /// Removes the dimension at position `AXIS`, an error is returned if this
+    /// dimension does not have size one.
+    pub fn f_squeeze<const AXIS: usize>(&self) -> Result<<Self as Squeeze<AXIS>>::Output, TchError>
+    where
+        Self: Squeeze<AXIS>,
+    {
+        let size = self.tensor.f_size()?;
+        if size[AXIS] != 1 {
+            return Err(TchError::Shape(format!(
+                "cannot squeeze dimension {AXIS} with size {}, shape {size:?}",
+                size[AXIS]
+            )));
+        }
+        let tensor = self.tensor.f_squeeze_dim(AXIS as i64)?;
+        Ok(FromTensorUnchecked::from_tensor_unchecked(tensor))
+    }
+
+    pub fn squeeze<const AXIS: usize>(&self) -> <Self as Squeeze<AXIS>>::Output
+    where
+        Self: Squeeze<AXIS>,
+    {
+        self.f_squeeze::<AXIS>().unwrap()
+    }
+
+    /// Selects the element at `index` along the first dimension.
+    pub fn f_get(&self, index: i64) -> Result<<Self as Select>::Output, TchError>
+    where
+        Self: Select,
+    {
+        let tensor = self.tensor.f_get(index)?;
+        Ok(FromTensorUnchecked::from_tensor_unchecked(tensor))
+    }
+
+    pub fn get(&self, index: i64) -> <Self as Select>::Output
+    where
+        Self: Select,
+    {
+        self.f_get(index).unwrap()
+    }
+
+    /// The element at `index`, there is one index per dimension.
+    pub fn f_value(&self, index: [i64; D]) -> Result<K::Elem, TchError> {
+        let mut tensor = self.tensor.shallow_clone();
+        for i in index {
+            tensor = tensor.f_get(i)?
+        }
+        let mut value = [<K::Elem as Element>::ZERO; 1];
+        tensor.f_to_device(Device::Cpu)?.f_copy_data(&mut value, 1)?;
+        Ok(value[0])
+    }
+
+    pub fn value(&self, index: [i64; D]) -> K::Elem {
+        self.f_value(index).unwrap()
+    }
+
+    /// Matrix product, the rank of the result depends on the ranks of both
+    /// operands. An error is returned if the sizes are not compatible.
+    pub fn f_matmul<const E: usize>(
+        &self,
+        rhs: &TypedTensor<K, E>,
+    ) -> Result<<Self as MatMul<TypedTensor<K, E>>>::Output, TchError>
+    where
+        Self: MatMul<TypedTensor<K, E>>,
+    {
+        let tensor = self.tensor.f_matmul(&rhs.tensor)?;
+        Ok(FromTensorUnchecked::from_tensor_unchecked(tensor))
+    }
+
+    pub fn matmul<const E: usize>(
+        &self,
+        rhs: &TypedTensor<K, E>,
+    ) -> <Self as MatMul<TypedTensor<K, E>>>::Output
+    where
+        Self: MatMul<TypedTensor<K, E>>,
+    {
+        self.f_matmul(rhs).unwrap()
+    }
+}
+
+impl<K: KindMarker> TypedTensor<K, 2> {
+    /// The transposed matrix.
+    pub fn tr(&self) -> Self {
+        Self::from_tensor_unchecked(self.tensor.tr())
+    }
+}
+
+impl<K: KindMarker, const D: usize> std::ops::Deref for TypedTensor<K, D> {
+    type Target = Tensor;
+
+    fn deref(&self) -> &Tensor {
+        &self.tensor
+    }
+}
+
+impl<K: KindMarker, const D: usize> AsRef<Tensor> for TypedTensor<K, D> {
+    fn as_ref(&self) -> &Tensor {
+        &self.tensor
+    }
+}
+
+impl<K: KindMarker, const D: usize> TryFrom<Tensor> for TypedTensor<K, D> {
+    type Error = TchError;
+
+    /// Checks the rank and kind of the tensor.
+    fn try_from(tensor: Tensor) -> Result<Self, Self::Error> {
+        Self::check(&tensor)?;
+        Ok(Self::from_tensor_unchecked(tensor))
+    }
+}
+
+impl<K: KindMarker, const D: usize> From<TypedTensor<K, D>> for Tensor {
+    fn from(tensor: TypedTensor<K, D>) -> Tensor {
+        tensor.tensor
+    }
+}
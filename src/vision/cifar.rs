@@ -1,47 +1,101 @@
-//! The CIFAR-10 dataset.
+//! The CIFAR-10 and CIFAR-100 datasets.
 //!
 //! The files can be downloaded from the following page:
 //! <https://www.cs.toronto.edu/~kriz/cifar.html>
-//! The binary version of the dataset is used.
+//! The binary version of the datasets is used, CIFAR-10 uses the
+//! `data_batch_1.bin` to `data_batch_5.bin` and `test_batch.bin` files and
+//! CIFAR-100 the `train.bin` and `test.bin` files.
 use super::dataset::Dataset;
-use crate::{kind, IndexOp, Kind, Tensor};
-use std::fs::File;
-use std::io::{BufReader, Read, Result};
+use crate::{Kind, TchError, Tensor};
+use std::path::Path;
 
 const W: i64 = 32;
 const H: i64 = 32;
 const C: i64 = 3;
-const BYTES_PER_IMAGE: i64 = W * H * C + 1;
-const SAMPLES_PER_FILE: i64 = 10000;
-
-fn read_file_(filename: &std::path::Path) -> Result<(Tensor, Tensor)> {
-    let mut buf_reader = BufReader::new(File::open(filename)?);
-    let mut data = vec![0u8; (SAMPLES_PER_FILE * BYTES_PER_IMAGE) as usize];
-    buf_reader.read_exact(&mut data)?;
-    let content = Tensor::from_slice(&data);
-    let images = Tensor::zeros([SAMPLES_PER_FILE, C, H, W], kind::FLOAT_CPU);
-    let labels = Tensor::zeros([SAMPLES_PER_FILE], kind::INT64_CPU);
-    for index in 0..SAMPLES_PER_FILE {
-        let content_offset = BYTES_PER_IMAGE * index;
-        labels.i(index).copy_(&content.i(content_offset));
-        images.i(index).copy_(
-            &content
-                .narrow(0, 1 + content_offset, BYTES_PER_IMAGE - 1)
-                .view((C, H, W))
-                .to_kind(Kind::Float),
-        );
+const BYTES_PER_IMAGE: i64 = W * H * C;
+
+// The number of samples in each file of the datasets.
+#[derive(Debug, Clone, Copy)]
+struct Samples {
+    cifar10_batch: i64,
+    cifar100_train: i64,
+    cifar100_test: i64,
+}
+
+const SAMPLES: Samples =
+    Samples { cifar10_batch: 10000, cifar100_train: 50000, cifar100_test: 10000 };
+
+/// Options for loading the CIFAR datasets.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadConfig {
+    /// When set, the images are float values between 0 and 1, otherwise they
+    /// are the original bytes.
+    pub normalize: bool,
+    /// For CIFAR-100, use the 100 fine labels rather than the 20 coarse ones.
+    pub fine_labels: bool,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig { normalize: true, fine_labels: true }
     }
-    Ok((images.to_kind(Kind::Float) / 255.0, labels))
 }
 
-fn read_file(filename: &std::path::Path) -> Result<(Tensor, Tensor)> {
-    read_file_(filename)
-        .map_err(|err| std::io::Error::new(err.kind(), format!("{filename:?} {err}")))
+// Reads the `samples` records of a binary file, each record has `label_bytes`
+// bytes of labels followed by the image. The label at `label_index` is
+// returned.
+fn read_file(
+    filename: &Path,
+    label_bytes: i64,
+    label_index: i64,
+    samples: i64,
+    normalize: bool,
+) -> Result<(Tensor, Tensor), TchError> {
+    let data = std::fs::read(filename)
+        .map_err(|err| std::io::Error::new(err.kind(), format!("{filename:?} {err}")))?;
+    let record_bytes = label_bytes + BYTES_PER_IMAGE;
+    let len = data.len() as i64;
+    if len != samples * record_bytes {
+        return Err(TchError::FileFormat(format!(
+            "{filename:?}: truncated or invalid file, got {len} bytes for {samples} records of {record_bytes} bytes"
+        )));
+    }
+    let content = Tensor::f_from_slice(&data)?.f_view((samples, record_bytes))?;
+    let labels = content.f_select(1, label_index)?.f_to_kind(Kind::Int64)?;
+    let images =
+        content.f_narrow(1, label_bytes, BYTES_PER_IMAGE)?.f_reshape([samples, C, H, W])?;
+    let images = if normalize { images.f_to_kind(Kind::Float)? / 255.0 } else { images };
+    Ok((images, labels))
+}
+
+/// Loads the CIFAR-10 or the CIFAR-100 dataset from a directory, depending on
+/// the files that it contains.
+///
+/// The images have shape `[N, 3, 32, 32]`, using bytes or float values
+/// depending on `config.normalize`, and the labels are `Int64` values.
+pub fn load_dir_with_config<T: AsRef<Path>>(
+    dir: T,
+    config: LoadConfig,
+) -> Result<Dataset, TchError> {
+    load_dir_with_samples(dir.as_ref(), config, SAMPLES)
 }
 
-pub fn load_dir<T: AsRef<std::path::Path>>(dir: T) -> Result<Dataset> {
-    let dir = dir.as_ref();
-    let (test_images, test_labels) = read_file(&dir.join("test_batch.bin"))?;
+fn load_dir_with_samples(
+    dir: &Path,
+    config: LoadConfig,
+    samples: Samples,
+) -> Result<Dataset, TchError> {
+    if dir.join("train.bin").exists() {
+        let label_index = if config.fine_labels { 1 } else { 0 };
+        let read =
+            |name, samples| read_file(&dir.join(name), 2, label_index, samples, config.normalize);
+        let (train_images, train_labels) = read("train.bin", samples.cifar100_train)?;
+        let (test_images, test_labels) = read("test.bin", samples.cifar100_test)?;
+        let labels = if config.fine_labels { 100 } else { 20 };
+        return Ok(Dataset { train_images, train_labels, test_images, test_labels, labels });
+    }
+    let read = |name| read_file(&dir.join(name), 1, 0, samples.cifar10_batch, config.normalize);
+    let (test_images, test_labels) = read("test_batch.bin")?;
     let train_images_and_labels = [
         "data_batch_1.bin",
         "data_batch_2.bin",
@@ -50,15 +104,95 @@ pub fn load_dir<T: AsRef<std::path::Path>>(dir: T) -> Result<Dataset> {
         "data_batch_5.bin",
     ]
     .iter()
-    .map(|x| read_file(&dir.join(x)))
-    .collect::<Result<Vec<_>>>()?;
+    .map(|x| read(x))
+    .collect::<Result<Vec<_>, TchError>>()?;
     let (train_images, train_labels): (Vec<_>, Vec<_>) =
         train_images_and_labels.into_iter().unzip();
     Ok(Dataset {
-        train_images: Tensor::cat(&train_images, 0),
-        train_labels: Tensor::cat(&train_labels, 0),
+        train_images: Tensor::f_cat(&train_images, 0)?,
+        train_labels: Tensor::f_cat(&train_labels, 0)?,
         test_images,
         test_labels,
         labels: 10,
     })
 }
+
+/// Loads the CIFAR-10 or the CIFAR-100 dataset from a directory with float
+/// images between 0 and 1 and, for CIFAR-100, the fine labels.
+pub fn load_dir<T: AsRef<Path>>(dir: T) -> Result<Dataset, TchError> {
+    load_dir_with_config(dir, LoadConfig::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_SAMPLES: Samples =
+        Samples { cifar10_batch: 2, cifar100_train: 3, cifar100_test: 1 };
+
+    // Writes a binary CIFAR file, the pixels of each image are set to its first
+    // label.
+    fn write_cifar_file(path: &std::path::Path, labels: &[&[u8]]) {
+        let mut data = vec![];
+        for labels in labels {
+            data.extend_from_slice(labels);
+            data.resize(data.len() + 3 * 32 * 32, labels[0]);
+        }
+        std::fs::write(path, data).unwrap()
+    }
+
+    #[test]
+    fn cifar() {
+        let dir = std::env::temp_dir().join(format!("tch-cifar10-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 1..=5 {
+            write_cifar_file(&dir.join(format!("data_batch_{i}.bin")), &[&[i], &[9]]);
+        }
+        write_cifar_file(&dir.join("test_batch.bin"), &[&[0], &[1]]);
+        let dataset = load_dir_with_samples(&dir, LoadConfig::default(), SMALL_SAMPLES).unwrap();
+        assert_eq!(dataset.labels, 10);
+        assert_eq!(dataset.train_images.size(), [10, 3, 32, 32]);
+        assert_eq!(dataset.train_images.kind(), crate::Kind::Float);
+        assert_eq!(
+            Vec::<i64>::try_from(&dataset.train_labels).unwrap(),
+            [1, 9, 2, 9, 3, 9, 4, 9, 5, 9]
+        );
+        assert_eq!(Vec::<i64>::try_from(&dataset.test_labels).unwrap(), [0, 1]);
+        assert_eq!(f64::try_from(dataset.test_images.get(1).max()).unwrap(), 1. / 255.);
+
+        let config = LoadConfig { normalize: false, ..Default::default() };
+        let dataset = load_dir_with_samples(&dir, config, SMALL_SAMPLES).unwrap();
+        assert_eq!(dataset.test_images.kind(), crate::Kind::Uint8);
+        assert_eq!(
+            dataset.test_images.get(1),
+            Tensor::ones([3, 32, 32], (crate::Kind::Uint8, crate::Device::Cpu))
+        );
+
+        // Truncated files are reported, including when truncated at a record
+        // boundary.
+        let mut data = std::fs::read(dir.join("test_batch.bin")).unwrap();
+        data.pop();
+        std::fs::write(dir.join("test_batch.bin"), &data).unwrap();
+        let err = load_dir_with_samples(&dir, LoadConfig::default(), SMALL_SAMPLES).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+        std::fs::write(dir.join("test_batch.bin"), &data[..data.len() / 2 + 1]).unwrap();
+        let err = load_dir_with_samples(&dir, LoadConfig::default(), SMALL_SAMPLES).unwrap_err();
+        assert!(matches!(err, TchError::FileFormat(_)), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tch-cifar100-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_cifar_file(&dir.join("train.bin"), &[&[3, 42], &[19, 99], &[0, 7]]);
+        write_cifar_file(&dir.join("test.bin"), &[&[5, 55]]);
+        let dataset = load_dir_with_samples(&dir, LoadConfig::default(), SMALL_SAMPLES).unwrap();
+        assert_eq!(dataset.labels, 100);
+        assert_eq!(dataset.train_images.size(), [3, 3, 32, 32]);
+        assert_eq!(Vec::<i64>::try_from(&dataset.train_labels).unwrap(), [42, 99, 7]);
+        assert_eq!(Vec::<i64>::try_from(&dataset.test_labels).unwrap(), [55]);
+        let config = LoadConfig { fine_labels: false, ..Default::default() };
+        let dataset = load_dir_with_samples(&dir, config, SMALL_SAMPLES).unwrap();
+        assert_eq!(dataset.labels, 20);
+        assert_eq!(Vec::<i64>::try_from(&dataset.train_labels).unwrap(), [3, 19, 0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
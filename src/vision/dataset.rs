@@ -1,6 +1,6 @@
 //! A simple dataset structure shared by various computer vision datasets.
 use crate::data::Iter2;
-use crate::{IndexOp, Tensor};
+use crate::{IndexOp, Kind, TchError, Tensor};
 use rand::Rng;
 
 #[derive(Debug)]
@@ -93,3 +93,107 @@ pub fn augmentation(t: &Tensor, flip: bool, crop: i64, cutout: i64) -> Tensor {
     }
     t
 }
+
+// Samples a value from a Beta(alpha, alpha) distribution as a scalar tensor on
+// the given device.
+fn sample_beta(alpha: f64, device: crate::Device) -> Result<Tensor, TchError> {
+    if alpha <= 0. {
+        return Err(TchError::Torch(format!("alpha has to be positive, got {alpha}")));
+    }
+    Tensor::f_full([2], alpha, (Kind::Float, device))?.f_internal_sample_dirichlet()?.f_select(0, 0)
+}
+
+// Converts the images to floats and the labels to one-hot targets, returning
+// them along with a random permutation of the batch.
+fn mix_inputs(
+    images: &Tensor,
+    labels: &Tensor,
+    num_classes: i64,
+) -> Result<(Tensor, Tensor, Tensor), TchError> {
+    let images = if images.f_is_floating_point()? {
+        images.shallow_clone()
+    } else {
+        images.f_to_kind(Kind::Float)?
+    };
+    let targets =
+        labels.f_to_kind(Kind::Int64)?.f_one_hot(num_classes)?.f_to_kind(images.f_kind()?)?;
+    let batch_size = images.f_size()?.first().copied().unwrap_or(0);
+    let permutation = Tensor::f_randperm(batch_size, (Kind::Int64, images.device()))?;
+    Ok((images, targets, permutation))
+}
+
+// Blends a batch with the permuted batch, xs having a weight of lambda.
+fn blend(xs: &Tensor, permutation: &Tensor, lambda: &Tensor) -> Result<Tensor, TchError> {
+    let permuted = xs.f_index_select(0, permutation)?;
+    xs.f_mul(lambda)?.f_add(&permuted.f_mul(&lambda.f_neg()?.f_add_scalar(1.)?)?)
+}
+
+/// Applies MixUp to a mini-batch: each image is blended with another image of
+/// the batch using a weight drawn from a Beta(alpha, alpha) distribution.
+/// <https://arxiv.org/abs/1710.09412>
+///
+/// The images have shape `[batch_size, ...]` and the labels are class indexes.
+/// Returns the mixed images, as floats, and the soft targets of shape
+/// `[batch_size, num_classes]` mixing the labels with the same weight. Label
+/// smoothing can be added through the `label_smoothing` argument of
+/// `Tensor::cross_entropy_loss`. All the computations run on the device of the
+/// images.
+pub fn f_mixup(
+    images: &Tensor,
+    labels: &Tensor,
+    num_classes: i64,
+    alpha: f64,
+) -> Result<(Tensor, Tensor), TchError> {
+    let (images, targets, permutation) = mix_inputs(images, labels, num_classes)?;
+    let lambda = sample_beta(alpha, images.device())?;
+    let mixed = blend(&images, &permutation, &lambda)?;
+    Ok((mixed, blend(&targets, &permutation, &lambda)?))
+}
+
+/// Applies MixUp to a mini-batch, see `f_mixup`.
+pub fn mixup(images: &Tensor, labels: &Tensor, num_classes: i64, alpha: f64) -> (Tensor, Tensor) {
+    f_mixup(images, labels, num_classes, alpha).unwrap()
+}
+
+/// Applies CutMix to a mini-batch of NCHW images: a rectangle of each image is
+/// replaced with the same rectangle of another image of the batch.
+/// <https://arxiv.org/abs/1905.04899>
+///
+/// The area of the rectangle is `1 - lambda` of the image area, lambda being
+/// drawn from a Beta(alpha, alpha) distribution, and its center is uniformly
+/// distributed. As the rectangle is clipped to the image, the targets are
+/// mixed using the actual pasted area. Returns the mixed images and the soft
+/// targets as in `f_mixup`.
+pub fn f_cutmix(
+    images: &Tensor,
+    labels: &Tensor,
+    num_classes: i64,
+    alpha: f64,
+) -> Result<(Tensor, Tensor), TchError> {
+    let (images, targets, permutation) = mix_inputs(images, labels, num_classes)?;
+    let (_, _, height, width) = images.size4()?;
+    let device = images.device();
+    let ratio = sample_beta(alpha, device)?.f_neg()?.f_add_scalar(1.)?.f_sqrt()?;
+    // The mask of the rectangle, computed on the device so that the sampled
+    // values are never copied to the host.
+    let in_range = |size: i64| -> Result<Tensor, TchError> {
+        let center = Tensor::f_randint(size, [], (Kind::Float, device))?;
+        let half = ratio.f_mul_scalar(size as f64 / 2.)?;
+        let start = center.f_sub(&half)?.f_round()?;
+        let end = center.f_add(&half)?.f_round()?;
+        let positions = Tensor::f_arange(size, (Kind::Float, device))?;
+        positions.f_ge_tensor(&start)?.f_logical_and(&positions.f_lt_tensor(&end)?)
+    };
+    let mask =
+        in_range(height)?.f_unsqueeze(1)?.f_logical_and(&in_range(width)?.f_unsqueeze(0)?)?;
+    let permuted = images.f_index_select(0, &permutation)?;
+    let mixed = permuted.f_where_self(&mask, &images)?;
+    let pasted = mask.f_sum(Kind::Float)?.f_div_scalar((height * width) as f64)?;
+    let lambda = pasted.f_neg()?.f_add_scalar(1.)?;
+    Ok((mixed, blend(&targets, &permutation, &lambda)?))
+}
+
+/// Applies CutMix to a mini-batch, see `f_cutmix`.
+pub fn cutmix(images: &Tensor, labels: &Tensor, num_classes: i64, alpha: f64) -> (Tensor, Tensor) {
+    f_cutmix(images, labels, num_classes, alpha).unwrap()
+}
@@ -1,4 +1,18 @@
 //! EfficientNet implementation.
+//!
+//! The variables use the names of the torchvision implementation, e.g. for
+//! `efficientnet_b0`:
+//! - `features.0.0.weight` and `features.0.1.*` for the stem convolution and
+//!   its batch norm.
+//! - `features.{stage}.{layer}.block.{i}` for the MBConv blocks, `i` indexes
+//!   the optional expansion, the depthwise convolution, the squeeze-excitation
+//!   with its `fc1` and `fc2` convolutions, and the projection.
+//! - `features.8.0.weight` and `features.8.1.*` for the final convolution.
+//! - `classifier.1.weight` and `classifier.1.bias` for the linear layer.
+//!
+//! The torchvision weights load directly from an exported state dict. As the
+//! convolutions use the TensorFlow `same` padding and the batch norms an
+//! epsilon of 1e-3, the outputs can differ slightly from torchvision.
 use crate::nn::{self, ConvConfig, Module, ModuleT};
 use crate::Tensor;
 
@@ -183,10 +197,11 @@ struct MBConv {
     squeeze_excitation: SqueezeExcitation,
     project_cna: ConvNormActivation,
     config: MBConvConfig,
+    stochastic_depth_prob: f64,
 }
 
 impl MBConv {
-    fn new(vs: nn::Path, c: MBConvConfig) -> Self {
+    fn new(vs: nn::Path, c: MBConvConfig, stochastic_depth_prob: f64) -> Self {
         let vs = &vs / "block";
         let exp = make_divisible(c.input_channels as f64 * c.expand_ratio, 8);
         let expand_cna = if exp != c.input_channels {
@@ -203,7 +218,14 @@ impl MBConv {
         let project_cna =
             ConvNormActivation::new(&vs / (start_index + 2), exp, c.out_channels, 1, 1, 1)
                 .no_activation();
-        Self { expand_cna, depthwise_cna, squeeze_excitation, project_cna, config: c }
+        Self {
+            expand_cna,
+            depthwise_cna,
+            squeeze_excitation,
+            project_cna,
+            config: c,
+            stochastic_depth_prob,
+        }
     }
 }
 
@@ -220,7 +242,7 @@ impl ModuleT for MBConv {
             .apply_t(&self.squeeze_excitation, t)
             .apply_t(&self.project_cna, t);
         if use_res_connect {
-            ys + xs
+            stochastic_depth(&ys, self.stochastic_depth_prob, t) + xs
         } else {
             ys
         }
@@ -233,22 +255,45 @@ impl Tensor {
     }
 }
 
+// Drops the residual branch of whole samples with probability p in train
+// mode, the kept samples are scaled by 1 / (1 - p).
+fn stochastic_depth(xs: &Tensor, p: f64, train: bool) -> Tensor {
+    if !train || p == 0. {
+        return xs.shallow_clone();
+    }
+    let mut size = vec![1; xs.dim()];
+    size[0] = xs.size()[0];
+    let survival = 1. - p;
+    let mask = Tensor::empty(size, (xs.kind(), xs.device())).bernoulli_float_(survival);
+    if survival > 0. {
+        xs * mask / survival
+    } else {
+        xs * mask
+    }
+}
+
 #[derive(Debug)]
 struct EfficientNet {
     init_cna: ConvNormActivation,
     blocks: Vec<MBConv>,
     final_cna: ConvNormActivation,
+    dropout: f64,
     classifier: nn::Linear,
 }
 
+// The stochastic depth probability of the last block, it increases linearly
+// from zero with the block index.
+const STOCHASTIC_DEPTH_PROB: f64 = 0.2;
+
 impl EfficientNet {
-    fn new(p: &nn::Path, configs: Vec<MBConvConfig>, nclasses: i64) -> Self {
+    fn new(p: &nn::Path, configs: Vec<MBConvConfig>, dropout: f64, nclasses: i64) -> Self {
         let f_p = p / "features";
         let first_in_c = configs[0].input_channels;
         let last_out_c = configs.last().unwrap().out_channels;
         let final_out_c = 4 * last_out_c;
         let init_cna = ConvNormActivation::new(&f_p / 0, 3, first_in_c, 3, 2, 1);
         let nconfigs = configs.len();
+        let total_blocks: usize = configs.iter().map(|c| c.num_layers).sum();
         let mut blocks = vec![];
         for (index, cnf) in configs.into_iter().enumerate() {
             let f_p = &f_p / (index + 1);
@@ -258,14 +303,15 @@ impl EfficientNet {
                 } else {
                     MBConvConfig { input_channels: cnf.out_channels, stride: 1, ..cnf }
                 };
-                blocks.push(MBConv::new(&f_p / r_index, cnf))
+                let sd_prob = STOCHASTIC_DEPTH_PROB * blocks.len() as f64 / total_blocks as f64;
+                blocks.push(MBConv::new(&f_p / r_index, cnf, sd_prob))
             }
         }
         let final_cna =
             ConvNormActivation::new(&f_p / (nconfigs + 1), last_out_c, final_out_c, 1, 1, 1);
         let classifier =
             nn::linear(p / "classifier" / 1, final_out_c, nclasses, Default::default());
-        Self { init_cna, blocks, final_cna, classifier }
+        Self { init_cna, blocks, final_cna, dropout, classifier }
     }
 }
 
@@ -279,33 +325,34 @@ impl ModuleT for EfficientNet {
             .adaptive_avg_pool2d([1, 1])
             .squeeze_dim(-1)
             .squeeze_dim(-1)
+            .dropout(self.dropout, t)
             .apply(&self.classifier)
     }
 }
 
 pub fn b0(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b0(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b0(), 0.2, nclasses)
 }
 pub fn b1(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b1(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b1(), 0.2, nclasses)
 }
 pub fn b2(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b2(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b2(), 0.3, nclasses)
 }
 pub fn b3(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b3(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b3(), 0.3, nclasses)
 }
 pub fn b4(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b4(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b4(), 0.4, nclasses)
 }
 pub fn b5(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b5(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b5(), 0.4, nclasses)
 }
 pub fn b6(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b6(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b6(), 0.5, nclasses)
 }
 pub fn b7(p: &nn::Path, nclasses: i64) -> impl ModuleT {
-    EfficientNet::new(p, MBConvConfig::b7(), nclasses)
+    EfficientNet::new(p, MBConvConfig::b7(), 0.5, nclasses)
 }
 
 #[allow(clippy::many_single_char_names)]
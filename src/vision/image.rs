@@ -1,6 +1,6 @@
 //! Utility functions to manipulate images.
-use crate::wrappers::image::{load_hwc, load_hwc_from_mem, resize_hwc, save_hwc};
-use crate::{Device, TchError, Tensor};
+use crate::wrappers::image::{encode_hwc, load_hwc, load_hwc_from_mem, resize_hwc, save_hwc};
+use crate::{Device, Kind, TchError, Tensor};
 use std::io;
 use std::path::Path;
 
@@ -20,14 +20,26 @@ pub fn load<T: AsRef<Path>>(path: T) -> Result<Tensor, TchError> {
     Ok(hwc_to_chw(&tensor))
 }
 
-/// Loads an image from memory.
+/// Loads an image from memory, e.g. the content of a jpg or png file.
 ///
-/// On success returns a tensor of shape [channel, height, width].
+/// On success returns a tensor of shape [channel, height, width], the errors
+/// include the reason given by the decoder for malformed data.
 pub fn load_from_memory(img_data: &[u8]) -> Result<Tensor, TchError> {
     let tensor = load_hwc_from_mem(img_data)?;
     Ok(hwc_to_chw(&tensor))
 }
 
+// Converts an image of shape [channel, height, width], or [1, channel, height,
+// width], to a byte tensor of shape [height, width, channel] on the cpu.
+fn to_hwc_bytes(t: &Tensor) -> Result<Tensor, TchError> {
+    let t = t.to_kind(Kind::Uint8);
+    match t.size().as_slice() {
+        [1, _, _, _] => Ok(chw_to_hwc(&t.squeeze_dim(0)).to_device(Device::Cpu)),
+        [_, _, _] => Ok(chw_to_hwc(&t).to_device(Device::Cpu)),
+        sz => Err(TchError::FileFormat(format!("unexpected size for image tensor {sz:?}"))),
+    }
+}
+
 /// Saves an image to a file.
 ///
 /// This expects as input a tensor of shape [channel, height, width].
@@ -36,22 +48,87 @@ pub fn load_from_memory(img_data: &[u8]) -> Result<Tensor, TchError> {
 /// The tensor input should be of kind UInt8 with values ranging from
 /// 0 to 255.
 pub fn save<T: AsRef<Path>>(t: &Tensor, path: T) -> Result<(), TchError> {
-    let t = t.to_kind(crate::Kind::Uint8);
-    match t.size().as_slice() {
-        [1, _, _, _] => save_hwc(&chw_to_hwc(&t.squeeze_dim(0)).to_device(Device::Cpu), path),
-        [_, _, _] => save_hwc(&chw_to_hwc(&t).to_device(Device::Cpu), path),
-        sz => Err(TchError::FileFormat(format!("unexpected size for image tensor {sz:?}"))),
+    save_hwc(&to_hwc_bytes(t)?, path)
+}
+
+/// Encodes an image in the jpg format, `quality` ranging from 1 to 100.
+///
+/// This expects as input a tensor of shape [channel, height, width] with
+/// values ranging from 0 to 255, as for `save`.
+pub fn encode_jpeg(t: &Tensor, quality: i64) -> Result<Vec<u8>, TchError> {
+    if !(1..=100).contains(&quality) {
+        return Err(TchError::FileFormat(format!(
+            "jpg quality has to be between 1 and 100, got {quality}"
+        )));
     }
+    encode_hwc(&to_hwc_bytes(t)?, "jpg", quality)
+}
+
+/// Encodes an image in the png format.
+///
+/// This expects as input a tensor of shape [channel, height, width] with
+/// values ranging from 0 to 255, as for `save`.
+pub fn encode_png(t: &Tensor) -> Result<Vec<u8>, TchError> {
+    encode_hwc(&to_hwc_bytes(t)?, "png", 0)
 }
 
 /// Resizes an image.
 ///
 /// This expects as input a tensor of shape [channel, height, width] and returns
 /// a tensor of shape [channel, out_h, out_w].
+/// The image is resized on the cpu using stb_image_resize, so the results
+/// differ slightly from the torchvision ones, see `resize_batch` for a
+/// libtorch based alternative.
 pub fn resize(t: &Tensor, out_w: i64, out_h: i64) -> Result<Tensor, TchError> {
     Ok(hwc_to_chw(&resize_hwc(&chw_to_hwc(t), out_w, out_h)?))
 }
 
+/// The interpolation modes used by `resize_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+/// Resizes a batch of images using libtorch, on the device of the images.
+///
+/// This expects as input a tensor of shape [batch, channel, height, width] and
+/// returns a tensor of shape [batch, channel, out_h, out_w]. The bilinear and
+/// bicubic modes are anti-aliased when downsampling, matching the torchvision
+/// `resize` function with `antialias=True`. Images of kind UInt8 are rounded
+/// back to bytes after the interpolation.
+pub fn resize_batch(
+    t: &Tensor,
+    out_h: i64,
+    out_w: i64,
+    interpolation: Interpolation,
+) -> Result<Tensor, TchError> {
+    if t.dim() != 4 {
+        return Err(TchError::Shape(format!(
+            "resize_batch expects a tensor of shape [batch, channel, height, width], got {:?}",
+            t.size()
+        )));
+    }
+    let kind = t.f_kind()?;
+    let float =
+        if t.f_is_floating_point()? { t.shallow_clone() } else { t.f_to_kind(Kind::Float)? };
+    let xs = match interpolation {
+        Interpolation::Nearest => return t.f_upsample_nearest2d([out_h, out_w], None, None),
+        Interpolation::Bilinear => {
+            float.f_internal_upsample_bilinear2d_aa([out_h, out_w], false, None, None)?
+        }
+        Interpolation::Bicubic => {
+            float.f_internal_upsample_bicubic2d_aa([out_h, out_w], false, None, None)?
+        }
+    };
+    if kind == Kind::Uint8 {
+        xs.f_round()?.f_clamp(0., 255.)?.f_to_kind(kind)
+    } else {
+        xs.f_to_kind(kind)
+    }
+}
+
 pub fn resize_preserve_aspect_ratio_hwc(
     t: &Tensor,
     out_w: i64,
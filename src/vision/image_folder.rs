@@ -0,0 +1,180 @@
+//! A dataset of images stored with one subdirectory per class, e.g.
+//! `root/cat/001.png` and `root/dog/001.jpg`.
+//!
+//! Only the paths are collected when creating the dataset, the images are
+//! decoded when iterating over the mini-batches so that datasets larger than
+//! the available memory can be used.
+use crate::{Device, TchError, Tensor};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::path::{Path, PathBuf};
+
+fn has_image_suffix(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => {
+            let extension = extension.to_ascii_lowercase();
+            matches!(extension.as_str(), "jpg" | "jpeg" | "png" | "bmp" | "tga")
+        }
+        None => false,
+    }
+}
+
+// The entries of a directory matching a predicate, sorted by name so that
+// the labels and the order of the samples are deterministic.
+fn sorted_entries(dir: &Path, f: impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>, TchError> {
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if f(&path) {
+            entries.push(path)
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// A dataset of images stored with one subdirectory per class, the classes
+/// are labeled in the alphabetical order of the subdirectories.
+#[derive(Debug)]
+pub struct ImageFolderDataset {
+    classes: Vec<String>,
+    samples: Vec<(PathBuf, i64)>,
+    resize: Option<(i64, i64)>,
+    rng: StdRng,
+}
+
+impl ImageFolderDataset {
+    /// Scans the class subdirectories of `root` for images.
+    ///
+    /// When `resize` is set to `(width, height)`, the images are resized to
+    /// this size, preserving their aspect ratio by taking a center crop.
+    /// Otherwise all the images should have the same size.
+    pub fn new<T: AsRef<Path>>(
+        root: T,
+        resize: Option<(i64, i64)>,
+    ) -> Result<ImageFolderDataset, TchError> {
+        let root = root.as_ref();
+        let mut classes = vec![];
+        let mut samples = vec![];
+        for class_dir in sorted_entries(root, |p| p.is_dir())? {
+            let label = classes.len() as i64;
+            let images = sorted_entries(&class_dir, |p| p.is_file() && has_image_suffix(p))?;
+            samples.extend(images.into_iter().map(|p| (p, label)));
+            classes.push(class_dir.file_name().unwrap_or_default().to_string_lossy().to_string());
+        }
+        if samples.is_empty() {
+            return Err(TchError::MissingImage(format!("{root:?}")));
+        }
+        Ok(ImageFolderDataset { classes, samples, resize, rng: StdRng::from_entropy() })
+    }
+
+    /// Seeds the random number generator used to shuffle the samples, so that
+    /// the successive calls to `batches` use reproducible orders.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed)
+    }
+
+    /// The number of images.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The class names, the label of a class is its index.
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    /// The path and the label of each image.
+    pub fn samples(&self) -> &[(PathBuf, i64)] {
+        &self.samples
+    }
+
+    /// Decodes the image at `index`, returning a `Uint8` tensor of shape
+    /// `[channels, height, width]` along with its label.
+    pub fn get(&self, index: usize) -> Result<(Tensor, i64), TchError> {
+        let (path, label) = match self.samples.get(index) {
+            Some(sample) => sample,
+            None => {
+                return Err(TchError::Shape(format!(
+                    "image index {index} out of range for {} images",
+                    self.samples.len()
+                )))
+            }
+        };
+        let image = match self.resize {
+            Some((w, h)) => super::image::load_and_resize(path, w, h),
+            None => super::image::load(path),
+        };
+        let image =
+            image.map_err(|err| TchError::FileFormat(format!("cannot decode {path:?}: {err}")))?;
+        Ok((image, *label))
+    }
+
+    /// Returns an iterator over mini-batches of images and labels, the images
+    /// are decoded on demand.
+    ///
+    /// The images are `Uint8` tensors of shape `[batch_size, channels, height,
+    /// width]` and the labels `Int64` tensors, the last mini-batch can be
+    /// smaller.
+    pub fn batches(
+        &mut self,
+        batch_size: usize,
+        shuffle: bool,
+        device: Device,
+    ) -> ImageFolderIter<'_> {
+        let mut indexes: Vec<usize> = (0..self.samples.len()).collect();
+        if shuffle {
+            indexes.shuffle(&mut self.rng)
+        }
+        ImageFolderIter { dataset: self, indexes, batch_size: batch_size.max(1), index: 0, device }
+    }
+}
+
+/// An iterator over the mini-batches of an `ImageFolderDataset`.
+#[derive(Debug)]
+pub struct ImageFolderIter<'a> {
+    dataset: &'a ImageFolderDataset,
+    indexes: Vec<usize>,
+    batch_size: usize,
+    index: usize,
+    device: Device,
+}
+
+impl ImageFolderIter<'_> {
+    fn batch(&self, indexes: &[usize]) -> Result<(Tensor, Tensor), TchError> {
+        let mut images = Vec::with_capacity(indexes.len());
+        let mut labels = Vec::with_capacity(indexes.len());
+        for &index in indexes {
+            let (image, label) = self.dataset.get(index)?;
+            images.push(image);
+            labels.push(label);
+        }
+        let images = Tensor::f_stack(&images, 0)?.f_to_device(self.device)?;
+        let labels = Tensor::f_from_slice(&labels)?.f_to_device(self.device)?;
+        Ok((images, labels))
+    }
+}
+
+impl Iterator for ImageFolderIter<'_> {
+    type Item = Result<(Tensor, Tensor), TchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.indexes.len() {
+            return None;
+        }
+        let end = usize::min(self.index + self.batch_size, self.indexes.len());
+        let batch = self.batch(&self.indexes[self.index..end]);
+        self.index = end;
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.indexes.len() - self.index).div_ceil(self.batch_size);
+        (remaining, Some(remaining))
+    }
+}
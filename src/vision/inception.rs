@@ -2,7 +2,12 @@
 use crate::{nn, nn::ModuleT, Tensor};
 
 fn conv_bn(p: nn::Path, c_in: i64, c_out: i64, ksize: i64, pad: i64, stride: i64) -> impl ModuleT {
-    let conv2d_cfg = nn::ConvConfig { stride, padding: pad, bias: false, ..Default::default() };
+    let conv2d_cfg = nn::ConvConfig {
+        stride,
+        padding: nn::Padding::Explicit(pad),
+        bias: false,
+        ..Default::default()
+    };
     let bn_cfg = nn::BatchNormConfig { eps: 0.001, ..Default::default() };
     nn::seq_t()
         .add(nn::conv2d(&p / "conv", c_in, c_out, ksize, conv2d_cfg))
@@ -11,8 +16,11 @@ fn conv_bn(p: nn::Path, c_in: i64, c_out: i64, ksize: i64, pad: i64, stride: i64
 }
 
 fn conv_bn2(p: nn::Path, c_in: i64, c_out: i64, ksize: [i64; 2], pad: [i64; 2]) -> impl ModuleT {
-    let conv2d_cfg =
-        nn::ConvConfigND::<[i64; 2]> { padding: pad, bias: false, ..Default::default() };
+    let conv2d_cfg = nn::ConvConfigND::<[i64; 2]> {
+        padding: nn::Padding::Explicit(pad),
+        bias: false,
+        ..Default::default()
+    };
     let bn_cfg = nn::BatchNormConfig { eps: 0.001, ..Default::default() };
     nn::seq_t()
         .add(nn::conv(&p / "conv", c_in, c_out, ksize, conv2d_cfg))
@@ -4,6 +4,8 @@ pub mod dataset;
 
 pub mod image;
 
+pub mod image_folder;
+
 pub mod mnist;
 
 pub mod cifar;
@@ -30,5 +32,11 @@ pub mod convmixer;
 
 pub mod dinov2;
 
+pub mod vit;
+
+pub mod transforms;
+
+pub mod ops;
+
 #[cfg(feature = "image")]
 mod rust_image;
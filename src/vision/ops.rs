@@ -0,0 +1,293 @@
+//! Operations for object detection.
+//!
+//! The boxes use the `(x1, y1, x2, y2)` format, with `x1 <= x2` and
+//! `y1 <= y2`, as in torchvision. The operations only use tensor functions so
+//! they run on the device of their inputs.
+use crate::{Device, Kind, TchError, Tensor};
+
+fn check_boxes(name: &str, boxes: &Tensor) -> Result<i64, TchError> {
+    match boxes.size().as_slice() {
+        &[n, 4] => Ok(n),
+        size => Err(TchError::Shape(format!("{name} should have shape [n, 4], got {size:?}"))),
+    }
+}
+
+/// The area of each box, boxes with `x2 < x1` or `y2 < y1` have an area of 0.
+pub fn f_box_area(boxes: &Tensor) -> Result<Tensor, TchError> {
+    check_boxes("boxes", boxes)?;
+    let width = boxes.f_select(1, 2)?.f_sub(&boxes.f_select(1, 0)?)?.f_clamp_min(0.)?;
+    let height = boxes.f_select(1, 3)?.f_sub(&boxes.f_select(1, 1)?)?.f_clamp_min(0.)?;
+    width.f_mul(&height)
+}
+
+/// The area of each box, see `f_box_area`.
+pub fn box_area(boxes: &Tensor) -> Tensor {
+    f_box_area(boxes).unwrap()
+}
+
+/// The intersection over union of each pair of boxes.
+///
+/// `boxes1` has shape `[n, 4]` and `boxes2` shape `[m, 4]`, the result has
+/// shape `[n, m]`. The IoU of two boxes with an empty union, e.g. two boxes
+/// with a zero area, is 0.
+pub fn f_box_iou(boxes1: &Tensor, boxes2: &Tensor) -> Result<Tensor, TchError> {
+    check_boxes("boxes1", boxes1)?;
+    check_boxes("boxes2", boxes2)?;
+    let area1 = f_box_area(boxes1)?;
+    let area2 = f_box_area(boxes2)?;
+    let boxes1 = boxes1.f_unsqueeze(1)?;
+    let top_left = boxes1.f_narrow(2, 0, 2)?.f_maximum(&boxes2.f_narrow(1, 0, 2)?)?;
+    let bottom_right = boxes1.f_narrow(2, 2, 2)?.f_minimum(&boxes2.f_narrow(1, 2, 2)?)?;
+    let wh = bottom_right.f_sub(&top_left)?.f_clamp_min(0.)?;
+    let inter = wh.f_select(2, 0)?.f_mul(&wh.f_select(2, 1)?)?;
+    let union = area1.f_unsqueeze(1)?.f_add(&area2.f_unsqueeze(0)?)?.f_sub(&inter)?;
+    let positive = union.f_gt(0.)?;
+    let iou = inter.f_div(&union.f_clamp_min(f64::MIN_POSITIVE)?)?;
+    iou.f_where_self(&positive, &iou.f_zeros_like()?)
+}
+
+/// The intersection over union of each pair of boxes, see `f_box_iou`.
+pub fn box_iou(boxes1: &Tensor, boxes2: &Tensor) -> Tensor {
+    f_box_iou(boxes1, boxes2).unwrap()
+}
+
+/// Non-maximum suppression.
+///
+/// Greedily selects the boxes by decreasing score, discarding the boxes that
+/// have an IoU greater than `iou_threshold` with an already selected box.
+/// `boxes` has shape `[n, 4]` and `scores` shape `[n]`. Returns the `Int64`
+/// indexes of the selected boxes sorted by decreasing score, as torchvision
+/// `nms`.
+pub fn f_nms(boxes: &Tensor, scores: &Tensor, iou_threshold: f64) -> Result<Tensor, TchError> {
+    let n = check_boxes("boxes", boxes)?;
+    if scores.size() != [n] {
+        return Err(TchError::Shape(format!(
+            "scores should have shape [{n}], got {:?}",
+            scores.size()
+        )));
+    }
+    let device = boxes.device();
+    if n == 0 {
+        return Tensor::f_empty([0], (Kind::Int64, device));
+    }
+    let (_, order) = scores.f_sort_stable(true, 0, true)?;
+    let sorted_boxes = boxes.f_index_select(0, &order)?;
+    // overlaps[i, j] is set when the box i has a higher score than j and
+    // would suppress it.
+    let overlaps = f_box_iou(&sorted_boxes, &sorted_boxes)?
+        .f_gt(iou_threshold)?
+        .f_triu(1)?
+        .f_to_kind(Kind::Float)?;
+    // A box is kept when no kept box with a higher score suppresses it. The
+    // iteration starts with all the boxes kept, after k steps the k boxes with
+    // the highest scores have their final state so this terminates in at most
+    // n steps.
+    let mut keep = Tensor::f_ones([n], (Kind::Float, device))?;
+    for _step in 0..n {
+        let suppressed = keep.f_unsqueeze(0)?.f_matmul(&overlaps)?.f_squeeze_dim(0)?.f_gt(0.)?;
+        let next = suppressed.f_logical_not()?.f_to_kind(Kind::Float)?;
+        if next.f_equal(&keep)? {
+            break;
+        }
+        keep = next;
+    }
+    order.f_masked_select(&keep.f_gt(0.)?)
+}
+
+/// Non-maximum suppression, see `f_nms`.
+pub fn nms(boxes: &Tensor, scores: &Tensor, iou_threshold: f64) -> Tensor {
+    f_nms(boxes, scores, iou_threshold).unwrap()
+}
+
+// Reads the regions of interest, returning the batch index and the box of
+// each region.
+fn read_rois(features: &Tensor, rois: &Tensor) -> Result<Vec<(i64, [f64; 4])>, TchError> {
+    let batch_size = match features.size().as_slice() {
+        &[batch_size, _, _, _] => batch_size,
+        size => {
+            return Err(TchError::Shape(format!(
+                "features should have shape [batch, channel, height, width], got {size:?}"
+            )))
+        }
+    };
+    let k = match rois.size().as_slice() {
+        &[k, 5] => k,
+        size => {
+            return Err(TchError::Shape(format!("rois should have shape [k, 5], got {size:?}")))
+        }
+    };
+    let values = rois.f_to_device(Device::Cpu)?.f_to_kind(Kind::Double)?.f_reshape([5 * k])?;
+    Vec::<f64>::try_from(&values)?
+        .chunks(5)
+        .map(|roi| {
+            let batch_index = roi[0] as i64;
+            if batch_index as f64 != roi[0] || batch_index < 0 || batch_index >= batch_size {
+                return Err(TchError::Shape(format!(
+                    "roi batch index {} is not valid for {batch_size} images",
+                    roi[0]
+                )));
+            }
+            Ok((batch_index, [roi[1], roi[2], roi[3], roi[4]]))
+        })
+        .collect()
+}
+
+/// Region of interest alignment, as used by Mask R-CNN.
+/// <https://arxiv.org/abs/1703.06870>
+///
+/// `features` has shape `[batch, channel, height, width]` and `rois` shape
+/// `[k, 5]`, each region being given as `(batch_index, x1, y1, x2, y2)` in the
+/// coordinates of the input image, `spatial_scale` maps these coordinates to
+/// the ones of the feature map. Each of the `output_size` bins averages
+/// `sampling_ratio` x `sampling_ratio` bilinearly interpolated values, when
+/// `sampling_ratio` is not positive this depends on the size of the region.
+/// When `aligned` is set, the pixel centers are shifted by half a pixel as in
+/// Detectron2. Returns a tensor of shape `[k, channel, output_h, output_w]`,
+/// this matches the torchvision `roi_align` function.
+///
+/// The regions are processed one after another using `grid_sampler_2d`.
+pub fn f_roi_align(
+    features: &Tensor,
+    rois: &Tensor,
+    output_size: (i64, i64),
+    spatial_scale: f64,
+    sampling_ratio: i64,
+    aligned: bool,
+) -> Result<Tensor, TchError> {
+    let rois = read_rois(features, rois)?;
+    let (_, channels, height, width) = features.size4()?;
+    let (output_h, output_w) = output_size;
+    let (kind, device) = (features.f_kind()?, features.device());
+    let offset = if aligned { 0.5 } else { 0. };
+    // The sample positions along an axis, together with whether they lie
+    // within the feature map. The positions are normalized for grid_sampler_2d
+    // so that the pixel centers are at the integer coordinates.
+    let samples = |start: f64, bin_size: f64, bins: i64, grid: i64, size: i64| {
+        let mut positions = vec![];
+        let mut valid = vec![];
+        for bin in 0..bins {
+            for i in 0..grid {
+                let p = start + bin as f64 * bin_size + (i as f64 + 0.5) * bin_size / grid as f64;
+                positions.push(((2. * p + 1.) / size as f64 - 1.) as f32);
+                valid.push(if p < -1. || p > size as f64 { 0f32 } else { 1. });
+            }
+        }
+        (positions, valid)
+    };
+    let mut outputs = Vec::with_capacity(rois.len());
+    for (batch_index, [x1, y1, x2, y2]) in rois {
+        let (start_w, start_h) = (x1 * spatial_scale - offset, y1 * spatial_scale - offset);
+        let mut roi_w = x2 * spatial_scale - offset - start_w;
+        let mut roi_h = y2 * spatial_scale - offset - start_h;
+        if !aligned {
+            roi_w = roi_w.max(1.);
+            roi_h = roi_h.max(1.);
+        }
+        let (bin_w, bin_h) = (roi_w / output_w as f64, roi_h / output_h as f64);
+        let (grid_h, grid_w) = if sampling_ratio > 0 {
+            (sampling_ratio, sampling_ratio)
+        } else {
+            (bin_h.ceil() as i64, bin_w.ceil() as i64)
+        };
+        if grid_h <= 0 || grid_w <= 0 {
+            outputs.push(Tensor::f_zeros([1, channels, output_h, output_w], (kind, device))?);
+            continue;
+        }
+        let (ys, valid_y) = samples(start_h, bin_h, output_h, grid_h, height);
+        let (xs, valid_x) = samples(start_w, bin_w, output_w, grid_w, width);
+        let (ny, nx) = (ys.len() as i64, xs.len() as i64);
+        let ys = Tensor::f_from_slice(&ys)?.f_view([ny, 1])?.f_expand([ny, nx], false)?;
+        let xs = Tensor::f_from_slice(&xs)?.f_view([1, nx])?.f_expand([ny, nx], false)?;
+        let grid =
+            Tensor::f_stack(&[xs, ys], 2)?.f_unsqueeze(0)?.f_to_kind(kind)?.f_to_device(device)?;
+        let valid_y = Tensor::f_from_slice(&valid_y)?.f_view([ny, 1])?;
+        let valid = valid_y.f_mul(&Tensor::f_from_slice(&valid_x)?.f_view([1, nx])?)?;
+        // Bilinear interpolation, clamping the positions to the border.
+        let sampled =
+            features.f_narrow(0, batch_index, 1)?.f_grid_sampler_2d(&grid, 0, 1, false)?;
+        let sampled = sampled.f_mul(&valid.f_to_kind(kind)?.f_to_device(device)?)?;
+        let pooled =
+            sampled.f_avg_pool2d([grid_h, grid_w], [grid_h, grid_w], [0, 0], false, true, None)?;
+        outputs.push(pooled);
+    }
+    if outputs.is_empty() {
+        return Tensor::f_zeros([0, channels, output_h, output_w], (kind, device));
+    }
+    Tensor::f_cat(&outputs, 0)
+}
+
+/// Region of interest alignment, see `f_roi_align`.
+pub fn roi_align(
+    features: &Tensor,
+    rois: &Tensor,
+    output_size: (i64, i64),
+    spatial_scale: f64,
+    sampling_ratio: i64,
+    aligned: bool,
+) -> Tensor {
+    f_roi_align(features, rois, output_size, spatial_scale, sampling_ratio, aligned).unwrap()
+}
+
+/// Region of interest max pooling, as used by Fast R-CNN.
+/// <https://arxiv.org/abs/1504.08083>
+///
+/// The arguments are the same as for `f_roi_align`, the region coordinates are
+/// rounded to the closest pixels of the feature map and each bin returns the
+/// maximum of its pixels, or 0 for the bins outside of the feature map. This
+/// matches the torchvision `roi_pool` function.
+pub fn f_roi_pool(
+    features: &Tensor,
+    rois: &Tensor,
+    output_size: (i64, i64),
+    spatial_scale: f64,
+) -> Result<Tensor, TchError> {
+    let rois = read_rois(features, rois)?;
+    let (_, channels, height, width) = features.size4()?;
+    let (output_h, output_w) = output_size;
+    let (kind, device) = (features.f_kind()?, features.device());
+    // The [start, end) pixel ranges of the bins along an axis.
+    let bins = |start: f64, end: f64, bins: i64, size: i64| {
+        let (start, end) = ((start * spatial_scale).round(), (end * spatial_scale).round());
+        let bin_size = f64::max(end - start + 1., 1.) / bins as f64;
+        (0..bins)
+            .map(|bin| {
+                let bin_start = (bin as f64 * bin_size).floor() + start;
+                let bin_end = ((bin + 1) as f64 * bin_size).ceil() + start;
+                (bin_start.clamp(0., size as f64) as i64, bin_end.clamp(0., size as f64) as i64)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut outputs = Vec::with_capacity(rois.len());
+    for (batch_index, [x1, y1, x2, y2]) in rois {
+        let image = features.f_select(0, batch_index)?;
+        let mut pooled = Vec::with_capacity((output_h * output_w) as usize);
+        for (start_h, end_h) in bins(y1, y2, output_h, height) {
+            for &(start_w, end_w) in bins(x1, x2, output_w, width).iter() {
+                let value = if end_h <= start_h || end_w <= start_w {
+                    Tensor::f_zeros([channels], (kind, device))?
+                } else {
+                    image
+                        .f_narrow(1, start_h, end_h - start_h)?
+                        .f_narrow(2, start_w, end_w - start_w)?
+                        .f_amax([1, 2], false)?
+                };
+                pooled.push(value)
+            }
+        }
+        outputs.push(Tensor::f_stack(&pooled, 1)?.f_view([1, channels, output_h, output_w])?);
+    }
+    if outputs.is_empty() {
+        return Tensor::f_zeros([0, channels, output_h, output_w], (kind, device));
+    }
+    Tensor::f_cat(&outputs, 0)
+}
+
+/// Region of interest max pooling, see `f_roi_pool`.
+pub fn roi_pool(
+    features: &Tensor,
+    rois: &Tensor,
+    output_size: (i64, i64),
+    spatial_scale: f64,
+) -> Tensor {
+    f_roi_pool(features, rois, output_size, spatial_scale).unwrap()
+}
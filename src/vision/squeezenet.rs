@@ -7,7 +7,7 @@ fn max_pool2d(xs: &Tensor) -> Tensor {
 }
 
 fn fire(p: nn::Path, c_in: i64, c_squeeze: i64, c_exp1: i64, c_exp3: i64) -> impl Module {
-    let cfg3 = nn::ConvConfig { padding: 1, ..Default::default() };
+    let cfg3 = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
     let squeeze = nn::conv2d(&p / "squeeze", c_in, c_squeeze, 1, Default::default());
     let exp1 = nn::conv2d(&p / "expand1x1", c_squeeze, c_exp1, 1, Default::default());
     let exp3 = nn::conv2d(&p / "expand3x3", c_squeeze, c_exp3, 3, cfg3);
@@ -0,0 +1,332 @@
+//! Data augmentation transforms for images.
+//!
+//! The transforms operate on `[channels, height, width]` images or on
+//! `[batch, channels, height, width]` batches, in which case the random
+//! parameters are drawn for each image. The images either use `Uint8` values
+//! between 0 and 255 or float values between 0 and 1, the transforms preserve
+//! the kind of their input except `Normalize` which returns float values.
+//!
+//! The random parameters are drawn from the given random number generator so
+//! that the transforms are reproducible, regardless of the device of the
+//! images.
+//!
+//! ```no_run
+//! use rand::SeedableRng;
+//! use tch::vision::transforms::{Compose, Normalize, RandomHorizontalFlip, RandomResizedCrop};
+//! use tch::vision::transforms::Transform;
+//! # let images = tch::Tensor::zeros([8, 3, 64, 64], (tch::Kind::Uint8, tch::Device::Cpu));
+//! let transform = Compose(vec![
+//!     Box::new(RandomResizedCrop::new(32, 32)),
+//!     Box::new(RandomHorizontalFlip { p: 0.5 }),
+//!     Box::new(Normalize::imagenet()),
+//! ]);
+//! let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+//! let images = transform.transform(&images, &mut rng);
+//! ```
+use crate::{Kind, TchError, Tensor};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// An image transform.
+pub trait Transform: std::fmt::Debug + Send {
+    /// Transforms an image or a batch of images.
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError>;
+
+    fn transform(&self, xs: &Tensor, rng: &mut StdRng) -> Tensor {
+        self.f_transform(xs, rng).unwrap()
+    }
+}
+
+// Applies f to each image of a batch, or to a single image.
+fn per_image<F>(xs: &Tensor, mut f: F) -> Result<Tensor, TchError>
+where
+    F: FnMut(&Tensor) -> Result<Tensor, TchError>,
+{
+    match xs.dim() {
+        3 => f(xs),
+        4 => {
+            let images = xs.f_unbind(0)?.iter().map(&mut f).collect::<Result<Vec<_>, _>>()?;
+            Tensor::f_stack(&images, 0)
+        }
+        dim => Err(TchError::Shape(format!(
+            "expected an image with 3 dimensions or a batch with 4 dimensions, got {dim}"
+        ))),
+    }
+}
+
+// The maximum value of the images of this kind.
+fn max_value(kind: Kind) -> f64 {
+    if kind == Kind::Uint8 {
+        255.
+    } else {
+        1.
+    }
+}
+
+// Converts float values back to the kind of the input image.
+fn to_image_kind(xs: &Tensor, kind: Kind) -> Result<Tensor, TchError> {
+    let xs = xs.f_clamp(0., max_value(kind))?;
+    if kind == Kind::Uint8 {
+        xs.f_round()?.f_to_kind(kind)
+    } else {
+        xs.f_to_kind(kind)
+    }
+}
+
+// Draws a region with a random area and aspect ratio as done by torchvision,
+// returning (top, left, height, width).
+fn random_region(
+    rng: &mut StdRng,
+    height: i64,
+    width: i64,
+    scale: (f64, f64),
+    ratio: (f64, f64),
+) -> Option<(i64, i64, i64, i64)> {
+    let area = (height * width) as f64;
+    let log_ratio = (ratio.0.ln(), ratio.1.ln());
+    for _attempt in 0..10 {
+        let target_area = area * rng.gen_range(scale.0..=scale.1);
+        let aspect_ratio = rng.gen_range(log_ratio.0..=log_ratio.1).exp();
+        let w = (target_area * aspect_ratio).sqrt().round() as i64;
+        let h = (target_area / aspect_ratio).sqrt().round() as i64;
+        if 0 < w && w <= width && 0 < h && h <= height {
+            let top = rng.gen_range(0..=height - h);
+            let left = rng.gen_range(0..=width - w);
+            return Some((top, left, h, w));
+        }
+    }
+    None
+}
+
+/// Crops a random region of the images and resizes it to `height` x `width`.
+///
+/// The area of the region relative to the image is drawn uniformly from
+/// `scale`, and the logarithm of its aspect ratio from the logarithms of
+/// `ratio`.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomResizedCrop {
+    pub height: i64,
+    pub width: i64,
+    pub scale: (f64, f64),
+    pub ratio: (f64, f64),
+}
+
+impl RandomResizedCrop {
+    pub fn new(height: i64, width: i64) -> Self {
+        RandomResizedCrop { height, width, scale: (0.08, 1.0), ratio: (3. / 4., 4. / 3.) }
+    }
+
+    fn crop(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        let (_, height, width) = xs.size3()?;
+        let (top, left, h, w) = match random_region(rng, height, width, self.scale, self.ratio) {
+            Some(region) => region,
+            None => {
+                // Falls back to a center crop with the closest aspect ratio.
+                let in_ratio = width as f64 / height as f64;
+                let (w, h) = if in_ratio < self.ratio.0 {
+                    (width, (width as f64 / self.ratio.0).round() as i64)
+                } else if in_ratio > self.ratio.1 {
+                    ((height as f64 * self.ratio.1).round() as i64, height)
+                } else {
+                    (width, height)
+                };
+                ((height - h) / 2, (width - w) / 2, h, w)
+            }
+        };
+        let kind = xs.f_kind()?;
+        let crop = xs.f_narrow(1, top, h)?.f_narrow(2, left, w)?.f_to_kind(Kind::Float)?;
+        let resized = crop.f_unsqueeze(0)?.f_upsample_bilinear2d(
+            [self.height, self.width],
+            false,
+            None,
+            None,
+        )?;
+        to_image_kind(&resized.f_squeeze_dim(0)?, kind)
+    }
+}
+
+impl Transform for RandomResizedCrop {
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        per_image(xs, |xs| self.crop(xs, rng))
+    }
+}
+
+/// Flips the images horizontally with probability `p`.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomHorizontalFlip {
+    pub p: f64,
+}
+
+impl Transform for RandomHorizontalFlip {
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        per_image(
+            xs,
+            |xs| {
+                if rng.gen_bool(self.p) {
+                    xs.f_flip([2])
+                } else {
+                    Ok(xs.shallow_clone())
+                }
+            },
+        )
+    }
+}
+
+/// Randomly changes the brightness, the contrast and the saturation of the
+/// images, in a random order.
+///
+/// The factor of each adjustment is drawn uniformly from `[1 - v, 1 + v]`,
+/// clamped to be non-negative, where `v` is the value of the field. A value
+/// of 0 disables the adjustment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ColorJitter {
+    pub brightness: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+}
+
+// The luminance of RGB images with shape [1, height, width], other images
+// are returned as is.
+fn grayscale(xs: &Tensor) -> Result<Tensor, TchError> {
+    if xs.size()[0] != 3 {
+        return Ok(xs.shallow_clone());
+    }
+    let weights = Tensor::f_from_slice(&[0.299f32, 0.587, 0.114])?
+        .f_to_device(xs.device())?
+        .f_view([3, 1, 1])?;
+    xs.f_mul(&weights)?.f_sum_dim_intlist(0, true, Kind::Float)
+}
+
+impl ColorJitter {
+    fn jitter(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        let kind = xs.f_kind()?;
+        let max = max_value(kind);
+        let mut xs = xs.f_to_kind(Kind::Float)?;
+        let mut adjustments = [(0, self.brightness), (1, self.contrast), (2, self.saturation)];
+        adjustments.shuffle(rng);
+        for (adjustment, v) in adjustments {
+            if v <= 0. {
+                continue;
+            }
+            let factor = rng.gen_range(f64::max(0., 1. - v)..=1. + v);
+            // Blends the image with a reference image, this is a scaling for
+            // the brightness.
+            let reference = match adjustment {
+                0 => xs.f_zeros_like()?,
+                1 => grayscale(&xs)?.f_mean(Kind::Float)?,
+                _ => grayscale(&xs)?,
+            };
+            xs = (xs.f_mul_scalar(factor)? + reference.f_mul_scalar(1. - factor)?)
+                .f_clamp(0., max)?;
+        }
+        to_image_kind(&xs, kind)
+    }
+}
+
+impl Transform for ColorJitter {
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        per_image(xs, |xs| self.jitter(xs, rng))
+    }
+}
+
+/// Normalizes each channel of the images with the given mean and standard
+/// deviation, returning float values. `Uint8` images are first scaled to
+/// values between 0 and 1.
+#[derive(Debug, Clone)]
+pub struct Normalize {
+    pub mean: Vec<f64>,
+    pub std: Vec<f64>,
+}
+
+impl Normalize {
+    /// The normalization used by the ImageNet models.
+    pub fn imagenet() -> Self {
+        Normalize { mean: vec![0.485, 0.456, 0.406], std: vec![0.229, 0.224, 0.225] }
+    }
+}
+
+impl Transform for Normalize {
+    fn f_transform(&self, xs: &Tensor, _rng: &mut StdRng) -> Result<Tensor, TchError> {
+        let size = xs.f_size()?;
+        let channels = match size.len() {
+            3 => size[0],
+            4 => size[1],
+            dim => {
+                return Err(TchError::Shape(format!(
+                    "expected an image with 3 dimensions or a batch with 4 dimensions, got {dim}"
+                )))
+            }
+        };
+        if self.mean.len() as i64 != channels || self.std.len() as i64 != channels {
+            return Err(TchError::Shape(format!(
+                "normalize: {} means and {} stds for {channels} channels",
+                self.mean.len(),
+                self.std.len()
+            )));
+        }
+        let channel_values = |values: &[f64]| {
+            Tensor::f_from_slice(values)?
+                .f_to_kind(Kind::Float)?
+                .f_to_device(xs.device())?
+                .f_view([-1, 1, 1])
+        };
+        let (mean, std) = (channel_values(&self.mean)?, channel_values(&self.std)?);
+        let xs = xs.f_to_kind(Kind::Float)?.f_div_scalar(max_value(xs.f_kind()?))?;
+        xs.f_sub(&mean)?.f_div(&std)
+    }
+}
+
+/// Erases a random rectangle of the images with probability `p`, setting it
+/// to `value`.
+///
+/// The area of the rectangle relative to the image is drawn uniformly from
+/// `scale`, and the logarithm of its aspect ratio from the logarithms of
+/// `ratio`.
+/// <https://arxiv.org/abs/1708.04896>
+#[derive(Debug, Clone, Copy)]
+pub struct RandomErasing {
+    pub p: f64,
+    pub scale: (f64, f64),
+    pub ratio: (f64, f64),
+    pub value: f64,
+}
+
+impl Default for RandomErasing {
+    fn default() -> Self {
+        RandomErasing { p: 0.5, scale: (0.02, 0.33), ratio: (0.3, 3.3), value: 0. }
+    }
+}
+
+impl Transform for RandomErasing {
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        per_image(xs, |xs| {
+            if !rng.gen_bool(self.p) {
+                return Ok(xs.shallow_clone());
+            }
+            let (_, height, width) = xs.size3()?;
+            match random_region(rng, height, width, self.scale, self.ratio) {
+                Some((top, left, h, w)) => {
+                    let xs = xs.f_copy()?;
+                    let _ = xs.f_narrow(1, top, h)?.f_narrow(2, left, w)?.f_fill_(self.value)?;
+                    Ok(xs)
+                }
+                None => Ok(xs.shallow_clone()),
+            }
+        })
+    }
+}
+
+/// Applies a sequence of transforms.
+#[derive(Debug)]
+pub struct Compose(pub Vec<Box<dyn Transform>>);
+
+impl Transform for Compose {
+    fn f_transform(&self, xs: &Tensor, rng: &mut StdRng) -> Result<Tensor, TchError> {
+        let mut xs = xs.shallow_clone();
+        for transform in self.0.iter() {
+            xs = transform.f_transform(&xs, rng)?
+        }
+        Ok(xs)
+    }
+}
@@ -0,0 +1,150 @@
+//! Vision Transformer (ViT) implementation.
+//!
+//! An Image is Worth 16x16 Words: Transformers for Image Recognition at Scale
+//! <https://arxiv.org/abs/2010.11929>
+//!
+//! The variables use the names of the torchvision implementation so that the
+//! exported state dicts of `vit_b_16` and `vit_l_16` can be loaded, e.g.
+//! `conv_proj.weight`, `class_token`, `encoder.pos_embedding`,
+//! `encoder.layers.encoder_layer_0.self_attention.in_proj_weight`,
+//! `encoder.layers.encoder_layer_0.mlp.0.weight`, `encoder.ln.weight` and
+//! `heads.head.weight`.
+use crate::{nn, nn::ModuleT, IndexOp, Tensor};
+
+const IMAGE_SIZE: i64 = 224;
+const PATCH_SIZE: i64 = 16;
+const LAYER_NORM_EPS: f64 = 1e-6;
+
+#[derive(Debug)]
+struct EncoderBlock {
+    ln_1: nn::LayerNorm,
+    self_attention: nn::MultiHeadAttention,
+    ln_2: nn::LayerNorm,
+    mlp_fc1: nn::Linear,
+    mlp_fc2: nn::Linear,
+}
+
+impl EncoderBlock {
+    fn new(vs: nn::Path, hidden_dim: i64, num_heads: i64, mlp_dim: i64) -> Self {
+        let ln_config = nn::LayerNormConfig { eps: LAYER_NORM_EPS, ..Default::default() };
+        let ln_1 = nn::layer_norm(&vs / "ln_1", vec![hidden_dim], ln_config);
+        let attn_config = nn::MultiHeadAttentionConfig { batch_first: true, ..Default::default() };
+        let self_attention =
+            nn::multi_head_attention(&vs / "self_attention", hidden_dim, num_heads, attn_config);
+        let ln_2 = nn::layer_norm(&vs / "ln_2", vec![hidden_dim], ln_config);
+        // The mlp is a sequential in torchvision, with a gelu and a dropout
+        // between the two linear layers.
+        let mlp_fc1 = nn::linear(&vs / "mlp" / 0, hidden_dim, mlp_dim, Default::default());
+        let mlp_fc2 = nn::linear(&vs / "mlp" / 3, mlp_dim, hidden_dim, Default::default());
+        Self { ln_1, self_attention, ln_2, mlp_fc1, mlp_fc2 }
+    }
+}
+
+impl ModuleT for EncoderBlock {
+    fn forward_t(&self, xs: &Tensor, t: bool) -> Tensor {
+        let xs = xs + xs.apply(&self.ln_1).apply_t(&self.self_attention, t);
+        let ys = xs.apply(&self.ln_2).apply(&self.mlp_fc1).gelu("none").apply(&self.mlp_fc2);
+        xs + ys
+    }
+}
+
+/// Interpolates the positional embeddings of the patches to a grid of
+/// `grid_h` x `grid_w` patches using a bicubic interpolation, as done by
+/// torchvision when using another image size.
+///
+/// `pos_embedding` has shape `[1, 1 + grid * grid, hidden_dim]`, the
+/// embedding of the class token comes first and is kept as is.
+pub fn interpolate_pos_embedding(pos_embedding: &Tensor, grid_h: i64, grid_w: i64) -> Tensor {
+    let (_, seq_len, hidden_dim) = pos_embedding.size3().unwrap();
+    let grid = ((seq_len - 1) as f64).sqrt() as i64;
+    if grid * grid != seq_len - 1 {
+        panic!("the positional embedding of {} patches is not a square grid", seq_len - 1)
+    }
+    if grid == grid_h && grid == grid_w {
+        return pos_embedding.shallow_clone();
+    }
+    let class_pos_embedding = pos_embedding.i((.., ..1));
+    let patch_pos_embedding = pos_embedding
+        .i((.., 1..))
+        .permute([0, 2, 1])
+        .reshape([1, hidden_dim, grid, grid])
+        .upsample_bicubic2d([grid_h, grid_w], true, None, None)
+        .reshape([1, hidden_dim, grid_h * grid_w])
+        .permute([0, 2, 1]);
+    Tensor::cat(&[class_pos_embedding, patch_pos_embedding], 1)
+}
+
+#[derive(Debug)]
+struct VisionTransformer {
+    conv_proj: nn::Conv2D,
+    class_token: Tensor,
+    pos_embedding: Tensor,
+    layers: Vec<EncoderBlock>,
+    ln: nn::LayerNorm,
+    head: nn::Linear,
+}
+
+impl VisionTransformer {
+    fn new(
+        p: &nn::Path,
+        num_layers: i64,
+        num_heads: i64,
+        hidden_dim: i64,
+        mlp_dim: i64,
+        num_classes: i64,
+    ) -> Self {
+        let config = nn::ConvConfig { stride: PATCH_SIZE, ..Default::default() };
+        let conv_proj = nn::conv2d(p / "conv_proj", 3, hidden_dim, PATCH_SIZE, config);
+        let class_token = p.var("class_token", &[1, 1, hidden_dim], nn::Init::Const(0.));
+        let encoder = p / "encoder";
+        let seq_len = (IMAGE_SIZE / PATCH_SIZE) * (IMAGE_SIZE / PATCH_SIZE) + 1;
+        let pos_embedding = encoder.var(
+            "pos_embedding",
+            &[1, seq_len, hidden_dim],
+            nn::Init::Randn { mean: 0., stdev: 0.02 },
+        );
+        let layers = (0..num_layers)
+            .map(|i| {
+                let vs = &encoder / "layers" / format!("encoder_layer_{i}");
+                EncoderBlock::new(vs, hidden_dim, num_heads, mlp_dim)
+            })
+            .collect();
+        let ln_config = nn::LayerNormConfig { eps: LAYER_NORM_EPS, ..Default::default() };
+        let ln = nn::layer_norm(&encoder / "ln", vec![hidden_dim], ln_config);
+        let head_config = nn::LinearConfig {
+            ws_init: nn::Init::Const(0.),
+            bs_init: Some(nn::Init::Const(0.)),
+            bias: true,
+        };
+        let head = nn::linear(p / "heads" / "head", hidden_dim, num_classes, head_config);
+        Self { conv_proj, class_token, pos_embedding, layers, ln, head }
+    }
+}
+
+impl ModuleT for VisionTransformer {
+    fn forward_t(&self, xs: &Tensor, t: bool) -> Tensor {
+        let (b, _c, h, w) = xs.size4().unwrap();
+        if h % PATCH_SIZE != 0 || w % PATCH_SIZE != 0 {
+            panic!("image size {h}x{w} is not a multiple of the patch size {PATCH_SIZE}")
+        }
+        let xs = xs.apply(&self.conv_proj);
+        let (_, hidden_dim, grid_h, grid_w) = xs.size4().unwrap();
+        let xs = xs.reshape([b, hidden_dim, grid_h * grid_w]).permute([0, 2, 1]);
+        let xs = Tensor::cat(&[self.class_token.expand([b, -1, -1], false), xs], 1);
+        let mut xs = xs + interpolate_pos_embedding(&self.pos_embedding, grid_h, grid_w);
+        for layer in self.layers.iter() {
+            xs = xs.apply_t(layer, t)
+        }
+        xs.apply(&self.ln).select(1, 0).apply(&self.head)
+    }
+}
+
+/// ViT-B/16, the base model using patches of 16x16 pixels.
+pub fn vit_b_16(p: &nn::Path, num_classes: i64) -> impl ModuleT {
+    VisionTransformer::new(p, 12, 12, 768, 3072, num_classes)
+}
+
+/// ViT-L/16, the large model using patches of 16x16 pixels.
+pub fn vit_l_16(p: &nn::Path, num_classes: i64) -> impl ModuleT {
+    VisionTransformer::new(p, 24, 16, 1024, 4096, num_classes)
+}
@@ -0,0 +1,587 @@
+//! Autograd related helpers.
+use super::tensor::Tensor;
+use crate::TchError;
+use libc::c_int;
+
+/// The options of a backward pass, see `Tensor::backward_opt`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BackwardOptions<'a> {
+    /// Keep the graph after the backward pass so that it can be run again. When
+    /// not set, the buffers saved in the forward pass are released.
+    pub retain_graph: bool,
+    /// Build the graph of the backward pass, this makes it possible to compute
+    /// higher order derivatives. This usually requires `retain_graph` too.
+    pub create_graph: bool,
+    /// The tensors in which to accumulate the gradients, all the leaves of the
+    /// graph are used when `None`.
+    pub inputs: Option<&'a [&'a Tensor]>,
+}
+
+/// Computes the gradients of `outputs` with respect to `inputs` and returns
+/// them rather than accumulating them in the `grad` field of the inputs.
+///
+/// `grad_outputs` contains the gradients with respect to each output, it can be
+/// left empty when all the outputs are scalars in which case ones are used.
+/// With `create_graph`, the returned gradients are part of the graph and can
+/// be differentiated again. An input that is not used to compute the outputs
+/// results in an error, or in `None` when `allow_unused` is set.
+pub fn grad(
+    outputs: &[&Tensor],
+    inputs: &[&Tensor],
+    grad_outputs: &[&Tensor],
+    retain_graph: bool,
+    create_graph: bool,
+    allow_unused: bool,
+) -> Result<Vec<Option<Tensor>>, TchError> {
+    let mut results = vec![std::ptr::null_mut(); inputs.len()];
+    let outputs: Vec<_> = outputs.iter().map(|x| x.c_tensor).collect();
+    let inputs: Vec<_> = inputs.iter().map(|x| x.c_tensor).collect();
+    let grad_outputs: Vec<_> = grad_outputs.iter().map(|x| x.c_tensor).collect();
+    unsafe_torch_err!(torch_sys::at_autograd_grad(
+        outputs.as_ptr(),
+        outputs.len() as c_int,
+        inputs.as_ptr(),
+        inputs.len() as c_int,
+        grad_outputs.as_ptr(),
+        grad_outputs.len() as c_int,
+        results.as_mut_ptr(),
+        retain_graph as c_int,
+        create_graph as c_int,
+        allow_unused as c_int,
+    ));
+    let results =
+        results
+            .into_iter()
+            .map(|c_tensor| if c_tensor.is_null() { None } else { Some(Tensor { c_tensor }) });
+    Ok(results.collect())
+}
+
+/// Runs the backward pass from multiple outputs, accumulating the gradients in
+/// the `grad` field of the graph leaves.
+///
+/// `grad_tensors` contains the gradient with respect to each output, i.e. the
+/// vector in a vector-Jacobian product, and must have the same shape as the
+/// corresponding output. Contrary to `Tensor::backward`, the outputs do not
+/// have to be scalars.
+pub fn backward(
+    tensors: &[&Tensor],
+    grad_tensors: &[&Tensor],
+    retain_graph: bool,
+    create_graph: bool,
+) -> Result<(), TchError> {
+    if tensors.len() != grad_tensors.len() {
+        return Err(TchError::Shape(format!(
+            "backward: got {} tensors but {} grad tensors",
+            tensors.len(),
+            grad_tensors.len()
+        )));
+    }
+    for (index, (tensor, grad)) in tensors.iter().zip(grad_tensors.iter()).enumerate() {
+        let (size, grad_size) = (tensor.f_size()?, grad.f_size()?);
+        if size != grad_size {
+            return Err(TchError::Shape(format!(
+                "backward: grad tensor {index} has shape {grad_size:?}, expected {size:?}"
+            )));
+        }
+    }
+    let tensors: Vec<_> = tensors.iter().map(|x| x.c_tensor).collect();
+    let grad_tensors: Vec<_> = grad_tensors.iter().map(|x| x.c_tensor).collect();
+    unsafe_torch_err!(torch_sys::at_autograd_backward(
+        tensors.as_ptr(),
+        tensors.len() as c_int,
+        grad_tensors.as_ptr(),
+        retain_graph as c_int,
+        create_graph as c_int,
+    ));
+    Ok(())
+}
+
+/// Enables or disables the autograd anomaly detection mode.
+///
+/// When enabled, the backward pass returns an error as soon as a backward
+/// function produces a NaN value, if `check_nan` is set. The error message
+/// names the offending backward node, e.g. `SqrtBackward0`. The forward pass
+/// also records extra metadata for each node so that errors in the backward
+/// pass can be related to the forward operation that created the node.
+///
+/// This mode has a large performance cost, the backward pass can be several
+/// times slower, so it should only be used for debugging. The
+/// `anomaly-detection` example measures this overhead.
+pub fn set_detect_anomaly(enabled: bool, check_nan: bool) {
+    unsafe_torch!(torch_sys::at_set_anomaly_mode(i32::from(enabled), i32::from(check_nan)))
+}
+
+/// Returns true if the anomaly detection mode is enabled.
+pub fn is_anomaly_enabled() -> bool {
+    unsafe_torch!(torch_sys::at_anomaly_mode_is_enabled()) != 0
+}
+
+/// Returns true if the anomaly detection mode checks for NaN values.
+pub fn is_anomaly_check_nan_enabled() -> bool {
+    unsafe_torch!(torch_sys::at_anomaly_mode_should_check_nan()) != 0
+}
+
+/// A RAII guard that enables the anomaly detection mode until deallocated.
+#[derive(Debug)]
+pub struct DetectAnomalyGuard {
+    prev_enabled: bool,
+    prev_check_nan: bool,
+}
+
+impl DetectAnomalyGuard {
+    /// Enables the anomaly detection mode, see `set_detect_anomaly`. The previous
+    /// mode is restored when the guard gets deallocated.
+    pub fn new(check_nan: bool) -> DetectAnomalyGuard {
+        let prev_enabled = is_anomaly_enabled();
+        let prev_check_nan = is_anomaly_check_nan_enabled();
+        set_detect_anomaly(true, check_nan);
+        DetectAnomalyGuard { prev_enabled, prev_check_nan }
+    }
+}
+
+impl Drop for DetectAnomalyGuard {
+    fn drop(&mut self) {
+        set_detect_anomaly(self.prev_enabled, self.prev_check_nan)
+    }
+}
+
+/// Runs a closure with the anomaly detection mode enabled, including the NaN
+/// checks. Backward passes have to be run within the closure to be checked.
+pub fn detect_anomaly<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let _guard = DetectAnomalyGuard::new(true);
+    f()
+}
+
+/// The options of `gradcheck` and `gradgradcheck`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradcheckOptions {
+    /// The perturbation used for the central finite differences.
+    pub eps: f64,
+    /// The absolute and relative tolerances, a gradient `a` matches its
+    /// numerical estimate `n` when `|a - n| <= atol + rtol * |n|`.
+    pub atol: f64,
+    pub rtol: f64,
+    /// Converts the floating point inputs to double precision before the
+    /// check, finite differences in single precision are only accurate with
+    /// much larger tolerances.
+    pub check_double: bool,
+}
+
+impl Default for GradcheckOptions {
+    fn default() -> Self {
+        GradcheckOptions { eps: 1e-6, atol: 1e-5, rtol: 1e-3, check_double: true }
+    }
+}
+
+/// The worst mismatch between the analytical and numerical gradients for an
+/// input of `gradcheck`, i.e. one element of the Jacobian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradcheckMismatch {
+    /// The index of the input in the inputs of the checked function.
+    pub input: usize,
+    /// The index of the perturbed element of the input.
+    pub input_index: Vec<i64>,
+    /// The index of the output element.
+    pub output_index: Vec<i64>,
+    pub analytical: f64,
+    pub numerical: f64,
+}
+
+impl std::fmt::Display for GradcheckMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "input {} element {:?} for output element {:?}: analytical {} numerical {}",
+            self.input, self.input_index, self.output_index, self.analytical, self.numerical
+        )
+    }
+}
+
+fn unravel(mut index: i64, size: &[i64]) -> Vec<i64> {
+    let mut result = vec![0; size.len()];
+    for (r, &s) in result.iter_mut().zip(size.iter()).rev() {
+        *r = index % s.max(1);
+        index /= s.max(1);
+    }
+    result
+}
+
+// Evaluates the function on detached outputs, flattened in double precision.
+fn eval_flat<F>(f: &F, inputs: &[Tensor]) -> Result<Tensor, TchError>
+where
+    F: Fn(&[Tensor]) -> Result<Tensor, TchError>,
+{
+    f(inputs)?.f_detach()?.f_to_kind(crate::Kind::Double)?.f_reshape([-1])
+}
+
+/// Checks the gradients computed by the backward pass of `f` against central
+/// finite differences, like `torch.autograd.gradcheck`.
+///
+/// The inputs for which `requires_grad` is set are checked, the other inputs
+/// are passed unchanged. The full Jacobian is computed, with one backward pass
+/// per output element and two evaluations of `f` per input element, so this
+/// should be used on small inputs.
+///
+/// An error listing the worst mismatch for each input is returned when the
+/// gradients differ. Elements where `f` is not differentiable, detected by
+/// one-sided differences that disagree, are skipped with a warning.
+pub fn gradcheck<F>(f: F, inputs: &[Tensor], options: GradcheckOptions) -> Result<(), TchError>
+where
+    F: Fn(&[Tensor]) -> Tensor,
+{
+    try_gradcheck(|xs| Ok(f(xs)), inputs, options)
+}
+
+// Same as `gradcheck` for a function that can fail, the errors are propagated.
+fn try_gradcheck<F>(f: F, inputs: &[Tensor], options: GradcheckOptions) -> Result<(), TchError>
+where
+    F: Fn(&[Tensor]) -> Result<Tensor, TchError>,
+{
+    let checked: Vec<usize> = (0..inputs.len()).filter(|&i| inputs[i].requires_grad()).collect();
+    if checked.is_empty() {
+        return Err(TchError::Torch("gradcheck: no input requires grad".to_string()));
+    }
+    let inputs = inputs
+        .iter()
+        .map(|x| {
+            let x = if options.check_double && x.f_is_floating_point()? {
+                x.f_to_kind(crate::Kind::Double)?
+            } else {
+                x.shallow_clone()
+            };
+            let requires_grad = x.requires_grad();
+            x.f_detach()?.f_copy()?.f_set_requires_grad(requires_grad)
+        })
+        .collect::<Result<Vec<_>, TchError>>()?;
+
+    let output = f(&inputs)?;
+    let output_size = output.size();
+    let numel = output.numel() as i64;
+    let checked_inputs: Vec<&Tensor> = checked.iter().map(|&i| &inputs[i]).collect();
+    // The analytical Jacobian of each checked input, with one row per output
+    // element.
+    let mut analytical: Vec<Vec<Tensor>> = checked.iter().map(|_| vec![]).collect();
+    for j in 0..numel {
+        let grads = if output.requires_grad() {
+            let grad_output = output.f_zeros_like()?;
+            let _ = grad_output.f_view([-1])?.f_get(j)?.f_fill_(1.)?;
+            grad(&[&output], &checked_inputs, &[&grad_output], true, false, true)?
+        } else {
+            checked.iter().map(|_| None).collect()
+        };
+        for ((rows, grad), x) in analytical.iter_mut().zip(grads).zip(checked_inputs.iter()) {
+            let row = match grad {
+                Some(grad) => grad.f_to_kind(crate::Kind::Double)?.f_reshape([-1])?,
+                None => Tensor::f_zeros([x.numel() as i64], (crate::Kind::Double, x.device()))?,
+            };
+            rows.push(row)
+        }
+    }
+    drop(checked_inputs);
+
+    let base = eval_flat(&f, &inputs)?;
+    let eps = options.eps;
+    let mut mismatches = vec![];
+    for (c, &i) in checked.iter().enumerate() {
+        let input_size = inputs[i].size();
+        let mut worst: Option<(f64, GradcheckMismatch)> = None;
+        for k in 0..inputs[i].numel() as i64 {
+            let element = {
+                let _guard = crate::no_grad_guard();
+                inputs[i].f_view([-1])?.f_get(k)?
+            };
+            let value = element.f_double_value(&[])?;
+            let eval_at = |v: f64| -> Result<Tensor, TchError> {
+                {
+                    let _guard = crate::no_grad_guard();
+                    let _ = element.shallow_clone().f_fill_(v)?;
+                }
+                eval_flat(&f, &inputs)
+            };
+            let plus = eval_at(value + eps)?;
+            let minus = eval_at(value - eps)?;
+            {
+                let _guard = crate::no_grad_guard();
+                let _ = element.shallow_clone().f_fill_(value)?;
+            }
+            let numerical = plus.f_sub(&minus)?.f_div_scalar(2. * eps)?;
+            // The one-sided differences disagree at non-differentiable points.
+            let forward = plus.f_sub(&base)?.f_div_scalar(eps)?;
+            let backward = base.f_sub(&minus)?.f_div_scalar(eps)?;
+            let tolerance =
+                numerical.f_abs()?.f_mul_scalar(options.rtol)?.f_add_scalar(options.atol)?;
+            let kink =
+                forward.f_sub(&backward)?.f_abs()?.f_gt_tensor(&tolerance.f_mul_scalar(10.)?)?;
+            if bool::try_from(kink.f_any()?)? {
+                log::warn!(
+                    target: "tch",
+                    "gradcheck: skipping element {:?} of input {i}, the function is not differentiable there",
+                    unravel(k, &input_size)
+                );
+                continue;
+            }
+            let column: Vec<f64> = Vec::<f64>::try_from(&numerical)?;
+            let rows: Vec<f64> = analytical[c]
+                .iter()
+                .map(|row| row.f_double_value(&[k]))
+                .collect::<Result<_, TchError>>()?;
+            for (j, (&a, &n)) in rows.iter().zip(column.iter()).enumerate() {
+                let excess = (a - n).abs() - (options.atol + options.rtol * n.abs());
+                let is_worse = match worst.as_ref() {
+                    None => true,
+                    Some((w, _)) => excess > *w,
+                };
+                if (excess > 0. || a.is_nan() != n.is_nan()) && is_worse {
+                    let mismatch = GradcheckMismatch {
+                        input: i,
+                        input_index: unravel(k, &input_size),
+                        output_index: unravel(j as i64, &output_size),
+                        analytical: a,
+                        numerical: n,
+                    };
+                    worst = Some((excess, mismatch))
+                }
+            }
+        }
+        mismatches.extend(worst.map(|(_, m)| m));
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(TchError::Gradcheck(mismatches))
+    }
+}
+
+/// Checks the second order gradients of `f`, like
+/// `torch.autograd.gradgradcheck`.
+///
+/// This runs `gradcheck` on the function returning the gradients of `f` with
+/// respect to the inputs that require grad, for a fixed vector of output
+/// gradients.
+pub fn gradgradcheck<F>(f: F, inputs: &[Tensor], options: GradcheckOptions) -> Result<(), TchError>
+where
+    F: Fn(&[Tensor]) -> Tensor,
+{
+    let first_order = |xs: &[Tensor]| -> Result<Tensor, TchError> {
+        let output = f(xs);
+        let checked: Vec<&Tensor> = xs.iter().filter(|x| x.requires_grad()).collect();
+        // A deterministic vector with varied entries, so that the check does
+        // not depend on the random number generator.
+        let grad_output =
+            Tensor::f_arange(output.numel() as i64, (output.kind(), output.device()))?
+                .f_add_scalar(1.)?
+                .f_sin()?
+                .f_reshape(output.size())?;
+        let grads = grad(&[&output], &checked, &[&grad_output], true, true, true)?;
+        let grads = grads
+            .into_iter()
+            .zip(checked.iter())
+            .map(|(g, x)| match g {
+                Some(g) => g.f_reshape([-1]),
+                None => x.f_zeros_like()?.f_reshape([-1]),
+            })
+            .collect::<Result<Vec<_>, TchError>>()?;
+        Tensor::f_cat(&grads, 0)
+    };
+    try_gradcheck(first_order, inputs, options)
+}
+
+/// Computes the gradients of the loss of each sample with respect to `params`,
+/// as needed for differentially private training or per-example influence
+/// diagnostics.
+///
+/// `loss_fn` is called on slices of `microbatch` samples of `inputs` and
+/// `targets` along their first dimension and must return the per-sample
+/// losses, i.e. one element per sample in the slice. For each parameter, the
+/// result stacks the per-sample gradients along a new first dimension of size
+/// the number of samples. Summing these gives the gradient of the sum of the
+/// losses over the batch.
+///
+/// Each slice is run through a single forward pass followed by one backward
+/// pass per sample. Parameters that do not contribute to the loss of a sample
+/// get a zero gradient.
+pub fn f_per_sample_grads<F>(
+    loss_fn: F,
+    params: &[Tensor],
+    inputs: &Tensor,
+    targets: &Tensor,
+    microbatch: usize,
+) -> Result<Vec<Tensor>, TchError>
+where
+    F: Fn(&Tensor, &Tensor) -> Tensor,
+{
+    if microbatch == 0 {
+        return Err(TchError::Shape("per_sample_grads: microbatch must be positive".to_string()));
+    }
+    let batch = *inputs.f_size()?.first().unwrap_or(&0);
+    let target_batch = *targets.f_size()?.first().unwrap_or(&0);
+    if batch != target_batch {
+        return Err(TchError::Shape(format!(
+            "per_sample_grads: {batch} inputs but {target_batch} targets"
+        )));
+    }
+    let params: Vec<&Tensor> = params.iter().collect();
+    let mut grads: Vec<Vec<Tensor>> = params.iter().map(|_| vec![]).collect();
+    let mut start = 0;
+    while start < batch {
+        let len = (microbatch as i64).min(batch - start);
+        let xs = inputs.f_narrow(0, start, len)?;
+        let ys = targets.f_narrow(0, start, len)?;
+        let losses = loss_fn(&xs, &ys);
+        if losses.numel() as i64 != len {
+            return Err(TchError::Shape(format!(
+                "per_sample_grads: the loss has shape {:?} for {len} samples, expected one loss per sample",
+                losses.size()
+            )));
+        }
+        let losses = losses.f_reshape([-1])?;
+        for i in 0..len {
+            let loss = losses.f_get(i)?;
+            let sample_grads = grad(&[&loss], &params, &[], i + 1 < len, false, true)?;
+            for ((g, p), acc) in sample_grads.into_iter().zip(params.iter()).zip(grads.iter_mut()) {
+                acc.push(match g {
+                    Some(g) => g,
+                    None => p.f_zeros_like()?,
+                })
+            }
+        }
+        start += len
+    }
+    grads
+        .iter()
+        .zip(params.iter())
+        .map(|(g, p)| {
+            if g.is_empty() {
+                let mut size = p.size();
+                size.insert(0, 0);
+                Tensor::f_zeros(&size, (p.kind(), p.device()))
+            } else {
+                Tensor::f_stack(g, 0)
+            }
+        })
+        .collect()
+}
+
+/// Computes the gradients of the loss of each sample, see
+/// `f_per_sample_grads`.
+pub fn per_sample_grads<F>(
+    loss_fn: F,
+    params: &[Tensor],
+    inputs: &Tensor,
+    targets: &Tensor,
+    microbatch: usize,
+) -> Vec<Tensor>
+where
+    F: Fn(&Tensor, &Tensor) -> Tensor,
+{
+    f_per_sample_grads(loss_fn, params, inputs, targets, microbatch).unwrap()
+}
+
+/// Computes the per-sample gradients of the weight and bias of a linear layer
+/// from its inputs of shape `[batch, .., in]` and the gradients of the loss
+/// with respect to its outputs, of shape `[batch, .., out]`.
+///
+/// This requires a single backward pass for the whole batch rather than one
+/// per sample: the output gradients can be obtained with `grad` applied to
+/// the sum of the per-sample losses and to the layer output. The returned
+/// weight gradients have shape `[batch, out, in]` and the bias gradients
+/// `[batch, out]`.
+pub fn f_linear_per_sample_grads(
+    inputs: &Tensor,
+    grad_outputs: &Tensor,
+) -> Result<(Tensor, Tensor), TchError> {
+    let (in_size, out_size) = (inputs.f_size()?, grad_outputs.f_size()?);
+    if in_size.len() < 2
+        || in_size.len() != out_size.len()
+        || in_size[..in_size.len() - 1] != out_size[..out_size.len() - 1]
+    {
+        return Err(TchError::Shape(format!(
+            "linear_per_sample_grads: incompatible inputs {in_size:?} and output gradients {out_size:?}"
+        )));
+    }
+    let batch = in_size[0];
+    let inputs = inputs.f_reshape([batch, -1, in_size[in_size.len() - 1]])?;
+    let grad_outputs = grad_outputs.f_reshape([batch, -1, out_size[out_size.len() - 1]])?;
+    let ws = Tensor::f_einsum("bso,bsi->boi", &[&grad_outputs, &inputs], None::<i64>)?;
+    let bs = grad_outputs.f_sum_dim_intlist(1, false, None)?;
+    Ok((ws, bs))
+}
+
+/// Computes the per-sample gradients of a linear layer, see
+/// `f_linear_per_sample_grads`.
+pub fn linear_per_sample_grads(inputs: &Tensor, grad_outputs: &Tensor) -> (Tensor, Tensor) {
+    f_linear_per_sample_grads(inputs, grad_outputs).unwrap()
+}
+
+// The norm of the gradients of each sample over all the parameters.
+fn per_sample_norms(grads: &[Tensor]) -> Result<Tensor, TchError> {
+    let mut sq_norms: Option<Tensor> = None;
+    for g in grads.iter() {
+        let sq = g.f_flatten(1, -1)?.f_square()?.f_sum_dim_intlist(1, false, crate::Kind::Float)?;
+        sq_norms = Some(match sq_norms {
+            None => sq,
+            Some(s) => s.f_add(&sq)?,
+        })
+    }
+    match sq_norms {
+        None => Err(TchError::Shape("per-sample gradients: no gradients".to_string())),
+        Some(sq_norms) => sq_norms.f_sqrt(),
+    }
+}
+
+/// Scales the per-sample gradients returned by `per_sample_grads` so that the
+/// norm of the gradients of each sample, computed over all the parameters,
+/// is at most `max_norm`. Samples with a smaller norm are left unchanged.
+pub fn f_clip_per_sample_grads(grads: &[Tensor], max_norm: f64) -> Result<Vec<Tensor>, TchError> {
+    let _guard = crate::no_grad_guard();
+    let norms = per_sample_norms(grads)?;
+    let scales =
+        norms.f_add_scalar(1e-6)?.f_reciprocal()?.f_mul_scalar(max_norm)?.f_clamp_max(1.)?;
+    grads
+        .iter()
+        .map(|g| {
+            let mut size = vec![1; g.dim()];
+            size[0] = -1;
+            g.f_mul(&scales.f_to_kind(g.kind())?.f_view(size.as_slice())?)
+        })
+        .collect()
+}
+
+/// Clips the per-sample gradients, see `f_clip_per_sample_grads`.
+pub fn clip_per_sample_grads(grads: &[Tensor], max_norm: f64) -> Vec<Tensor> {
+    f_clip_per_sample_grads(grads, max_norm).unwrap()
+}
+
+/// Combines per-sample gradients into the gradients of a DP-SGD step, see
+/// "Deep Learning with Differential Privacy", Abadi et al. 2016.
+///
+/// The gradients of each sample are clipped to `max_norm` as done by
+/// `clip_per_sample_grads`, then summed over the batch. Gaussian noise with
+/// a standard deviation of `noise_multiplier * max_norm` is added to the sum
+/// before dividing by the number of samples. The result has one gradient per
+/// parameter, with the shape of the parameter.
+pub fn f_dp_sgd_grads(
+    grads: &[Tensor],
+    max_norm: f64,
+    noise_multiplier: f64,
+) -> Result<Vec<Tensor>, TchError> {
+    let _guard = crate::no_grad_guard();
+    let clipped = f_clip_per_sample_grads(grads, max_norm)?;
+    clipped
+        .iter()
+        .map(|g| {
+            let batch = g.f_size()?[0].max(1);
+            let sum = g.f_sum_dim_intlist(0, false, None)?;
+            let noise = sum.f_randn_like()?.f_mul_scalar(noise_multiplier * max_norm)?;
+            sum.f_add(&noise)?.f_div_scalar(batch as f64)
+        })
+        .collect()
+}
+
+/// Combines per-sample gradients into DP-SGD gradients, see `f_dp_sgd_grads`.
+pub fn dp_sgd_grads(grads: &[Tensor], max_norm: f64, noise_multiplier: f64) -> Vec<Tensor> {
+    f_dp_sgd_grads(grads, max_norm, noise_multiplier).unwrap()
+}
@@ -0,0 +1,74 @@
+//! Flags controlling the behavior of the compute backends.
+//!
+//! These flags are global and are safe to set on CPU-only builds where they
+//! simply have no effect.
+
+/// CuDNN related flags.
+pub mod cudnn {
+    use crate::TchError;
+    use torch_sys::cuda::*;
+
+    /// Returns true if CUDA is available, and CuDNN is available.
+    pub fn is_available() -> bool {
+        crate::Cuda::cudnn_is_available()
+    }
+
+    /// Returns the CuDNN version, or `None` if CuDNN is not available.
+    pub fn version() -> Option<i64> {
+        if !is_available() {
+            return None;
+        }
+        let version = || -> Result<i64, TchError> { Ok(unsafe_torch_err!(atc_cudnn_version())) };
+        version().ok()
+    }
+
+    /// Sets the CuDNN benchmark mode.
+    ///
+    /// When enabled, CuDNN benchmarks multiple algorithms for each new input shape
+    /// and selects the fastest one. This can improve performance when the input
+    /// shapes do not change but the selected algorithms may vary between runs.
+    pub fn set_benchmark(b: bool) {
+        unsafe_torch!(atc_set_benchmark_cudnn(i32::from(b)))
+    }
+
+    /// Returns true if the CuDNN benchmark mode is enabled.
+    pub fn benchmark() -> bool {
+        unsafe_torch!(atc_benchmark_cudnn()) != 0
+    }
+
+    /// Restricts CuDNN to deterministic algorithms.
+    pub fn set_deterministic(b: bool) {
+        unsafe_torch!(atc_set_deterministic_cudnn(i32::from(b)))
+    }
+
+    /// Returns true if CuDNN is restricted to deterministic algorithms.
+    pub fn deterministic() -> bool {
+        unsafe_torch!(atc_deterministic_cudnn()) != 0
+    }
+}
+
+/// CUDA related flags.
+pub mod cuda {
+    use torch_sys::cuda::*;
+
+    /// Allows matrix multiplications to use TF32 tensor cores on Ampere and
+    /// later GPUs. This is faster but less precise than full f32 computations.
+    pub fn set_matmul_allow_tf32(b: bool) {
+        unsafe_torch!(atc_set_allow_tf32_cublas(i32::from(b)))
+    }
+
+    /// Returns true if matrix multiplications can use TF32.
+    pub fn matmul_allow_tf32() -> bool {
+        unsafe_torch!(atc_allow_tf32_cublas()) != 0
+    }
+
+    /// Allows CuDNN convolutions to use TF32 tensor cores on Ampere and later GPUs.
+    pub fn set_cudnn_allow_tf32(b: bool) {
+        unsafe_torch!(atc_set_allow_tf32_cudnn(i32::from(b)))
+    }
+
+    /// Returns true if CuDNN convolutions can use TF32.
+    pub fn cudnn_allow_tf32() -> bool {
+        unsafe_torch!(atc_allow_tf32_cudnn()) != 0
+    }
+}
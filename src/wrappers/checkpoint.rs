@@ -0,0 +1,135 @@
+//! Activation checkpointing, trading compute for memory.
+use super::tensor::Tensor;
+use crate::nn::Module;
+use crate::TchError;
+use libc::{c_int, c_void};
+use std::sync::{Arc, Mutex};
+use torch_sys::C_tensor;
+
+// The closure is called from the autograd engine threads during the backward
+// pass, the mutex makes it possible to only require it to be Send.
+type Closure = Mutex<Box<dyn FnMut(&[Tensor]) -> Tensor + Send>>;
+
+extern "C" fn call_closure(
+    data: *mut c_void,
+    c_inputs: *mut *mut C_tensor,
+    ninputs: c_int,
+) -> *mut C_tensor {
+    let closure = unsafe { &*(data as *const Closure) };
+    // The C++ side gives us the ownership of the input tensors.
+    let inputs: Vec<Tensor> =
+        (0..ninputs as usize).map(|i| unsafe { Tensor::from_ptr(*c_inputs.add(i)) }).collect();
+    // Unwinding through the C++ frames is not allowed, a null pointer results in
+    // an error being raised on the C++ side instead.
+    let output = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut f = closure.lock().unwrap_or_else(|e| e.into_inner());
+        f(&inputs)
+    }));
+    match output {
+        Ok(output) => {
+            let c_tensor = output.c_tensor;
+            std::mem::forget(output);
+            c_tensor
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+extern "C" fn free_closure(data: *mut c_void) {
+    drop(unsafe { Box::from_raw(data as *mut Closure) })
+}
+
+/// Runs `f` on multiple inputs without storing the intermediate activations, see
+/// `f_checkpoint`.
+pub fn f_checkpoint_multi<F>(f: F, inputs: &[&Tensor]) -> Result<Tensor, TchError>
+where
+    F: Fn(&[Tensor]) -> Tensor + Send + 'static,
+{
+    let closure: Box<Closure> = Box::new(Mutex::new(Box::new(f)));
+    let data = Box::into_raw(closure) as *mut c_void;
+    let c_inputs: Vec<*mut C_tensor> = inputs.iter().map(|x| x.c_tensor).collect();
+    let c_tensor = unsafe_torch_err!(torch_sys::at_checkpoint(
+        c_inputs.as_ptr(),
+        c_inputs.len() as c_int,
+        data,
+        call_closure,
+        free_closure,
+    ));
+    Ok(Tensor { c_tensor })
+}
+
+/// Runs `f` on multiple inputs without storing the intermediate activations, see
+/// `f_checkpoint`.
+pub fn checkpoint_multi<F>(f: F, inputs: &[&Tensor]) -> Tensor
+where
+    F: Fn(&[Tensor]) -> Tensor + Send + 'static,
+{
+    f_checkpoint_multi(f, inputs).unwrap()
+}
+
+/// Runs `f` on the input without storing the intermediate activations.
+///
+/// In the forward pass `f` is run without gradient tracking, only the input is
+/// kept. During the backward pass, `f` is run again with gradient tracking to
+/// compute the gradients of the input and of the variables used by `f`. The
+/// random number generator states are saved in the forward pass and restored
+/// when running `f` again so that random operations such as dropout produce the
+/// same results.
+///
+/// As `f` is called during the backward pass, possibly from another thread, it
+/// cannot borrow any data. Modules can be shared with the closure using an
+/// `Arc<Mutex<_>>`, see `checkpoint_sequential`. When gradient tracking is
+/// disabled, `f` is simply applied to the input.
+pub fn f_checkpoint<F>(f: F, input: &Tensor) -> Result<Tensor, TchError>
+where
+    F: Fn(&Tensor) -> Tensor + Send + 'static,
+{
+    f_checkpoint_multi(move |xs| f(&xs[0]), &[input])
+}
+
+/// Runs `f` on the input without storing the intermediate activations, see
+/// `f_checkpoint`.
+pub fn checkpoint<F>(f: F, input: &Tensor) -> Tensor
+where
+    F: Fn(&Tensor) -> Tensor + Send + 'static,
+{
+    f_checkpoint(f, input).unwrap()
+}
+
+/// Applies the blocks in sequence, splitting them in `segments` segments that
+/// are checkpointed, see `f_checkpoint`.
+///
+/// Only the inputs of each segment are stored in the forward pass. As the
+/// last segment would be recomputed immediately, it is not checkpointed.
+pub fn f_checkpoint_sequential<M>(
+    blocks: &[Arc<Mutex<M>>],
+    segments: usize,
+    input: &Tensor,
+) -> Result<Tensor, TchError>
+where
+    M: Module + ?Sized + 'static,
+{
+    if segments == 0 {
+        return Err(TchError::Torch("checkpoint_sequential requires at least one segment".into()));
+    }
+    let segment_size = blocks.len().div_ceil(segments);
+    let mut xs = input.shallow_clone();
+    let mut segments = blocks.chunks(segment_size.max(1)).peekable();
+    while let Some(segment) = segments.next() {
+        let segment = segment.to_vec();
+        let forward = move |xs: &Tensor| {
+            segment.iter().fold(xs.shallow_clone(), |xs, block| block.lock().unwrap().forward(&xs))
+        };
+        xs = if segments.peek().is_some() { f_checkpoint(forward, &xs)? } else { forward(&xs) }
+    }
+    Ok(xs)
+}
+
+/// Applies the blocks in sequence with checkpointing, see
+/// `f_checkpoint_sequential`.
+pub fn checkpoint_sequential<M>(blocks: &[Arc<Mutex<M>>], segments: usize, input: &Tensor) -> Tensor
+where
+    M: Module + ?Sized + 'static,
+{
+    f_checkpoint_sequential(blocks, segments, input).unwrap()
+}
@@ -0,0 +1,849 @@
+//! CUDA streams, events, graphs, and memory management.
+//!
+//! These functions return an error when libtorch has not been compiled with
+//! CUDA support.
+use super::utils::ptr_to_string;
+use crate::{Device, TchError, Tensor};
+use std::sync::Mutex;
+use torch_sys::cuda::*;
+
+fn cuda_index(device: Device) -> Result<libc::c_int, TchError> {
+    match device {
+        Device::Cuda(index) => {
+            // Out of range indexes would trigger some libtorch assertions.
+            let device_count = crate::Cuda::device_count();
+            if index as i64 >= device_count {
+                return Err(TchError::Torch(format!(
+                    "invalid CUDA device index {index}, {device_count} devices available"
+                )));
+            }
+            Ok(index as libc::c_int)
+        }
+        Device::Cpu | Device::Mps | Device::Vulkan => {
+            Err(TchError::Torch(format!("expected a CUDA device, got {device:?}")))
+        }
+    }
+}
+
+/// A CUDA stream.
+///
+/// Operations on CUDA tensors are queued on the current stream of the
+/// device they run on. Use `set_current` to run operations on a specific
+/// stream.
+pub struct CudaStream {
+    c_stream: *mut C_cuda_stream,
+}
+
+unsafe impl Send for CudaStream {}
+
+impl CudaStream {
+    fn of_c(c_stream: *mut C_cuda_stream) -> Self {
+        CudaStream { c_stream }
+    }
+
+    /// Returns a new stream from the pool of streams for the given device.
+    pub fn new(device: Device) -> Result<CudaStream, TchError> {
+        let device = cuda_index(device)?;
+        let c_stream = unsafe_torch_err!(atcs_new(device, 0));
+        Ok(Self::of_c(c_stream))
+    }
+
+    /// Returns a new high priority stream for the given device.
+    pub fn new_high_priority(device: Device) -> Result<CudaStream, TchError> {
+        let device = cuda_index(device)?;
+        let c_stream = unsafe_torch_err!(atcs_new(device, 1));
+        Ok(Self::of_c(c_stream))
+    }
+
+    /// Returns the default stream for the given device.
+    pub fn default(device: Device) -> Result<CudaStream, TchError> {
+        let device = cuda_index(device)?;
+        let c_stream = unsafe_torch_err!(atcs_default(device));
+        Ok(Self::of_c(c_stream))
+    }
+
+    /// Returns the current stream for the given device.
+    pub fn current(device: Device) -> Result<CudaStream, TchError> {
+        let device = cuda_index(device)?;
+        let c_stream = unsafe_torch_err!(atcs_current(device));
+        Ok(Self::of_c(c_stream))
+    }
+
+    /// Returns the current stream for the current CUDA device.
+    pub fn current_for_current_device() -> Result<CudaStream, TchError> {
+        let c_stream = unsafe_torch_err!(atcs_current(-1));
+        Ok(Self::of_c(c_stream))
+    }
+
+    /// Returns the device this stream is associated with.
+    pub fn device(&self) -> Device {
+        let index = unsafe_torch!(atcs_device(self.c_stream));
+        Device::Cuda(index as usize)
+    }
+
+    /// Makes this stream the current stream for its device.
+    ///
+    /// The previous current stream is restored when the returned guard is dropped.
+    /// Note that it is important to bind the guard to a name like `_guard` and not
+    /// to `_` as the latter would immediately drop it.
+    pub fn set_current(&self) -> Result<StreamGuard, TchError> {
+        let prev = CudaStream::current(self.device())?;
+        unsafe_torch_err!(atcs_set_current(self.c_stream));
+        Ok(StreamGuard { prev })
+    }
+
+    /// Waits for all the work queued on this stream to complete.
+    pub fn synchronize(&self) -> Result<(), TchError> {
+        unsafe_torch_err!(atcs_synchronize(self.c_stream));
+        Ok(())
+    }
+
+    /// Returns true if all the work queued on this stream has completed.
+    pub fn query(&self) -> Result<bool, TchError> {
+        let completed = unsafe_torch_err!(atcs_query(self.c_stream));
+        Ok(completed != 0)
+    }
+
+    /// Makes all the work queued on this stream from now on wait for the event.
+    ///
+    /// This does not block the calling thread.
+    pub fn wait_event(&self, event: &CudaEvent) -> Result<(), TchError> {
+        unsafe_torch_err!(atcs_wait_event(self.c_stream, event.c_event));
+        Ok(())
+    }
+}
+
+impl PartialEq for CudaStream {
+    fn eq(&self, other: &Self) -> bool {
+        let id = unsafe_torch!(atcs_id(self.c_stream));
+        let other_id = unsafe_torch!(atcs_id(other.c_stream));
+        id == other_id && self.device() == other.device()
+    }
+}
+
+impl std::fmt::Debug for CudaStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = unsafe_torch!(atcs_id(self.c_stream));
+        write!(f, "CudaStream {{ device: {:?}, id: {id} }}", self.device())
+    }
+}
+
+impl Drop for CudaStream {
+    fn drop(&mut self) {
+        unsafe_torch_free!(atcs_free(self.c_stream))
+    }
+}
+
+/// A RAII guard that restores the previous current stream when dropped.
+pub struct StreamGuard {
+    prev: CudaStream,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        unsafe_torch!(atcs_set_current(self.prev.c_stream))
+    }
+}
+
+/// A CUDA event, used to synchronize streams.
+pub struct CudaEvent {
+    c_event: *mut C_cuda_event,
+}
+
+unsafe impl Send for CudaEvent {}
+
+impl CudaEvent {
+    /// Creates a new event, timing data is only recorded if `enable_timing` is true.
+    pub fn new(enable_timing: bool) -> Result<CudaEvent, TchError> {
+        let c_event = unsafe_torch_err!(atce_new(i32::from(enable_timing)));
+        Ok(CudaEvent { c_event })
+    }
+
+    /// Records the event on a stream, the event completes once all the work queued
+    /// on the stream at this point has completed.
+    pub fn record(&mut self, stream: &CudaStream) -> Result<(), TchError> {
+        unsafe_torch_err!(atce_record(self.c_event, stream.c_stream));
+        Ok(())
+    }
+
+    /// Blocks the calling thread until the event has completed.
+    pub fn synchronize(&self) -> Result<(), TchError> {
+        unsafe_torch_err!(atce_synchronize(self.c_event));
+        Ok(())
+    }
+
+    /// Returns true if the event has completed, this does not block.
+    pub fn query(&self) -> Result<bool, TchError> {
+        let completed = unsafe_torch_err!(atce_query(self.c_event));
+        Ok(completed != 0)
+    }
+
+    /// Returns the time elapsed in milliseconds between this event and `end`.
+    ///
+    /// Both events must have been created with timing enabled, recorded, and
+    /// have completed.
+    pub fn elapsed_time(&self, end: &CudaEvent) -> Result<f32, TchError> {
+        let elapsed = unsafe_torch_err!(atce_elapsed_time(self.c_event, end.c_event));
+        Ok(elapsed)
+    }
+
+    /// Makes all the work queued on `stream` from now on wait for this event.
+    pub fn wait(&self, stream: &CudaStream) -> Result<(), TchError> {
+        stream.wait_event(self)
+    }
+}
+
+/// Returns the time in milliseconds taken on the GPU by the work queued by `f`.
+///
+/// Events are recorded on the current stream of the current device before and
+/// after running `f`, and the calling thread then waits for the end event. As
+/// kernel launches are asynchronous, this is more accurate than measuring the
+/// wall-clock time taken by `f`.
+pub fn time_it<F: FnOnce()>(f: F) -> Result<f32, TchError> {
+    let stream = CudaStream::current_for_current_device()?;
+    let mut start = CudaEvent::new(true)?;
+    let mut end = CudaEvent::new(true)?;
+    start.record(&stream)?;
+    f();
+    end.record(&stream)?;
+    end.synchronize()?;
+    start.elapsed_time(&end)
+}
+
+impl Drop for CudaEvent {
+    fn drop(&mut self) {
+        unsafe_torch_free!(atce_free(self.c_event))
+    }
+}
+
+/// Identifies a memory pool that can be shared between CUDA graphs.
+///
+/// Graphs that share a pool can reuse each other's memory, this is only safe
+/// if the graphs are replayed in the same order as they have been captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GraphPoolHandle(u64, u64);
+
+/// Returns a handle to a new memory pool that can be shared between graphs.
+pub fn graph_pool_handle() -> Result<GraphPoolHandle, TchError> {
+    let (mut id0, mut id1) = (0, 0);
+    unsafe_torch_err!(atcg_graph_pool_handle(&mut id0, &mut id1));
+    Ok(GraphPoolHandle(id0, id1))
+}
+
+/// A CUDA graph, used to replay a fixed sequence of kernels with a single launch.
+///
+/// Launching many small kernels can be dominated by the CPU launch overhead,
+/// capturing them in a graph removes most of this overhead. The captured
+/// kernels always read from and write to the same memory so the inputs have
+/// to be copied to the static input tensors before each replay, and the
+/// outputs are written to the static output tensor, see `replay_with`. Shapes
+/// are fixed at capture time, as are the operations to run, so data dependent
+/// control flow and CPU synchronizations are not supported.
+///
+/// ```no_run
+/// # use tch::{cuda::CudaGraph, nn, nn::Module, Device, Kind, Tensor};
+/// # fn main() -> Result<(), tch::TchError> {
+/// let device = Device::Cuda(0);
+/// let vs = nn::VarStore::new(device);
+/// let mlp = nn::seq().add(nn::linear(vs.root(), 16, 16, Default::default())).add_fn(|xs| xs.relu());
+/// let xs = Tensor::randn([8, 16], (Kind::Float, device));
+/// let mut graph = CudaGraph::new(device)?;
+/// tch::no_grad(|| graph.capture(&[&xs], |inputs| mlp.forward(&inputs[0])))?;
+/// let new_xs = Tensor::randn([8, 16], (Kind::Float, device));
+/// let ys = graph.replay_with(&[&new_xs])?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CudaGraph {
+    c_graph: *mut C_cuda_graph,
+    device: Device,
+    pool: Option<GraphPoolHandle>,
+    static_inputs: Vec<Tensor>,
+    static_output: Option<Tensor>,
+}
+
+unsafe impl Send for CudaGraph {}
+
+impl std::fmt::Debug for CudaGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CudaGraph")
+            .field("device", &self.device)
+            .field("pool", &self.pool)
+            .field("captured", &self.static_output.is_some())
+            .finish()
+    }
+}
+
+impl CudaGraph {
+    /// The number of times the closure is run before the capture, this lets
+    /// libtorch run its lazy initializations, e.g. cuBLAS workspaces or cuDNN
+    /// benchmarking, outside of the graph.
+    pub const WARMUP_ITERS: usize = 3;
+
+    /// Creates a new graph using a private memory pool.
+    pub fn new(device: Device) -> Result<CudaGraph, TchError> {
+        cuda_index(device)?;
+        let c_graph = unsafe_torch_err!(atcg_new());
+        Ok(CudaGraph { c_graph, device, pool: None, static_inputs: vec![], static_output: None })
+    }
+
+    /// Creates a new graph using a memory pool that can be shared with other
+    /// graphs, see `graph_pool_handle` and `CudaGraph::pool`.
+    pub fn new_with_pool(device: Device, pool: GraphPoolHandle) -> Result<CudaGraph, TchError> {
+        let mut graph = CudaGraph::new(device)?;
+        graph.pool = Some(pool);
+        Ok(graph)
+    }
+
+    /// Captures the kernels launched by `f`.
+    ///
+    /// The inputs are copied to static input tensors which are passed to `f`.
+    /// `f` is first run a few times on a side stream to warm up, it is then run
+    /// once more on this side stream while capturing. An error is returned if
+    /// the output shape is not the same for all these runs. The output of the
+    /// captured run is returned, it is the static output tensor that gets
+    /// overwritten on each replay.
+    ///
+    /// Gradient tracking should usually be disabled while capturing, e.g. using
+    /// `no_grad`.
+    pub fn capture<F>(&mut self, inputs: &[&Tensor], mut f: F) -> Result<Tensor, TchError>
+    where
+        F: FnMut(&[Tensor]) -> Tensor,
+    {
+        if self.static_output.is_some() {
+            return Err(TchError::Torch("this CUDA graph has already been captured".into()));
+        }
+        let static_inputs = inputs
+            .iter()
+            .map(|xs| {
+                if xs.device() != self.device {
+                    return Err(TchError::Torch(format!(
+                        "CUDA graph input on {:?}, expected {:?}",
+                        xs.device(),
+                        self.device
+                    )));
+                }
+                let mut static_input = xs.f_zeros_like()?;
+                crate::no_grad(|| static_input.f_copy_(xs))?;
+                Ok(static_input)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // The capture cannot happen on the default stream, the side stream
+        // waits for the inputs to be ready and the current stream then waits
+        // for the warm-up runs.
+        let current = CudaStream::current(self.device)?;
+        let side = CudaStream::new(self.device)?;
+        let mut event = CudaEvent::new(false)?;
+        event.record(&current)?;
+        side.wait_event(&event)?;
+        let output = {
+            let _guard = side.set_current()?;
+            let mut shape = None;
+            for _ in 0..Self::WARMUP_ITERS {
+                let ys = f(&static_inputs);
+                check_static_shape(&mut shape, &ys)?;
+            }
+            side.synchronize()?;
+            let (id0, id1) = self.pool.map_or((0, 0), |GraphPoolHandle(id0, id1)| (id0, id1));
+            unsafe_torch_err!(atcg_capture_begin(self.c_graph, id0, id1));
+            let ys = f(&static_inputs);
+            unsafe_torch_err!(atcg_capture_end(self.c_graph));
+            check_static_shape(&mut shape, &ys)?;
+            ys
+        };
+        event.record(&side)?;
+        current.wait_event(&event)?;
+        self.static_inputs = static_inputs;
+        self.static_output = Some(output.shallow_clone());
+        Ok(output)
+    }
+
+    /// Launches the captured kernels on the current stream, the static output
+    /// tensor is updated once these have completed.
+    pub fn replay(&self) -> Result<(), TchError> {
+        if self.static_output.is_none() {
+            return Err(TchError::Torch("this CUDA graph has not been captured".into()));
+        }
+        unsafe_torch_err!(atcg_replay(self.c_graph));
+        Ok(())
+    }
+
+    /// Copies the inputs to the static input tensors, replays the graph, and
+    /// returns the static output tensor.
+    ///
+    /// The inputs must have the same shapes and kinds as the inputs used at
+    /// capture time. The returned tensor is overwritten by the next replay so it
+    /// should be copied if it has to be kept around.
+    pub fn replay_with(&mut self, inputs: &[&Tensor]) -> Result<Tensor, TchError> {
+        if inputs.len() != self.static_inputs.len() {
+            return Err(TchError::Torch(format!(
+                "CUDA graph captured with {} inputs, got {}",
+                self.static_inputs.len(),
+                inputs.len()
+            )));
+        }
+        for (static_input, xs) in self.static_inputs.iter_mut().zip(inputs.iter()) {
+            if static_input.size() != xs.size() || static_input.f_kind()? != xs.f_kind()? {
+                return Err(TchError::Shape(format!(
+                    "CUDA graphs do not support dynamic shapes, expected an input {:?} {:?}, got {:?} {:?}",
+                    static_input.size(),
+                    static_input.kind(),
+                    xs.size(),
+                    xs.kind(),
+                )));
+            }
+            crate::no_grad(|| static_input.f_copy_(xs))?;
+        }
+        self.replay()?;
+        Ok(self.static_output.as_ref().unwrap().shallow_clone())
+    }
+
+    /// The static input tensors, these can be modified in place before calling
+    /// `replay`.
+    pub fn static_inputs(&self) -> &[Tensor] {
+        &self.static_inputs
+    }
+
+    /// The static output tensor, `None` if the graph has not been captured.
+    pub fn static_output(&self) -> Option<&Tensor> {
+        self.static_output.as_ref()
+    }
+
+    /// Returns the memory pool used by the graph, this can be used to create
+    /// other graphs sharing the same pool.
+    pub fn pool(&self) -> Result<GraphPoolHandle, TchError> {
+        let (mut id0, mut id1) = (0, 0);
+        unsafe_torch_err!(atcg_pool(self.c_graph, &mut id0, &mut id1));
+        Ok(GraphPoolHandle(id0, id1))
+    }
+
+    /// Releases the captured graph, the graph can then be captured again.
+    pub fn reset(&mut self) -> Result<(), TchError> {
+        self.static_inputs.clear();
+        self.static_output = None;
+        unsafe_torch_err!(atcg_reset(self.c_graph));
+        Ok(())
+    }
+}
+
+// Records the shape of the first output and checks that subsequent outputs
+// have the same shape.
+fn check_static_shape(shape: &mut Option<Vec<i64>>, ys: &Tensor) -> Result<(), TchError> {
+    let size = ys.size();
+    match shape {
+        None => *shape = Some(size),
+        Some(shape) if *shape != size => {
+            return Err(TchError::Shape(format!(
+                "CUDA graphs do not support dynamic shapes, got outputs with shapes {shape:?} and {size:?}"
+            )))
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+impl Drop for CudaGraph {
+    fn drop(&mut self) {
+        // The graph has to be released before the static tensors.
+        unsafe_torch_free!(atcg_free(self.c_graph))
+    }
+}
+
+impl Tensor {
+    /// Marks the memory of this tensor as being used by `stream`.
+    ///
+    /// The caching allocator associates each block of memory with the stream
+    /// that was current when it was allocated. When a tensor is dropped, its
+    /// memory can immediately be reused by new allocations on that stream, which
+    /// is only correct if no other stream still has work queued that uses it.
+    /// This should be called when a tensor allocated on one stream is used by
+    /// operations on another stream and may be dropped before that work
+    /// completes, the memory is then only reused once the work queued on
+    /// `stream` at the time of the drop has completed. This does not block.
+    pub fn record_stream(&self, stream: &CudaStream) -> Result<(), TchError> {
+        unsafe_torch_err!(atcs_record_stream(self.c_tensor, stream.c_stream));
+        Ok(())
+    }
+
+    /// Defers the deallocation of this tensor until the next call to
+    /// `flush_deferred_frees` on the current thread, see `DeferredFree`.
+    pub fn defer_drop(self) {
+        DEFERRED_FREES.with(|bin| bin.borrow_mut().push(self))
+    }
+}
+
+thread_local! {
+    static DEFERRED_FREES: std::cell::RefCell<Vec<Tensor>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Deferred deallocation of tensors, to keep frees out of latency-critical code.
+///
+/// Dropping a tensor is usually cheap as the caching allocator only returns
+/// its memory to the cache. It can however take a while in some cases, e.g.
+/// when the caching allocator is disabled with `PYTORCH_NO_CUDA_MEMORY_CACHING`
+/// and `cudaFree` synchronizes the device, when the tensor was created from
+/// external memory with a custom deleter, or when dropping the last reference
+/// to a large autograd graph. `Tensor::defer_drop` moves the tensor to a
+/// thread-local bin that is only emptied by `flush_deferred_frees`, which should
+/// be called at a point where latency does not matter, e.g. between two
+/// requests. The tensors in the bin keep their memory until then.
+///
+/// Deferring the drop does not make it safe to reuse memory that other streams
+/// are still using, `Tensor::record_stream` is the tool for this.
+#[derive(Debug)]
+pub enum DeferredFree {}
+
+impl DeferredFree {
+    /// The number of tensors waiting to be deallocated on the current thread.
+    pub fn len() -> usize {
+        DEFERRED_FREES.with(|bin| bin.borrow().len())
+    }
+
+    /// Deallocates the tensors deferred on the current thread, returns the
+    /// number of tensors deallocated.
+    pub fn flush() -> usize {
+        // The tensors are dropped outside of the borrow in case one of their
+        // deleters defers some other tensor.
+        let tensors = DEFERRED_FREES.with(|bin| std::mem::take(&mut *bin.borrow_mut()));
+        tensors.len()
+    }
+}
+
+/// Deallocates the tensors deferred on the current thread, see `DeferredFree`.
+pub fn flush_deferred_frees() -> usize {
+    DeferredFree::flush()
+}
+
+/// Statistics from the CUDA caching allocator for a device.
+///
+/// Memory sizes are in bytes, `*_peak` fields hold the maximum value since the
+/// device was initialized or since the last call to `reset_peak_memory_stats`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Memory currently used by tensors.
+    pub allocated_bytes: i64,
+    pub allocated_bytes_peak: i64,
+    /// Memory currently held by the caching allocator.
+    pub reserved_bytes: i64,
+    pub reserved_bytes_peak: i64,
+    /// Memory in active blocks, this includes blocks still used by some streams.
+    pub active_bytes: i64,
+    pub active_bytes_peak: i64,
+    /// Number of live allocations.
+    pub allocations: i64,
+    pub allocations_peak: i64,
+    /// Total number of allocations and frees performed.
+    pub allocations_total: i64,
+    pub frees_total: i64,
+    /// Number of times the cache had to be flushed to satisfy an allocation.
+    pub num_alloc_retries: i64,
+    /// Number of out of memory errors.
+    pub num_ooms: i64,
+}
+
+/// Returns the caching allocator statistics for the given device.
+pub fn memory_stats(device: Device) -> Result<MemoryStats, TchError> {
+    let device = cuda_index(device)?;
+    let mut s = [0i64; 12];
+    unsafe_torch_err!(atc_memory_stats(device, s.as_mut_ptr()));
+    Ok(MemoryStats {
+        allocated_bytes: s[0],
+        allocated_bytes_peak: s[1],
+        reserved_bytes: s[2],
+        reserved_bytes_peak: s[3],
+        active_bytes: s[4],
+        active_bytes_peak: s[5],
+        allocations: s[6],
+        allocations_peak: s[7],
+        allocations_total: s[8],
+        frees_total: s[9],
+        num_alloc_retries: s[10],
+        num_ooms: s[11],
+    })
+}
+
+/// Returns the memory currently used by tensors on the device in bytes.
+pub fn memory_allocated(device: Device) -> Result<i64, TchError> {
+    Ok(memory_stats(device)?.allocated_bytes)
+}
+
+/// Returns the maximum memory used by tensors on the device in bytes.
+pub fn max_memory_allocated(device: Device) -> Result<i64, TchError> {
+    Ok(memory_stats(device)?.allocated_bytes_peak)
+}
+
+/// Returns the memory held by the caching allocator on the device in bytes.
+pub fn memory_reserved(device: Device) -> Result<i64, TchError> {
+    Ok(memory_stats(device)?.reserved_bytes)
+}
+
+/// Resets the peak values tracked by the caching allocator for the device.
+pub fn reset_peak_memory_stats(device: Device) -> Result<(), TchError> {
+    let device = cuda_index(device)?;
+    unsafe_torch_err!(atc_reset_peak_memory_stats(device));
+    Ok(())
+}
+
+/// Releases the cached memory that is not used by any tensor so that it can be
+/// used by other applications.
+pub fn empty_cache() -> Result<(), TchError> {
+    unsafe_torch_err!(atc_empty_cache());
+    Ok(())
+}
+
+/// Limits the memory that the caching allocator can use on the device to a
+/// fraction of the total device memory.
+///
+/// Allocations that would go above this limit fail with an out of memory error
+/// which is returned as a `TchError` by the fallible functions.
+pub fn set_per_process_memory_fraction(fraction: f64, device: Device) -> Result<(), TchError> {
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(TchError::Torch(format!("memory fraction {fraction} is not between 0 and 1")));
+    }
+    let device = cuda_index(device)?;
+    unsafe_torch_err!(atc_set_memory_fraction(fraction, device));
+    Ok(())
+}
+
+// The last settings passed to the allocator, so that `set_expandable_segments`
+// can keep the other settings.
+static ALLOCATOR_SETTINGS: Mutex<Option<String>> = Mutex::new(None);
+
+fn apply_allocator_settings(current: &mut Option<String>, settings: &str) -> Result<(), TchError> {
+    let c_settings = std::ffi::CString::new(settings)?;
+    unsafe_torch_err!(atc_set_allocator_settings(c_settings.as_ptr()));
+    *current = Some(settings.to_string());
+    Ok(())
+}
+
+/// Configures the CUDA caching allocator.
+///
+/// The settings use the same format as the `PYTORCH_CUDA_ALLOC_CONF` environment
+/// variable, e.g. `"max_split_size_mb:128,garbage_collection_threshold:0.8"`,
+/// and replace the current settings. Unknown options and invalid values are
+/// reported by libtorch.
+///
+/// From libtorch 2.1, the settings can be changed at any time but only apply to
+/// the memory allocated afterwards. Older versions only read the settings from
+/// the environment when the allocator gets initialized, so an error is returned
+/// once some CUDA memory has been allocated.
+pub fn set_allocator_settings(settings: &str) -> Result<(), TchError> {
+    let mut current = ALLOCATOR_SETTINGS.lock().unwrap_or_else(|e| e.into_inner());
+    apply_allocator_settings(&mut current, settings)
+}
+
+// Returns the major and minor version of libtorch.
+fn torch_version() -> Result<(u32, u32), TchError> {
+    let version = crate::utils::version_torch()?;
+    let mut parts = version.split('.').map(|p| p.parse::<u32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Ok((major, minor)),
+        _ => Err(TchError::Torch(format!("unexpected libtorch version {version}"))),
+    }
+}
+
+/// Enables or disables the expandable segments of the CUDA caching allocator,
+/// keeping the other allocator settings.
+///
+/// With expandable segments, the allocator maps more memory into existing
+/// segments rather than allocating new ones, which reduces fragmentation when
+/// the allocation sizes change, e.g. with variable batch sizes. This requires
+/// libtorch 2.1 or later, an error is returned for older versions.
+pub fn set_expandable_segments(enabled: bool) -> Result<(), TchError> {
+    let (major, minor) = torch_version()?;
+    if (major, minor) < (2, 1) {
+        return Err(TchError::Torch(format!(
+            "expandable segments require libtorch 2.1 or later, got {major}.{minor}"
+        )));
+    }
+    let mut current = ALLOCATOR_SETTINGS.lock().unwrap_or_else(|e| e.into_inner());
+    // Before any call, the settings are the ones from the environment.
+    let previous = match current.as_ref() {
+        Some(settings) => settings.clone(),
+        None => std::env::var("PYTORCH_CUDA_ALLOC_CONF").unwrap_or_default(),
+    };
+    let mut settings: Vec<_> = previous
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && !s.starts_with("expandable_segments"))
+        .map(str::to_string)
+        .collect();
+    settings.push(format!("expandable_segments:{}", if enabled { "True" } else { "False" }));
+    apply_allocator_settings(&mut current, &settings.join(","))
+}
+
+/// The properties of a CUDA device.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceProperties {
+    pub name: String,
+    /// Total memory of the device in bytes.
+    pub total_memory: u64,
+    /// Major compute capability.
+    pub major: i32,
+    /// Minor compute capability.
+    pub minor: i32,
+    pub multi_processor_count: i32,
+}
+
+/// Returns the properties of the given device.
+pub fn device_properties(device: Device) -> Result<DeviceProperties, TchError> {
+    let device = cuda_index(device)?;
+    let mut name = std::ptr::null_mut();
+    let mut total_memory = 0i64;
+    let mut major = 0;
+    let mut minor = 0;
+    let mut multi_processor_count = 0;
+    unsafe_torch_err!(atc_device_properties(
+        device,
+        &mut name,
+        &mut total_memory,
+        &mut major,
+        &mut minor,
+        &mut multi_processor_count
+    ));
+    let name = unsafe { ptr_to_string(name) }.unwrap_or_default();
+    Ok(DeviceProperties {
+        name,
+        total_memory: total_memory as u64,
+        major,
+        minor,
+        multi_processor_count,
+    })
+}
+
+/// Returns the number of CUDA devices available.
+pub fn device_count() -> i64 {
+    crate::Cuda::device_count()
+}
+
+/// Returns the index of the current CUDA device.
+pub fn current_device() -> Result<usize, TchError> {
+    let index = unsafe_torch_err!(atc_current_device());
+    Ok(index as usize)
+}
+
+/// Sets the current CUDA device, this is the device used for CUDA operations
+/// that do not specify a device index.
+pub fn set_device(index: usize) -> Result<(), TchError> {
+    unsafe_torch_err!(atc_set_device(index as libc::c_int));
+    Ok(())
+}
+
+/// Returns the major and minor compute capability of the device.
+pub fn get_device_capability(index: usize) -> Result<(i32, i32), TchError> {
+    let properties = device_properties(Device::Cuda(index))?;
+    Ok((properties.major, properties.minor))
+}
+
+/// An out of memory error caught by `OomRetry`.
+#[derive(Debug, Clone)]
+pub struct OomEvent {
+    /// The number of retries already done, 0 for the first failure.
+    pub attempt: usize,
+    /// The size of the failed allocation and the free and total memory of the
+    /// device in bytes, as reported by the error.
+    pub requested: u64,
+    pub free: u64,
+    pub total: u64,
+    /// The allocator statistics of the current device when the error was
+    /// caught, before the cache is emptied.
+    pub stats: Option<MemoryStats>,
+}
+
+/// A hook called by `OomRetry` for each out of memory error, e.g. to log the
+/// fragmentation state with the difference between reserved and allocated
+/// memory.
+pub trait OomObserver {
+    fn on_oom(&mut self, event: &OomEvent);
+}
+
+impl<F: FnMut(&OomEvent)> OomObserver for F {
+    fn on_oom(&mut self, event: &OomEvent) {
+        self(event)
+    }
+}
+
+/// Retries a closure failing with a CUDA out of memory error after releasing
+/// the cached memory.
+///
+/// Before each retry the deferred frees of the current thread are flushed and
+/// the unused memory of the caching allocator is released, so that an
+/// allocation that failed because of fragmentation can succeed. Other errors
+/// are returned immediately, and the last out of memory error is returned once
+/// the retries are exhausted.
+pub struct OomRetry {
+    max_retries: usize,
+    synchronize: bool,
+    observer: Option<Box<dyn OomObserver>>,
+}
+
+impl std::fmt::Debug for OomRetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("OomRetry")
+            .field("max_retries", &self.max_retries)
+            .field("synchronize", &self.synchronize)
+            .finish()
+    }
+}
+
+impl OomRetry {
+    pub fn new(max_retries: usize) -> OomRetry {
+        OomRetry { max_retries, synchronize: false, observer: None }
+    }
+
+    /// Synchronizes the current device before emptying the cache, so that
+    /// the memory of the tensors freed by kernels still running can be
+    /// released.
+    pub fn synchronize(mut self, synchronize: bool) -> Self {
+        self.synchronize = synchronize;
+        self
+    }
+
+    /// Sets the hook called each time an out of memory error is caught.
+    pub fn observer<O: OomObserver + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    pub fn run<T, F>(&mut self, mut f: F) -> Result<T, TchError>
+    where
+        F: FnMut() -> Result<T, TchError>,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match f() {
+                Err(err @ TchError::CudaOutOfMemory { .. }) => err,
+                res => return res,
+            };
+            let device = current_device().ok();
+            if let (Some(observer), TchError::CudaOutOfMemory { requested, free, total, .. }) =
+                (self.observer.as_mut(), &err)
+            {
+                let stats = device.and_then(|index| memory_stats(Device::Cuda(index)).ok());
+                let (requested, free, total) = (*requested, *free, *total);
+                observer.on_oom(&OomEvent { attempt, requested, free, total, stats })
+            }
+            if attempt >= self.max_retries {
+                return Err(err);
+            }
+            if let (true, Some(index)) = (self.synchronize, device) {
+                crate::Cuda::synchronize(index as i64)
+            }
+            flush_deferred_frees();
+            empty_cache()?;
+            attempt += 1
+        }
+    }
+}
+
+/// Runs `f` and retries it up to `max_retries` times when it fails with a
+/// CUDA out of memory error, see `OomRetry`.
+pub fn with_oom_retry<T, F>(max_retries: usize, f: F) -> Result<T, TchError>
+where
+    F: FnMut() -> Result<T, TchError>,
+{
+    OomRetry::new(max_retries).run(f)
+}
@@ -10,6 +10,11 @@ pub enum Device {
     /// The main MPS device.
     Mps,
     /// The main Vulkan device.
+    ///
+    /// The Vulkan backend is only available when libtorch has been compiled with
+    /// it, use `utils::has_vulkan` to check for this at runtime. Only a subset of
+    /// the operations are implemented for this backend, running an unsupported
+    /// operation returns an error mentioning the operation name.
     Vulkan,
 }
 
@@ -82,6 +87,20 @@ impl Cuda {
     }
 }
 
+/// MPS related helper functions, MPS is the GPU backend used on Apple Silicon.
+pub enum Mps {}
+impl Mps {
+    /// Returns true if the MPS backend is available.
+    pub fn is_available() -> bool {
+        crate::utils::has_mps()
+    }
+
+    /// Waits for all the kernels submitted to the MPS device to complete.
+    pub fn synchronize() {
+        unsafe_torch!(torch_sys::at_mps_synchronize())
+    }
+}
+
 impl Device {
     pub(super) fn c_int(self) -> libc::c_int {
         match self {
@@ -111,6 +130,18 @@ impl Device {
         }
     }
 
+    /// Returns an accelerator device if available, this is a CUDA device if
+    /// available, otherwise the MPS device if available, and defaults to CPU.
+    pub fn accelerator_if_available() -> Device {
+        if Cuda::is_available() {
+            Device::Cuda(0)
+        } else if Mps::is_available() {
+            Device::Mps
+        } else {
+            Device::Cpu
+        }
+    }
+
     pub fn is_cuda(self) -> bool {
         match self {
             Device::Cuda(_) => true,
@@ -0,0 +1,405 @@
+//! Distributed training using the c10d process groups.
+//!
+//! This requires the `distributed` feature. A default process group has to be
+//! created with [`init_process_group`] or [`init_process_group_from_env`], the
+//! collective functions of this module then operate on this group. The
+//! environment based initialization uses the same variables as `torchrun`,
+//! i.e. `MASTER_ADDR`, `MASTER_PORT`, `RANK` and `WORLD_SIZE`, so that
+//! binaries using this module can be launched with it.
+use crate::nn::{ModuleT, VarStore};
+use crate::{TchError, Tensor};
+use libc::c_int;
+use std::sync::Mutex;
+use torch_sys::distributed::*;
+
+/// The default timeout for collective operations, in milliseconds.
+pub const DEFAULT_TIMEOUT_MS: i64 = 30 * 60 * 1000;
+
+/// The communication backend used by a process group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// NCCL, for CUDA tensors. This requires a CUDA enabled libtorch.
+    Nccl,
+    /// Gloo, mostly used for CPU tensors.
+    Gloo,
+}
+
+impl Backend {
+    fn to_c_int(self) -> c_int {
+        match self {
+            Backend::Gloo => 0,
+            Backend::Nccl => 1,
+        }
+    }
+}
+
+/// The reduction applied by [`all_reduce`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Product,
+    Min,
+    Max,
+    /// Average, only supported by the NCCL backend.
+    Avg,
+}
+
+impl ReduceOp {
+    fn to_c_int(self) -> c_int {
+        match self {
+            ReduceOp::Sum => 0,
+            ReduceOp::Product => 1,
+            ReduceOp::Min => 2,
+            ReduceOp::Max => 3,
+            ReduceOp::Avg => 4,
+        }
+    }
+}
+
+/// A group of processes that can run collective operations together.
+pub struct ProcessGroup {
+    c_pg: *mut C_process_group,
+}
+
+unsafe impl Send for ProcessGroup {}
+// The c10d backends can run collectives issued from multiple threads.
+unsafe impl Sync for ProcessGroup {}
+
+enum Store<'a> {
+    Tcp { addr: &'a str, port: u16 },
+    File { path: &'a str },
+}
+
+impl<'a> Store<'a> {
+    fn parse(init_method: &'a str) -> Result<Self, TchError> {
+        let err = || TchError::Torch(format!("invalid init method {init_method}"));
+        if let Some(path) = init_method.strip_prefix("file://") {
+            Ok(Store::File { path })
+        } else if let Some(addr_port) = init_method.strip_prefix("tcp://") {
+            let (addr, port) = addr_port.rsplit_once(':').ok_or_else(err)?;
+            let port = port.parse().map_err(|_| err())?;
+            Ok(Store::Tcp { addr, port })
+        } else {
+            Err(err())
+        }
+    }
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Result<T, TchError> {
+    let value = std::env::var(name)
+        .map_err(|_| TchError::Torch(format!("environment variable {name} is not set")))?;
+    value
+        .parse()
+        .map_err(|_| TchError::Torch(format!("invalid value for environment variable {name}")))
+}
+
+impl ProcessGroup {
+    /// Creates a new process group.
+    ///
+    /// The `init_method` is used by the processes to find each other, it can either
+    /// be `tcp://addr:port` where the rank 0 process listens on the given address,
+    /// `file:///path/to/file` for a file shared by all the processes, or `env://` to
+    /// get the address and port from the `MASTER_ADDR` and `MASTER_PORT` environment
+    /// variables. This blocks until all the processes have joined the group.
+    pub fn new(
+        backend: Backend,
+        init_method: &str,
+        rank: usize,
+        world_size: usize,
+    ) -> Result<ProcessGroup, TchError> {
+        ProcessGroup::new_with_timeout(backend, init_method, rank, world_size, DEFAULT_TIMEOUT_MS)
+    }
+
+    /// Creates a new process group using a custom timeout in milliseconds.
+    pub fn new_with_timeout(
+        backend: Backend,
+        init_method: &str,
+        rank: usize,
+        world_size: usize,
+        timeout_ms: i64,
+    ) -> Result<ProcessGroup, TchError> {
+        if rank >= world_size {
+            return Err(TchError::Torch(format!(
+                "invalid rank {rank} for world size {world_size}"
+            )));
+        }
+        let env_init_method;
+        let store = if init_method == "env://" {
+            let addr: String = env_var("MASTER_ADDR")?;
+            let port: u16 = env_var("MASTER_PORT")?;
+            env_init_method = format!("tcp://{addr}:{port}");
+            Store::parse(&env_init_method)?
+        } else {
+            Store::parse(init_method)?
+        };
+        let (store, addr, port) = match store {
+            Store::Tcp { addr, port } => (0, addr, port),
+            Store::File { path } => (1, path, 0),
+        };
+        let addr = std::ffi::CString::new(addr)?;
+        let c_pg = unsafe_torch_err!(atd_new_process_group(
+            backend.to_c_int(),
+            store,
+            addr.as_ptr(),
+            port as c_int,
+            rank as c_int,
+            world_size as c_int,
+            timeout_ms
+        ));
+        Ok(ProcessGroup { c_pg })
+    }
+
+    /// Creates a new process group using the `RANK` and `WORLD_SIZE` environment
+    /// variables as set by `torchrun`, as well as `MASTER_ADDR` and `MASTER_PORT`.
+    pub fn from_env(backend: Backend) -> Result<ProcessGroup, TchError> {
+        let rank = env_var("RANK")?;
+        let world_size = env_var("WORLD_SIZE")?;
+        ProcessGroup::new(backend, "env://", rank, world_size)
+    }
+
+    /// The rank of the current process within the group.
+    pub fn rank(&self) -> usize {
+        unsafe_torch!(atd_rank(self.c_pg)) as usize
+    }
+
+    /// The number of processes in the group.
+    pub fn world_size(&self) -> usize {
+        unsafe_torch!(atd_size(self.c_pg)) as usize
+    }
+
+    /// Reduces the tensor values across all the processes, the result is
+    /// stored in place on each process.
+    pub fn all_reduce(&self, tensor: &mut Tensor, op: ReduceOp) -> Result<(), TchError> {
+        let c_tensors = [tensor.c_tensor];
+        unsafe_torch_err!(atd_all_reduce(self.c_pg, c_tensors.as_ptr(), 1, op.to_c_int()));
+        Ok(())
+    }
+
+    /// Copies the tensor values from the process with rank `root` to all the
+    /// other processes.
+    pub fn broadcast(&self, tensor: &mut Tensor, root: usize) -> Result<(), TchError> {
+        let c_tensors = [tensor.c_tensor];
+        unsafe_torch_err!(atd_broadcast(self.c_pg, c_tensors.as_ptr(), 1, root as c_int));
+        Ok(())
+    }
+
+    /// Gathers a tensor from all the processes, the returned vector is indexed by rank.
+    ///
+    /// All the processes have to use tensors with the same shape and kind.
+    pub fn all_gather(&self, tensor: &Tensor) -> Result<Vec<Tensor>, TchError> {
+        let outputs =
+            (0..self.world_size()).map(|_| tensor.f_empty_like()).collect::<Result<Vec<_>, _>>()?;
+        let c_outputs = outputs.iter().map(|t| t.c_tensor).collect::<Vec<_>>();
+        unsafe_torch_err!(atd_all_gather(self.c_pg, c_outputs.as_ptr(), tensor.c_tensor));
+        Ok(outputs)
+    }
+
+    /// Returns a copy of `tensor`, the gradient of which is summed over all
+    /// the processes in the backward pass.
+    ///
+    /// This is used when a tensor with the same value on all the processes is
+    /// the input of a computation split across processes, e.g. the input of
+    /// `nn::parallel::ColumnParallelLinear`.
+    pub fn copy_to_parallel(&self, tensor: &Tensor) -> Result<Tensor, TchError> {
+        let c_tensor = unsafe_torch_err!(atd_copy_to_parallel(self.c_pg, tensor.c_tensor));
+        Ok(unsafe { Tensor::from_ptr(c_tensor) })
+    }
+
+    /// Sums `tensor` over all the processes, like `all_reduce`, but returns
+    /// the result as a new tensor that gradients flow through unchanged.
+    pub fn reduce_from_parallel(&self, tensor: &Tensor) -> Result<Tensor, TchError> {
+        let c_tensor = unsafe_torch_err!(atd_reduce_from_parallel(self.c_pg, tensor.c_tensor));
+        Ok(unsafe { Tensor::from_ptr(c_tensor) })
+    }
+
+    /// Concatenates `tensor` from all the processes along `dim`, ordered by
+    /// rank. Each process gets the gradient of its own chunk in the backward
+    /// pass. All the processes have to use tensors with the same shape.
+    pub fn gather_from_parallel(&self, tensor: &Tensor, dim: i64) -> Result<Tensor, TchError> {
+        let c_tensor = unsafe_torch_err!(atd_gather_from_parallel(self.c_pg, tensor.c_tensor, dim));
+        Ok(unsafe { Tensor::from_ptr(c_tensor) })
+    }
+
+    /// Returns the chunk of `tensor` along `dim` for the current rank, the
+    /// size of `dim` has to be divisible by the world size. The gradients of
+    /// all the chunks are gathered in the backward pass.
+    pub fn scatter_to_parallel(&self, tensor: &Tensor, dim: i64) -> Result<Tensor, TchError> {
+        let c_tensor = unsafe_torch_err!(atd_scatter_to_parallel(self.c_pg, tensor.c_tensor, dim));
+        Ok(unsafe { Tensor::from_ptr(c_tensor) })
+    }
+
+    /// Blocks until all the processes have reached this barrier.
+    pub fn barrier(&self) -> Result<(), TchError> {
+        unsafe_torch_err!(atd_barrier(self.c_pg));
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ProcessGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ProcessGroup(rank: {}, world_size: {})", self.rank(), self.world_size())
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        unsafe_torch_free!(atd_free(self.c_pg))
+    }
+}
+
+static DEFAULT_PROCESS_GROUP: Mutex<Option<ProcessGroup>> = Mutex::new(None);
+
+fn with_default_group<T, F>(f: F) -> Result<T, TchError>
+where
+    F: FnOnce(&ProcessGroup) -> Result<T, TchError>,
+{
+    match DEFAULT_PROCESS_GROUP.lock().unwrap().as_ref() {
+        None => Err(TchError::Torch("the default process group is not initialized".to_string())),
+        Some(pg) => f(pg),
+    }
+}
+
+/// Initializes the default process group, see [`ProcessGroup::new`].
+pub fn init_process_group(
+    backend: Backend,
+    init_method: &str,
+    rank: usize,
+    world_size: usize,
+) -> Result<(), TchError> {
+    let mut default_pg = DEFAULT_PROCESS_GROUP.lock().unwrap();
+    if default_pg.is_some() {
+        return Err(TchError::Torch("the default process group is already initialized".into()));
+    }
+    *default_pg = Some(ProcessGroup::new(backend, init_method, rank, world_size)?);
+    Ok(())
+}
+
+/// Initializes the default process group using environment variables, see
+/// [`ProcessGroup::from_env`].
+pub fn init_process_group_from_env(backend: Backend) -> Result<(), TchError> {
+    let mut default_pg = DEFAULT_PROCESS_GROUP.lock().unwrap();
+    if default_pg.is_some() {
+        return Err(TchError::Torch("the default process group is already initialized".into()));
+    }
+    *default_pg = Some(ProcessGroup::from_env(backend)?);
+    Ok(())
+}
+
+/// Destroys the default process group.
+pub fn destroy_process_group() {
+    let _pg = DEFAULT_PROCESS_GROUP.lock().unwrap().take();
+}
+
+/// Returns true if the default process group has been initialized.
+pub fn is_initialized() -> bool {
+    DEFAULT_PROCESS_GROUP.lock().unwrap().is_some()
+}
+
+/// The rank of the current process in the default process group.
+pub fn rank() -> Result<usize, TchError> {
+    with_default_group(|pg| Ok(pg.rank()))
+}
+
+/// The number of processes in the default process group.
+pub fn world_size() -> Result<usize, TchError> {
+    with_default_group(|pg| Ok(pg.world_size()))
+}
+
+/// Runs [`ProcessGroup::all_reduce`] on the default process group.
+pub fn all_reduce(tensor: &mut Tensor, op: ReduceOp) -> Result<(), TchError> {
+    with_default_group(|pg| pg.all_reduce(tensor, op))
+}
+
+/// Runs [`ProcessGroup::broadcast`] on the default process group.
+pub fn broadcast(tensor: &mut Tensor, root: usize) -> Result<(), TchError> {
+    with_default_group(|pg| pg.broadcast(tensor, root))
+}
+
+/// Runs [`ProcessGroup::all_gather`] on the default process group.
+pub fn all_gather(tensor: &Tensor) -> Result<Vec<Tensor>, TchError> {
+    with_default_group(|pg| pg.all_gather(tensor))
+}
+
+/// Runs [`ProcessGroup::barrier`] on the default process group.
+pub fn barrier() -> Result<(), TchError> {
+    with_default_group(|pg| pg.barrier())
+}
+
+/// A module replicated over all the processes of the default process group.
+///
+/// The trainable variables are broadcast from rank 0 on creation so that all
+/// the processes start from the same weights. After the backward pass,
+/// `reduce_gradients` averages the gradients across processes and should be
+/// called before the optimizer step, each process then applies the same update.
+#[derive(Debug)]
+pub struct DistributedModel<M> {
+    module: M,
+    variables: Vec<Tensor>,
+}
+
+impl<M: ModuleT> DistributedModel<M> {
+    /// Wraps a module which trainable variables are stored in `vs`.
+    pub fn new(module: M, vs: &VarStore) -> Result<Self, TchError> {
+        let variables = vs.trainable_variables();
+        with_default_group(|pg| {
+            crate::no_grad(|| {
+                for var in variables.iter() {
+                    pg.broadcast(&mut var.shallow_clone(), 0)?
+                }
+                Ok::<_, TchError>(())
+            })
+        })?;
+        Ok(DistributedModel { module, variables })
+    }
+
+    /// The wrapped module.
+    pub fn module(&self) -> &M {
+        &self.module
+    }
+
+    /// Averages the gradients of the trainable variables across all processes.
+    ///
+    /// The gradients are flattened into a single buffer so that a single
+    /// all-reduce operation is used.
+    pub fn reduce_gradients(&self) -> Result<(), TchError> {
+        if self.variables.is_empty() {
+            return Ok(());
+        }
+        with_default_group(|pg| {
+            crate::no_grad(|| {
+                // Variables without a gradient on this process contribute zeros so that
+                // the flattened buffer has the same layout on every process.
+                let mut grads = Vec::with_capacity(self.variables.len());
+                for var in self.variables.iter() {
+                    let grad = var.grad();
+                    if grad.defined() {
+                        grads.push(grad)
+                    } else {
+                        let mut var = var.shallow_clone();
+                        var.f_set_grad(&var.f_zeros_like()?)?;
+                        grads.push(var.grad())
+                    }
+                }
+                let flat =
+                    grads.iter().map(|g| g.f_reshape([-1])).collect::<Result<Vec<_>, _>>()?;
+                let mut flat = Tensor::f_cat(&flat, 0)?;
+                pg.all_reduce(&mut flat, ReduceOp::Sum)?;
+                let _ = flat.f_div_scalar_(pg.world_size() as i64)?;
+                let mut offset = 0;
+                for grad in grads.iter() {
+                    let numel = grad.numel() as i64;
+                    let src = flat.f_narrow(0, offset, numel)?.f_view_as(grad)?;
+                    grad.shallow_clone().f_copy_(&src)?;
+                    offset += numel;
+                }
+                Ok(())
+            })
+        })
+    }
+}
+
+impl<M: ModuleT> ModuleT for DistributedModel<M> {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> Tensor {
+        self.module.forward_t(xs, train)
+    }
+}
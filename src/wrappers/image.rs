@@ -25,6 +25,22 @@ pub fn save_hwc<T: AsRef<Path>>(t: &Tensor, path: T) -> Result<(), TchError> {
     Ok(())
 }
 
+/// Expects a tensor of shape [width, height, channels].
+/// The format is either "jpg" or "png", the quality is only used for jpg.
+pub fn encode_hwc(t: &Tensor, format: &str, quality: i64) -> Result<Vec<u8>, TchError> {
+    let format = std::ffi::CString::new(format)?;
+    let mut len = 0;
+    let data = unsafe_torch_err!(torch_sys::at_encode_image(
+        t.c_tensor,
+        format.as_ptr(),
+        quality as c_int,
+        &mut len
+    ));
+    let bytes = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+    unsafe { libc::free(data as *mut libc::c_void) };
+    Ok(bytes)
+}
+
 /// Expects a tensor of shape [width, height, channels].
 /// On success returns a tensor of shape [width, height, channels].
 pub fn resize_hwc(t: &Tensor, out_w: i64, out_h: i64) -> Result<Tensor, TchError> {
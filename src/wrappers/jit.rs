@@ -420,7 +420,7 @@ unsafe impl Sync for CModule {}
 
 impl Drop for CModule {
     fn drop(&mut self) {
-        unsafe_torch!(atm_free(self.c_module))
+        unsafe_torch_free!(atm_free(self.c_module))
     }
 }
 
@@ -593,6 +593,28 @@ impl CModule {
         Ok(v)
     }
 
+    /// Loads the named buffers from a module, e.g. the running statistics of
+    /// batch normalization layers. The names of the buffers of submodules are
+    /// prefixed with the submodule names, e.g. `bn.running_mean`.
+    pub fn named_buffers(&self) -> Result<Vec<(String, Tensor)>, TchError> {
+        let mut v: Vec<(String, Tensor)> = vec![];
+        unsafe_torch_err!(atm_named_buffers(
+            self.c_module,
+            &mut v as *mut _ as *mut c_void,
+            super::tensor::add_callback
+        ));
+        Ok(v)
+    }
+
+    /// Returns the buffer with the given name, see `named_buffers`. The
+    /// returned tensor shares its storage with the module.
+    pub fn get_buffer(&self, name: &str) -> Result<Tensor, TchError> {
+        self.named_buffers()?
+            .into_iter()
+            .find_map(|(n, tensor)| (n == name).then_some(tensor))
+            .ok_or_else(|| TchError::TensorNameNotFound(name.to_string(), "module".to_string()))
+    }
+
     /// Create a new module by tracing the application of the specified function on
     /// the given inputs.
     pub fn create_by_tracing<F>(
@@ -836,7 +858,7 @@ impl Object {
 
 impl Drop for Object {
     fn drop(&mut self) {
-        unsafe_torch!(ati_free(self.c_ivalue))
+        unsafe_torch_free!(ati_free(self.c_ivalue))
     }
 }
 
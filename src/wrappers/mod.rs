@@ -2,20 +2,33 @@
 pub mod utils;
 
 pub use utils::{
-    get_num_interop_threads, get_num_threads, manual_seed, set_num_interop_threads,
-    set_num_threads, QEngine,
+    deterministic_report, f_set_num_interop_threads, f_set_num_threads, get_num_interop_threads,
+    get_num_threads, initial_seed, manual_seed, manual_seed_all, set_deterministic,
+    set_num_interop_threads, set_num_threads, DeterminismReport, QEngine,
 };
 
+pub mod autograd;
+pub mod backends;
+pub(crate) mod checkpoint;
+pub mod cuda;
 pub(crate) mod device;
+#[cfg(feature = "distributed")]
+pub mod distributed;
 pub(crate) mod image;
 pub mod jit;
 pub mod kind;
 pub(crate) mod layout;
+pub mod ops;
 pub(crate) mod optimizer;
+pub mod profiler;
 #[cfg(feature = "python-extension")]
 pub mod python;
+pub mod rng;
 pub(crate) mod scalar;
 pub(crate) mod stream;
 pub(crate) mod tensor;
 pub(crate) mod tensor_fallible_generated;
 pub(crate) mod tensor_generated;
+pub mod version;
+pub mod warnings;
+pub(crate) mod windows;
@@ -0,0 +1,237 @@
+//! Custom operators implemented in Rust.
+//!
+//! Registered operators can be called from TorchScript and, when the library is
+//! loaded in a Python process e.g. via the `python-extension` feature, from
+//! Python using `torch.ops.my_ns.my_op`.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), tch::TchError> {
+//! use tch::{ops, IValue, Tensor};
+//! tch::register_op!("my_ns::add_mul(Tensor a, Tensor b, float s) -> Tensor", |a: Tensor, b: Tensor, s: f64| {
+//!     (a + b).f_mul_scalar(s)
+//! })?;
+//! let a = Tensor::from_slice(&[1f32, 2.]);
+//! let res = ops::call("my_ns::add_mul", &[IValue::from(a.copy()), IValue::from(a), IValue::from(0.5)])?;
+//! # Ok(())
+//! # }
+//! ```
+use super::jit::IValue;
+use crate::{TchError, Tensor};
+use libc::{c_char, c_int, c_void};
+use std::ffi::CString;
+use torch_sys::CIValue;
+
+type Closure = Box<dyn Fn(Vec<IValue>) -> Result<IValue, TchError> + Send + Sync>;
+
+fn set_error(err: *mut *mut c_char, msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    unsafe { *err = libc::strdup(msg.as_ptr()) }
+}
+
+// Values converted to the C side, they are freed when dropped.
+struct CIValues(Vec<*mut CIValue>);
+
+impl Drop for CIValues {
+    fn drop(&mut self) {
+        for &c_ivalue in self.0.iter() {
+            unsafe { torch_sys::ati_free(c_ivalue) }
+        }
+    }
+}
+
+extern "C" fn call_closure(
+    data: *mut c_void,
+    c_inputs: *mut *mut CIValue,
+    ninputs: c_int,
+    err: *mut *mut c_char,
+) -> *mut CIValue {
+    let closure = unsafe { &*(data as *const Closure) };
+    // Unwinding through the C++ frames is not allowed, errors and panics are
+    // converted to C++ exceptions.
+    let output = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // The C++ side gives us the ownership of the inputs, the ones that have
+        // not been converted are freed on errors.
+        let mut inputs = Vec::with_capacity(ninputs as usize);
+        for i in 0..ninputs as usize {
+            match IValue::from_c(unsafe { *c_inputs.add(i) }) {
+                Ok(input) => inputs.push(input),
+                Err(err) => {
+                    drop(CIValues(
+                        (i + 1..ninputs as usize).map(|j| unsafe { *c_inputs.add(j) }).collect(),
+                    ));
+                    return Err(err);
+                }
+            }
+        }
+        closure(inputs)?.to_c()
+    }));
+    match output {
+        Ok(Ok(c_ivalue)) => c_ivalue,
+        Ok(Err(e)) => {
+            set_error(err, e.to_string());
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            set_error(err, "custom operator panicked".to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+extern "C" fn free_closure(data: *mut c_void) {
+    drop(unsafe { Box::from_raw(data as *mut Closure) })
+}
+
+/// Registers a custom operator with the given schema, e.g.
+/// `my_ns::my_op(Tensor a, Tensor b) -> Tensor`.
+///
+/// The closure gets one value per argument of the schema. It should return
+/// `IValue::None` when the schema has no outputs and a tuple when it has
+/// multiple outputs. The closure can be called concurrently from multiple
+/// threads, errors and panics are reported as errors to the caller of the
+/// operator. The operators cannot be unregistered.
+pub fn register<F>(schema: &str, f: F) -> Result<(), TchError>
+where
+    F: Fn(Vec<IValue>) -> Result<IValue, TchError> + Send + Sync + 'static,
+{
+    let schema = CString::new(schema)?;
+    let closure: Box<Closure> = Box::new(Box::new(f));
+    let data = Box::into_raw(closure) as *mut c_void;
+    unsafe_torch_err!(torch_sys::at_register_op(schema.as_ptr(), data, call_closure, free_closure));
+    Ok(())
+}
+
+/// The dispatch keys for which a kernel can be registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DispatchKey {
+    Cpu,
+    Cuda,
+    /// A kernel implemented using differentiable operations, it is used for
+    /// all the devices and the backward pass is derived automatically.
+    CompositeImplicitAutograd,
+}
+
+// The values of the dispatch_key argument of at_register_kernel, see the
+// TCH_DISPATCH_KEY_* constants in torch_api.h.
+const DISPATCH_KEY_CPU: c_int = 0;
+const DISPATCH_KEY_CUDA: c_int = 1;
+const DISPATCH_KEY_COMPOSITE_IMPLICIT_AUTOGRAD: c_int = 2;
+// Used for the backward closures registered by `register_backward`.
+const DISPATCH_KEY_AUTOGRAD: c_int = 3;
+
+impl DispatchKey {
+    fn c_int(self) -> c_int {
+        match self {
+            DispatchKey::Cpu => DISPATCH_KEY_CPU,
+            DispatchKey::Cuda => DISPATCH_KEY_CUDA,
+            DispatchKey::CompositeImplicitAutograd => DISPATCH_KEY_COMPOSITE_IMPLICIT_AUTOGRAD,
+        }
+    }
+}
+
+fn register_closure(schema: &str, dispatch_key: c_int, closure: Closure) -> Result<(), TchError> {
+    let schema = CString::new(schema)?;
+    let data = Box::into_raw(Box::new(closure)) as *mut c_void;
+    unsafe_torch_err!(torch_sys::at_register_kernel(
+        schema.as_ptr(),
+        dispatch_key,
+        data,
+        call_closure,
+        free_closure
+    ));
+    Ok(())
+}
+
+/// Registers a kernel for an operator in the libtorch dispatcher, the operator
+/// is defined using the schema if this has not been done yet.
+///
+/// Unlike `register`, the operator can have different kernels per device. This
+/// has to be called before loading TorchScript models that use the operator
+/// with `CModule::load`, e.g. from an initialization function of the
+/// application. When no backward has been registered with `register_backward`,
+/// the outputs of the `Cpu` and `Cuda` kernels do not track gradients. Errors
+/// and panics in the kernel are reported as errors to the caller.
+pub fn register_kernel<F>(schema: &str, dispatch_key: DispatchKey, f: F) -> Result<(), TchError>
+where
+    F: Fn(Vec<IValue>) -> Result<IValue, TchError> + Send + Sync + 'static,
+{
+    register_closure(schema, dispatch_key.c_int(), Box::new(f))
+}
+
+/// Registers the backward pass of an operator with kernels registered via
+/// `register_kernel`.
+///
+/// The closure gets the gradients for the tensor outputs and the inputs of the
+/// forward pass, it returns one gradient per tensor input or `None` for the
+/// inputs that do not need one.
+pub fn register_backward<F>(schema: &str, f: F) -> Result<(), TchError>
+where
+    F: Fn(Vec<Tensor>, Vec<IValue>) -> Result<Vec<Option<Tensor>>, TchError>
+        + Send
+        + Sync
+        + 'static,
+{
+    let closure = move |inputs: Vec<IValue>| {
+        let mut inputs = inputs.into_iter();
+        let (grads, inputs) = match (inputs.next(), inputs.next()) {
+            (Some(IValue::TensorList(grads)), Some(IValue::Tuple(inputs))) => (grads, inputs),
+            inputs => return Err(TchError::Kind(format!("unexpected backward inputs {inputs:?}"))),
+        };
+        let grads = f(grads, inputs)?;
+        let grads = grads.into_iter().map(|g| g.map_or(IValue::None, IValue::Tensor)).collect();
+        Ok(IValue::GenericList(grads))
+    };
+    register_closure(schema, DISPATCH_KEY_AUTOGRAD, Box::new(closure))
+}
+
+/// Calls a registered operator by its qualified name, e.g. `my_ns::my_op`.
+/// Multiple outputs are returned as a tuple.
+pub fn call(name: &str, inputs: &[IValue]) -> Result<IValue, TchError> {
+    let name = CString::new(name)?;
+    // The inputs are copied by the C++ side and freed here, including on errors.
+    let mut c_inputs = CIValues(Vec::with_capacity(inputs.len()));
+    for input in inputs.iter() {
+        c_inputs.0.push(input.to_c()?)
+    }
+    let c_ivalue = unsafe_torch_err!(torch_sys::at_call_op(
+        name.as_ptr(),
+        c_inputs.0.as_ptr(),
+        c_inputs.0.len() as c_int
+    ));
+    drop(c_inputs);
+    IValue::from_c(c_ivalue)
+}
+
+/// Registers a custom operator, converting the arguments to the given types
+/// and the result of the body, a `Result<T, TchError>`, back to an `IValue`.
+///
+/// ```no_run
+/// # fn main() -> Result<(), tch::TchError> {
+/// use tch::Tensor;
+/// tch::register_op!("my_ns::scale(Tensor x, float s) -> Tensor", |x: Tensor, s: f64| {
+///     x.f_mul_scalar(s)
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! register_op {
+    ($schema:expr, |$($arg:ident : $ty:ty),* $(,)?| $body:expr) => {
+        $crate::ops::register($schema, |inputs: Vec<$crate::IValue>| {
+            let mut inputs = inputs.into_iter();
+            $(
+                let $arg: $ty = match inputs.next() {
+                    Some(input) => ::std::convert::TryFrom::try_from(input)?,
+                    None => {
+                        return Err($crate::TchError::Kind(format!(
+                            "missing argument {}",
+                            stringify!($arg)
+                        )))
+                    }
+                };
+            )*
+            let output: Result<_, $crate::TchError> = $body;
+            output.map($crate::IValue::from)
+        })
+    };
+}
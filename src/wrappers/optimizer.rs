@@ -1,4 +1,5 @@
 use super::tensor::Tensor;
+use super::utils::path_to_cstring;
 use crate::TchError;
 
 pub struct COptimizer {
@@ -68,6 +69,23 @@ impl COptimizer {
         Ok(COptimizer { c_optimizer })
     }
 
+    pub fn adagrad(
+        lr: f64,
+        lr_decay: f64,
+        wd: f64,
+        initial_accumulator_value: f64,
+        eps: f64,
+    ) -> Result<COptimizer, TchError> {
+        let c_optimizer = unsafe_torch_err!(torch_sys::ato_adagrad(
+            lr,
+            lr_decay,
+            wd,
+            initial_accumulator_value,
+            eps
+        ));
+        Ok(COptimizer { c_optimizer })
+    }
+
     pub fn add_parameters(&mut self, t: &Tensor, group: usize) -> Result<(), TchError> {
         unsafe_torch_err!(torch_sys::ato_add_parameters(self.c_optimizer, t.c_tensor, group));
         Ok(())
@@ -112,7 +130,12 @@ impl COptimizer {
     }
 
     pub fn zero_grad(&self) -> Result<(), TchError> {
-        unsafe_torch_err!(torch_sys::ato_zero_grad(self.c_optimizer));
+        unsafe_torch_err!(torch_sys::ato_zero_grad(self.c_optimizer, 0));
+        Ok(())
+    }
+
+    pub fn zero_grad_set_to_none(&self) -> Result<(), TchError> {
+        unsafe_torch_err!(torch_sys::ato_zero_grad(self.c_optimizer, 1));
         Ok(())
     }
 
@@ -120,10 +143,22 @@ impl COptimizer {
         unsafe_torch_err!(torch_sys::ato_step(self.c_optimizer));
         Ok(())
     }
+
+    pub fn save<T: AsRef<std::path::Path>>(&self, path: T) -> Result<(), TchError> {
+        let path = path_to_cstring(path)?;
+        unsafe_torch_err!(torch_sys::ato_save(self.c_optimizer, path.as_ptr()));
+        Ok(())
+    }
+
+    pub fn load<T: AsRef<std::path::Path>>(&mut self, path: T) -> Result<(), TchError> {
+        let path = path_to_cstring(path)?;
+        unsafe_torch_err!(torch_sys::ato_load(self.c_optimizer, path.as_ptr()));
+        Ok(())
+    }
 }
 
 impl Drop for COptimizer {
     fn drop(&mut self) {
-        unsafe { torch_sys::ato_free(self.c_optimizer) }
+        unsafe_torch_free!(torch_sys::ato_free(self.c_optimizer))
     }
 }
@@ -0,0 +1,223 @@
+//! Profiling of the operations run by libtorch.
+//!
+//! ```no_run
+//! # use tch::{profiler, Device, Kind, Tensor};
+//! # fn main() -> Result<(), tch::TchError> {
+//! let xs = Tensor::randn([64, 64], (Kind::Float, Device::Cpu));
+//! let profile = profiler::profile(Default::default(), || {
+//!     profiler::record_function("matmul", || xs.matmul(&xs));
+//! })?;
+//! for stats in profile.key_averages() {
+//!     println!("{} {} {}us", stats.name, stats.count, stats.cpu_time_us);
+//! }
+//! profile.export_chrome_trace("trace.json")?;
+//! # Ok(())
+//! # }
+//! ```
+use super::utils::{path_to_cstring, ptr_to_string};
+use crate::TchError;
+use std::collections::HashMap;
+use torch_sys::{C_profiler_result, C_record_function};
+
+/// The kind of activities to record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Activity {
+    /// Operators run on the CPU, including the launch of CUDA kernels.
+    Cpu,
+    /// CUDA kernels run on the GPU.
+    Cuda,
+}
+
+/// The profiler configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfilerConfig {
+    pub activities: Vec<Activity>,
+    /// Record the input shapes of the operators.
+    pub record_shapes: bool,
+    /// Record the memory allocated and released by the operators.
+    pub profile_memory: bool,
+    /// Record the source location of the operators.
+    pub with_stack: bool,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        ProfilerConfig {
+            activities: vec![Activity::Cpu],
+            record_shapes: false,
+            profile_memory: false,
+            with_stack: false,
+        }
+    }
+}
+
+/// A single profiled event.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event {
+    pub name: String,
+    /// True for events that ran on a CUDA device, e.g. kernels.
+    pub is_cuda: bool,
+    pub start_us: u64,
+    pub duration_us: u64,
+    /// The number of bytes allocated, negative when memory is released. This
+    /// is only set when `profile_memory` is enabled.
+    pub memory_bytes: i64,
+}
+
+/// Statistics for all the events sharing the same name.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventStats {
+    pub name: String,
+    pub count: usize,
+    /// The total time spent in CPU events, in microseconds.
+    pub cpu_time_us: u64,
+    /// The total time spent in CUDA events, in microseconds.
+    pub cuda_time_us: u64,
+    pub memory_bytes: i64,
+}
+
+/// The result of a profiling run.
+#[derive(Debug)]
+pub struct Profile {
+    c_result: *mut C_profiler_result,
+}
+
+unsafe impl Send for Profile {}
+
+impl Drop for Profile {
+    fn drop(&mut self) {
+        unsafe_torch_free!(torch_sys::atp_result_free(self.c_result))
+    }
+}
+
+impl Profile {
+    /// Returns all the recorded events.
+    pub fn events(&self) -> Result<Vec<Event>, TchError> {
+        let num_events = unsafe_torch_err!(torch_sys::atp_result_num_events(self.c_result));
+        let mut events = Vec::with_capacity(num_events.max(0) as usize);
+        for index in 0..num_events {
+            let mut name = std::ptr::null_mut();
+            let mut is_cuda = 0;
+            let mut start_us = 0;
+            let mut duration_us = 0;
+            let mut memory_bytes = 0;
+            unsafe_torch_err!(torch_sys::atp_result_event(
+                self.c_result,
+                index,
+                &mut name,
+                &mut is_cuda,
+                &mut start_us,
+                &mut duration_us,
+                &mut memory_bytes,
+            ));
+            let name = unsafe { ptr_to_string(name) }.unwrap_or_default();
+            events.push(Event { name, is_cuda: is_cuda != 0, start_us, duration_us, memory_bytes })
+        }
+        Ok(events)
+    }
+
+    /// Aggregates the events by name, the result is sorted by decreasing total
+    /// time.
+    pub fn key_averages(&self) -> Result<Vec<EventStats>, TchError> {
+        let mut stats: Vec<EventStats> = vec![];
+        let mut index_by_name: HashMap<String, usize> = HashMap::new();
+        for event in self.events()? {
+            let index = *index_by_name.entry(event.name.clone()).or_insert_with(|| {
+                stats.push(EventStats {
+                    name: event.name.clone(),
+                    count: 0,
+                    cpu_time_us: 0,
+                    cuda_time_us: 0,
+                    memory_bytes: 0,
+                });
+                stats.len() - 1
+            });
+            let s = &mut stats[index];
+            s.count += 1;
+            if event.is_cuda {
+                s.cuda_time_us += event.duration_us
+            } else {
+                s.cpu_time_us += event.duration_us
+            }
+            s.memory_bytes += event.memory_bytes
+        }
+        stats.sort_by_key(|s| std::cmp::Reverse(s.cpu_time_us + s.cuda_time_us));
+        Ok(stats)
+    }
+
+    /// Writes the events in the chrome trace format, the resulting file can be
+    /// opened with `chrome://tracing` or Perfetto.
+    pub fn export_chrome_trace<T: AsRef<std::path::Path>>(&self, path: T) -> Result<(), TchError> {
+        let path = path_to_cstring(path)?;
+        unsafe_torch_err!(torch_sys::atp_result_save(self.c_result, path.as_ptr()));
+        Ok(())
+    }
+}
+
+// Stops the profiler when deallocated so that it does not remain enabled if the
+// profiled closure panics.
+struct ProfilerGuard {
+    enabled: bool,
+}
+
+impl ProfilerGuard {
+    fn stop(&mut self) -> Result<Profile, TchError> {
+        self.enabled = false;
+        let c_result = unsafe_torch_err!(torch_sys::atp_disable_profiler());
+        Ok(Profile { c_result })
+    }
+}
+
+impl Drop for ProfilerGuard {
+    fn drop(&mut self) {
+        if self.enabled {
+            let _ = self.stop();
+        }
+    }
+}
+
+/// Runs a closure with the profiler enabled and returns the recorded events.
+///
+/// An error is returned if the profiler is already running or if CUDA
+/// activities are requested but not supported by libtorch.
+pub fn profile<F>(config: ProfilerConfig, f: F) -> Result<Profile, TchError>
+where
+    F: FnOnce(),
+{
+    let activities = &config.activities;
+    unsafe_torch_err!(torch_sys::atp_enable_profiler(
+        i32::from(activities.contains(&Activity::Cpu)),
+        i32::from(activities.contains(&Activity::Cuda)),
+        i32::from(config.record_shapes),
+        i32::from(config.profile_memory),
+        i32::from(config.with_stack),
+    ));
+    let mut guard = ProfilerGuard { enabled: true };
+    f();
+    guard.stop()
+}
+
+struct RecordFunctionGuard {
+    c_record_function: *mut C_record_function,
+}
+
+impl Drop for RecordFunctionGuard {
+    fn drop(&mut self) {
+        unsafe_torch_free!(torch_sys::atp_record_function_exit(self.c_record_function))
+    }
+}
+
+/// Runs a closure within a named range, the range appears as an event in the
+/// profiling results. This has almost no overhead when the profiler is not
+/// running.
+pub fn record_function<T, F>(name: &str, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let name = std::ffi::CString::new(name).unwrap();
+    let c_record_function = unsafe_torch!(torch_sys::atp_record_function_enter(name.as_ptr()));
+    let _guard = RecordFunctionGuard { c_record_function };
+    f()
+}
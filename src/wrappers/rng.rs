@@ -0,0 +1,116 @@
+//! Capture and restoration of the random number generator states.
+//!
+//! Random operations draw from the default generator of the device their
+//! output lives on:
+//! - operations creating or modifying CPU tensors, e.g. `Tensor::rand`,
+//!   `Tensor::randn`, `Tensor::randperm`, `dropout`, `bernoulli`,
+//!   `multinomial`, `uniform_`, `normal_` and the `nn::Init` initializations,
+//!   use the CPU generator. This includes the shuffling of the data iterators
+//!   and the data augmentations of the `vision` module on CPU tensors.
+//! - the same operations on CUDA tensors use the generator of their CUDA
+//!   device, e.g. the dropout masks of a model on `Device::Cuda(1)` come from
+//!   the generator of this device only.
+//!
+//! The generators are global: random operations run concurrently on other
+//! threads consume the same states, so the replays below are only
+//! reproducible if no other thread uses the generators meanwhile.
+//!
+//! ```no_run
+//! # use tch::{rng, Kind, Tensor};
+//! let xs = Tensor::ones([4, 8], (Kind::Float, tch::Device::Cpu));
+//! // Two forward passes with the same dropout masks, e.g. for R-Drop.
+//! let (ys1, ys2) = rng::replay_twice(|| xs.dropout(0.1, true));
+//! assert!(ys1.equal(&ys2));
+//! ```
+use crate::{Cuda, Device, TchError, Tensor};
+
+/// The states of the CPU generator and of the generators of all the CUDA
+/// devices.
+#[derive(Debug)]
+pub struct RngState {
+    cpu: Tensor,
+    // The states of the CUDA generators, indexed by device.
+    cuda: Vec<Tensor>,
+}
+
+impl Clone for RngState {
+    fn clone(&self) -> Self {
+        let cuda = self.cuda.iter().map(|s| s.shallow_clone()).collect();
+        RngState { cpu: self.cpu.shallow_clone(), cuda }
+    }
+}
+
+fn f_get_state(device: Device) -> Result<Tensor, TchError> {
+    let c_tensor = unsafe_torch_err!(torch_sys::at_get_rng_state(device.c_int()));
+    Ok(unsafe { Tensor::from_ptr(c_tensor) })
+}
+
+fn f_set_state(device: Device, state: &Tensor) -> Result<(), TchError> {
+    unsafe_torch_err!(torch_sys::at_set_rng_state(device.c_int(), state.c_tensor));
+    Ok(())
+}
+
+/// Captures the current states of the CPU and CUDA generators.
+///
+/// The generators are not modified, the states are copied so that the
+/// following random operations can be replayed with `restore`.
+pub fn f_fork_state() -> Result<RngState, TchError> {
+    let cpu = f_get_state(Device::Cpu)?;
+    let cuda = (0..Cuda::device_count())
+        .map(|index| f_get_state(Device::Cuda(index as usize)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RngState { cpu, cuda })
+}
+
+/// Captures the current states of the CPU and CUDA generators, see
+/// `f_fork_state`.
+pub fn fork_state() -> RngState {
+    f_fork_state().unwrap()
+}
+
+/// Sets the states of the CPU and CUDA generators to a captured state.
+pub fn f_restore(state: &RngState) -> Result<(), TchError> {
+    f_set_state(Device::Cpu, &state.cpu)?;
+    for (index, cuda) in state.cuda.iter().enumerate() {
+        f_set_state(Device::Cuda(index), cuda)?
+    }
+    Ok(())
+}
+
+/// Sets the states of the CPU and CUDA generators to a captured state, see
+/// `f_restore`.
+pub fn restore(state: &RngState) {
+    f_restore(state).unwrap()
+}
+
+// Restores the captured state when dropped, including on panics.
+pub(crate) struct RestoreOnDrop(pub(crate) RngState);
+
+impl Drop for RestoreOnDrop {
+    fn drop(&mut self) {
+        if let Err(err) = f_restore(&self.0) {
+            log::error!(target: "tch", "cannot restore the generator states: {err}")
+        }
+    }
+}
+
+/// Runs `f` with the generators set to `state`, the prior states of the
+/// generators are restored afterwards so the random operations of `f` do not
+/// affect the ones run after it.
+pub fn with_rng_state<T, F: FnOnce() -> T>(state: &RngState, f: F) -> T {
+    let _prior = RestoreOnDrop(fork_state());
+    restore(state);
+    f()
+}
+
+/// Runs `f` twice from the same generator states, so that both runs use the
+/// same random values, e.g. the same dropout masks.
+///
+/// The generators are left in the state following the second run.
+pub fn replay_twice<T, F: FnMut() -> T>(mut f: F) -> (T, T) {
+    let state = fork_state();
+    let first = f();
+    restore(&state);
+    let second = f();
+    (first, second)
+}
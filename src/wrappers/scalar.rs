@@ -1,8 +1,13 @@
 //! Scalar elements.
 
+use super::kind::Kind;
 use crate::TchError;
 
 /// A single scalar value.
+///
+/// The scalar keeps track of whether it holds a boolean, an integer, or a float
+/// value. Integer values are stored as `i64` and are never converted to `f64`
+/// so that large integers are passed to the operations exactly.
 pub struct Scalar {
     pub(super) c_scalar: *mut torch_sys::C_scalar,
 }
@@ -20,6 +25,25 @@ impl Scalar {
         Scalar { c_scalar }
     }
 
+    /// Creates a boolean scalar.
+    pub fn bool(v: bool) -> Scalar {
+        let c_scalar = unsafe_torch!(torch_sys::ats_bool(i32::from(v)));
+        Scalar { c_scalar }
+    }
+
+    /// Returns the kind of the stored value, one of `Kind::Bool`, `Kind::Int64`,
+    /// `Kind::Double`, or `Kind::ComplexDouble`.
+    pub fn kind(&self) -> Result<Kind, TchError> {
+        let kind = unsafe_torch_err!(torch_sys::ats_type(self.c_scalar));
+        Kind::from_c_int(kind)
+    }
+
+    /// Returns a boolean value.
+    pub fn to_bool(&self) -> Result<bool, TchError> {
+        let b = unsafe_torch_err!(torch_sys::ats_to_bool(self.c_scalar));
+        Ok(b != 0)
+    }
+
     /// Returns an integer value.
     pub fn to_int(&self) -> Result<i64, TchError> {
         let i = unsafe_torch_err!(torch_sys::ats_to_int(self.c_scalar));
@@ -55,7 +79,7 @@ impl std::fmt::Debug for Scalar {
 
 impl Drop for Scalar {
     fn drop(&mut self) {
-        unsafe_torch!(torch_sys::ats_free(self.c_scalar))
+        unsafe_torch_free!(torch_sys::ats_free(self.c_scalar))
     }
 }
 
@@ -71,6 +95,42 @@ impl From<f64> for Scalar {
     }
 }
 
+impl From<bool> for Scalar {
+    fn from(v: bool) -> Scalar {
+        Scalar::bool(v)
+    }
+}
+
+macro_rules! from_int {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Scalar {
+            fn from(v: $t) -> Scalar {
+                Scalar::int(i64::from(v))
+            }
+        }
+    )*};
+}
+
+from_int!(u8, i8, i16, i32);
+
+impl From<f32> for Scalar {
+    fn from(v: f32) -> Scalar {
+        Scalar::float(f64::from(v))
+    }
+}
+
+impl From<half::f16> for Scalar {
+    fn from(v: half::f16) -> Scalar {
+        Scalar::float(f64::from(v))
+    }
+}
+
+impl From<half::bf16> for Scalar {
+    fn from(v: half::bf16) -> Scalar {
+        Scalar::float(f64::from(v))
+    }
+}
+
 impl From<Scalar> for i64 {
     fn from(s: Scalar) -> i64 {
         Self::from(&s)
@@ -95,6 +155,12 @@ impl From<&Scalar> for f64 {
     }
 }
 
+impl From<&Scalar> for bool {
+    fn from(s: &Scalar) -> bool {
+        s.to_bool().unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Scalar;
@@ -108,4 +174,20 @@ mod tests {
         assert_eq!(f64::from(&leet), 1337.);
         assert_eq!(&format!("{pi:?}"), "scalar<3.14159>");
     }
+
+    #[test]
+    fn scalar_kind() {
+        use crate::Kind;
+        assert_eq!(Scalar::from(true).kind().unwrap(), Kind::Bool);
+        assert!(bool::from(&Scalar::from(true)));
+        assert_eq!(Scalar::from(42i32).kind().unwrap(), Kind::Int64);
+        assert_eq!(Scalar::from(-3i8).to_int().unwrap(), -3);
+        assert_eq!(Scalar::from(255u8).to_int().unwrap(), 255);
+        assert_eq!(Scalar::from(1.5f32).kind().unwrap(), Kind::Double);
+        assert_eq!(Scalar::from(half::f16::from_f32(0.5)).to_float().unwrap(), 0.5);
+        // Large integers are not converted to floats.
+        let v = (1i64 << 53) + 1;
+        assert_eq!(Scalar::from(v).kind().unwrap(), Kind::Int64);
+        assert_eq!(Scalar::from(v).to_int().unwrap(), v);
+    }
 }
@@ -1,6 +1,7 @@
 use super::stream::ReadSeekAdapter;
 use super::utils::{path_to_cstring, ptr_to_string};
 use super::{
+    autograd::BackwardOptions,
     device::{Cuda, Device},
     kind,
     kind::Kind,
@@ -74,17 +75,31 @@ impl Tensor {
         self.c_tensor
     }
 
+    /// Returns the number of dimension of the tensor, an error is returned on
+    /// undefined tensors.
+    pub fn f_dim(&self) -> Result<usize, TchError> {
+        Ok(unsafe_torch_err!(at_dim(self.c_tensor)))
+    }
+
     /// Returns the number of dimension of the tensor.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_dim"))]
     pub fn dim(&self) -> usize {
-        unsafe_torch!(at_dim(self.c_tensor))
+        self.f_dim().unwrap()
+    }
+
+    /// Returns the shape of the input tensor, an error is returned on undefined
+    /// tensors.
+    pub fn f_size(&self) -> Result<Vec<i64>, TchError> {
+        let dim = unsafe_torch_err!(at_dim(self.c_tensor));
+        let mut sz = vec![0i64; dim];
+        unsafe_torch_err!(at_shape(self.c_tensor, sz.as_mut_ptr()));
+        Ok(sz)
     }
 
     /// Returns the shape of the input tensor.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_size"))]
     pub fn size(&self) -> Vec<i64> {
-        let dim = unsafe_torch!(at_dim(self.c_tensor));
-        let mut sz = vec![0i64; dim];
-        unsafe_torch!(at_shape(self.c_tensor, sz.as_mut_ptr()));
-        sz
+        self.f_size().unwrap()
     }
 
     /// Returns the tensor size for single dimension tensors.
@@ -135,12 +150,19 @@ impl Tensor {
         }
     }
 
+    /// Returns the stride of the input tensor, an error is returned on undefined
+    /// tensors and on tensors that do not have strides, e.g. sparse tensors.
+    pub fn f_stride(&self) -> Result<Vec<i64>, TchError> {
+        let dim = unsafe_torch_err!(at_dim(self.c_tensor));
+        let mut sz = vec![0i64; dim];
+        unsafe_torch_err!(at_stride(self.c_tensor, sz.as_mut_ptr()));
+        Ok(sz)
+    }
+
     /// Returns the stride of the input tensor.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_stride"))]
     pub fn stride(&self) -> Vec<i64> {
-        let dim = unsafe_torch!(at_dim(self.c_tensor));
-        let mut sz = vec![0i64; dim];
-        unsafe_torch!(at_stride(self.c_tensor, sz.as_mut_ptr()));
-        sz
+        self.f_stride().unwrap()
     }
 
     /// Returns the tensor strides for single dimension tensors.
@@ -200,6 +222,7 @@ impl Tensor {
 
     /// Returns the kind of elements stored in the input tensor. Panics
     /// an error on undefined tensors and unsupported data types.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_kind"))]
     pub fn kind(&self) -> Kind {
         self.f_kind().unwrap()
     }
@@ -235,11 +258,13 @@ impl Tensor {
     }
 
     /// Returns a double value on tensors holding a single element. Panics otherwise.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_double_value"))]
     pub fn double_value(&self, idx: &[i64]) -> f64 {
         self.f_double_value(idx).unwrap()
     }
 
     /// Returns an int value on tensors holding a single element. Panics otherwise.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_int64_value"))]
     pub fn int64_value(&self, idx: &[i64]) -> i64 {
         self.f_int64_value(idx).unwrap()
     }
@@ -249,11 +274,96 @@ impl Tensor {
         unsafe_torch!(at_requires_grad(self.c_tensor)) != 0
     }
 
+    /// Turns the gradient tracking on or off for this tensor, the returned
+    /// tensor shares the same storage and flags.
+    ///
+    /// This is only valid on leaf tensors, i.e. tensors that were not computed
+    /// by operations tracked by autograd. The gradients of a computed tensor
+    /// can be kept with `retain_grad`, and `detach` returns a new leaf tensor
+    /// on which gradient tracking can be turned on.
+    pub fn f_set_requires_grad(&self, r: bool) -> Result<Tensor, TchError> {
+        if !self.f_is_leaf()? {
+            return Err(TchError::Torch(
+                "set_requires_grad can only be called on leaf tensors, this tensor was computed by \
+                 an operation tracked by autograd; use `detach().set_requires_grad(true)` to get \
+                 a new leaf tensor, or `retain_grad()` to keep the gradient of this tensor"
+                    .to_string(),
+            ));
+        }
+        let c_tensor = unsafe_torch_err!(at_set_requires_grad(self.c_tensor, r as c_int));
+        Ok(Tensor { c_tensor })
+    }
+
+    /// Turns the gradient tracking on or off for this tensor, see
+    /// `f_set_requires_grad`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_set_requires_grad"))]
+    pub fn set_requires_grad(&self, r: bool) -> Tensor {
+        self.f_set_requires_grad(r).unwrap()
+    }
+
+    /// Returns the gradient accumulated for this tensor, `None` if no gradient
+    /// has been computed, e.g. when the tensor does not require grad or when it
+    /// is not a leaf and `retain_grad` has not been called.
+    pub fn grad_opt(&self) -> Option<Tensor> {
+        let grad = self.grad();
+        if grad.defined() {
+            Some(grad)
+        } else {
+            None
+        }
+    }
+
+    /// Keeps the gradient of this non-leaf tensor in the backward pass, by
+    /// default gradients are only accumulated for leaf tensors. This has no
+    /// effect on leaf tensors and returns an error on tensors that do not
+    /// require grad.
+    pub fn f_retain_grad(&self) -> Result<(), TchError> {
+        unsafe_torch_err!(at_retain_grad(self.c_tensor));
+        Ok(())
+    }
+
+    /// Keeps the gradient of this non-leaf tensor in the backward pass, see
+    /// `f_retain_grad`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_retain_grad"))]
+    pub fn retain_grad(&self) {
+        self.f_retain_grad().unwrap()
+    }
+
+    /// Replaces the gradient attached to this tensor, the gradient shares its
+    /// storage with `grad`.
+    pub fn f_set_grad(&mut self, grad: &Tensor) -> Result<(), TchError> {
+        unsafe_torch_err!(at_set_grad(self.c_tensor, grad.c_tensor));
+        Ok(())
+    }
+
+    /// Replaces the gradient attached to this tensor, see `f_set_grad`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_set_grad"))]
+    pub fn set_grad(&mut self, grad: &Tensor) {
+        self.f_set_grad(grad).unwrap()
+    }
+
     /// Returns the address of the first element of this tensor.
     pub fn data_ptr(&self) -> *mut c_void {
         unsafe_torch!(at_data_ptr(self.c_tensor))
     }
 
+    /// Returns true if both tensors are the same tensor, i.e. one of them is a
+    /// shallow clone of the other. Views and copies are different tensors.
+    pub fn f_is_same(&self, other: &Tensor) -> Result<bool, TchError> {
+        Ok(self.f_impl_id()? == other.f_impl_id()?)
+    }
+
+    /// Returns true if both tensors are the same tensor, see `f_is_same`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_is_same"))]
+    pub fn is_same(&self, other: &Tensor) -> bool {
+        self.f_is_same(other).unwrap()
+    }
+
+    // Identifies the tensor, the shallow clones of a tensor share the same id.
+    pub(crate) fn f_impl_id(&self) -> Result<usize, TchError> {
+        Ok(unsafe_torch_err!(at_tensor_impl(self.c_tensor)) as usize)
+    }
+
     /// Returns true if the tensor is defined.
     pub fn defined(&self) -> bool {
         unsafe_torch!(at_defined(self.c_tensor) != 0)
@@ -275,11 +385,18 @@ impl Tensor {
     }
 
     /// Zeroes the gradient tensor attached to this tensor if defined.
-    pub fn zero_grad(&mut self) {
-        let mut grad = self.grad();
+    pub fn f_zero_grad(&mut self) -> Result<(), TchError> {
+        let mut grad = self.f_grad()?;
         if grad.defined() {
-            let _ = grad.detach_().zero_();
+            let _ = grad.f_detach_()?.f_zero_()?;
         }
+        Ok(())
+    }
+
+    /// Zeroes the gradient tensor attached to this tensor if defined.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_zero_grad"))]
+    pub fn zero_grad(&mut self) {
+        self.f_zero_grad().unwrap()
     }
 
     /// Runs the backward pass, populating the gradient tensors for tensors
@@ -296,10 +413,68 @@ impl Tensor {
     ///
     /// Gradients tracking can be turned on via `set_requires_grad`.
     /// Panics if the C++ api returns an exception.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_backward"))]
     pub fn backward(&self) {
         self.f_backward().unwrap()
     }
 
+    /// Runs the backward pass with the given options, see `BackwardOptions`.
+    ///
+    /// ```no_run
+    /// # use tch::{autograd::BackwardOptions, Kind, Tensor};
+    /// # fn main() -> Result<(), tch::TchError> {
+    /// let x = Tensor::from_slice(&[1f32, 2.]).set_requires_grad(true);
+    /// let w = Tensor::from_slice(&[3f32, 4.]).set_requires_grad(true);
+    /// let y = (&x * &w).sum(Kind::Float);
+    /// // Only accumulate the gradient of x, keeping the graph for another pass.
+    /// y.backward_opt(BackwardOptions { retain_graph: true, inputs: Some(&[&x]), ..Default::default() })?;
+    /// y.backward_opt(Default::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn backward_opt(&self, options: BackwardOptions) -> Result<(), TchError> {
+        let inputs: Option<Vec<_>> =
+            options.inputs.map(|inputs| inputs.iter().map(|x| x.c_tensor).collect());
+        let (inputs_ptr, ninputs) = match &inputs {
+            None => (std::ptr::null(), 0),
+            Some(inputs) => (inputs.as_ptr(), inputs.len() as c_int),
+        };
+        unsafe_torch_err!(at_backward_opt(
+            self.c_tensor,
+            inputs_ptr,
+            ninputs,
+            options.retain_graph as c_int,
+            options.create_graph as c_int,
+        ));
+        Ok(())
+    }
+
+    /// Computes the vector-Jacobian product of this tensor with respect to
+    /// `inputs`, i.e. the gradients of `(self * cotangent).sum()` without
+    /// accumulating them in the inputs `grad` field.
+    ///
+    /// `cotangent` must have the same shape as this tensor. The graph is
+    /// retained so that multiple products can be computed, e.g. to assemble a
+    /// Jacobian row by row. The product is zero for inputs that are not used to
+    /// compute this tensor.
+    pub fn vjp(&self, cotangent: &Tensor, inputs: &[&Tensor]) -> Result<Vec<Tensor>, TchError> {
+        let (size, cotangent_size) = (self.f_size()?, cotangent.f_size()?);
+        if size != cotangent_size {
+            return Err(TchError::Shape(format!(
+                "vjp: cotangent has shape {cotangent_size:?}, expected {size:?}"
+            )));
+        }
+        let grads = super::autograd::grad(&[self], inputs, &[cotangent], true, false, true)?;
+        grads
+            .into_iter()
+            .zip(inputs.iter())
+            .map(|(grad, input)| match grad {
+                Some(grad) => Ok(grad),
+                None => input.f_zeros_like(),
+            })
+            .collect()
+    }
+
     pub fn f_run_backward<T1, T2>(
         tensors: &[T1],
         inputs: &[T2],
@@ -325,6 +500,7 @@ impl Tensor {
         Ok(outputs.into_iter().map(|c_tensor| Tensor { c_tensor }).collect())
     }
 
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_run_backward"))]
     pub fn run_backward<T1, T2>(
         tensors: &[T1],
         inputs: &[T2],
@@ -379,6 +555,10 @@ impl Tensor {
     /// presence of infinite values. `inv_scale` is a scalar containing
     /// the inverse scaling factor. This method is only available
     /// for CUDA tensors.
+    #[cfg_attr(
+        feature = "panic-free",
+        deprecated(note = "use f_internal_amp_non_finite_check_and_unscale")
+    )]
     pub fn internal_amp_non_finite_check_and_unscale(
         &mut self,
         found_inf: &mut Tensor,
@@ -388,6 +568,7 @@ impl Tensor {
     }
 
     /// Copies `numel` elements from `self` to `dst`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_copy_data_u8"))]
     pub fn copy_data_u8(&self, dst: &mut [u8], numel: usize) {
         self.f_copy_data_u8(dst, numel).unwrap()
     }
@@ -418,6 +599,7 @@ impl Tensor {
     }
 
     /// Copies `numel` elements from `self` to `dst`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_copy_data"))]
     pub fn copy_data<T: kind::Element>(&self, dst: &mut [T], numel: usize) {
         self.f_copy_data(dst, numel).unwrap()
     }
@@ -443,6 +625,7 @@ impl Tensor {
     }
 
     /// Converts a slice to a tensor.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_from_slice"))]
     pub fn from_slice<T: kind::Element>(data: &[T]) -> Tensor {
         Self::f_from_slice(data).unwrap()
     }
@@ -487,6 +670,32 @@ impl Tensor {
         Ok(Tensor { c_tensor })
     }
 
+    // Creates a tensor from data owned by `deleter_ctx`, `deleter` is called
+    // on `deleter_ctx` when the tensor storage is released.
+    pub(crate) unsafe fn f_from_blob_with_deleter(
+        data: *const u8,
+        size: &[i64],
+        strides: &[i64],
+        kind: Kind,
+        deleter: extern "C" fn(*mut c_void),
+        deleter_ctx: *mut c_void,
+    ) -> Result<Tensor, TchError> {
+        let data = data as *const c_void;
+        #[allow(unused_unsafe)]
+        let c_tensor = unsafe_torch_err!(at_tensor_of_blob_with_deleter(
+            data,
+            size.as_ptr(),
+            size.len(),
+            strides.as_ptr(),
+            strides.len(),
+            kind.c_int(),
+            Device::Cpu.c_int(),
+            deleter,
+            deleter_ctx
+        ));
+        Ok(Tensor { c_tensor })
+    }
+
     /// Creates a tensor from data that is assumed to be initialized.
     /// Resize operations are not allowed on this tensor without copying the data first.
     /// An empty strides slice will result in using the default strides.
@@ -503,6 +712,7 @@ impl Tensor {
     }
 
     /// Converts some byte data to a tensor with some specified kind and shape.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_from_data_size"))]
     pub fn from_data_size(data: &[u8], size: &[i64], kind: Kind) -> Tensor {
         Self::f_from_data_size(data, size, kind).unwrap()
     }
@@ -513,17 +723,107 @@ impl Tensor {
         Tensor { c_tensor }
     }
 
-    /// Gets the sub-tensor at the given index.
+    // Returns the shape of the tensor and the dimension, negative dimensions
+    // being counted from the end.
+    fn f_wrap_dim(&self, op: &str, dim: i64) -> Result<(Vec<i64>, i64), TchError> {
+        let shape = self.f_size()?;
+        let ndim = shape.len() as i64;
+        if ndim == 0 {
+            return Err(TchError::Shape(format!("{op} cannot be applied to a 0-dim tensor")));
+        }
+        if dim < -ndim || dim >= ndim {
+            return Err(TchError::Shape(format!(
+                "{op}: dimension {dim} out of range for a tensor with {ndim} dimensions \
+                 (tensor shape {shape:?})"
+            )));
+        }
+        Ok((shape, if dim < 0 { dim + ndim } else { dim }))
+    }
+
+    // Checks that the index is valid for the given dimension, negative indexes
+    // are counted from the end of the dimension so the valid range is
+    // [-size, size).
+    fn f_check_index(&self, op: &str, dim: i64, index: i64) -> Result<(), TchError> {
+        let (shape, dim) = self.f_wrap_dim(op, dim)?;
+        let size = shape[dim as usize];
+        if index < -size || index >= size {
+            return Err(TchError::IndexOutOfRange { dim, index, size, shape });
+        }
+        Ok(())
+    }
+
+    /// Gets the sub-tensor at the given index along the first dimension,
+    /// negative indexes are counted from the end.
     pub fn f_get(&self, index: i64) -> Result<Tensor, TchError> {
+        self.f_check_index("get", 0, index)?;
         let c_tensor = unsafe_torch_err!(at_get(self.c_tensor, index as c_int));
         Ok(Tensor { c_tensor })
     }
 
     /// Gets the sub-tensor at the given index.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_get"))]
     pub fn get(&self, index: i64) -> Tensor {
         self.f_get(index).unwrap()
     }
 
+    /// Selects the sub-tensor at the given index along dimension `dim`, the
+    /// dimension is removed from the result. Negative dimensions and indexes
+    /// are counted from the end.
+    pub fn f_select(&self, dim: i64, index: i64) -> Result<Tensor, TchError> {
+        self.f_check_index("select", dim, index)?;
+        let mut c_tensors = [std::ptr::null_mut(); 1];
+        unsafe_torch_err!(
+            "select",
+            [("self", self)],
+            c_generated::atg_select(c_tensors.as_mut_ptr(), self.c_tensor, dim, index)
+        );
+        Ok(Tensor { c_tensor: c_tensors[0] })
+    }
+
+    /// Selects the sub-tensor at the given index along dimension `dim`, see
+    /// `f_select`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_select"))]
+    pub fn select(&self, dim: i64, index: i64) -> Tensor {
+        self.f_select(dim, index).unwrap()
+    }
+
+    /// Returns a view on the `length` elements starting at `start` along
+    /// dimension `dim`. A negative `start` is counted from the end of the
+    /// dimension, the valid range is [-size, size].
+    pub fn f_narrow(&self, dim: i64, start: i64, length: i64) -> Result<Tensor, TchError> {
+        let (shape, wrapped_dim) = self.f_wrap_dim("narrow", dim)?;
+        let size = shape[wrapped_dim as usize];
+        if length < 0 {
+            return Err(TchError::Shape(format!(
+                "narrow: length must be non-negative, got {length}"
+            )));
+        }
+        if start < -size || start > size {
+            return Err(TchError::IndexOutOfRange { dim: wrapped_dim, index: start, size, shape });
+        }
+        let wrapped_start = if start < 0 { start + size } else { start };
+        if wrapped_start > size - length {
+            return Err(TchError::Shape(format!(
+                "narrow: start {start} + length {length} exceeds size {size} of dimension \
+                 {wrapped_dim} (tensor shape {shape:?})"
+            )));
+        }
+        let mut c_tensors = [std::ptr::null_mut(); 1];
+        unsafe_torch_err!(
+            "narrow",
+            [("self", self)],
+            c_generated::atg_narrow(c_tensors.as_mut_ptr(), self.c_tensor, dim, start, length)
+        );
+        Ok(Tensor { c_tensor: c_tensors[0] })
+    }
+
+    /// Returns a view on the `length` elements starting at `start` along
+    /// dimension `dim`, see `f_narrow`.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_narrow"))]
+    pub fn narrow(&self, dim: i64, start: i64, length: i64) -> Tensor {
+        self.f_narrow(dim, start, length).unwrap()
+    }
+
     /// Copies values from the argument tensor to the input tensor.
     pub fn f_copy_(&mut self, src: &Tensor) -> Result<(), TchError> {
         unsafe_torch_err!(at_copy_(self.c_tensor, src.c_tensor));
@@ -531,6 +831,7 @@ impl Tensor {
     }
 
     /// Copies values from the argument tensor to the input tensor.
+    #[cfg_attr(feature = "panic-free", deprecated(note = "use f_copy_"))]
     pub fn copy_(&mut self, src: &Tensor) {
         self.f_copy_(src).unwrap()
     }
@@ -755,7 +1056,7 @@ impl Default for Tensor {
 
 impl Drop for Tensor {
     fn drop(&mut self) {
-        unsafe_torch!(at_free(self.c_tensor))
+        unsafe_torch_free!(at_free(self.c_tensor))
     }
 }
 
@@ -771,51 +1072,138 @@ fn autocast_increment_nesting() -> isize {
     unsafe_torch!(at_autocast_increment_nesting() as isize)
 }
 
-fn autocast_is_enabled() -> bool {
-    unsafe_torch!(at_autocast_is_enabled() != 0)
+fn autocast_set_enabled(cpu: bool, b: bool) -> bool {
+    if cpu {
+        unsafe_torch!(at_autocast_set_cpu_enabled(i32::from(b)) != 0)
+    } else {
+        unsafe_torch!(at_autocast_set_enabled(i32::from(b)) != 0)
+    }
 }
 
-fn autocast_set_enabled(b: bool) -> bool {
-    unsafe_torch!(at_autocast_set_enabled(i32::from(b)) != 0)
+fn autocast_set_kind(cpu: bool, kind: Kind) -> Kind {
+    let prev = unsafe_torch!(at_autocast_set_dtype(i32::from(cpu), kind.c_int()));
+    Kind::from_c_int(prev).unwrap()
 }
 
-/// Runs a closure in mixed precision.
-pub fn autocast<T, F>(enabled: bool, f: F) -> T
-where
-    F: FnOnce() -> T,
-{
-    if !Cuda::is_available() {
-        return f();
-    }
+/// A RAII guard that enables or disables mixed precision until deallocated.
+#[derive(Debug)]
+pub struct AutocastGuard {
+    // None when autocast was requested on CUDA but CUDA is not available.
+    prev: Option<(bool, bool, Kind)>,
+}
 
-    // Check whether we are using CUDA.
-    let prev = autocast_is_enabled();
-    autocast_set_enabled(enabled);
+/// Enables or disables mixed precision for the operations running on the given
+/// device type, the previous state is restored when the returned guard gets
+/// deallocated.
+///
+/// Within an enabled scope, the operations that support it run using `kind`,
+/// e.g. matrix multiplications, whereas precision sensitive operations such as
+/// softmax keep on using single precision, this follows the PyTorch casting
+/// policy. Scopes can be nested, an inner scope can disable mixed precision.
+/// The supported kinds are `Half` and `BFloat16` on CUDA devices, and only
+/// `BFloat16` on the CPU. When CUDA is not available, requesting mixed precision
+/// on a CUDA device has no effect.
+/// As for `no_grad_guard`, this should be bound to a name like `_guard`.
+pub fn f_autocast_guard(
+    device: Device,
+    kind: Kind,
+    enabled: bool,
+) -> Result<AutocastGuard, TchError> {
+    let cpu = match (device, kind) {
+        (Device::Cpu, Kind::BFloat16) => true,
+        (Device::Cuda(_), Kind::Half | Kind::BFloat16) => false,
+        (Device::Cpu | Device::Cuda(_), _) => {
+            return Err(TchError::Kind(format!("unsupported autocast kind {kind:?} on {device:?}")))
+        }
+        _ => return Err(TchError::Torch(format!("autocast is not supported on {device:?}"))),
+    };
+    if !cpu && !Cuda::is_available() {
+        return Ok(AutocastGuard { prev: None });
+    }
+    let prev_enabled = autocast_set_enabled(cpu, enabled);
+    let prev_kind = autocast_set_kind(cpu, kind);
     autocast_increment_nesting();
+    Ok(AutocastGuard { prev: Some((cpu, prev_enabled, prev_kind)) })
+}
 
-    let result = f();
+/// Enables or disables mixed precision until the returned guard gets deallocated,
+/// see `f_autocast_guard`. Panics on unsupported devices or kinds.
+pub fn autocast_guard(device: Device, kind: Kind, enabled: bool) -> AutocastGuard {
+    f_autocast_guard(device, kind, enabled).unwrap()
+}
 
-    if autocast_decrement_nesting() == 0 {
-        autocast_clear_cache();
+impl Drop for AutocastGuard {
+    fn drop(&mut self) {
+        if let Some((cpu, prev_enabled, prev_kind)) = self.prev {
+            if autocast_decrement_nesting() == 0 {
+                autocast_clear_cache();
+            }
+            autocast_set_enabled(cpu, prev_enabled);
+            autocast_set_kind(cpu, prev_kind);
+        }
     }
-    autocast_set_enabled(prev);
+}
 
-    result
+/// Runs a closure in mixed precision, see `f_autocast_guard` for details.
+///
+/// ```no_run
+/// # use tch::{autocast, Device, Kind, Tensor};
+/// let xs = Tensor::randn([8, 8], (Kind::Float, Device::Cpu));
+/// let ys = autocast(Device::Cpu, Kind::BFloat16, true, || xs.matmul(&xs));
+/// assert_eq!(ys.kind(), Kind::BFloat16);
+/// ```
+pub fn autocast<T, F>(device: Device, kind: Kind, enabled: bool, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let _guard = autocast_guard(device, kind, enabled);
+    f()
 }
 
 fn grad_set_enabled(b: bool) -> bool {
     unsafe_torch!(at_grad_set_enabled(i32::from(b)) != 0)
 }
 
+/// Returns true if gradient tracking is enabled on the current thread.
+pub fn is_grad_enabled() -> bool {
+    unsafe_torch!(at_grad_is_enabled() != 0)
+}
+
+/// A RAII guard that enables or disables gradient tracking until deallocated.
+///
+/// The previous mode is restored when the guard gets deallocated, including when
+/// unwinding from a panic, so guards can be nested freely as long as they are
+/// deallocated in the reverse order of their creation. Gradient tracking is a
+/// per-thread setting.
+#[derive(Debug)]
+pub struct GradGuard {
+    prev: bool,
+}
+
+impl GradGuard {
+    /// Enables or disables gradient tracking.
+    /// Note that it is important to bind the guard to a name like `_guard`
+    /// and not to `_` as the latter would immediately drop it.
+    /// See <https://internals.rust-lang.org/t/pre-rfc-must-bind/12658/46>
+    /// for more details.
+    pub fn new(enabled: bool) -> GradGuard {
+        GradGuard { prev: grad_set_enabled(enabled) }
+    }
+}
+
+impl Drop for GradGuard {
+    fn drop(&mut self) {
+        let _enabled = grad_set_enabled(self.prev);
+    }
+}
+
 /// Runs a closure without keeping track of gradients.
 pub fn no_grad<T, F>(f: F) -> T
 where
     F: FnOnce() -> T,
 {
-    let prev = grad_set_enabled(false);
-    let result = f();
-    let _false = grad_set_enabled(prev);
-    result
+    let _guard = GradGuard::new(false);
+    f()
 }
 
 /// Runs a closure explicitly keeping track of gradients, this could be
@@ -824,36 +1212,81 @@ pub fn with_grad<T, F>(f: F) -> T
 where
     F: FnOnce() -> T,
 {
-    let prev = grad_set_enabled(true);
-    let result = f();
-    let _false = grad_set_enabled(prev);
-    result
+    let _guard = GradGuard::new(true);
+    f()
 }
 
 /// A RAII guard that prevents gradient tracking until deallocated.
-pub struct NoGradGuard {
-    enabled: bool,
-}
+pub type NoGradGuard = GradGuard;
 
 /// Disables gradient tracking, this will be enabled back when the
-/// returned value gets deallocated.
-/// Note that it is important to bind this to a name like `_guard`
-/// and not to `_` as the latter would immediately drop the guard.
-/// See <https://internals.rust-lang.org/t/pre-rfc-must-bind/12658/46>
-/// for more details.
+/// returned value gets deallocated, see `GradGuard`.
 pub fn no_grad_guard() -> NoGradGuard {
-    NoGradGuard { enabled: grad_set_enabled(false) }
+    GradGuard::new(false)
 }
 
-impl std::convert::AsRef<Tensor> for Tensor {
-    fn as_ref(&self) -> &Self {
-        self
+/// A RAII guard that enables or disables the inference mode until deallocated.
+///
+/// The inference mode is a stricter version of `no_grad` that also skips the
+/// view tracking and version counter bookkeeping, making it faster for pure
+/// inference. Tensors created within an inference mode scope are inference
+/// tensors, they come with the following restrictions:
+/// - they cannot be used in operations recorded by autograd, e.g. combined
+///   with a tensor that requires grad, after the scope has ended,
+/// - they cannot be modified in place outside of inference mode,
+/// - `set_requires_grad(true)` cannot be called on them outside of inference
+///   mode.
+///
+/// Violating these restrictions results in a `TchError::InferenceTensor` error.
+/// `Tensor::copy`, when run outside of inference mode, returns a normal tensor
+/// that can be used freely. The guard applies to the current thread only and
+/// guards have to be deallocated in the reverse order of their creation.
+#[derive(Debug)]
+pub struct InferenceModeGuard {
+    c_guard: *mut torch_sys::C_inference_mode,
+}
+
+impl InferenceModeGuard {
+    /// Enables or disables the inference mode, the previous mode is restored
+    /// when the guard gets deallocated.
+    pub fn new(enabled: bool) -> InferenceModeGuard {
+        let c_guard = unsafe_torch!(torch_sys::at_inference_mode_enter(i32::from(enabled)));
+        InferenceModeGuard { c_guard }
+    }
+
+    /// Returns true if the inference mode is enabled on the current thread.
+    pub fn is_enabled() -> bool {
+        unsafe_torch!(torch_sys::at_inference_mode_is_enabled()) != 0
     }
 }
 
-impl Drop for NoGradGuard {
+impl Drop for InferenceModeGuard {
     fn drop(&mut self) {
-        let _enabled = grad_set_enabled(self.enabled);
+        unsafe_torch_free!(torch_sys::at_inference_mode_exit(self.c_guard))
+    }
+}
+
+/// Runs a closure in inference mode, see `InferenceModeGuard` for the
+/// restrictions that apply to the tensors created within the closure.
+///
+/// ```no_run
+/// # use tch::{nn, nn::Module, Device, Tensor};
+/// let vs = nn::VarStore::new(Device::Cpu);
+/// let linear = nn::linear(vs.root(), 4, 2, Default::default());
+/// let ys = tch::inference_mode(|| linear.forward(&Tensor::ones([1, 4], tch::kind::FLOAT_CPU)));
+/// assert!(ys.is_inference());
+/// ```
+pub fn inference_mode<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let _guard = InferenceModeGuard::new(true);
+    f()
+}
+
+impl std::convert::AsRef<Tensor> for Tensor {
+    fn as_ref(&self) -> &Self {
+        self
     }
 }
 
@@ -12971,10 +12971,6 @@ impl Tensor {
         self.f_nansum_out(out, dim, keepdim, dtype).unwrap()
     }
 
-    pub fn narrow(&self, dim: i64, start: i64, length: i64) -> Tensor {
-        self.f_narrow(dim, start, length).unwrap()
-    }
-
     pub fn narrow_copy(&self, dim: i64, start: i64, length: i64) -> Tensor {
         self.f_narrow_copy(dim, start, length).unwrap()
     }
@@ -15077,10 +15073,6 @@ impl Tensor {
         .unwrap()
     }
 
-    pub fn select(&self, dim: i64, index: i64) -> Tensor {
-        self.f_select(dim, index).unwrap()
-    }
-
     pub fn select_backward(
         grad_output: &Tensor,
         input_sizes: impl IntList,
@@ -15140,10 +15132,6 @@ impl Tensor {
         self.f_set_out(out).unwrap()
     }
 
-    pub fn set_requires_grad(&self, r: bool) -> Tensor {
-        self.f_set_requires_grad(r).unwrap()
-    }
-
     pub fn set_source_tensor(&self, source: &Tensor) -> Tensor {
         self.f_set_source_tensor(source).unwrap()
     }
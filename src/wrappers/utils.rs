@@ -1,7 +1,16 @@
-use crate::TchError;
+use crate::{TchError, Tensor, TensorMeta};
 use libc::c_char;
+use std::borrow::Borrow;
 use std::io;
 
+pub use super::checkpoint::{
+    checkpoint, checkpoint_multi, checkpoint_sequential, f_checkpoint, f_checkpoint_multi,
+    f_checkpoint_sequential,
+};
+pub use super::windows::{
+    f_sliding_windows, f_stitch, sliding_windows, stitch, Overlap, SlidingWindows,
+};
+
 // This returns None on the null pointer. If not null, the pointer gets
 // freed.
 pub(super) unsafe fn ptr_to_string(ptr: *mut c_char) -> Option<String> {
@@ -14,11 +23,69 @@ pub(super) unsafe fn ptr_to_string(ptr: *mut c_char) -> Option<String> {
     }
 }
 
+// The errors raised by libtorch when an inference tensor escapes from an
+// inference mode scope, e.g. "Inference tensors cannot be saved for backward".
+fn is_inference_tensor_error(c_error: &str) -> bool {
+    c_error.contains("Inference tensors cannot be saved for backward")
+        || c_error.contains("Inplace update to inference tensor outside InferenceMode")
+        || c_error.contains("Setting requires_grad=True on inference tensor outside InferenceMode")
+}
+
+// Parses a size formatted by the CUDA allocator, e.g. "20.00 GiB".
+fn parse_size(value: &str, unit: &str) -> Option<u64> {
+    let value: f64 = value.parse().ok()?;
+    let scale = match unit.trim_end_matches(|c: char| !c.is_ascii_alphabetic()) {
+        "B" => 1u64,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+    Some((value * scale as f64) as u64)
+}
+
+fn size_after(c_error: &str, marker: &str) -> Option<u64> {
+    let index = c_error.find(marker)?;
+    let mut tokens = c_error[index + marker.len()..].split_whitespace();
+    parse_size(tokens.next()?, tokens.next()?)
+}
+
+fn size_before(c_error: &str, marker: &str) -> Option<u64> {
+    let index = c_error.find(marker)?;
+    let mut tokens = c_error[..index].split_whitespace().rev();
+    let unit = tokens.next()?;
+    parse_size(tokens.next()?, unit)
+}
+
+// The CUDA allocator error messages look like the following, depending on the
+// libtorch version:
+// "CUDA out of memory. Tried to allocate 20.00 GiB (GPU 0; 15.78 GiB total
+//  capacity; 1.23 GiB already allocated; 13.50 GiB free; ...)"
+// "CUDA out of memory. Tried to allocate 20.00 GiB. GPU 0 has a total capacity
+//  of 15.78 GiB of which 13.50 GiB is free. ..."
+fn cuda_out_of_memory_error(c_error: &str) -> Option<TchError> {
+    if !c_error.contains("CUDA out of memory") {
+        return None;
+    }
+    let requested = size_after(c_error, "Tried to allocate ")?;
+    let total = size_before(c_error, " total capacity")
+        .or_else(|| size_after(c_error, "total capacity of "))?;
+    let free = size_before(c_error, " free;").or_else(|| size_after(c_error, " of which "))?;
+    Some(TchError::CudaOutOfMemory { requested, free, total, message: c_error.to_string() })
+}
+
 pub(super) fn read_and_clean_error() -> Result<(), TchError> {
     unsafe {
         match ptr_to_string(torch_sys::get_and_reset_last_err()) {
             None => Ok(()),
-            Some(c_error) => Err(TchError::Torch(c_error)),
+            Some(c_error) if is_inference_tensor_error(&c_error) => {
+                Err(TchError::InferenceTensor(c_error))
+            }
+            Some(c_error) => match cuda_out_of_memory_error(&c_error) {
+                Some(err) => Err(err),
+                None => Err(TchError::Torch(c_error)),
+            },
         }
     }
 }
@@ -31,7 +98,33 @@ macro_rules! unsafe_torch {
     }};
 }
 
+// Used when releasing objects in destructors: panicking there would abort the
+// process if already unwinding, so errors are logged and the object is leaked.
+macro_rules! unsafe_torch_free {
+    ($e:expr) => {{
+        unsafe { $e };
+        if let Err(err) = crate::wrappers::utils::read_and_clean_error() {
+            log::error!(target: "tch", "{} failed, leaking the object: {err}", stringify!($e))
+        }
+    }};
+}
+
+// The generated functions also pass the operation name and their tensor
+// arguments, the metadata of these tensors is only collected when the call
+// fails and attached to the returned error.
 macro_rules! unsafe_torch_err {
+    ($op:literal, [$(($name:literal, $t:expr)),* $(,)?], $e:expr) => {{
+        let v = unsafe { $e };
+        if let Err(err) = crate::wrappers::utils::read_and_clean_error() {
+            #[allow(unused_imports)]
+            use crate::wrappers::utils::TensorArg;
+            #[allow(unused_mut)]
+            let mut inputs = vec![];
+            $($t.push_meta($name, &mut inputs);)*
+            return Err(err.op_context($op, inputs));
+        }
+        v
+    }};
     ($e:expr) => {{
         let v = unsafe { $e };
         crate::wrappers::utils::read_and_clean_error()?;
@@ -39,9 +132,49 @@ macro_rules! unsafe_torch_err {
     }};
 }
 
+// The tensor arguments of the generated functions, possibly optional or lists.
+pub(crate) trait TensorArg {
+    fn push_meta(&self, name: &'static str, inputs: &mut Vec<TensorMeta>);
+}
+
+impl TensorArg for Tensor {
+    fn push_meta(&self, name: &'static str, inputs: &mut Vec<TensorMeta>) {
+        // Undefined tensors, e.g. some optional arguments, are skipped.
+        if let Some(meta) = TensorMeta::from_tensor(name, self) {
+            inputs.push(meta)
+        }
+    }
+}
+
+impl<T: Borrow<Tensor>> TensorArg for Option<T> {
+    fn push_meta(&self, name: &'static str, inputs: &mut Vec<TensorMeta>) {
+        if let Some(t) = self {
+            t.borrow().push_meta(name, inputs)
+        }
+    }
+}
+
+impl<T: Borrow<Tensor>> TensorArg for [T] {
+    fn push_meta(&self, name: &'static str, inputs: &mut Vec<TensorMeta>) {
+        for t in self.iter() {
+            t.borrow().push_meta(name, inputs)
+        }
+    }
+}
+
+impl<T: Borrow<Tensor>> TensorArg for [Option<T>] {
+    fn push_meta(&self, name: &'static str, inputs: &mut Vec<TensorMeta>) {
+        for t in self.iter() {
+            t.push_meta(name, inputs)
+        }
+    }
+}
+
 // Be cautious when using this function as the returned CString should be stored
 // in a variable when using as_ptr. Otherwise dangling pointer issues are likely
 // to happen.
+// Paths are passed to the C++ side as UTF-8, they are converted to wide
+// strings there on Windows.
 pub(super) fn path_to_cstring<T: AsRef<std::path::Path>>(
     path: T,
 ) -> Result<std::ffi::CString, TchError> {
@@ -55,9 +188,138 @@ pub(super) fn path_to_cstring<T: AsRef<std::path::Path>>(
     }
 }
 
+/// The path as a string for error messages, paths that are not valid unicode
+/// use their debug representation so that they are not altered.
+pub(crate) fn path_to_string(path: &std::path::Path) -> String {
+    match path.to_str() {
+        Some(path) => path.to_string(),
+        None => format!("{path:?}"),
+    }
+}
+
+/// Adds the path to the message of an I/O error.
+pub(crate) fn path_io_error(path: &std::path::Path, err: io::Error) -> TchError {
+    TchError::Io(io::Error::new(err.kind(), format!("{}: {err}", path_to_string(path))))
+}
+
+// The last seed set via manual_seed or manual_seed_all.
+static SEED: std::sync::Mutex<Option<i64>> = std::sync::Mutex::new(None);
+
 /// Sets the random seed used by torch.
 pub fn manual_seed(seed: i64) {
-    unsafe_torch!(torch_sys::at_manual_seed(seed))
+    unsafe_torch!(torch_sys::at_manual_seed(seed));
+    *SEED.lock().unwrap() = Some(seed)
+}
+
+/// Sets the random seed for the CPU and for all the CUDA devices.
+///
+/// The seed can be retrieved with `initial_seed`.
+pub fn manual_seed_all(seed: i64) {
+    manual_seed(seed);
+    if crate::Cuda::is_available() {
+        crate::Cuda::manual_seed_all(seed as u64)
+    }
+}
+
+/// Returns the last seed set via `manual_seed` or `manual_seed_all` if any.
+pub fn initial_seed() -> Option<i64> {
+    *SEED.lock().unwrap()
+}
+
+// The cuBLAS workspace configurations for which cuBLAS is deterministic.
+const CUBLAS_WORKSPACE_CONFIG: &str = "CUBLAS_WORKSPACE_CONFIG";
+const DETERMINISTIC_CUBLAS_CONFIGS: [&str; 2] = [":4096:8", ":16:8"];
+
+/// Restricts torch to deterministic algorithms.
+///
+/// When enabled, operations that do not have a deterministic implementation
+/// return an error mentioning the operation name, or only emit a warning when
+/// `warn_only` is set. Enabling this mode also makes CuDNN deterministic, turns
+/// off the CuDNN benchmark mode, and sets the `CUBLAS_WORKSPACE_CONFIG`
+/// environment variable if it is not set already. Note that the latter only has
+/// an effect if CUDA has not been used yet. Use `deterministic_report` to check
+/// for the remaining sources of non-determinism.
+pub fn set_deterministic(mode: bool, warn_only: bool) {
+    unsafe_torch!(torch_sys::at_set_deterministic_algorithms(
+        i32::from(mode),
+        i32::from(warn_only)
+    ));
+    crate::backends::cudnn::set_deterministic(mode);
+    if mode {
+        crate::backends::cudnn::set_benchmark(false);
+        if std::env::var_os(CUBLAS_WORKSPACE_CONFIG).is_none() {
+            std::env::set_var(CUBLAS_WORKSPACE_CONFIG, DETERMINISTIC_CUBLAS_CONFIGS[0])
+        }
+    }
+}
+
+/// The state of the flags that impact determinism.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeterminismReport {
+    pub deterministic_algorithms: bool,
+    pub warn_only: bool,
+    pub cudnn_deterministic: bool,
+    pub cudnn_benchmark: bool,
+    pub cublas_workspace_config: Option<String>,
+    pub seed: Option<i64>,
+    pub num_threads: i32,
+    /// The known sources of non-determinism that remain with these settings.
+    pub issues: Vec<String>,
+}
+
+impl DeterminismReport {
+    /// Returns true if no known source of non-determinism remains.
+    pub fn is_deterministic(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Returns the current determinism related settings.
+pub fn deterministic_report() -> DeterminismReport {
+    let deterministic_algorithms = unsafe_torch!(torch_sys::at_deterministic_algorithms()) != 0;
+    let warn_only = unsafe_torch!(torch_sys::at_deterministic_algorithms_warn_only()) != 0;
+    let cudnn_deterministic = crate::backends::cudnn::deterministic();
+    let cudnn_benchmark = crate::backends::cudnn::benchmark();
+    let cublas_workspace_config = std::env::var(CUBLAS_WORKSPACE_CONFIG).ok();
+    let seed = initial_seed();
+    let mut issues = vec![];
+    if !deterministic_algorithms {
+        issues.push("deterministic algorithms are not enforced".to_string())
+    } else if warn_only {
+        issues.push("non-deterministic algorithms only trigger warnings".to_string())
+    }
+    if seed.is_none() {
+        issues.push("no random seed has been set".to_string())
+    }
+    if crate::Cuda::is_available() {
+        if !cudnn_deterministic {
+            issues.push("cudnn is not restricted to deterministic algorithms".to_string())
+        }
+        if cudnn_benchmark {
+            issues.push("cudnn benchmark mode is enabled".to_string())
+        }
+        let cublas_ok = matches!(
+            cublas_workspace_config.as_deref(),
+            Some(c) if DETERMINISTIC_CUBLAS_CONFIGS.contains(&c)
+        );
+        if !cublas_ok {
+            issues.push(format!(
+                "{CUBLAS_WORKSPACE_CONFIG} should be set to {} or {}",
+                DETERMINISTIC_CUBLAS_CONFIGS[0], DETERMINISTIC_CUBLAS_CONFIGS[1]
+            ))
+        }
+    }
+    DeterminismReport {
+        deterministic_algorithms,
+        warn_only,
+        cudnn_deterministic,
+        cudnn_benchmark,
+        cublas_workspace_config,
+        seed,
+        num_threads: get_num_threads(),
+        issues,
+    }
 }
 
 /// Get the number of threads used by torch for inter-op parallelism.
@@ -70,14 +332,42 @@ pub fn get_num_threads() -> i32 {
     unsafe_torch!(torch_sys::at_get_num_threads())
 }
 
-/// Set the number of threads used by torch for inter-op parallelism.
+/// Set the number of threads used by torch for inter-op parallelism, i.e. to
+/// run independent operations concurrently, e.g. in TorchScript models.
+///
+/// This can only be called once and before any inter-op parallel work has
+/// started, an error is returned otherwise.
+pub fn f_set_num_interop_threads(n_threads: i32) -> Result<(), TchError> {
+    unsafe_torch_err!(torch_sys::at_set_num_interop_threads(n_threads));
+    Ok(())
+}
+
+/// Set the number of threads used by torch for inter-op parallelism, see
+/// `f_set_num_interop_threads`. Panics if inter-op parallel work has already
+/// started.
 pub fn set_num_interop_threads(n_threads: i32) {
-    unsafe_torch!(torch_sys::at_set_num_interop_threads(n_threads))
+    f_set_num_interop_threads(n_threads).unwrap()
+}
+
+/// Set the number of threads used by torch in parallel regions, i.e. to split
+/// the work of a single operation such as a matrix multiplication.
+///
+/// By default, libtorch uses one thread per physical core which may hurt the
+/// latency of other services running on the same machine. The default value
+/// can also be set via the `OMP_NUM_THREADS` and `MKL_NUM_THREADS` environment
+/// variables, these have to be set before the process starts as they are read
+/// by the OpenMP runtime (gomp on Linux) when it gets initialized. Calling
+/// this function takes precedence over the environment variables.
+/// An error is returned if `n_threads` is not positive.
+pub fn f_set_num_threads(n_threads: i32) -> Result<(), TchError> {
+    unsafe_torch_err!(torch_sys::at_set_num_threads(n_threads));
+    Ok(())
 }
 
-/// Set the number of threads used by torch in parallel regions.
+/// Set the number of threads used by torch in parallel regions, see
+/// `f_set_num_threads`. Panics if `n_threads` is not positive.
 pub fn set_num_threads(n_threads: i32) {
-    unsafe_torch!(torch_sys::at_set_num_threads(n_threads))
+    f_set_num_threads(n_threads).unwrap()
 }
 
 pub fn has_openmp() -> bool {
@@ -130,6 +420,12 @@ pub fn version_cudart() -> i64 {
     unsafe_torch!(torch_sys::at_context_version_cudart())
 }
 
+/// The version of the libtorch library in use, e.g. "2.0.0".
+pub fn version_torch() -> Result<String, TchError> {
+    let version = unsafe_torch_err!(ptr_to_string(torch_sys::at_torch_version()));
+    version.ok_or_else(|| TchError::Torch("unable to get the libtorch version".to_string()))
+}
+
 /// Check whether the vulkan backend is available. None that this
 /// backend is not included by default as of PyTorch 2.0.0.
 /// https://pytorch.org/tutorials/prototype/vulkan_workflow.html#building-pytorch-with-vulkan-backend
@@ -159,3 +455,30 @@ impl QEngine {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{cuda_out_of_memory_error, TchError};
+
+    #[test]
+    fn cuda_out_of_memory() {
+        const GIB: f64 = (1u64 << 30) as f64;
+        let messages = [
+            "CUDA out of memory. Tried to allocate 20.00 GiB (GPU 0; 15.78 GiB total capacity; \
+             1.23 GiB already allocated; 13.50 GiB free; 1.25 GiB reserved in total by PyTorch)",
+            "CUDA out of memory. Tried to allocate 20.00 GiB. GPU 0 has a total capacity of \
+             15.78 GiB of which 13.50 GiB is free. Of the allocated memory 1.23 GiB is allocated.",
+        ];
+        for message in messages {
+            match cuda_out_of_memory_error(message) {
+                Some(TchError::CudaOutOfMemory { requested, free, total, .. }) => {
+                    assert_eq!(requested, 20 << 30);
+                    assert_eq!(free, (13.5 * GIB) as u64);
+                    assert_eq!(total, (15.78 * GIB) as u64);
+                }
+                err => panic!("unexpected error {err:?}"),
+            }
+        }
+        assert!(cuda_out_of_memory_error("DefaultCPUAllocator: not enough memory").is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Versions of the libraries in use and build configuration, e.g. to include
+//! in bug reports.
+//!
+//! ```no_run
+//! println!("{}", tch::build_info());
+//! ```
+use super::utils::ptr_to_string;
+use crate::TchError;
+
+/// The version of the libtorch library in use, e.g. "2.0.0".
+pub fn libtorch() -> String {
+    crate::utils::version_torch().unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// The build configuration of libtorch, as reported by `torch.__config__.show()`
+/// in Python.
+pub fn libtorch_config() -> Result<String, TchError> {
+    let config = unsafe_torch_err!(ptr_to_string(torch_sys::at_show_config()));
+    config.ok_or_else(|| TchError::Torch("unable to get the libtorch config".to_string()))
+}
+
+/// The version of the CUDA runtime libtorch was built against, e.g. "11.8",
+/// or `None` when CUDA is not available.
+pub fn cuda_runtime_version() -> Option<String> {
+    if !crate::Cuda::is_available() {
+        return None;
+    }
+    let version = || -> Result<i64, TchError> {
+        Ok(unsafe_torch_err!(torch_sys::at_context_version_cudart()))
+    };
+    // The version is encoded as 1000 * major + 10 * minor.
+    let version = version().ok().filter(|&v| v > 0)?;
+    Some(format!("{}.{}", version / 1000, version % 1000 / 10))
+}
+
+/// The version of cuDNN, e.g. "8.7.0", or `None` when cuDNN is not
+/// available.
+pub fn cudnn_version() -> Option<String> {
+    let version = crate::backends::cudnn::version().filter(|&v| v > 0)?;
+    // The encoding changed from 1000 * major + 100 * minor + patch to
+    // 10000 * major + 100 * minor + patch with cuDNN 9.
+    let version = if version >= 90000 {
+        format!("{}.{}.{}", version / 10000, version % 10000 / 100, version % 100)
+    } else {
+        format!("{}.{}.{}", version / 1000, version % 1000 / 100, version % 100)
+    };
+    Some(version)
+}
+
+/// The git commit the bindings were built from, only available when building
+/// from a git checkout of the repository.
+pub fn git_commit_of_bindings() -> Option<&'static str> {
+    option_env!("TCH_GIT_COMMIT")
+}
+
+/// Returns true if the bindings were built against a CUDA version of
+/// libtorch, regardless of whether a CUDA device is available at runtime.
+pub fn built_with_cuda() -> bool {
+    unsafe_torch!(torch_sys::cuda::atc_built_with_cuda()) != 0
+}
+
+// The cargo features of the crate, only the ones that are relevant in bug
+// reports are listed.
+fn features() -> Vec<String> {
+    let features = [
+        ("download-libtorch", cfg!(feature = "download-libtorch")),
+        ("python-extension", cfg!(feature = "python-extension")),
+        ("distributed", cfg!(feature = "distributed")),
+        ("panic-free", cfg!(feature = "panic-free")),
+        ("serde", cfg!(feature = "serde")),
+        ("tracing", cfg!(feature = "tracing")),
+    ];
+    features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect()
+}
+
+/// The versions of the libraries in use along with the build configuration of
+/// the bindings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuildInfo {
+    /// The version of the tch crate.
+    pub tch: String,
+    /// The version of the libtorch library in use.
+    pub libtorch: String,
+    /// The commit of the bindings, only set when they were built from a git
+    /// checkout of the repository.
+    pub git_commit: Option<String>,
+    /// The version of the CUDA runtime libtorch was built against, see
+    /// `cuda_runtime_version`.
+    pub cuda_runtime: Option<String>,
+    /// The version of cuDNN, see `cudnn_version`.
+    pub cudnn: Option<String>,
+    /// Whether the bindings were built against a CUDA version of libtorch.
+    pub built_with_cuda: bool,
+    /// The number of CUDA devices available.
+    pub cuda_device_count: i64,
+    /// Whether the MPS device is available.
+    pub mps_available: bool,
+    /// The enabled cargo features of the tch crate.
+    pub features: Vec<String>,
+}
+
+/// Collects the versions of the libraries in use and the build configuration.
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        tch: env!("CARGO_PKG_VERSION").to_string(),
+        libtorch: libtorch(),
+        git_commit: git_commit_of_bindings().map(|c| c.to_string()),
+        cuda_runtime: cuda_runtime_version(),
+        cudnn: cudnn_version(),
+        built_with_cuda: built_with_cuda(),
+        cuda_device_count: crate::Cuda::device_count(),
+        mps_available: crate::utils::has_mps(),
+        features: features(),
+    }
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let or_none = |v: &Option<String>| v.clone().unwrap_or_else(|| "none".to_string());
+        writeln!(f, "tch: {}", self.tch)?;
+        writeln!(f, "libtorch: {}", self.libtorch)?;
+        writeln!(f, "git commit: {}", or_none(&self.git_commit))?;
+        writeln!(f, "cuda runtime: {}", or_none(&self.cuda_runtime))?;
+        writeln!(f, "cudnn: {}", or_none(&self.cudnn))?;
+        writeln!(f, "built with cuda: {}", self.built_with_cuda)?;
+        writeln!(f, "cuda devices: {}", self.cuda_device_count)?;
+        writeln!(f, "mps available: {}", self.mps_available)?;
+        write!(f, "features: {}", self.features.join(", "))
+    }
+}
@@ -0,0 +1,170 @@
+//! Handling of the warnings emitted by libtorch.
+//!
+//! By default libtorch prints its warnings, e.g. about deprecated operations or
+//! nondeterministic algorithms, on stderr. Once the handler from this module is
+//! installed, the warnings are forwarded to the `log` crate using the `tch`
+//! target, to `tracing` when the `tracing` feature is enabled, or to a custom
+//! handler set with `set_handler`.
+//!
+//! Warning handlers are per thread in libtorch: the handler only applies to the
+//! threads on which `install` has been called, `set_handler` and
+//! `capture_warnings` install it on the current thread. Warnings emitted on
+//! other threads, e.g. by the autograd engine in the backward pass, are still
+//! printed on stderr.
+//!
+//! ```no_run
+//! # use tch::{warnings, Device, Kind, Tensor};
+//! let out = Tensor::zeros([3], (Kind::Float, Device::Cpu));
+//! let xs = Tensor::ones([2], (Kind::Float, Device::Cpu));
+//! let (_, warnings) = warnings::capture_warnings(|| xs.add_out(&out, &xs));
+//! for warning in warnings {
+//!     println!("{warning}");
+//! }
+//! ```
+use std::cell::RefCell;
+use std::sync::RwLock;
+
+/// The source location of a warning in the libtorch codebase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub function: String,
+    pub line: u32,
+}
+
+/// A warning emitted by libtorch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub message: String,
+    pub location: Option<Location>,
+    /// Whether the warnings that are normally only emitted once per process
+    /// were emitted every time when this warning was raised, see
+    /// `set_warn_always`.
+    pub warn_always: bool,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.location {
+            Some(loc) => write!(f, "{} ({}:{})", self.message, loc.file, loc.line),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(Warning) + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<Warning>>> = const { RefCell::new(None) };
+}
+
+fn default_handler(warning: Warning) {
+    log::warn!(target: "tch", "{warning}");
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "tch", "{warning}");
+}
+
+unsafe fn str_from_ptr(ptr: *const libc::c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+extern "C" fn handle_warning(
+    message: *const libc::c_char,
+    file: *const libc::c_char,
+    function: *const libc::c_char,
+    line: u32,
+    warn_always: libc::c_int,
+) {
+    let message = unsafe { str_from_ptr(message) }.unwrap_or_default();
+    let location = unsafe { str_from_ptr(file) }.map(|file| Location {
+        file,
+        function: unsafe { str_from_ptr(function) }.unwrap_or_default(),
+        line,
+    });
+    let warning = Warning { message, location, warn_always: warn_always != 0 };
+    // Unwinding through the C++ frames is not allowed, panics in the handler
+    // are dropped.
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let warning = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => {
+                captured.push(warning);
+                None
+            }
+            None => Some(warning),
+        });
+        if let Some(warning) = warning {
+            match HANDLER.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+                Some(handler) => handler(warning),
+                None => default_handler(warning),
+            }
+        }
+    }));
+}
+
+/// Routes the warnings emitted by libtorch on the current thread to the
+/// handler set with `set_handler`, or to `log` if no handler has been set.
+pub fn install() {
+    let _prev = unsafe_torch!(torch_sys::at_warning_handler_install(handle_warning));
+}
+
+/// Sets the handler called on each warning and installs it on the current
+/// thread, see `install`.
+pub fn set_handler(handler: Box<dyn Fn(Warning) + Send + Sync>) {
+    *HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(handler);
+    install()
+}
+
+/// Removes the handler set with `set_handler`, the warnings are forwarded to
+/// `log` again.
+pub fn reset_handler() {
+    *HANDLER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// Restores the previous libtorch handler and captured warnings, including when
+// the captured closure panics.
+struct CaptureGuard {
+    prev_handler: *mut libc::c_void,
+    prev_captured: Option<Vec<Warning>>,
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        unsafe_torch!(torch_sys::at_warning_handler_restore(self.prev_handler));
+        CAPTURED.with(|captured| captured.replace(self.prev_captured.take()));
+    }
+}
+
+/// Runs a closure and returns the warnings emitted by libtorch on the current
+/// thread while it ran, these warnings are not passed to the handler.
+///
+/// Some warnings are only emitted once per process, `set_warn_always` can be
+/// used to have them emitted every time.
+pub fn capture_warnings<T, F>(f: F) -> (T, Vec<Warning>)
+where
+    F: FnOnce() -> T,
+{
+    let prev_captured = CAPTURED.with(|captured| captured.replace(Some(vec![])));
+    let prev_handler = unsafe_torch!(torch_sys::at_warning_handler_install(handle_warning));
+    let guard = CaptureGuard { prev_handler, prev_captured };
+    let value = f();
+    let warnings = CAPTURED.with(|captured| captured.borrow_mut().take()).unwrap_or_default();
+    drop(guard);
+    (value, warnings)
+}
+
+/// Emits the warnings that are normally only emitted once per process every
+/// time, this applies to all the threads.
+pub fn set_warn_always(enabled: bool) {
+    unsafe_torch!(torch_sys::at_set_warn_always(i32::from(enabled)))
+}
+
+/// Returns true if the warnings normally emitted once are emitted every time.
+pub fn warn_always() -> bool {
+    unsafe_torch!(torch_sys::at_get_warn_always()) != 0
+}
@@ -0,0 +1,193 @@
+//! Chunking long sequences in overlapping windows and stitching the outputs.
+use super::tensor::Tensor;
+use crate::TchError;
+use std::ops::Range;
+
+fn normalize_dim(input: &Tensor, dim: i64) -> Result<i64, TchError> {
+    let ndim = input.dim() as i64;
+    let d = if dim < 0 { dim + ndim } else { dim };
+    if d < 0 || d >= ndim {
+        return Err(TchError::Shape(format!(
+            "dimension {dim} out of range for a tensor of shape {:?}",
+            input.size()
+        )));
+    }
+    Ok(d)
+}
+
+/// An iterator over overlapping windows of a tensor along a dimension, see
+/// `sliding_windows`.
+#[derive(Debug)]
+pub struct SlidingWindows {
+    input: Tensor,
+    dim: i64,
+    window: i64,
+    hop: i64,
+    len: i64,
+    start: Option<i64>,
+}
+
+impl Iterator for SlidingWindows {
+    type Item = (Tensor, Range<i64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.start?;
+        let end = (start + self.window).min(self.len);
+        self.start = if end < self.len { Some(start + self.hop) } else { None };
+        Some((self.input.narrow(self.dim, start, end - start), start..end))
+    }
+}
+
+/// Splits `input` along `dim` in windows of `window` elements starting every
+/// `hop` elements. The windows are views on the input returned along with the
+/// range that they cover, the last window is shorter when the windows do not
+/// divide the input evenly.
+///
+/// `hop` must be between 1 and `window` so that the windows cover the input.
+pub fn f_sliding_windows(
+    input: &Tensor,
+    dim: i64,
+    window: i64,
+    hop: i64,
+) -> Result<SlidingWindows, TchError> {
+    let dim = normalize_dim(input, dim)?;
+    if window <= 0 || hop <= 0 || hop > window {
+        return Err(TchError::Shape(format!(
+            "sliding windows: invalid window {window} and hop {hop}, expected 0 < hop <= window"
+        )));
+    }
+    let len = input.size()[dim as usize];
+    let start = if len > 0 { Some(0) } else { None };
+    Ok(SlidingWindows { input: input.shallow_clone(), dim, window, hop, len, start })
+}
+
+/// Splits `input` along `dim` in overlapping windows, see `f_sliding_windows`.
+pub fn sliding_windows(input: &Tensor, dim: i64, window: i64, hop: i64) -> SlidingWindows {
+    f_sliding_windows(input, dim, window, hop).unwrap()
+}
+
+/// How the overlapping parts of windows are combined by `stitch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlap {
+    /// The average of the windows covering each position.
+    Mean,
+    /// A cross-fade between consecutive windows: in the part where a window
+    /// overlaps with the previous one its weight rises with a raised cosine
+    /// (half of a Hann window), and it falls symmetrically where it overlaps
+    /// with the next one.
+    Hann,
+    /// The value of the last window covering each position.
+    Last,
+}
+
+// The raised cosine ramp over `len` positions, sampled at the middle of each
+// position so that all the weights are positive.
+fn ramp(t: i64, len: i64) -> f64 {
+    0.5 - 0.5 * (std::f64::consts::PI * (t as f64 + 0.5) / len as f64).cos()
+}
+
+fn cross_fade(len: i64, fade_in: i64, fade_out: i64) -> Vec<f64> {
+    (0..len)
+        .map(|t| {
+            let w_in = if t < fade_in { ramp(t, fade_in) } else { 1. };
+            let w_out = if len - t <= fade_out { ramp(len - 1 - t, fade_out) } else { 1. };
+            w_in * w_out
+        })
+        .collect()
+}
+
+/// Reconstructs an output of length `total_len` along `dim` from the outputs
+/// of the windows returned by `sliding_windows`, each output having the same
+/// length along `dim` as the range that it covers.
+///
+/// The overlapping values are combined with weighted running averages, so
+/// when all the windows agree on a value it is reproduced exactly. Every
+/// position up to `total_len` must be covered by some window. The outputs must
+/// have a floating point kind for `Overlap::Mean` and `Overlap::Hann`.
+pub fn f_stitch(
+    outputs: &[(Tensor, Range<i64>)],
+    dim: i64,
+    total_len: i64,
+    overlap: Overlap,
+) -> Result<Tensor, TchError> {
+    let first = match outputs.first() {
+        Some((first, _)) => first,
+        None => return Err(TchError::Shape("stitch: no outputs to stitch".to_string())),
+    };
+    let dim = normalize_dim(first, dim)?;
+    let mut size = first.size();
+    size[dim as usize] = total_len;
+    let options = (first.kind(), first.device());
+    let _no_grad = crate::no_grad_guard();
+    let out = Tensor::f_zeros(&size, options)?;
+    let mut weight_sums = vec![0f64; total_len.max(0) as usize];
+
+    let mut order: Vec<usize> = (0..outputs.len()).collect();
+    order.sort_by_key(|&i| outputs[i].1.start);
+    for (index, &i) in order.iter().enumerate() {
+        let (output, range) = &outputs[i];
+        let len = range.end - range.start;
+        if range.start < 0
+            || range.end > total_len
+            || len <= 0
+            || output.size()[dim as usize] != len
+        {
+            return Err(TchError::Shape(format!(
+                "stitch: output of shape {:?} for range {range:?} with a total length {total_len}",
+                output.size()
+            )));
+        }
+        let sums = &mut weight_sums[range.start as usize..range.end as usize];
+        let mut target = out.f_narrow(dim, range.start, len)?;
+        let weights = match overlap {
+            Overlap::Last => {
+                sums.iter_mut().for_each(|sum| *sum += 1.);
+                target.f_copy_(output)?;
+                continue;
+            }
+            Overlap::Mean => vec![1.; len as usize],
+            Overlap::Hann => {
+                let prev_end = index.checked_sub(1).map_or(0, |j| outputs[order[j]].1.end);
+                let next_start = order
+                    .get(index + 1)
+                    .map_or(range.end, |&j| outputs[j].1.start.max(range.start));
+                let fade_in = (prev_end - range.start).clamp(0, len);
+                let fade_out = (range.end - next_start).clamp(0, len);
+                cross_fade(len, fade_in, fade_out)
+            }
+        };
+        let ratios: Vec<f64> = weights
+            .iter()
+            .zip(sums.iter_mut())
+            .map(|(w, sum)| {
+                *sum += w;
+                w / *sum
+            })
+            .collect();
+        let mut ratio_size = vec![1; size.len()];
+        ratio_size[dim as usize] = len;
+        let ratios = Tensor::f_from_slice(&ratios)?
+            .f_to_kind(options.0)?
+            .f_to_device(options.1)?
+            .f_view(ratio_size.as_slice())?;
+        // A running weighted average, the update is exactly zero when the
+        // output matches the current value.
+        let update = output.f_sub(&target)?.f_mul(&ratios)?;
+        let _ = target.f_add_(&update)?;
+    }
+    if let Some(position) = weight_sums.iter().position(|&w| w == 0.) {
+        return Err(TchError::Shape(format!("stitch: position {position} is not covered")));
+    }
+    Ok(out)
+}
+
+/// Reconstructs an output from the outputs of overlapping windows, see
+/// `f_stitch`.
+pub fn stitch(
+    outputs: &[(Tensor, Range<i64>)],
+    dim: i64,
+    total_len: i64,
+    overlap: Overlap,
+) -> Tensor {
+    f_stitch(outputs, dim, total_len, overlap).unwrap()
+}
@@ -0,0 +1,103 @@
+use tch::nn::{self, GradScaler, Module, OptimizerConfig};
+use tch::{Device, Kind, Reduction, Tensor};
+
+#[test]
+fn grad_scaler_cpu() {
+    tch::manual_seed(42);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root(), 4, 1, Default::default());
+    let mut opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+    let mut scaler = GradScaler::new(1024., 2., 0.5, 2);
+    let xs = Tensor::randn([8, 4], (Kind::Float, Device::Cpu));
+    let ys = Tensor::randn([8, 1], (Kind::Float, Device::Cpu));
+
+    // The unscaled gradients match the gradients computed without scaling.
+    let loss = linear.forward(&xs).mse_loss(&ys, Reduction::Mean);
+    opt.zero_grad();
+    loss.backward();
+    let grad = linear.ws.grad().copy();
+    let loss = linear.forward(&xs).mse_loss(&ys, Reduction::Mean);
+    opt.zero_grad();
+    scaler.scale(&loss).unwrap().backward();
+    assert!(linear.ws.grad().allclose(&(&grad * 1024.), 1e-4, 1e-4, false));
+    scaler.unscale(&mut opt).unwrap();
+    assert!(linear.ws.grad().allclose(&grad, 1e-5, 1e-5, false));
+    assert!(scaler.unscale(&mut opt).is_err());
+    let ws = linear.ws.copy();
+    assert!(scaler.step(&mut opt).unwrap());
+    assert!(scaler.step(&mut opt).is_err());
+    assert!(!linear.ws.allclose(&ws, 1e-8, 1e-8, false));
+    scaler.update().unwrap();
+    assert_eq!(scaler.get_scale(), 1024.);
+
+    // A second step without overflow grows the scale.
+    let loss = linear.forward(&xs).mse_loss(&ys, Reduction::Mean);
+    opt.zero_grad();
+    scaler.scale(&loss).unwrap().backward();
+    assert!(scaler.step(&mut opt).unwrap());
+    scaler.update().unwrap();
+    assert_eq!(scaler.get_scale(), 2048.);
+
+    // An infinite gradient skips the step and reduces the scale.
+    let loss = linear.forward(&xs).mse_loss(&ys, Reduction::Mean);
+    opt.zero_grad();
+    scaler.scale(&loss).unwrap().backward();
+    let _ = linear.ws.grad().get(0).get(0).fill_(f64::INFINITY);
+    let ws = linear.ws.copy();
+    assert!(!scaler.step(&mut opt).unwrap());
+    assert!(linear.ws.equal(&ws));
+    scaler.update().unwrap();
+    assert_eq!(scaler.get_scale(), 1024.);
+}
+
+#[cfg(test)]
+#[cfg(feature = "cuda-tests")]
+mod tests {
+    use tch::amp::GradScaler;
+    use tch::nn::{self, Module, OptimizerConfig};
+    use tch::{autocast, Device, Kind, Reduction, Tensor};
+
+    // Trains a linear regression with a tiny loss multiplier so that the gradients
+    // underflow in half precision, returns the initial and final losses. Without
+    // autocast the training runs in single precision.
+    fn train(use_autocast: bool, use_scaler: bool) -> (f64, f64) {
+        tch::manual_seed(42);
+        let device = Device::Cuda(0);
+        let vs = nn::VarStore::new(device);
+        let linear = nn::linear(vs.root(), 16, 1, Default::default());
+        let mut opt = nn::Sgd::default().build(&vs, 1e6).unwrap();
+        let mut scaler = GradScaler::default();
+        let xs = Tensor::randn([64, 16], (Kind::Float, device));
+        let ys = xs.matmul(&Tensor::randn([16, 1], (Kind::Float, device)));
+        let mut losses = vec![];
+        for _ in 0..50 {
+            let loss = autocast(device, Kind::Half, use_autocast, || {
+                linear.forward(&xs).mse_loss(&ys, Reduction::Mean) * 1e-7
+            });
+            losses.push(f64::try_from(&loss).unwrap() * 1e7);
+            opt.zero_grad();
+            if use_scaler {
+                scaler.scale(&loss).unwrap().backward();
+                scaler.step(&mut opt).unwrap();
+                scaler.update().unwrap();
+            } else {
+                loss.backward();
+                opt.step();
+            }
+        }
+        (losses[0], *losses.last().unwrap())
+    }
+
+    #[test]
+    fn grad_scaler_f16_training() {
+        let (initial_loss, final_loss) = train(true, true);
+        assert!(final_loss < 0.1 * initial_loss, "{initial_loss} {final_loss}");
+        let (_, f32_final_loss) = train(false, false);
+        assert!(
+            (final_loss - f32_final_loss).abs() < 0.05 * initial_loss,
+            "{final_loss} {f32_final_loss}"
+        );
+        let (initial_loss, final_loss) = train(true, false);
+        assert!(final_loss.is_nan() || final_loss >= 0.99 * initial_loss);
+    }
+}
@@ -0,0 +1,73 @@
+use tch::nn::{self, analysis, Module};
+use tch::{Device, Tensor};
+
+#[test]
+fn linear_and_conv() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root() / "linear", 10, 20, Default::default());
+    let report = analysis::estimate(&linear, &[4, 10]);
+    assert_eq!(report.layers.len(), 1);
+    let layer = &report.layers[0];
+    assert_eq!(layer.name, "linear.0");
+    // 4 x 20 outputs, each with 10 multiply-accumulates.
+    assert_eq!(layer.flops, Some(800));
+    assert_eq!(layer.params, 220);
+    assert_eq!(layer.output_shape, [4, 20]);
+    assert_eq!(layer.activation_bytes, 4 * 20 * 4);
+
+    let cfg = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
+    let conv = nn::conv2d(vs.root() / "conv", 3, 8, 3, cfg);
+    let report = analysis::estimate(&conv, &[1, 3, 16, 16]);
+    let layer = &report.layers[0];
+    assert_eq!(layer.name, "conv2d.0");
+    // 8 x 16 x 16 outputs, each with 3 x 3 x 3 multiply-accumulates.
+    assert_eq!(layer.flops, Some(8 * 16 * 16 * 27));
+    assert_eq!(layer.params, 8 * 27 + 8);
+    assert_eq!(report.total_activation_bytes(), 8 * 16 * 16 * 4);
+}
+
+#[derive(Debug)]
+struct Custom;
+
+impl Module for Custom {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        xs.relu()
+    }
+}
+
+#[test]
+fn unmeasured() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let net = nn::seq()
+        .add(nn::linear(vs.root() / "l1", 10, 20, Default::default()))
+        .add_fn(|xs| xs.relu())
+        .add(Custom)
+        .add(nn::linear(vs.root() / "l2", 20, 5, Default::default()));
+    let report = analysis::estimate(&net, &[2, 10]);
+    let names: Vec<_> = report.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["linear.0", "func.0", "Custom.0", "linear.1"]);
+    let unmeasured: Vec<_> = report.unmeasured().map(|l| l.kind.as_str()).collect();
+    assert_eq!(unmeasured, ["func", "Custom"]);
+    assert_eq!(report.total_flops(), 2 * 20 * 10 + 2 * 5 * 20);
+    let table = report.to_string();
+    assert!(table.contains("unmeasured"), "{table}");
+    // The most expensive layer comes first.
+    assert!(table.lines().nth(1).unwrap().starts_with("linear.0"), "{table}");
+
+    let report = analysis::estimate(&Custom, &[2, 10]);
+    assert_eq!(report.layers.len(), 1);
+    assert_eq!(report.layers[0].flops, None);
+}
+
+#[test]
+fn resnet18() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let net = tch::vision::resnet::resnet18(&vs.root(), 1000);
+    let report = analysis::estimate(&net, &[1, 3, 224, 224]);
+    // The published figure is 1.8 GFLOPs, counted as multiply-accumulates.
+    let flops = report.total_flops() as f64;
+    assert!((flops / 1.8e9 - 1.).abs() < 0.01, "{flops}");
+    assert_eq!(report.total_params(), 11_689_512);
+    assert_eq!(report.unmeasured().count(), 0);
+    assert_eq!(report.layers.iter().filter(|l| l.kind == "conv2d").count(), 20);
+}
@@ -1,3 +1,28 @@
+use tch::{autocast, autocast_guard, Device, Kind, Tensor};
+
+#[test]
+fn autocast_cpu_bf16() {
+    let linear = Tensor::rand([10, 10], (Kind::Float, Device::Cpu));
+    let input = Tensor::rand([10], (Kind::Float, Device::Cpu));
+
+    autocast(Device::Cpu, Kind::BFloat16, true, || {
+        let output1 = linear.matmul(&input);
+        assert_eq!(output1.kind(), Kind::BFloat16);
+        let output2 = autocast(Device::Cpu, Kind::BFloat16, false, || linear.matmul(&input));
+        assert_eq!(output2.kind(), Kind::Float);
+        let output3 = tch::no_grad(|| linear.matmul(&input));
+        assert_eq!(output3.kind(), Kind::BFloat16);
+    });
+    assert_eq!(linear.matmul(&input).kind(), Kind::Float);
+    {
+        let _guard = autocast_guard(Device::Cpu, Kind::BFloat16, true);
+        assert_eq!(linear.matmul(&input).kind(), Kind::BFloat16);
+    }
+    assert_eq!(linear.matmul(&input).kind(), Kind::Float);
+    assert!(tch::f_autocast_guard(Device::Cpu, Kind::Half, true).is_err());
+    assert!(tch::f_autocast_guard(Device::Mps, Kind::Half, true).is_err());
+}
+
 #[cfg(test)]
 #[cfg(feature = "cuda-tests")]
 mod tests {
@@ -10,13 +35,23 @@ mod tests {
         let linear = Tensor::rand([10, 10], (Kind::Float, device));
         let input = Tensor::rand([10], (Kind::Float, device));
 
-        autocast(true, || {
-            let output1 = autocast(false, || linear.matmul(&input));
+        autocast(device, Kind::Half, true, || {
+            let output1 = autocast(device, Kind::Half, false, || linear.matmul(&input));
             assert_eq!(output1.kind(), Kind::Float);
             let output2 = linear.matmul(&output1);
             assert_eq!(output2.kind(), Kind::Half);
-            let output3 = autocast(false, || linear.matmul(&output1));
+            let output3 = autocast(device, Kind::Half, false, || linear.matmul(&output1));
             assert_eq!(output3.kind(), Kind::Float);
+            let output4 = output2.softmax(-1, None);
+            assert_eq!(output4.kind(), Kind::Float);
         });
     }
+
+    #[test]
+    fn autocast_cuda_bf16() {
+        let device = Device::Cuda(0);
+        let linear = Tensor::rand([10, 10], (Kind::Float, device));
+        let output = autocast(device, Kind::BFloat16, true, || linear.matmul(&linear));
+        assert_eq!(output.kind(), Kind::BFloat16);
+    }
 }
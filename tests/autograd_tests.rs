@@ -0,0 +1,34 @@
+// The anomaly detection mode is global so these tests are kept in a separate
+// test binary and run sequentially from a single test.
+use tch::{autograd, Kind, Tensor};
+
+fn anomaly_nan_backward() {
+    let x = Tensor::from_slice(&[-1f32, 4.]).set_requires_grad(true);
+    assert!(!autograd::is_anomaly_enabled());
+    // Without anomaly detection, the NaN silently propagates to the gradient.
+    x.sqrt().sum(Kind::Float).backward();
+    assert!(f64::try_from(x.grad().isnan().any()).unwrap() == 1.);
+
+    let err = autograd::detect_anomaly(|| {
+        assert!(autograd::is_anomaly_enabled());
+        assert!(autograd::is_anomaly_check_nan_enabled());
+        x.sqrt().sum(Kind::Float).f_backward()
+    });
+    let err = err.unwrap_err().to_string();
+    assert!(err.contains("SqrtBackward0"), "{err}");
+    assert!(err.contains("nan"), "{err}");
+    assert!(!autograd::is_anomaly_enabled());
+
+    // The NaN check can be disabled while keeping the anomaly mode.
+    {
+        let _guard = autograd::DetectAnomalyGuard::new(false);
+        assert!(autograd::is_anomaly_enabled());
+        assert!(x.sqrt().sum(Kind::Float).f_backward().is_ok());
+    }
+    assert!(!autograd::is_anomaly_enabled());
+}
+
+#[test]
+fn anomaly_detection() {
+    anomaly_nan_backward();
+}
@@ -0,0 +1,60 @@
+use tch::calibrate::{self, TemperatureScaler};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+// Logits along with labels sampled from their softmax, so that the logits are
+// calibrated.
+fn calibrated_logits() -> (Tensor, Tensor) {
+    tch::manual_seed(42);
+    let logits = Tensor::randn([20000, 5], (Kind::Double, Device::Cpu)) * 2.;
+    let labels = logits.softmax(-1, Kind::Double).multinomial(1, true).squeeze_dim(1);
+    (logits, labels)
+}
+
+#[test]
+fn temperature_scaling() {
+    let (logits, labels) = calibrated_logits();
+    let scaler = TemperatureScaler::fit(&logits, &labels).unwrap();
+    assert!((scaler.temperature() - 1.).abs() < 0.05, "{scaler:?}");
+
+    // Overconfident logits.
+    let logits = logits * 3.;
+    let scaler = TemperatureScaler::fit(&logits, &labels).unwrap();
+    assert!((scaler.temperature() - 3.).abs() < 0.3, "{scaler:?}");
+    let ece_before = calibrate::expected_calibration_error(&logits.softmax(-1, None), &labels, 15);
+    let scaled = scaler.apply(&logits).softmax(-1, None);
+    let ece_after = calibrate::expected_calibration_error(&scaled, &labels, 15);
+    assert!(ece_after < ece_before, "{ece_before} {ece_after}");
+
+    let scaler = TemperatureScaler::new(scaler.temperature());
+    assert_eq!(
+        vec_f64_from(&scaler.apply(&logits)),
+        vec_f64_from(&(&logits / scaler.temperature()))
+    );
+}
+
+#[test]
+fn invalid_inputs() {
+    let logits = Tensor::randn([4, 3], (Kind::Float, Device::Cpu));
+    let labels = Tensor::from_slice(&[1i64, 1, 1, 1]);
+    assert!(TemperatureScaler::fit(&logits, &labels).is_err());
+    let labels = Tensor::from_slice(&[0i64, 1, 2]);
+    assert!(TemperatureScaler::fit(&logits, &labels).is_err());
+    assert!(calibrate::f_expected_calibration_error(&logits, &labels, 10).is_err());
+}
+
+#[test]
+fn reliability_diagram() {
+    let probs = Tensor::from_slice2(&[[0.9, 0.1], [0.45, 0.55], [0.65, 0.35], [0.2, 0.8]]);
+    let labels = Tensor::from_slice(&[0i64, 1, 1, 1]);
+    let diagram = calibrate::reliability_diagram(&probs, &labels, 4);
+    assert_eq!(vec_f64_from(&diagram.counts), [0., 0., 2., 2.]);
+    let confidences = vec_f64_from(&diagram.confidences);
+    assert_eq!(confidences[..2], [0., 0.]);
+    assert!((confidences[2] - 0.6).abs() < 1e-9 && (confidences[3] - 0.85).abs() < 1e-9);
+    assert_eq!(vec_f64_from(&diagram.accuracies), [0., 0., 0.5, 1.]);
+    let ece = calibrate::expected_calibration_error(&probs, &labels, 4);
+    assert!((ece - 0.125).abs() < 1e-9, "{ece}");
+}
@@ -0,0 +1,94 @@
+// Checkpointing replays the random number generator states, as these are global
+// the checks run from a single test in a separate test binary.
+use std::sync::{Arc, Mutex};
+use tch::nn::{self, Module};
+use tch::{autograd, utils, Device, Kind, Tensor};
+
+#[derive(Debug)]
+struct Block {
+    linear: nn::Linear,
+}
+
+impl Module for Block {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        self.linear.forward(xs).relu().dropout(0.5, true)
+    }
+}
+
+fn grads(vs: &nn::VarStore) -> Vec<(String, Tensor)> {
+    let mut grads: Vec<_> = vs.variables().into_iter().map(|(n, v)| (n, v.grad().copy())).collect();
+    grads.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, mut var) in vs.variables() {
+        var.zero_grad()
+    }
+    grads
+}
+
+#[test]
+fn checkpoint() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let blocks: Vec<Arc<Mutex<Block>>> = (0..4)
+        .map(|i| {
+            let linear = nn::linear(vs.root() / i, 8, 8, Default::default());
+            Arc::new(Mutex::new(Block { linear }))
+        })
+        .collect();
+    let xs = Tensor::randn([16, 8], (Kind::Float, Device::Cpu)).set_requires_grad(true);
+
+    // Reference gradients without checkpointing.
+    tch::manual_seed(42);
+    let ys = blocks.iter().fold(xs.shallow_clone(), |xs, b| b.lock().unwrap().forward(&xs));
+    ys.sum(Kind::Float).backward();
+    let expected = grads(&vs);
+    let expected_xs_grad = xs.grad().copy();
+    let _ = xs.grad().zero_();
+
+    // A single checkpointed block, the dropout mask has to be replayed.
+    tch::manual_seed(42);
+    let block = blocks[0].clone();
+    let ys = utils::checkpoint(move |xs| block.lock().unwrap().forward(xs), &xs);
+    let ys = blocks[1..].iter().fold(ys, |xs, b| b.lock().unwrap().forward(&xs));
+    ys.sum(Kind::Float).backward();
+    let got = grads(&vs);
+    assert_eq!(got.len(), expected.len());
+    for ((name, expected), (_, got)) in expected.iter().zip(got.iter()) {
+        assert!(expected.equal(got), "{name}");
+    }
+    assert!(xs.grad().equal(&expected_xs_grad));
+    let _ = xs.grad().zero_();
+
+    // Checkpointing segments of blocks, the first segment input does not
+    // require grad but the parameters still get their gradients.
+    let inputs = xs.detach();
+    tch::manual_seed(42);
+    let ys = blocks.iter().fold(inputs.shallow_clone(), |xs, b| b.lock().unwrap().forward(&xs));
+    ys.sum(Kind::Float).backward();
+    let expected = grads(&vs);
+    tch::manual_seed(42);
+    let ys = utils::checkpoint_sequential(&blocks, 2, &inputs);
+    ys.sum(Kind::Float).backward();
+    let got = grads(&vs);
+    for ((name, expected), (_, got)) in expected.iter().zip(got.iter()) {
+        assert!(expected.defined() && expected.equal(got), "{name}");
+    }
+
+    // Multiple inputs.
+    let ws = Tensor::randn([8, 8], (Kind::Float, Device::Cpu)).set_requires_grad(true);
+    let ys = utils::checkpoint_multi(|xs| xs[0].matmul(&xs[1]).tanh(), &[&xs, &ws]);
+    ys.sum(Kind::Float).backward();
+    let (xs_grad, ws_grad) = (xs.grad().copy(), ws.grad().copy());
+    let _ = xs.grad().zero_();
+    let _ = ws.grad().zero_();
+    xs.matmul(&ws).tanh().sum(Kind::Float).backward();
+    assert!(xs_grad.allclose(&xs.grad(), 1e-6, 1e-6, false));
+    assert!(ws_grad.allclose(&ws.grad(), 1e-6, 1e-6, false));
+    let inputs = [xs.narrow(0, 0, 2).detach().set_requires_grad(true), ws.set_requires_grad(true)];
+    let f = |xs: &[Tensor]| {
+        utils::checkpoint_multi(|xs| xs[0].matmul(&xs[1]).tanh(), &[&xs[0], &xs[1]])
+    };
+    autograd::gradcheck(f, &inputs, Default::default()).unwrap();
+
+    // Panics in the checkpointed function are reported as errors.
+    let err = utils::f_checkpoint(|_| panic!("checkpointed panic"), &xs);
+    assert!(err.is_err());
+}
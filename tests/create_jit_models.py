@@ -117,3 +117,34 @@ class DictExample(torch.jit.ScriptModule):
 
 foo_8 = DictExample()
 foo_8.save("foo8.pt")
+
+# A model using a custom operator, the kernel used when running it from tch is
+# registered with tch::ops::register_kernel before loading the model.
+tch_test_lib = torch.library.Library("tch_test", "DEF")
+tch_test_lib.define("fused_add_mul(Tensor x, Tensor y, float s) -> Tensor")
+tch_test_lib.impl("fused_add_mul", lambda x, y, s: (x + y) * s, "CPU")
+
+class CustomOpExample(torch.jit.ScriptModule):
+    @torch.jit.script_method
+    def forward(self, x, y):
+        return torch.ops.tch_test.fused_add_mul(x, y, 2.0) + 1
+
+foo_9 = CustomOpExample()
+foo_9.save("foo9.pt")
+
+# A model with a batch normalization layer, used to check that the running
+# statistics are returned as named buffers.
+class BatchNormModel(torch.nn.Module):
+    def __init__(self):
+        super().__init__()
+        self.bn = torch.nn.BatchNorm1d(3)
+
+    def forward(self, x):
+        return self.bn(x)
+
+foo_10 = BatchNormModel()
+foo_10.bn.running_mean.copy_(torch.tensor([0.5, -1.0, 2.0]))
+foo_10.bn.running_var.copy_(torch.tensor([1.0, 4.0, 0.25]))
+foo_10.bn.num_batches_tracked.fill_(7)
+foo_10.eval()
+torch.jit.script(foo_10).save("foo10.pt")
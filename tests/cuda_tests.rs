@@ -0,0 +1,385 @@
+#[cfg(test)]
+#[cfg(feature = "cuda-tests")]
+mod tests {
+    use tch::cuda::{CudaEvent, CudaStream};
+    use tch::{Device, Kind, Tensor};
+
+    #[test]
+    fn stream_guard() {
+        let device = Device::Cuda(0);
+        let default_stream = CudaStream::default(device).unwrap();
+        assert_eq!(CudaStream::current(device).unwrap(), default_stream);
+        let stream = CudaStream::new(device).unwrap();
+        assert_ne!(stream, default_stream);
+        {
+            let _guard = stream.set_current().unwrap();
+            assert_eq!(CudaStream::current(device).unwrap(), stream);
+        }
+        assert_eq!(CudaStream::current(device).unwrap(), default_stream);
+    }
+
+    #[test]
+    fn stream_event_wait() {
+        let device = Device::Cuda(0);
+        let default_stream = CudaStream::default(device).unwrap();
+        let stream = CudaStream::new(device).unwrap();
+        let xs = Tensor::ones([1024, 1024], (Kind::Float, device));
+        let mut event = CudaEvent::new(false).unwrap();
+        let ys = {
+            let _guard = stream.set_current().unwrap();
+            let ys = xs.matmul(&xs);
+            event.record(&stream).unwrap();
+            ys
+        };
+        // Work queued on the default stream has to wait for the matmul.
+        default_stream.wait_event(&event).unwrap();
+        let sum = ys.sum(Kind::Float).to_device(Device::Cpu);
+        assert_eq!(sum.double_value(&[]), 1024. * 1024. * 1024.);
+        stream.synchronize().unwrap();
+        assert!(stream.query().unwrap());
+    }
+
+    #[test]
+    fn event_timing() {
+        let device = Device::Cuda(0);
+        let xs = Tensor::randn([4096, 4096], (Kind::Float, device));
+        let elapsed = tch::cuda::time_it(|| {
+            let _ys = xs.matmul(&xs);
+        })
+        .unwrap();
+        assert!(elapsed > 0.);
+
+        let stream = CudaStream::current(device).unwrap();
+        let mut start = CudaEvent::new(true).unwrap();
+        let mut end = CudaEvent::new(true).unwrap();
+        start.record(&stream).unwrap();
+        let _ys = xs.matmul(&xs);
+        end.record(&stream).unwrap();
+        end.synchronize().unwrap();
+        assert!(end.query().unwrap());
+        assert!(start.elapsed_time(&end).unwrap() > 0.);
+    }
+
+    #[test]
+    fn event_ordering() {
+        let device = Device::Cuda(0);
+        let producer = CudaStream::new(device).unwrap();
+        let consumer = CudaStream::new(device).unwrap();
+        let xs = Tensor::ones([2048, 2048], (Kind::Float, device));
+        let mut ys = Tensor::zeros([2048, 2048], (Kind::Float, device));
+        let mut event = CudaEvent::new(false).unwrap();
+        {
+            let _guard = producer.set_current().unwrap();
+            // A slow kernel writing to ys.
+            ys.copy_(&xs.matmul(&xs).matmul(&xs));
+            event.record(&producer).unwrap();
+        }
+        let sum = {
+            let _guard = consumer.set_current().unwrap();
+            // Without this wait the consumer could read ys before it gets written.
+            event.wait(&consumer).unwrap();
+            ys.sum(Kind::Double)
+        };
+        consumer.synchronize().unwrap();
+        let expected = 2048f64.powi(4);
+        assert_eq!(sum.to_device(Device::Cpu).double_value(&[]), expected);
+    }
+
+    #[test]
+    fn memory_stats() {
+        let device = Device::Cuda(0);
+        tch::cuda::empty_cache().unwrap();
+        let before = tch::cuda::memory_stats(device).unwrap();
+        let nbytes = 64 * 1024 * 1024;
+        let xs = Tensor::zeros([nbytes / 4], (Kind::Float, device));
+        let during = tch::cuda::memory_stats(device).unwrap();
+        assert!(during.allocated_bytes >= before.allocated_bytes + nbytes);
+        assert!(tch::cuda::max_memory_allocated(device).unwrap() >= during.allocated_bytes);
+        drop(xs);
+        assert!(tch::cuda::memory_allocated(device).unwrap() < during.allocated_bytes);
+        tch::cuda::empty_cache().unwrap();
+        assert!(tch::cuda::memory_reserved(device).unwrap() < during.reserved_bytes);
+        tch::cuda::reset_peak_memory_stats(device).unwrap();
+        let after = tch::cuda::memory_stats(device).unwrap();
+        assert!(after.allocated_bytes_peak < during.allocated_bytes);
+        let invalid = Device::Cuda(tch::Cuda::device_count() as usize);
+        assert!(tch::cuda::memory_allocated(invalid).is_err());
+        assert!(tch::cuda::reset_peak_memory_stats(invalid).is_err());
+    }
+
+    #[test]
+    fn memory_fraction() {
+        let device = Device::Cuda(0);
+        assert!(tch::cuda::set_per_process_memory_fraction(1.5, device).is_err());
+        assert!(tch::cuda::set_per_process_memory_fraction(-0.1, device).is_err());
+        tch::cuda::set_per_process_memory_fraction(0.001, device).unwrap();
+        // 512MB is above the limit for any device with less than 512GB of memory.
+        let res = Tensor::f_zeros([512 * 1024 * 1024 / 4], (Kind::Float, device));
+        assert!(res.is_err());
+        tch::cuda::set_per_process_memory_fraction(1.0, device).unwrap();
+    }
+
+    #[test]
+    fn allocator_settings() {
+        assert!(tch::cuda::set_allocator_settings("max_split_size_mb").is_err());
+        assert!(tch::cuda::set_allocator_settings("unknown_key:1").is_err());
+        let _t = Tensor::zeros([16], (Kind::Float, Device::Cuda(0)));
+        let version = tch::utils::version_torch().unwrap();
+        let res = tch::cuda::set_allocator_settings(
+            "max_split_size_mb:128,garbage_collection_threshold:0.8",
+        );
+        let res_segments = tch::cuda::set_expandable_segments(true);
+        if version.starts_with("2.0.") {
+            // The allocator has been initialized by the allocation above.
+            assert!(res.is_err());
+            assert!(res_segments.is_err());
+        } else {
+            res.unwrap();
+            res_segments.unwrap();
+        }
+    }
+
+    #[test]
+    fn device_properties() {
+        assert!(tch::cuda::device_count() > 0);
+        let properties = tch::cuda::device_properties(Device::Cuda(0)).unwrap();
+        println!("{properties:?}");
+        assert!(properties.total_memory > 0);
+        assert!(!properties.name.is_empty());
+        let capability = tch::cuda::get_device_capability(0).unwrap();
+        assert_eq!(capability, (properties.major, properties.minor));
+        tch::cuda::set_device(0).unwrap();
+        assert_eq!(tch::cuda::current_device().unwrap(), 0);
+        assert!(tch::cuda::device_properties(Device::Cpu).is_err());
+    }
+
+    #[test]
+    fn data_parallel() {
+        use tch::nn::{self, Module};
+        let devices = if tch::cuda::device_count() >= 2 {
+            [Device::Cuda(0), Device::Cuda(1)]
+        } else {
+            [Device::Cuda(0), Device::Cuda(0)]
+        };
+        let builder = |p: &nn::Path| nn::linear(p, 16, 4, Default::default());
+        let dp = nn::DataParallel::new(builder, &devices).unwrap();
+        let mut vs = nn::VarStore::new(Device::Cpu);
+        let single = builder(&vs.root());
+        vs.copy(dp.var_store()).unwrap();
+        let xs = Tensor::randn([33, 16], (Kind::Float, Device::Cpu));
+        let dp_ys = nn::ModuleT::forward_t(&dp, &xs, true);
+        assert_eq!(dp_ys.device(), devices[0]);
+        let ys = single.forward(&xs);
+        dp_ys.square().sum(Kind::Float).backward();
+        dp.reduce_gradients().unwrap();
+        ys.square().sum(Kind::Float).backward();
+        let dp_variables = dp.var_store().variables();
+        for (name, var) in vs.variables() {
+            let diff = (dp_variables[&name].grad().to_device(Device::Cpu) - var.grad()).abs();
+            assert!(f64::try_from(diff.max()).unwrap() < 1e-3, "{name}");
+        }
+    }
+
+    #[test]
+    fn matmul_tf32() {
+        // TF32 is only supported on Ampere and later GPUs.
+        if tch::cuda::get_device_capability(0).unwrap().0 < 8 {
+            return;
+        }
+        let device = Device::Cuda(0);
+        let xs = Tensor::randn([2048, 2048], (Kind::Float, device));
+        let prev = tch::backends::cuda::matmul_allow_tf32();
+        tch::backends::cuda::set_matmul_allow_tf32(true);
+        let tf32 = xs.matmul(&xs);
+        tch::backends::cuda::set_matmul_allow_tf32(false);
+        let f32 = xs.matmul(&xs);
+        tch::backends::cuda::set_matmul_allow_tf32(prev);
+        assert!(!tf32.equal(&f32));
+        assert!(tf32.allclose(&f32, 1e-1, 1e-1, false));
+    }
+
+    #[test]
+    fn deterministic_error() {
+        let xs = Tensor::rand([100], (Kind::Float, Device::Cuda(0)));
+        tch::set_deterministic(true, false);
+        let res = xs.f_histc(10);
+        tch::set_deterministic(false, false);
+        let err = res.unwrap_err().to_string();
+        assert!(err.contains("histc"), "{err}");
+        assert!(xs.f_histc(10).is_ok());
+    }
+
+    fn small_mlp(vs: &tch::nn::VarStore) -> impl tch::nn::Module {
+        use tch::nn;
+        let mut mlp = nn::seq();
+        for i in 0..8 {
+            mlp = mlp
+                .add(nn::linear(vs.root() / i, 64, 64, Default::default()))
+                .add_fn(|xs| xs.relu());
+        }
+        mlp
+    }
+
+    #[test]
+    fn cuda_graph() {
+        use tch::cuda::CudaGraph;
+        use tch::nn::Module;
+        let device = Device::Cuda(0);
+        let vs = tch::nn::VarStore::new(device);
+        let mlp = small_mlp(&vs);
+        let _guard = tch::no_grad_guard();
+        let xs = Tensor::randn([4, 64], (Kind::Float, device));
+        let mut graph = CudaGraph::new(device).unwrap();
+        let ys = graph.capture(&[&xs], |inputs| mlp.forward(&inputs[0])).unwrap();
+        assert!(ys.allclose(&mlp.forward(&xs), 1e-5, 1e-5, false));
+
+        for _ in 0..5 {
+            let xs = Tensor::randn([4, 64], (Kind::Float, device));
+            let ys = graph.replay_with(&[&xs]).unwrap();
+            assert!(ys.allclose(&mlp.forward(&xs), 1e-5, 1e-5, false));
+        }
+        // Dynamic shapes are rejected.
+        let xs = Tensor::randn([5, 64], (Kind::Float, device));
+        assert!(graph.replay_with(&[&xs]).is_err());
+        let mut graph2 = CudaGraph::new_with_pool(device, graph.pool().unwrap()).unwrap();
+        let mut batch_size = 1;
+        let err = graph2.capture(&[], |_| {
+            batch_size += 1;
+            Tensor::zeros([batch_size], (Kind::Float, device))
+        });
+        assert!(err.is_err());
+
+        // Replaying is much faster than running the kernels eagerly as the MLP
+        // is dominated by the kernel launch overhead.
+        let iters = 1000;
+        let eager_ms = tch::cuda::time_it(|| {
+            for _ in 0..iters {
+                let _ = mlp.forward(&xs);
+            }
+        })
+        .unwrap();
+        let replay_ms = tch::cuda::time_it(|| {
+            for _ in 0..iters {
+                graph.replay().unwrap();
+            }
+        })
+        .unwrap();
+        assert!(replay_ms < eager_ms, "replay {replay_ms}ms, eager {eager_ms}ms");
+    }
+
+    #[test]
+    fn op_failed_device_mismatch() {
+        let lhs = Tensor::zeros([32, 512], (Kind::Float, Device::Cuda(0)));
+        let rhs = Tensor::zeros([512, 10], (Kind::Float, Device::Cpu));
+        let err = lhs.f_matmul(&rhs).unwrap_err().to_string();
+        assert!(err.ends_with("self=[32, 512] f32 cuda:0, other=[512, 10] f32 cpu"), "{err}");
+    }
+
+    #[test]
+    fn record_stream() {
+        let device = Device::Cuda(0);
+        let stream = CudaStream::new(device).unwrap();
+        let xs = Tensor::ones([1024, 1024], (Kind::Float, device));
+        let ys = {
+            let _guard = stream.set_current().unwrap();
+            xs.record_stream(&stream).unwrap();
+            xs.matmul(&xs)
+        };
+        // The memory of xs is not reused until the matmul has completed.
+        drop(xs);
+        let zs = Tensor::zeros([1024, 1024], (Kind::Float, device));
+        stream.synchronize().unwrap();
+        assert_eq!(ys.double_value(&[0, 0]), 1024.);
+        assert_eq!(zs.sum(Kind::Float).double_value(&[]), 0.);
+        let cpu = Tensor::ones([2], (Kind::Float, Device::Cpu));
+        assert!(cpu.record_stream(&stream).is_err());
+    }
+
+    #[test]
+    fn deferred_free() {
+        let device = Device::Cuda(0);
+        let before = tch::cuda::memory_allocated(device).unwrap();
+        let xs = Tensor::zeros([1024, 1024], (Kind::Float, device));
+        xs.defer_drop();
+        assert_eq!(tch::cuda::DeferredFree::len(), 1);
+        assert!(tch::cuda::memory_allocated(device).unwrap() >= before + 4 * 1024 * 1024);
+        assert_eq!(tch::cuda::flush_deferred_frees(), 1);
+        assert_eq!(tch::cuda::DeferredFree::len(), 0);
+        assert_eq!(tch::cuda::memory_allocated(device).unwrap(), before);
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let device = Device::Cuda(0);
+        // A 1TiB allocation, larger than the memory of any current GPU.
+        match Tensor::f_empty([1 << 38], (Kind::Float, device)) {
+            Err(tch::TchError::CudaOutOfMemory { requested, free, total, .. }) => {
+                assert_eq!(requested, 1 << 40);
+                assert!(free <= total && total < requested);
+            }
+            res => panic!("unexpected result {res:?}"),
+        }
+        // The device can still be used after the failure.
+        let xs = Tensor::ones([2], (Kind::Float, device));
+        assert_eq!(xs.sum(Kind::Float).double_value(&[]), 2.);
+    }
+
+    #[test]
+    fn oom_retry() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        let device = Device::Cuda(0);
+        let total = tch::cuda::device_properties(device).unwrap().total_memory;
+        const MIB: i64 = 1 << 20;
+        tch::cuda::empty_cache().unwrap();
+        let limit = tch::cuda::memory_reserved(device).unwrap() + 1024 * MIB;
+        tch::cuda::set_per_process_memory_fraction(limit as f64 / total as f64, device).unwrap();
+        // The memory of the deferred frees is only released before a retry.
+        for _ in 0..4 {
+            Tensor::zeros([192 * MIB / 4], (Kind::Float, device)).defer_drop();
+        }
+        let alloc = || Tensor::f_empty([512 * MIB / 4], (Kind::Float, device));
+        assert!(matches!(alloc(), Err(tch::TchError::CudaOutOfMemory { .. })));
+        let events = Rc::new(RefCell::new(vec![]));
+        let observed = events.clone();
+        let mut retry = tch::cuda::OomRetry::new(2)
+            .synchronize(true)
+            .observer(move |event: &tch::cuda::OomEvent| observed.borrow_mut().push(event.clone()));
+        let xs = retry.run(alloc);
+        tch::cuda::set_per_process_memory_fraction(1.0, device).unwrap();
+        assert_eq!(xs.unwrap().size(), [512 * MIB / 4]);
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].attempt, 0);
+        assert_eq!(events[0].requested, 512 * MIB as u64);
+        assert!(events[0].stats.unwrap().allocated_bytes >= 768 * MIB);
+    }
+}
+
+#[test]
+fn oom_retry_errors() {
+    use tch::TchError;
+    let oom =
+        || TchError::CudaOutOfMemory { requested: 1, free: 0, total: 1, message: "oom".into() };
+    // Other errors are not retried.
+    let mut calls = 0;
+    let res: Result<(), _> = tch::cuda::with_oom_retry(3, || {
+        calls += 1;
+        Err(TchError::Shape("shape".into()))
+    });
+    assert!(matches!(res, Err(TchError::Shape(_))));
+    assert_eq!(calls, 1);
+    // Without retries, the out of memory error is returned after notifying the
+    // observer.
+    let mut calls = 0;
+    let mut retry = tch::cuda::OomRetry::new(0).observer(|event: &tch::cuda::OomEvent| {
+        assert_eq!((event.attempt, event.requested), (0, 1))
+    });
+    let res: Result<(), _> = retry.run(|| {
+        calls += 1;
+        Err(oom())
+    });
+    assert!(matches!(res, Err(TchError::CudaOutOfMemory { .. })));
+    assert_eq!(calls, 1);
+    assert_eq!(tch::cuda::with_oom_retry(3, || Ok(42)).unwrap(), 42);
+}
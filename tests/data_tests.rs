@@ -38,6 +38,255 @@ fn iter2() {
     assert!(!all_in_order)
 }
 
+#[test]
+fn iter2_drop_last() {
+    let xs = Tensor::arange(10, tch::kind::INT64_CPU);
+    let ys = &xs * 2;
+    let sizes = |iter: &mut data::Iter2| iter.map(|(xs, _)| xs.size()[0]).collect::<Vec<_>>();
+    let mut iter = data::Iter2::new(&xs, &ys, 4);
+    assert_eq!(sizes(&mut iter), [4, 4]);
+    assert_eq!(sizes(data::Iter2::new(&xs, &ys, 4).drop_last(false)), [4, 4, 2]);
+    assert_eq!(sizes(data::Iter2::new(&xs, &ys, 4).drop_last(true)), [4, 4]);
+    assert_eq!(sizes(data::Iter2::new(&xs, &ys, 5).drop_last(true)), [5, 5]);
+    assert_eq!(sizes(data::Iter2::new(&xs, &ys, 12).drop_last(true)), Vec::<i64>::new());
+    assert_eq!(sizes(data::Iter2::new(&xs, &ys, 12).drop_last(false)), [10]);
+}
+
+#[test]
+fn iter2_map_prefetch() {
+    let xs = Tensor::arange(103, tch::kind::INT64_CPU);
+    let ys = &xs * 2;
+    let batches = |prefetch: bool| {
+        let mut iter = data::Iter2::new(&xs, &ys, 8);
+        iter.seed(42).shuffle().drop_last(false).map_batches(|(xs, ys)| (xs + 1, ys));
+        if prefetch {
+            iter.prefetch_to(tch::Device::Cpu);
+        }
+        iter.map(|(xs, ys)| (vec_i64_from(&xs), vec_i64_from(&ys))).collect::<Vec<_>>()
+    };
+    let sync_batches = batches(false);
+    assert_eq!(sync_batches.len(), 13);
+    for (xs, ys) in sync_batches.iter() {
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            assert_eq!(2 * (x - 1), *y)
+        }
+    }
+    // Prefetching and seeding give the same mini-batches.
+    assert_eq!(batches(true), sync_batches);
+    assert_eq!(batches(false), sync_batches);
+
+    // Stopping the iteration early stops the prefetching thread.
+    // The batch function holds a reference that is only released once the
+    // thread has exited.
+    let alive = std::sync::Arc::new(());
+    let mut iter = data::Iter2::new(&xs, &ys, 8);
+    let alive_in_thread = alive.clone();
+    iter.map_batches(move |batch| {
+        let _alive = &alive_in_thread;
+        batch
+    });
+    let first = iter.prefetch_to(tch::Device::Cpu).next().unwrap();
+    assert_eq!(vec_i64_from(&first.0), (0..8).collect::<Vec<_>>());
+    assert_eq!(std::sync::Arc::strong_count(&alive), 2);
+    drop(iter);
+    assert_eq!(std::sync::Arc::strong_count(&alive), 1);
+}
+
+// A dataset where loading a sample takes some time, the sample i is a tensor
+// filled with i.
+struct SlowDataset {
+    len: usize,
+    delay: std::time::Duration,
+    error_at: Option<usize>,
+    panic_at: Option<usize>,
+}
+
+impl data::Dataset for SlowDataset {
+    type Sample = (Tensor, i64);
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> Result<(Tensor, i64), tch::TchError> {
+        std::thread::sleep(self.delay);
+        if self.error_at == Some(index) {
+            return Err(tch::TchError::FileFormat(format!("cannot load {index}")));
+        }
+        if self.panic_at == Some(index) {
+            panic!("cannot load {index}")
+        }
+        Ok((Tensor::full([2, 3], index as i64, tch::kind::INT64_CPU), index as i64))
+    }
+}
+
+fn slow_dataset(len: usize, delay_ms: u64) -> SlowDataset {
+    let delay = std::time::Duration::from_millis(delay_ms);
+    SlowDataset { len, delay, error_at: None, panic_at: None }
+}
+
+#[test]
+fn data_loader() {
+    let labels = |loader: &mut data::DataLoader<SlowDataset>| {
+        let mut labels = vec![];
+        for batch in loader.iter() {
+            let (xs, ys) = batch.unwrap();
+            assert_eq!(xs.size()[1..], [2, 3]);
+            let ys = vec_i64_from(&ys);
+            assert_eq!(vec_i64_from(&xs.select(1, 0).select(1, 0)), ys);
+            labels.push(ys)
+        }
+        labels
+    };
+    let mut loader = data::DataLoader::new(slow_dataset(10, 0), 4);
+    assert_eq!(loader.len(), 3);
+    assert_eq!(labels(&mut loader), [vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+    loader.drop_last(true);
+    assert_eq!(loader.len(), 2);
+    assert_eq!(labels(&mut loader).len(), 2);
+
+    // The workers deliver the same mini-batches in order.
+    let mut sync_loader = data::DataLoader::new(slow_dataset(30, 1), 3);
+    sync_loader.shuffle(true).seed(42);
+    let expected = labels(&mut sync_loader);
+    let mut loader = data::DataLoader::new(slow_dataset(30, 1), 3);
+    loader.shuffle(true).seed(42).num_workers(4);
+    assert_eq!(labels(&mut loader), expected);
+    // The next iteration uses another order.
+    assert_ne!(labels(&mut loader), expected);
+    // Out of order, all the mini-batches are still returned once.
+    let mut loader = data::DataLoader::new(slow_dataset(30, 1), 3);
+    loader.num_workers(4).in_order(false);
+    let mut all: Vec<i64> = labels(&mut loader).concat();
+    all.sort();
+    assert_eq!(all, (0..30).collect::<Vec<_>>());
+
+    // Loading in parallel is faster.
+    let time = |num_workers| {
+        let mut loader = data::DataLoader::new(slow_dataset(16, 25), 2);
+        loader.num_workers(num_workers);
+        let start = std::time::Instant::now();
+        assert_eq!(labels(&mut loader).len(), 8);
+        start.elapsed()
+    };
+    let (sequential, parallel) = (time(0), time(4));
+    assert!(parallel.as_secs_f64() < 0.6 * sequential.as_secs_f64(), "{parallel:?} {sequential:?}");
+}
+
+#[test]
+fn data_loader_errors() {
+    // Errors are returned for the mini-batch that failed to load.
+    let mut dataset = slow_dataset(8, 0);
+    dataset.error_at = Some(5);
+    let mut loader = data::DataLoader::new(dataset, 2);
+    loader.num_workers(3);
+    let batches: Vec<_> = loader.iter().collect();
+    assert_eq!(batches.len(), 4);
+    assert!(batches[2].as_ref().unwrap_err().to_string().contains("cannot load 5"));
+    assert!(batches.iter().enumerate().all(|(i, b)| b.is_ok() == (i != 2)));
+
+    // Panics are forwarded rather than blocking the iteration.
+    let mut dataset = slow_dataset(8, 0);
+    dataset.panic_at = Some(3);
+    let mut loader = data::DataLoader::new(dataset, 2);
+    loader.num_workers(3);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| loader.iter().count()));
+    assert!(result.is_err());
+
+    // Samples with different shapes cannot be stacked.
+    use data::Collate;
+    let samples =
+        vec![Tensor::zeros([2], tch::kind::FLOAT_CPU), Tensor::zeros([3], tch::kind::FLOAT_CPU)];
+    let err = data::Stack.collate(samples).unwrap_err();
+    assert!(err.to_string().contains("[2] and [3]"), "{err}");
+}
+
+#[test]
+fn samplers() {
+    use data::sampler::{DistributedSampler, RandomSampler, Sampler, WeightedRandomSampler};
+
+    let mut sampler = RandomSampler::new(20);
+    sampler.seed(42);
+    let mut indexes = sampler.indexes();
+    assert_ne!(indexes, (0..20).collect::<Vec<_>>());
+    indexes.sort();
+    assert_eq!(indexes, (0..20).collect::<Vec<_>>());
+
+    // The sampling frequencies match the weights.
+    let mut sampler = WeightedRandomSampler::new(&[1., 2., 0., 7.], 20000, true).unwrap();
+    sampler.seed(42);
+    let indexes = sampler.indexes();
+    assert_eq!(indexes.len(), 20000);
+    for (index, expected) in [(0, 0.1), (1, 0.2), (2, 0.), (3, 0.7)] {
+        let frequency = indexes.iter().filter(|&&i| i == index).count() as f64 / 20000.;
+        assert!((frequency - expected).abs() < 0.02, "{index} {frequency}");
+    }
+    let counts = |sampler: &mut WeightedRandomSampler| {
+        let mut counts = [0usize; 4];
+        for _ in 0..2000 {
+            for i in sampler.indexes() {
+                counts[i as usize] += 1
+            }
+        }
+        counts
+    };
+    // Without replacement, the indexes are distinct and the ones with a
+    // larger weight are more likely to be selected.
+    let mut sampler = WeightedRandomSampler::new(&[1., 2., 0., 7.], 2, false).unwrap();
+    sampler.seed(42);
+    let mut indexes = sampler.indexes();
+    indexes.dedup();
+    assert_eq!(indexes.len(), 2);
+    let counts = counts(&mut sampler);
+    assert_eq!(counts[2], 0);
+    assert!(counts[0] < counts[1] && counts[1] < counts[3], "{counts:?}");
+    assert_eq!(counts.iter().sum::<usize>(), 4000);
+    assert!(WeightedRandomSampler::new(&[0., 0.], 2, true).is_err());
+    assert!(WeightedRandomSampler::new(&[1., -1.], 2, true).is_err());
+    assert!(WeightedRandomSampler::new(&[1., 0., 1.], 3, false).is_err());
+
+    // The replicas get disjoint parts of the indexes, padded to the same size.
+    for (shuffle, drop_last) in [(false, false), (true, false), (false, true), (true, true)] {
+        let parts: Vec<Vec<i64>> = (0..3)
+            .map(|rank| {
+                let mut sampler = DistributedSampler::new(10, 3, rank, shuffle, 42).unwrap();
+                sampler.drop_last(drop_last).set_epoch(1);
+                assert_eq!(sampler.len(), if drop_last { 3 } else { 4 });
+                sampler.indexes()
+            })
+            .collect();
+        let mut all = parts.concat();
+        assert!(parts.iter().all(|p| p.len() == parts[0].len()));
+        all.sort();
+        if drop_last {
+            all.dedup();
+            assert_eq!(all.len(), 9);
+        } else {
+            assert_eq!(all.len(), 12);
+            all.dedup();
+            assert_eq!(all, (0..10).collect::<Vec<_>>());
+        }
+    }
+    let mut sampler = DistributedSampler::new(10, 2, 1, false, 0).unwrap();
+    assert_eq!(sampler.indexes(), [1, 3, 5, 7, 9]);
+    let mut sampler = DistributedSampler::new(10, 2, 0, true, 0).unwrap();
+    let epoch0 = sampler.indexes();
+    assert_eq!(sampler.indexes(), epoch0);
+    assert_ne!(sampler.set_epoch(1).indexes(), epoch0);
+    assert!(DistributedSampler::new(10, 2, 2, false, 0).is_err());
+
+    // Samplers can be used by the data loader or to select some samples.
+    let sampler = WeightedRandomSampler::new(&[0., 1., 0., 0., 0.], 6, true).unwrap();
+    let mut loader = data::DataLoader::new(slow_dataset(5, 0), 4);
+    loader.sampler(sampler).num_workers(2);
+    assert_eq!(loader.len(), 2);
+    let labels: Vec<Vec<i64>> = loader.iter().map(|b| vec_i64_from(&b.unwrap().1)).collect();
+    assert_eq!(labels, [vec![1, 1, 1, 1], vec![1, 1]]);
+    let xs = Tensor::arange(10, tch::kind::INT64_CPU) * 3;
+    let mut sampler = DistributedSampler::new(10, 2, 1, false, 0).unwrap();
+    assert_eq!(vec_i64_from(&xs.index_select(0, &sampler.index_tensor())), [3, 9, 15, 21, 27]);
+}
+
 #[test]
 fn text() {
     let filename = std::env::temp_dir().join(format!("tch-{}.txt", std::process::id()));
@@ -58,3 +307,77 @@ fn text() {
         assert_eq!(err, 0)
     }
 }
+
+#[test]
+fn text_windows() {
+    let text_data = data::TextData::from_string("0123456789");
+    let char_map = text_data.char_map();
+    assert_eq!(char_map.len(), 10);
+    let decode = |xs: &Tensor| -> Vec<String> {
+        (0..xs.size()[0]).map(|i| char_map.decode(&xs.get(i)).unwrap()).collect()
+    };
+    let config = data::TextIterConfig { stride: 3, shuffle: false, seed: None };
+    let batches: Vec<_> = text_data.iter_with_config(3, 1, config).map(|xs| decode(&xs)).collect();
+    assert_eq!(batches, [["012"], ["345"], ["678"]]);
+    // Overlapping windows, the last incomplete batch is dropped.
+    let config = data::TextIterConfig { stride: 2, shuffle: false, seed: None };
+    let batches: Vec<_> = text_data.iter_with_config(4, 2, config).map(|xs| decode(&xs)).collect();
+    assert_eq!(batches, [["0123", "2345"], ["4567", "6789"]]);
+    let batches: Vec<_> = text_data.iter_with_config(3, 2, config).map(|xs| decode(&xs)).collect();
+    assert_eq!(batches, [["012", "234"], ["456", "678"]]);
+    let config = data::TextIterConfig { stride: 1, shuffle: false, seed: None };
+    assert_eq!(text_data.iter_with_config(10, 1, config).count(), 1);
+    assert_eq!(text_data.iter_with_config(11, 1, config).count(), 0);
+    assert_eq!(text_data.iter_with_config(9, 1, config).count(), 2);
+
+    let config = data::TextIterConfig { stride: 1, shuffle: true, seed: Some(42) };
+    let epoch1: Vec<_> =
+        text_data.iter_with_config(2, 3, config).flat_map(|xs| decode(&xs)).collect();
+    let epoch2: Vec<_> =
+        text_data.iter_with_config(2, 3, config).flat_map(|xs| decode(&xs)).collect();
+    assert_eq!(epoch1.len(), 9);
+    assert_eq!(epoch1, epoch2);
+    let mut sorted = epoch1.clone();
+    sorted.sort();
+    assert_eq!(sorted, ["01", "12", "23", "34", "45", "56", "67", "78", "89"]);
+
+    let labels = char_map.encode("9870").unwrap();
+    assert_eq!(char_map.decode(&labels).unwrap(), "9870");
+    assert_eq!(char_map.label_to_char(char_map.char_to_label('7').unwrap() as i64), '7');
+    assert!(char_map.encode("a").is_err());
+}
+
+#[test]
+fn text_files() {
+    let dir = std::env::temp_dir();
+    let filename1 = dir.join(format!("tch-{}-1.txt", std::process::id()));
+    let filename2 = dir.join(format!("tch-{}-2.txt", std::process::id()));
+    std::fs::write(&filename1, b"abc").unwrap();
+    std::fs::write(&filename2, b"cde").unwrap();
+    let text_data = data::TextData::from_files(&[&filename1, &filename2]).unwrap();
+    assert_eq!(text_data.labels(), 5);
+    assert_eq!(text_data.char_map().decode(&text_data.data()).unwrap(), "abccde");
+    assert!(data::TextData::from_files(&[dir.join("tch-missing.txt")]).is_err());
+}
+
+#[test]
+fn tensor_dataset() {
+    let xs = Tensor::arange(20, tch::kind::INT64_CPU).view([10, 2]);
+    let ys = Tensor::arange(10, tch::kind::INT64_CPU);
+    assert!(data::TensorDataset::new(xs.shallow_clone(), ys.narrow(0, 0, 9)).is_err());
+    let dataset = data::TensorDataset::new(xs, ys).unwrap();
+    let mut loader = data::DataLoader::new(dataset, 4);
+    loader.num_workers(3);
+    let mut labels = vec![];
+    for batch in loader.iter() {
+        let (xs, ys) = batch.unwrap();
+        let ys = vec_i64_from(&ys);
+        assert_eq!(vec_i64_from(&xs.select(1, 0)), ys.iter().map(|y| 2 * y).collect::<Vec<_>>());
+        labels.push(ys)
+    }
+    assert_eq!(labels, [vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+    // The samples are copies, the dataset tensors are not modified.
+    let (mut x, _) = loader.dataset().get(0).unwrap();
+    let _ = x.fill_(42);
+    assert_eq!(vec_i64_from(&loader.dataset().xs().copy().select(0, 0)), [0, 1]);
+}
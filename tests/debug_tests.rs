@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+use tch::debug::{NanGuard, TensorStats};
+use tch::nn::{self, ModuleT};
+use tch::{Device, TchError, Tensor};
+
+// A network whose middle layer overflows to infinity.
+fn exploding_net(vs: &nn::VarStore, guard: &NanGuard) -> nn::SequentialT {
+    let root = vs.root() / "net";
+    let config = |w| nn::LinearConfig {
+        ws_init: nn::Init::Const(w),
+        bs_init: Some(nn::Init::Const(0.)),
+        bias: true,
+    };
+    let fc1 = nn::linear(&root / "fc1", 4, 4, config(1.));
+    let explode = nn::linear(&root / "explode", 4, 4, config(1e38));
+    let fc2 = nn::linear(&root / "fc2", 4, 2, config(1.));
+    nn::seq_t()
+        .add(guard.wrap(&root / "fc1", fc1))
+        .add_fn(|xs| xs.relu())
+        .add(guard.wrap(&root / "explode", explode))
+        .add(guard.wrap(&root / "fc2", fc2))
+}
+
+#[test]
+fn nan_guard_error() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let guard = NanGuard::new();
+    let net = exploding_net(&vs, &guard);
+    guard.step();
+    guard.step();
+    let xs = Tensor::ones([2, 4], tch::kind::FLOAT_CPU);
+    let report = match net.f_forward_t(&xs, true) {
+        Err(TchError::NonFinite(report)) => report,
+        res => panic!("unexpected result {res:?}"),
+    };
+    assert_eq!(report.module, "net.explode");
+    assert_eq!(report.step, 2);
+    assert_eq!((report.nan_count, report.inf_count), (0, 8));
+    let input = TensorStats { size: vec![2, 4], min: 4., max: 4., mean: 4. };
+    assert_eq!(report.input, input);
+    assert_eq!(guard.first_report().as_ref(), Some(report.as_ref()));
+
+    guard.reset();
+    guard.set_enabled(false);
+    assert!(net.f_forward_t(&xs, true).is_ok());
+    assert_eq!(guard.first_report(), None);
+}
+
+#[test]
+fn nan_guard_callback() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let guard = NanGuard::new();
+    let net = exploding_net(&vs, &guard);
+    let reports = Arc::new(Mutex::new(vec![]));
+    let reports_ = reports.clone();
+    guard.set_callback(move |report| reports_.lock().unwrap().push(report.clone()));
+    let xs = Tensor::ones([2, 4], tch::kind::FLOAT_CPU);
+    for _ in 0..2 {
+        let ys = net.forward_t(&xs, false);
+        assert_eq!(ys.size(), [2, 2]);
+        guard.step()
+    }
+    // The callback is only called for the first module in each step.
+    let reports = reports.lock().unwrap();
+    let modules: Vec<_> = reports.iter().map(|r| (r.module.as_str(), r.step)).collect();
+    assert_eq!(modules, [("net.explode", 0), ("net.explode", 1)]);
+    assert_eq!(guard.first_report().unwrap().step, 0);
+}
@@ -0,0 +1,60 @@
+// This is kept in a separate test binary as the other tests would otherwise
+// use the global random generator concurrently.
+use tch::nn::OptimizerConfig;
+use tch::{kind, nn, Device, Kind, Reduction, Tensor};
+
+fn deterministic_training_run() -> Vec<Tensor> {
+    tch::manual_seed_all(1337);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let model = nn::seq()
+        .add(nn::linear(vs.root() / "l1", 8, 16, Default::default()))
+        .add_fn(|xs| xs.relu())
+        .add(nn::linear(vs.root() / "l2", 16, 1, Default::default()));
+    let mut opt = nn::Adam::default().build(&vs, 1e-2).unwrap();
+    for _ in 0..10 {
+        let xs = Tensor::randn([32, 8], kind::FLOAT_CPU);
+        let ys = xs.sum_dim_intlist(-1, true, Kind::Float);
+        let loss = xs.apply(&model).mse_loss(&ys, Reduction::Mean);
+        opt.backward_step(&loss);
+    }
+    let mut variables = vs.variables().into_iter().collect::<Vec<_>>();
+    variables.sort_by(|(n1, _), (n2, _)| n1.cmp(n2));
+    variables.into_iter().map(|(_, v)| v).collect()
+}
+
+#[test]
+fn deterministic_training() {
+    tch::set_deterministic(true, false);
+    let report = tch::deterministic_report();
+    assert!(report.deterministic_algorithms);
+    assert!(!report.warn_only);
+    let ws1 = deterministic_training_run();
+    let ws2 = deterministic_training_run();
+    assert_eq!(tch::initial_seed(), Some(1337));
+    for (w1, w2) in ws1.iter().zip(ws2.iter()) {
+        assert!(w1.equal(w2));
+    }
+    assert!(tch::deterministic_report().seed.is_some());
+    tch::set_deterministic(false, false);
+    let report = tch::deterministic_report();
+    assert!(!report.deterministic_algorithms);
+    assert!(!report.is_deterministic());
+}
+
+#[test]
+fn replay_rng_state() {
+    tch::manual_seed(42);
+    let xs = Tensor::ones([16, 32], kind::FLOAT_CPU);
+    let (ys1, ys2) = tch::rng::replay_twice(|| xs.dropout(0.5, true));
+    assert!(ys1.equal(&ys2));
+    let ys3 = xs.dropout(0.5, true);
+    assert!(!ys3.equal(&ys1));
+
+    // The prior state is restored after running with a captured state.
+    let state = tch::rng::fork_state();
+    let ys4 = tch::rng::with_rng_state(&state, || xs.dropout(0.5, true));
+    let ys5 = xs.dropout(0.5, true);
+    assert!(ys4.equal(&ys5));
+    tch::rng::restore(&state);
+    assert!(xs.dropout(0.5, true).equal(&ys4));
+}
@@ -1,7 +1,116 @@
-use tch::{Device, Tensor};
+use tch::nn::{Module, OptimizerConfig};
+use tch::{nn, Device, Kind, Tensor};
 
 #[test]
 fn tensor_device() {
     let t = Tensor::from_slice(&[3, 1, 4]);
     assert_eq!(t.device(), Device::Cpu)
 }
+
+#[test]
+fn accelerator_if_available() {
+    let device = Device::accelerator_if_available();
+    if tch::Cuda::is_available() {
+        assert_eq!(device, Device::Cuda(0))
+    } else if tch::Mps::is_available() {
+        assert_eq!(device, Device::Mps)
+    } else {
+        assert_eq!(device, Device::Cpu)
+    }
+}
+
+#[test]
+fn mps_roundtrip() {
+    if !tch::Mps::is_available() {
+        return;
+    }
+    let t = Tensor::from_slice(&[3f32, 1., 4.]).to_device(Device::Mps);
+    assert_eq!(t.device(), Device::Mps);
+    let t = (t * 2.).to_device(Device::Cpu);
+    tch::Mps::synchronize();
+    assert_eq!(Vec::<f32>::try_from(&t).unwrap(), [6., 2., 8.]);
+    // Double precision values are not supported on MPS.
+    assert!(Tensor::from_slice(&[1f64]).f_to_device(Device::Mps).is_err());
+    assert!(Tensor::from_slice(&[1f32]).to_device(Device::Mps).f_to_kind(Kind::Double).is_err());
+}
+
+#[test]
+fn mps_matmul() {
+    let device = Device::accelerator_if_available();
+    if device != Device::Mps {
+        return;
+    }
+    let xs = Tensor::randn([16, 32], (Kind::Float, Device::Cpu));
+    let ys = Tensor::randn([32, 8], (Kind::Float, Device::Cpu));
+    let expected = xs.matmul(&ys);
+    let zs = xs.to_device(device).matmul(&ys.to_device(device));
+    assert_eq!(zs.device(), Device::Mps);
+    assert!(zs.to_device(Device::Cpu).allclose(&expected, 1e-4, 1e-4, false));
+}
+
+#[test]
+fn mps_training_loop() {
+    if !tch::Mps::is_available() {
+        return;
+    }
+    let vs = nn::VarStore::new(Device::Mps);
+    let linear = nn::linear(vs.root(), 1, 1, Default::default());
+    let mut opt = nn::Sgd::default().build(&vs, 0.1).unwrap();
+    let xs = Tensor::arange(8, (Kind::Float, Device::Mps)).view([8, 1]) / 8.;
+    let ys = &xs * 3. + 1.;
+    for _idx in 0..200 {
+        let loss = linear.forward(&xs).mse_loss(&ys, tch::Reduction::Mean);
+        opt.backward_step(&loss);
+    }
+    let loss = linear.forward(&xs).mse_loss(&ys, tch::Reduction::Mean);
+    assert!(loss.to_device(Device::Cpu).double_value(&[]) < 1e-2);
+}
+
+#[test]
+fn vulkan_conv2d() {
+    if !tch::utils::has_vulkan() {
+        assert!(Tensor::from_slice(&[1f32]).f_to_device(Device::Vulkan).is_err());
+        return;
+    }
+    let xs = Tensor::ones([1, 2, 5, 5], (Kind::Float, Device::Cpu));
+    let ws = Tensor::ones([3, 2, 3, 3], (Kind::Float, Device::Cpu));
+    let expected = xs.conv2d::<Tensor>(&ws, None, [1, 1], [0, 0], [1, 1], 1);
+    let xs = xs.to_device(Device::Vulkan);
+    assert_eq!(xs.device(), Device::Vulkan);
+    // The Vulkan convolution prepacks the weights from the CPU.
+    let ys = xs.conv2d::<Tensor>(&ws, None, [1, 1], [0, 0], [1, 1], 1).to_device(Device::Cpu);
+    assert_eq!(ys.size(), [1, 3, 3, 3]);
+    assert!(ys.allclose(&expected, 1e-5, 1e-5, false));
+}
+
+#[test]
+fn backend_flags() {
+    use tch::backends::{cuda, cudnn};
+    // These flags can be set even on CPU-only builds.
+    for b in [true, false] {
+        cudnn::set_benchmark(b);
+        assert_eq!(cudnn::benchmark(), b);
+        cudnn::set_deterministic(b);
+        assert_eq!(cudnn::deterministic(), b);
+        cuda::set_matmul_allow_tf32(b);
+        assert_eq!(cuda::matmul_allow_tf32(), b);
+        cuda::set_cudnn_allow_tf32(b);
+        assert_eq!(cuda::cudnn_allow_tf32(), b);
+    }
+    cuda::set_cudnn_allow_tf32(true);
+    assert_eq!(cudnn::version().is_some(), cudnn::is_available());
+}
+
+#[test]
+fn build_info() {
+    let info = tch::build_info();
+    assert_eq!(info.tch, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.libtorch, tch::utils::version_torch().unwrap());
+    assert_eq!(info.cuda_device_count, tch::Cuda::device_count());
+    if !tch::Cuda::is_available() {
+        assert_eq!(info.cuda_runtime, None);
+        assert_eq!(info.cudnn, None);
+    }
+    assert!(info.to_string().contains(&format!("libtorch: {}", info.libtorch)));
+    assert!(!tch::version::libtorch_config().unwrap().is_empty());
+}
@@ -0,0 +1,183 @@
+#[cfg(test)]
+#[cfg(feature = "distributed")]
+mod tests {
+    use std::sync::Arc;
+    use tch::distributed::{self, Backend, DistributedModel, ProcessGroup, ReduceOp};
+    use tch::nn::parallel::{self, ColumnParallelLinear, ParallelLinearConfig, RowParallelLinear};
+    use tch::nn::{self, Module, ModuleT, OptimizerConfig};
+    use tch::{Device, Kind, Reduction, Tensor};
+
+    const WORLD_SIZE: usize = 2;
+
+    // This is run in the processes spawned by `gloo_two_processes`.
+    #[test]
+    #[ignore]
+    fn gloo_worker() {
+        if std::env::var("RANK").is_err() {
+            return;
+        }
+        distributed::init_process_group_from_env(Backend::Gloo).unwrap();
+        let rank = distributed::rank().unwrap();
+        assert_eq!(distributed::world_size().unwrap(), WORLD_SIZE);
+
+        let mut t = Tensor::from_slice(&[rank as f32 + 1.]);
+        distributed::all_reduce(&mut t, ReduceOp::Sum).unwrap();
+        assert_eq!(Vec::<f32>::try_from(&t).unwrap(), [3.]);
+        let mut t = Tensor::from_slice(&[rank as i64]);
+        distributed::broadcast(&mut t, 1).unwrap();
+        assert_eq!(Vec::<i64>::try_from(&t).unwrap(), [1]);
+        let ts = distributed::all_gather(&Tensor::from_slice(&[rank as i64, 42])).unwrap();
+        let ts = ts.iter().map(|t| Vec::<i64>::try_from(t).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ts, [[0, 42], [1, 42]]);
+        distributed::barrier().unwrap();
+
+        // Each process uses a different seed, the initial weights are broadcast from
+        // rank 0 and the gradients are averaged so that the weights stay in sync.
+        tch::manual_seed(rank as i64);
+        let vs = nn::VarStore::new(Device::Cpu);
+        let linear = nn::linear(vs.root(), 4, 2, Default::default());
+        let model = DistributedModel::new(linear, &vs).unwrap();
+        let mut opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+        for _ in 0..5 {
+            let xs = Tensor::randn([8, 4], (Kind::Float, Device::Cpu));
+            let ys = Tensor::randn([8, 2], (Kind::Float, Device::Cpu));
+            let loss = model.forward_t(&xs, true).mse_loss(&ys, Reduction::Mean);
+            opt.zero_grad();
+            loss.backward();
+            model.reduce_gradients().unwrap();
+            opt.step();
+        }
+        for var in vs.trainable_variables() {
+            let vars = distributed::all_gather(&var.detach()).unwrap();
+            assert!(vars[0].allclose(&vars[1], 1e-6, 1e-6, false));
+        }
+        distributed::destroy_process_group();
+    }
+
+    // Compares tensor parallel layers with a single process reference using
+    // the full weights, the same on all processes as the seed is shared.
+    #[test]
+    #[ignore]
+    fn tensor_parallel_worker() {
+        if std::env::var("RANK").is_err() {
+            return;
+        }
+        let pg = Arc::new(ProcessGroup::from_env(Backend::Gloo).unwrap());
+        let rank = pg.rank() as i64;
+        tch::manual_seed(42);
+        let opts = (Kind::Float, Device::Cpu);
+        let (w1, b1) = (Tensor::randn([8, 6], opts), Tensor::randn([8], opts));
+        let (w2, b2) = (Tensor::randn([6, 8], opts), Tensor::randn([6], opts));
+        let xs = Tensor::randn([5, 6], opts);
+
+        let vs = nn::VarStore::new(Device::Cpu);
+        let config = ParallelLinearConfig { gather_output: false, ..Default::default() };
+        let column =
+            ColumnParallelLinear::new(vs.root() / "fc1", pg.clone(), 6, 8, config).unwrap();
+        let config = ParallelLinearConfig { input_is_parallel: true, ..Default::default() };
+        let row = RowParallelLinear::new(vs.root() / "fc2", pg.clone(), 8, 6, config).unwrap();
+        tch::no_grad(|| {
+            column.ws.shallow_clone().copy_(&w1.narrow(0, 4 * rank, 4));
+            column.bs.as_ref().unwrap().shallow_clone().copy_(&b1.narrow(0, 4 * rank, 4));
+            row.ws.shallow_clone().copy_(&w2.narrow(1, 4 * rank, 4));
+            row.bs.as_ref().unwrap().shallow_clone().copy_(&b2);
+        });
+        assert!(column.gather_full_weight().unwrap().allclose(&w1, 1e-6, 1e-6, false));
+        assert!(row.gather_full_weight().unwrap().allclose(&w2, 1e-6, 1e-6, false));
+
+        let xs_parallel = xs.copy().set_requires_grad(true);
+        let ys = row.forward(&column.forward(&xs_parallel).relu());
+        ys.square().sum(Kind::Float).backward();
+        let xs_ref = xs.copy().set_requires_grad(true);
+        let (w1, w2) = (w1.set_requires_grad(true), w2.set_requires_grad(true));
+        let ys_ref = xs_ref.linear(&w1, Some(&b1)).relu().linear(&w2, Some(&b2));
+        ys_ref.square().sum(Kind::Float).backward();
+        assert!(ys.allclose(&ys_ref, 1e-5, 1e-5, false));
+        assert!(xs_parallel.grad().allclose(&xs_ref.grad(), 1e-4, 1e-4, false));
+        let w1_grad = w1.grad().narrow(0, 4 * rank, 4);
+        assert!(column.ws.grad().allclose(&w1_grad, 1e-4, 1e-4, false));
+        let w2_grad = w2.grad().narrow(1, 4 * rank, 4);
+        assert!(row.ws.grad().allclose(&w2_grad, 1e-4, 1e-4, false));
+
+        // The other configurations gather the outputs and scatter the inputs.
+        let vs2 = nn::VarStore::new(Device::Cpu);
+        let gathered = ColumnParallelLinear::new(vs2.root(), pg.clone(), 6, 8, Default::default());
+        let gathered = gathered.unwrap();
+        let full = gathered.gather_full_weight().unwrap();
+        let ys = gathered.forward(&xs);
+        assert_eq!(ys.size(), [5, 8]);
+        let bs = Tensor::cat(&pg.all_gather(gathered.bs.as_ref().unwrap()).unwrap(), 0);
+        assert!(ys.allclose(&xs.linear(&full, Some(&bs)), 1e-5, 1e-5, false));
+        let scattered =
+            RowParallelLinear::new(vs2.root() / "row", pg.clone(), 6, 3, Default::default());
+        let scattered = scattered.unwrap();
+        let full = scattered.gather_full_weight().unwrap();
+        let ys = scattered.forward(&xs);
+        assert!(ys.allclose(&xs.linear(&full, scattered.bs.as_ref()), 1e-5, 1e-5, false));
+        assert!(RowParallelLinear::new(vs2.root(), pg.clone(), 5, 3, Default::default()).is_err());
+
+        // Each rank saves and loads its own shard.
+        let dir = std::env::var("TP_CHECKPOINT_DIR").unwrap();
+        let path = std::path::Path::new(&dir).join("model.safetensors");
+        parallel::save_shard(&vs, &path, &pg).unwrap();
+        pg.barrier().unwrap();
+        let mut vs3 = nn::VarStore::new(Device::Cpu);
+        let config = ParallelLinearConfig { gather_output: false, ..Default::default() };
+        let column3 = ColumnParallelLinear::new(vs3.root() / "fc1", pg.clone(), 6, 8, config);
+        let config = ParallelLinearConfig { input_is_parallel: true, ..Default::default() };
+        let _row3 = RowParallelLinear::new(vs3.root() / "fc2", pg.clone(), 8, 6, config);
+        parallel::load_shard(&mut vs3, &path, &pg).unwrap();
+        assert_eq!(column3.unwrap().ws, column.ws);
+        // The shard of the other rank is rejected.
+        let other = parallel::shard_path(&path, 1 - rank as usize, WORLD_SIZE);
+        let renamed = std::path::Path::new(&dir).join(format!("other{rank}.safetensors"));
+        std::fs::copy(other, parallel::shard_path(&renamed, rank as usize, WORLD_SIZE)).unwrap();
+        assert!(parallel::load_shard(&mut vs3, &renamed, &pg).is_err());
+    }
+
+    fn spawn_workers(test_name: &str, envs: &[(&str, &str)]) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let exe = std::env::current_exe().unwrap();
+        let children = (0..WORLD_SIZE)
+            .map(|rank| {
+                std::process::Command::new(&exe)
+                    .args([test_name, "--exact", "--ignored", "--nocapture"])
+                    .envs(envs.iter().copied())
+                    .env("MASTER_ADDR", "127.0.0.1")
+                    .env("MASTER_PORT", port.to_string())
+                    .env("RANK", rank.to_string())
+                    .env("WORLD_SIZE", WORLD_SIZE.to_string())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for mut child in children {
+            assert!(child.wait().unwrap().success())
+        }
+    }
+
+    #[test]
+    fn gloo_two_processes() {
+        spawn_workers("tests::gloo_worker", &[])
+    }
+
+    #[test]
+    fn tensor_parallel_two_processes() {
+        let dir = std::env::temp_dir().join(format!("tch-tensor-parallel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        spawn_workers(
+            "tests::tensor_parallel_worker",
+            &[("TP_CHECKPOINT_DIR", dir.to_str().unwrap())],
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_init() {
+        assert!(distributed::rank().is_err());
+        assert!(distributed::init_process_group(Backend::Gloo, "foo://bar", 0, 1).is_err());
+        assert!(distributed::init_process_group(Backend::Gloo, "tcp://localhost", 0, 1).is_err());
+        assert!(distributed::init_process_group(Backend::Gloo, "tcp://localhost:1", 1, 1).is_err());
+        assert!(!distributed::is_initialized());
+    }
+}
@@ -0,0 +1,67 @@
+use tch::nn::{self, VarStoreEma};
+use tch::{Device, Kind};
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn ema_converges() {
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let p = vs.root().zeros("p", &[3]);
+    let _ = vs.root().zeros_no_train("buffer", &[2]);
+    let mut ema = VarStoreEma::new(&vs, 0.9);
+    assert_eq!(ema.averages().len(), 1);
+    tch::no_grad(|| {
+        let _ = p.shallow_clone().fill_(1.);
+    });
+    ema.update(&vs);
+    assert!((f64_from(&ema.averages()["p"].mean(Kind::Float)) - 0.1).abs() < 1e-6);
+
+    // The average converges to a constant parameter.
+    tch::no_grad(|| {
+        let _ = p.shallow_clone().fill_(5.);
+    });
+    for _ in 0..300 {
+        ema.update(&vs);
+    }
+    for v in vec_f64_from(&ema.averages()["p"]) {
+        assert!((v - 5.).abs() < 1e-4);
+    }
+
+    // Variables added after the creation of the average are added lazily.
+    let q = vs.root().ones("q", &[2]);
+    ema.update(&vs);
+    assert_eq!(vec_f64_from(&ema.averages()["q"]), [1., 1.]);
+
+    // Swapping twice restores the training weights.
+    tch::no_grad(|| {
+        let _ = p.shallow_clone().fill_(-1.);
+        let _ = q.shallow_clone().fill_(3.);
+    });
+    ema.swap(&mut vs);
+    assert!((f64_from(&p.mean(Kind::Float)) - 5.).abs() < 1e-4);
+    assert_eq!(vec_f64_from(&q), [1., 1.]);
+    assert_eq!(vec_f64_from(&ema.averages()["p"]), [-1.; 3]);
+    ema.swap(&mut vs);
+    assert_eq!(vec_f64_from(&p), [-1.; 3]);
+    assert_eq!(vec_f64_from(&q), [3., 3.]);
+
+    ema.copy_into(&mut vs);
+    assert_eq!(vec_f64_from(&q), [1., 1.]);
+    let vs2 = nn::VarStore::new(Device::Cpu);
+    assert!(ema.f_update(&vs2).is_err());
+}
+
+#[test]
+fn ema_empty_variables() {
+    // Empty tensors may share their data pointer, the variables are matched
+    // by name.
+    let vs = nn::VarStore::new(Device::Cpu);
+    let _ = vs.root().zeros("a", &[0]);
+    let _ = vs.root().zeros_no_train("b", &[0]);
+    let _ = vs.root().zeros("c", &[0, 2]);
+    let ema = VarStoreEma::new(&vs, 0.9);
+    let mut names: Vec<_> = ema.averages().keys().cloned().collect();
+    names.sort();
+    assert_eq!(names, ["a", "c"]);
+}
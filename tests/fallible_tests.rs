@@ -0,0 +1,81 @@
+// The fallible variants should report invalid inputs as errors rather than
+// panicking, these checks go through the hand-written part of the api.
+use tch::nn::{Module, ModuleT};
+use tch::{nn, Device, IndexOp, Kind, TchError, Tensor};
+
+#[test]
+fn fallible_tensor_ops() {
+    let xs = Tensor::arange(12, (Kind::Float, Device::Cpu)).view([3, 4]);
+    assert!(xs.f_i(5).is_err());
+    assert!(xs.f_i((.., 7)).is_err());
+    assert!(xs.f_i(1..9).is_err());
+    assert!(xs.f_get(3).is_err());
+    assert!(matches!(xs.f_view([5, 5]), Err(TchError::OpFailed { op: "view_", .. })));
+    assert!(matches!(xs.f_zero_pad2d(1, 1, 1, 1), Err(TchError::Shape(_))));
+    assert!(xs.f_onehot(3).is_err());
+    assert!(Tensor::f_random_batch2(&xs, &xs.i(..2), 2).is_err());
+    assert!(xs.f_random_batch(0).is_ok());
+
+    // Nested slices with rows of different lengths.
+    let rows = vec![vec![1f32, 2., 3.], vec![4., 5.], vec![6., 7., 8.]];
+    assert!(matches!(Tensor::f_from_slice2(&rows), Err(TchError::Shape(_))));
+    let rows = [[1i64, 2], [3, 4], [5, 6]];
+    let ys = Tensor::f_from_slice2(&rows).unwrap();
+    assert_eq!(ys.size(), [3, 2]);
+    assert_eq!(Vec::<Vec<i64>>::try_from(&ys).unwrap(), rows);
+    assert_eq!(Tensor::f_from_slice2::<f64, [f64; 0]>(&[]).unwrap().size(), [0, 0]);
+
+    // Undefined tensors.
+    let undefined = Tensor::new();
+    assert!(undefined.f_size().is_err());
+    assert!(undefined.f_dim().is_err());
+    assert!(undefined.f_kind().is_err());
+    assert!(undefined.f_flat_view().is_err());
+    assert!(undefined.f_copy().is_err());
+
+    // Losses with mismatched targets.
+    let logits = Tensor::randn([8, 10], (Kind::Float, Device::Cpu));
+    let targets = Tensor::from_slice(&[0i64, 1, 2]);
+    assert!(logits.f_cross_entropy_for_logits(&targets).is_err());
+    assert!(logits.f_accuracy_for_logits(&targets).is_err());
+    let targets = Tensor::from_slice(&[42i64; 8]);
+    assert!(logits.f_cross_entropy_for_logits(&targets).is_err());
+}
+
+#[test]
+fn fallible_forward() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let root = vs.root();
+    let xs = Tensor::randn([2, 3], (Kind::Float, Device::Cpu));
+    let linear = nn::linear(&root / "linear", 4, 5, Default::default());
+    assert!(linear.f_forward(&xs).is_err());
+    assert!(xs.f_apply(&linear).is_err());
+    let layer_norm = nn::layer_norm(&root / "ln", vec![4], Default::default());
+    assert!(layer_norm.f_forward(&xs).is_err());
+    // Modules without a dedicated implementation have their panics converted.
+    let conv = nn::conv2d(&root / "conv", 3, 4, 3, Default::default());
+    assert!(conv.f_forward(&xs).is_err());
+    let seq = nn::seq_t().add(linear);
+    assert!(seq.f_forward_t(&xs, true).is_err());
+    let xs = Tensor::randn([2, 4], (Kind::Float, Device::Cpu));
+    assert_eq!(seq.f_forward_t(&xs, false).unwrap().size(), [2, 5]);
+}
+
+#[test]
+fn fallible_display() {
+    let xs = Tensor::from_slice(&[1f32, 2., 3.]);
+    let qs = xs.quantize_per_tensor(0.5, 0, Kind::QUInt8);
+    let display = format!("{qs}");
+    assert!(display.contains("QUInt8"), "{display}");
+    let _ = format!("{qs:?}");
+    let _ = format!("{:?}", Tensor::new());
+}
+
+#[test]
+fn cpu_out_of_memory() {
+    // The allocation fails with a catchable error rather than aborting.
+    let err = Tensor::f_empty([1 << 40, 1 << 20], (Kind::Float, Device::Cpu)).unwrap_err();
+    assert!(err.to_string().contains("memory"), "{err}");
+    let xs = Tensor::f_ones([2], (Kind::Float, Device::Cpu)).unwrap();
+    assert_eq!(xs.size(), [2]);
+}
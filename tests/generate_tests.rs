@@ -0,0 +1,150 @@
+use tch::generate::{self, GenerateConfig};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+fn probs(logits: &Tensor) -> Vec<f64> {
+    vec_f64_from(&logits.softmax(-1, Kind::Double))
+}
+
+fn assert_close(xs: &[f64], ys: &[f64]) {
+    assert_eq!(xs.len(), ys.len());
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        assert!((x - y).abs() < 1e-6, "{xs:?} {ys:?}")
+    }
+}
+
+#[test]
+fn top_p_filter() {
+    let logits = Tensor::from_slice(&[0.2f32, 0.5, 0.1, 0.2]).log();
+    // 0.5 alone is below 0.6, ties are broken in favor of the lowest token.
+    assert_close(&probs(&generate::top_p_filter(&logits, 0.6)), &[2. / 7., 5. / 7., 0., 0.]);
+    assert_close(&probs(&generate::top_p_filter(&logits, 0.8)), &[2. / 9., 5. / 9., 0., 2. / 9.]);
+    assert_close(&probs(&generate::top_p_filter(&logits, 0.95)), &[0.2, 0.5, 0.1, 0.2]);
+    // The most likely token is always kept.
+    assert_close(&probs(&generate::top_p_filter(&logits, 0.)), &[0., 1., 0., 0.]);
+    // Each row is filtered independently.
+    let logits = Tensor::from_slice(&[0.6f32, 0.3, 0.1, 0.1, 0.3, 0.6]).log().view([2, 3]);
+    let filtered = generate::top_p_filter(&logits, 0.7);
+    assert_close(&probs(&filtered.get(0)), &[2. / 3., 1. / 3., 0.]);
+    assert_close(&probs(&filtered.get(1)), &[0., 1. / 3., 2. / 3.]);
+}
+
+#[test]
+fn top_k_filter() {
+    let logits = Tensor::from_slice(&[0.2f32, 0.5, 0.1, 0.2]).log();
+    assert_close(&probs(&generate::top_k_filter(&logits, 2)), &[2. / 7., 5. / 7., 0., 0.]);
+    assert_close(&probs(&generate::top_k_filter(&logits, 1)), &[0., 1., 0., 0.]);
+    assert_close(&probs(&generate::top_k_filter(&logits, 10)), &[0.2, 0.5, 0.1, 0.2]);
+}
+
+#[test]
+fn repetition_penalty() {
+    let logits = Tensor::from_slice(&[2f32, -2., 1.]).view([1, 3]);
+    let ids = Tensor::from_slice(&[0i64, 1, 1]).view([1, 3]);
+    let logits = generate::apply_repetition_penalty(&logits, &ids, 2.);
+    assert_eq!(vec_f64_from(&logits), [1., -4., 1.]);
+}
+
+// Always predicts the last token plus one.
+fn next_token(ids: &Tensor) -> Tensor {
+    let last = ids.select(1, -1);
+    ((last + 1).remainder(8)).one_hot(8).to_kind(Kind::Float)
+}
+
+#[test]
+fn greedy_with_eos() {
+    let prompt = Tensor::from_slice(&[0i64, 5]).view([2, 1]);
+    let config = GenerateConfig { max_new_tokens: 5, eos_token: Some(3), ..Default::default() };
+    let ids = generate::generate(&prompt, &mut (), |ids, _| next_token(ids), &config);
+    let ids = Vec::<Vec<i64>>::try_from(&ids).unwrap();
+    assert_eq!(ids, [[0, 1, 2, 3, 3, 3], [5, 6, 7, 0, 1, 2]]);
+
+    // Generation stops once all the sequences are finished.
+    let prompt = Tensor::from_slice(&[0i64, 1]).view([2, 1]);
+    let ids = generate::generate(&prompt, &mut (), |ids, _| next_token(ids), &config);
+    let ids = Vec::<Vec<i64>>::try_from(&ids).unwrap();
+    assert_eq!(ids, [[0, 1, 2, 3], [1, 2, 3, 3]]);
+}
+
+#[test]
+fn sampling_is_deterministic() {
+    let logits = Tensor::randn([4, 32], (Kind::Float, Device::Cpu));
+    let step_fn = |ids: &Tensor, _: &mut ()| logits.narrow(0, 0, ids.size()[0]) * ids.size()[1];
+    let prompt = Tensor::zeros([4, 1], (Kind::Int64, Device::Cpu));
+    let config = GenerateConfig {
+        max_new_tokens: 10,
+        do_sample: true,
+        temperature: 5.,
+        top_k: Some(20),
+        top_p: Some(0.9),
+        repetition_penalty: 1.2,
+        seed: Some(42),
+        ..Default::default()
+    };
+    let ids1 = generate::generate(&prompt, &mut (), step_fn, &config);
+    let ids2 = generate::generate(&prompt, &mut (), step_fn, &config);
+    assert_eq!(ids1.size(), [4, 11]);
+    assert!(ids1.equal(&ids2));
+    let config = GenerateConfig { seed: Some(43), ..config };
+    let ids3 = generate::generate(&prompt, &mut (), step_fn, &config);
+    assert!(!ids1.equal(&ids3));
+
+    // The seed does not reset the generators of the other random operations.
+    let state = tch::rng::fork_state();
+    let xs1 = Tensor::rand([8], (Kind::Float, Device::Cpu));
+    tch::rng::restore(&state);
+    let _ = generate::generate(&prompt, &mut (), step_fn, &config);
+    let xs2 = Tensor::rand([8], (Kind::Float, Device::Cpu));
+    assert!(xs1.equal(&xs2));
+
+    let config = GenerateConfig { temperature: 0., ..config };
+    assert!(generate::f_generate(&prompt, &mut (), step_fn, &config).is_err());
+}
+
+// The next token probabilities only depend on the last token, token 3 is the
+// prompt and token 2 the end of sequence.
+fn beam_step(ids: &Tensor) -> Tensor {
+    let table = Tensor::from_slice(&[
+        0.4f32, 0.3, 0.3, 1e-6, // after 0
+        1e-6, 1e-6, 1., 1e-6, // after 1
+        1e-6, 1e-6, 1., 1e-6, // after 2
+        0.6, 0.4, 1e-6, 1e-6, // after 3
+    ])
+    .view([4, 4])
+    .log();
+    table.index_select(0, &ids.select(1, -1))
+}
+
+#[test]
+fn beam_search() {
+    let prompt = Tensor::from_slice(&[3i64]).view([1, 1]);
+    let config = GenerateConfig { max_new_tokens: 2, eos_token: Some(2), ..Default::default() };
+    let ids = generate::generate(&prompt, &mut (), |ids, _| beam_step(ids), &config);
+    assert_eq!(Vec::<Vec<i64>>::try_from(&ids).unwrap(), [[3, 0, 0]]);
+
+    // Beam search finds the more likely sequence 1, 2 with probability 0.4
+    // rather than 0, 0 with probability 0.24.
+    let config = GenerateConfig { num_beams: 2, ..config };
+    let mut beam_indices = vec![];
+    let mut state = Reorder(&mut beam_indices);
+    let ids = generate::generate(&prompt, &mut state, |ids, _| beam_step(ids), &config);
+    assert_eq!(Vec::<Vec<i64>>::try_from(&ids).unwrap(), [[3, 1, 2]]);
+    assert_eq!(beam_indices, [[0, 0], [0, 0]]);
+
+    // Batched beam search, the shorter sequences are padded.
+    let prompt = Tensor::from_slice(&[3i64, 2]).view([2, 1]);
+    let config = GenerateConfig { max_new_tokens: 3, ..config };
+    let ids = generate::generate(&prompt, &mut (), |ids, _| beam_step(ids), &config);
+    assert_eq!(Vec::<Vec<i64>>::try_from(&ids).unwrap(), [[3, 1, 2], [2, 2, 2]]);
+}
+
+struct Reorder<'a>(&'a mut Vec<Vec<i64>>);
+
+impl generate::State for Reorder<'_> {
+    fn reorder(&mut self, indices: &Tensor) -> Result<(), tch::TchError> {
+        self.0.push(Vec::<i64>::try_from(indices)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use tch::autograd::{self, GradcheckOptions};
+use tch::{Device, Kind, TchError, Tensor};
+
+fn randn(size: &[i64]) -> Tensor {
+    Tensor::randn(size, (Kind::Double, Device::Cpu)).set_requires_grad(true)
+}
+
+#[test]
+fn gradcheck() {
+    let inputs = [randn(&[3, 4]), randn(&[4, 2])];
+    let f = |xs: &[Tensor]| xs[0].matmul(&xs[1]).sin() * &xs[0].sum_dim_intlist(1, true, None);
+    autograd::gradcheck(f, &inputs, Default::default()).unwrap();
+    autograd::gradgradcheck(f, &inputs, Default::default()).unwrap();
+
+    // Single precision inputs are converted to double precision.
+    let xs = Tensor::randn([5], (Kind::Float, Device::Cpu)).set_requires_grad(true);
+    autograd::gradcheck(|xs| xs[0].exp(), &[xs.shallow_clone()], Default::default()).unwrap();
+    // Without the conversion, the tolerances have to account for the rounding
+    // errors of the finite differences.
+    let options = GradcheckOptions { eps: 1e-2, atol: 1e-2, rtol: 1e-2, check_double: false };
+    autograd::gradcheck(|xs| xs[0].exp(), &[xs], options).unwrap();
+
+    // Inputs that do not require grad are not checked.
+    let ys = Tensor::randn([5], (Kind::Double, Device::Cpu));
+    assert!(autograd::gradcheck(|xs| xs[0].exp(), &[ys], Default::default()).is_err());
+}
+
+#[test]
+fn wrong_backward() {
+    // The value of sin with a backward returning twice its derivative.
+    let wrong_sin = |xs: &[Tensor]| {
+        let x = &xs[0];
+        x.sin().detach() + (x - x.detach()) * x.cos().detach() * 2.
+    };
+    let xs = randn(&[2, 3]);
+    match autograd::gradcheck(wrong_sin, &[xs.shallow_clone()], Default::default()) {
+        Err(TchError::Gradcheck(mismatches)) => {
+            assert_eq!(mismatches.len(), 1);
+            let m = &mismatches[0];
+            assert_eq!(m.input, 0);
+            assert_eq!(m.input_index, m.output_index);
+            let cos = xs.cos().double_value(&m.input_index);
+            assert!((m.numerical - cos).abs() < 1e-6, "{m}");
+            assert!((m.analytical - 2. * cos).abs() < 1e-12, "{m}");
+        }
+        res => panic!("unexpected result {res:?}"),
+    }
+
+    // The first order gradient is correct but is not differentiable itself.
+    let wrong_cube = |xs: &[Tensor]| {
+        let x = &xs[0];
+        x.detach().pow_tensor_scalar(3) + (x - x.detach()) * x.detach().square() * 3.
+    };
+    autograd::gradcheck(wrong_cube, &[xs.shallow_clone()], Default::default()).unwrap();
+    let err = autograd::gradgradcheck(wrong_cube, &[xs], Default::default());
+    assert!(matches!(err, Err(TchError::Gradcheck(_))), "{err:?}");
+}
+
+#[test]
+fn non_differentiable_points() {
+    // relu and abs are not differentiable at 0, these elements are skipped.
+    let xs = Tensor::from_slice(&[-1.5, 0., 2.]).set_requires_grad(true);
+    autograd::gradcheck(|xs| xs[0].relu(), &[xs.shallow_clone()], Default::default()).unwrap();
+    autograd::gradcheck(|xs| xs[0].abs(), &[xs], Default::default()).unwrap();
+}
+
+#[test]
+fn losses() {
+    let logits = randn(&[4, 5]);
+    let targets = Tensor::from_slice(&[0i64, 3, 4, 1]);
+    let inputs = [logits, targets];
+    let options = GradcheckOptions::default();
+    autograd::gradcheck(|xs| xs[0].cross_entropy_for_logits(&xs[1]), &inputs, options).unwrap();
+    let nll = |xs: &[Tensor]| xs[0].log_softmax(-1, None).nll_loss(&xs[1]);
+    autograd::gradcheck(nll, &inputs, options).unwrap();
+    autograd::gradgradcheck(nll, &inputs, options).unwrap();
+    let mse = |xs: &[Tensor]| xs[0].mse_loss(&xs[1], tch::Reduction::Mean);
+    autograd::gradcheck(mse, &[randn(&[3, 2]), randn(&[3, 2])], options).unwrap();
+}
@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+use tch::interrupt::{self, CancellationToken};
+use tch::nn::{self, ModuleT};
+use tch::{Device, Kind, TchError, Tensor};
+
+// Runs ops until the token is cancelled.
+fn long_loop(token: &CancellationToken) -> Result<(), TchError> {
+    let mut xs = Tensor::randn([64, 64], (Kind::Float, Device::Cpu));
+    loop {
+        token.check()?;
+        xs = xs.f_matmul(&xs)?.f_tanh()?;
+    }
+}
+
+#[test]
+fn timeout() {
+    let token = CancellationToken::new();
+    let start = Instant::now();
+    let res = interrupt::run_with_timeout(Duration::from_millis(50), &token, long_loop);
+    assert!(matches!(res, Err(TchError::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // The parent token is not cancelled by the timeout.
+    assert!(!token.is_cancelled());
+    let res = interrupt::run_with_timeout(Duration::from_secs(60), &token, |t| {
+        t.check()?;
+        Ok(42)
+    });
+    assert_eq!(res.unwrap(), 42);
+}
+
+#[test]
+fn cancel_from_another_thread() {
+    let token = CancellationToken::new();
+    let start = Instant::now();
+    let res = std::thread::scope(|s| {
+        let t = token.clone();
+        s.spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            t.cancel()
+        });
+        interrupt::run_with_timeout(Duration::from_secs(60), &token, long_loop)
+    });
+    assert!(matches!(res, Err(TchError::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(token.is_cancelled());
+    // A cancelled token stays cancelled.
+    let res = interrupt::run_with_timeout(Duration::from_secs(60), &token, |_| Ok(()));
+    assert!(matches!(res, Err(TchError::Cancelled)));
+}
+
+#[test]
+fn wrapped_modules() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let token = CancellationToken::new();
+    let net = nn::seq_t()
+        .add(token.wrap(nn::linear(vs.root() / "fc1", 4, 4, Default::default())))
+        .add(token.wrap(nn::linear(vs.root() / "fc2", 4, 2, Default::default())));
+    let xs = Tensor::ones([3, 4], (Kind::Float, Device::Cpu));
+    assert_eq!(net.f_forward_t(&xs, false).unwrap().size(), [3, 2]);
+    token.cancel();
+    assert!(matches!(net.f_forward_t(&xs, false), Err(TchError::Cancelled)));
+}
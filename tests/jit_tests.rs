@@ -188,3 +188,25 @@ fn specialized_dict() {
     assert_eq!(Vec::<f64>::try_from(&result.0).unwrap(), [1.0, 2.0, 3.0]);
     assert_eq!(Vec::<f64>::try_from(&result.1).unwrap(), [1.0, 7.0])
 }
+
+#[test]
+fn named_buffers() {
+    let mod_ = tch::CModule::load("tests/foo10.pt").unwrap();
+    let buffers = mod_.named_buffers().unwrap();
+    let names: Vec<_> = buffers.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["bn.running_mean", "bn.running_var", "bn.num_batches_tracked"]);
+    let parameters = mod_.named_parameters().unwrap();
+    let names: Vec<_> = parameters.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["bn.weight", "bn.bias"]);
+
+    let running_mean = mod_.get_buffer("bn.running_mean").unwrap();
+    assert_eq!(running_mean.size(), [3]);
+    assert_eq!(vec_f64_from(&running_mean), [0.5, -1.0, 2.0]);
+    assert_eq!(i64::try_from(mod_.get_buffer("bn.num_batches_tracked").unwrap()).unwrap(), 7);
+    assert!(mod_.get_buffer("bn.running_std").is_err());
+
+    // The module normalizes its input with the running statistics.
+    let xs = Tensor::from_slice(&[1.5f32, 1.0, 2.5]).view([1, 3]);
+    let ys = mod_.forward_ts(&[xs]).unwrap();
+    assert!(ys.allclose(&Tensor::ones([1, 3], tch::kind::FLOAT_CPU), 1e-4, 1e-4, false));
+}
@@ -0,0 +1,155 @@
+use tch::nn::{self, CacheConfig, KvCache, Module};
+use tch::{Device, Kind, Tensor};
+
+const VOCAB: i64 = 16;
+const DIM: i64 = 32;
+const HEADS: i64 = 4;
+const LAYERS: usize = 2;
+const MAX_POS: i64 = 32;
+
+struct Block {
+    qkv: nn::Linear,
+    proj: nn::Linear,
+    ln: nn::LayerNorm,
+}
+
+// A small decoder-only transformer.
+struct Transformer {
+    wte: nn::Embedding,
+    wpe: nn::Embedding,
+    blocks: Vec<Block>,
+    head: nn::Linear,
+}
+
+impl Transformer {
+    fn new(vs: &nn::Path) -> Transformer {
+        let blocks = (0..LAYERS)
+            .map(|i| Block {
+                qkv: nn::linear(vs / i / "qkv", DIM, 3 * DIM, Default::default()),
+                proj: nn::linear(vs / i / "proj", DIM, DIM, Default::default()),
+                ln: nn::layer_norm(vs / i / "ln", vec![DIM], Default::default()),
+            })
+            .collect();
+        Transformer {
+            wte: nn::embedding(vs / "wte", VOCAB, DIM, Default::default()),
+            wpe: nn::embedding(vs / "wpe", MAX_POS, DIM, Default::default()),
+            blocks,
+            head: nn::linear(vs / "head", DIM, VOCAB, Default::default()),
+        }
+    }
+
+    // Runs the tokens at positions `offset..offset+t`, either with a cache
+    // holding the previous positions or on the full sequence with a mask.
+    fn forward(&self, tokens: &Tensor, mut cache: Option<&mut KvCache>, mask: &Tensor) -> Tensor {
+        let (b, t) = tokens.size2().unwrap();
+        let offset = cache.as_ref().map_or(0, |c| c.len());
+        let pos = Tensor::arange_start(offset, offset + t, (Kind::Int64, Device::Cpu));
+        let mut xs = self.wte.forward(tokens) + self.wpe.forward(&pos);
+        for (i, block) in self.blocks.iter().enumerate() {
+            let qkv = block.qkv.forward(&block.ln.forward(&xs));
+            let heads = |t: &Tensor| t.view([b, -1, HEADS, DIM / HEADS]).transpose(1, 2);
+            let qkv = qkv.chunk(3, 2);
+            let (q, k, v) = (heads(&qkv[0]), heads(&qkv[1]), heads(&qkv[2]));
+            let (k, v) = match cache.as_mut() {
+                Some(cache) => cache.append(i, &k, &v),
+                None => (k, v),
+            };
+            let ys = Tensor::scaled_dot_product_attention(&q, &k, &v, Some(mask), 0., false);
+            xs += block.proj.forward(&ys.transpose(1, 2).contiguous().view([b, t, DIM]));
+        }
+        self.head.forward(&xs)
+    }
+}
+
+// The mask for queries at positions `offset..offset+t` attending to keys at
+// positions `start..offset+t`, each query attends to the previous `window`
+// positions including itself.
+fn mask(offset: i64, t: i64, start: i64, window: i64) -> Tensor {
+    let i = Tensor::arange_start(offset, offset + t, (Kind::Int64, Device::Cpu)).unsqueeze(1);
+    let j = Tensor::arange_start(start, offset + t, (Kind::Int64, Device::Cpu)).unsqueeze(0);
+    j.le_tensor(&i).logical_and(&(&i - &j).lt(window))
+}
+
+fn config(max_seq_len: i64, sliding_window: bool) -> CacheConfig {
+    CacheConfig {
+        max_seq_len,
+        num_heads: HEADS,
+        head_dim: DIM / HEADS,
+        batch: 2,
+        sliding_window,
+        ..Default::default()
+    }
+}
+
+fn check_incremental(window: i64, sliding_window: bool) {
+    tch::manual_seed(0);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let model = Transformer::new(&vs.root());
+    let tokens = Tensor::randint(VOCAB, [2, 24], (Kind::Int64, Device::Cpu));
+    let _no_grad = tch::no_grad_guard();
+    let full = model.forward(&tokens, None, &mask(0, 24, 0, window));
+
+    let mut cache = KvCache::new(LAYERS, config(window, sliding_window));
+    // A prefill of the first tokens followed by one token at a time.
+    let prefill = 4;
+    let mut logits = vec![model.forward(
+        &tokens.narrow(1, 0, prefill),
+        Some(&mut cache),
+        &mask(0, prefill, 0, window),
+    )];
+    for pos in prefill..24 {
+        let start = (pos + 1 - window).max(0);
+        let mask = mask(pos, 1, start, window);
+        logits.push(model.forward(&tokens.narrow(1, pos, 1), Some(&mut cache), &mask));
+    }
+    assert_eq!(cache.len(), 24);
+    let logits = Tensor::cat(&logits, 1);
+    assert!(logits.allclose(&full, 1e-5, 1e-5, false));
+}
+
+#[test]
+fn incremental_decoding() {
+    check_incremental(MAX_POS, false)
+}
+
+#[test]
+fn sliding_window() {
+    check_incremental(8, true)
+}
+
+#[test]
+fn max_seq_len() {
+    let mut cache = KvCache::new(1, config(4, false));
+    let kv = Tensor::ones([2, HEADS, 3, DIM / HEADS], (Kind::Float, Device::Cpu));
+    assert!(cache.f_append(0, &kv, &kv).is_ok());
+    assert!(cache.f_append(0, &kv, &kv).is_err());
+    assert!(cache.f_append(1, &kv, &kv).is_err());
+    let kv = Tensor::ones([1, HEADS, 1, DIM / HEADS], (Kind::Float, Device::Cpu));
+    assert!(cache.f_append(0, &kv, &kv).is_err());
+}
+
+#[test]
+fn reorder_and_crop() {
+    let mut cache = KvCache::new(1, config(8, false));
+    let kv = Tensor::arange(2 * HEADS * 5 * DIM / HEADS, (Kind::Float, Device::Cpu)).view([
+        2,
+        HEADS,
+        5,
+        DIM / HEADS,
+    ]);
+    let (k, v) = cache.append(0, &kv, &(-&kv));
+    assert_eq!(k.size(), [2, HEADS, 5, DIM / HEADS]);
+    assert!(k.equal(&kv) && v.equal(&(-&kv)));
+
+    cache.reorder(&Tensor::from_slice(&[1i64, 1]));
+    let (k, _) = cache.f_get(0).unwrap();
+    assert!(k.get(0).equal(&kv.get(1)) && k.get(1).equal(&kv.get(1)));
+
+    cache.crop(3);
+    assert_eq!(cache.len(), 3);
+    let new_kv = Tensor::zeros([2, HEADS, 1, DIM / HEADS], (Kind::Float, Device::Cpu));
+    let (k, _) = cache.append(0, &new_kv, &new_kv);
+    assert_eq!(k.size(), [2, HEADS, 4, DIM / HEADS]);
+    assert!(k.get(0).narrow(1, 0, 3).equal(&kv.get(1).narrow(1, 0, 3)));
+    assert_eq!(f64::try_from(k.narrow(2, 3, 1).abs().sum(Kind::Float)).unwrap(), 0.);
+}
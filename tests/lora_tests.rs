@@ -0,0 +1,113 @@
+use tch::nn::lora::{self, LoraConfig, LoraConv2D, LoraLinear, LoraTargets};
+use tch::nn::{self, ModuleT, OptimizerConfig};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn merge_linear() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root() / "fc", 4, 3, Default::default());
+    let base_ws = linear.ws.copy();
+    let config = LoraConfig { r: 2, alpha: 4., dropout: 0.5 };
+    let mut layer = LoraLinear::wrap(&vs, linear, config).unwrap();
+    let variables = vs.variables();
+    assert!(variables.contains_key("fc.lora_a.weight"));
+    assert!(variables.contains_key("fc.lora_b.weight"));
+    let (_, b) = layer.adapter_weights().unwrap();
+    tch::no_grad(|| b.shallow_clone().copy_(&Tensor::randn([3, 2], tch::kind::FLOAT_CPU)));
+
+    let xs = Tensor::randn([5, 4], tch::kind::FLOAT_CPU);
+    let ys = layer.forward_t(&xs, false);
+    layer.merge();
+    assert!(layer.is_merged());
+    assert!(!layer.base.ws.allclose(&base_ws, 1e-5, 1e-5, false));
+    assert!(layer.forward_t(&xs, false).allclose(&ys, 1e-5, 1e-5, false));
+    layer.unmerge();
+    assert!(layer.base.ws.allclose(&base_ws, 1e-5, 1e-5, false));
+    assert!(layer.forward_t(&xs, false).allclose(&ys, 1e-5, 1e-5, false));
+}
+
+#[test]
+fn merge_conv2d() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let conv_config =
+        nn::ConvConfig { padding: nn::Padding::Explicit(1), stride: 2, ..Default::default() };
+    let conv = nn::conv2d(vs.root() / "conv", 3, 4, 3, conv_config);
+    let config = LoraConfig { r: 2, ..Default::default() };
+    let mut layer = LoraConv2D::wrap(&vs, conv, config).unwrap();
+    let (_, b) = layer.adapter_weights();
+    tch::no_grad(|| b.shallow_clone().copy_(&Tensor::randn([4, 2, 1, 1], tch::kind::FLOAT_CPU)));
+
+    let xs = Tensor::randn([2, 3, 7, 7], tch::kind::FLOAT_CPU);
+    let ys = layer.forward_t(&xs, false);
+    assert_eq!(ys.size(), [2, 4, 4, 4]);
+    layer.merge();
+    assert!(layer.forward_t(&xs, false).allclose(&ys, 1e-4, 1e-4, false));
+
+    let grouped = nn::ConvConfig { groups: 3, ..Default::default() };
+    let conv = nn::conv2d(vs.root() / "grouped", 3, 3, 3, grouped);
+    assert!(LoraConv2D::wrap(&vs, conv, config).is_err());
+}
+
+#[test]
+fn train_adapters_only() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root(), 4, 1, Default::default());
+    let base_ws = linear.ws.copy();
+    let base_bs = linear.bs.as_ref().unwrap().copy();
+    let layer = LoraLinear::wrap(&vs, linear, LoraConfig { r: 2, ..Default::default() }).unwrap();
+    assert_eq!(vs.trainable_variables().len(), 2);
+    let (a, b) = layer.adapter_weights().unwrap();
+    let a_init = a.copy();
+
+    let mut opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+    let xs = Tensor::randn([16, 4], tch::kind::FLOAT_CPU);
+    let ys = xs.sum_dim_intlist(1, true, Kind::Float);
+    for _ in 0..5 {
+        let loss = layer.forward_t(&xs, true).mse_loss(&ys, tch::Reduction::Mean);
+        opt.backward_step(&loss);
+    }
+    assert_eq!(vec_f64_from(&layer.base.ws), vec_f64_from(&base_ws));
+    assert_eq!(vec_f64_from(layer.base.bs.as_ref().unwrap()), vec_f64_from(&base_bs));
+    assert!(f64_from(&b.abs().sum(Kind::Float)) > 0.);
+    assert_ne!(vec_f64_from(a), vec_f64_from(&a_init));
+}
+
+#[test]
+fn targets_and_adapter_checkpoints() {
+    let targets = LoraTargets::new(LoraConfig { r: 1, ..Default::default() }).pattern("*.attn.*");
+    let build = |vs: &nn::VarStore| {
+        let root = vs.root();
+        let q = lora::linear(&root / "enc" / "attn" / "q", 3, 3, Default::default(), &targets);
+        let mlp = lora::linear(&root / "enc" / "mlp", 3, 3, Default::default(), &targets);
+        (q, mlp)
+    };
+    let vs = nn::VarStore::new(Device::Cpu);
+    let (q, mlp) = build(&vs);
+    assert!(q.has_adapter());
+    assert!(!mlp.has_adapter());
+    assert!(targets.matches("enc.attn.q"));
+    assert!(!targets.matches("enc.mlp"));
+    let (_, b) = q.adapter_weights().unwrap();
+    tch::no_grad(|| {
+        let _ = b.shallow_clone().fill_(0.5);
+    });
+
+    let filename = std::env::temp_dir().join(format!("tch-lora-adapters-{}", std::process::id()));
+    lora::save_adapters(&vs, &filename).unwrap();
+    let mut names: Vec<_> =
+        Tensor::load_multi(&filename).unwrap().into_iter().map(|(name, _)| name).collect();
+    names.sort();
+    assert_eq!(names, ["enc.attn.q.lora_a.weight", "enc.attn.q.lora_b.weight"]);
+
+    let mut vs2 = nn::VarStore::new(Device::Cpu);
+    let (q2, _) = build(&vs2);
+    lora::load_adapters(&mut vs2, &filename).unwrap();
+    let (a, _) = q.adapter_weights().unwrap();
+    let (a2, b2) = q2.adapter_weights().unwrap();
+    assert_eq!(vec_f64_from(a2), vec_f64_from(a));
+    assert_eq!(vec_f64_from(b2), [0.5; 3]);
+    std::fs::remove_file(filename).unwrap();
+}
@@ -0,0 +1,72 @@
+use tch::nn::loss::{chunked_cross_entropy, f_chunked_cross_entropy};
+use tch::{kind, Kind, Reduction, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn chunked_cross_entropy_parity() {
+    tch::manual_seed(0);
+    let hidden = Tensor::randn([2, 3, 5], kind::DOUBLE_CPU).set_requires_grad(true);
+    let weight = Tensor::randn([11, 5], kind::DOUBLE_CPU).set_requires_grad(true);
+    let targets = Tensor::from_slice(&[0i64, 3, 10, -100, 7, 4]).view([2, 3]);
+    for reduction in [Reduction::Mean, Reduction::Sum, Reduction::None] {
+        let logits = hidden.matmul(&weight.tr());
+        let expected = logits.view([-1, 11]).cross_entropy_loss::<Tensor>(
+            &targets.view([-1]),
+            None,
+            reduction,
+            -100,
+            0.,
+        );
+        let expected_grads =
+            Tensor::run_backward(&[expected.sum(Kind::Double)], &[&hidden, &weight], false, false);
+        // An uneven number of chunks, a single chunk and one chunk per class.
+        for chunk_size in [4, 11, 16, 1] {
+            let loss =
+                chunked_cross_entropy(&hidden, &weight, &targets, chunk_size, -100, reduction);
+            let expected = match reduction {
+                Reduction::None => expected.view([2, 3]),
+                _ => expected.shallow_clone(),
+            };
+            assert!(loss.allclose(&expected, 1e-9, 1e-9, false));
+            let grads =
+                Tensor::run_backward(&[loss.sum(Kind::Double)], &[&hidden, &weight], false, false);
+            for (grad, expected) in grads.iter().zip(expected_grads.iter()) {
+                assert!(grad.allclose(expected, 1e-9, 1e-9, false));
+            }
+        }
+    }
+    let none = chunked_cross_entropy(&hidden, &weight, &targets, 4, -100, Reduction::None);
+    assert_eq!(f64_from(&none.get(1).get(0)), 0.);
+    let bad_targets = Tensor::zeros([6], kind::INT64_CPU);
+    assert!(
+        f_chunked_cross_entropy(&hidden, &weight, &bad_targets, 4, -100, Reduction::Mean).is_err()
+    );
+    assert!(
+        f_chunked_cross_entropy(&hidden, &weight.tr(), &targets, 4, -100, Reduction::Mean).is_err()
+    );
+    assert!(f_chunked_cross_entropy(&hidden, &weight, &targets, 0, -100, Reduction::Mean).is_err());
+}
+
+#[test]
+#[cfg(feature = "cuda-tests")]
+fn chunked_cross_entropy_memory() {
+    let device = tch::Device::Cuda(0);
+    let (n, dim, vocab, chunk_size) = (1024, 64, 131072, 8192);
+    let hidden = Tensor::randn([n, dim], (Kind::Float, device)).set_requires_grad(true);
+    let weight = Tensor::randn([vocab, dim], (Kind::Float, device)).set_requires_grad(true);
+    let targets = Tensor::randint(vocab, [n], (Kind::Int64, device));
+    tch::Cuda::synchronize(0);
+    let before = tch::cuda::memory_allocated(device).unwrap();
+    tch::cuda::reset_peak_memory_stats(device).unwrap();
+    let loss = chunked_cross_entropy(&hidden, &weight, &targets, chunk_size, -100, Reduction::Mean);
+    loss.backward();
+    tch::Cuda::synchronize(0);
+    let peak = tch::cuda::max_memory_allocated(device).unwrap() - before;
+    // The full logits would take 512MB on their own, the logits of a chunk and
+    // their gradients take 32MB each, as do the weight gradients.
+    let logits_bytes = n * vocab * 4;
+    assert!(peak < logits_bytes / 2, "peak {peak} logits {logits_bytes}");
+    assert!(weight.grad().defined());
+}
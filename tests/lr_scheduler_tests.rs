@@ -0,0 +1,100 @@
+use tch::nn::lr_scheduler::{
+    CosineAnnealingLR, LinearWarmup, MultiStepLR, OneCycleLR, Scheduler, StepLR,
+};
+use tch::nn::{self, OptimizerConfig};
+use tch::Device;
+
+// The reference values were obtained by stepping the corresponding PyTorch
+// schedulers, for an optimizer with a learning rate of 0.1.
+fn check<S: Scheduler>(scheduler: &S, expected: &[f64]) {
+    for (step, &lr) in expected.iter().enumerate() {
+        let got = scheduler.lr(0.1, step as i64);
+        assert!((got - lr).abs() < 1e-10, "step {step}: {got} <> {lr}");
+    }
+}
+
+#[test]
+fn step_lr() {
+    #[rustfmt::skip]
+    let expected = [
+        0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.05, 0.05, 0.05, 0.05, 0.05, 0.05, 0.05, 0.025,
+        0.025, 0.025, 0.025, 0.025, 0.025, 0.025, 0.0125, 0.0125, 0.0125, 0.0125, 0.0125, 0.0125,
+        0.0125, 0.00625, 0.00625,
+    ];
+    check(&StepLR::new(7, 0.5), &expected);
+}
+
+#[test]
+fn multi_step_lr() {
+    let mut expected = vec![0.1; 5];
+    expected.extend([0.01; 7]);
+    expected.extend([0.001; 8]);
+    expected.extend([0.0001; 10]);
+    check(&MultiStepLR::new(&[5, 12, 20], 0.1), &expected);
+}
+
+#[test]
+fn cosine_annealing_lr() {
+    #[rustfmt::skip]
+    let expected = [
+        0.1, 0.09757729755661011, 0.0905463412215599, 0.07959536998847742, 0.0657963412215599,
+        0.0505, 0.03520365877844011, 0.02140463001152259, 0.010453658778440109,
+        0.0034227024433899004, 0.001, 0.0034227024433899004, 0.01045365877844014,
+        0.02140463001152267, 0.035203658778440255, 0.05050000000000022, 0.06579634122156018,
+        0.07959536998847777, 0.09054634122156031, 0.09757729755661054, 0.10000000000000045,
+        0.09757729755661054, 0.09054634122156033, 0.07959536998847778, 0.0657963412215602,
+        0.050500000000000225, 0.03520365877844027, 0.021404630011522683, 0.010453658778440154,
+        0.003422702443389916,
+    ];
+    check(&CosineAnnealingLR::new(10, 0.001), &expected);
+}
+
+#[test]
+fn one_cycle_lr() {
+    #[rustfmt::skip]
+    let expected = [
+        0.0040000000000000036, 0.0076537824394582454, 0.018058874503045733, 0.03363119524647569,
+        0.052000000000000005, 0.0703688047535243, 0.08594112549695429, 0.09634621756054176, 0.1,
+        0.09944154354509119, 0.0977786491747459, 0.09504846320134738, 0.09131197346804489,
+        0.08665264698111695, 0.0811745653949763, 0.0750001, 0.06826717815011489,
+        0.06112620219362893, 0.0537366897333025, 0.046263710266697504, 0.03887419780637107,
+        0.03173322184988512, 0.025000300000000013, 0.0188258346050237, 0.01334775301888306,
+        0.008688426531955128, 0.004951936798652629, 0.002221750825254118, 0.0005588564549088189,
+        4e-07,
+    ];
+    check(&OneCycleLR::new(30), &expected);
+}
+
+#[test]
+fn linear_warmup() {
+    #[rustfmt::skip]
+    let expected = [
+        0.010000000000000002, 0.028000000000000004, 0.046000000000000006, 0.064, 0.082, 0.1,
+        0.0993844170297569, 0.09755282581475769, 0.0945503262094184, 0.0904508497187474,
+        0.08535533905932739, 0.07938926261462367, 0.07269952498697735, 0.06545084971874739,
+        0.05782172325201156, 0.05000000000000001, 0.04217827674798848, 0.03454915028125264,
+        0.027300475013022667, 0.020610737385376353, 0.014644660940672629, 0.009549150281252633,
+        0.005449673790581611, 0.0024471741852423235, 0.0006155829702431171, 0.0,
+        0.0006155829702431115, 0.0024471741852423123, 0.005449673790581605, 0.009549150281252621,
+    ];
+    check(&LinearWarmup::new(CosineAnnealingLR::new(20, 0.), 5, 0.1), &expected);
+}
+
+#[test]
+fn param_groups() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let _ = vs.root().zeros("w0", &[2]);
+    let _ = vs.root().set_group(1).zeros("w1", &[2]);
+    let mut opt = nn::Sgd::default().build(&vs, 0.1).unwrap();
+    opt.set_lr_group(1, 0.01);
+    assert_eq!(opt.num_groups(), 2);
+    let mut scheduler = StepLR::new(2, 0.5);
+    for step in 0..5 {
+        scheduler.step(&mut opt, step);
+    }
+    // Each group is scaled from its own base learning rate.
+    assert_eq!(opt.lr_group(0), 0.025);
+    assert_eq!(opt.lr_group(1), 0.0025);
+    opt.set_lr(0.3);
+    assert_eq!(opt.lr_group(1), 0.3);
+}
@@ -0,0 +1,115 @@
+use tch::metrics::{Accuracy, AuRoc, ConfusionMatrix, MeanMetric, Metric};
+use tch::Tensor;
+
+fn assert_close(x: f64, y: f64) {
+    assert!((x - y).abs() < 1e-9, "{x} {y}")
+}
+
+fn assert_all_close(xs: &[f64], ys: &[f64]) {
+    assert_eq!(xs.len(), ys.len());
+    for (&x, &y) in xs.iter().zip(ys.iter()) {
+        assert_close(x, y)
+    }
+}
+
+#[test]
+fn accuracy() {
+    let logits =
+        Tensor::from_slice2(&[[2.0, 1.0, 0.1], [0.5, 0.2, 1.5], [0.1, 0.3, 0.2], [1.0, 2.0, 3.0]]);
+    let targets = Tensor::from_slice(&[0i64, 0, 0, 2]);
+    let mut top1 = Accuracy::new();
+    let mut top2 = Accuracy::topk(2);
+    top1.update(&logits, &targets).unwrap();
+    top2.update(&logits, &targets).unwrap();
+    assert_close(top1.compute().unwrap(), 0.5);
+    assert_close(top2.compute().unwrap(), 0.75);
+
+    // Merging is equivalent to updating a single accumulator.
+    let mut other = Accuracy::new();
+    other.update(&logits.narrow(0, 0, 1), &targets.narrow(0, 0, 1)).unwrap();
+    top1.merge(&other).unwrap();
+    assert_close(top1.compute().unwrap(), 0.6);
+    top1.reset();
+    assert_close(top1.compute().unwrap(), 0.);
+}
+
+#[test]
+fn confusion_matrix() {
+    // The expected values are the ones given by sklearn.metrics for these
+    // labels: confusion_matrix, precision_recall_fscore_support with
+    // average=None, "macro" and "micro", and accuracy_score.
+    let targets = [0i64, 1, 2, 2, 1, 0, 1, 2, 0, 2];
+    let preds = [0i64, 2, 2, 2, 1, 0, 0, 1, 0, 2];
+    let mut confusion = ConfusionMatrix::new(3);
+    // Updating with two batches, the second one with scores.
+    confusion.update(&Tensor::from_slice(&preds[..4]), &Tensor::from_slice(&targets[..4])).unwrap();
+    let scores = Tensor::from_slice(&preds[4..]).one_hot(3);
+    confusion.update(&scores, &Tensor::from_slice(&targets[4..])).unwrap();
+    let report = confusion.compute().unwrap();
+    assert_eq!(report.matrix, [[3, 0, 0], [1, 1, 1], [0, 1, 3]]);
+    assert_eq!(report.support, [3, 3, 4]);
+    assert_all_close(&report.precision, &[0.75, 0.5, 0.75]);
+    assert_all_close(&report.recall, &[1.0, 1. / 3., 0.75]);
+    assert_all_close(&report.f1, &[6. / 7., 0.4, 0.75]);
+    assert_close(report.accuracy, 0.7);
+    assert_close(report.macro_precision, 2. / 3.);
+    assert_close(report.macro_recall, 25. / 36.);
+    assert_close(report.macro_f1, 0.669047619047619);
+    assert_close(report.micro_precision, 0.7);
+    assert_close(report.micro_recall, 0.7);
+    assert_close(report.micro_f1, 0.7);
+
+    let mut other = ConfusionMatrix::new(3);
+    other.update(&Tensor::from_slice(&[1i64]), &Tensor::from_slice(&[1i64])).unwrap();
+    confusion.merge(&other).unwrap();
+    assert_eq!(confusion.compute().unwrap().matrix, [[3, 0, 0], [1, 2, 1], [0, 1, 3]]);
+    assert!(confusion.merge(&ConfusionMatrix::new(4)).is_err());
+    // Out of range classes are rejected rather than counted in another cell.
+    assert!(other.update(&Tensor::from_slice(&[3i64]), &Tensor::from_slice(&[1i64])).is_err());
+    assert!(other.update(&Tensor::from_slice(&[1i64]), &Tensor::from_slice(&[-1i64])).is_err());
+    assert_eq!(other.compute().unwrap().matrix, [[0, 0, 0], [0, 1, 0], [0, 0, 0]]);
+
+    // Classes without predictions have a precision of zero.
+    let mut confusion = ConfusionMatrix::new(2);
+    confusion.update(&Tensor::from_slice(&[0i64, 0]), &Tensor::from_slice(&[0i64, 1])).unwrap();
+    let report = confusion.compute().unwrap();
+    assert_all_close(&report.precision, &[0.5, 0.]);
+    assert_all_close(&report.f1, &[2. / 3., 0.]);
+}
+
+#[test]
+fn mean_metric() {
+    let mut mean = MeanMetric::new();
+    mean.update(&Tensor::from_slice(&[1f32, 2., 3.])).unwrap();
+    mean.update_weighted(&Tensor::from(6f32), 2.).unwrap();
+    assert_close(mean.compute().unwrap(), 18. / 5.);
+    let mut other = MeanMetric::new();
+    other.update(&Tensor::from_slice(&[0f32])).unwrap();
+    mean.merge(&other).unwrap();
+    assert_close(mean.compute().unwrap(), 3.);
+}
+
+#[test]
+fn auroc() {
+    // The expected value is the one given by sklearn.metrics.roc_auc_score,
+    // the scores include ties between positive and negative samples.
+    let targets = Tensor::from_slice(&[0i64, 0, 1, 1, 0, 1, 1, 0, 1, 0]);
+    let scores = Tensor::from_slice(&[0.1, 0.4, 0.35, 0.8, 0.4, 0.7, 0.4, 0.2, 0.9, 0.6]);
+    let mut auroc = AuRoc::new();
+    auroc.update(&scores.narrow(0, 0, 5), &targets.narrow(0, 0, 5)).unwrap();
+    let mut other = AuRoc::new();
+    other.update(&scores.narrow(0, 5, 5), &targets.narrow(0, 5, 5)).unwrap();
+    auroc.merge(&other).unwrap();
+    assert_close(auroc.compute().unwrap(), 0.8);
+
+    // Perfectly separated and inverted scores.
+    let mut auroc = AuRoc::new();
+    auroc.update(&Tensor::from_slice(&[0.1, 0.2, 0.8]), &Tensor::from_slice(&[0, 1, 1])).unwrap();
+    assert_close(auroc.compute().unwrap(), 1.);
+    auroc.reset();
+    auroc.update(&Tensor::from_slice(&[0.9, 0.2, 0.1]), &Tensor::from_slice(&[0, 1, 1])).unwrap();
+    assert_close(auroc.compute().unwrap(), 0.);
+    auroc.reset();
+    auroc.update(&Tensor::from_slice(&[0.9, 0.2]), &Tensor::from_slice(&[1, 1])).unwrap();
+    assert!(auroc.compute().is_err());
+}
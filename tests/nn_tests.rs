@@ -138,6 +138,45 @@ fn gradient_clip_test() {
     assert_eq!(round4(g3), [-0.5657, -0.5657]);
 }
 
+// Leaf tensors with the gradients [3, 4] and [12], along with a tensor that
+// has no gradient.
+fn leaves_with_grads() -> Vec<Tensor> {
+    let a = Tensor::zeros([2], kind::FLOAT_CPU).set_requires_grad(true);
+    let b = Tensor::zeros([1], kind::FLOAT_CPU).set_requires_grad(true);
+    let c = Tensor::zeros([3], kind::FLOAT_CPU).set_requires_grad(true);
+    let loss = (&a * Tensor::from_slice(&[3f32, 4.])).sum(Kind::Float) + (&b * 12).sum(Kind::Float);
+    loss.backward();
+    vec![a, b, c]
+}
+
+#[test]
+fn clip_grad_functions() {
+    let vars = leaves_with_grads();
+    let all_grads = Tensor::cat(&[vars[0].grad(), vars[1].grad()], 0);
+    let norm = nn::clip_grad_norm(&vars, 6.5, 2.);
+    assert_eq!(f64_from(&norm), f64_from(&all_grads.norm()));
+    assert_eq!(f64_from(&norm), 13.);
+    assert!(!vars[2].grad().defined());
+    assert_eq!(round4(vars[0].grad()), [1.5, 2.]);
+    assert_eq!(round4(vars[1].grad()), [6.]);
+    // The gradients are left unchanged when their norm is below the maximum.
+    let norm = nn::clip_grad_norm(&vars, 10., 1.);
+    assert!((f64_from(&norm) - 9.5).abs() < 1e-4);
+    assert_eq!(round4(vars[1].grad()), [6.]);
+
+    let vars = leaves_with_grads();
+    let norm = nn::clip_grad_norm(&vars, 6., f64::INFINITY);
+    assert_eq!(f64_from(&norm), f64_from(&all_grads.abs().max()));
+    assert_eq!(round4(vars[0].grad()), [1.5, 2.]);
+    assert_eq!(round4(vars[1].grad()), [6.]);
+
+    let vars = leaves_with_grads();
+    nn::clip_grad_value(&vars, 3.5);
+    assert_eq!(vec_f64_from(&vars[0].grad()), [3., 3.5]);
+    assert_eq!(vec_f64_from(&vars[1].grad()), [3.5]);
+    assert_eq!(f64_from(&nn::clip_grad_norm(&vars[2..], 1., 2.)), 0.);
+}
+
 #[test]
 fn bn_test() {
     let opts = (tch::Kind::Float, tch::Device::Cpu);
@@ -177,6 +216,82 @@ fn group_norm_test() {
     let _y = x.apply(&ln);
 }
 
+// Normalizes over the given dimensions with explicit mean and variance ops.
+fn reference_norm(xs: &Tensor, dims: &[i64], eps: f64) -> Tensor {
+    let mean = xs.mean_dim(dims, true, Kind::Float);
+    let var = (xs - &mean).square().mean_dim(dims, true, Kind::Float);
+    (xs - mean) / (var + eps).sqrt()
+}
+
+// Random affine parameters so that they are covered by the comparisons.
+fn randomize(vs: &nn::VarStore) {
+    tch::no_grad(|| {
+        for (_, mut v) in vs.variables() {
+            let _ = v.uniform_(0.5, 1.5);
+        }
+    })
+}
+
+#[test]
+fn group_norm_reference() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let gn = group_norm(vs.root(), 3, 6, Default::default());
+    randomize(&vs);
+    let xs = Tensor::randn([2, 6, 5], kind::FLOAT_CPU);
+    let expected = reference_norm(&xs.view([2, 3, 10]), &[2], 1e-5).view([2, 6, 5]);
+    let expected = expected * gn.ws.as_ref().unwrap().view([1, 6, 1])
+        + gn.bs.as_ref().unwrap().view([1, 6, 1]);
+    assert!(xs.apply(&gn).allclose(&expected, 1e-5, 1e-5, false));
+}
+
+#[test]
+fn instance_norm() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::InstanceNormConfig { affine: true, ..Default::default() };
+    let norm = nn::instance_norm2d(vs.root() / "norm", 3, config);
+    let mut names: Vec<_> = vs.variables().into_keys().collect();
+    names.sort();
+    assert_eq!(names, ["norm.bias", "norm.weight"]);
+    randomize(&vs);
+    let xs = Tensor::randn([2, 3, 4, 5], kind::FLOAT_CPU);
+    let expected = reference_norm(&xs, &[2, 3], 1e-5)
+        * norm.ws.as_ref().unwrap().view([1, 3, 1, 1])
+        + norm.bs.as_ref().unwrap().view([1, 3, 1, 1]);
+    let ys = xs.apply(&norm);
+    assert!(ys.allclose(&expected, 1e-5, 1e-5, false));
+    // Unbatched inputs.
+    let y = xs.get(1).apply(&norm);
+    assert!(y.allclose(&ys.get(1), 1e-5, 1e-5, false));
+    assert!(norm.f_forward(&xs.view([2, 3, 20])).is_err());
+
+    let vs = nn::VarStore::new(Device::Cpu);
+    let norm = nn::instance_norm1d(vs.root(), 3, Default::default());
+    assert!(vs.variables().is_empty());
+    let xs = Tensor::randn([2, 3, 7], kind::FLOAT_CPU);
+    assert!(xs.apply(&norm).allclose(&reference_norm(&xs, &[2], 1e-5), 1e-5, 1e-5, false));
+}
+
+#[test]
+fn rms_norm() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let norm = nn::rms_norm(vs.root() / "norm", vec![8], Default::default());
+    assert_eq!(vs.variables().into_keys().collect::<Vec<_>>(), ["norm.weight"]);
+    randomize(&vs);
+    let xs = Tensor::randn([2, 3, 8], kind::FLOAT_CPU);
+    let rms = (xs.square().mean_dim(-1, true, Kind::Float) + 1e-6).sqrt();
+    let expected = &xs / rms * norm.ws.as_ref().unwrap();
+    assert!(xs.apply(&norm).allclose(&expected, 1e-5, 1e-5, false));
+    assert!(norm.f_forward(&xs.view([2, 8, 3])).is_err());
+
+    // Normalization over the last two dimensions without weight.
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::RmsNormConfig { elementwise_affine: false, eps: 1e-5, ..Default::default() };
+    let norm = nn::rms_norm(vs.root(), vec![3, 8], config);
+    assert!(vs.variables().is_empty());
+    let rms = (xs.square().mean_dim([1i64, 2].as_slice(), true, Kind::Float) + 1e-5).sqrt();
+    assert!(xs.apply(&norm).allclose(&(&xs / rms), 1e-5, 1e-5, false));
+}
+
 #[test]
 fn layer_norm_parameters_test() {
     tch::manual_seed(42);
@@ -280,6 +395,149 @@ fn lstm() {
     lstm_test(nn::RNNConfig { num_layers: 2, bidirectional: true, ..Default::default() });
 }
 
+#[test]
+fn packed_sequence() {
+    use nn::RNN;
+    let vs = nn::VarStore::new(Device::Cpu);
+    let lstm = nn::lstm(&vs.root() / "lstm", 4, 6, Default::default());
+    let config = nn::RNNConfig { num_layers: 2, bidirectional: true, ..Default::default() };
+    let gru = nn::gru(&vs.root() / "gru", 4, 6, config);
+
+    // The sequences are not sorted by length.
+    let lengths = [2i64, 5, 3];
+    let input = Tensor::randn([3, 5, 4], kind::FLOAT_CPU);
+    let sequences: Vec<_> =
+        lengths.iter().enumerate().map(|(i, &len)| input.get(i as i64).narrow(0, 0, len)).collect();
+    let packed = nn::pack_padded_sequence(&input, &Tensor::from_slice(&lengths), true, false);
+    assert_eq!(Vec::<i64>::try_from(&packed.batch_sizes).unwrap(), [3, 3, 2, 1, 1]);
+    assert_eq!(packed.data.size(), [10, 4]);
+    assert_eq!(nn::pack_sequence(&sequences, false).data, packed.data);
+    assert!(nn::f_pack_padded_sequence(&input, &Tensor::from_slice(&lengths), true, true).is_err());
+    assert!(nn::f_pack_sequence(&sequences, true).is_err());
+
+    let (padded, padded_lengths) = nn::pad_packed_sequence(&packed, true, -1., Some(6));
+    assert_eq!(padded.size(), [3, 6, 4]);
+    assert_eq!(Vec::<i64>::try_from(&padded_lengths).unwrap(), lengths);
+    for (i, (sequence, &len)) in sequences.iter().zip(lengths.iter()).enumerate() {
+        let padded = padded.get(i as i64);
+        assert_eq!(&padded.narrow(0, 0, len), sequence);
+        assert_eq!(padded.narrow(0, len, 6 - len), padded.narrow(0, len, 6 - len).full_like(-1.));
+    }
+    assert!(nn::f_pad_packed_sequence(&packed, true, 0., Some(4)).is_err());
+
+    // The packed forward passes match the forward passes on each sequence,
+    // and so the padded ones on the valid time steps.
+    let (ys, nn::LSTMState((h, c))) = lstm.seq_packed(&packed);
+    let (ys, _) = nn::pad_packed_sequence(&ys, true, 0., None);
+    let (padded_ys, _) = lstm.seq(&input);
+    for (i, (sequence, &len)) in sequences.iter().zip(lengths.iter()).enumerate() {
+        let (expected, nn::LSTMState((expected_h, expected_c))) = lstm.seq(&sequence.unsqueeze(0));
+        let ys = ys.get(i as i64).narrow(0, 0, len);
+        assert!(ys.allclose(&expected.get(0), 1e-5, 1e-5, false));
+        assert!(ys.allclose(&padded_ys.get(i as i64).narrow(0, 0, len), 1e-5, 1e-5, false));
+        assert!(h.narrow(1, i as i64, 1).allclose(&expected_h, 1e-5, 1e-5, false));
+        assert!(c.narrow(1, i as i64, 1).allclose(&expected_c, 1e-5, 1e-5, false));
+    }
+
+    let (ys, nn::GRUState(h)) = gru.seq_packed(&packed);
+    let (ys, _) = nn::pad_packed_sequence(&ys, true, 0., None);
+    assert_eq!(ys.size(), [3, 5, 12]);
+    assert_eq!(h.size(), [4, 3, 6]);
+    for (i, (sequence, &len)) in sequences.iter().zip(lengths.iter()).enumerate() {
+        let (expected, nn::GRUState(expected_h)) = gru.seq(&sequence.unsqueeze(0));
+        let ys = ys.get(i as i64).narrow(0, 0, len);
+        assert!(ys.allclose(&expected.get(0), 1e-5, 1e-5, false));
+        assert!(h.narrow(1, i as i64, 1).allclose(&expected_h, 1e-5, 1e-5, false));
+    }
+}
+
+// A recurrent network relying on the default packed sequence methods.
+struct StepGru(nn::GRU);
+
+impl nn::RNN for StepGru {
+    type State = nn::GRUState;
+
+    fn zero_state(&self, batch_dim: i64) -> nn::GRUState {
+        self.0.zero_state(batch_dim)
+    }
+
+    fn step(&self, input: &Tensor, state: &nn::GRUState) -> nn::GRUState {
+        self.0.step(input, state)
+    }
+
+    fn seq_init(&self, input: &Tensor, state: &nn::GRUState) -> (Tensor, nn::GRUState) {
+        self.0.seq_init(input, state)
+    }
+}
+
+#[test]
+fn rnn_default_seq_packed() {
+    use nn::RNN;
+    let vs = nn::VarStore::new(Device::Cpu);
+    let gru = StepGru(nn::gru(vs.root(), 4, 6, Default::default()));
+    let lengths = [2i64, 5, 3];
+    let input = Tensor::randn([3, 5, 4], kind::FLOAT_CPU);
+    let packed = nn::pack_padded_sequence(&input, &Tensor::from_slice(&lengths), true, false);
+    let (ys, nn::GRUState(h)) = gru.f_seq_packed(&packed).unwrap();
+    let (expected_ys, nn::GRUState(expected_h)) = gru.0.seq_packed(&packed);
+    assert_eq!(ys.batch_sizes, expected_ys.batch_sizes);
+    assert!(ys.data.allclose(&expected_ys.data, 1e-5, 1e-5, false));
+    // The padding is processed so only the final state of the longest
+    // sequence matches.
+    assert_eq!(h.size(), [1, 3, 6]);
+    assert!(h.narrow(1, 1, 1).allclose(&expected_h.narrow(1, 1, 1), 1e-5, 1e-5, false));
+}
+
+#[test]
+fn lstm_projections() {
+    use nn::RNN;
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config =
+        nn::RNNConfig { num_layers: 2, bidirectional: true, proj_size: 3, ..Default::default() };
+    let lstm = nn::lstm(vs.root(), 4, 6, config);
+    let variables = vs.variables();
+    assert_eq!(variables.len(), 2 * 2 * 5);
+    assert_eq!(variables["weight_hr_l0"].size(), [3, 6]);
+    assert_eq!(variables["weight_hr_l1_reverse"].size(), [3, 6]);
+    assert_eq!(variables["weight_hh_l0"].size(), [24, 3]);
+    assert_eq!(variables["weight_ih_l1"].size(), [24, 6]);
+
+    let lengths = Tensor::from_slice(&[2i64, 5, 3]);
+    let input = Tensor::randn([3, 5, 4], kind::FLOAT_CPU);
+    let packed = nn::pack_padded_sequence(&input, &lengths, true, false);
+    let (ys, state) = lstm.seq_packed(&packed);
+    let (ys, lengths) = nn::pad_packed_sequence(&ys, true, 0., None);
+    assert_eq!(ys.size(), [3, 5, 6]);
+    assert_eq!(state.h().size(), [4, 3, 3]);
+    assert_eq!(state.c().size(), [4, 3, 6]);
+    let last_h = state.last_layer_h(true);
+    assert_eq!(last_h.size(), [2, 3, 3]);
+    assert_eq!(state.last_layer_c(true).size(), [2, 3, 6]);
+
+    // The forward direction ends on the last valid step and the backward one
+    // on the first step.
+    let last = nn::last_outputs(&ys, &lengths, true, true);
+    assert_eq!(last.size(), [3, 6]);
+    for (i, len) in Vec::<i64>::try_from(&lengths).unwrap().into_iter().enumerate() {
+        let ys = ys.get(i as i64);
+        let expected =
+            Tensor::cat(&[ys.get(len - 1).narrow(0, 0, 3), ys.get(0).narrow(0, 3, 3)], 0);
+        assert_eq!(last.get(i as i64), expected);
+        let h = Tensor::cat(&[last_h.get(0).get(i as i64), last_h.get(1).get(i as i64)], 0);
+        assert!(h.allclose(&expected, 1e-5, 1e-5, false));
+    }
+    assert_eq!(nn::last_outputs(&ys.transpose(0, 1), &lengths, false, true), last);
+    assert!(nn::f_last_outputs(&ys, &Tensor::from_slice(&[2i64, 5]), true, true).is_err());
+    assert!(nn::f_last_outputs(&ys, &Tensor::from_slice(&[2i64, 6, 1]), true, true).is_err());
+    assert!(nn::f_last_outputs(&ys, &Tensor::from_slice(&[0i64, 5, 1]), true, true).is_err());
+
+    let config = nn::RNNConfig { proj_size: 2, ..Default::default() };
+    let lstm = nn::lstm(&vs.root() / "unidirectional", 4, 6, config);
+    let (ys, state) = lstm.seq(&input);
+    let last = nn::last_outputs(&ys, &Tensor::from_slice(&[5i64, 5, 5]), true, false);
+    assert!(last.allclose(&state.last_layer_h(false).get(0), 1e-5, 1e-5, false));
+}
+
 fn embedding_test(embedding_config: nn::EmbeddingConfig) {
     let batch_dim = 5;
     let seq_len = 7;
@@ -320,6 +578,89 @@ fn embedding_zero_padding() {
     embedding_test(nn::EmbeddingConfig { padding_idx: 0, ..Default::default() });
 }
 
+#[test]
+fn embedding_padding_stays_zero() {
+    for sparse in [false, true] {
+        let vs = nn::VarStore::new(Device::Cpu);
+        let config = nn::EmbeddingConfig { padding_idx: 2, sparse, ..Default::default() };
+        let embeddings = nn::embedding(vs.root(), 5, 3, config);
+        assert_eq!(vec_f64_from(&embeddings.ws.get(2)), [0., 0., 0.]);
+        let before = embeddings.ws.copy();
+        let mut opt = nn::Sgd::default().build(&vs, 0.1).unwrap();
+        let xs = Tensor::from_slice(&[0i64, 2, 2, 4, 4]);
+        opt.backward_step(&embeddings.forward(&xs).sum(Kind::Float));
+        assert_eq!(vec_f64_from(&embeddings.ws.get(2)), [0., 0., 0.]);
+        let delta = (&embeddings.ws - &before).sum_dim_intlist(1, false, Kind::Float);
+        assert_eq!(round4(delta), [-0.3, 0., 0., 0., -0.6]);
+    }
+}
+
+#[test]
+fn embedding_max_norm() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::EmbeddingConfig { max_norm: Some(1.), ..Default::default() };
+    let embeddings = nn::embedding(vs.root(), 4, 3, config);
+    tch::no_grad(|| {
+        let init = Tensor::from_slice(&[3f32, 4., 0., 0.3, 0.4, 0., 6., 8., 0., 1., 1., 1.]);
+        embeddings.ws.shallow_clone().copy_(&init.view([4, 3]))
+    });
+    let ys = embeddings.forward(&Tensor::from_slice(&[0i64, 1, 0]));
+    let norms = ys.norm_scalaropt_dim(2, [1], false);
+    assert_eq!(round4(norms), [1., 0.5, 1.]);
+    // Only the embeddings that are looked up are renormalized.
+    let norms = embeddings.ws.norm_scalaropt_dim(2, [1], false);
+    assert_eq!(round4(norms), [1., 0.5, 10., 1.7321]);
+}
+
+#[test]
+fn embedding_bag() {
+    for mode in [nn::EmbeddingBagMode::Sum, nn::EmbeddingBagMode::Mean, nn::EmbeddingBagMode::Max] {
+        let vs = nn::VarStore::new(Device::Cpu);
+        let config = nn::EmbeddingBagConfig { mode, ..Default::default() };
+        let bag = nn::embedding_bag(vs.root(), 10, 3, config);
+        let reduce = |xs: &[i64]| {
+            let embeddings = bag.ws.index_select(0, &Tensor::from_slice(xs));
+            match mode {
+                nn::EmbeddingBagMode::Sum => embeddings.sum_dim_intlist(0, false, Kind::Float),
+                nn::EmbeddingBagMode::Mean => embeddings.mean_dim(0, false, Kind::Float),
+                nn::EmbeddingBagMode::Max => embeddings.amax(0, false),
+            }
+        };
+        // Bags of different sizes given by offsets.
+        let xs = Tensor::from_slice(&[1i64, 2, 4, 5, 4, 3, 2, 9]);
+        let offsets = Tensor::from_slice(&[0i64, 3, 4]);
+        let ys = bag.forward_bags(&xs, Some(&offsets), None);
+        let expected = Tensor::stack(&[reduce(&[1, 2, 4]), reduce(&[5]), reduce(&[4, 3, 2, 9])], 0);
+        assert!(ys.allclose(&expected, 1e-6, 1e-6, false));
+        // Bags of the same size.
+        let xs = Tensor::from_slice(&[1i64, 2, 4, 5, 4, 3]).view([2, 3]);
+        let expected = Tensor::stack(&[reduce(&[1, 2, 4]), reduce(&[5, 4, 3])], 0);
+        assert!(bag.forward(&xs).allclose(&expected, 1e-6, 1e-6, false));
+        let weights = Tensor::from_slice(&[0.5f32, 1., 2., 1., 1., 1.]).view([2, 3]);
+        let ys = bag.f_forward_bags(&xs, None, Some(&weights));
+        assert_eq!(ys.is_ok(), mode == nn::EmbeddingBagMode::Sum);
+        if let Ok(ys) = ys {
+            let first = bag.ws.get(1) * 0.5 + bag.ws.get(2) + bag.ws.get(4) * 2.;
+            assert!(ys.get(0).allclose(&first, 1e-6, 1e-6, false));
+            assert!(ys.get(1).allclose(&expected.get(1), 1e-6, 1e-6, false));
+        }
+        assert!(bag.f_forward_bags(&xs, Some(&offsets), None).is_err());
+        assert!(bag.f_forward_bags(&xs.view([-1]), None, None).is_err());
+    }
+}
+
+#[test]
+fn embedding_bag_padding() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::EmbeddingBagConfig { padding_idx: 0, ..Default::default() };
+    let bag = nn::embedding_bag(vs.root(), 10, 3, config);
+    assert_eq!(vec_f64_from(&bag.ws.get(0)), [0., 0., 0.]);
+    // The padding index is excluded from the mean.
+    let ys = bag.forward(&Tensor::from_slice(&[3i64, 0, 5, 0]).view([1, 4]));
+    let expected = (bag.ws.get(3) + bag.ws.get(5)) / 2.;
+    assert!(ys.get(0).allclose(&expected, 1e-6, 1e-6, false));
+}
+
 fn linear_test(linear_config: nn::LinearConfig) {
     let batch_dim = 5;
     let input_dim = 10;
@@ -347,6 +688,176 @@ fn linear() {
     linear_test(nn::LinearConfig { bias: false, ..Default::default() });
 }
 
+// Scaled dot-product attention computed head by head for a self-attention
+// layer, `padding[b][s]` masks the key `s` for the batch element `b`.
+fn naive_self_attention(
+    mha: &nn::MultiHeadAttention,
+    xs: &Tensor,
+    padding: &[Vec<bool>],
+) -> (Tensor, Tensor) {
+    let (_, batch, embed_dim) = xs.size3().unwrap();
+    let num_heads = mha.num_heads();
+    let head_dim = embed_dim / num_heads;
+    let ws = mha.in_proj_weight.as_ref().unwrap().chunk(3, 0);
+    let bs = mha.in_proj_bias.as_ref().unwrap().chunk(3, 0);
+    let mut ys = vec![];
+    let mut weights = vec![];
+    for b in 0..batch {
+        let x = xs.select(1, b);
+        let q = x.matmul(&ws[0].tr()) + &bs[0];
+        let k = x.matmul(&ws[1].tr()) + &bs[1];
+        let v = x.matmul(&ws[2].tr()) + &bs[2];
+        let mask: Vec<f32> =
+            padding[b as usize].iter().map(|&p| if p { f32::NEG_INFINITY } else { 0. }).collect();
+        let mask = Tensor::from_slice(&mask);
+        let mut heads = vec![];
+        let mut head_weights = vec![];
+        for h in 0..num_heads {
+            let q = q.narrow(1, h * head_dim, head_dim);
+            let k = k.narrow(1, h * head_dim, head_dim);
+            let v = v.narrow(1, h * head_dim, head_dim);
+            let scores = q.matmul(&k.tr()) / (head_dim as f64).sqrt() + &mask;
+            let w = scores.softmax(-1, Kind::Float);
+            heads.push(w.matmul(&v));
+            head_weights.push(w);
+        }
+        let y = Tensor::cat(&heads, 1).matmul(&mha.out_proj.ws.tr())
+            + mha.out_proj.bs.as_ref().unwrap();
+        ys.push(y);
+        weights.push(Tensor::stack(&head_weights, 0).mean_dim(0, false, Kind::Float));
+    }
+    (Tensor::stack(&ys, 1), Tensor::stack(&weights, 0))
+}
+
+#[test]
+fn multi_head_attention() {
+    tch::manual_seed(0);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let mha = nn::multi_head_attention(vs.root(), 4, 2, Default::default());
+    let variables = vs.variables();
+    let mut names: Vec<_> = variables.keys().map(|n| n.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["in_proj_bias", "in_proj_weight", "out_proj.bias", "out_proj.weight"]);
+    assert_eq!(variables["in_proj_weight"].size(), [12, 4]);
+    // Non-zero biases so that they are covered by the comparison.
+    tch::no_grad(|| {
+        let _ = mha.in_proj_bias.as_ref().unwrap().shallow_clone().uniform_(-1., 1.);
+        let _ = mha.out_proj.bs.as_ref().unwrap().shallow_clone().uniform_(-1., 1.);
+    });
+
+    // [seq, batch, feature] inputs.
+    let xs = Tensor::randn([3, 2, 4], kind::FLOAT_CPU);
+    let no_padding = vec![vec![false; 3]; 2];
+    let (expected, expected_weights) = naive_self_attention(&mha, &xs, &no_padding);
+    let (ys, weights) = mha.forward_qkv(&xs, &xs, &xs, None, None, true);
+    assert_eq!(ys.size(), [3, 2, 4]);
+    assert!(ys.allclose(&expected, 1e-5, 1e-5, false));
+    assert!(weights.unwrap().allclose(&expected_weights, 1e-5, 1e-5, false));
+    let (ys, weights) = mha.forward_qkv(&xs, &xs, &xs, None, None, false);
+    assert!(ys.allclose(&expected, 1e-5, 1e-5, false));
+    assert!(weights.is_none());
+
+    // Padding masks, as booleans or as additive masks.
+    let padding = vec![vec![false, false, true], vec![false, true, true]];
+    let (expected, expected_weights) = naive_self_attention(&mha, &xs, &padding);
+    let bool_mask = Tensor::from_slice(&[false, false, true, false, true, true]).view([2, 3]);
+    let float_mask = bool_mask.to_kind(Kind::Float) * -1e9;
+    for mask in [bool_mask, float_mask] {
+        for need_weights in [true, false] {
+            let (ys, weights) = mha.forward_qkv(&xs, &xs, &xs, Some(&mask), None, need_weights);
+            assert!(ys.allclose(&expected, 1e-5, 1e-5, false));
+            if let Some(weights) = weights {
+                assert!(weights.allclose(&expected_weights, 1e-5, 1e-5, false));
+                assert_eq!(f64_from(&weights.get(1).get(0).get(2)), 0.);
+            }
+        }
+    }
+    // A causal attention mask.
+    let causal = Tensor::ones([3, 3], kind::FLOAT_CPU).triu(1).to_kind(Kind::Bool);
+    let (_, weights) = mha.forward_qkv(&xs, &xs, &xs, None, Some(&causal), true);
+    assert_eq!(f64_from(&weights.unwrap().get(0).get(0).get(1)), 0.);
+    // Unbatched inputs.
+    let x = xs.select(1, 1);
+    let (y, _) = mha.forward_qkv(&x, &x, &x, None, None, false);
+    assert!(y.allclose(&ys.select(1, 1), 1e-5, 1e-5, false));
+    assert!(mha.f_forward_qkv(&xs, &xs.narrow(2, 0, 3), &xs, None, None, false).is_err());
+}
+
+#[test]
+fn multi_head_attention_kdim_vdim() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::MultiHeadAttentionConfig {
+        kdim: Some(3),
+        vdim: Some(5),
+        bias: false,
+        batch_first: true,
+        ..Default::default()
+    };
+    let mha = nn::multi_head_attention(vs.root() / "attn", 8, 4, config);
+    let variables = vs.variables();
+    let mut names: Vec<_> = variables.keys().map(|n| n.as_str()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["attn.k_proj_weight", "attn.out_proj.weight", "attn.q_proj_weight", "attn.v_proj_weight"]
+    );
+    assert_eq!(variables["attn.k_proj_weight"].size(), [8, 3]);
+    assert_eq!(variables["attn.v_proj_weight"].size(), [8, 5]);
+    // [batch, seq, feature] inputs.
+    let q = Tensor::randn([2, 6, 8], kind::FLOAT_CPU);
+    let k = Tensor::randn([2, 7, 3], kind::FLOAT_CPU);
+    let v = Tensor::randn([2, 7, 5], kind::FLOAT_CPU);
+    let (ys, weights) = mha.forward_qkv(&q, &k, &v, None, None, true);
+    assert_eq!(ys.size(), [2, 6, 8]);
+    assert_eq!(weights.unwrap().size(), [2, 6, 7]);
+}
+
+#[test]
+fn transformer_matches_pytorch() {
+    let io: std::collections::HashMap<_, _> =
+        Tensor::read_safetensors("tests/transformer/io.safetensors").unwrap().into_iter().collect();
+    let padding = Tensor::from_slice(&[false, false, true, false, false, false]).view([2, 3]);
+    let config = nn::TransformerLayerConfig {
+        d_model: 4,
+        nhead: 2,
+        dim_feedforward: 6,
+        dropout: 0.,
+        ..Default::default()
+    };
+
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let encoder = nn::transformer_encoder(vs.root(), config, 2);
+    let report =
+        vs.load_with_options("tests/transformer/encoder.safetensors", Default::default()).unwrap();
+    assert!(report.is_exact());
+    let memory = encoder.forward_masked_t(&io["src"], None, Some(&padding), false);
+    assert!(memory.allclose(&io["encoder_out"], 1e-5, 1e-5, false));
+
+    let config = nn::TransformerLayerConfig {
+        activation: nn::TransformerActivation::Gelu,
+        batch_first: true,
+        norm_first: true,
+        ..config
+    };
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let decoder = nn::transformer_decoder_layer(vs.root(), config);
+    let report =
+        vs.load_with_options("tests/transformer/decoder.safetensors", Default::default()).unwrap();
+    assert!(report.is_exact());
+    let causal = Tensor::ones([3, 3], kind::FLOAT_CPU).triu(1).to_kind(Kind::Bool);
+    let memory = memory.transpose(0, 1);
+    let ys = decoder.forward_masked_t(
+        &io["tgt"],
+        &memory,
+        Some(&causal),
+        None,
+        None,
+        Some(&padding),
+        false,
+    );
+    assert!(ys.allclose(&io["decoder_out"], 1e-5, 1e-5, false));
+}
+
 #[test]
 fn pad() {
     let xs = Tensor::from_slice(&[1., 2., 3.]);
@@ -377,7 +888,12 @@ fn pad() {
 
 fn apply_conv(xs: &Tensor, padding_mode: nn::PaddingMode) -> Tensor {
     let vs = nn::VarStore::new(Device::Cpu);
-    let conv_cfg = nn::ConvConfig { padding: 1, bias: false, padding_mode, ..Default::default() };
+    let conv_cfg = nn::ConvConfig {
+        padding: nn::Padding::Explicit(1),
+        bias: false,
+        padding_mode,
+        ..Default::default()
+    };
     let mut conv = nn::conv2d(vs.root(), 1, 1, 3, conv_cfg);
     tch::no_grad(|| {
         _ = conv.ws.fill_(1.);
@@ -402,6 +918,51 @@ fn conv() {
     assert_eq!(vec_f32_from(&conved.reshape(-1)), &[18.0, 21.0, 24.0, 27.0]);
 }
 
+#[test]
+fn conv_same_padding() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let root = vs.root();
+    let same = nn::ConvConfig { padding: nn::Padding::Same, ..Default::default() };
+    for ksize in 1..=5 {
+        let xs = Tensor::randn([2, 3, 7], kind::FLOAT_CPU);
+        let conv = nn::conv1d(&root / "c1" / ksize, 3, 4, ksize, same);
+        assert_eq!(xs.apply(&conv).size(), [2, 4, 7]);
+        let xs = Tensor::randn([2, 3, 6, 5], kind::FLOAT_CPU);
+        let conv = nn::conv2d(&root / "c2" / ksize, 3, 4, ksize, same);
+        assert_eq!(xs.apply(&conv).size(), [2, 4, 6, 5]);
+        let config = nn::ConvConfig { dilation: 2, ..same };
+        let conv = nn::conv2d(&root / "dilated" / ksize, 3, 4, ksize, config);
+        assert_eq!(xs.apply(&conv).size(), [2, 4, 6, 5]);
+        let xs = Tensor::randn([1, 3, 4, 5, 6], kind::FLOAT_CPU);
+        let conv = nn::conv3d(&root / "c3" / ksize, 3, 2, ksize, same);
+        assert_eq!(xs.apply(&conv).size(), [1, 2, 4, 5, 6]);
+    }
+
+    // With an even kernel size, the extra padding is on the right.
+    let xs = Tensor::from_slice(&[1f32, 2., 3., 4.]).view([1, 1, 2, 2]);
+    let config = nn::ConvConfig { bias: false, ..same };
+    let mut conv = nn::conv2d(&root / "even", 1, 1, 2, config);
+    tch::no_grad(|| {
+        _ = conv.ws.fill_(1.);
+    });
+    assert_eq!(vec_f32_from(&xs.apply(&conv).reshape(-1)), [10.0, 6.0, 7.0, 4.0]);
+
+    let config = nn::ConvConfig { padding_mode: nn::PaddingMode::Reflect, bias: false, ..same };
+    let mut conv = nn::conv2d(&root / "reflect", 1, 1, 3, config);
+    tch::no_grad(|| {
+        _ = conv.ws.fill_(1.);
+    });
+    assert_eq!(xs.apply(&conv), apply_conv(&xs, nn::PaddingMode::Reflect));
+
+    let valid = nn::ConvConfig { padding: nn::Padding::Valid, ..Default::default() };
+    let xs = Tensor::randn([2, 3, 6, 5], kind::FLOAT_CPU);
+    let conv = nn::conv2d(&root / "valid", 3, 4, 3, valid);
+    assert_eq!(xs.apply(&conv).size(), [2, 4, 4, 3]);
+
+    let config = nn::ConvConfig { stride: 2, ..same };
+    assert!(nn::f_conv2d(&root / "strided", 3, 4, 3, config).is_err());
+}
+
 #[test]
 fn seq() {
     let s = nn::seq().add_fn(|xs| xs.shallow_clone().relu_());
@@ -410,3 +971,238 @@ fn seq() {
     assert_eq!(vec_f32_from(&xs), [1., 0., 2.]);
     assert_eq!(vec_f32_from(&ys), [1., 0., 2.]);
 }
+
+#[test]
+fn dropout() {
+    use nn::ModuleT;
+    let xs = Tensor::randn([8, 16], kind::FLOAT_CPU);
+    let seq = nn::seq_t().add(nn::dropout(0.5));
+    assert_eq!(xs.apply_t(&seq, false), xs);
+    assert_eq!(xs.apply_t(&nn::dropout(0.), true), xs);
+    assert_eq!(xs.apply_t(&nn::dropout(1.), true), xs.zeros_like());
+    assert!(nn::dropout(1.5).f_forward_t(&xs, true).is_err());
+
+    // The fraction of zeroed channels is close to p and the other channels
+    // are scaled by 1 / (1 - p).
+    let xs = Tensor::ones([64, 100, 4, 4], kind::FLOAT_CPU);
+    let ys = xs.apply_t(&nn::dropout2d(0.3), true);
+    let channels = ys.sum_dim_intlist([2i64, 3].as_slice(), false, Kind::Float);
+    let zeroed = f64::try_from(channels.eq(0.).to_kind(Kind::Float).mean(Kind::Float)).unwrap();
+    assert!((zeroed - 0.3).abs() < 0.03, "{zeroed}");
+    let kept = channels.ne(0.);
+    assert!(channels.masked_select(&kept).allclose(&Tensor::from(16. / 0.7), 1e-5, 1e-5, false));
+    assert_eq!(xs.dropout2d(0., true), xs);
+    assert_eq!(xs.dropout2d(1., true), xs.zeros_like());
+
+    let xs = Tensor::ones([32, 50, 2, 3, 3], kind::FLOAT_CPU);
+    let channels =
+        xs.dropout3d(0.6, true).sum_dim_intlist([2i64, 3, 4].as_slice(), false, Kind::Float);
+    let zeroed = f64::try_from(channels.eq(0.).to_kind(Kind::Float).mean(Kind::Float)).unwrap();
+    assert!((zeroed - 0.6).abs() < 0.05, "{zeroed}");
+
+    // Unbatched inputs are supported, inputs with fewer dimensions are not.
+    let xs = Tensor::ones([100, 4, 4], kind::FLOAT_CPU);
+    let ys = xs.dropout2d(0.5, true);
+    assert_eq!(ys.size(), [100, 4, 4]);
+    let zeroed = ys.sum_dim_intlist([1i64, 2].as_slice(), false, Kind::Float).eq(0.);
+    let zeroed = i64::try_from(zeroed.sum(Kind::Int64)).unwrap();
+    assert!(zeroed > 0 && zeroed < 100);
+    assert!(Tensor::ones([4, 4], kind::FLOAT_CPU).f_dropout2d(0.5, true).is_err());
+    assert!(xs.f_dropout3d(0.5, true).is_err());
+    assert!(xs.f_dropout2d(-0.1, true).is_err());
+
+    // Alpha dropout preserves the mean and the variance of its inputs.
+    let xs = Tensor::randn([100_000], kind::FLOAT_CPU);
+    let ys = xs.apply_t(&nn::alpha_dropout(0.2), true);
+    let mean = f64::try_from(ys.mean(Kind::Float)).unwrap();
+    let std = f64::try_from(ys.std(true)).unwrap();
+    assert!(mean.abs() < 0.03, "{mean}");
+    assert!((std - 1.).abs() < 0.03, "{std}");
+    assert_ne!(ys, xs);
+    assert_eq!(xs.apply_t(&nn::alpha_dropout(0.2), false), xs);
+}
+
+#[test]
+fn seq_any() {
+    use nn::{ModuleAny, ModuleIO, ModuleT};
+    let vs = nn::VarStore::new(Device::Cpu);
+    let root = vs.root();
+    let xs = Tensor::randn([5, 3, 10], kind::FLOAT_CPU);
+    // [batch, channels, len] -> [batch, len, channels] for the LSTM.
+    let recurrent = nn::seq_any()
+        .add(nn::conv1d(&root / "conv", 3, 8, 3, Default::default()))
+        .add_fn(|xs| xs.single().transpose(1, 2).into())
+        .add_any(nn::lstm(&root / "lstm", 8, 16, Default::default()));
+    let sizes: Vec<_> = match recurrent.forward_any(&ModuleIO::Single(xs.shallow_clone()), false) {
+        ModuleIO::Many(ys) => ys.iter().map(|y| y.size()).collect(),
+        ys => panic!("unexpected output {ys:?}"),
+    };
+    assert_eq!(sizes, [vec![5, 8, 16], vec![1, 5, 16], vec![1, 5, 16]]);
+
+    // Classifies the final hidden state of the LSTM.
+    let net = recurrent
+        .add_fn(|ys| ys.shallow_clone().into_tensors()[1].get(0).into())
+        .add(nn::linear(&root / "linear", 16, 4, Default::default()));
+    assert_eq!(net.len(), 5);
+    let ys = net.forward_any(&xs.shallow_clone().into(), false);
+    assert_eq!(ys.single().size(), [5, 4]);
+    assert_eq!(xs.apply_t(&net, false).size(), [5, 4]);
+    let pair = ModuleIO::Pair(xs.shallow_clone(), xs.shallow_clone());
+    assert!(net.f_forward_any(&pair, false).is_err());
+
+    let gru = nn::seq_any().add_any(nn::gru(&root / "gru", 10, 6, Default::default()));
+    match gru.forward_any(&xs.shallow_clone().into(), false) {
+        ModuleIO::Pair(ys, h) => {
+            assert_eq!(ys.size(), [5, 3, 6]);
+            assert_eq!(h.size(), [1, 5, 6]);
+        }
+        ys => panic!("unexpected output {ys:?}"),
+    }
+    // The output of the GRU is not a single tensor.
+    assert!(gru.f_forward_t(&xs, false).is_err());
+}
+
+fn data_parallel_grads(devices: &[Device], batch_size: i64) {
+    tch::manual_seed(42);
+    let builder = |p: &nn::Path| {
+        nn::seq()
+            .add(nn::linear(p / "l1", 4, 8, Default::default()))
+            .add_fn(|xs| xs.relu())
+            .add(nn::linear(p / "l2", 8, 3, Default::default()))
+    };
+    let dp = nn::DataParallel::new(builder, devices).unwrap();
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let single = builder(&vs.root());
+    vs.copy(dp.var_store()).unwrap();
+
+    let xs = Tensor::randn([batch_size, 4], kind::FLOAT_CPU);
+    let dp_ys = xs.apply_t(&dp, true);
+    let ys = xs.apply(&single);
+    assert_eq!(dp_ys.size(), [batch_size, 3]);
+    assert!(from::<f64>(&(&dp_ys - &ys).abs().max()) < 1e-5);
+
+    dp_ys.square().sum(Kind::Float).backward();
+    dp.reduce_gradients().unwrap();
+    ys.square().sum(Kind::Float).backward();
+    let dp_variables = dp.var_store().variables();
+    for (name, var) in vs.variables() {
+        let dp_grad = dp_variables[&name].grad();
+        assert!(from::<f64>(&(dp_grad - var.grad()).abs().max()) < 1e-4, "{name}");
+    }
+}
+
+#[test]
+fn data_parallel() {
+    // Single device, even and uneven batches, batch smaller than the number of devices.
+    data_parallel_grads(&[Device::Cpu], 8);
+    data_parallel_grads(&[Device::Cpu, Device::Cpu], 8);
+    data_parallel_grads(&[Device::Cpu, Device::Cpu], 7);
+    data_parallel_grads(&[Device::Cpu, Device::Cpu, Device::Cpu], 2);
+    assert!(nn::DataParallel::new(|p| nn::linear(p, 1, 1, Default::default()), &[]).is_err());
+}
+
+#[test]
+fn data_parallel_unused_on_primary() {
+    // The weight is only used for positive inputs, i.e. by the second replica.
+    let builder = |p: &nn::Path| {
+        let w = p.ones("w", &[1]);
+        nn::func(move |xs| if xs.double_value(&[0, 0]) > 0. { xs * &w } else { xs * 1. })
+    };
+    let dp = nn::DataParallel::new(builder, &[Device::Cpu, Device::Cpu]).unwrap();
+    let xs = Tensor::from_slice(&[-1f32, 2.]).view([2, 1]);
+    xs.apply_t(&dp, true).sum(Kind::Float).backward();
+    let w = &dp.var_store().variables()["w"];
+    assert!(!w.grad().defined());
+    dp.reduce_gradients().unwrap();
+    assert_eq!(w.grad().double_value(&[0]), 2.);
+}
+
+#[test]
+fn data_parallel_sync_replicas() {
+    let devices = [Device::Cpu, Device::Cpu];
+    let mut dp =
+        nn::DataParallel::new(|p| nn::linear(p, 2, 1, Default::default()), &devices).unwrap();
+    let mut opt = nn::Sgd::default().build(dp.var_store(), 0.1).unwrap();
+    let xs = Tensor::randn([4, 2], kind::FLOAT_CPU);
+    for _ in 0..3 {
+        let loss = xs.apply_t(&dp, true).square().mean(Kind::Float);
+        opt.zero_grad();
+        loss.backward();
+        dp.reduce_gradients().unwrap();
+        opt.step();
+        dp.sync_replicas().unwrap();
+    }
+    // The second half of the batch is processed by the second replica.
+    let ys = xs.apply_t(&dp, false);
+    let primary_ys = xs.apply(dp.module());
+    assert!(from::<f64>(&(ys - primary_ys).abs().max()) < 1e-6);
+}
+
+// Runs a few optimization steps on an embedding layer and returns its weights
+// along with the kind of gradient of the last step.
+fn embedding_steps<O: OptimizerConfig>(opt: O, sparse: bool) -> (Tensor, bool) {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::EmbeddingConfig { sparse, ..Default::default() };
+    let embeddings = nn::embedding(vs.root(), 10, 3, config);
+    tch::no_grad(|| {
+        let init = Tensor::arange(30, kind::FLOAT_CPU).view([10, 3]) / 10.;
+        embeddings.ws.shallow_clone().copy_(&init)
+    });
+    let mut opt = opt.build(&vs, 0.1).unwrap();
+    let mut is_sparse = false;
+    for step in 0..3i64 {
+        let xs = Tensor::from_slice(&[1, 3, 3, 7 + step % 2]);
+        let loss = embeddings.forward(&xs).pow_tensor_scalar(2).sum(Kind::Float);
+        opt.zero_grad();
+        loss.backward();
+        is_sparse = embeddings.ws.grad().is_sparse();
+        opt.step();
+    }
+    (embeddings.ws.detach(), is_sparse)
+}
+
+#[test]
+fn sparse_gradients_sgd() {
+    let (dense, is_sparse) = embedding_steps(nn::Sgd::default(), false);
+    assert!(!is_sparse);
+    let (sparse, is_sparse) = embedding_steps(nn::Sgd::default(), true);
+    assert!(is_sparse);
+    assert!(sparse.allclose(&dense, 1e-6, 1e-6, false));
+    let momentum = nn::Sgd { momentum: 0.9, ..Default::default() };
+    let (dense, _) = embedding_steps(momentum, false);
+    let (sparse, _) = embedding_steps(momentum, true);
+    assert!(sparse.allclose(&dense, 1e-6, 1e-6, false));
+}
+
+#[test]
+fn sparse_gradients_adagrad() {
+    let (dense, _) = embedding_steps(nn::Adagrad::default(), false);
+    let (sparse, is_sparse) = embedding_steps(nn::Adagrad::default(), true);
+    assert!(is_sparse);
+    assert!(sparse.allclose(&dense, 1e-6, 1e-6, false));
+    // Untouched rows are left unchanged.
+    let first_row = Tensor::from_slice(&[0f32, 0.1, 0.2]);
+    assert!(sparse.get(0).allclose(&first_row, 1e-6, 1e-6, false));
+}
+
+#[test]
+fn sparse_gradients_unsupported() {
+    assert!(nn::Sgd::default().supports_sparse());
+    assert!(!nn::Sgd { wd: 1e-4, ..Default::default() }.supports_sparse());
+    assert!(!nn::Adam::default().supports_sparse());
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::EmbeddingConfig { sparse: true, ..Default::default() };
+    let embeddings = nn::embedding(vs.root(), 10, 3, config);
+    let mut opt = nn::Adam::default().build(&vs, 0.1).unwrap();
+    assert!(!opt.supports_sparse());
+    let loss = embeddings.forward(&Tensor::from_slice(&[1, 2])).sum(Kind::Float);
+    opt.zero_grad();
+    loss.backward();
+    assert!(opt.f_step().is_err());
+    opt.zero_grad();
+    let grad = embeddings.ws.grad();
+    assert!(grad.is_sparse());
+    assert_eq!(f64::try_from(grad.to_dense(None).abs().sum(Kind::Float)).unwrap(), 0.);
+    opt.zero_grad_set_to_none();
+    assert!(!embeddings.ws.grad().defined());
+}
@@ -0,0 +1,114 @@
+use tch::{autograd, ops, IValue, Kind, TchError, Tensor};
+
+#[test]
+fn register_and_call() {
+    tch::register_op!(
+        "tch_tests::add_mul(Tensor a, Tensor b, float s) -> Tensor",
+        |a: Tensor, b: Tensor, s: f64| { (a + b).f_mul_scalar(s) }
+    )
+    .unwrap();
+    let a = Tensor::from_slice(&[1f32, 2., 3.]);
+    let b = Tensor::from_slice(&[3f32, 2., 1.]);
+    let res =
+        ops::call("tch_tests::add_mul", &[IValue::from(a), IValue::from(b), IValue::from(0.5)])
+            .unwrap();
+    let res = Tensor::try_from(res).unwrap();
+    assert_eq!(res.kind(), Kind::Float);
+    assert_eq!(Vec::<f32>::try_from(&res).unwrap(), [2., 2., 2.]);
+}
+
+#[test]
+fn multiple_outputs() {
+    ops::register("tch_tests::min_max(Tensor a) -> (Tensor, Tensor)", |inputs| {
+        let a = Tensor::try_from(inputs.into_iter().next().unwrap())?;
+        Ok(IValue::Tuple(vec![IValue::from(a.f_min()?), IValue::from(a.f_max()?)]))
+    })
+    .unwrap();
+    let a = Tensor::from_slice(&[1i64, 5, 3]);
+    let res = ops::call("tch_tests::min_max", &[IValue::from(a)]).unwrap();
+    let (min, max) = <(Tensor, Tensor)>::try_from(res).unwrap();
+    assert_eq!(i64::try_from(&min).unwrap(), 1);
+    assert_eq!(i64::try_from(&max).unwrap(), 5);
+}
+
+#[test]
+fn errors_and_panics() {
+    tch::register_op!("tch_tests::fail(int x) -> int", |x: i64| {
+        if x < 0 {
+            panic!("negative input")
+        }
+        Err::<i64, _>(TchError::Kind(format!("cannot handle {x}")))
+    })
+    .unwrap();
+    let err = ops::call("tch_tests::fail", &[IValue::from(3i64)]).unwrap_err().to_string();
+    assert!(err.contains("cannot handle 3"), "{err}");
+    let err = ops::call("tch_tests::fail", &[IValue::from(-1i64)]).unwrap_err().to_string();
+    assert!(err.contains("custom operator panicked"), "{err}");
+    assert!(ops::call("tch_tests::unknown", &[]).is_err());
+    assert!(ops::register("not a schema", |_| Ok(IValue::None)).is_err());
+}
+
+#[test]
+fn kernel_in_cmodule() {
+    // The model from foo9.pt calls tch_test::fused_add_mul, the kernel has to be
+    // registered before loading it.
+    let schema = "tch_test::fused_add_mul(Tensor x, Tensor y, float s) -> Tensor";
+    ops::register_kernel(schema, ops::DispatchKey::Cpu, |inputs| {
+        let mut inputs = inputs.into_iter();
+        let x = Tensor::try_from(inputs.next().unwrap())?;
+        let y = Tensor::try_from(inputs.next().unwrap())?;
+        let s = f64::try_from(inputs.next().unwrap())?;
+        Ok(IValue::from((x + y).f_mul_scalar(s)?))
+    })
+    .unwrap();
+    let mod_ = tch::CModule::load("tests/foo9.pt").unwrap();
+    let xs = Tensor::from_slice(&[1f32, 2., 3.]);
+    let ys = Tensor::from_slice(&[3f32, 3., 3.]);
+    let res = mod_.forward_ts(&[xs, ys]).unwrap();
+    assert_eq!(Vec::<f32>::try_from(&res).unwrap(), [9., 11., 13.]);
+}
+
+#[test]
+fn kernel_backward() {
+    let schema = "tch_test::square(Tensor x) -> Tensor";
+    ops::register_kernel(schema, ops::DispatchKey::Cpu, |inputs| {
+        let x = Tensor::try_from(inputs.into_iter().next().unwrap())?;
+        Ok(IValue::from(x.f_mul(&x)?))
+    })
+    .unwrap();
+    let x = Tensor::from_slice(&[1f32, 2., 3.]).set_requires_grad(true);
+    // Without a backward the outputs do not track gradients.
+    let res = Tensor::try_from(
+        ops::call("tch_test::square", &[IValue::from(x.shallow_clone())]).unwrap(),
+    )
+    .unwrap();
+    assert!(!res.requires_grad());
+    ops::register_backward(schema, |grads, inputs| {
+        let x = Tensor::try_from(inputs.into_iter().next().unwrap())?;
+        Ok(vec![Some(grads[0].f_mul(&x)?.f_mul_scalar(2)?)])
+    })
+    .unwrap();
+    let res = Tensor::try_from(
+        ops::call("tch_test::square", &[IValue::from(x.shallow_clone())]).unwrap(),
+    )
+    .unwrap();
+    assert!(res.requires_grad());
+    assert_eq!(Vec::<f32>::try_from(&res.detach()).unwrap(), [1., 4., 9.]);
+    let square = |xs: &[Tensor]| {
+        Tensor::try_from(
+            ops::call("tch_test::square", &[IValue::from(xs[0].shallow_clone())]).unwrap(),
+        )
+        .unwrap()
+    };
+    autograd::gradcheck(square, &[x], Default::default()).unwrap();
+}
+
+#[test]
+fn kernel_panic() {
+    ops::register_kernel("tch_test::panic(Tensor x) -> Tensor", ops::DispatchKey::Cpu, |_| {
+        panic!("kernel panic")
+    })
+    .unwrap();
+    let err = ops::call("tch_test::panic", &[IValue::from(Tensor::from(1f32))]).unwrap_err();
+    assert!(err.to_string().contains("custom operator panicked"), "{err}");
+}
@@ -0,0 +1,93 @@
+use tch::{Device, Kind, OutputArena, Tensor};
+
+fn randn(size: &[i64]) -> Tensor {
+    Tensor::randn(size, (Kind::Float, Device::Cpu))
+}
+
+fn empty(size: &[i64]) -> Tensor {
+    Tensor::empty(size, (Kind::Float, Device::Cpu))
+}
+
+#[test]
+fn matmul_into() {
+    for (a, b, out) in [
+        (&[3, 4][..], &[4, 5][..], &[3, 5][..]),
+        (&[2, 3, 4], &[4, 5], &[2, 3, 5]),
+        (&[2, 1, 3, 4], &[5, 4, 2], &[2, 5, 3, 2]),
+        (&[4], &[2, 4, 5], &[2, 5]),
+        (&[3, 4], &[4], &[3]),
+        (&[4], &[4], &[]),
+    ] {
+        let (a, b, mut out) = (randn(a), randn(b), empty(out));
+        a.matmul_into(&b, &mut out);
+        assert!(out.allclose(&a.matmul(&b), 1e-5, 1e-5, false));
+    }
+    let (a, b) = (randn(&[3, 4]), randn(&[4, 5]));
+    assert!(a.f_matmul_into(&b, &mut empty(&[3, 4])).is_err());
+    assert!(a.f_matmul_into(&randn(&[3, 5]), &mut empty(&[3, 5])).is_err());
+    let mut out = Tensor::empty([3, 5], (Kind::Double, Device::Cpu));
+    assert!(a.f_matmul_into(&b, &mut out).is_err());
+}
+
+#[test]
+fn other_ops_into() {
+    let xs = randn(&[2, 3, 8]);
+    let (ws, bs) = (randn(&[4, 8]), randn(&[4]));
+    let mut out = empty(&[2, 3, 4]);
+    xs.linear_into(&ws, Some(&bs), &mut out);
+    assert!(out.allclose(&xs.linear(&ws, Some(&bs)), 1e-5, 1e-5, false));
+    assert!(xs.f_linear_into(&ws, None, &mut empty(&[2, 3, 8])).is_err());
+
+    let xs = randn(&[2, 4, 9, 9]);
+    let ws = randn(&[6, 2, 3, 3]);
+    let mut out = empty(&[2, 6, 5, 5]);
+    xs.conv2d_into(&ws, None, [2, 2], [1, 1], [1, 1], 2, &mut out);
+    let expected = xs.conv2d(&ws, None::<Tensor>, [2, 2], [1, 1], [1, 1], 2);
+    assert!(out.allclose(&expected, 1e-5, 1e-5, false));
+    assert!(xs.f_conv2d_into(&ws, None, [1, 1], [1, 1], [1, 1], 2, &mut out).is_err());
+
+    let xs = randn(&[3, 5]);
+    let mut out = empty(&[3, 5]);
+    xs.softmax_into(-1, &mut out);
+    assert!(out.allclose(&xs.softmax(-1, Kind::Float), 1e-5, 1e-5, false));
+    xs.relu_into(&mut out);
+    assert!(out.equal(&xs.relu()));
+    let bs = randn(&[5]);
+    xs.add_into(&bs, &mut out);
+    assert!(out.equal(&(&xs + &bs)));
+    assert!(xs.f_add_into(&randn(&[3]), &mut out).is_err());
+}
+
+#[test]
+fn output_arena() {
+    let mut arena = OutputArena::new(Device::Cpu);
+    let out = arena.get("hidden", &[4, 8], Kind::Float);
+    assert_eq!(out.size(), [4, 8]);
+    assert_eq!(arena.size_in_bytes(), 4 * 8 * 4);
+    // The same storage is returned on the next request.
+    let again = arena.get("hidden", &[4, 8], Kind::Float);
+    assert_eq!(out.data_ptr(), again.data_ptr());
+    assert!(arena.f_get("hidden", &[2, 8], Kind::Float).is_err());
+    assert!(arena.f_get("hidden", &[4, 8], Kind::Double).is_err());
+
+    // Shrinking reuses the storage when resizing is allowed.
+    let mut arena = arena.allow_resize(true);
+    let smaller = arena.get("hidden", &[2, 8], Kind::Float);
+    assert_eq!(smaller.size(), [2, 8]);
+    assert_eq!(smaller.data_ptr(), out.data_ptr());
+    let double = arena.get("hidden", &[2, 8], Kind::Double);
+    assert_eq!(double.kind(), Kind::Double);
+    assert_eq!(arena.len(), 1);
+
+    // A forward pass writing to the arena.
+    let xs = randn(&[4, 8]);
+    let ws = randn(&[3, 8]);
+    let mut hidden = arena.get("linear", &[4, 3], Kind::Float);
+    xs.linear_into(&ws, None, &mut hidden);
+    let mut probs = arena.get("probs", &[4, 3], Kind::Float);
+    hidden.softmax_into(-1, &mut probs);
+    let expected = xs.linear(&ws, None::<Tensor>).softmax(-1, Kind::Float);
+    assert!(arena.get("probs", &[4, 3], Kind::Float).allclose(&expected, 1e-5, 1e-5, false));
+    arena.clear();
+    assert!(arena.is_empty());
+}
@@ -0,0 +1,31 @@
+#[cfg(test)]
+#[cfg(feature = "rayon")]
+mod tests {
+    use tch::{Kind, Tensor};
+
+    #[test]
+    fn par_map_dim0_matches_sequential() {
+        let t = Tensor::arange(70, (Kind::Float, tch::Device::Cpu)).view([35, 2]);
+        let f = |t: Tensor| (&t * 2.0 + 1.0).sum_dim_intlist(-1, true, Kind::Float);
+        let expected = f(t.shallow_clone());
+        for chunk in [1, 4, 7, 35, 100] {
+            let parallel = t.par_map_dim0(chunk, f).unwrap();
+            assert_eq!(parallel.size(), [35, 1]);
+            assert!(parallel.equal(&expected));
+        }
+    }
+
+    #[test]
+    fn par_map_dim0_errors() {
+        let t = Tensor::zeros([10, 3], (Kind::Float, tch::Device::Cpu));
+        assert!(t.par_map_dim0(0, |t| t).is_err());
+        assert!(Tensor::from(1.0).par_map_dim0(1, |t| t).is_err());
+        // Outputs with incompatible trailing dimensions cannot be concatenated.
+        let res = t.par_map_dim0(4, |t| if t.size()[0] == 4 { t } else { t.view(-1) });
+        assert!(res.is_err());
+        // The number of intra-op threads is restored.
+        let num_threads = tch::get_num_threads();
+        let _ = t.par_map_dim0(2, |t| t).unwrap();
+        assert_eq!(tch::get_num_threads(), num_threads);
+    }
+}
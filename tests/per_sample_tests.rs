@@ -0,0 +1,98 @@
+use tch::{autograd, Kind, Tensor};
+
+fn linear_params() -> (Tensor, Tensor) {
+    let ws =
+        Tensor::from_slice(&[0.1f32, -0.2, 0.3, 0.4, 0.5, -0.6, 0.7, 0.8, -0.9, 1.0, 1.1, 1.2])
+            .view([3, 4])
+            .set_requires_grad(true);
+    let bs = Tensor::from_slice(&[0.1f32, 0.2, -0.3]).set_requires_grad(true);
+    (ws, bs)
+}
+
+fn batch() -> (Tensor, Tensor) {
+    let xs = Tensor::arange(20, tch::kind::FLOAT_CPU).view([5, 4]).sin();
+    let ys = Tensor::arange(15, tch::kind::FLOAT_CPU).view([5, 3]).cos();
+    (xs, ys)
+}
+
+// The squared error of each sample.
+fn sample_losses(ws: &Tensor, bs: &Tensor, xs: &Tensor, ys: &Tensor) -> Tensor {
+    (xs.linear(ws, Some(bs)) - ys).square().sum_dim_intlist(1, false, Kind::Float)
+}
+
+#[test]
+fn per_sample_grads_sum_to_batch_grad() {
+    let (ws, bs) = linear_params();
+    let (xs, ys) = batch();
+    let params = [ws.shallow_clone(), bs.shallow_clone()];
+    let loss_fn = |xs: &Tensor, ys: &Tensor| sample_losses(&ws, &bs, xs, ys);
+    // The microbatches do not divide the batch evenly.
+    let grads = autograd::per_sample_grads(loss_fn, &params, &xs, &ys, 2);
+    assert_eq!(grads[0].size(), [5, 3, 4]);
+    assert_eq!(grads[1].size(), [5, 3]);
+
+    sample_losses(&ws, &bs, &xs, &ys).sum(Kind::Float).backward();
+    let sum = grads[0].sum_dim_intlist(0, false, Kind::Float);
+    assert!(sum.allclose(&ws.grad(), 1e-5, 1e-5, false));
+    let sum = grads[1].sum_dim_intlist(0, false, Kind::Float);
+    assert!(sum.allclose(&bs.grad(), 1e-5, 1e-5, false));
+
+    // The gradient of a single sample matches a backward pass on this sample.
+    let loss = sample_losses(&ws, &bs, &xs.narrow(0, 3, 1), &ys.narrow(0, 3, 1));
+    let sample = autograd::grad(&[&loss.sum(Kind::Float)], &[&ws], &[], false, false, false);
+    assert!(sample.unwrap()[0].as_ref().unwrap().allclose(&grads[0].get(3), 1e-5, 1e-5, false));
+
+    let loss_fn = |xs: &Tensor, ys: &Tensor| sample_losses(&ws, &bs, xs, ys).sum(Kind::Float);
+    assert!(autograd::f_per_sample_grads(loss_fn, &params, &xs, &ys, 2).is_err());
+}
+
+#[test]
+fn linear_per_sample_grads() {
+    let (ws, bs) = linear_params();
+    let (xs, ys) = batch();
+    let params = [ws.shallow_clone(), bs.shallow_clone()];
+    let loss_fn = |xs: &Tensor, ys: &Tensor| sample_losses(&ws, &bs, xs, ys);
+    let grads = autograd::per_sample_grads(loss_fn, &params, &xs, &ys, 5);
+
+    let outputs = xs.linear(&ws, Some(&bs));
+    let loss = (&outputs - &ys).square().sum(Kind::Float);
+    let grad_outputs = autograd::grad(&[&loss], &[&outputs], &[], false, false, false).unwrap();
+    let grad_outputs = grad_outputs[0].as_ref().unwrap();
+    let (ws_grads, bs_grads) = autograd::linear_per_sample_grads(&xs, grad_outputs);
+    assert!(ws_grads.allclose(&grads[0], 1e-5, 1e-5, false));
+    assert!(bs_grads.allclose(&grads[1], 1e-5, 1e-5, false));
+}
+
+#[test]
+fn clip_per_sample_grads() {
+    let (ws, bs) = linear_params();
+    let (xs, ys) = batch();
+    let params = [ws.shallow_clone(), bs.shallow_clone()];
+    let loss_fn = |xs: &Tensor, ys: &Tensor| sample_losses(&ws, &bs, xs, ys);
+    let grads = autograd::per_sample_grads(loss_fn, &params, &xs, &ys, 1);
+    let norms = |grads: &[Tensor]| -> Vec<f64> {
+        let sq = grads
+            .iter()
+            .map(|g| g.flatten(1, -1).square().sum_dim_intlist(1, false, Kind::Double))
+            .fold(Tensor::zeros([5], (Kind::Double, tch::Device::Cpu)), |acc, sq| acc + sq);
+        Vec::<f64>::try_from(sq.sqrt()).unwrap()
+    };
+    let before = norms(&grads);
+    let max_norm = 1.0;
+    assert!(before.iter().any(|&n| n > max_norm));
+    let clipped = autograd::clip_per_sample_grads(&grads, max_norm);
+    for (&n, &b) in norms(&clipped).iter().zip(before.iter()) {
+        assert!(n <= max_norm + 1e-5, "{n}");
+        if b <= max_norm {
+            assert!((n - b).abs() < 1e-5, "{n} {b}")
+        }
+    }
+
+    // Without noise, the DP-SGD gradients are the mean of the clipped ones.
+    let dp_grads = autograd::dp_sgd_grads(&grads, max_norm, 0.);
+    assert_eq!(dp_grads[0].size(), [3, 4]);
+    let mean = clipped[0].mean_dim(0, false, Kind::Float);
+    assert!(dp_grads[0].allclose(&mean, 1e-5, 1e-5, false));
+    let noisy = autograd::dp_sgd_grads(&grads, max_norm, 1.);
+    assert!(!noisy[1].allclose(&dp_grads[1], 1e-5, 1e-5, false));
+}
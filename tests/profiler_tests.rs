@@ -0,0 +1,32 @@
+// The profiler state is global so all the checks run from a single test.
+use tch::{profiler, Device, Kind, Tensor};
+
+#[test]
+fn profile_matmul() {
+    let xs = Tensor::randn([32, 32], (Kind::Float, Device::Cpu));
+    let config = profiler::ProfilerConfig { record_shapes: true, ..Default::default() };
+    let profile = profiler::profile(config, || {
+        let _ys = profiler::record_function("my_stage", || xs.matmul(&xs));
+    })
+    .unwrap();
+    let stats = profile.key_averages().unwrap();
+    let names: Vec<&str> = stats.iter().map(|s| s.name.as_str()).collect();
+    assert!(names.contains(&"my_stage"), "{names:?}");
+    assert!(names.iter().any(|n| *n == "aten::matmul" || *n == "aten::mm"), "{names:?}");
+    let my_stage = stats.iter().find(|s| s.name == "my_stage").unwrap();
+    assert_eq!(my_stage.count, 1);
+
+    let path = std::env::temp_dir().join(format!("tch-trace-{}.json", std::process::id()));
+    profile.export_chrome_trace(&path).unwrap();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(trace.contains("traceEvents"));
+    assert!(trace.contains("my_stage"));
+
+    // The profiler is stopped even if the profiled closure panics.
+    let res = std::panic::catch_unwind(|| {
+        let _ = profiler::profile(Default::default(), || panic!("profiled closure"));
+    });
+    assert!(res.is_err());
+    assert!(profiler::profile(Default::default(), || ()).is_ok());
+}
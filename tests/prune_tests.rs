@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use tch::nn::{self, Module, OptimizerConfig};
+use tch::prune::{self, Granularity, PrunedConv2D, PrunedLinear};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+fn pruned_count(mask: &Tensor) -> usize {
+    vec_bool_from(&mask.reshape([-1])).iter().filter(|&&kept| !kept).count()
+}
+
+#[test]
+fn sparsity_levels() {
+    tch::manual_seed(0);
+    let weight = Tensor::randn([8, 2, 3, 3], tch::kind::FLOAT_CPU);
+    for sparsity in [0., 0.25, 0.5, 0.75, 1.] {
+        let mask = prune::magnitude_mask(&weight, sparsity, Granularity::Elementwise);
+        assert_eq!(mask.kind(), Kind::Bool);
+        assert_eq!(mask.size(), weight.size());
+        assert_eq!(pruned_count(&mask), (144. * sparsity) as usize);
+
+        let mask = prune::magnitude_mask(&weight, sparsity, Granularity::Row);
+        assert_eq!(pruned_count(&mask), (144. * sparsity) as usize);
+        let rows =
+            mask.reshape([-1, 3]).to_kind(Kind::Int64).sum_dim_intlist(1, false, Kind::Int64);
+        assert!(vec_i64_from(&rows).iter().all(|&n| n == 0 || n == 3));
+
+        let mask = prune::magnitude_mask(&weight, sparsity, Granularity::Channel);
+        let kept = prune::kept_channels(&mask).unwrap();
+        assert_eq!(kept.len(), 8 - (8. * sparsity) as usize);
+        assert_eq!(pruned_count(&mask), 18 * (8 - kept.len()));
+    }
+    // The pruned weights have the smallest magnitudes.
+    let mask = prune::magnitude_mask(&weight, 0.5, Granularity::Elementwise);
+    let pruned_max = f64_from(&weight.abs().masked_select(&mask.logical_not()).max());
+    let kept_min = f64_from(&weight.abs().masked_select(&mask).min());
+    assert!(pruned_max <= kept_min);
+    assert!(prune::f_magnitude_mask(&weight, 1.5, Granularity::Row).is_err());
+}
+
+#[test]
+fn apply_masks() {
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root() / "fc", 10, 4, Default::default());
+    let mask = prune::magnitude_mask(&linear.ws, 0.3, Granularity::Elementwise);
+    let masks = HashMap::from([("fc.weight".to_string(), mask.shallow_clone())]);
+    prune::apply_masks(&mut vs, &masks).unwrap();
+    let zeros = vec_f64_from(&linear.ws.reshape([-1])).iter().filter(|&&w| w == 0.).count();
+    assert_eq!(zeros, 12);
+    let masks = HashMap::from([("fc.missing".to_string(), mask)]);
+    assert!(prune::apply_masks(&mut vs, &masks).is_err());
+}
+
+#[test]
+fn masked_weights_stay_zero() {
+    tch::manual_seed(0);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root() / "fc", 6, 4, Default::default());
+    let mask = prune::magnitude_mask(&linear.ws, 0.5, Granularity::Elementwise);
+    let layer = PrunedLinear::new(vs.root() / "fc", linear, &mask);
+    assert!(vs.variables().contains_key("fc.weight_mask"));
+    assert_eq!(vs.trainable_variables().len(), 2);
+    assert_eq!(layer.sparsity(), 0.5);
+    let kept_init = layer.base.ws.masked_select(&mask);
+
+    let mut opt = nn::Adam { wd: 1e-2, ..Default::default() }.build(&vs, 1e-2).unwrap();
+    let xs = Tensor::randn([16, 6], tch::kind::FLOAT_CPU);
+    let ys = Tensor::randn([16, 4], tch::kind::FLOAT_CPU);
+    for _ in 0..5 {
+        let loss = layer.forward(&xs).mse_loss(&ys, tch::Reduction::Mean);
+        opt.backward_step(&loss);
+    }
+    let pruned = layer.base.ws.masked_select(&mask.logical_not());
+    assert_eq!(vec_f64_from(&pruned), [0.; 12]);
+    assert_ne!(vec_f64_from(&layer.base.ws.masked_select(&mask)), vec_f64_from(&kept_init));
+}
+
+#[test]
+fn compact_two_layer_cnn() {
+    tch::manual_seed(0);
+    let vs = nn::VarStore::new(Device::Cpu);
+    let config = nn::ConvConfig { padding: nn::Padding::Explicit(1), ..Default::default() };
+    let conv1 = nn::conv2d(vs.root() / "conv1", 3, 6, 3, config);
+    let conv2 = nn::conv2d(vs.root() / "conv2", 6, 2, 3, config);
+    tch::no_grad(|| {
+        let _ = conv1.bs.as_ref().unwrap().shallow_clone().uniform_(-1., 1.);
+    });
+    let mask = prune::magnitude_mask(&conv1.ws, 0.5, Granularity::Channel);
+    let conv1 = PrunedConv2D::new(vs.root() / "conv1", conv1, &mask);
+    let xs = Tensor::randn([2, 3, 5, 5], tch::kind::FLOAT_CPU);
+    let ys = conv2.forward(&conv1.forward(&xs).relu());
+
+    let kept = conv1.kept_channels().unwrap();
+    assert_eq!(kept.len(), 3);
+    let compact_vs = nn::VarStore::new(Device::Cpu);
+    let (compact1, index_map) =
+        prune::compact_conv2d(&(compact_vs.root() / "conv1"), &conv1.base, &kept).unwrap();
+    let compact2 =
+        prune::compact_conv2d_inputs(&(compact_vs.root() / "conv2"), &conv2, &index_map).unwrap();
+    assert_eq!(compact1.ws.size(), [3, 3, 3, 3]);
+    assert_eq!(compact2.ws.size(), [2, 3, 3, 3]);
+    let compact_ys = compact2.forward(&compact1.forward(&xs).relu());
+    assert!(compact_ys.allclose(&ys, 1e-5, 1e-5, false));
+    assert!(prune::compact_conv2d(&compact_vs.root(), &conv2, &[0, 0]).is_err());
+}
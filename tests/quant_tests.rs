@@ -0,0 +1,82 @@
+use tch::nn::{self, Module};
+use tch::quant::{self, QLinear, QuantConfig};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+fn relative_error(ys: &Tensor, expected: &Tensor) -> f64 {
+    f64_from(&(ys - expected).norm()) / f64_from(&expected.norm())
+}
+
+// A linear layer with weights in the usual range of trained layers.
+fn linear(vs: &nn::VarStore) -> nn::Linear {
+    tch::manual_seed(0);
+    nn::linear(vs.root() / "fc", 256, 64, Default::default())
+}
+
+#[test]
+fn int8_group_accuracy() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = linear(&vs);
+    let xs = Tensor::randn([16, 256], (Kind::Float, Device::Cpu));
+    let expected = linear.forward(&xs);
+    for symmetric in [true, false] {
+        let qvs = nn::VarStore::new(Device::Cpu);
+        let config = QuantConfig { bits: 8, group_size: 64, symmetric };
+        let qlinear = QLinear::from_linear(qvs.root() / "fc", &linear, config);
+        assert_eq!(qlinear.weight.packed.kind(), Kind::Uint8);
+        assert_eq!(qlinear.weight.packed.size(), [64, 256]);
+        assert_eq!(qlinear.weight.scales.size(), [64, 4]);
+        assert_eq!(qlinear.weight.zero_points.is_some(), !symmetric);
+        let err = relative_error(&qlinear.forward(&xs), &expected);
+        assert!(err < 1e-2, "symmetric {symmetric} error {err}");
+    }
+}
+
+#[test]
+fn int4_packing() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = linear(&vs);
+    let config = QuantConfig { bits: 4, group_size: 32, symmetric: false };
+    let qw = quant::quantize_weights(&linear.ws, config);
+    assert_eq!(qw.packed.size(), [64, 128]);
+    assert_eq!(qw.shape().unwrap(), (64, 256));
+    let ws = qw.dequantize();
+    assert!(relative_error(&ws, &linear.ws) < 0.15);
+    // Each group of 32 weights uses 16 bytes, a 4 bytes scale and a zero point.
+    assert_eq!(qw.size_in_bytes(), 64 * (128 + 8 * 5));
+}
+
+#[test]
+fn invalid_config() {
+    let ws = Tensor::randn([4, 10], (Kind::Float, Device::Cpu));
+    let config = QuantConfig { bits: 8, group_size: 4, symmetric: true };
+    assert!(quant::f_quantize_weights(&ws, config).is_err());
+    let config = QuantConfig { bits: 4, group_size: 5, symmetric: true };
+    assert!(quant::f_quantize_weights(&ws, config).is_err());
+    let config = QuantConfig { bits: 2, group_size: 2, symmetric: true };
+    assert!(quant::f_quantize_weights(&ws, config).is_err());
+}
+
+#[test]
+fn save_and_load() {
+    let filename = std::env::temp_dir().join(format!("tch-quant-{}.ot", std::process::id()));
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = linear(&vs);
+    for bits in [8, 4] {
+        let config = QuantConfig { bits, group_size: 32, symmetric: false };
+        let vs1 = nn::VarStore::new(Device::Cpu);
+        let q1 = QLinear::from_linear(vs1.root() / "fc", &linear, config);
+        vs1.save(&filename).unwrap();
+        let mut vs2 = nn::VarStore::new(Device::Cpu);
+        let q2 = quant::qlinear(vs2.root() / "fc", 256, 64, config, true);
+        vs2.load(&filename).unwrap();
+        assert!(q2.weight.packed.equal(&q1.weight.packed));
+        assert_eq!(q2.weight.packed.kind(), Kind::Uint8);
+        assert!(q2.weight.scales.equal(&q1.weight.scales));
+        assert!(q2.weight.zero_points.unwrap().equal(q1.weight.zero_points.as_ref().unwrap()));
+        assert!(q2.bs.unwrap().equal(q1.bs.as_ref().unwrap()));
+    }
+    std::fs::remove_file(filename).unwrap();
+}
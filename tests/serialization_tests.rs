@@ -159,6 +159,37 @@ fn save_and_load_npy() {
     assert_eq!(vec_f64_from(&pi.flatten(0, -1)), [3.0, 1.0, 4.0, 1.0, 5.0, 9.0]);
 }
 
+#[test]
+fn npy_round_trip_kinds() {
+    let tmp_file = TmpFile::create("npy-round-trip-kinds");
+    let xs = Tensor::from_slice(&[3, 1, 4, 1, 5, 9, 0, 6]).view([2, 4]);
+    let kinds = [
+        Kind::Half,
+        Kind::Float,
+        Kind::Double,
+        Kind::Int8,
+        Kind::Uint8,
+        Kind::Int16,
+        Kind::Int,
+        Kind::Int64,
+        Kind::Bool,
+    ];
+    for kind in kinds {
+        let xs = xs.to_kind(kind);
+        xs.write_npy(&tmp_file).unwrap();
+        let ys = Tensor::read_npy(&tmp_file).unwrap();
+        assert_eq!(ys.kind(), kind);
+        assert_eq!(ys, xs);
+    }
+    // Non-contiguous tensors are written in row-major order.
+    let xs = xs.to_kind(Kind::Float).tr();
+    assert!(!xs.is_contiguous());
+    Tensor::write_npz(&[("xs", &xs)], &tmp_file).unwrap();
+    let named_tensors = Tensor::read_npz(&tmp_file).unwrap();
+    assert_eq!(named_tensors[0].0, "xs");
+    assert_eq!(named_tensors[0].1, xs);
+}
+
 #[test]
 fn save_and_load_safetensors() {
     let tmp_file = TmpFile::create("save-and-load-safetensors");
@@ -193,3 +224,115 @@ fn save_and_load_safetensors_half() {
         }
     }
 }
+
+#[test]
+fn to_and_from_le_bytes() {
+    let t1 = Tensor::from_slice(&[3.0f32, 1.0, 4.0, 1.0, 5.0, 9.0]).view([2, 3]);
+    let bytes = t1.to_le_bytes();
+    let t2 = Tensor::from_le_bytes(&bytes).unwrap();
+    assert_eq!(t2.kind(), Kind::Float);
+    assert_eq!(t2.size(), [2, 3]);
+    assert_eq!(vec_f32_from(&t2), [3.0, 1.0, 4.0, 1.0, 5.0, 9.0]);
+
+    // Non-contiguous tensors go through a copy.
+    let t3 = Tensor::from_le_bytes(&t1.tr().to_le_bytes()).unwrap();
+    assert_eq!(t3.size(), [3, 2]);
+    assert_eq!(vec_f32_from(&t3), [3.0, 1.0, 1.0, 5.0, 4.0, 9.0]);
+
+    for kind in [Kind::Uint8, Kind::Int16, Kind::Int64, Kind::Half, Kind::BFloat16, Kind::Bool] {
+        let t = Tensor::from_slice(&[0i64, 1, 1, 0]).to_kind(kind);
+        let t2 = Tensor::from_le_bytes(&t.to_le_bytes()).unwrap();
+        assert_eq!(t2.kind(), kind);
+        assert_eq!(vec_i64_from(&t2.to_kind(Kind::Int64)), [0, 1, 1, 0]);
+    }
+
+    // Trailing or missing bytes are rejected.
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert!(Tensor::from_le_bytes(&longer).is_err());
+    assert!(Tensor::from_le_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn write_and_read_le_bytes_stream() {
+    let t1 = Tensor::from_slice(&[1i64, 2, 3]);
+    let t2 = Tensor::from_slice(&[0.5f64]).view(());
+    let mut buffer = vec![];
+    t1.write_le_bytes(&mut buffer).unwrap();
+    t2.write_le_bytes(&mut buffer).unwrap();
+    let mut reader = buffer.as_slice();
+    let r1 = Tensor::read_le_bytes(&mut reader).unwrap();
+    let r2 = Tensor::read_le_bytes(&mut reader).unwrap();
+    assert!(reader.is_empty());
+    assert_eq!(vec_i64_from(&r1), [1, 2, 3]);
+    assert_eq!(r2.size(), Vec::<i64>::new());
+    assert_eq!(f64_from(&r2), 0.5);
+    assert!(Tensor::read_le_bytes(&mut reader).is_err());
+}
+
+// A temporary directory removed with its content when dropped.
+struct TmpDir(std::path::PathBuf);
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).unwrap()
+    }
+}
+
+// Saves and loads tensors, var-stores, images and modules in `dir`.
+fn round_trips_in(dir: &std::path::Path) {
+    let t = Tensor::from_slice(&[3u8, 1, 4, 1, 5, 9]).view([1, 2, 3]);
+    t.save(dir.join("tensor.pt")).unwrap();
+    assert_eq!(vec_i64_from(&Tensor::load(dir.join("tensor.pt")).unwrap()), [3, 1, 4, 1, 5, 9]);
+    let named = [("t", t.shallow_clone())];
+    Tensor::save_multi(&named, dir.join("multi.ot")).unwrap();
+    assert_eq!(Tensor::load_multi(dir.join("multi.ot")).unwrap().len(), 1);
+    Tensor::write_npz(&named, dir.join("tensors.npz")).unwrap();
+    assert_eq!(Tensor::read_npz(dir.join("tensors.npz")).unwrap().len(), 1);
+    Tensor::write_safetensors(&named, dir.join("tensors.safetensors")).unwrap();
+    assert_eq!(Tensor::read_safetensors(dir.join("tensors.safetensors")).unwrap().len(), 1);
+
+    let vs = tch::nn::VarStore::new(tch::Device::Cpu);
+    let _ = vs.root().ones("w", &[2]);
+    vs.save(dir.join("vs.safetensors")).unwrap();
+    vs.save(dir.join("vs.ot")).unwrap();
+    let mut vs2 = tch::nn::VarStore::new(tch::Device::Cpu);
+    let w = vs2.root().zeros("w", &[2]);
+    vs2.load(dir.join("vs.ot")).unwrap();
+    assert_eq!(vec_f64_from(&w), [1., 1.]);
+
+    let image = Tensor::full([3, 4, 5], 42, (Kind::Uint8, tch::Device::Cpu));
+    tch::vision::image::save(&image, dir.join("image.png")).unwrap();
+    assert_eq!(tch::vision::image::load(dir.join("image.png")).unwrap().size(), [3, 4, 5]);
+
+    std::fs::copy("tests/foo.pt", dir.join("foo.pt")).unwrap();
+    let module = tch::CModule::load(dir.join("foo.pt")).unwrap();
+    module.save(dir.join("foo2.pt")).unwrap();
+    assert!(tch::CModule::load(dir.join("foo2.pt")).is_ok());
+
+    let missing = dir.join("missing.pt");
+    let err = Tensor::load(&missing).unwrap_err().to_string();
+    assert!(err.contains(missing.to_str().unwrap()), "{err}");
+    let err = Tensor::read_npy(dir.join("missing.npy")).unwrap_err().to_string();
+    assert!(err.contains(dir.join("missing.npy").to_str().unwrap()), "{err}");
+}
+
+#[test]
+fn unicode_paths() {
+    let dir = std::env::temp_dir().join(format!("tch-路径-テスト-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = TmpDir(dir);
+    round_trips_in(&dir.0);
+}
+
+#[test]
+fn long_paths() {
+    let root = std::env::temp_dir().join(format!("tch-long-{}", std::process::id()));
+    let mut dir = root.clone();
+    while dir.as_os_str().len() < 300 {
+        dir = dir.join("d".repeat(50));
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    let _root = TmpDir(root);
+    round_trips_in(&dir);
+}
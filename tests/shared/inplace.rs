@@ -0,0 +1,9 @@
+use tch::{Device, Kind, SharedTensor, Tensor};
+
+fn main() {
+    let xs = SharedTensor::new(Tensor::zeros([3], (Kind::Float, Device::Cpu)));
+    let ys = xs.clone();
+    std::thread::spawn(move || {
+        let _ = ys.f_add_(&Tensor::ones([3], (Kind::Float, Device::Cpu)));
+    });
+}
@@ -0,0 +1,7 @@
+use tch::{Device, Kind, SharedTensor, Tensor};
+
+fn main() {
+    let xs = SharedTensor::new(Tensor::zeros([3], (Kind::Float, Device::Cpu)));
+    let mut ys = xs.shallow_clone();
+    let _ = ys.f_add_(&Tensor::ones([3], (Kind::Float, Device::Cpu)));
+}
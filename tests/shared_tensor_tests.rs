@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use tch::nn::{self, Module};
+use tch::{Device, Kind, SharedTensor, Tensor};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn shared_tensor_send_sync() {
+    assert_send_sync::<SharedTensor>();
+    let xs = SharedTensor::new(Tensor::from_slice(&[1f32, 2., 3.]));
+    let ys = xs.clone();
+    assert_eq!(SharedTensor::handle_count(&xs), 2);
+    let xs = SharedTensor::try_unwrap(xs).unwrap_err();
+    drop(ys);
+    let mut xs = SharedTensor::try_unwrap(xs).unwrap();
+    let _ = xs.f_add_(&Tensor::ones([3], (Kind::Float, Device::Cpu))).unwrap();
+    assert_eq!(Vec::<f32>::try_from(&xs).unwrap(), [2., 3., 4.]);
+}
+
+#[test]
+fn shared_var_store_concurrent_forward() {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root() / "linear", 64, 32, Default::default());
+    let xs = Tensor::randn([16, 64], (Kind::Float, Device::Cpu));
+    let expected = tch::no_grad(|| linear.forward(&xs));
+    let weights = Arc::new(vs.shared_variables());
+    let xs = SharedTensor::new(xs);
+    let threads: Vec<_> = (0..16)
+        .map(|_| {
+            let weights = weights.clone();
+            let xs = xs.clone();
+            std::thread::spawn(move || {
+                let ws = &weights["linear.weight"];
+                let bs = &weights["linear.bias"];
+                let xs = xs.copy();
+                let mut outputs = vec![];
+                for _ in 0..200 {
+                    // The forward pass is recorded by autograd as the weights
+                    // require grad.
+                    let ys = ws.linear(&xs, Some(bs));
+                    assert!(ys.requires_grad());
+                    outputs.push(ys.detach())
+                }
+                outputs
+            })
+        })
+        .collect();
+    for thread in threads {
+        for ys in thread.join().unwrap() {
+            assert!(ys.allclose(&expected, 1e-6, 1e-6, false));
+        }
+    }
+    // The var-store still holds the weights, the shared handles are the only
+    // ones left once the threads have been joined.
+    let weights = Arc::try_unwrap(weights).unwrap();
+    for (_, ws) in weights {
+        assert!(SharedTensor::try_unwrap(ws).is_ok());
+    }
+}
+
+#[test]
+fn shared_tensor_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/shared/*.rs");
+}
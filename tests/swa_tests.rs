@@ -0,0 +1,63 @@
+use tch::nn::{self, ModuleT, Swa, SwaLr};
+use tch::{Device, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn averaged_weights() {
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let linear = nn::linear(vs.root(), 3, 2, Default::default());
+    let mut swa = Swa::new(&vs);
+    assert_eq!(swa.averages().len(), 2);
+    assert!(swa.f_finalize(&mut vs, std::iter::empty::<Tensor>(), |xs| xs).is_err());
+    for v in [1., 2., 6.] {
+        tch::no_grad(|| {
+            let _ = linear.ws.shallow_clone().fill_(v);
+            let _ = linear.bs.as_ref().unwrap().shallow_clone().fill_(-v);
+        });
+        swa.update(&vs);
+    }
+    assert_eq!(swa.n_averaged(), 3);
+    assert_eq!(vec_f64_from(&swa.averages()["weight"]), [3.; 6]);
+    assert_eq!(vec_f64_from(&swa.averages()["bias"]), [-3.; 2]);
+    // The model has no batch-norm layer so the data is not used.
+    swa.finalize(&mut vs, std::iter::empty::<Tensor>(), |xs| xs);
+    assert_eq!(vec_f64_from(&linear.ws), [3.; 6]);
+    assert_eq!(vec_f64_from(linear.bs.as_ref().unwrap()), [-3.; 2]);
+}
+
+#[test]
+fn batch_norm_statistics() {
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let bn = nn::batch_norm1d(vs.root(), 2, Default::default());
+    let xs = Tensor::from_slice(&[5f32, 5., 5., 7.]).view([2, 2]);
+    for _ in 0..10 {
+        let _ = bn.forward_t(&xs, true);
+    }
+    let last_mean = vec_f64_from(&bn.running_mean);
+    let mut swa = Swa::new(&vs);
+    swa.update(&vs);
+    // The batch means are [1, 3] and [4, 2], the unbiased variances are all 2.
+    let data = [
+        Tensor::from_slice(&[0f32, 2., 2., 4.]).view([2, 2]),
+        Tensor::from_slice(&[3f32, 1., 5., 3.]).view([2, 2]),
+    ];
+    swa.finalize(&mut vs, data.iter(), |xs| bn.forward_t(xs, true));
+    assert_ne!(vec_f64_from(&bn.running_mean), last_mean);
+    assert_eq!(vec_f64_from(&bn.running_mean), [2.5, 2.5]);
+    assert_eq!(vec_f64_from(&bn.running_var), [2., 2.]);
+}
+
+#[test]
+fn swa_lr() {
+    let lr = SwaLr::constant(10, 0.05);
+    assert_eq!(lr.lr(0), 0.05);
+    assert_eq!(lr.lr(7), 0.05);
+    let lr = SwaLr::cyclic(10, 0.5, 0.1, 4);
+    let lrs: Vec<f64> = (0..5).map(|step| lr.lr(step)).collect();
+    let expected = [0.4, 0.3, 0.2, 0.1, 0.4];
+    for (lr, expected) in lrs.iter().zip(expected.iter()) {
+        assert!((lr - expected).abs() < 1e-9, "{lrs:?}")
+    }
+}
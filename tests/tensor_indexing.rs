@@ -227,3 +227,76 @@ fn indexing_doc() {
     let t = tensor.i((.., .., NewAxis));
     assert_eq!(t.size(), &[2, 3, 1]);
 }
+
+#[test]
+fn index_out_of_range() {
+    let tensor = Tensor::zeros([5, 3], (Kind::Float, Device::Cpu));
+    let err = tensor.f_get(7).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "index 7 out of range for dimension 0 of size 5 (tensor shape [5, 3])"
+    );
+    match tensor.f_select(-1, -4) {
+        Err(tch::TchError::IndexOutOfRange { dim, index, size, shape }) => {
+            assert_eq!((dim, index, size), (1, -4, 3));
+            assert_eq!(shape, [5, 3]);
+        }
+        res => panic!("unexpected result {res:?}"),
+    }
+    assert_eq!(tensor.f_select(-1, -3).unwrap().size(), [5]);
+    assert!(matches!(tensor.f_select(2, 0), Err(tch::TchError::Shape(_))));
+    match tensor.f_i((1, 3)) {
+        Err(tch::TchError::IndexOutOfRange { dim, index, size, shape }) => {
+            assert_eq!((dim, index, size), (1, 3, 3));
+            assert_eq!(shape, [5, 3]);
+        }
+        res => panic!("unexpected result {res:?}"),
+    }
+    match tensor.f_i((.., NewAxis, -4)) {
+        Err(tch::TchError::IndexOutOfRange { dim, index, size, shape }) => {
+            assert_eq!((dim, index, size), (1, -4, 3));
+            assert_eq!(shape, [5, 3]);
+        }
+        res => panic!("unexpected result {res:?}"),
+    }
+    let err = tensor.f_narrow(0, 3, 4).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid shape: narrow: start 3 + length 4 exceeds size 5 of dimension 0 (tensor shape [5, 3])"
+    );
+    assert!(matches!(tensor.f_i(2..7), Err(tch::TchError::Shape(_))));
+    assert!(matches!(Tensor::from(1.).f_get(0), Err(tch::TchError::Shape(_))));
+}
+
+// The validation done on the Rust side should accept exactly the same indexes
+// as libtorch, select_copy and narrow_tensor are not validated on the Rust side.
+#[test]
+fn index_validation_matches_libtorch() {
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+    for _ in 0..500 {
+        let ndim = rng.gen_range(0..4);
+        let shape: Vec<i64> = (0..ndim).map(|_| rng.gen_range(0..4)).collect();
+        let tensor = Tensor::zeros(shape.as_slice(), (Kind::Float, Device::Cpu));
+        let dim = rng.gen_range(-4..4);
+        let index = rng.gen_range(-6..6);
+        let length = rng.gen_range(-1..6);
+        assert_eq!(
+            tensor.f_select(dim, index).is_ok(),
+            tensor.f_select_copy(dim, index).is_ok(),
+            "select {shape:?} {dim} {index}"
+        );
+        assert_eq!(
+            tensor.f_narrow(dim, index, length).is_ok(),
+            tensor.f_narrow_tensor(dim, &Tensor::from(index), length).is_ok(),
+            "narrow {shape:?} {dim} {index} {length}"
+        );
+        if ndim > 0 {
+            assert_eq!(
+                tensor.f_get(index).is_ok(),
+                tensor.f_select_copy(0, index).is_ok(),
+                "get {shape:?} {index}"
+            );
+        }
+    }
+}
@@ -112,6 +112,170 @@ fn grad_without_requires() {
     let _dy_over_dx = Tensor::run_backward(&[y], &[&x], true, true);
 }
 
+#[test]
+fn autograd_grad_second_order() -> Result<()> {
+    // y = sum(x^3 - 2x^2), dy/dx = 3x^2 - 4x, d2y/dx2 = 6x - 4.
+    let x = Tensor::from_slice(&[1f64, 2., 3.]).set_requires_grad(true);
+    let y = (x.pow_tensor_scalar(3) - x.pow_tensor_scalar(2) * 2).sum(tch::Kind::Double);
+    let dy = tch::autograd::grad(&[&y], &[&x], &[], true, true, false)?;
+    let dy = dy[0].as_ref().unwrap();
+    assert_eq!(vec_f64_from(dy), [-1., 4., 15.]);
+    let d2y = tch::autograd::grad(&[&dy.sum(tch::Kind::Double)], &[&x], &[], false, false, false)?;
+    assert_eq!(vec_f64_from(d2y[0].as_ref().unwrap()), [2., 8., 14.]);
+    // The gradients are returned rather than accumulated.
+    assert!(!x.grad().defined());
+    Ok(())
+}
+
+#[test]
+fn autograd_grad_allow_unused() -> Result<()> {
+    let x = Tensor::from_slice(&[1f32, 2.]).set_requires_grad(true);
+    let z = Tensor::from_slice(&[3f32]).set_requires_grad(true);
+    let y = &x * 2;
+    let grad_outputs = Tensor::from_slice(&[1f32, 10.]);
+    assert!(tch::autograd::grad(&[&y], &[&x, &z], &[&grad_outputs], true, false, false).is_err());
+    let grads = tch::autograd::grad(&[&y], &[&x, &z], &[&grad_outputs], false, false, true)?;
+    assert_eq!(vec_f32_from(grads[0].as_ref().unwrap()), [2., 20.]);
+    assert!(grads[1].is_none());
+    Ok(())
+}
+
+#[test]
+fn backward_opt() -> Result<()> {
+    use tch::autograd::BackwardOptions;
+    let x = Tensor::from_slice(&[1f32, 2.]).set_requires_grad(true);
+    let w = Tensor::from_slice(&[3f32, 4.]).set_requires_grad(true);
+    let y = (&x * &w).exp().sum(tch::Kind::Float);
+    let retain = BackwardOptions { retain_graph: true, ..Default::default() };
+    y.backward_opt(BackwardOptions { inputs: Some(&[&x]), ..retain })?;
+    assert!(!w.grad().defined());
+    let dx = (&x * &w).exp() * &w;
+    assert!(x.grad().allclose(&dx, 1e-5, 1e-5, false));
+    // The graph was retained so the backward pass can be run again, the
+    // gradients get accumulated.
+    y.backward_opt(Default::default())?;
+    assert!(x.grad().allclose(&(&dx * 2), 1e-5, 1e-5, false));
+    assert!(w.grad().defined());
+    // The graph has now been released.
+    assert!(y.backward_opt(Default::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn leaf_and_grad_introspection() -> Result<()> {
+    let x = Tensor::from_slice(&[1f32, 2.]).requires_grad_(true);
+    assert!(x.is_leaf() && x.requires_grad());
+    assert!(x.grad_opt().is_none());
+    let y = &x * 3;
+    assert!(!y.is_leaf());
+    assert!(!y.retains_grad());
+    y.f_retain_grad()?;
+    assert!(y.retains_grad());
+    y.sum(tch::Kind::Float).backward();
+    assert_eq!(vec_f32_from(&x.grad_opt().unwrap()), [3., 3.]);
+    assert_eq!(vec_f32_from(&y.grad_opt().unwrap()), [1., 1.]);
+
+    // Tensors that do not require grad are leaves without gradients.
+    let z = Tensor::from_slice(&[1f32, 2.]);
+    assert!(z.is_leaf() && !z.requires_grad());
+    assert!(z.grad_opt().is_none());
+    Ok(())
+}
+
+#[test]
+fn set_requires_grad_non_leaf() {
+    let x = Tensor::from_slice(&[1f32, 2.]).set_requires_grad(true);
+    let y = &x * 3;
+    let err = y.f_set_requires_grad(true).unwrap_err().to_string();
+    assert!(err.contains("set_requires_grad can only be called on leaf tensors"), "{err}");
+    assert!(err.contains("detach().set_requires_grad(true)"), "{err}");
+    assert!(y.f_set_requires_grad(false).is_err());
+    // Detaching returns a leaf on which gradients can be tracked.
+    let z = y.detach().set_requires_grad(true);
+    assert!(z.is_leaf() && z.requires_grad());
+}
+
+#[test]
+fn retain_grad_without_requires_grad() {
+    let x = Tensor::from_slice(&[1f32, 2.]);
+    let err = x.f_retain_grad().unwrap_err().to_string();
+    assert!(err.contains("can't retain_grad on Tensor that has requires_grad=False"), "{err}");
+}
+
+#[test]
+fn scalar_fill_values() {
+    // 2^53 + 1 cannot be represented as a f64.
+    let v = 9007199254740993i64;
+    let t = Tensor::full([2], v, (tch::Kind::Int64, Device::Cpu));
+    assert_eq!(Vec::<i64>::try_from(&t).unwrap(), [v, v]);
+    let mut t = Tensor::zeros([2], (tch::Kind::Int64, Device::Cpu));
+    let _ = t.fill_(v);
+    assert_eq!(Vec::<i64>::try_from(&t).unwrap(), [v, v]);
+
+    let mut t = Tensor::from_slice(&[false, false, true]);
+    let mask = Tensor::from_slice(&[true, false, false]);
+    let _ = t.masked_fill_(&mask, true);
+    assert_eq!(Vec::<bool>::try_from(&t).unwrap(), [true, false, true]);
+
+    let t = Tensor::full([2], 3i16, (tch::Kind::Int16, Device::Cpu));
+    assert_eq!(Vec::<i16>::try_from(&t).unwrap(), [3, 3]);
+    let t = Tensor::from_slice(&[1f32, 2.]) * 0.5f32;
+    assert_eq!(vec_f32_from(&t), [0.5, 1.]);
+}
+
+fn vjp_fn(x: &Tensor) -> Tensor {
+    // f(x) = [x0 * x1, x0^2, sin(x1)]
+    let (x0, x1) = (x.get(0), x.get(1));
+    Tensor::stack(&[&x0 * &x1, &x0 * &x0, x1.sin()], 0)
+}
+
+#[test]
+fn vjp() -> Result<()> {
+    let x = Tensor::from_slice(&[2f64, 3.]).set_requires_grad(true);
+    let y = vjp_fn(&x);
+    let jacobian = [[3., 2.], [4., 0.], [0., 3f64.cos()]];
+    // Assemble the Jacobian row by row using unit cotangents.
+    for (i, row) in jacobian.iter().enumerate() {
+        let cotangent = Tensor::zeros([3], (tch::Kind::Double, Device::Cpu)).index_fill(
+            0,
+            &Tensor::from(i as i64),
+            1.,
+        );
+        let grads = y.vjp(&cotangent, &[&x])?;
+        assert!(grads[0].allclose(&Tensor::from_slice(row), 1e-9, 1e-9, false));
+    }
+    let v = Tensor::from_slice(&[1f64, 2., 3.]);
+    let expected = Tensor::from_slice(&[3. + 2. * 4., 2. + 3. * 3f64.cos()]);
+    let unused = Tensor::from_slice(&[1f64]).set_requires_grad(true);
+    let grads = y.vjp(&v, &[&x, &unused])?;
+    assert!(grads[0].allclose(&expected, 1e-9, 1e-9, false));
+    assert_eq!(vec_f64_from(&grads[1]), [0.]);
+    assert!(!x.grad().defined());
+
+    // The same product accumulated in the grad field.
+    tch::autograd::backward(&[&y], &[&v], false, false)?;
+    assert!(x.grad().allclose(&expected, 1e-9, 1e-9, false));
+    Ok(())
+}
+
+#[test]
+fn vjp_shape_mismatch() {
+    let x = Tensor::from_slice(&[2f64, 3.]).set_requires_grad(true);
+    let y = vjp_fn(&x);
+    let v = Tensor::from_slice(&[1f64, 2.]);
+    let err = y.vjp(&v, &[&x]).unwrap_err();
+    assert!(matches!(err, TchError::Shape(_)), "{err}");
+    let err = tch::autograd::backward(&[&y], &[&v], false, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid shape: backward: grad tensor 0 has shape [2], expected [3]"
+    );
+    let err = tch::autograd::backward(&[&y], &[], false, false).unwrap_err();
+    assert!(matches!(err, TchError::Shape(_)), "{err}");
+    // Nothing was dispatched so the graph can still be used.
+    assert!(tch::autograd::backward(&[&y], &[&Tensor::ones_like(&y)], false, false).is_ok());
+}
+
 #[test]
 fn cat_and_stack() {
     let t = Tensor::from_slice(&[13.0, 37.0]);
@@ -235,6 +399,55 @@ fn from_ndarray_bool() {
     assert_eq!(vec_bool_from(&tensor).as_slice(), nd.as_slice().unwrap());
 }
 
+#[test]
+fn ndarray_round_trip() {
+    fn round_trip<T: tch::kind::Element + PartialEq + std::fmt::Debug + Send>(values: Vec<T>) {
+        let nd = ndarray::Array::from_shape_vec((2, 3), values).unwrap().into_dyn();
+        let tensor = Tensor::from_ndarray(nd.clone());
+        assert_eq!(tensor.kind(), T::KIND);
+        assert_eq!(tensor.size(), [2, 3]);
+        let back: ndarray::ArrayD<T> = (&tensor).try_into().unwrap();
+        assert_eq!(back, nd);
+        let borrowed = Tensor::try_from(&nd).unwrap();
+        assert_eq!(ndarray::ArrayD::<T>::try_from(borrowed).unwrap(), nd);
+    }
+    round_trip(vec![1f32, 2., 3., 4., 5., 6.]);
+    round_trip(vec![1f64, -2., 3.5, 4., 5., 6.]);
+    round_trip(vec![1i64, 2, 3, -4, 5, 6]);
+    round_trip(vec![true, false, true, true, false, false]);
+}
+
+#[test]
+fn ndarray_zero_copy() {
+    let nd = ndarray::Array::from_shape_vec((2, 3), vec![1f32, 2., 3., 4., 5., 6.]).unwrap();
+    let ptr = nd.as_ptr() as usize;
+    let tensor = Tensor::from_ndarray(nd);
+    assert_eq!(tensor.data_ptr() as usize, ptr);
+    let transposed = tensor.tr();
+    let nd: ndarray::ArrayD<f32> = (&transposed).try_into().unwrap();
+    assert_eq!(nd, ndarray::arr2(&[[1f32, 4.], [2., 5.], [3., 6.]]).into_dyn());
+}
+
+#[test]
+fn ndarray_non_contiguous() {
+    let nd = ndarray::arr2(&[[1i64, 2, 3], [4, 5, 6]]);
+    let tensor = Tensor::try_from(&nd.t()).unwrap();
+    assert_eq!(vec_i64_from(&tensor), [1, 4, 2, 5, 3, 6]);
+    let reversed = nd.slice_move(ndarray::s![.., ..;-1]);
+    assert_eq!(vec_i64_from(&Tensor::try_from(&reversed).unwrap()), [3, 2, 1, 6, 5, 4]);
+    assert_eq!(vec_i64_from(&Tensor::from_ndarray(reversed)), [3, 2, 1, 6, 5, 4]);
+    let empty = Tensor::from_ndarray(ndarray::Array2::<f64>::zeros((0, 3)));
+    assert_eq!(empty.size(), [0, 3]);
+    assert_eq!(ndarray::ArrayD::<f64>::try_from(&empty).unwrap().shape(), [0, 3]);
+}
+
+#[test]
+fn ndarray_kind_mismatch() {
+    let tensor = Tensor::from_slice(&[1f32, 2., 3.]);
+    assert!(ndarray::ArrayD::<f64>::try_from(&tensor).is_err());
+    assert!(ndarray::ArrayD::<i64>::try_from(&tensor).is_err());
+}
+
 #[test]
 fn from_primitive() -> Result<()> {
     assert_eq!(vec_i32_from(&Tensor::try_from(1_i32)?), vec![1]);
@@ -489,3 +702,133 @@ fn convert_ndarray() {
     let array_3d: ndarray::ArrayD<i64> = t_3d.as_ref().try_into().unwrap();
     assert_eq!(array_3d.as_slice(), ndarray::array![[[0, 1], [2, 3]], [[4, 5], [6, 7]]].as_slice());
 }
+
+#[test]
+fn inference_mode() {
+    let w = Tensor::ones([3], (tch::Kind::Float, Device::Cpu)).set_requires_grad(true);
+    assert!(!tch::InferenceModeGuard::is_enabled());
+    let ys = tch::inference_mode(|| {
+        assert!(tch::InferenceModeGuard::is_enabled());
+        // Operations on tensors requiring grad are not recorded.
+        let ys = &w * 2.;
+        assert!(!ys.requires_grad());
+        assert!(ys.is_inference());
+        {
+            let _guard = tch::InferenceModeGuard::new(false);
+            assert!(!tch::InferenceModeGuard::is_enabled());
+        }
+        assert!(tch::InferenceModeGuard::is_enabled());
+        ys
+    });
+    assert!(!tch::InferenceModeGuard::is_enabled());
+    assert_eq!(vec_f32_from(&ys), [2., 2., 2.]);
+
+    // Inference tensors cannot be mutated or used in autograd outside of the scope.
+    let mut leaked = ys.shallow_clone();
+    let err = leaked.f_add_(&Tensor::ones([3], (tch::Kind::Float, Device::Cpu)));
+    assert!(matches!(err, Err(TchError::InferenceTensor(_))), "{err:?}");
+    let err = w.f_mul(&ys);
+    assert!(matches!(err, Err(TchError::InferenceTensor(_))), "{err:?}");
+
+    // A copy made outside of inference mode is a normal tensor.
+    let ys = ys.copy();
+    assert!(!ys.is_inference());
+    let loss = w.f_mul(&ys).unwrap().sum(tch::Kind::Float);
+    loss.backward();
+    assert_eq!(vec_f32_from(&w.grad()), [2., 2., 2.]);
+}
+
+#[test]
+fn op_failed_context() {
+    let lhs = Tensor::zeros([32, 512], (tch::Kind::Float, Device::Cpu)).set_requires_grad(true);
+    let rhs = Tensor::zeros([256, 10], (tch::Kind::Double, Device::Cpu));
+    let err = lhs.f_matmul(&rhs).unwrap_err();
+    match &err {
+        TchError::OpFailed { op, inputs, .. } => {
+            assert_eq!(*op, "matmul");
+            assert_eq!(inputs.len(), 2);
+            assert_eq!(inputs[0].name, "self");
+            assert_eq!(inputs[0].size, [32, 512]);
+            assert!(inputs[0].requires_grad);
+            assert_eq!(inputs[1].kind, tch::Kind::Double);
+        }
+        _ => panic!("unexpected error {err:?}"),
+    }
+    let err = err.to_string();
+    assert!(err.starts_with("matmul failed: "), "{err}");
+    assert!(
+        err.ends_with("self=[32, 512] f32 cpu requires_grad, other=[256, 10] f64 cpu"),
+        "{err}"
+    );
+
+    // Undefined optional arguments are skipped.
+    let xs = Tensor::zeros([2, 3], (tch::Kind::Float, Device::Cpu));
+    let ws = Tensor::zeros([4, 5], (tch::Kind::Float, Device::Cpu));
+    let err = xs.f_linear(&ws, None::<Tensor>).unwrap_err().to_string();
+    assert!(err.ends_with("self=[2, 3] f32 cpu, weight=[4, 5] f32 cpu"), "{err}");
+}
+
+#[test]
+fn grad_mode_nesting() {
+    assert!(tch::is_grad_enabled());
+    let x = Tensor::from_slice(&[1f32, 2.]).set_requires_grad(true);
+    let ys = tch::no_grad(|| {
+        assert!(!tch::is_grad_enabled());
+        let ys = tch::with_grad(|| {
+            assert!(tch::is_grad_enabled());
+            let ys = tch::no_grad(|| {
+                assert!(!tch::is_grad_enabled());
+                &x * 2.
+            });
+            assert!(!ys.requires_grad());
+            assert!(tch::is_grad_enabled());
+            (&x * 3., ys)
+        });
+        assert!(!tch::is_grad_enabled());
+        ys
+    });
+    assert!(tch::is_grad_enabled());
+    assert!(ys.0.requires_grad());
+
+    {
+        let _guard = tch::GradGuard::new(false);
+        assert!(!tch::is_grad_enabled());
+        {
+            let _guard = tch::GradGuard::new(true);
+            assert!(tch::is_grad_enabled());
+            let _guard = tch::GradGuard::new(false);
+            assert!(!tch::is_grad_enabled());
+        }
+        assert!(!tch::is_grad_enabled());
+    }
+    assert!(tch::is_grad_enabled());
+}
+
+#[test]
+fn grad_mode_restored_after_panic() {
+    let result = std::panic::catch_unwind(|| tch::no_grad(|| panic!("inside no_grad")));
+    assert!(result.is_err());
+    assert!(tch::is_grad_enabled());
+    let result = std::panic::catch_unwind(|| {
+        let _guard = tch::GradGuard::new(false);
+        tch::with_grad(|| {
+            assert!(tch::is_grad_enabled());
+            panic!("inside with_grad")
+        })
+    });
+    assert!(result.is_err());
+    assert!(tch::is_grad_enabled());
+}
+
+#[test]
+fn deferred_free() {
+    let xs = Tensor::from_slice(&[1f32, 2., 3.]);
+    let ys = xs.shallow_clone();
+    xs.defer_drop();
+    assert_eq!(tch::cuda::DeferredFree::len(), 1);
+    // The bin is per-thread.
+    std::thread::spawn(|| assert_eq!(tch::cuda::DeferredFree::len(), 0)).join().unwrap();
+    assert_eq!(tch::cuda::flush_deferred_frees(), 1);
+    assert_eq!(tch::cuda::DeferredFree::len(), 0);
+    assert_eq!(vec_f32_from(&ys), [1., 2., 3.]);
+}
@@ -0,0 +1,22 @@
+// The thread pools are global and the inter-op thread count can only be set
+// once, so these checks are kept in a separate test binary and run from a
+// single test.
+use tch::{Device, Kind, Tensor};
+
+#[test]
+fn num_threads() {
+    tch::set_num_interop_threads(2);
+    assert_eq!(tch::get_num_interop_threads(), 2);
+    // The inter-op thread count cannot be changed once set.
+    assert!(tch::f_set_num_interop_threads(4).is_err());
+    assert_eq!(tch::get_num_interop_threads(), 2);
+
+    tch::set_num_threads(1);
+    assert_eq!(tch::get_num_threads(), 1);
+    let xs = Tensor::randn([64, 64], (Kind::Float, Device::Cpu));
+    let _ys = xs.matmul(&xs);
+    assert_eq!(tch::get_num_threads(), 1);
+    tch::set_num_threads(2);
+    assert_eq!(tch::get_num_threads(), 2);
+    assert!(tch::f_set_num_threads(0).is_err());
+}
@@ -0,0 +1,196 @@
+use std::cell::Cell;
+use std::sync::Mutex;
+use tch::nn::{self, OptimizerConfig};
+use tch::train::{self, Callback, Checkpoint, EarlyStopping, LrScheduler, Mode, Trainer};
+use tch::{data::Iter2, Device, Kind, Tensor};
+
+// The Ctrl-C handling uses a global flag, the trainers are run one at a time.
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("tch-train-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn dataset() -> (Tensor, Tensor) {
+    tch::manual_seed(0);
+    let xs = Tensor::randn([64, 4], (Kind::Float, Device::Cpu));
+    let ys = xs.sum_dim_intlist(1, false, Kind::Float).gt(0.).to_kind(Kind::Int64);
+    (xs, ys)
+}
+
+fn model(vs: &nn::VarStore) -> nn::SequentialT {
+    nn::seq_t()
+        .add(nn::linear(vs.root() / "l1", 4, 16, Default::default()))
+        .add_fn_t(|xs, train| xs.relu().dropout(0.2, train))
+        .add(nn::linear(vs.root() / "l2", 16, 2, Default::default()))
+}
+
+fn train(
+    vs: &mut nn::VarStore,
+    net: &nn::SequentialT,
+    callbacks: Vec<Box<dyn Callback>>,
+    max_epochs: usize,
+    resume_from: Option<&std::path::Path>,
+) -> train::TrainState {
+    let (xs, ys) = dataset();
+    let opt = nn::Adam::default().build(vs, 1e-2).unwrap();
+    let mut trainer = Trainer::new(vs, opt, callbacks).max_epochs(max_epochs).seed(42);
+    if let Some(dir) = resume_from {
+        trainer.resume(dir).unwrap();
+    }
+    trainer
+        .fit_module(
+            net,
+            || {
+                let mut iter = Iter2::new(&xs, &ys, 16);
+                iter.shuffle();
+                iter
+            },
+            |logits, ys| logits.cross_entropy_for_logits(ys),
+        )
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn resume_identical_continuation() {
+    let _lock = LOCK.lock().unwrap();
+    let dir = temp_dir("resume");
+
+    tch::manual_seed(1);
+    let mut vs1 = nn::VarStore::new(Device::Cpu);
+    let net1 = model(&vs1);
+    let state1 = train(&mut vs1, &net1, vec![], 4, None);
+    assert_eq!(state1.epoch, 4);
+
+    // Same initialization, stopped after two epochs.
+    tch::manual_seed(1);
+    let mut vs2 = nn::VarStore::new(Device::Cpu);
+    let net2 = model(&vs2);
+    let state2 = train(&mut vs2, &net2, vec![Box::new(Checkpoint::new(&dir))], 2, None);
+    assert_eq!(state2.epoch, 2);
+
+    // A different initialization, the checkpoint restores everything.
+    tch::manual_seed(2);
+    let mut vs3 = nn::VarStore::new(Device::Cpu);
+    let net3 = model(&vs3);
+    let state3 = train(&mut vs3, &net3, vec![], 4, Some(&dir));
+    assert_eq!(state3.epoch, 4);
+    assert_eq!(state3.history, state1.history);
+    let vars1 = vs1.variables();
+    for (name, var3) in vs3.variables() {
+        assert!(var3.equal(&vars1[&name]), "{name}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn early_stopping() {
+    let _lock = LOCK.lock().unwrap();
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let net = model(&vs);
+    let opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+    let losses = [1.0, 0.5, 0.6, 0.7, 0.4, 0.3];
+    let epoch = Cell::new(0);
+    let callbacks: Vec<Box<dyn Callback>> = vec![Box::new(EarlyStopping::new("val", Mode::Min, 2))];
+    let mut trainer = Trainer::new(&mut vs, opt, callbacks).max_epochs(6).validation(|| {
+        let val = losses[epoch.get()];
+        epoch.set(epoch.get() + 1);
+        Ok(vec![("val".to_string(), val)])
+    });
+    let (xs, ys) = dataset();
+    let state = trainer
+        .fit_module(&net, || Iter2::new(&xs, &ys, 32), |l, ys| l.cross_entropy_for_logits(ys))
+        .unwrap();
+    // No improvement after the second epoch for two epochs.
+    assert_eq!(state.epoch, 4);
+    assert_eq!(state.metric("val").unwrap(), 0.7);
+    assert!(state.metric("train_loss").is_ok());
+    assert!(state.metric("unknown").is_err());
+
+    let mut early_stopping = EarlyStopping::new("train_loss", Mode::Min, 1);
+    assert!(early_stopping.restore(state).is_ok());
+    let mut early_stopping = EarlyStopping::new("unknown", Mode::Min, 1);
+    assert!(early_stopping.restore(state).is_err());
+}
+
+#[test]
+fn lr_scheduler() {
+    let _lock = LOCK.lock().unwrap();
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let net = model(&vs);
+    let before: Vec<_> = vs.trainable_variables().iter().map(|v| v.copy()).collect();
+    let opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+    let callbacks: Vec<Box<dyn Callback>> = vec![Box::new(LrScheduler::step(0., 1, 0.5))];
+    let mut trainer = Trainer::new(&mut vs, opt, callbacks).max_epochs(2);
+    let (xs, ys) = dataset();
+    trainer
+        .fit_module(&net, || Iter2::new(&xs, &ys, 32), |l, ys| l.cross_entropy_for_logits(ys))
+        .unwrap();
+    drop(trainer);
+    // A learning rate of zero leaves the variables unchanged.
+    for (v1, v2) in before.iter().zip(vs.trainable_variables().iter()) {
+        assert!(v1.equal(v2))
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_saves_checkpoint() {
+    let _lock = LOCK.lock().unwrap();
+    let dir = temp_dir("ctrl-c");
+    let mut vs = nn::VarStore::new(Device::Cpu);
+    let net = model(&vs);
+    let opt = nn::Sgd::default().build(&vs, 1e-2).unwrap();
+    let callbacks: Vec<Box<dyn Callback>> = vec![Box::new(Checkpoint::new(&dir).every(10))];
+    let mut trainer = Trainer::new(&mut vs, opt, callbacks).max_epochs(5);
+    let (xs, ys) = dataset();
+    let batches = Cell::new(0);
+    let state = trainer
+        .fit_module(
+            &net,
+            || Iter2::new(&xs, &ys, 16),
+            |l, ys| {
+                batches.set(batches.get() + 1);
+                // Interrupt during the second epoch.
+                if batches.get() == 6 {
+                    unsafe { libc::raise(libc::SIGINT) };
+                }
+                l.cross_entropy_for_logits(ys)
+            },
+        )
+        .unwrap();
+    assert!(state.interrupted);
+    assert_eq!(state.epoch, 1);
+    let mut vs2 = nn::VarStore::new(Device::Cpu);
+    let _net2 = model(&vs2);
+    let mut opt2 = nn::Sgd::default().build(&vs2, 1e-2).unwrap();
+    let saved = train::load_checkpoint(&dir, &mut vs2, &mut opt2).unwrap();
+    assert_eq!(saved.epoch, 1);
+    // The files are written atomically, no temporary files are left behind.
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["model.ot", "optimizer.pt", "state.txt"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tensorboard_writer() {
+    let dir = temp_dir("tensorboard");
+    let mut writer = train::TensorboardWriter::new(&dir).unwrap();
+    writer.add_scalar("loss", 0.5, 1).unwrap();
+    writer.flush().unwrap();
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let data = std::fs::read(files[0].as_ref().unwrap().path()).unwrap();
+    // Two records, each with a 16 bytes header and footer.
+    let len = u64::from_le_bytes(data[..8].try_into().unwrap()) as usize;
+    let len2 = u64::from_le_bytes(data[len + 16..len + 24].try_into().unwrap()) as usize;
+    assert_eq!(data.len(), len + len2 + 32);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,124 @@
+# This generates the state dicts of a tiny transformer encoder and decoder layer along with sample
+# inputs and outputs, used to check that the `nn::transformer_*` layers match PyTorch and that
+# PyTorch weights load into a `VarStore`.
+#
+# - encoder.safetensors: a `torch.nn.TransformerEncoder` with two post-LN relu layers.
+# - decoder.safetensors: a pre-LN gelu `torch.nn.TransformerDecoderLayer` using batch_first.
+# - io.safetensors: the inputs and the outputs of both, see `tests/nn_tests.rs` for the masks.
+#
+# The weights are drawn from python's `random` module so that the files are reproducible, the
+# outputs are computed by torch in eval mode.
+
+import json
+import random
+import struct
+
+import torch
+
+D_MODEL, NHEAD, DIM_FF, NUM_LAYERS = 4, 2, 6, 2
+SRC_LEN, TGT_LEN, BATCH = 3, 3, 2
+# True marks the padded source positions, used as `src_key_padding_mask` and
+# `memory_key_padding_mask`.
+PADDING = [[False, False, True], [False, False, False]]
+
+rng = random.Random(0)
+
+
+def f32(x):
+    return struct.unpack("<f", struct.pack("<f", x))[0]
+
+
+def rand(shape, lo=-0.5, up=0.5):
+    if len(shape) == 1:
+        return [f32(rng.uniform(lo, up)) for _ in range(shape[0])]
+    return [rand(shape[1:], lo, up) for _ in range(shape[0])]
+
+
+def attention_weights(prefix):
+    return {
+        prefix + "in_proj_weight": rand([3 * D_MODEL, D_MODEL]),
+        prefix + "in_proj_bias": rand([3 * D_MODEL]),
+        prefix + "out_proj.weight": rand([D_MODEL, D_MODEL]),
+        prefix + "out_proj.bias": rand([D_MODEL]),
+    }
+
+
+def layer_weights(prefix, num_norms, attentions):
+    ws = {}
+    for attn in attentions:
+        ws.update(attention_weights(prefix + attn + "."))
+    ws[prefix + "linear1.weight"] = rand([DIM_FF, D_MODEL])
+    ws[prefix + "linear1.bias"] = rand([DIM_FF])
+    ws[prefix + "linear2.weight"] = rand([D_MODEL, DIM_FF])
+    ws[prefix + "linear2.bias"] = rand([D_MODEL])
+    for i in range(1, num_norms + 1):
+        ws[prefix + f"norm{i}.weight"] = rand([D_MODEL], 0.5, 1.5)
+        ws[prefix + f"norm{i}.bias"] = rand([D_MODEL])
+    return ws
+
+
+encoder_ws = {}
+for layer in range(NUM_LAYERS):
+    encoder_ws.update(layer_weights(f"layers.{layer}.", 2, ["self_attn"]))
+decoder_ws = layer_weights("", 3, ["self_attn", "multihead_attn"])
+src = rand([SRC_LEN, BATCH, D_MODEL], -1.0, 1.0)
+tgt = rand([BATCH, TGT_LEN, D_MODEL], -1.0, 1.0)
+
+
+def with_torch():
+    def load(module, ws):
+        module.load_state_dict({k: torch.tensor(v) for k, v in ws.items()})
+        return module.eval()
+
+    layer = torch.nn.TransformerEncoderLayer(D_MODEL, NHEAD, DIM_FF, dropout=0.0)
+    encoder = torch.nn.TransformerEncoder(layer, NUM_LAYERS, enable_nested_tensor=False)
+    encoder = load(encoder, encoder_ws)
+    decoder = torch.nn.TransformerDecoderLayer(
+        D_MODEL, NHEAD, DIM_FF, dropout=0.0, activation="gelu", batch_first=True, norm_first=True
+    )
+    decoder = load(decoder, decoder_ws)
+    padding = torch.tensor(PADDING)
+    causal = torch.ones(TGT_LEN, TGT_LEN, dtype=torch.bool).triu(1)
+    with torch.no_grad():
+        encoder_out = encoder(torch.tensor(src), src_key_padding_mask=padding)
+        decoder_out = decoder(
+            torch.tensor(tgt),
+            encoder_out.transpose(0, 1),
+            tgt_mask=causal,
+            memory_key_padding_mask=padding,
+        )
+    return encoder_out.tolist(), decoder_out.tolist()
+
+
+def flatten(xs):
+    return [v for x in xs for v in flatten(x)] if isinstance(xs, list) else [xs]
+
+
+def shape(xs):
+    return [len(xs)] + shape(xs[0]) if isinstance(xs, list) else []
+
+
+def save_safetensors(path, tensors):
+    header, data = {}, b""
+    for name, value in tensors.items():
+        values = flatten(value)
+        header[name] = {
+            "dtype": "F32",
+            "shape": shape(value),
+            "data_offsets": [len(data), len(data) + 4 * len(values)],
+        }
+        data += struct.pack(f"<{len(values)}f", *values)
+    header = json.dumps(header, separators=(",", ":")).encode()
+    header += b" " * (-len(header) % 8)
+    with open(path, "wb") as f:
+        f.write(struct.pack("<Q", len(header)) + header + data)
+
+
+encoder_out, decoder_out = with_torch()
+
+save_safetensors("encoder.safetensors", encoder_ws)
+save_safetensors("decoder.safetensors", decoder_ws)
+save_safetensors(
+    "io.safetensors",
+    {"src": src, "tgt": tgt, "encoder_out": encoder_out, "decoder_out": decoder_out},
+)
@@ -0,0 +1,8 @@
+use tch::typed::{TypedTensor, F32, I64};
+use tch::Device;
+
+fn main() {
+    let xs = TypedTensor::<F32, 2>::zeros([2, 3], Device::Cpu);
+    let ws = TypedTensor::<I64, 2>::zeros([3, 4], Device::Cpu);
+    let _ys = xs.matmul(&ws);
+}
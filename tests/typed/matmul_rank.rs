@@ -0,0 +1,8 @@
+use tch::typed::{TypedTensor, F32};
+use tch::Device;
+
+fn main() {
+    let xs = TypedTensor::<F32, 2>::zeros([2, 3], Device::Cpu);
+    let ws = TypedTensor::<F32, 2>::zeros([3, 4], Device::Cpu);
+    let _ys: TypedTensor<F32, 1> = xs.matmul(&ws);
+}
@@ -0,0 +1,7 @@
+use tch::typed::{TypedTensor, F32};
+use tch::Device;
+
+fn main() {
+    let xs = TypedTensor::<F32, 2>::zeros([2, 6], Device::Cpu);
+    let _ys: TypedTensor<F32, 2> = xs.reshape([2, 3, 2]);
+}
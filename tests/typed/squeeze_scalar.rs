@@ -0,0 +1,7 @@
+use tch::typed::{TypedTensor, F32};
+use tch::Device;
+
+fn main() {
+    let xs = TypedTensor::<F32, 0>::zeros([], Device::Cpu);
+    let _ys = xs.squeeze::<0>();
+}
@@ -0,0 +1,7 @@
+use tch::typed::{TypedTensor, F32};
+use tch::Device;
+
+fn main() {
+    let xs = TypedTensor::<F32, 2>::zeros([2, 3], Device::Cpu);
+    let _v = xs.value([1]);
+}
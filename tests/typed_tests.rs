@@ -0,0 +1,54 @@
+use tch::typed::{TypedTensor, F32, I64};
+use tch::{Device, Kind, TchError, Tensor};
+
+#[test]
+fn typed_ops() {
+    let xs = TypedTensor::<F32, 2>::zeros([2, 3], Device::Cpu);
+    let ws =
+        TypedTensor::<F32, 2>::try_from(Tensor::ones([3, 4], (Kind::Float, Device::Cpu))).unwrap();
+    let ys: TypedTensor<F32, 2> = (xs.as_tensor() + 1.).try_into().unwrap();
+    let zs = ys.matmul(&ws);
+    assert_eq!(zs.size(), [2, 4]);
+    assert_eq!(zs.value([1, 3]), 3.);
+    let vs = TypedTensor::<F32, 1>::try_from(Tensor::from_slice(&[1f32, 2., 3.])).unwrap();
+    let mv: TypedTensor<F32, 1> = ys.matmul(&vs);
+    assert_eq!(mv.size(), [2]);
+    let dot: TypedTensor<F32, 0> = vs.matmul(&vs);
+    assert_eq!(dot.value([]), 14.);
+    assert_eq!(ys.tr().size(), [3, 2]);
+
+    let us = zs.unsqueeze::<1>();
+    assert_eq!(us.size(), [2, 1, 4]);
+    assert_eq!(us.squeeze::<1>().size(), [2, 4]);
+    assert!(matches!(us.f_squeeze::<0>(), Err(TchError::Shape(_))));
+    assert_eq!(us.reshape([8]).size(), [8]);
+    assert!(us.f_reshape([3, 3]).is_err());
+    assert_eq!(zs.get(1).size(), [4]);
+    assert!(zs.f_get(2).is_err());
+    assert!(zs.f_matmul(&ws).is_err());
+
+    // The untyped operations are available through Deref.
+    let relu = zs.relu();
+    assert_eq!(relu.size(), [2, 4]);
+    let ints: TypedTensor<I64, 2> = zs.to_kind();
+    assert_eq!(ints.value([0, 0]), 3);
+    assert_eq!(Tensor::from(ints).kind(), Kind::Int64);
+    assert_eq!(zs.into_dyn().size(), [2, 4]);
+}
+
+#[test]
+fn typed_try_from() {
+    let xs = Tensor::zeros([2, 3], (Kind::Float, Device::Cpu));
+    let err = TypedTensor::<F32, 3>::try_from(xs.shallow_clone());
+    assert!(matches!(err, Err(TchError::Shape(_))));
+    let err = TypedTensor::<I64, 2>::try_from(xs.shallow_clone());
+    assert!(matches!(err, Err(TchError::Kind(_))));
+    assert!(TypedTensor::<F32, 2>::try_from(xs).is_ok());
+    assert!(TypedTensor::<F32, 2>::try_from(Tensor::new()).is_err());
+}
+
+#[test]
+fn typed_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/typed/*.rs");
+}
@@ -1,6 +1,8 @@
 use std::fs;
 use tch::nn::OptimizerConfig;
-use tch::{nn, nn::linear, nn::Init, nn::VarStore, Device, Kind, TchError, Tensor};
+use tch::{
+    nn, nn::linear, nn::Init, nn::LoadOptions, nn::VarStore, Device, Kind, TchError, Tensor,
+};
 
 mod test_utils;
 use test_utils::*;
@@ -179,11 +181,11 @@ fn save_and_load_partial_var_store() {
     assert_eq!(f64_from(&u2.mean(Kind::Float)), 0.0);
     assert_eq!(f64_from(&v2.mean(Kind::Float)), 1.0);
     vs1.save(&filename).unwrap();
-    let missing_variables = vs2.load_partial(&filename).unwrap();
+    let report = vs2.load_partial(&filename).unwrap();
     assert_eq!(f64_from(&u1.mean(Kind::Float)), 42.0);
     assert_eq!(f64_from(&u2.mean(Kind::Float)), 42.0);
     assert_eq!(f64_from(&v2.mean(Kind::Float)), 2.0);
-    assert!(missing_variables.is_empty());
+    assert!(report.is_exact());
     fs::remove_file(&filename).unwrap();
 
     // Save and reload in half-precision
@@ -191,12 +193,12 @@ fn save_and_load_partial_var_store() {
     let mut vs2 = VarStore::new(Device::Cpu);
     let (u2, v2) = add(&vs2.root());
     vs1.save(&filename).unwrap();
-    let missing_variables = vs2.load_partial(&filename).unwrap();
+    let report = vs2.load_partial(&filename).unwrap();
     assert_eq!(u2.kind(), Kind::Half);
     assert_eq!(v2.kind(), Kind::Half);
     assert_eq!(f64_from(&u2.mean(Kind::Half)), 42.0);
     assert_eq!(f64_from(&v2.mean(Kind::Half)), 2.0);
-    assert!(missing_variables.is_empty());
+    assert!(report.is_exact());
     fs::remove_file(filename).unwrap();
 }
 
@@ -260,11 +262,11 @@ fn save_and_load_partial_var_store_incomplete_file() {
     assert_eq!(f64_from(&u2.mean(Kind::Float)), 0.0);
     assert_eq!(f64_from(&v2.mean(Kind::Float)), 1.0);
     vs1.save(&filename).unwrap();
-    let missing_variables = vs2.load_partial(&filename).unwrap();
+    let report = vs2.load_partial(&filename).unwrap();
     assert_eq!(f64_from(&u1.mean(Kind::Float)), 42.0);
     assert_eq!(f64_from(&u2.mean(Kind::Float)), 42.0);
     assert_eq!(f64_from(&v2.mean(Kind::Float)), 1.0);
-    assert_eq!(missing_variables, vec!(String::from("a.b.t2")));
+    assert_eq!(report.missing, vec!(String::from("a.b.t2")));
     fs::remove_file(&filename).unwrap();
 
     // Save and reload in half-precision
@@ -272,12 +274,126 @@ fn save_and_load_partial_var_store_incomplete_file() {
     let mut vs2 = VarStore::new(Device::Cpu);
     let (u2, v2) = add_partial(&vs2.root());
     vs1.save(&filename).unwrap();
-    let missing_variables = vs2.load_partial(&filename).unwrap();
+    let report = vs2.load_partial(&filename).unwrap();
     assert_eq!(u2.kind(), Kind::Half);
     assert_eq!(v2.kind(), Kind::Float);
     assert_eq!(f64_from(&u2.mean(Kind::Half)), 42.0);
     assert_eq!(f64_from(&v2.mean(Kind::Float)), 1.0);
-    assert_eq!(missing_variables, vec!(String::from("a.b.t2")));
+    assert_eq!(report.missing, vec!(String::from("a.b.t2")));
+    fs::remove_file(&filename).unwrap();
+}
+
+#[test]
+fn load_partial_report() {
+    let filename =
+        std::env::temp_dir().join(format!("tch-vs-partial-load-report-{}", std::process::id()));
+    let vs1 = VarStore::new(Device::Cpu);
+    let _ = vs1.root().ones("backbone", &[4]);
+    let _ = vs1.root().sub("fc").ones("weight", &[10, 4]);
+    let _ = vs1.root().sub("fc").ones("bias", &[10]);
+    vs1.save(&filename).unwrap();
+    // Drop one entry from the file and add another one.
+    let mut named_tensors = Tensor::load_multi(&filename).unwrap();
+    named_tensors.retain(|(name, _)| name != "fc.bias");
+    named_tensors.push(("extra".to_string(), Tensor::ones([2], tch::kind::FLOAT_CPU)));
+    Tensor::save_multi(&named_tensors, &filename).unwrap();
+
+    let mut vs2 = VarStore::new(Device::Cpu);
+    let backbone = vs2.root().zeros("backbone", &[4]);
+    let weight = vs2.root().sub("fc").zeros("weight", &[3, 4]);
+    let _ = vs2.root().sub("fc").zeros("bias", &[3]);
+    let report = vs2.load_partial(&filename).unwrap();
+    assert_eq!(report.missing, ["fc.bias"]);
+    assert_eq!(report.unexpected, ["extra"]);
+    assert_eq!(report.shape_mismatch, ["fc.weight"]);
+    assert!(!report.is_exact());
+    assert_eq!(vec_f64_from(&backbone), [1., 1., 1., 1.]);
+    assert_eq!(f64_from(&weight.sum(Kind::Float)), 0.);
+
+    // Strict mode fails on the missing variable.
+    let strict = LoadOptions { strict: true };
+    assert!(vs2.load_with_options(&filename, strict).is_err());
+    assert!(vs2.load(&filename).is_err());
+    fs::remove_file(&filename).unwrap();
+}
+
+#[test]
+fn load_with_rename() {
+    let mlp = |p: nn::Path| {
+        let _ = linear(&p / "fc1", 4, 8, Default::default());
+        let _ = linear(&p / "fc2", 8, 2, Default::default());
+    };
+    for ext in ["ot", "safetensors"] {
+        let filename =
+            std::env::temp_dir().join(format!("tch-vs-load-rename-{}.{ext}", std::process::id()));
+        let vs1 = VarStore::new(Device::Cpu);
+        mlp(vs1.root() / "model" / "encoder");
+        let _ = vs1.root().sub("model").zeros("step", &[1]);
+        vs1.save(&filename).unwrap();
+
+        let mut vs2 = VarStore::new(Device::Cpu);
+        mlp(vs2.root() / "encoder");
+        let rename = |key: &str| key.strip_prefix("model.").map(|key| key.to_string());
+        // The step counter is renamed to a name unknown to the var-store.
+        let report = vs2.load_with_rename(&filename, rename).unwrap();
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unexpected, ["model.step"]);
+        let bytes = |t: &Tensor| Vec::<u8>::try_from(t.view_dtype(Kind::Uint8).reshape([-1]));
+        let vars1 = vs1.variables();
+        for (name, var) in vs2.variables() {
+            let var1 = &vars1[&format!("model.{name}")];
+            assert_eq!(bytes(&var).unwrap(), bytes(var1).unwrap());
+        }
+
+        // Dropping entries fails in strict mode and reports them otherwise.
+        let drop_fc2 = |key: &str| match key.strip_prefix("model.") {
+            Some(key) if !key.starts_with("encoder.fc2") => Some(key.to_string()),
+            _ => None,
+        };
+        assert!(vs2.load_with_rename(&filename, drop_fc2).is_err());
+        let options = LoadOptions { strict: false };
+        let report = vs2.load_with_rename_and_options(&filename, drop_fc2, options).unwrap();
+        assert_eq!(report.missing, ["encoder.fc2.bias", "encoder.fc2.weight"]);
+        assert!(report.unexpected.is_empty());
+        let collide = |_: &str| Some("encoder.fc1.bias".to_string());
+        assert!(vs2.load_with_rename(&filename, collide).is_err());
+        fs::remove_file(&filename).unwrap();
+    }
+}
+
+#[test]
+fn save_and_load_safetensors_kinds() {
+    let filename = std::env::temp_dir()
+        .join(format!("tch-vs-safetensors-kinds-{}.safetensors", std::process::id()));
+    let add = |vs: &VarStore| {
+        let root = vs.root();
+        let _ = root.sub("f32").randn_standard("w", &[3, 4]);
+        let _ = root.sub("f16").randn_standard("w", &[5]);
+        let _ = root.sub("bf16").randn_standard("w", &[2, 2]);
+        let _ = root.sub("i64").zeros_no_train("ids", &[6]);
+    };
+    let vs1 = VarStore::new(Device::Cpu);
+    add(&vs1);
+    vs1.root().sub("f16").half();
+    vs1.root().sub("bf16").bfloat16();
+    vs1.root().sub("i64").set_kind(Kind::Int64);
+    tch::no_grad(|| {
+        let mut ids = vs1.root().sub("i64").get("ids").unwrap();
+        ids.copy_(&Tensor::arange(6, tch::kind::INT64_CPU).pow_tensor_scalar(11));
+    });
+    vs1.save(&filename).unwrap();
+
+    // The variables take the kinds of the file.
+    let mut vs2 = VarStore::new(Device::Cpu);
+    add(&vs2);
+    vs2.load(&filename).unwrap();
+    let vars2 = vs2.variables();
+    for (name, var1) in vs1.variables() {
+        let var2 = &vars2[&name];
+        assert_eq!(var2.kind(), var1.kind(), "{name}");
+        assert!(var2.equal(&var1), "{name}");
+    }
+    assert_eq!(vars2["i64.ids"].int64_value(&[5]), 48828125);
     fs::remove_file(&filename).unwrap();
 }
 
@@ -328,6 +444,65 @@ fn init_test() {
     assert!(f64::abs(f64_from(&kaiming_n.std(true)) - (0.02f64).sqrt()) < 3e-3);
 }
 
+// Checks the mean and standard deviation of an initialized tensor, with a
+// relative tolerance on the standard deviation.
+fn check_stats(t: &Tensor, mean: f64, std: f64) {
+    let (t_mean, t_std) = (f64_from(&t.mean(Kind::Float)), f64_from(&t.std(true)));
+    assert!(f64::abs(t_mean - mean) < 0.05 * std, "mean {t_mean}, expected {mean}");
+    assert!(f64::abs(t_std - std) < 0.02 * std, "std {t_std}, expected {std}");
+}
+
+#[test]
+fn init_functions() {
+    use nn::init::{FanInOut, NonLinearity};
+    tch::manual_seed(42);
+    let vs = VarStore::new(Device::Cpu);
+    // The default initialization of the PyTorch conv layers, the fan-in of a
+    // conv kernel includes the kernel size.
+    let mut ws = vs.root().zeros("conv_u", &[256, 64, 3, 3]);
+    nn::init::kaiming_uniform_(&mut ws, FanInOut::FanIn, NonLinearity::LeakyReLU(5f64.sqrt()));
+    let std = (2. / 6f64).sqrt() / (64. * 9f64).sqrt();
+    check_stats(&ws, 0., std);
+    assert!(f64_from(&ws.abs().max()) <= 3f64.sqrt() * std);
+    let mut ws = vs.root().zeros("conv_n", &[64, 128, 3, 3]);
+    nn::init::kaiming_normal_(&mut ws, FanInOut::FanOut, NonLinearity::ReLU);
+    check_stats(&ws, 0., (2f64 / (64. * 9.)).sqrt());
+
+    let mut ws = Tensor::zeros([300, 200], (Kind::Float, Device::Cpu));
+    nn::init::xavier_uniform_(&mut ws, 1.);
+    check_stats(&ws, 0., (2. / 500f64).sqrt());
+    assert!(f64_from(&ws.abs().max()) <= (6. / 500f64).sqrt());
+    let mut ws = Tensor::zeros([64, 32, 5, 5], (Kind::Float, Device::Cpu));
+    nn::init::xavier_normal_(&mut ws, 2.);
+    check_stats(&ws, 0., 2. * (2f64 / (32. * 25. + 64. * 25.)).sqrt());
+    let ws = vs.root().var(
+        "xavier",
+        &[300, 200],
+        Init::Xavier { dist: nn::init::NormalOrUniform::Normal, gain: 1. },
+    );
+    check_stats(&ws, 0., (2. / 500f64).sqrt());
+
+    // The columns of the flattened tensor are orthogonal with norm gain.
+    let mut ws = vs.root().zeros("ortho", &[50, 20, 2]);
+    nn::init::orthogonal_(&mut ws, 1.5);
+    let ws = ws.view([50, 40]);
+    let gram = ws.tr().matmul(&ws);
+    let expected = Tensor::eye(40, (Kind::Float, Device::Cpu)) * 2.25;
+    assert!(gram.allclose(&expected, 1e-4, 1e-4, false));
+
+    // The mean and standard deviation of a standard normal truncated to
+    // [-1, 0.5] after shifting by 0.5.
+    let mut ws = Tensor::zeros([200000], (Kind::Float, Device::Cpu));
+    nn::init::trunc_normal_(&mut ws, 0.5, 1., -0.5, 1.);
+    assert!(f64_from(&ws.min()) >= -0.5 && f64_from(&ws.max()) <= 1.);
+    check_stats(&ws, 0.29337, 0.41566);
+    let init = Init::TruncatedNormal { mean: 0., stdev: 0.02, lo: -0.04, up: 0.04 };
+    let ws = vs.root().var("trunc", &[100000], init);
+    assert!(f64_from(&ws.abs().max()) <= 0.04);
+    let init = Init::TruncatedNormal { mean: 0., stdev: 1., lo: 1., up: -1. };
+    assert!(nn::f_init(init, &[10], Device::Cpu).is_err());
+}
+
 fn check_param_group(mut opt: tch::nn::Optimizer, var_foo: Tensor, var_bar: Tensor) {
     opt.set_lr(0.1);
     opt.set_lr_group(0, 0.);
@@ -440,6 +615,46 @@ fn param_group_weight_decay() {
     assert_eq!(format!("{:.2}", f64_from(&var_bar)), "0.69");
 }
 
+#[test]
+fn param_group_configs() {
+    let vs = VarStore::new(Device::Cpu);
+    let mut opt = tch::nn::Sgd::default().build(&vs, 0.1).unwrap();
+    opt.set_weight_decay(0.5);
+    let root = vs.root();
+    let decayed = root.set_group(0).ones("decayed", &[2]);
+    let kept = root.set_group(1).ones("kept", &[2]);
+    let no_decay = nn::ParamGroupConfig { weight_decay: Some(0.), ..Default::default() };
+    opt.set_group_config(1, &no_decay);
+    let extra = Tensor::ones([2], tch::kind::FLOAT_CPU).set_requires_grad(true);
+    let config = nn::ParamGroupConfig { lr: Some(1.), ..Default::default() };
+    let group = opt.add_parameters_with_config(&[extra.shallow_clone()], &config).unwrap();
+    assert_eq!(group, 2);
+    assert_eq!(opt.num_groups(), 3);
+    assert_eq!(opt.lr_group(2), 1.);
+    assert!(opt.add_parameters_with_config(&[kept.shallow_clone()], &config).is_err());
+    // With zero gradients only the weight decay updates the parameters.
+    for _idx in 0..3 {
+        let loss = (decayed.sum(Kind::Float) + kept.sum(Kind::Float) + extra.sum(Kind::Float)) * 0.;
+        opt.backward_step(&loss);
+    }
+    assert!(decayed.allclose(&Tensor::from_slice(&[0.857375f32; 2]), 1e-5, 1e-5, false));
+    assert_eq!(vec_f64_from(&kept), [1., 1.]);
+    assert_eq!(vec_f64_from(&extra), [0.125, 0.125]);
+
+    let vs = VarStore::new(Device::Cpu);
+    let mut opt = tch::nn::Adam::default().build(&vs, 1e-3).unwrap();
+    let _ = vs.root().set_group(1).zeros("var", &[]);
+    let config = nn::ParamGroupConfig { momentum: Some(0.5), weight_decay: Some(0.1), lr: None };
+    opt.set_group_config(1, &config);
+    // Zero-element tensors have no data but are still distinct parameters.
+    let empty = || Tensor::zeros([0], tch::kind::FLOAT_CPU).set_requires_grad(true);
+    let (empty1, empty2) = (empty(), empty());
+    opt.add_parameters_with_config(&[empty1.shallow_clone()], &config).unwrap();
+    opt.add_parameters_with_config(&[empty2.shallow_clone()], &config).unwrap();
+    assert!(opt.add_parameters_with_config(&[empty1], &config).is_err());
+    assert!(opt.add_parameters_with_config(&[empty(), empty2], &config).is_err());
+}
+
 #[test]
 fn half_precision_conversion_entire_varstore() {
     let mut vs = VarStore::new(Device::Cpu);
@@ -631,3 +846,130 @@ fn merge_var_stores_with_prefixes() {
     assert!(merged_vs.variables().contains_key("vs_2.key_3"));
     assert!(merged_vs.variables().contains_key("vs_2.key_4"));
 }
+
+#[test]
+fn concurrent_saves() {
+    let dir = std::env::temp_dir().join(format!("tch-vs-concurrent-save-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let filename = dir.join("model.safetensors");
+    std::thread::scope(|s| {
+        for i in 0..4 {
+            let filename = &filename;
+            s.spawn(move || {
+                let vs = VarStore::new(Device::Cpu);
+                let _t = vs
+                    .root()
+                    .var_copy("t", &Tensor::full([16], i as f64, (Kind::Float, Device::Cpu)));
+                for _ in 0..8 {
+                    vs.save(filename).unwrap()
+                }
+            });
+        }
+    });
+    let mut vs = VarStore::new(Device::Cpu);
+    let t = vs.root().zeros("t", &[16]);
+    vs.load(&filename).unwrap();
+    let values = vec_f64_from(&t);
+    assert!(values.iter().all(|v| *v == values[0]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interrupted_save_keeps_checkpoint() {
+    let dir = std::env::temp_dir().join(format!("tch-vs-atomic-save-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let filename = dir.join("model.safetensors");
+    let tmp_filename = dir.join(".model.safetensors.1234.0.tmp");
+    let tmp_files = || {
+        fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".tmp"))
+            .count()
+    };
+    let vs = VarStore::new(Device::Cpu);
+    let mut t = vs.root().ones("t", &[3]);
+    vs.save_with_metadata(&filename, &[("epoch".to_string(), "3".to_string())].into()).unwrap();
+    assert_eq!(tmp_files(), 0);
+
+    // A save that fails when renaming the temporary file removes it.
+    tch::no_grad(|| t *= 2.0);
+    let dir_filename = dir.join("model-dir.safetensors");
+    fs::create_dir(&dir_filename).unwrap();
+    fs::write(dir_filename.join("file"), b"").unwrap();
+    assert!(vs.save(&dir_filename).is_err());
+    assert_eq!(tmp_files(), 0);
+    // A temporary file left behind by a save that crashed.
+    fs::write(&tmp_filename, b"partial").unwrap();
+
+    let mut vs2 = VarStore::new(Device::Cpu);
+    let t2 = vs2.root().zeros("t", &[3]);
+    vs2.load(&filename).unwrap();
+    assert_eq!(vec_f64_from(&t2), [1.0, 1.0, 1.0]);
+    let metadata = VarStore::read_metadata(&filename).unwrap();
+    assert_eq!(metadata["epoch"], "3");
+    assert_eq!(metadata["tch_version"], env!("CARGO_PKG_VERSION"));
+    assert!(metadata.contains_key("torch_version"));
+
+    vs.save(&filename).unwrap();
+    assert_eq!(tmp_files(), 1);
+    fs::remove_file(&tmp_filename).unwrap();
+    vs2.load(&filename).unwrap();
+    assert_eq!(vec_f64_from(&t2), [2.0, 2.0, 2.0]);
+    // Files without a header have no metadata.
+    assert!(VarStore::read_metadata(&filename).unwrap().is_empty());
+    let ot_filename = dir.join("model.ot");
+    vs.save(&ot_filename).unwrap();
+    assert!(VarStore::read_metadata(&ot_filename).unwrap().is_empty());
+    assert!(vs.save_with_metadata(&ot_filename, &Default::default()).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_versioned() {
+    let dir = std::env::temp_dir().join(format!("tch-vs-versioned-{}", std::process::id()));
+    let vs = VarStore::new(Device::Cpu);
+    let mut t = vs.root().zeros("t", &[2]);
+    assert_eq!(VarStore::latest_checkpoint(&dir).unwrap(), None);
+    for _ in 0..3 {
+        tch::no_grad(|| t += 1.0);
+        vs.save_versioned(&dir, 2).unwrap();
+    }
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["checkpoint-000002.safetensors", "checkpoint-000003.safetensors", "latest"]);
+    let latest = VarStore::latest_checkpoint(&dir).unwrap().unwrap();
+    assert_eq!(latest, dir.join("checkpoint-000003.safetensors"));
+    assert_eq!(VarStore::read_metadata(&latest).unwrap()["checkpoint"], "3");
+
+    let mut vs2 = VarStore::new(Device::Cpu);
+    let t2 = vs2.root().zeros("t", &[2]);
+    vs2.load(&latest).unwrap();
+    assert_eq!(vec_f64_from(&t2), [3.0, 3.0]);
+    fs::remove_file(dir.join("latest")).unwrap();
+    assert_eq!(VarStore::latest_checkpoint(&dir).unwrap(), Some(latest));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn freeze_prefix() {
+    let mut vs = VarStore::new(Device::Cpu);
+    let _ = tch::vision::resnet::resnet18(&vs.root(), 10);
+    let trainable = vs.trainable_variables_with_names();
+    assert_eq!(trainable.len(), vs.trainable_variables().len());
+    assert_eq!(vs.freeze_prefix(""), trainable.len());
+    assert_eq!(vs.unfreeze_prefix("fc"), 2);
+    let requires_grad: Vec<_> = vs
+        .trainable_variables_with_names()
+        .into_iter()
+        .filter(|(_, t)| t.requires_grad())
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(requires_grad, ["fc.bias", "fc.weight"]);
+    // Prefixes match whole path components.
+    assert_eq!(vs.freeze_prefix("f"), 0);
+    assert_eq!(vs.unfreeze_prefix("layer1.0"), 6);
+    assert_eq!(vs.unfreeze_prefix("layer1.0.conv1.weight"), 1);
+}
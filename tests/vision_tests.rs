@@ -9,6 +9,220 @@ fn mobilenet() {
     assert_eq!(logits.size(), [1, 1000]);
 }
 
+#[test]
+fn efficientnet() {
+    let vs = nn::VarStore::new(tch::Device::Cpu);
+    let net = vision::efficientnet::b0(&vs.root(), 1000);
+    let variables = vs.variables();
+    assert_eq!(variables["features.0.0.weight"].size(), [32, 3, 3, 3]);
+    assert_eq!(variables["features.1.0.block.1.fc1.weight"].size(), [8, 32, 1, 1]);
+    assert_eq!(variables["features.8.0.weight"].size(), [1280, 320, 1, 1]);
+    assert_eq!(variables["classifier.1.weight"].size(), [1000, 1280]);
+    let img = Tensor::randn([1, 3, 224, 224], tch::kind::FLOAT_CPU);
+    let logits = img.apply_t(&net, false);
+    assert_eq!(logits.size(), [1, 1000]);
+    // Dropout and stochastic depth are only active in train mode.
+    assert_eq!(img.apply_t(&net, false), logits);
+    let img = Tensor::randn([2, 3, 64, 64], tch::kind::FLOAT_CPU);
+    assert_eq!(img.apply_t(&net, true).size(), [2, 1000]);
+}
+
+#[test]
+fn vit() {
+    let vs = nn::VarStore::new(tch::Device::Cpu);
+    let net = vision::vit::vit_b_16(&vs.root(), 10);
+    let variables = vs.variables();
+    assert_eq!(variables["conv_proj.weight"].size(), [768, 3, 16, 16]);
+    assert_eq!(variables["encoder.pos_embedding"].size(), [1, 197, 768]);
+    let layer = "encoder.layers.encoder_layer_11";
+    assert_eq!(variables[&format!("{layer}.self_attention.in_proj_weight")].size(), [2304, 768]);
+    assert_eq!(variables[&format!("{layer}.mlp.3.weight")].size(), [768, 3072]);
+    assert_eq!(variables["heads.head.weight"].size(), [10, 768]);
+    let img = Tensor::randn([2, 3, 224, 224], tch::kind::FLOAT_CPU);
+    assert_eq!(img.apply_t(&net, false).size(), [2, 10]);
+    // Other image sizes use interpolated positional embeddings.
+    let img = Tensor::randn([1, 3, 384, 384], tch::kind::FLOAT_CPU);
+    assert_eq!(img.apply_t(&net, false).size(), [1, 10]);
+}
+
+#[test]
+fn vit_interpolate_pos_embedding() {
+    let pos_embedding = Tensor::randn([1, 197, 8], tch::kind::FLOAT_CPU);
+    let interpolated = vision::vit::interpolate_pos_embedding(&pos_embedding, 24, 24);
+    assert_eq!(interpolated.size(), [1, 577, 8]);
+    // The class token embedding is kept and, as the corners are aligned, so
+    // are the embeddings of the corner patches.
+    for (src, dst) in [(0, 0), (1, 1), (14, 24), (183, 553), (196, 576)] {
+        let (src, dst) = (pos_embedding.get(0).get(src), interpolated.get(0).get(dst));
+        assert!(src.allclose(&dst, 1e-5, 1e-5, false));
+    }
+    let same = vision::vit::interpolate_pos_embedding(&pos_embedding, 14, 14);
+    assert_eq!(same, pos_embedding);
+}
+
+#[test]
+fn cifar_sample_counts() {
+    // The files hold fewer samples than the actual datasets.
+    let dir = std::env::temp_dir().join(format!("tch-cifar-counts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let record = vec![1u8; 2 + 3 * 32 * 32];
+    std::fs::write(dir.join("train.bin"), record.repeat(2)).unwrap();
+    std::fs::write(dir.join("test.bin"), &record).unwrap();
+    let err = vision::cifar::load_dir(&dir).unwrap_err();
+    assert!(matches!(err, tch::TchError::FileFormat(_)), "{err}");
+    assert!(err.to_string().contains("50000 records"), "{err}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn image_folder() {
+    use vision::image_folder::ImageFolderDataset;
+    let root = std::env::temp_dir().join(format!("tch-image-folder-{}", std::process::id()));
+    // The pixels of each image are set to an identifier, the images of the
+    // first class have different sizes.
+    let images = [("cat", 0, 8), ("cat", 1, 12), ("cat", 2, 16), ("dog", 3, 8), ("dog", 4, 8)];
+    for (class, id, size) in images {
+        std::fs::create_dir_all(root.join(class)).unwrap();
+        let image = Tensor::full([3, size, size], 10 * id, (tch::Kind::Uint8, tch::Device::Cpu));
+        vision::image::save(&image, root.join(class).join(format!("{id}.png"))).unwrap();
+    }
+    std::fs::write(root.join("dog").join("notes.txt"), "not an image").unwrap();
+
+    let mut dataset = ImageFolderDataset::new(&root, Some((8, 8))).unwrap();
+    assert_eq!(dataset.len(), 5);
+    assert_eq!(dataset.classes(), ["cat", "dog"]);
+    let batches: Vec<_> =
+        dataset.batches(2, false, tch::Device::Cpu).map(|batch| batch.unwrap()).collect();
+    assert_eq!(batches.len(), 3);
+    assert_eq!(batches[0].0.size(), [2, 3, 8, 8]);
+    assert_eq!(batches[2].0.size(), [1, 3, 8, 8]);
+    let labels: Vec<i64> =
+        batches.iter().flat_map(|(_, l)| Vec::<i64>::try_from(l).unwrap()).collect();
+    assert_eq!(labels, [0, 0, 0, 1, 1]);
+
+    // The shuffled order depends on the seed, images and labels stay aligned.
+    let shuffled_ids = |dataset: &mut ImageFolderDataset| {
+        let mut ids = vec![];
+        for batch in dataset.batches(2, true, tch::Device::Cpu) {
+            let (images, labels) = batch.unwrap();
+            for (image, label) in
+                images.split(1, 0).iter().zip(Vec::<i64>::try_from(&labels).unwrap())
+            {
+                let id = i64::try_from(image.max()).unwrap() / 10;
+                assert_eq!(label, if id < 3 { 0 } else { 1 });
+                ids.push(id);
+            }
+        }
+        ids
+    };
+    dataset.set_seed(42);
+    let ids = shuffled_ids(&mut dataset);
+    let mut sorted_ids = ids.clone();
+    sorted_ids.sort();
+    assert_eq!(sorted_ids, [0, 1, 2, 3, 4]);
+    let mut other = ImageFolderDataset::new(&root, Some((8, 8))).unwrap();
+    other.set_seed(42);
+    assert_eq!(shuffled_ids(&mut other), ids);
+
+    // Corrupt images are reported with their path.
+    std::fs::write(root.join("dog").join("5.png"), "not a png").unwrap();
+    let mut dataset = ImageFolderDataset::new(&root, Some((8, 8))).unwrap();
+    assert_eq!(dataset.len(), 6);
+    let err = dataset.batches(4, false, tch::Device::Cpu).find_map(|b| b.err()).unwrap();
+    assert!(err.to_string().contains("5.png"), "{err}");
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn transforms() {
+    use rand::SeedableRng;
+    use vision::transforms::*;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+    let images = Tensor::randint(256, [4, 3, 32, 48], (tch::Kind::Uint8, tch::Device::Cpu));
+
+    let crop = RandomResizedCrop::new(16, 24);
+    let ys = crop.transform(&images, &mut rng);
+    assert_eq!(ys.size(), [4, 3, 16, 24]);
+    assert_eq!(ys.kind(), tch::Kind::Uint8);
+    assert_eq!(crop.transform(&images.get(0), &mut rng).size(), [3, 16, 24]);
+
+    let flip = RandomHorizontalFlip { p: 1.0 };
+    assert!(flip.transform(&images, &mut rng).equal(&images.flip([3])));
+    let flip = RandomHorizontalFlip { p: 0.0 };
+    assert!(flip.transform(&images, &mut rng).equal(&images));
+
+    let floats = images.to_kind(tch::Kind::Float) / 255.;
+    let jitter = ColorJitter { brightness: 0.4, contrast: 0.4, saturation: 0.4 };
+    let ys = jitter.transform(&floats, &mut rng);
+    assert_eq!(ys.size(), [4, 3, 32, 48]);
+    assert!(f64::try_from(ys.min()).unwrap() >= 0.);
+    assert!(f64::try_from(ys.max()).unwrap() <= 1.);
+    assert!(ColorJitter::default()
+        .transform(&floats, &mut rng)
+        .allclose(&floats, 1e-6, 1e-6, false));
+
+    let normalize = Normalize { mean: vec![0.5; 3], std: vec![0.5; 3] };
+    let ys = normalize.transform(&images, &mut rng);
+    assert_eq!(ys.kind(), tch::Kind::Float);
+    assert!(ys.allclose(&(&floats * 2. - 1.), 1e-5, 1e-5, false));
+    assert!(Normalize { mean: vec![0.5], std: vec![0.5] }.f_transform(&images, &mut rng).is_err());
+
+    let erasing = RandomErasing { p: 1.0, value: 0., ..Default::default() };
+    let ones = Tensor::ones([2, 3, 32, 32], tch::kind::FLOAT_CPU);
+    let ys = erasing.transform(&ones, &mut rng);
+    for image in ys.unbind(0) {
+        let erased = f64::try_from((1f64 - image).sum(tch::Kind::Float)).unwrap() / 3.;
+        assert!((0.02 * 1024. - 1. ..=0.33 * 1024. + 1.).contains(&erased), "{erased}");
+    }
+
+    // The same seed gives the same results.
+    let compose = Compose(vec![
+        Box::new(RandomResizedCrop::new(16, 16)),
+        Box::new(RandomHorizontalFlip { p: 0.5 }),
+        Box::new(jitter),
+        Box::new(RandomErasing::default()),
+        Box::new(Normalize::imagenet()),
+    ]);
+    let run = |seed| {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        compose.transform(&images, &mut rng)
+    };
+    let ys = run(1);
+    assert_eq!(ys.size(), [4, 3, 16, 16]);
+    assert!(ys.equal(&run(1)));
+    assert!(!ys.equal(&run(2)));
+}
+
+#[test]
+fn mixup_cutmix() {
+    // Image i is filled with the value i and has label i, so that the mean of
+    // a mixed image is given by its soft target.
+    let labels = Tensor::arange(8, tch::kind::INT64_CPU);
+    let images = labels.to_kind(tch::Kind::Float).view([8, 1, 1, 1]).expand([8, 3, 16, 20], false);
+    let class_values = Tensor::arange(10, tch::kind::FLOAT_CPU);
+    for seed in 0..5 {
+        tch::manual_seed(seed);
+        for (mix, alpha) in
+            [(vision::dataset::mixup as fn(_, _, _, _) -> _, 0.2), (vision::dataset::cutmix, 1.0)]
+        {
+            let (mixed, targets) = mix(&images, &labels, 10, alpha);
+            assert_eq!(mixed.size(), [8, 3, 16, 20]);
+            assert_eq!(targets.size(), [8, 10]);
+            let sums = targets.sum_dim_intlist(1, false, tch::Kind::Float);
+            assert!(sums.allclose(&Tensor::ones([8], tch::kind::FLOAT_CPU), 1e-5, 1e-5, false));
+            let means = mixed.mean_dim([1, 2, 3].as_slice(), false, tch::Kind::Float);
+            assert!(means.allclose(&targets.mv(&class_values), 1e-4, 1e-4, false));
+        }
+    }
+    // The pixels of cutmix come from one of the two images.
+    let (mixed, targets) = vision::dataset::cutmix(&images, &labels, 10, 1.0);
+    for i in 0..8 {
+        let own = mixed.get(i).eq(i as f64).to_kind(tch::Kind::Float).mean(tch::Kind::Float);
+        let weight = targets.double_value(&[i, i]);
+        assert!((own.double_value(&[]) - weight).abs() < 1e-5, "{i} {weight}");
+    }
+}
+
 #[test]
 fn resize() {
     // Check that resizing returns a tensor with the appropriate dimensions.
@@ -32,3 +246,189 @@ fn resize_preserve_aspect_ratio() {
     let resized_img = vision::image::resize(&img, 32, 8).unwrap();
     assert_eq!(resized_img.size(), [3, 8, 32]);
 }
+
+#[test]
+fn encode_decode_memory() {
+    // A smooth gradient so that the jpg compression error is small.
+    let rows = Tensor::arange(32, tch::kind::FLOAT_CPU).view([1, 32, 1]) * 4.;
+    let cols = Tensor::arange(48, tch::kind::FLOAT_CPU).view([1, 1, 48]) * 2.;
+    let channels = Tensor::from_slice(&[0f32, 0.5, 1.]).view([3, 1, 1]);
+    let image = (rows * (1f64 - &channels) + cols * channels).to_kind(tch::Kind::Uint8);
+
+    let png = vision::image::encode_png(&image).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    let decoded = vision::image::load_from_memory(&png).unwrap();
+    assert!(decoded.equal(&image));
+
+    let jpeg = vision::image::encode_jpeg(&image, 95).unwrap();
+    assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+    let decoded = vision::image::load_from_memory(&jpeg).unwrap();
+    assert_eq!(decoded.size(), [3, 32, 48]);
+    let diff = (decoded.to_kind(tch::Kind::Float) - image.to_kind(tch::Kind::Float)).abs();
+    assert!(f64::try_from(diff.mean(tch::Kind::Float)).unwrap() < 4., "{diff}");
+    assert!(vision::image::encode_jpeg(&image, 0).is_err());
+
+    let err = vision::image::load_from_memory(&jpeg[..jpeg.len() / 8]).unwrap_err();
+    assert!(!err.to_string().is_empty());
+    let err = vision::image::load_from_memory(b"not an image").unwrap_err();
+    assert!(err.to_string().contains("unknown image type"), "{err}");
+}
+
+#[test]
+fn resize_batch() {
+    use vision::image::Interpolation;
+    let images = Tensor::randint(256, [4, 3, 20, 30], (tch::Kind::Uint8, tch::Device::Cpu));
+    for interpolation in [Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic] {
+        for (out_h, out_w) in [(10, 12), (32, 40)] {
+            let resized =
+                vision::image::resize_batch(&images, out_h, out_w, interpolation).unwrap();
+            assert_eq!(resized.size(), [4, 3, out_h, out_w]);
+            assert_eq!(resized.kind(), tch::Kind::Uint8);
+            // The batched version matches the resizing of each image.
+            for i in 0..4 {
+                let image = images.narrow(0, i, 1);
+                let expected =
+                    vision::image::resize_batch(&image, out_h, out_w, interpolation).unwrap();
+                assert!(resized.narrow(0, i, 1).equal(&expected));
+            }
+            // Non-contiguous inputs give the same results.
+            let nhwc = images.permute([0, 2, 3, 1]).contiguous();
+            let strided = nhwc.permute([0, 3, 1, 2]);
+            assert!(!strided.is_contiguous());
+            let other = vision::image::resize_batch(&strided, out_h, out_w, interpolation).unwrap();
+            assert!(other.equal(&resized));
+        }
+    }
+    // Anti-aliasing averages the stripes when downsampling, the borders are
+    // slightly off as the filter is truncated.
+    let stripes = Tensor::arange(16, tch::kind::FLOAT_CPU).remainder(2).view([1, 1, 1, 16]);
+    let stripes = stripes.expand([1, 1, 4, 16], false);
+    let resized = vision::image::resize_batch(&stripes, 4, 4, Interpolation::Bilinear).unwrap();
+    assert!(resized.allclose(
+        &Tensor::full([1, 1, 4, 4], 0.5, tch::kind::FLOAT_CPU),
+        0.1,
+        0.0,
+        false
+    ));
+    assert!(vision::image::resize_batch(&images.get(0), 8, 8, Interpolation::Nearest).is_err());
+}
+
+fn iou_reference(a: &[f32], b: &[f32]) -> f32 {
+    let area = |b: &[f32]| (b[2] - b[0]).max(0.) * (b[3] - b[1]).max(0.);
+    let w = (a[2].min(b[2]) - a[0].max(b[0])).max(0.);
+    let h = (a[3].min(b[3]) - a[1].max(b[1])).max(0.);
+    let union = area(a) + area(b) - w * h;
+    if union > 0. {
+        w * h / union
+    } else {
+        0.
+    }
+}
+
+fn nms_reference(boxes: &[Vec<f32>], scores: &[f32], iou_threshold: f32) -> Vec<i64> {
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|&i, &j| scores[j].total_cmp(&scores[i]));
+    let mut keep: Vec<usize> = vec![];
+    for i in order {
+        if keep.iter().all(|&k| iou_reference(&boxes[k], &boxes[i]) <= iou_threshold) {
+            keep.push(i)
+        }
+    }
+    keep.into_iter().map(|i| i as i64).collect()
+}
+
+#[test]
+fn nms_box_iou() {
+    use vision::ops::{box_iou, nms};
+    tch::manual_seed(42);
+    for n in [1, 5, 50] {
+        let xy = Tensor::rand([n, 2], tch::kind::FLOAT_CPU) * 10.;
+        let wh = Tensor::rand([n, 2], tch::kind::FLOAT_CPU) * 5.;
+        let boxes = Tensor::cat(&[&xy, &(&xy + wh)], 1);
+        let scores = Tensor::rand([n], tch::kind::FLOAT_CPU);
+        let boxes_vec: Vec<Vec<f32>> = Vec::try_from(&boxes).unwrap();
+        let scores_vec: Vec<f32> = Vec::try_from(&scores).unwrap();
+
+        let iou: Vec<Vec<f32>> = Vec::try_from(&box_iou(&boxes, &boxes)).unwrap();
+        for (i, row) in iou.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate() {
+                assert!((v - iou_reference(&boxes_vec[i], &boxes_vec[j])).abs() < 1e-5);
+            }
+        }
+        for iou_threshold in [0.0, 0.3, 0.7] {
+            let keep = nms(&boxes, &scores, iou_threshold);
+            assert_eq!(keep.kind(), tch::Kind::Int64);
+            let expected = nms_reference(&boxes_vec, &scores_vec, iou_threshold as f32);
+            assert_eq!(Vec::<i64>::try_from(&keep).unwrap(), expected);
+        }
+    }
+
+    // Empty inputs and boxes with a zero area.
+    let empty = Tensor::zeros([0, 4], tch::kind::FLOAT_CPU);
+    let keep = nms(&empty, &Tensor::zeros([0], tch::kind::FLOAT_CPU), 0.5);
+    assert_eq!(keep.size(), [0]);
+    assert_eq!(keep.kind(), tch::Kind::Int64);
+    assert_eq!(box_iou(&empty, &empty.ones_like()).size(), [0, 0]);
+    let boxes = Tensor::from_slice2(&[[1f32, 1., 1., 1.], [1., 1., 1., 1.], [0., 0., 2., 2.]]);
+    let iou: Vec<Vec<f32>> = Vec::try_from(&box_iou(&boxes, &boxes)).unwrap();
+    assert_eq!(iou, [[0., 0., 0.], [0., 0., 0.], [0., 0., 1.]]);
+    let keep = nms(&boxes, &Tensor::from_slice(&[0.9f32, 0.8, 0.7]), 0.5);
+    assert_eq!(Vec::<i64>::try_from(&keep).unwrap(), [0, 1, 2]);
+    assert!(vision::ops::f_nms(&boxes, &Tensor::zeros([2], tch::kind::FLOAT_CPU), 0.5).is_err());
+}
+
+#[test]
+fn roi_align_roi_pool() {
+    use vision::ops::{f_roi_align, roi_align, roi_pool};
+    // The first image is f(y, x) = 4y + x so that bilinear interpolations are
+    // exact, the second one is its opposite.
+    let image = Tensor::arange(16, tch::kind::FLOAT_CPU).view([1, 1, 4, 4]);
+    let features = Tensor::cat(&[&image, &-&image], 0);
+    let rois = Tensor::from_slice2(&[[0f32, 0., 0., 4., 4.], [1., 0., 0., 4., 4.]]);
+    // Rounds the values to ignore the float errors of the interpolations.
+    let values = |t: Tensor| {
+        let values = Vec::<Vec<f32>>::try_from(t.view([-1, 4])).unwrap();
+        values
+            .iter()
+            .map(|v| v.iter().map(|x| (x * 1e4).round() / 1e4).collect())
+            .collect::<Vec<Vec<f32>>>()
+    };
+
+    // The samples are at the centers of the bins, 1 and 3.
+    let ys = roi_align(&features, &rois, (2, 2), 1.0, 1, false);
+    assert_eq!(ys.size(), [2, 1, 2, 2]);
+    assert_eq!(values(ys), [[5., 7., 13., 15.], [-5., -7., -13., -15.]]);
+    // Aligned regions are shifted by half a pixel.
+    let ys = roi_align(&features, &rois, (2, 2), 1.0, 1, true);
+    assert_eq!(values(ys.narrow(0, 0, 1)), [[2.5, 4.5, 10.5, 12.5]]);
+    // The samples at 3.5 are clamped to the border 3, each bin averages the
+    // samples at (0.5, 1.5) and (2.5, 3.5).
+    let ys = roi_align(&features, &rois, (2, 2), 1.0, 2, false);
+    assert_eq!(values(ys.narrow(0, 0, 1)), [[5., 6.75, 12., 13.75]]);
+    // The spatial scale maps the image coordinates to the feature map.
+    let scaled = Tensor::from_slice2(&[[0f32, 0., 0., 8., 8.]]);
+    let ys = roi_align(&features, &scaled, (2, 2), 0.5, 1, false);
+    assert_eq!(values(ys), [[5., 7., 13., 15.]]);
+    // The adaptive sampling ratio uses two samples per bin here.
+    let ys = roi_align(&features, &rois, (2, 2), 1.0, 0, false);
+    assert_eq!(values(ys.narrow(0, 0, 1)), [[5., 6.75, 12., 13.75]]);
+
+    let rois_pool = Tensor::from_slice2(&[[0f32, 0., 0., 3., 3.], [1., 0., 0., 1., 1.]]);
+    let ys = roi_pool(&features, &rois_pool, (2, 2), 1.0);
+    assert_eq!(values(ys), [[5., 7., 13., 15.], [-0., -1., -4., -5.]]);
+
+    let invalid = Tensor::from_slice2(&[[2f32, 0., 0., 4., 4.]]);
+    assert!(f_roi_align(&features, &invalid, (2, 2), 1.0, 1, false).is_err());
+    let invalid = Tensor::from_slice2(&[[0.5f32, 0., 0., 4., 4.]]);
+    assert!(vision::ops::f_roi_pool(&features, &invalid, (2, 2), 1.0).is_err());
+
+    if tch::Cuda::is_available() {
+        let device = tch::Device::Cuda(0);
+        let ys = roi_align(&features.to(device), &rois.to(device), (2, 2), 1.0, 2, true);
+        let expected = roi_align(&features, &rois, (2, 2), 1.0, 2, true);
+        assert_eq!(ys.device(), device);
+        assert!(ys.to(tch::Device::Cpu).allclose(&expected, 1e-5, 1e-5, false));
+        let ys = roi_pool(&features.to(device), &rois_pool.to(device), (2, 2), 1.0);
+        assert!(ys.to(tch::Device::Cpu).equal(&roi_pool(&features, &rois_pool, (2, 2), 1.0)));
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::{Arc, Mutex};
+use tch::{warnings, Device, Kind, Tensor};
+
+// Resizing a non-empty output tensor is deprecated and raises a warning.
+fn resize_out() -> Tensor {
+    let out = Tensor::zeros([3], (Kind::Float, Device::Cpu));
+    let xs = Tensor::ones([2], (Kind::Float, Device::Cpu));
+    xs.add_out(&out, &xs)
+}
+
+#[test]
+fn capture_warnings() {
+    warnings::set_warn_always(true);
+    assert!(warnings::warn_always());
+    let (out, captured) = warnings::capture_warnings(resize_out);
+    assert_eq!(out.size(), [2]);
+    assert_eq!(captured.len(), 1, "{captured:?}");
+    let warning = &captured[0];
+    assert!(warning.message.contains("was resized"), "{warning}");
+    assert!(warning.warn_always);
+    assert!(warning.location.as_ref().map_or(false, |loc| loc.line > 0), "{warning:?}");
+
+    // Nested captures only see their own warnings.
+    let ((_, inner), outer) = warnings::capture_warnings(|| {
+        let _ = resize_out();
+        warnings::capture_warnings(resize_out)
+    });
+    assert_eq!((inner.len(), outer.len()), (1, 1));
+
+    let ((), captured) = warnings::capture_warnings(|| {
+        let _ = Tensor::ones([2], (Kind::Float, Device::Cpu)) * 2;
+    });
+    assert!(captured.is_empty(), "{captured:?}");
+}
+
+#[test]
+fn custom_handler() {
+    warnings::set_warn_always(true);
+    let seen = Arc::new(Mutex::new(vec![]));
+    let seen_ = seen.clone();
+    warnings::set_handler(Box::new(move |warning| seen_.lock().unwrap().push(warning)));
+    let _ = resize_out();
+    warnings::reset_handler();
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1, "{seen:?}");
+    assert!(seen[0].message.contains("was resized"), "{}", seen[0]);
+}
@@ -0,0 +1,60 @@
+use tch::utils::{self, Overlap};
+use tch::{Device, Kind, Tensor};
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn sliding_windows() {
+    let xs = Tensor::arange(10, (Kind::Int64, Device::Cpu));
+    let windows: Vec<_> = utils::sliding_windows(&xs, 0, 4, 3).collect();
+    let ranges: Vec<_> = windows.iter().map(|(_, r)| r.clone()).collect();
+    assert_eq!(ranges, [0..4, 3..7, 6..10]);
+    assert_eq!(Vec::<i64>::try_from(&windows[1].0).unwrap(), [3, 4, 5, 6]);
+
+    // The last window is shorter.
+    let ranges: Vec<_> = utils::sliding_windows(&xs, -1, 4, 4).map(|(_, r)| r).collect();
+    assert_eq!(ranges, [0..4, 4..8, 8..10]);
+    // A single window covers the whole sequence.
+    let ranges: Vec<_> = utils::sliding_windows(&xs, 0, 16, 2).map(|(_, r)| r).collect();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0], 0..10);
+
+    assert!(utils::f_sliding_windows(&xs, 0, 4, 0).is_err());
+    assert!(utils::f_sliding_windows(&xs, 0, 4, 5).is_err());
+    assert!(utils::f_sliding_windows(&xs, 1, 4, 2).is_err());
+}
+
+#[test]
+fn stitch_round_trip() {
+    let xs = Tensor::randn([2, 37, 3], (Kind::Float, Device::Cpu));
+    for (window, hop) in [(8, 5), (8, 3), (8, 8), (40, 8), (7, 1)] {
+        let windows: Vec<_> = utils::sliding_windows(&xs, 1, window, hop).collect();
+        for overlap in [Overlap::Mean, Overlap::Hann, Overlap::Last] {
+            let ys = utils::stitch(&windows, 1, 37, overlap);
+            assert!(ys.equal(&xs), "{window} {hop} {overlap:?}");
+        }
+    }
+}
+
+#[test]
+fn stitch_overlap() {
+    let outputs = [
+        (Tensor::ones([4], (Kind::Double, Device::Cpu)), 0..4),
+        (Tensor::full([4], 3., (Kind::Double, Device::Cpu)), 2..6),
+    ];
+    let mean = utils::stitch(&outputs, 0, 6, Overlap::Mean);
+    assert_eq!(vec_f64_from(&mean), [1., 1., 2., 2., 3., 3.]);
+    let last = utils::stitch(&outputs, 0, 6, Overlap::Last);
+    assert_eq!(vec_f64_from(&last), [1., 1., 3., 3., 3., 3.]);
+    // The cross-fade weights are symmetric.
+    let hann = vec_f64_from(&utils::stitch(&outputs, 0, 6, Overlap::Hann));
+    assert_eq!(hann[..2], [1., 1.]);
+    assert_eq!(hann[4..], [3., 3.]);
+    assert!(hann[2] > 1. && hann[2] < 2. && (hann[2] + hann[3] - 4.).abs() < 1e-9, "{hann:?}");
+
+    // The outputs must cover all the positions.
+    assert!(utils::f_stitch(&outputs, 0, 8, Overlap::Mean).is_err());
+    assert!(utils::f_stitch(&outputs[..1], 0, 4, Overlap::Mean).is_ok());
+    assert!(utils::f_stitch(&outputs[..1], 0, 3, Overlap::Mean).is_err());
+}
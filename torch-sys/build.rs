@@ -361,9 +361,15 @@ impl SystemInfo {
         } else {
             "libtch/fake_cuda_dependency.cpp"
         };
+        // The CUDA specific functions, e.g. for streams, are only compiled when
+        // targeting a CUDA build, otherwise some stubs returning errors are used.
+        let cuda_api = if use_cuda { "libtch/cuda_api.cpp" } else { "libtch/fake_cuda_api.cpp" };
         println!("cargo:rerun-if-changed={}", cuda_dependency);
+        println!("cargo:rerun-if-changed={}", cuda_api);
         println!("cargo:rerun-if-changed=libtch/torch_python.cpp");
         println!("cargo:rerun-if-changed=libtch/torch_python.h");
+        println!("cargo:rerun-if-changed=libtch/torch_distributed.cpp");
+        println!("cargo:rerun-if-changed=libtch/torch_distributed.h");
         println!("cargo:rerun-if-changed=libtch/torch_api_generated.cpp");
         println!("cargo:rerun-if-changed=libtch/torch_api_generated.h");
         println!("cargo:rerun-if-changed=libtch/torch_api.cpp");
@@ -371,11 +377,24 @@ impl SystemInfo {
         println!("cargo:rerun-if-changed=libtch/stb_image_write.h");
         println!("cargo:rerun-if-changed=libtch/stb_image_resize.h");
         println!("cargo:rerun-if-changed=libtch/stb_image.h");
-        let mut c_files =
-            vec!["libtch/torch_api.cpp", "libtch/torch_api_generated.cpp", cuda_dependency];
+        let mut c_files = vec![
+            "libtch/torch_api.cpp",
+            "libtch/torch_api_generated.cpp",
+            cuda_dependency,
+            cuda_api,
+        ];
         if cfg!(feature = "python-extension") {
             c_files.push("libtch/torch_python.cpp")
         }
+        // The c10d headers only declare the process groups when these are defined.
+        let mut defines = vec![];
+        if cfg!(feature = "distributed") {
+            c_files.push("libtch/torch_distributed.cpp");
+            defines.push("USE_C10D_GLOO");
+            if use_cuda {
+                defines.push("USE_C10D_NCCL")
+            }
+        }
 
         match self.os {
             Os::Linux | Os::Macos => {
@@ -383,7 +402,11 @@ impl SystemInfo {
                 // as DEP_TORCH_SYS_LIBTORCH_LIB, see:
                 // https://doc.rust-lang.org/cargo/reference/build-scripts.html#the-links-manifest-key
                 println!("cargo:libtorch_lib={}", self.libtorch_lib_dir.display());
-                cc::Build::new()
+                let mut build = cc::Build::new();
+                defines.iter().for_each(|d| {
+                    build.define(d, None);
+                });
+                build
                     .cpp(true)
                     .pic(true)
                     .warnings(false)
@@ -398,7 +421,11 @@ impl SystemInfo {
                 // TODO: Pass "/link" "LIBPATH:{}" to cl.exe in order to emulate rpath.
                 //       Not yet supported by cc=rs.
                 //       https://github.com/alexcrichton/cc-rs/issues/323
-                cc::Build::new()
+                let mut build = cc::Build::new();
+                defines.iter().for_each(|d| {
+                    build.define(d, None);
+                });
+                build
                     .cpp(true)
                     .pic(true)
                     .warnings(false)
@@ -452,6 +479,8 @@ fn main() -> anyhow::Result<()> {
             || si_lib.join("torch_cuda_cpp.dll").exists();
         let use_hip =
             si_lib.join("libtorch_hip.so").exists() || si_lib.join("torch_hip.dll").exists();
+        let use_c10_cuda =
+            si_lib.join("libc10_cuda.so").exists() || si_lib.join("c10_cuda.dll").exists();
         println!("cargo:rustc-link-search=native={}", si_lib.display());
 
         system_info.make(use_cuda, use_hip);
@@ -500,6 +529,9 @@ fn main() -> anyhow::Result<()> {
         if use_hip {
             system_info.link("c10_hip");
         }
+        if use_cuda && use_c10_cuda {
+            system_info.link("c10_cuda");
+        }
 
         let target = env::var("TARGET").context("TARGET variable not set")?;
 
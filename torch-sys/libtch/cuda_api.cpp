@@ -0,0 +1,288 @@
+#include<c10/cuda/CUDAStream.h>
+#include<ATen/cuda/CUDAEvent.h>
+#include<c10/cuda/CUDACachingAllocator.h>
+#include<c10/cuda/CUDAFunctions.h>
+#include<ATen/cuda/CUDAContext.h>
+#include<ATen/cuda/CUDAGraph.h>
+#include<torch/version.h>
+#include<cstdlib>
+#include<sstream>
+#include<stdexcept>
+#include<string>
+#include "torch_api.h"
+
+#define STREAM(s) static_cast<c10::cuda::CUDAStream*>(s)
+#define EVENT(e) static_cast<at::cuda::CUDAEvent*>(e)
+#define GRAPH(g) static_cast<at::cuda::CUDAGraph*>(g)
+
+int atc_built_with_cuda() {
+  return 1;
+}
+
+cuda_stream atcs_new(int device, int high_priority) {
+  PROTECT(
+    return new c10::cuda::CUDAStream(c10::cuda::getStreamFromPool(high_priority != 0, device));
+  )
+  return nullptr;
+}
+
+cuda_stream atcs_default(int device) {
+  PROTECT(
+    return new c10::cuda::CUDAStream(c10::cuda::getDefaultCUDAStream(device));
+  )
+  return nullptr;
+}
+
+cuda_stream atcs_current(int device) {
+  PROTECT(
+    return new c10::cuda::CUDAStream(c10::cuda::getCurrentCUDAStream(device));
+  )
+  return nullptr;
+}
+
+void atcs_set_current(cuda_stream s) {
+  PROTECT(
+    c10::cuda::setCurrentCUDAStream(*STREAM(s));
+  )
+}
+
+void atcs_synchronize(cuda_stream s) {
+  PROTECT(
+    STREAM(s)->synchronize();
+  )
+}
+
+int atcs_query(cuda_stream s) {
+  PROTECT(
+    return STREAM(s)->query();
+  )
+  return -1;
+}
+
+int64_t atcs_id(cuda_stream s) {
+  PROTECT(
+    return STREAM(s)->id();
+  )
+  return -1;
+}
+
+int atcs_device(cuda_stream s) {
+  PROTECT(
+    return STREAM(s)->device_index();
+  )
+  return -1;
+}
+
+void atcs_wait_event(cuda_stream s, cuda_event e) {
+  PROTECT(
+    EVENT(e)->block(*STREAM(s));
+  )
+}
+
+void atcs_record_stream(tensor t, cuda_stream s) {
+  PROTECT(
+    t->record_stream(STREAM(s)->unwrap());
+  )
+}
+
+void atcs_free(cuda_stream s) {
+  PROTECT(delete STREAM(s);)
+}
+
+cuda_event atce_new(int enable_timing) {
+  PROTECT(
+    return new at::cuda::CUDAEvent(enable_timing ? cudaEventDefault : cudaEventDisableTiming);
+  )
+  return nullptr;
+}
+
+void atce_record(cuda_event e, cuda_stream s) {
+  PROTECT(
+    EVENT(e)->record(*STREAM(s));
+  )
+}
+
+void atce_synchronize(cuda_event e) {
+  PROTECT(
+    EVENT(e)->synchronize();
+  )
+}
+
+int atce_query(cuda_event e) {
+  PROTECT(
+    return EVENT(e)->query();
+  )
+  return -1;
+}
+
+float atce_elapsed_time(cuda_event start, cuda_event end) {
+  PROTECT(
+    return EVENT(start)->elapsed_time(*EVENT(end));
+  )
+  return -1.;
+}
+
+void atce_free(cuda_event e) {
+  PROTECT(delete EVENT(e);)
+}
+
+cuda_graph atcg_new() {
+  PROTECT(
+    return new at::cuda::CUDAGraph();
+  )
+  return nullptr;
+}
+
+void atcg_capture_begin(cuda_graph g, uint64_t pool_id0, uint64_t pool_id1) {
+  PROTECT(
+    GRAPH(g)->capture_begin(at::cuda::MempoolId_t{pool_id0, pool_id1});
+  )
+}
+
+void atcg_capture_end(cuda_graph g) {
+  PROTECT(
+    GRAPH(g)->capture_end();
+  )
+}
+
+void atcg_replay(cuda_graph g) {
+  PROTECT(
+    GRAPH(g)->replay();
+  )
+}
+
+void atcg_reset(cuda_graph g) {
+  PROTECT(
+    GRAPH(g)->reset();
+  )
+}
+
+void atcg_pool(cuda_graph g, uint64_t *pool_id0, uint64_t *pool_id1) {
+  PROTECT(
+    auto pool = GRAPH(g)->pool();
+    *pool_id0 = pool.first;
+    *pool_id1 = pool.second;
+  )
+}
+
+void atcg_free(cuda_graph g) {
+  PROTECT(delete GRAPH(g);)
+}
+
+void atcg_graph_pool_handle(uint64_t *pool_id0, uint64_t *pool_id1) {
+  PROTECT(
+    auto pool = at::cuda::graph_pool_handle();
+    *pool_id0 = pool.first;
+    *pool_id1 = pool.second;
+  )
+}
+
+void atc_memory_stats(int device, int64_t *stats) {
+  PROTECT(
+    using c10::cuda::CUDACachingAllocator::StatType;
+    auto device_stats = c10::cuda::CUDACachingAllocator::getDeviceStats(device);
+    const size_t aggregate = static_cast<size_t>(StatType::AGGREGATE);
+    stats[0] = device_stats.allocated_bytes[aggregate].current;
+    stats[1] = device_stats.allocated_bytes[aggregate].peak;
+    stats[2] = device_stats.reserved_bytes[aggregate].current;
+    stats[3] = device_stats.reserved_bytes[aggregate].peak;
+    stats[4] = device_stats.active_bytes[aggregate].current;
+    stats[5] = device_stats.active_bytes[aggregate].peak;
+    stats[6] = device_stats.allocation[aggregate].current;
+    stats[7] = device_stats.allocation[aggregate].peak;
+    stats[8] = device_stats.allocation[aggregate].allocated;
+    stats[9] = device_stats.allocation[aggregate].freed;
+    stats[10] = device_stats.num_alloc_retries;
+    stats[11] = device_stats.num_ooms;
+  )
+}
+
+void atc_reset_peak_memory_stats(int device) {
+  PROTECT(
+    c10::cuda::CUDACachingAllocator::resetPeakStats(device);
+  )
+}
+
+void atc_empty_cache() {
+  PROTECT(
+    c10::cuda::CUDACachingAllocator::emptyCache();
+  )
+}
+
+void atc_set_memory_fraction(double fraction, int device) {
+  PROTECT(
+    c10::cuda::CUDACachingAllocator::setMemoryFraction(fraction, device);
+  )
+}
+
+#if TORCH_VERSION_MAJOR > 2 || (TORCH_VERSION_MAJOR == 2 && TORCH_VERSION_MINOR >= 1)
+void atc_set_allocator_settings(char *settings) {
+  PROTECT(
+    c10::cuda::CUDACachingAllocator::setAllocatorSettings(settings);
+  )
+}
+#else
+// Before PyTorch 2.1, the settings are only read from the environment when the
+// allocator gets initialized, the options are checked here so that errors are
+// reported upfront rather than on the first allocation.
+static void check_allocator_settings(const std::string &settings) {
+  std::stringstream stream(settings);
+  std::string option;
+  while (std::getline(stream, option, ',')) {
+    option.erase(0, option.find_first_not_of(" \t"));
+    option.erase(option.find_last_not_of(" \t") + 1);
+    if (option.empty()) continue;
+    size_t sep = option.find(':');
+    if (sep == std::string::npos || sep + 1 == option.size())
+      throw std::invalid_argument("invalid allocator setting " + option + ", expected key:value");
+    std::string key = option.substr(0, sep);
+    key.erase(key.find_last_not_of(" \t") + 1);
+    if (key != "backend" && key != "garbage_collection_threshold" && key != "max_split_size_mb"
+        && key != "roundup_bypass_threshold_mb" && key != "roundup_power2_divisions")
+      throw std::invalid_argument("unrecognized CachingAllocator option: " + key);
+  }
+}
+
+static int set_allocator_env(const char *settings) {
+#ifdef _WIN32
+  return _putenv_s("PYTORCH_CUDA_ALLOC_CONF", settings);
+#else
+  return setenv("PYTORCH_CUDA_ALLOC_CONF", settings, 1);
+#endif
+}
+
+void atc_set_allocator_settings(char *settings) {
+  PROTECT(
+    c10::cuda::CUDACachingAllocator::CUDAAllocator *allocator = c10::cuda::CUDACachingAllocator::get();
+    if (allocator != nullptr && allocator->initialized())
+      throw std::runtime_error("the CUDA caching allocator settings cannot be changed after CUDA memory has been allocated with libtorch " TORCH_VERSION);
+    check_allocator_settings(settings);
+    if (set_allocator_env(settings) != 0)
+      throw std::runtime_error("cannot set PYTORCH_CUDA_ALLOC_CONF");
+  )
+}
+#endif
+
+void atc_device_properties(int device, char **name, int64_t *total_memory, int *major, int *minor, int *multi_processor_count) {
+  PROTECT(
+    cudaDeviceProp *prop = at::cuda::getDeviceProperties(device);
+    *total_memory = prop->totalGlobalMem;
+    *major = prop->major;
+    *minor = prop->minor;
+    *multi_processor_count = prop->multiProcessorCount;
+    *name = strdup(prop->name);
+  )
+}
+
+int atc_current_device() {
+  PROTECT(
+    return c10::cuda::current_device();
+  )
+  return -1;
+}
+
+void atc_set_device(int device) {
+  PROTECT(
+    c10::cuda::set_device(device);
+  )
+}
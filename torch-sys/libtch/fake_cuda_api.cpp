@@ -0,0 +1,148 @@
+// Stubs used when libtorch has been compiled without CUDA support.
+#include "torch_api.h"
+
+#define NO_CUDA throw std::runtime_error("libtorch has been compiled without CUDA support");
+
+int atc_built_with_cuda() {
+  return 0;
+}
+
+cuda_stream atcs_new(int device, int high_priority) {
+  PROTECT(NO_CUDA)
+  return nullptr;
+}
+
+cuda_stream atcs_default(int device) {
+  PROTECT(NO_CUDA)
+  return nullptr;
+}
+
+cuda_stream atcs_current(int device) {
+  PROTECT(NO_CUDA)
+  return nullptr;
+}
+
+void atcs_set_current(cuda_stream s) {
+  PROTECT(NO_CUDA)
+}
+
+void atcs_synchronize(cuda_stream s) {
+  PROTECT(NO_CUDA)
+}
+
+int atcs_query(cuda_stream s) {
+  PROTECT(NO_CUDA)
+  return -1;
+}
+
+int64_t atcs_id(cuda_stream s) {
+  PROTECT(NO_CUDA)
+  return -1;
+}
+
+int atcs_device(cuda_stream s) {
+  PROTECT(NO_CUDA)
+  return -1;
+}
+
+void atcs_wait_event(cuda_stream s, cuda_event e) {
+  PROTECT(NO_CUDA)
+}
+
+void atcs_record_stream(tensor t, cuda_stream s) {
+  PROTECT(NO_CUDA)
+}
+
+void atcs_free(cuda_stream s) {
+}
+
+cuda_event atce_new(int enable_timing) {
+  PROTECT(NO_CUDA)
+  return nullptr;
+}
+
+void atce_record(cuda_event e, cuda_stream s) {
+  PROTECT(NO_CUDA)
+}
+
+void atce_synchronize(cuda_event e) {
+  PROTECT(NO_CUDA)
+}
+
+int atce_query(cuda_event e) {
+  PROTECT(NO_CUDA)
+  return -1;
+}
+
+float atce_elapsed_time(cuda_event start, cuda_event end) {
+  PROTECT(NO_CUDA)
+  return -1.;
+}
+
+void atce_free(cuda_event e) {
+}
+
+cuda_graph atcg_new() {
+  PROTECT(NO_CUDA)
+  return nullptr;
+}
+
+void atcg_capture_begin(cuda_graph g, uint64_t pool_id0, uint64_t pool_id1) {
+  PROTECT(NO_CUDA)
+}
+
+void atcg_capture_end(cuda_graph g) {
+  PROTECT(NO_CUDA)
+}
+
+void atcg_replay(cuda_graph g) {
+  PROTECT(NO_CUDA)
+}
+
+void atcg_reset(cuda_graph g) {
+  PROTECT(NO_CUDA)
+}
+
+void atcg_pool(cuda_graph g, uint64_t *pool_id0, uint64_t *pool_id1) {
+  PROTECT(NO_CUDA)
+}
+
+void atcg_free(cuda_graph g) {
+}
+
+void atcg_graph_pool_handle(uint64_t *pool_id0, uint64_t *pool_id1) {
+  PROTECT(NO_CUDA)
+}
+
+void atc_memory_stats(int device, int64_t *stats) {
+  PROTECT(NO_CUDA)
+}
+
+void atc_reset_peak_memory_stats(int device) {
+  PROTECT(NO_CUDA)
+}
+
+void atc_empty_cache() {
+  PROTECT(NO_CUDA)
+}
+
+void atc_set_memory_fraction(double fraction, int device) {
+  PROTECT(NO_CUDA)
+}
+
+void atc_set_allocator_settings(char *settings) {
+  PROTECT(NO_CUDA)
+}
+
+void atc_device_properties(int device, char **name, int64_t *total_memory, int *major, int *minor, int *multi_processor_count) {
+  PROTECT(NO_CUDA)
+}
+
+int atc_current_device() {
+  PROTECT(NO_CUDA)
+  return -1;
+}
+
+void atc_set_device(int device) {
+  PROTECT(NO_CUDA)
+}
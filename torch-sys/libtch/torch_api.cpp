@@ -1,19 +1,42 @@
+#include<torch/csrc/autograd/anomaly_mode.h>
 #include<torch/csrc/autograd/engine.h>
 #include<torch/csrc/jit/frontend/tracer.h>
 #include<torch/csrc/jit/runtime/graph_executor.h>
 #include<torch/csrc/jit/passes/fixup_trace_scope_blocks.h>
 #include<torch/csrc/jit/passes/normalize_ops.h>
+#include<torch/csrc/jit/frontend/function_schema_parser.h>
+#include<torch/csrc/jit/runtime/custom_operator.h>
+#include<torch/csrc/autograd/functions/utils.h>
+#include<torch/library.h>
 #include<torch/csrc/jit/mobile/import_data.h>
 #include<torch/csrc/jit/runtime/graph_executor.h>
 #include<torch/torch.h>
+#include<torch/version.h>
+#include<ATen/Version.h>
 #include<ATen/autocast_mode.h>
+#include<ATen/detail/CUDAHooksInterface.h>
+#include<ATen/detail/MPSHooksInterface.h>
 #include<torch/script.h>
 #include<torch/csrc/jit/passes/tensorexpr_fuser.h>
 #include<torch/csrc/jit/codegen/cuda/interface.h>
+#include<cerrno>
+#include<cstring>
+#include<fstream>
+#include<mutex>
 #include<stdexcept>
 #include<vector>
 #include "torch_api.h"
 
+#ifdef _WIN32
+#ifndef WIN32_LEAN_AND_MEAN
+#define WIN32_LEAN_AND_MEAN
+#endif
+#ifndef NOMINMAX
+#define NOMINMAX
+#endif
+#include<windows.h>
+#endif
+
 #define STB_IMAGE_IMPLEMENTATION
 #include "stb_image.h"
 
@@ -31,8 +54,93 @@ char *get_and_reset_last_err() {
     return tmp;
 }
 
+// The paths are passed as UTF-8. On Windows the narrow file APIs would use the
+// ANSI code page, so the paths are converted to wide strings. The long paths
+// are made absolute and get the \\?\ prefix lifting the MAX_PATH limit.
+#ifdef _WIN32
+std::wstring wide_path(const char *path) {
+  int len = MultiByteToWideChar(CP_UTF8, MB_ERR_INVALID_CHARS, path, -1, nullptr, 0);
+  if (len == 0)
+    throw std::invalid_argument(std::string("invalid UTF-8 path ") + path);
+  std::wstring wide(len, L'\0');
+  MultiByteToWideChar(CP_UTF8, MB_ERR_INVALID_CHARS, path, -1, &wide[0], len);
+  wide.resize(len - 1);
+  // Directories are limited to MAX_PATH - 12 characters.
+  if (wide.size() < MAX_PATH - 12 || wide.rfind(L"\\\\?\\", 0) == 0)
+    return wide;
+  DWORD full_len = GetFullPathNameW(wide.c_str(), 0, nullptr, nullptr);
+  if (full_len == 0)
+    throw std::invalid_argument(std::string("invalid path ") + path);
+  std::wstring full(full_len, L'\0');
+  full_len = GetFullPathNameW(wide.c_str(), full_len, &full[0], nullptr);
+  full.resize(full_len);
+  if (full.rfind(L"\\\\", 0) == 0)
+    return L"\\\\?\\UNC\\" + full.substr(2);
+  return L"\\\\?\\" + full;
+}
+#endif
+
+std::ifstream open_input_file(const char *path) {
+#ifdef _WIN32
+  std::ifstream file(wide_path(path).c_str(), std::ios::binary);
+#else
+  std::ifstream file(path, std::ios::binary);
+#endif
+  if (!file)
+    throw std::runtime_error(std::string("cannot open ") + path + ": " + strerror(errno));
+  return file;
+}
+
+std::ofstream open_output_file(const char *path) {
+#ifdef _WIN32
+  std::ofstream file(wide_path(path).c_str(), std::ios::binary);
+#else
+  std::ofstream file(path, std::ios::binary);
+#endif
+  if (!file)
+    throw std::runtime_error(std::string("cannot create ") + path + ": " + strerror(errno));
+  return file;
+}
+
+void close_output_file(std::ofstream &file, const char *path) {
+  file.close();
+  if (file.fail())
+    throw std::runtime_error(std::string("cannot write ") + path);
+}
+
 void at_manual_seed(int64_t seed) {
-  torch::manual_seed(seed);
+  PROTECT(torch::manual_seed(seed);)
+}
+
+tensor at_get_rng_state(int device) {
+  PROTECT(
+    at::Generator gen = at::globalContext().defaultGenerator(device_of_int(device));
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    return new torch::Tensor(gen.get_state());
+  )
+  return nullptr;
+}
+
+void at_set_rng_state(int device, tensor state) {
+  PROTECT(
+    at::Generator gen = at::globalContext().defaultGenerator(device_of_int(device));
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    gen.set_state(*state);
+  )
+}
+
+void at_set_deterministic_algorithms(int b, int warn_only) {
+  PROTECT(at::globalContext().setDeterministicAlgorithms(b, warn_only);)
+}
+
+int at_deterministic_algorithms() {
+  PROTECT(return at::globalContext().deterministicAlgorithms();)
+  return -1;
+}
+
+int at_deterministic_algorithms_warn_only() {
+  PROTECT(return at::globalContext().deterministicAlgorithmsWarnOnly();)
+  return -1;
 }
 
 vector<torch::Tensor> of_carray_tensor(torch::Tensor **vs, int len) {
@@ -76,6 +184,15 @@ tensor at_tensor_of_blob(void *data, int64_t *dims, size_t ndims, int64_t *strid
   return nullptr;
 }
 
+tensor at_tensor_of_blob_with_deleter(void *data, int64_t *dims, size_t ndims, int64_t *strides, size_t nstrides, int type, int device, void (*deleter)(void *), void *deleter_ctx) {
+  PROTECT(
+    at::TensorOptions blobOptions = at::TensorOptions().device(device_of_int(device)).dtype(torch::ScalarType(type));
+    auto free_data = [deleter, deleter_ctx](void *) { deleter(deleter_ctx); };
+    return new torch::Tensor(torch::from_blob(data, torch::IntArrayRef(dims, ndims), torch::IntArrayRef(strides, nstrides), free_data, blobOptions));
+  )
+  return nullptr;
+}
+
 tensor at_tensor_of_data(void *vs, int64_t *dims, size_t ndims, size_t element_size_in_bytes, int type) {
   PROTECT(
     torch::Tensor tensor = torch::zeros(torch::IntArrayRef(dims, ndims), torch::ScalarType(type));
@@ -117,6 +234,11 @@ void *at_data_ptr(tensor t) {
   return nullptr;
 }
 
+void *at_tensor_impl(tensor t) {
+  PROTECT(return t->unsafeGetTensorImpl();)
+  return nullptr;
+}
+
 
 int at_defined(tensor t) {
   PROTECT(return t->defined();)
@@ -171,7 +293,7 @@ void at__amp_non_finite_check_and_unscale(tensor t, tensor found_inf, tensor inf
 }
 
 void at_autocast_clear_cache() {
-  at::autocast::clear_cache();
+  PROTECT(at::autocast::clear_cache();)
 }
 
 int at_autocast_decrement_nesting() {
@@ -204,6 +326,42 @@ bool at_autocast_set_enabled(bool b) {
   return -1;
 }
 
+int at_grad_is_enabled() {
+  PROTECT(return torch::autograd::GradMode::is_enabled();)
+  return -1;
+}
+
+bool at_autocast_is_cpu_enabled() {
+  PROTECT(
+    return at::autocast::is_cpu_enabled();
+  )
+  return -1;
+}
+
+bool at_autocast_set_cpu_enabled(bool b) {
+  PROTECT(
+    bool is_enabled = at::autocast::is_cpu_enabled();
+    at::autocast::set_cpu_enabled(b);
+    return is_enabled;
+  )
+  return -1;
+}
+
+int at_autocast_set_dtype(bool cpu, int dtype) {
+  PROTECT(
+    if (cpu) {
+      int prev = static_cast<int>(at::autocast::get_autocast_cpu_dtype());
+      at::autocast::set_autocast_cpu_dtype(torch::ScalarType(dtype));
+      return prev;
+    } else {
+      int prev = static_cast<int>(at::autocast::get_autocast_gpu_dtype());
+      at::autocast::set_autocast_gpu_dtype(torch::ScalarType(dtype));
+      return prev;
+    }
+  )
+  return -1;
+}
+
 int at_device(tensor t) {
   PROTECT(
     auto device = t->device();
@@ -211,14 +369,42 @@ int at_device(tensor t) {
     if (device.type() == at::kMPS) return -2;
     if (device.type() == at::kVulkan) return -3;
     if (device.type() == at::kCUDA) return device.index();
+    throw std::invalid_argument("unsupported device type " + c10::DeviceTypeName(device.type()));
   )
-  return -2;
+  return -4;
 }
 
 void at_backward(tensor t, int keep_graph, int create_graph) {
   PROTECT(t->backward({}, keep_graph, create_graph);)
 }
 
+void at_backward_opt(tensor t, tensor *inputs, int ninputs, int keep_graph, int create_graph) {
+  PROTECT(
+    // A null inputs pointer accumulates the gradients in all the graph leaves.
+    if (inputs == nullptr) {
+      t->backward({}, keep_graph, create_graph);
+    } else {
+      vector<torch::Tensor> inputs_;
+      for (int i = 0; i < ninputs; ++i)
+        inputs_.push_back(*inputs[i]);
+      t->backward({}, keep_graph, create_graph, inputs_);
+    }
+  )
+}
+
+tensor at_set_requires_grad(tensor t, int requires_grad) {
+  PROTECT(return new torch::Tensor(t->set_requires_grad((bool)requires_grad));)
+  return nullptr;
+}
+
+void at_retain_grad(tensor t) {
+  PROTECT(t->retain_grad();)
+}
+
+void at_set_grad(tensor t, tensor grad) {
+  PROTECT(t->mutable_grad() = *grad;)
+}
+
 int at_requires_grad(tensor t) {
   PROTECT(return t->requires_grad();)
   return -1;
@@ -233,6 +419,265 @@ int at_grad_set_enabled(int b) {
   return -1;
 }
 
+void at_set_anomaly_mode(int enabled, int check_nan) {
+  PROTECT(torch::autograd::AnomalyMode::set_enabled(enabled, check_nan);)
+}
+
+int at_anomaly_mode_is_enabled() {
+  PROTECT(return torch::autograd::AnomalyMode::is_enabled();)
+  return -1;
+}
+
+int at_anomaly_mode_should_check_nan() {
+  PROTECT(return torch::autograd::AnomalyMode::should_check_nan();)
+  return -1;
+}
+
+// Forwards the warnings to a callback rather than printing them on stderr. The
+// callback must not throw.
+class CallbackWarningHandler : public c10::WarningHandler {
+ public:
+  explicit CallbackWarningHandler(warning_callback f) : f_(f) {}
+  void process(const c10::Warning &warning) override {
+    auto loc = warning.source_location();
+    f_(warning.msg().c_str(), loc.file, loc.function, loc.line, c10::WarningUtils::get_warnAlways());
+  }
+ private:
+  warning_callback f_;
+};
+
+void *at_warning_handler_install(warning_callback f) {
+  PROTECT(
+    // The handler has to outlive all the threads it gets installed on.
+    static CallbackWarningHandler *handler = new CallbackWarningHandler(f);
+    auto prev = c10::WarningUtils::get_warning_handler();
+    c10::WarningUtils::set_warning_handler(handler);
+    return (void*)prev;
+  )
+  return nullptr;
+}
+
+void at_warning_handler_restore(void *prev) {
+  PROTECT(c10::WarningUtils::set_warning_handler((c10::WarningHandler*)prev);)
+}
+
+void at_set_warn_always(int enabled) {
+  PROTECT(c10::WarningUtils::set_warnAlways(enabled);)
+}
+
+int at_get_warn_always() {
+  PROTECT(return c10::WarningUtils::get_warnAlways();)
+  return -1;
+}
+
+inference_mode at_inference_mode_enter(int enabled) {
+  PROTECT(return new c10::InferenceMode(enabled);)
+  return nullptr;
+}
+
+void at_inference_mode_exit(inference_mode guard) {
+  PROTECT(delete guard;)
+}
+
+int at_inference_mode_is_enabled() {
+  PROTECT(return c10::InferenceMode::is_enabled();)
+  return -1;
+}
+
+namespace {
+struct CheckpointClosure : torch::CustomClassHolder {
+  void *data;
+  tensor (*f)(void *, tensor *, int);
+  void (*free_data)(void *);
+
+  CheckpointClosure(void *data, tensor (*f)(void *, tensor *, int), void (*free_data)(void *))
+    : data(data), f(f), free_data(free_data) {}
+
+  ~CheckpointClosure() {
+    free_data(data);
+  }
+
+  // The callback takes ownership of the inputs.
+  torch::Tensor call(const std::vector<torch::Tensor> &inputs) {
+    std::vector<tensor> c_inputs;
+    for (auto &input : inputs) c_inputs.push_back(new torch::Tensor(input));
+    tensor output = f(data, c_inputs.data(), c_inputs.size());
+    if (output == nullptr) {
+      throw std::runtime_error("checkpointed function failed");
+    }
+    torch::Tensor result = *output;
+    delete output;
+    return result;
+  }
+};
+
+// The random number generator states for the CPU and the CUDA devices used by
+// the inputs, so that operations such as dropout are replayed identically.
+void get_rng_states(const std::vector<torch::Tensor> &inputs, torch::Tensor &cpu_state, std::vector<int64_t> &devices, std::vector<torch::Tensor> &cuda_states) {
+  {
+    auto gen = at::detail::getDefaultCPUGenerator();
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    cpu_state = gen.get_state();
+  }
+  for (auto &input : inputs) {
+    if (!input.is_cuda()) continue;
+    int64_t index = input.device().index();
+    if (std::find(devices.begin(), devices.end(), index) != devices.end()) continue;
+    auto gen = at::globalContext().defaultGenerator(input.device());
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    devices.push_back(index);
+    cuda_states.push_back(gen.get_state());
+  }
+}
+
+void set_rng_states(const torch::Tensor &cpu_state, const std::vector<int64_t> &devices, const std::vector<torch::Tensor> &cuda_states) {
+  {
+    auto gen = at::detail::getDefaultCPUGenerator();
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    gen.set_state(cpu_state);
+  }
+  for (size_t i = 0; i < devices.size(); ++i) {
+    auto gen = at::globalContext().defaultGenerator(c10::Device(c10::kCUDA, devices[i]));
+    std::lock_guard<std::mutex> lock(gen.mutex());
+    gen.set_state(cuda_states[i]);
+  }
+}
+
+struct CheckpointFunction : public torch::autograd::Function<CheckpointFunction> {
+  // dummy requires grad so that the output is tracked by autograd even if none
+  // of the inputs require grad, e.g. for the parameters used by the closure.
+  static torch::autograd::variable_list forward(
+      torch::autograd::AutogradContext *ctx,
+      torch::autograd::variable_list inputs,
+      torch::Tensor dummy,
+      c10::intrusive_ptr<CheckpointClosure> closure) {
+    torch::Tensor cpu_state;
+    std::vector<int64_t> devices;
+    std::vector<torch::Tensor> cuda_states;
+    get_rng_states(inputs, cpu_state, devices, cuda_states);
+    ctx->saved_data["closure"] = c10::IValue::make_capsule(closure);
+    ctx->saved_data["cpu_state"] = cpu_state;
+    ctx->saved_data["cuda_states"] = cuda_states;
+    ctx->save_for_backward(inputs);
+    return {closure->call(inputs)};
+  }
+
+  static torch::autograd::variable_list backward(
+      torch::autograd::AutogradContext *ctx,
+      torch::autograd::variable_list grad_outputs) {
+    auto closure = c10::static_intrusive_pointer_cast<CheckpointClosure>(ctx->saved_data["closure"].toCapsule());
+    auto inputs = ctx->get_saved_variables();
+    std::vector<torch::Tensor> detached;
+    for (auto &input : inputs) {
+      detached.push_back(input.detach().requires_grad_(input.requires_grad()));
+    }
+    // Replay the forward pass with the stashed random states, restoring the
+    // current states afterwards.
+    torch::Tensor cpu_state;
+    std::vector<int64_t> devices;
+    std::vector<torch::Tensor> cuda_states;
+    get_rng_states(inputs, cpu_state, devices, cuda_states);
+    set_rng_states(
+      ctx->saved_data["cpu_state"].toTensor(),
+      devices,
+      ctx->saved_data["cuda_states"].toTensorVector());
+    torch::Tensor output;
+    try {
+      torch::AutoGradMode enable_grad(true);
+      output = closure->call(detached);
+    } catch (...) {
+      set_rng_states(cpu_state, devices, cuda_states);
+      throw;
+    }
+    set_rng_states(cpu_state, devices, cuda_states);
+    if (output.requires_grad()) {
+      torch::autograd::backward({output}, {grad_outputs[0]});
+    }
+    torch::autograd::variable_list grads;
+    for (auto &input : detached) grads.push_back(input.grad());
+    // No gradients for the dummy tensor and the closure.
+    grads.push_back(torch::Tensor());
+    grads.push_back(torch::Tensor());
+    return grads;
+  }
+};
+}
+
+tensor at_checkpoint(tensor *inputs, int ninputs, void *data, tensor (*f)(void *, tensor *, int), void (*free_data)(void *)) {
+  PROTECT(
+    auto closure = c10::make_intrusive<CheckpointClosure>(data, f, free_data);
+    auto inputs_ = of_carray_tensor(inputs, ninputs);
+    if (!torch::autograd::GradMode::is_enabled()) {
+      return new torch::Tensor(closure->call(inputs_));
+    }
+    auto dummy = torch::empty({0}, torch::requires_grad());
+    return new torch::Tensor(CheckpointFunction::apply(inputs_, dummy, closure)[0]);
+  )
+  return nullptr;
+}
+
+void atp_enable_profiler(int cpu, int cuda, int record_shapes, int profile_memory, int with_stack) {
+  PROTECT(
+    using torch::profiler::impl::ActivityType;
+    std::set<ActivityType> activities;
+    if (cpu) activities.insert(ActivityType::CPU);
+    if (cuda) activities.insert(ActivityType::CUDA);
+    torch::profiler::impl::ProfilerConfig config(
+      torch::profiler::impl::ProfilerState::KINETO, record_shapes, profile_memory, with_stack);
+    torch::autograd::profiler::prepareProfiler(config, activities);
+    torch::autograd::profiler::enableProfiler(config, activities);
+  )
+}
+
+profiler_result atp_disable_profiler() {
+  PROTECT(
+    return torch::autograd::profiler::disableProfiler().release();
+  )
+  return nullptr;
+}
+
+int64_t atp_result_num_events(profiler_result r) {
+  PROTECT(return r->events().size();)
+  return -1;
+}
+
+void atp_result_event(profiler_result r, int64_t index, char **name, int *is_cuda, uint64_t *start_us, uint64_t *duration_us, int64_t *nbytes) {
+  PROTECT(
+    const auto &event = r->events().at(index);
+    *name = strdup(event.name().c_str());
+    *is_cuda = event.deviceType() == c10::DeviceType::CUDA;
+    *start_us = event.startUs();
+    *duration_us = event.durationUs();
+    *nbytes = event.nBytes();
+  )
+}
+
+void atp_result_save(profiler_result r, char *path) {
+  PROTECT(r->save(std::string(path));)
+}
+
+void atp_result_free(profiler_result r) {
+  PROTECT(delete r;)
+}
+
+record_function atp_record_function_enter(char *name) {
+  PROTECT(
+    auto rf = new at::RecordFunction(at::RecordScope::USER_SCOPE);
+    if (rf->isActive()) {
+      rf->before(std::string(name));
+    }
+    return rf;
+  )
+  return nullptr;
+}
+
+void atp_record_function_exit(record_function rf) {
+  PROTECT(
+    rf->end();
+    delete rf;
+  )
+}
+
 tensor at_get(tensor t, int index) {
   PROTECT(return new torch::Tensor((*t)[index]);)
   return nullptr;
@@ -374,7 +819,11 @@ namespace {
 }
 
 void at_save(tensor t, char *filename) {
-  PROTECT(torch::save(*t, filename);)
+  PROTECT(
+    auto file = open_output_file(filename);
+    torch::save(*t, file);
+    close_output_file(file, filename);
+  )
 }
 
 void at_save_to_stream(tensor t, void *stream_ptr) {
@@ -391,7 +840,9 @@ void at_save_multi(tensor *tensors, char **tensor_names, int ntensors, char *fil
     torch::serialize::OutputArchive archive;
     for (int i = 0; i < ntensors; ++i)
       archive.write(std::string(tensor_names[i]), *(tensors[i]), /* buffer=*/ false);
-    archive.save_to(filename);
+    auto file = open_output_file(filename);
+    archive.save_to(file);
+    close_output_file(file, filename);
   )
 }
 
@@ -410,7 +861,8 @@ void at_save_multi_to_stream(tensor *tensors, char **tensor_names, int ntensors,
 void at_load_multi(tensor *tensors, char **tensor_names, int ntensors, char *filename) {
   PROTECT(
     torch::serialize::InputArchive archive;
-    archive.load_from(std::string(filename));
+    auto file = open_input_file(filename);
+    archive.load_from(file);
     vector<torch::Tensor> ts(ntensors);
     for (int i = 0; i < ntensors; ++i)
       archive.read(std::string(tensor_names[i]), ts[i]);
@@ -423,7 +875,8 @@ void at_load_multi(tensor *tensors, char **tensor_names, int ntensors, char *fil
 
 void at_loadz_callback(char *filename, void *data, void (*f)(void *, char *, tensor)) {
   PROTECT(
-    auto params = torch::jit::_load_parameters(filename);
+    auto file = open_input_file(filename);
+    auto params = torch::jit::_load_parameters(file);
     for (const auto &p : params) {
       f(data, (char*)p.first.c_str(), new torch::Tensor(p.second));
     }
@@ -432,7 +885,8 @@ void at_loadz_callback(char *filename, void *data, void (*f)(void *, char *, ten
 
 void at_loadz_callback_with_device(char *filename, void *data, void (*f)(void *, char *, tensor), int device_id) {
   PROTECT(
-    auto params = torch::jit::_load_parameters(filename, device_of_int(device_id));
+    auto file = open_input_file(filename);
+    auto params = torch::jit::_load_parameters(file, device_of_int(device_id));
     for (const auto &p : params) {
       f(data, (char*)p.first.c_str(), new torch::Tensor(p.second));
     }
@@ -441,7 +895,8 @@ void at_loadz_callback_with_device(char *filename, void *data, void (*f)(void *,
 
 void at_load_callback(char *filename, void *data, void (*f)(void *, char *, tensor)) {
   PROTECT(
-    auto module = torch::jit::load(filename);
+    auto file = open_input_file(filename);
+    auto module = torch::jit::load(file);
     for (const auto &p : module.named_parameters()) {
       auto v = p.value;
       f(data, (char*)p.name.c_str(), new torch::Tensor(v));
@@ -449,9 +904,19 @@ void at_load_callback(char *filename, void *data, void (*f)(void *, char *, tens
   )
 }
 
+void atm_named_buffers(module m, void *data, void (*f)(void *, char *, tensor)) {
+  PROTECT(
+    for (const auto &p : m->named_buffers()) {
+      auto v = p.value;
+      f(data, (char*)p.name.c_str(), new torch::Tensor(v));
+    }
+  )
+}
+
 void at_load_callback_with_device(char *filename, void *data, void (*f)(void *, char *, tensor), int device_id) {
   PROTECT(
-    auto module = torch::jit::load(filename, device_of_int(device_id));
+    auto file = open_input_file(filename);
+    auto module = torch::jit::load(file, device_of_int(device_id));
     for (const auto &p : module.named_parameters()) {
       auto v = p.value;
       f(data, (char*)p.name.c_str(), new torch::Tensor(v));
@@ -474,7 +939,8 @@ void at_load_multi_(tensor *tensors, char **tensor_names, int ntensors, char *fi
   PROTECT(
     torch::NoGradGuard no_grad;
     torch::serialize::InputArchive archive;
-    archive.load_from(std::string(filename));
+    auto file = open_input_file(filename);
+    archive.load_from(file);
     for (int i = 0; i < ntensors; ++i) {
       if (tensors[i]->device().type() == at::kCPU)
         archive.read(std::string(tensor_names[i]), *(tensors[i]));
@@ -490,7 +956,8 @@ void at_load_multi_(tensor *tensors, char **tensor_names, int ntensors, char *fi
 tensor at_load(char *filename) {
   PROTECT(
     torch::Tensor tensor;
-    torch::load(tensor, filename);
+    auto file = open_input_file(filename);
+    torch::load(tensor, file);
     return new torch::Tensor(tensor);
   )
   return nullptr;
@@ -516,7 +983,9 @@ tensor at_load_image(char *filename) {
     int w = -1;
     int h = -1;
     int c = -1;
-    void *data = stbi_load(filename, &w, &h, &c, 3);
+    auto file = open_input_file(filename);
+    std::vector<unsigned char> bytes((std::istreambuf_iterator<char>(file)), std::istreambuf_iterator<char>());
+    void *data = stbi_load_from_memory(bytes.data(), (int)bytes.size(), &w, &h, &c, 3);
     if (data == nullptr)
       throw std::invalid_argument(stbi_failure_reason());
     torch::Tensor tensor = torch::zeros({ h, w, 3 }, at::ScalarType::Byte);
@@ -564,17 +1033,62 @@ int at_save_image(tensor tensor, char *filename) {
     int c = sizes[2];
     auto tmp_tensor = tensor->contiguous();
     void *tensor_data = tmp_tensor.data_ptr();
+    auto file = open_output_file(filename);
+    auto write = [](void *context, void *data, int size) {
+      static_cast<std::ofstream*>(context)->write(static_cast<char*>(data), size);
+    };
+    int res;
     if (ends_with(filename, ".jpg"))
-      return stbi_write_jpg(filename, w, h, c, tensor_data, 90);
-    if (ends_with(filename, ".bmp"))
-      return stbi_write_bmp(filename, w, h, c, tensor_data);
-    if (ends_with(filename, ".tga"))
-      return stbi_write_tga(filename, w, h, c, tensor_data);
-    return stbi_write_png(filename, w, h, c, tensor_data, 0);
+      res = stbi_write_jpg_to_func(write, &file, w, h, c, tensor_data, 90);
+    else if (ends_with(filename, ".bmp"))
+      res = stbi_write_bmp_to_func(write, &file, w, h, c, tensor_data);
+    else if (ends_with(filename, ".tga"))
+      res = stbi_write_tga_to_func(write, &file, w, h, c, tensor_data);
+    else
+      res = stbi_write_png_to_func(write, &file, w, h, c, tensor_data, 0);
+    close_output_file(file, filename);
+    return res;
   )
   return -1;
 }
 
+unsigned char *at_encode_image(tensor tensor, char *format, int quality, size_t *len) {
+  PROTECT(
+    auto sizes = tensor->sizes();
+    if (tensor->device().type() != at::kCPU)
+      throw std::invalid_argument("the input tensor has to be on cpu");
+    if (tensor->scalar_type() != at::ScalarType::Byte)
+      throw std::invalid_argument("the input tensor has to use bytes");
+    if (sizes.size() != 3)
+      throw std::invalid_argument("invalid number of dimensions, should be 3");
+    int h = sizes[0];
+    int w = sizes[1];
+    int c = sizes[2];
+    auto tmp_tensor = tensor->contiguous();
+    void *tensor_data = tmp_tensor.data_ptr();
+    std::vector<unsigned char> bytes;
+    auto write = [](void *context, void *data, int size) {
+      auto bytes = static_cast<std::vector<unsigned char>*>(context);
+      auto begin = static_cast<unsigned char*>(data);
+      bytes->insert(bytes->end(), begin, begin + size);
+    };
+    int res;
+    if (strcmp(format, "jpg") == 0)
+      res = stbi_write_jpg_to_func(write, &bytes, w, h, c, tensor_data, quality);
+    else if (strcmp(format, "png") == 0)
+      res = stbi_write_png_to_func(write, &bytes, w, h, c, tensor_data, 0);
+    else
+      throw std::invalid_argument(std::string("unsupported image format ") + format);
+    if (res == 0)
+      throw std::invalid_argument(std::string("cannot encode the image as ") + format);
+    unsigned char *data = (unsigned char*)malloc(bytes.size());
+    memcpy(data, bytes.data(), bytes.size());
+    *len = bytes.size();
+    return data;
+  )
+  return nullptr;
+}
+
 int at_get_num_interop_threads() {
   PROTECT(return at::get_num_interop_threads();)
   return -1;
@@ -634,7 +1148,7 @@ tensor at_resize_image(tensor tensor, int out_w, int out_h) {
 }
 
 void at_free(tensor t) {
-  delete(t);
+  PROTECT(delete(t);)
 }
 
 void at_run_backward(tensor *tensors,
@@ -667,6 +1181,52 @@ void at_run_backward(tensor *tensors,
   )
 }
 
+void at_autograd_grad(tensor *outputs,
+                      int noutputs,
+                      tensor *inputs,
+                      int ninputs,
+                      tensor *grad_outputs,
+                      int ngrad_outputs,
+                      tensor *results,
+                      int keep_graph,
+                      int create_graph,
+                      int allow_unused) {
+  PROTECT(
+    vector<torch::Tensor> outputs_;
+    for (int i = 0; i < noutputs; ++i)
+      outputs_.push_back(*outputs[i]);
+    vector<torch::Tensor> inputs_;
+    for (int i = 0; i < ninputs; ++i)
+      inputs_.push_back(*inputs[i]);
+    // Null grad outputs are left undefined, the engine then uses ones for
+    // scalar outputs.
+    vector<torch::Tensor> grad_outputs_;
+    for (int i = 0; i < ngrad_outputs; ++i)
+      grad_outputs_.push_back(grad_outputs[i] == nullptr ? torch::Tensor() : *grad_outputs[i]);
+
+    auto vl = torch::autograd::grad(outputs_, inputs_, grad_outputs_, keep_graph, create_graph, allow_unused);
+    for (int i = 0; i < ninputs; ++i) {
+      results[i] = vl[i].defined() ? new torch::Tensor(vl[i]) : nullptr;
+    }
+  )
+}
+
+void at_autograd_backward(tensor *tensors,
+                          int ntensors,
+                          tensor *grad_tensors,
+                          int keep_graph,
+                          int create_graph) {
+  PROTECT(
+    vector<torch::Tensor> tensors_;
+    vector<torch::Tensor> grad_tensors_;
+    for (int i = 0; i < ntensors; ++i) {
+      tensors_.push_back(*tensors[i]);
+      grad_tensors_.push_back(*grad_tensors[i]);
+    }
+    torch::autograd::backward(tensors_, grad_tensors_, keep_graph, create_graph);
+  )
+}
+
 optimizer ato_adam(double learning_rate,
                    double beta1,
                    double beta2,
@@ -739,6 +1299,23 @@ optimizer ato_sgd(double learning_rate,
   return nullptr;
 }
 
+optimizer ato_adagrad(double learning_rate,
+                      double lr_decay,
+                      double weight_decay,
+                      double initial_accumulator_value,
+                      double eps) {
+  PROTECT(
+    auto options =
+      torch::optim::AdagradOptions(learning_rate)
+      .lr_decay(lr_decay)
+      .weight_decay(weight_decay)
+      .initial_accumulator_value(initial_accumulator_value)
+      .eps(eps);
+    return new torch::optim::Adagrad(vector<torch::Tensor>(), options);
+  )
+  return nullptr;
+}
+
 void ato_add_parameters(optimizer t, tensor tensor, size_t group) {
   PROTECT(
     auto &groups = t->param_groups();
@@ -770,6 +1347,7 @@ void ato_set_learning_rate(optimizer t, double learning_rate) {
     set_lr<torch::optim::AdamWOptions>(t, learning_rate);
     set_lr<torch::optim::RMSpropOptions>(t, learning_rate);
     set_lr<torch::optim::SGDOptions>(t, learning_rate);
+    set_lr<torch::optim::AdagradOptions>(t, learning_rate);
   )
 }
 
@@ -788,6 +1366,7 @@ void ato_set_learning_rate_group(optimizer t, size_t group, double learning_rate
     set_lr_group<torch::optim::AdamWOptions>(t, group, learning_rate);
     set_lr_group<torch::optim::RMSpropOptions>(t, group, learning_rate);
     set_lr_group<torch::optim::SGDOptions>(t, group, learning_rate);
+    set_lr_group<torch::optim::AdagradOptions>(t, group, learning_rate);
   )
 }
 
@@ -857,7 +1436,7 @@ void ato_set_momentum_group(optimizer t, size_t group, double momentum) {
     else if (auto rms = dynamic_cast<torch::optim::RMSpropOptions*>(d)) {
         rms->momentum(momentum);
     }
-    if (auto sgd = dynamic_cast<torch::optim::SGDOptions*>(d)) {
+    else if (auto sgd = dynamic_cast<torch::optim::SGDOptions*>(d)) {
         sgd->momentum(momentum);
     }
     else
@@ -886,6 +1465,7 @@ void ato_set_weight_decay(optimizer t, double weight_decay) {
     set_weight_decay<torch::optim::AdamWOptions>(t, weight_decay);
     set_weight_decay<torch::optim::RMSpropOptions>(t, weight_decay);
     set_weight_decay<torch::optim::SGDOptions>(t, weight_decay);
+    set_weight_decay<torch::optim::AdagradOptions>(t, weight_decay);
   )
 }
 
@@ -904,19 +1484,35 @@ void ato_set_weight_decay_group(optimizer t, size_t group, double weight_decay)
     set_weight_decay_group<torch::optim::AdamWOptions>(t, group, weight_decay);
     set_weight_decay_group<torch::optim::RMSpropOptions>(t, group, weight_decay);
     set_weight_decay_group<torch::optim::SGDOptions>(t, group, weight_decay);
+    set_weight_decay_group<torch::optim::AdagradOptions>(t, group, weight_decay);
   )
 }
 
-void ato_zero_grad(optimizer t) {
-  PROTECT(t->zero_grad();)
+void ato_zero_grad(optimizer t, int set_to_none) {
+  PROTECT(t->zero_grad(set_to_none);)
 }
 
 void ato_step(optimizer t) {
   PROTECT(t->step();)
 }
 
+void ato_save(optimizer t, char *filename) {
+  PROTECT(
+    auto file = open_output_file(filename);
+    torch::save(*t, file);
+    close_output_file(file, filename);
+  )
+}
+
+void ato_load(optimizer t, char *filename) {
+  PROTECT(
+    auto file = open_input_file(filename);
+    torch::load(*t, file);
+  )
+}
+
 void ato_free(optimizer t) {
-  delete(t);
+  PROTECT(delete(t);)
 }
 
 scalar ats_int(int64_t v) {
@@ -929,6 +1525,21 @@ scalar ats_float(double v) {
   return nullptr;
 }
 
+scalar ats_bool(int v) {
+  PROTECT(return new torch::Scalar((bool)v);)
+  return nullptr;
+}
+
+int ats_type(scalar s) {
+  PROTECT(return static_cast<int>(s->type());)
+  return -1;
+}
+
+int ats_to_bool(scalar s) {
+  PROTECT(return s->toBool();)
+  return -1;
+}
+
 int64_t ats_to_int(scalar s) {
   PROTECT(return s->toLong();)
   return -1;
@@ -950,7 +1561,7 @@ char *ats_to_string(scalar s) {
 }
 
 void ats_free(scalar s) {
-  delete(s);
+  PROTECT(delete(s);)
 }
 
 int atc_cuda_device_count() {
@@ -997,6 +1608,49 @@ void atc_set_benchmark_cudnn(int b) {
   )
 }
 
+int atc_benchmark_cudnn() {
+  PROTECT(return at::globalContext().benchmarkCuDNN();)
+  return -1;
+}
+
+int atc_deterministic_cudnn() {
+  PROTECT(return at::globalContext().deterministicCuDNN();)
+  return -1;
+}
+
+void atc_set_deterministic_cudnn(int b) {
+  PROTECT(
+  at::globalContext().setDeterministicCuDNN(b);
+  )
+}
+
+int64_t atc_cudnn_version() {
+  PROTECT(return at::detail::getCUDAHooks().versionCuDNN();)
+  return -1;
+}
+
+int atc_allow_tf32_cublas() {
+  PROTECT(return at::globalContext().allowTF32CuBLAS();)
+  return -1;
+}
+
+void atc_set_allow_tf32_cublas(int b) {
+  PROTECT(
+  at::globalContext().setAllowTF32CuBLAS(b);
+  )
+}
+
+int atc_allow_tf32_cudnn() {
+  PROTECT(return at::globalContext().allowTF32CuDNN();)
+  return -1;
+}
+
+void atc_set_allow_tf32_cudnn(int b) {
+  PROTECT(
+  at::globalContext().setAllowTF32CuDNN(b);
+  )
+}
+
 bool at_context_has_openmp() {
   PROTECT (
   return at::globalContext().hasOpenMP();
@@ -1067,6 +1721,16 @@ int64_t at_context_version_cudart() {
   return 0;
 }
 
+char *at_torch_version() {
+  PROTECT(return strdup(TORCH_VERSION);)
+  return nullptr;
+}
+
+char *at_show_config() {
+  PROTECT(return strdup(at::show_config().c_str());)
+  return nullptr;
+}
+
 bool at_context_has_cusolver() {
   PROTECT (
   return at::globalContext().hasCuSOLVER();
@@ -1109,6 +1773,14 @@ bool at_context_has_mps() {
   return 0;
 }
 
+void at_mps_synchronize() {
+  PROTECT(
+    if (!at::globalContext().hasMPS())
+      throw std::runtime_error("MPS backend is not available");
+    at::detail::getMPSHooks().deviceSynchronize();
+  )
+}
+
 bool at_context_has_ort() {
   PROTECT (
   return at::globalContext().hasORT();
@@ -1118,14 +1790,16 @@ bool at_context_has_ort() {
 
 module atm_load(char *filename) {
   PROTECT(
-    return new torch::jit::script::Module(torch::jit::load(filename));
+    auto file = open_input_file(filename);
+    return new torch::jit::script::Module(torch::jit::load(file));
   )
   return nullptr;
 }
 
 module atm_load_on_device(char *filename, int device) {
   PROTECT(
-    return new torch::jit::script::Module(torch::jit::load(filename, device_of_int(device)));
+    auto file = open_input_file(filename);
+    return new torch::jit::script::Module(torch::jit::load(file, device_of_int(device)));
   )
   return nullptr;
 }
@@ -1222,12 +1896,14 @@ void atm_train(module m) {
 }
 
 void atm_free(module m) {
-  delete(m);
+  PROTECT(delete(m);)
 }
 
 void atm_save(module m, char *filename) {
   PROTECT(
-    m->save(filename);
+    auto file = open_output_file(filename);
+    m->save(file);
+    close_output_file(file, filename);
   )
 }
 
@@ -1658,9 +2334,175 @@ ivalue ati_clone(ivalue i) {
 }
 
 void ati_free(ivalue i) {
-  delete(i);
+  PROTECT(delete(i);)
 }
 
 void at_set_graph_executor_optimize(bool o) {
-  torch::jit::setGraphExecutorOptimize(o);
+  PROTECT(torch::jit::setGraphExecutorOptimize(o);)
+}
+
+// Runs an operator implemented through a callback on the top of the stack.
+// The callback takes the ownership of the inputs and returns a single value,
+// a tuple when there are multiple outputs.
+static void run_op_callback(void *data, op_callback f, size_t nargs, size_t nreturns, torch::jit::Stack &stack) {
+  vector<ivalue> inputs;
+  for (auto &input : torch::jit::last(stack, nargs))
+    inputs.push_back(new torch::jit::IValue(input));
+  torch::jit::drop(stack, nargs);
+  char *err = nullptr;
+  ivalue output = f(data, inputs.data(), inputs.size(), &err);
+  if (output == nullptr) {
+    std::string msg = err == nullptr ? "custom operator failed" : err;
+    free(err);
+    throw std::runtime_error(msg);
+  }
+  torch::jit::IValue result = std::move(*output);
+  delete output;
+  if (nreturns == 1) {
+    stack.push_back(std::move(result));
+  } else if (nreturns > 1) {
+    for (auto &elem : result.toTupleRef().elements())
+      stack.push_back(elem);
+  }
+}
+
+void at_register_op(const char *schema, void *data, op_callback f, void (*free_data)(void *)) {
+  // The data is owned from the start so that it gets freed on errors.
+  auto data_ = std::shared_ptr<void>(data, free_data);
+  PROTECT(
+    auto parsed = torch::jit::parseSchema(schema);
+    auto nargs = parsed.arguments().size();
+    auto nreturns = parsed.returns().size();
+    auto op = [data_, f, nargs, nreturns](torch::jit::Stack &stack) {
+      run_op_callback(data_.get(), f, nargs, nreturns, stack);
+    };
+    // Operators registered this way are never unregistered.
+    torch::jit::RegisterOperators registration(
+      {torch::jit::Operator(schema, op, c10::AliasAnalysisKind::FROM_SCHEMA)});
+  )
+}
+
+struct CallbackKernel : public c10::OperatorKernel {
+  std::shared_ptr<void> data;
+  op_callback f;
+  CallbackKernel(std::shared_ptr<void> data, op_callback f) : data(data), f(f) {}
+  void operator()(const c10::OperatorHandle &op, c10::DispatchKeySet, torch::jit::Stack *stack) {
+    run_op_callback(data.get(), f, op.schema().arguments().size(), op.schema().returns().size(), *stack);
+  }
+};
+
+// The backward callback gets the list of gradients for the tensor outputs and
+// a tuple with the inputs of the forward pass, it returns a list with one
+// gradient or None per tensor input.
+struct CallbackBackward : public torch::autograd::Node {
+  std::shared_ptr<void> data;
+  op_callback f;
+  std::vector<torch::jit::IValue> inputs;
+  CallbackBackward(std::shared_ptr<void> data, op_callback f, std::vector<torch::jit::IValue> inputs)
+    : data(data), f(f), inputs(std::move(inputs)) {}
+  torch::autograd::variable_list apply(torch::autograd::variable_list &&grads) override {
+    for (size_t i = 0; i < grads.size(); ++i)
+      if (!grads[i].defined()) grads[i] = input_metadata(i).zeros_like();
+    torch::jit::Stack stack;
+    stack.push_back(c10::List<at::Tensor>(grads));
+    stack.push_back(c10::ivalue::Tuple::create(inputs));
+    run_op_callback(data.get(), f, 2, 1, stack);
+    auto result = stack.back().toList();
+    if (result.size() != num_outputs())
+      throw std::runtime_error(
+        "backward returned " + std::to_string(result.size()) + " gradients, expected " + std::to_string(num_outputs()));
+    torch::autograd::variable_list grad_inputs;
+    for (size_t i = 0; i < result.size(); ++i) {
+      torch::jit::IValue grad = result.get(i);
+      grad_inputs.push_back(grad.isNone() ? at::Tensor() : grad.toTensor());
+    }
+    return grad_inputs;
+  }
+  void release_variables() override { inputs.clear(); }
+};
+
+struct CallbackAutogradKernel : public c10::OperatorKernel {
+  std::shared_ptr<void> data;
+  op_callback f;
+  CallbackAutogradKernel(std::shared_ptr<void> data, op_callback f) : data(data), f(f) {}
+  void operator()(const c10::OperatorHandle &op, c10::DispatchKeySet ks, torch::jit::Stack *stack) {
+    auto nargs = op.schema().arguments().size();
+    auto nreturns = op.schema().returns().size();
+    auto args = torch::jit::last(*stack, nargs);
+    std::vector<torch::jit::IValue> inputs(args.begin(), args.end());
+    torch::autograd::variable_list tensor_inputs;
+    for (auto &input : inputs)
+      if (input.isTensor()) tensor_inputs.push_back(input.toTensor());
+    {
+      at::AutoDispatchBelowADInplaceOrView guard;
+      op.redispatchBoxed(ks & c10::after_autograd_keyset, stack);
+    }
+    bool requires_grad = torch::autograd::GradMode::is_enabled() && std::any_of(
+      tensor_inputs.begin(), tensor_inputs.end(),
+      [](const at::Tensor &t) { return t.defined() && t.requires_grad(); });
+    if (!requires_grad) return;
+    auto node = std::shared_ptr<CallbackBackward>(
+      new CallbackBackward(data, f, std::move(inputs)), torch::autograd::deleteNode);
+    node->set_next_edges(torch::autograd::collect_next_edges(tensor_inputs));
+    std::vector<at::Tensor> outputs;
+    for (auto &output : torch::jit::last(*stack, nreturns))
+      if (output.isTensor()) outputs.push_back(output.toTensor());
+    torch::autograd::set_history(outputs, node);
+  }
+};
+
+void at_register_kernel(const char *schema, int dispatch_key, void *data, op_callback f, void (*free_data)(void *)) {
+  // The data is owned from the start so that it gets freed on errors.
+  auto data_ = std::shared_ptr<void>(data, free_data);
+  PROTECT(
+    auto parsed = torch::jit::parseSchema(schema);
+    auto name = parsed.name();
+    auto sep = name.find("::");
+    if (sep == std::string::npos)
+      throw std::invalid_argument("the operator name has no namespace " + name);
+    auto ns = name.substr(0, sep);
+    c10::DispatchKey key;
+    switch (dispatch_key) {
+      case TCH_DISPATCH_KEY_CPU: key = c10::DispatchKey::CPU; break;
+      case TCH_DISPATCH_KEY_CUDA: key = c10::DispatchKey::CUDA; break;
+      case TCH_DISPATCH_KEY_COMPOSITE_IMPLICIT_AUTOGRAD: key = c10::DispatchKey::CompositeImplicitAutograd; break;
+      case TCH_DISPATCH_KEY_AUTOGRAD: key = c10::DispatchKey::Autograd; break;
+      default: throw std::invalid_argument("unknown dispatch key " + std::to_string(dispatch_key));
+    }
+    // The libraries are leaked on purpose as the registrations are undone
+    // when they get dropped.
+    if (!c10::Dispatcher::singleton().findSchema({name, parsed.overload_name()}).has_value()) {
+      auto def = new torch::Library(torch::Library::FRAGMENT, ns, c10::nullopt, __FILE__, __LINE__);
+      def->def(schema);
+    }
+    auto impl = new torch::Library(torch::Library::IMPL, ns, key, __FILE__, __LINE__);
+    auto op_name = name + (parsed.overload_name().empty() ? "" : "." + parsed.overload_name());
+    if (key == c10::DispatchKey::Autograd)
+      impl->impl(op_name.c_str(), torch::CppFunction::makeFromBoxedFunctor(
+        std::make_unique<CallbackAutogradKernel>(data_, f)));
+    else
+      impl->impl(op_name.c_str(), torch::CppFunction::makeFromBoxedFunctor(
+        std::make_unique<CallbackKernel>(data_, f)));
+  )
+}
+
+ivalue at_call_op(const char *name, ivalue *inputs, int ninputs) {
+  PROTECT(
+    auto symbol = c10::Symbol::fromQualString(name);
+    auto ops = torch::jit::getAllOperatorsFor(symbol);
+    if (ops.empty())
+      throw std::invalid_argument(std::string("unknown operator ") + name);
+    auto &op = ops.front();
+    if (op->schema().arguments().size() != (size_t)ninputs)
+      throw std::invalid_argument(
+        std::string("unexpected number of inputs for ") + name + ", got " + std::to_string(ninputs));
+    torch::jit::Stack stack;
+    for (int i = 0; i < ninputs; ++i)
+      stack.push_back(*inputs[i]);
+    op->getOperation()(stack);
+    if (stack.size() == 0) return new torch::jit::IValue();
+    if (stack.size() == 1) return new torch::jit::IValue(stack[0]);
+    return new torch::jit::IValue(c10::ivalue::Tuple::create(stack));
+  )
+  return nullptr;
 }
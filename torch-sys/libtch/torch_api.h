@@ -4,6 +4,8 @@
 
 #ifdef __cplusplus
 #include<torch/torch.h>
+#include<torch/csrc/autograd/profiler_kineto.h>
+#include<ATen/record_function.h>
 #include<stdexcept>
 using namespace std;
 extern thread_local char *torch_last_err;
@@ -14,11 +16,18 @@ typedef torch::Scalar *scalar;
 typedef torch::optim::Optimizer *optimizer;
 typedef torch::jit::script::Module *module;
 typedef torch::jit::IValue *ivalue;
+typedef torch::autograd::profiler::ProfilerResult *profiler_result;
+typedef at::RecordFunction *record_function;
+typedef c10::InferenceMode *inference_mode;
+// Exceptions must not propagate through the C api, this includes c10::Error
+// and std::bad_alloc which derive from std::exception.
 #define PROTECT(x) \
   try { \
     x \
   } catch (const exception& e) { \
       torch_last_err = strdup(e.what()); \
+  } catch (...) { \
+      torch_last_err = strdup("unknown C++ exception"); \
   }
 #else
 typedef void *tensor;
@@ -26,17 +35,28 @@ typedef void *optimizer;
 typedef void *scalar;
 typedef void *module;
 typedef void *ivalue;
+typedef void *profiler_result;
+typedef void *record_function;
+typedef void *inference_mode;
 #endif
 
 char *get_and_reset_last_err(); // thread-local
 void at_manual_seed(int64_t);
+tensor at_get_rng_state(int device);
+void at_set_rng_state(int device, tensor state);
+void at_set_deterministic_algorithms(int b, int warn_only);
+int at_deterministic_algorithms();
+int at_deterministic_algorithms_warn_only();
 tensor at_new_tensor();
 tensor at_tensor_of_blob(void *data, int64_t *dims, size_t ndims, int64_t *strides, size_t nstrides, int type, int device);
+tensor at_tensor_of_blob_with_deleter(void *data, int64_t *dims, size_t ndims, int64_t *strides, size_t nstrides, int type, int device, void (*deleter)(void *), void *deleter_ctx);
 tensor at_tensor_of_data(void *vs, int64_t *dims, size_t ndims, size_t element_size_in_bytes, int type);
 void at_copy_data(tensor tensor, void *vs, size_t numel, size_t element_size_in_bytes);
 tensor at_shallow_clone(tensor);
 
 void *at_data_ptr(tensor);
+// Returns the TensorImpl of the tensor, shared by its shallow clones.
+void *at_tensor_impl(tensor);
 int at_defined(tensor);
 int at_is_mkldnn(tensor);
 int at_is_sparse(tensor);
@@ -54,10 +74,44 @@ int at_autocast_decrement_nesting();
 int at_autocast_increment_nesting();
 bool at_autocast_is_enabled();
 bool at_autocast_set_enabled(bool b);
+bool at_autocast_is_cpu_enabled();
+bool at_autocast_set_cpu_enabled(bool b);
+int at_autocast_set_dtype(bool cpu, int dtype);
 
 void at_backward(tensor, int, int);
+void at_backward_opt(tensor, tensor *inputs, int ninputs, int keep_graph, int create_graph);
+tensor at_set_requires_grad(tensor, int requires_grad);
+void at_retain_grad(tensor);
+void at_set_grad(tensor, tensor grad);
 int at_requires_grad(tensor);
 int at_grad_set_enabled(int);
+int at_grad_is_enabled();
+void at_set_anomaly_mode(int enabled, int check_nan);
+int at_anomaly_mode_is_enabled();
+int at_anomaly_mode_should_check_nan();
+typedef void (*warning_callback)(const char *msg, const char *file, const char *function, uint32_t line, int warn_always);
+// Installs the warning handler on the current thread, the previous handler is returned.
+void *at_warning_handler_install(warning_callback);
+void at_warning_handler_restore(void *);
+void at_set_warn_always(int enabled);
+int at_get_warn_always();
+inference_mode at_inference_mode_enter(int enabled);
+void at_inference_mode_exit(inference_mode);
+int at_inference_mode_is_enabled();
+// Runs f on the inputs without recording the intermediate activations, f is run
+// again with gradient tracking during the backward pass. f returns a new
+// tensor or null on failure, free_data is called once f is not needed anymore.
+tensor at_checkpoint(tensor *inputs, int ninputs, void *data, tensor (*f)(void *, tensor *, int), void (*free_data)(void *));
+
+void atp_enable_profiler(int cpu, int cuda, int record_shapes, int profile_memory, int with_stack);
+profiler_result atp_disable_profiler();
+int64_t atp_result_num_events(profiler_result);
+// The name is allocated with strdup and has to be freed by the caller.
+void atp_result_event(profiler_result, int64_t index, char **name, int *is_cuda, uint64_t *start_us, uint64_t *duration_us, int64_t *nbytes);
+void atp_result_save(profiler_result, char *path);
+void atp_result_free(profiler_result);
+record_function atp_record_function_enter(char *name);
+void atp_record_function_exit(record_function);
 
 tensor at_get(tensor, int index);
 void at_fill_double(tensor, double);
@@ -79,6 +133,9 @@ tensor at_load_from_stream(void *stream_ptr);
 tensor at_load_image(char *filename);
 tensor at_load_image_from_memory(unsigned char *img_data, size_t img_size);
 int at_save_image(tensor, char *filename);
+// Encodes an image in the jpg or png format, the returned data is allocated
+// with malloc and has to be freed by the caller.
+unsigned char *at_encode_image(tensor, char *format, int quality, size_t *len);
 tensor at_resize_image(tensor, int w, int h);
 
 void at_save_multi(tensor *tensors, char **tensor_names, int ntensors, char *filename);
@@ -114,6 +171,23 @@ void at_run_backward(tensor *tensors,
                       int keep_graph,
                       int create_graph);
 
+void at_autograd_grad(tensor *outputs,
+                      int noutputs,
+                      tensor *inputs,
+                      int ninputs,
+                      tensor *grad_outputs,
+                      int ngrad_outputs,
+                      tensor *results,
+                      int keep_graph,
+                      int create_graph,
+                      int allow_unused);
+
+void at_autograd_backward(tensor *tensors,
+                          int ntensors,
+                          tensor *grad_tensors,
+                          int keep_graph,
+                          int create_graph);
+
 optimizer ato_adam(double learning_rate,
                    double beta1,
                    double beta2,
@@ -137,6 +211,11 @@ optimizer ato_sgd(double learning_rate,
                   double dampening,
                   double weight_decay,
                   int nesterov);
+optimizer ato_adagrad(double learning_rate,
+                      double lr_decay,
+                      double weight_decay,
+                      double initial_accumulator_value,
+                      double eps);
 void ato_add_parameters(optimizer, tensor, size_t group);
 void ato_set_learning_rate(optimizer, double learning_rate);
 void ato_set_momentum(optimizer, double momentum);
@@ -144,12 +223,19 @@ void ato_set_learning_rate_group(optimizer, size_t group, double learning_rate);
 void ato_set_momentum_group(optimizer, size_t group, double momentum);
 void ato_set_weight_decay(optimizer t, double weight_decay);
 void ato_set_weight_decay_group(optimizer t, size_t group, double weight_decay);
-void ato_zero_grad(optimizer);
+void ato_zero_grad(optimizer, int set_to_none);
 void ato_step(optimizer);
+// Saves and restores the optimizer state, e.g. the Adam moments, as well as
+// the options of the parameter groups.
+void ato_save(optimizer, char *filename);
+void ato_load(optimizer, char *filename);
 void ato_free(optimizer);
 
 scalar ats_int(int64_t);
 scalar ats_float(double);
+scalar ats_bool(int);
+int ats_type(scalar);
+int ats_to_bool(scalar);
 int64_t ats_to_int(scalar);
 double ats_to_float(scalar);
 char *ats_to_string(scalar);
@@ -165,12 +251,15 @@ bool at_context_has_cudart();
 bool at_context_has_cudnn();
 int64_t at_context_version_cudnn();
 int64_t at_context_version_cudart();
+char *at_torch_version();
+char *at_show_config();
 bool at_context_has_cusolver();
 bool at_context_has_hip();
 bool at_context_has_ipu();
 bool at_context_has_xla();
 bool at_context_has_lazy();
 bool at_context_has_mps();
+void at_mps_synchronize();
 bool at_context_has_ort();
 
 
@@ -196,6 +285,67 @@ void atc_synchronize(int64_t device_index);
 int atc_user_enabled_cudnn();
 void atc_set_user_enabled_cudnn(int b);
 void atc_set_benchmark_cudnn(int b);
+int atc_benchmark_cudnn();
+int atc_deterministic_cudnn();
+void atc_set_deterministic_cudnn(int b);
+int64_t atc_cudnn_version();
+int atc_allow_tf32_cublas();
+void atc_set_allow_tf32_cublas(int b);
+int atc_allow_tf32_cudnn();
+void atc_set_allow_tf32_cudnn(int b);
+
+// The CUDA specific types are opaque here so that this header can be used
+// without the CUDA headers.
+typedef void *cuda_stream;
+typedef void *cuda_event;
+typedef void *cuda_graph;
+
+int atc_built_with_cuda();
+cuda_stream atcs_new(int device, int high_priority);
+cuda_stream atcs_default(int device);
+cuda_stream atcs_current(int device);
+void atcs_set_current(cuda_stream);
+void atcs_synchronize(cuda_stream);
+int atcs_query(cuda_stream);
+int64_t atcs_id(cuda_stream);
+int atcs_device(cuda_stream);
+void atcs_wait_event(cuda_stream, cuda_event);
+// Marks the memory of the tensor as used by the stream so that the caching
+// allocator does not reuse it before the work queued on the stream completes.
+void atcs_record_stream(tensor, cuda_stream);
+void atcs_free(cuda_stream);
+
+cuda_event atce_new(int enable_timing);
+void atce_record(cuda_event, cuda_stream);
+void atce_synchronize(cuda_event);
+int atce_query(cuda_event);
+float atce_elapsed_time(cuda_event start, cuda_event end);
+
+// Fills [stats] with the caching allocator statistics, see the Rust
+// MemoryStats struct for the layout.
+void atc_memory_stats(int device, int64_t *stats);
+void atc_reset_peak_memory_stats(int device);
+void atc_empty_cache();
+void atc_set_memory_fraction(double fraction, int device);
+// Uses the same format as the PYTORCH_CUDA_ALLOC_CONF environment variable.
+void atc_set_allocator_settings(char *settings);
+
+// [name] is allocated with malloc and has to be freed by the caller.
+void atc_device_properties(int device, char **name, int64_t *total_memory, int *major, int *minor, int *multi_processor_count);
+int atc_current_device();
+void atc_set_device(int device);
+void atce_free(cuda_event);
+
+cuda_graph atcg_new();
+// Memory pools are identified by a pair of ids, (0, 0) stands for a new pool
+// private to the graph.
+void atcg_capture_begin(cuda_graph, uint64_t pool_id0, uint64_t pool_id1);
+void atcg_capture_end(cuda_graph);
+void atcg_replay(cuda_graph);
+void atcg_reset(cuda_graph);
+void atcg_pool(cuda_graph, uint64_t *pool_id0, uint64_t *pool_id1);
+void atcg_free(cuda_graph);
+void atcg_graph_pool_handle(uint64_t *pool_id0, uint64_t *pool_id1);
 
 module atm_load(char *);
 module atm_load_on_device(char *, int device);
@@ -227,6 +377,7 @@ void atm_set_profiling_mode(int);
 void atm_fuser_cuda_set_enabled(bool);
 bool atm_fuser_cuda_is_enabled();
 void atm_named_parameters(module, void *data, void (*f)(void *, char *, tensor));
+void atm_named_buffers(module, void *data, void (*f)(void *, char *, tensor));
 
 // This function has to be followed by a call to atm_end_tracing.
 module atm_create_for_tracing(char *modl_name, tensor *inputs, int ninputs);
@@ -276,6 +427,23 @@ void ati_free(ivalue);
 /// Enables or disables the graph executor optimizer for the current thread.
 void at_set_graph_executor_optimize(bool);
 
+// The callback returns nullptr on errors and sets the error message which is
+// then freed using free.
+typedef ivalue (*op_callback)(void *data, ivalue *inputs, int ninputs, char **err);
+void at_register_op(const char *schema, void *data, op_callback f, void (*free_data)(void *));
+ivalue at_call_op(const char *name, ivalue *inputs, int ninputs);
+// The values of the dispatch_key argument of at_register_kernel, these are
+// mirrored by the DispatchKey enum in src/wrappers/ops.rs.
+enum {
+  TCH_DISPATCH_KEY_CPU = 0,
+  TCH_DISPATCH_KEY_CUDA = 1,
+  TCH_DISPATCH_KEY_COMPOSITE_IMPLICIT_AUTOGRAD = 2,
+  TCH_DISPATCH_KEY_AUTOGRAD = 3,
+};
+// Registers a kernel in the dispatcher, the operator is defined first if
+// needed. The Autograd callback is used for the backward pass.
+void at_register_kernel(const char *schema, int dispatch_key, void *data, op_callback f, void (*free_data)(void *));
+
 // for internal use
 bool tch_write_stream_destructor(void *stream_ptr);
 bool tch_write_stream_write(void *stream_ptr, const uint8_t *buf, size_t size, size_t *out_size);
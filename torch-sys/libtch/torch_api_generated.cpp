@@ -14328,13 +14328,6 @@ void atg_set_out(tensor *out__, tensor out, tensor self) {
   )
 }
 
-void atg_set_requires_grad(tensor *out__, tensor self, int r) {
-  PROTECT(
-    auto outputs__ = self->set_requires_grad((bool)r);
-    out__[0] = new torch::Tensor(outputs__);
-  )
-}
-
 void atg_set_source_tensor(tensor *out__, tensor self, tensor source) {
   PROTECT(
     auto outputs__ = torch::set(*self, *source);
@@ -1989,7 +1989,6 @@ void atg_set(tensor *, tensor self);
 void atg_set_(tensor *, tensor self);
 void atg_set_data(tensor self, tensor new_data);
 void atg_set_out(tensor *, tensor out, tensor self);
-void atg_set_requires_grad(tensor *, tensor self, int r);
 void atg_set_source_tensor(tensor *, tensor self, tensor source);
 void atg_set_source_tensor_(tensor *, tensor self, tensor source);
 void atg_set_source_tensor_out(tensor *, tensor out, tensor self, tensor source);
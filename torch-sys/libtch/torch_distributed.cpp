@@ -0,0 +1,218 @@
+#include<torch/csrc/distributed/c10d/FileStore.hpp>
+#include<torch/csrc/distributed/c10d/TCPStore.hpp>
+#include<torch/csrc/distributed/c10d/ProcessGroupGloo.hpp>
+#ifdef USE_C10D_NCCL
+#include<torch/csrc/distributed/c10d/ProcessGroupNCCL.hpp>
+#endif
+#include<torch/csrc/autograd/functions/utils.h>
+#include<cstdlib>
+#include<functional>
+#include "torch_distributed.h"
+
+static c10d::ReduceOp reduce_op(int op) {
+  switch (op) {
+    case 0: return c10d::ReduceOp::SUM;
+    case 1: return c10d::ReduceOp::PRODUCT;
+    case 2: return c10d::ReduceOp::MIN;
+    case 3: return c10d::ReduceOp::MAX;
+    case 4: return c10d::ReduceOp::AVG;
+  }
+  throw std::invalid_argument("unknown reduce op " + std::to_string(op));
+}
+
+static std::vector<at::Tensor> of_carray_tensor(tensor *vs, int len) {
+  std::vector<at::Tensor> result;
+  for (int i = 0; i < len; ++i) result.push_back(*(vs[i]));
+  return result;
+}
+
+process_group atd_new_process_group(int backend, int store_kind, char *addr, int port, int rank, int world_size, int64_t timeout_ms) {
+  PROTECT(
+    auto timeout = std::chrono::milliseconds(timeout_ms);
+    c10::intrusive_ptr<c10d::Store> store;
+    if (store_kind == 0) {
+      c10d::TCPStoreOptions opts;
+      opts.port = port;
+      opts.isServer = rank == 0;
+      opts.numWorkers = world_size;
+      opts.timeout = timeout;
+      store = c10::make_intrusive<c10d::TCPStore>(std::string(addr), opts);
+    } else {
+      store = c10::make_intrusive<c10d::FileStore>(std::string(addr), world_size);
+      store->setTimeout(timeout);
+    }
+    if (backend == 0) {
+      auto options = c10d::ProcessGroupGloo::Options::create();
+      options->timeout = timeout;
+      char *ifname = std::getenv("GLOO_SOCKET_IFNAME");
+      if (ifname != nullptr) {
+        options->devices.push_back(c10d::ProcessGroupGloo::createDeviceForInterface(ifname));
+      } else {
+        options->devices.push_back(c10d::ProcessGroupGloo::createDefaultDevice());
+      }
+      c10::intrusive_ptr<c10d::Backend> pg =
+        c10::make_intrusive<c10d::ProcessGroupGloo>(store, rank, world_size, options);
+      return new c10::intrusive_ptr<c10d::Backend>(pg);
+    }
+#ifdef USE_C10D_NCCL
+    auto options = c10d::ProcessGroupNCCL::Options::create();
+    options->timeout = timeout;
+    c10::intrusive_ptr<c10d::Backend> pg =
+      c10::make_intrusive<c10d::ProcessGroupNCCL>(store, rank, world_size, options);
+    return new c10::intrusive_ptr<c10d::Backend>(pg);
+#else
+    throw std::invalid_argument("libtorch has been compiled without NCCL support");
+#endif
+  )
+  return nullptr;
+}
+
+int atd_rank(process_group pg) {
+  PROTECT(return (*pg)->getRank();)
+  return -1;
+}
+
+int atd_size(process_group pg) {
+  PROTECT(return (*pg)->getSize();)
+  return -1;
+}
+
+void atd_all_reduce(process_group pg, tensor *ts, int ntensors, int op) {
+  PROTECT(
+    auto tensors = of_carray_tensor(ts, ntensors);
+    c10d::AllreduceOptions opts;
+    opts.reduceOp = reduce_op(op);
+    (*pg)->allreduce(tensors, opts)->wait();
+  )
+}
+
+void atd_broadcast(process_group pg, tensor *ts, int ntensors, int root) {
+  PROTECT(
+    auto tensors = of_carray_tensor(ts, ntensors);
+    c10d::BroadcastOptions opts;
+    opts.rootRank = root;
+    (*pg)->broadcast(tensors, opts)->wait();
+  )
+}
+
+void atd_all_gather(process_group pg, tensor *outputs, tensor input) {
+  PROTECT(
+    std::vector<std::vector<at::Tensor>> output_tensors{of_carray_tensor(outputs, (*pg)->getSize())};
+    std::vector<at::Tensor> input_tensors{*input};
+    (*pg)->allgather(output_tensors, input_tensors)->wait();
+  )
+}
+
+// The backward node of a differentiable collective, `backward` computes the
+// gradient of the input from the gradient of the output.
+struct CollectiveBackward : public torch::autograd::Node {
+  c10::intrusive_ptr<c10d::Backend> pg;
+  std::function<at::Tensor(c10d::Backend &, const at::Tensor &)> backward;
+
+  torch::autograd::variable_list apply(torch::autograd::variable_list &&grads) override {
+    if (!grads[0].defined()) return {at::Tensor()};
+    return {backward(*pg, grads[0])};
+  }
+};
+
+// Connects `output`, computed without tracking gradients, to `input` in the
+// autograd graph.
+static at::Tensor with_backward(
+    process_group pg,
+    const at::Tensor &input,
+    at::Tensor output,
+    std::function<at::Tensor(c10d::Backend &, const at::Tensor &)> backward) {
+  if (!torch::autograd::compute_requires_grad(input)) return output;
+  auto node = std::shared_ptr<CollectiveBackward>(new CollectiveBackward(), torch::autograd::deleteNode);
+  node->pg = *pg;
+  node->backward = backward;
+  node->set_next_edges(torch::autograd::collect_next_edges(input));
+  torch::autograd::set_history(output, node);
+  return output;
+}
+
+static at::Tensor all_reduce_sum(c10d::Backend &pg, const at::Tensor &t) {
+  std::vector<at::Tensor> tensors{t.clone()};
+  pg.allreduce(tensors)->wait();
+  return tensors[0];
+}
+
+static at::Tensor all_gather_cat(c10d::Backend &pg, const at::Tensor &t, int64_t dim) {
+  auto input = t.contiguous();
+  std::vector<at::Tensor> outputs;
+  for (int i = 0; i < pg.getSize(); ++i) outputs.push_back(at::empty_like(input));
+  std::vector<std::vector<at::Tensor>> output_tensors{outputs};
+  std::vector<at::Tensor> input_tensors{input};
+  pg.allgather(output_tensors, input_tensors)->wait();
+  return at::cat(output_tensors[0], dim);
+}
+
+tensor atd_copy_to_parallel(process_group pg, tensor t) {
+  PROTECT(
+    at::Tensor output;
+    {
+      at::NoGradGuard no_grad;
+      output = t->clone();
+    }
+    return new torch::Tensor(with_backward(pg, *t, output, all_reduce_sum));
+  )
+  return nullptr;
+}
+
+tensor atd_reduce_from_parallel(process_group pg, tensor t) {
+  PROTECT(
+    at::Tensor output;
+    {
+      at::NoGradGuard no_grad;
+      output = all_reduce_sum(**pg, *t);
+    }
+    auto backward = [](c10d::Backend &, const at::Tensor &grad) { return grad; };
+    return new torch::Tensor(with_backward(pg, *t, output, backward));
+  )
+  return nullptr;
+}
+
+tensor atd_gather_from_parallel(process_group pg, tensor t, int64_t dim) {
+  PROTECT(
+    dim = at::maybe_wrap_dim(dim, t->dim());
+    int64_t size = t->size(dim);
+    at::Tensor output;
+    {
+      at::NoGradGuard no_grad;
+      output = all_gather_cat(**pg, *t, dim);
+    }
+    auto backward = [dim, size](c10d::Backend &pg, const at::Tensor &grad) {
+      return grad.narrow(dim, pg.getRank() * size, size).contiguous();
+    };
+    return new torch::Tensor(with_backward(pg, *t, output, backward));
+  )
+  return nullptr;
+}
+
+tensor atd_scatter_to_parallel(process_group pg, tensor t, int64_t dim) {
+  PROTECT(
+    dim = at::maybe_wrap_dim(dim, t->dim());
+    int64_t world_size = (*pg)->getSize();
+    if (t->size(dim) % world_size != 0)
+      throw std::invalid_argument("the scattered dimension is not divisible by the world size");
+    int64_t size = t->size(dim) / world_size;
+    at::Tensor output;
+    {
+      at::NoGradGuard no_grad;
+      output = t->narrow(dim, (*pg)->getRank() * size, size).clone();
+    }
+    auto backward = [dim](c10d::Backend &pg, const at::Tensor &grad) {
+      return all_gather_cat(pg, grad, dim);
+    };
+    return new torch::Tensor(with_backward(pg, *t, output, backward));
+  )
+  return nullptr;
+}
+
+void atd_barrier(process_group pg) {
+  PROTECT((*pg)->barrier()->wait();)
+}
+
+void atd_free(process_group pg) {
+  PROTECT(delete pg;)
+}
@@ -0,0 +1,37 @@
+#ifndef __TORCH_DISTRIBUTED_H__
+#define __TORCH_DISTRIBUTED_H__
+
+#include "torch_api.h"
+#include<torch/csrc/distributed/c10d/Backend.hpp>
+
+#ifdef __cplusplus
+extern "C" {
+typedef c10::intrusive_ptr<c10d::Backend> *process_group;
+#else
+typedef void *process_group;
+#endif
+
+// backend: 0 for gloo, 1 for nccl.
+// store: 0 for a tcp store using addr/port, 1 for a file store using addr as the path.
+process_group atd_new_process_group(int backend, int store, char *addr, int port, int rank, int world_size, int64_t timeout_ms);
+int atd_rank(process_group);
+int atd_size(process_group);
+void atd_all_reduce(process_group, tensor *, int ntensors, int op);
+void atd_broadcast(process_group, tensor *, int ntensors, int root);
+void atd_all_gather(process_group, tensor *outputs, tensor input);
+// Differentiable collectives for tensor parallelism: identity with an
+// all-reduce of the gradients, all-reduce with an identity backward,
+// all-gather along a dimension, and selection of the local chunk along a
+// dimension.
+tensor atd_copy_to_parallel(process_group, tensor);
+tensor atd_reduce_from_parallel(process_group, tensor);
+tensor atd_gather_from_parallel(process_group, tensor, int64_t dim);
+tensor atd_scatter_to_parallel(process_group, tensor, int64_t dim);
+void atd_barrier(process_group);
+void atd_free(process_group);
+
+#ifdef __cplusplus
+};
+#endif
+
+#endif
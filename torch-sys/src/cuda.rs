@@ -1,4 +1,5 @@
-use libc::c_int;
+use crate::C_tensor;
+use libc::{c_char, c_int};
 
 extern "C" {
     /// Returns the number of CUDA devices available.
@@ -27,4 +28,157 @@ extern "C" {
 
     /// Sets CUDNN benchmark mode.
     pub fn atc_set_benchmark_cudnn(b: c_int);
+
+    /// Returns true if CUDNN benchmark mode is enabled.
+    pub fn atc_benchmark_cudnn() -> c_int;
+
+    /// Returns true if CUDNN is restricted to deterministic algorithms.
+    pub fn atc_deterministic_cudnn() -> c_int;
+
+    /// Restricts CUDNN to deterministic algorithms.
+    pub fn atc_set_deterministic_cudnn(b: c_int);
+
+    /// Returns the CUDNN version.
+    pub fn atc_cudnn_version() -> i64;
+
+    /// Returns true if TF32 can be used for CUBLAS matmuls.
+    pub fn atc_allow_tf32_cublas() -> c_int;
+
+    /// Allows TF32 for CUBLAS matmuls.
+    pub fn atc_set_allow_tf32_cublas(b: c_int);
+
+    /// Returns true if TF32 can be used by CUDNN.
+    pub fn atc_allow_tf32_cudnn() -> c_int;
+
+    /// Allows TF32 for CUDNN convolutions.
+    pub fn atc_set_allow_tf32_cudnn(b: c_int);
+}
+
+#[repr(C)]
+pub struct C_cuda_stream {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct C_cuda_event {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct C_cuda_graph {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    /// Returns true if the CUDA specific functions, e.g. for streams, have
+    /// been compiled, i.e. if the bindings were built against a CUDA
+    /// version of libtorch.
+    pub fn atc_built_with_cuda() -> c_int;
+
+    /// Returns a new stream from the stream pool of the given device.
+    pub fn atcs_new(device: c_int, high_priority: c_int) -> *mut C_cuda_stream;
+
+    /// Returns the default stream for the given device.
+    pub fn atcs_default(device: c_int) -> *mut C_cuda_stream;
+
+    /// Returns the current stream for the given device.
+    pub fn atcs_current(device: c_int) -> *mut C_cuda_stream;
+
+    /// Sets the current stream for the device of the stream.
+    pub fn atcs_set_current(s: *mut C_cuda_stream);
+
+    /// Waits for all the work submitted on the stream to complete.
+    pub fn atcs_synchronize(s: *mut C_cuda_stream);
+
+    /// Returns true if all the work submitted on the stream has completed.
+    pub fn atcs_query(s: *mut C_cuda_stream) -> c_int;
+
+    /// Returns the id of the stream.
+    pub fn atcs_id(s: *mut C_cuda_stream) -> i64;
+
+    /// Returns the device index of the stream.
+    pub fn atcs_device(s: *mut C_cuda_stream) -> c_int;
+
+    /// Makes all future work submitted to the stream wait for the event.
+    pub fn atcs_wait_event(s: *mut C_cuda_stream, e: *mut C_cuda_event);
+
+    /// Marks the memory of the tensor as being used by the stream.
+    pub fn atcs_record_stream(t: *mut C_tensor, s: *mut C_cuda_stream);
+
+    pub fn atcs_free(s: *mut C_cuda_stream);
+
+    /// Creates a new event.
+    pub fn atce_new(enable_timing: c_int) -> *mut C_cuda_event;
+
+    /// Records the event on the given stream.
+    pub fn atce_record(e: *mut C_cuda_event, s: *mut C_cuda_stream);
+
+    /// Waits for the event to complete.
+    pub fn atce_synchronize(e: *mut C_cuda_event);
+
+    /// Returns true if the event has completed.
+    pub fn atce_query(e: *mut C_cuda_event) -> c_int;
+
+    /// Returns the time elapsed between two recorded events in milliseconds.
+    pub fn atce_elapsed_time(start: *mut C_cuda_event, end: *mut C_cuda_event) -> f32;
+
+    pub fn atce_free(e: *mut C_cuda_event);
+
+    /// Creates a new CUDA graph.
+    pub fn atcg_new() -> *mut C_cuda_graph;
+
+    /// Starts capturing the work queued on the current stream, which must not be
+    /// the default stream. The pool ids (0, 0) stand for a private memory pool.
+    pub fn atcg_capture_begin(g: *mut C_cuda_graph, pool_id0: u64, pool_id1: u64);
+
+    /// Ends the capture started by `atcg_capture_begin`.
+    pub fn atcg_capture_end(g: *mut C_cuda_graph);
+
+    /// Launches the captured work on the current stream.
+    pub fn atcg_replay(g: *mut C_cuda_graph);
+
+    /// Releases the captured graph and its memory pool.
+    pub fn atcg_reset(g: *mut C_cuda_graph);
+
+    /// Returns the ids of the memory pool used by the graph.
+    pub fn atcg_pool(g: *mut C_cuda_graph, pool_id0: *mut u64, pool_id1: *mut u64);
+
+    pub fn atcg_free(g: *mut C_cuda_graph);
+
+    /// Returns the ids of a new memory pool that can be shared between graphs.
+    pub fn atcg_graph_pool_handle(pool_id0: *mut u64, pool_id1: *mut u64);
+
+    /// Fills `stats` with the caching allocator statistics for the device, `stats`
+    /// must have space for at least 12 values.
+    pub fn atc_memory_stats(device: c_int, stats: *mut i64);
+
+    /// Resets the peak statistics of the caching allocator for the device.
+    pub fn atc_reset_peak_memory_stats(device: c_int);
+
+    /// Releases the unoccupied cached memory held by the caching allocator.
+    pub fn atc_empty_cache();
+
+    /// Limits the memory that the caching allocator can use on the device.
+    pub fn atc_set_memory_fraction(fraction: f64, device: c_int);
+
+    /// Configures the caching allocator, `settings` uses the same format as the
+    /// `PYTORCH_CUDA_ALLOC_CONF` environment variable.
+    pub fn atc_set_allocator_settings(settings: *const c_char);
+
+    /// Returns the properties of the device, `name` is allocated with malloc and
+    /// has to be freed by the caller.
+    pub fn atc_device_properties(
+        device: c_int,
+        name: *mut *mut c_char,
+        total_memory: *mut i64,
+        major: *mut c_int,
+        minor: *mut c_int,
+        multi_processor_count: *mut c_int,
+    );
+
+    /// Returns the index of the current CUDA device.
+    pub fn atc_current_device() -> c_int;
+
+    /// Sets the current CUDA device.
+    pub fn atc_set_device(device: c_int);
 }
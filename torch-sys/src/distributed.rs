@@ -0,0 +1,52 @@
+use super::C_tensor;
+use libc::c_int;
+
+#[repr(C)]
+pub struct C_process_group {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    pub fn atd_new_process_group(
+        backend: c_int,
+        store: c_int,
+        addr: *const libc::c_char,
+        port: c_int,
+        rank: c_int,
+        world_size: c_int,
+        timeout_ms: i64,
+    ) -> *mut C_process_group;
+    pub fn atd_rank(pg: *mut C_process_group) -> c_int;
+    pub fn atd_size(pg: *mut C_process_group) -> c_int;
+    pub fn atd_all_reduce(
+        pg: *mut C_process_group,
+        ts: *const *mut C_tensor,
+        ntensors: c_int,
+        op: c_int,
+    );
+    pub fn atd_broadcast(
+        pg: *mut C_process_group,
+        ts: *const *mut C_tensor,
+        ntensors: c_int,
+        root: c_int,
+    );
+    pub fn atd_all_gather(
+        pg: *mut C_process_group,
+        outputs: *const *mut C_tensor,
+        input: *mut C_tensor,
+    );
+    pub fn atd_copy_to_parallel(pg: *mut C_process_group, t: *mut C_tensor) -> *mut C_tensor;
+    pub fn atd_reduce_from_parallel(pg: *mut C_process_group, t: *mut C_tensor) -> *mut C_tensor;
+    pub fn atd_gather_from_parallel(
+        pg: *mut C_process_group,
+        t: *mut C_tensor,
+        dim: i64,
+    ) -> *mut C_tensor;
+    pub fn atd_scatter_to_parallel(
+        pg: *mut C_process_group,
+        t: *mut C_tensor,
+        dim: i64,
+    ) -> *mut C_tensor;
+    pub fn atd_barrier(pg: *mut C_process_group);
+    pub fn atd_free(pg: *mut C_process_group);
+}
@@ -1,4 +1,6 @@
 pub mod cuda;
+#[cfg(feature = "distributed")]
+pub mod distributed;
 pub mod io;
 #[cfg(feature = "python-extension")]
 pub mod python;
@@ -15,6 +17,9 @@ pub struct C_scalar {
 extern "C" {
     pub fn ats_int(v: i64) -> *mut C_scalar;
     pub fn ats_float(v: f64) -> *mut C_scalar;
+    pub fn ats_bool(v: c_int) -> *mut C_scalar;
+    pub fn ats_type(arg: *mut C_scalar) -> c_int;
+    pub fn ats_to_bool(arg: *mut C_scalar) -> c_int;
     pub fn ats_to_int(arg: *mut C_scalar) -> i64;
     pub fn ats_to_float(arg: *mut C_scalar) -> f64;
     pub fn ats_to_string(arg: *mut C_scalar) -> *mut c_char;
@@ -31,15 +36,26 @@ extern "C" {
     pub fn at_shallow_clone(arg: *mut C_tensor) -> *mut C_tensor;
     pub fn at_copy_(dst: *mut C_tensor, src: *mut C_tensor);
     pub fn at_data_ptr(arg: *mut C_tensor) -> *mut c_void;
+    pub fn at_tensor_impl(arg: *mut C_tensor) -> *mut c_void;
     pub fn at_defined(arg: *mut C_tensor) -> c_int;
     pub fn at_is_sparse(arg: *mut C_tensor) -> c_int;
     pub fn at_is_mkldnn(arg: *mut C_tensor) -> c_int;
     pub fn at_is_contiguous(args: *mut C_tensor) -> c_int;
     pub fn at_backward(arg: *mut C_tensor, keep_graph: c_int, create_graph: c_int);
+    pub fn at_backward_opt(
+        arg: *mut C_tensor,
+        inputs: *const *mut C_tensor,
+        ninputs: c_int,
+        keep_graph: c_int,
+        create_graph: c_int,
+    );
     pub fn at_print(arg: *mut C_tensor);
     pub fn at_to_string(arg: *mut C_tensor, line_size: c_int) -> *mut c_char;
     pub fn at_dim(arg: *mut C_tensor) -> size_t;
     pub fn at_get(arg: *mut C_tensor, index: c_int) -> *mut C_tensor;
+    pub fn at_set_requires_grad(arg: *mut C_tensor, requires_grad: c_int) -> *mut C_tensor;
+    pub fn at_retain_grad(arg: *mut C_tensor);
+    pub fn at_set_grad(arg: *mut C_tensor, grad: *mut C_tensor);
     pub fn at_requires_grad(arg: *mut C_tensor) -> c_int;
     pub fn at_shape(arg: *mut C_tensor, sz: *mut i64);
     pub fn at_stride(arg: *mut C_tensor, sz: *mut i64);
@@ -60,6 +76,25 @@ extern "C" {
         keep_graph: c_int,
         create_graph: c_int,
     );
+    pub fn at_autograd_grad(
+        outputs: *const *mut C_tensor,
+        noutputs: c_int,
+        inputs: *const *mut C_tensor,
+        ninputs: c_int,
+        grad_outputs: *const *mut C_tensor,
+        ngrad_outputs: c_int,
+        results: *mut *mut C_tensor,
+        keep_graph: c_int,
+        create_graph: c_int,
+        allow_unused: c_int,
+    );
+    pub fn at_autograd_backward(
+        tensors: *const *mut C_tensor,
+        ntensors: c_int,
+        grad_tensors: *const *mut C_tensor,
+        keep_graph: c_int,
+        create_graph: c_int,
+    );
     pub fn at_copy_data(
         arg: *mut C_tensor,
         vs: *const c_void,
@@ -77,6 +112,9 @@ extern "C" {
     pub fn at_autocast_increment_nesting() -> c_int;
     pub fn at_autocast_is_enabled() -> c_int;
     pub fn at_autocast_set_enabled(b: c_int) -> c_int;
+    pub fn at_autocast_is_cpu_enabled() -> c_int;
+    pub fn at_autocast_set_cpu_enabled(b: c_int) -> c_int;
+    pub fn at_autocast_set_dtype(cpu: c_int, dtype: c_int) -> c_int;
     pub fn at_device(arg: *mut C_tensor) -> c_int;
     pub fn at_tensor_of_data(
         vs: *const c_void,
@@ -94,7 +132,78 @@ extern "C" {
         kind: c_int,
         device: c_int,
     ) -> *mut C_tensor;
+    pub fn at_tensor_of_blob_with_deleter(
+        vs: *const c_void,
+        dims: *const i64,
+        ndims: size_t,
+        strides: *const i64,
+        nstrides: size_t,
+        kind: c_int,
+        device: c_int,
+        deleter: extern "C" fn(*mut c_void),
+        deleter_ctx: *mut c_void,
+    ) -> *mut C_tensor;
     pub fn at_grad_set_enabled(b: c_int) -> c_int;
+    pub fn at_grad_is_enabled() -> c_int;
+    pub fn at_set_anomaly_mode(enabled: c_int, check_nan: c_int);
+    pub fn at_anomaly_mode_is_enabled() -> c_int;
+    pub fn at_anomaly_mode_should_check_nan() -> c_int;
+    pub fn at_warning_handler_install(
+        f: extern "C" fn(*const c_char, *const c_char, *const c_char, u32, c_int),
+    ) -> *mut c_void;
+    pub fn at_warning_handler_restore(prev: *mut c_void);
+    pub fn at_set_warn_always(enabled: c_int);
+    pub fn at_get_warn_always() -> c_int;
+    pub fn at_inference_mode_enter(enabled: c_int) -> *mut C_inference_mode;
+    pub fn at_inference_mode_exit(guard: *mut C_inference_mode);
+    pub fn at_inference_mode_is_enabled() -> c_int;
+    pub fn at_checkpoint(
+        inputs: *const *mut C_tensor,
+        ninputs: c_int,
+        data: *mut c_void,
+        f: extern "C" fn(*mut c_void, *mut *mut C_tensor, c_int) -> *mut C_tensor,
+        free_data: extern "C" fn(*mut c_void),
+    ) -> *mut C_tensor;
+}
+
+#[repr(C)]
+pub struct C_inference_mode {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct C_profiler_result {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct C_record_function {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    pub fn atp_enable_profiler(
+        cpu: c_int,
+        cuda: c_int,
+        record_shapes: c_int,
+        profile_memory: c_int,
+        with_stack: c_int,
+    );
+    pub fn atp_disable_profiler() -> *mut C_profiler_result;
+    pub fn atp_result_num_events(r: *mut C_profiler_result) -> i64;
+    pub fn atp_result_event(
+        r: *mut C_profiler_result,
+        index: i64,
+        name: *mut *mut c_char,
+        is_cuda: *mut c_int,
+        start_us: *mut u64,
+        duration_us: *mut u64,
+        nbytes: *mut i64,
+    );
+    pub fn atp_result_save(r: *mut C_profiler_result, path: *const c_char);
+    pub fn atp_result_free(r: *mut C_profiler_result);
+    pub fn atp_record_function_enter(name: *const c_char) -> *mut C_record_function;
+    pub fn atp_record_function_exit(rf: *mut C_record_function);
     pub fn at_save(arg: *mut C_tensor, filename: *const c_char);
     pub fn at_save_to_stream(arg: *mut C_tensor, stream_ptr: *mut c_void);
     pub fn at_load(filename: *const c_char) -> *mut C_tensor;
@@ -142,6 +251,11 @@ extern "C" {
     );
 
     pub fn at_manual_seed(seed: i64);
+    pub fn at_get_rng_state(device: c_int) -> *mut C_tensor;
+    pub fn at_set_rng_state(device: c_int, state: *mut C_tensor);
+    pub fn at_set_deterministic_algorithms(b: c_int, warn_only: c_int);
+    pub fn at_deterministic_algorithms() -> c_int;
+    pub fn at_deterministic_algorithms_warn_only() -> c_int;
     pub fn at_set_graph_executor_optimize(b: bool);
     pub fn at_context_has_openmp() -> bool;
     pub fn at_context_has_mkl() -> bool;
@@ -156,9 +270,12 @@ extern "C" {
     pub fn at_context_has_xla() -> bool;
     pub fn at_context_has_lazy() -> bool;
     pub fn at_context_has_mps() -> bool;
+    pub fn at_mps_synchronize();
     pub fn at_context_has_ort() -> bool;
     pub fn at_context_version_cudnn() -> i64;
     pub fn at_context_version_cudart() -> i64;
+    pub fn at_torch_version() -> *mut c_char;
+    pub fn at_show_config() -> *mut c_char;
 }
 
 pub mod c_generated;
@@ -204,6 +321,13 @@ extern "C" {
         wd: f64,
         nesterov: c_int,
     ) -> *mut C_optimizer;
+    pub fn ato_adagrad(
+        lr: f64,
+        lr_decay: f64,
+        wd: f64,
+        initial_accumulator_value: f64,
+        eps: f64,
+    ) -> *mut C_optimizer;
     pub fn ato_add_parameters(arg: *mut C_optimizer, ts: *mut C_tensor, group: size_t);
     pub fn ato_set_learning_rate(arg: *mut C_optimizer, lr: f64);
     pub fn ato_set_learning_rate_group(arg: *mut C_optimizer, group: size_t, lr: f64);
@@ -211,10 +335,18 @@ extern "C" {
     pub fn ato_set_momentum_group(arg: *mut C_optimizer, group: size_t, momentum: f64);
     pub fn ato_set_weight_decay(arg: *mut C_optimizer, weight_decay: f64);
     pub fn ato_set_weight_decay_group(arg: *mut C_optimizer, group: size_t, weight_decay: f64);
-    pub fn ato_zero_grad(arg: *mut C_optimizer);
+    pub fn ato_zero_grad(arg: *mut C_optimizer, set_to_none: c_int);
     pub fn ato_step(arg: *mut C_optimizer);
+    pub fn ato_save(arg: *mut C_optimizer, filename: *const c_char);
+    pub fn ato_load(arg: *mut C_optimizer, filename: *const c_char);
     pub fn ato_free(arg: *mut C_optimizer);
     pub fn at_save_image(arg: *mut C_tensor, filename: *const c_char) -> c_int;
+    pub fn at_encode_image(
+        arg: *mut C_tensor,
+        format: *const c_char,
+        quality: c_int,
+        len: *mut size_t,
+    ) -> *mut c_uchar;
     pub fn at_load_image(filename: *const c_char) -> *mut C_tensor;
     pub fn at_load_image_from_memory(
         img_data: *const c_uchar,
@@ -272,6 +404,24 @@ extern "C" {
 
     pub fn ati_clone(arg: *mut CIValue) -> *mut CIValue;
     pub fn ati_free(arg: *mut CIValue);
+    pub fn at_register_op(
+        schema: *const c_char,
+        data: *mut c_void,
+        f: extern "C" fn(*mut c_void, *mut *mut CIValue, c_int, *mut *mut c_char) -> *mut CIValue,
+        free_data: extern "C" fn(*mut c_void),
+    );
+    pub fn at_register_kernel(
+        schema: *const c_char,
+        dispatch_key: c_int,
+        data: *mut c_void,
+        f: extern "C" fn(*mut c_void, *mut *mut CIValue, c_int, *mut *mut c_char) -> *mut CIValue,
+        free_data: extern "C" fn(*mut c_void),
+    );
+    pub fn at_call_op(
+        name: *const c_char,
+        inputs: *const *mut CIValue,
+        ninputs: c_int,
+    ) -> *mut CIValue;
 
     pub fn ati_object_method_(
         arg: *mut CIValue,
@@ -320,6 +470,11 @@ extern "C" {
         data: *mut c_void,
         f: extern "C" fn(*mut c_void, name: *const c_char, t: *mut C_tensor),
     );
+    pub fn atm_named_buffers(
+        m: *mut CModule_,
+        data: *mut c_void,
+        f: extern "C" fn(*mut c_void, name: *const c_char, t: *mut C_tensor),
+    );
     pub fn atm_create_for_tracing(
         modl_name: *const c_char,
         inputs: *const *mut C_tensor,